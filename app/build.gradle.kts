@@ -96,6 +96,11 @@ android {
         buildConfig = true
     }
 
+    testOptions {
+        // android.util.Log and the native bridge are stubbed out in JVM tests
+        unitTests.isReturnDefaultValues = true
+    }
+
     applicationVariants.all {
         outputs.all {
             val output = this as com.android.build.gradle.internal.api.BaseVariantOutputImpl
@@ -43,6 +43,8 @@ class PreferencesDataStore @Inject constructor(
         val CONNECTION_TIMEOUT = intPreferencesKey("connection_timeout")
         val BUFFER_SIZE = stringPreferencesKey("buffer_size")
         val CONNECTION_POOL_SIZE = intPreferencesKey("connection_pool_size")
+        val SOCKS_CONNECT_TIMEOUT = intPreferencesKey("socks_connect_timeout")
+        val DOH_MIN_TLS_VERSION = stringPreferencesKey("doh_min_tls_version")
         // SSH Tunnel Keys
         val SSH_CIPHER = stringPreferencesKey("ssh_cipher")
         val SSH_COMPRESSION = booleanPreferencesKey("ssh_compression")
@@ -214,6 +216,29 @@ class PreferencesDataStore @Inject constructor(
         }
     }
 
+    // Wait for the remote proxy's reply to a CONNECT through the Slipstream SOCKS bridge
+    val socksConnectTimeout: Flow<Int> = dataStore.data.map { prefs ->
+        prefs[Keys.SOCKS_CONNECT_TIMEOUT] ?: 15000
+    }
+
+    suspend fun setSocksConnectTimeout(timeout: Int) {
+        dataStore.edit { prefs ->
+            prefs[Keys.SOCKS_CONNECT_TIMEOUT] = timeout.coerceIn(1000, 60000)
+        }
+    }
+
+    // Oldest TLS version the Slipstream SOCKS bridge's DoH fallback accepts
+    val dohMinTlsVersion: Flow<String> = dataStore.data.map { prefs ->
+        prefs[Keys.DOH_MIN_TLS_VERSION] ?: "TLSv1.2"
+    }
+
+    suspend fun setDohMinTlsVersion(version: String) {
+        if (version !in listOf("TLSv1.2", "TLSv1.3")) return
+        dataStore.edit { prefs ->
+            prefs[Keys.DOH_MIN_TLS_VERSION] = version
+        }
+    }
+
     // SSH Tunnel Settings
     val sshCipher: Flow<SshCipher> = dataStore.data.map { prefs ->
         SshCipher.fromValue(prefs[Keys.SSH_CIPHER] ?: SshCipher.AUTO.value)
@@ -184,6 +184,8 @@ class VpnRepositoryImpl @Inject constructor(
     /**
      * Start SlipstreamSocksBridge — a middleman SOCKS5 proxy for Slipstream non-SSH.
      * Chains CONNECT to Slipstream's SOCKS5 and handles FWD_UDP (DNS/UDP) directly.
+     * The CONNECT reply timeout and the DoH fallback's minimum TLS version come from
+     * preferences unless given.
      */
     suspend fun startSlipstreamSocksBridge(
         slipstreamPort: Int,
@@ -191,7 +193,10 @@ class VpnRepositoryImpl @Inject constructor(
         bridgePort: Int,
         bridgeHost: String,
         socksUsername: String? = null,
-        socksPassword: String? = null
+        socksPassword: String? = null,
+        connectionWarmup: Boolean = false,
+        connectTimeoutMs: Int? = null,
+        minTlsVersion: String? = null
     ): Result<Unit> = withContext(Dispatchers.IO) {
         val result = SlipstreamSocksBridge.start(
             slipstreamPort = slipstreamPort,
@@ -199,7 +204,10 @@ class VpnRepositoryImpl @Inject constructor(
             listenPort = bridgePort,
             listenHost = bridgeHost,
             socksUsername = socksUsername,
-            socksPassword = socksPassword
+            socksPassword = socksPassword,
+            minTlsVersion = minTlsVersion ?: preferencesDataStore.dohMinTlsVersion.first(),
+            warmup = connectionWarmup,
+            connectTimeoutMs = connectTimeoutMs ?: preferencesDataStore.socksConnectTimeout.first()
         )
         if (result.isSuccess) {
             Log.i(TAG, "SlipstreamSocksBridge started on $bridgeHost:$bridgePort -> $slipstreamHost:$slipstreamPort")
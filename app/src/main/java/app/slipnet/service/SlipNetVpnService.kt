@@ -1081,6 +1081,7 @@ class SlipNetVpnService : VpnService() {
 
             override fun onAvailable(network: Network) {
                 Log.d(TAG, "Network available: $network")
+                SlipstreamBridge.notifyNetworkChanged()
                 if (currentNetwork != null && currentNetwork != network) {
                     Log.i(TAG, "Network changed from $currentNetwork to $network, triggering reconnection")
                     debouncedReconnect("network change")
@@ -2,6 +2,7 @@ package app.slipnet.tunnel
 
 import android.net.VpnService
 import android.util.Log
+import org.json.JSONObject
 import java.lang.ref.WeakReference
 
 /**
@@ -15,6 +16,37 @@ object SlipstreamBridge {
     const val DEFAULT_SLIPSTREAM_PORT = 1080
     const val DEFAULT_LISTEN_HOST = "127.0.0.1"
 
+    /** A start while running fails with "Client already running" (the default). */
+    const val DUPLICATE_START_REJECT = 0
+    /**
+     * A start while running succeeds without change when its arguments match the
+     * running client; otherwise the running client is stopped and replaced.
+     */
+    const val DUPLICATE_START_REUSE_OR_RESTART = 1
+
+    /** Protect the DNS socket through [VpnService.protect] (the default). */
+    const val PROTECTION_VPN = 0
+    /** Skip socket protection, for running only the SOCKS5 proxy without a VpnService. */
+    const val PROTECTION_NONE = 1
+
+    /**
+     * When the VPN revokes protection mid-session, drop the tunnel and refuse new
+     * connections until the client is stopped (the default).
+     */
+    const val PROTECTION_LOSS_HOLD = 0
+    /** When the VPN revokes protection mid-session, exit the client with an error. */
+    const val PROTECTION_LOSS_STOP = 1
+
+    /** [beginConnect]: open the stream, then report the outcome with [finishConnect]. */
+    const val CONNECT_PROCEED = 0
+    /** [beginConnect]: an earlier CONNECT to the destination is in flight; ask again shortly. */
+    const val CONNECT_WAIT = 1
+    /** [beginConnect]: refuse the CONNECT as a duplicate without opening a stream. */
+    const val CONNECT_REFUSE = 2
+
+    /** [reportConnectFailure]: no SOCKS5 reply came back through the tunnel. */
+    const val CONNECT_NO_REPLY = -1
+
     private var isLibraryLoaded = false
     private var currentPort = DEFAULT_SLIPSTREAM_PORT
 
@@ -22,6 +54,100 @@ object SlipstreamBridge {
     @Volatile
     private var vpnServiceRef: WeakReference<VpnService>? = null
 
+    /**
+     * Invoked with (oldResolver, newResolver, reason) when the active primary resolver
+     * changes; reason is "failover" or "health". Called on the native client thread, and
+     * at most once per 10 seconds while the primary flaps.
+     */
+    @Volatile
+    var resolverChangedListener: ((String, String, String) -> Unit)? = null
+
+    /**
+     * Invoked with the number of queries sent when the limit from
+     * [setMaxLifetimeQueries] stops the client. Called on the native client thread.
+     */
+    @Volatile
+    var limitReachedListener: ((Long) -> Unit)? = null
+
+    /**
+     * Invoked with the `Log` priority and text of each native log line while a stream
+     * from [setLogStream] is running. Called on the native log stream thread; lines are
+     * dropped, not queued without bound, if this falls behind.
+     */
+    @Volatile
+    var logLineListener: ((Int, String) -> Unit)? = null
+
+    /**
+     * Invoked with the sleep length in ms when the client notices the device woke
+     * from Doze. After a minute or more the client reconnects on its own. Called on
+     * the native client thread.
+     */
+    @Volatile
+    var wokeFromDozeListener: ((Long) -> Unit)? = null
+
+    /**
+     * Invoked when the VPN keeps refusing to protect the DNS socket, for example after
+     * the OS tore down the VpnService. The client has already dropped the tunnel; stop
+     * or restart it from here. Called on the native client thread.
+     */
+    @Volatile
+    var protectionLostListener: (() -> Unit)? = null
+
+    /**
+     * Invoked with (failure, attempts) once [setBlockBackoff]'s attempt count of reconnects
+     * in a row all failed the same way, which suggests the domain is blocked. failure is
+     * "no_answers", "no_tunnel_data" or "handshake". The client keeps retrying at the long
+     * backoff; switching domain or server is up to the app. Called on the native client
+     * thread.
+     */
+    @Volatile
+    var persistentBlockListener: ((String, Int) -> Unit)? = null
+
+    /**
+     * Invoked with true when the client pauses outside the windows set by
+     * [setActiveWindows], and with false when the next window opens and it reconnects.
+     * Called on the native client thread.
+     */
+    @Volatile
+    var scheduledPauseListener: ((Boolean) -> Unit)? = null
+
+    /**
+     * Invoked with true when no resolver can be reached because the device has no
+     * network, and the client stops retrying until [notifyNetworkChanged] or its own
+     * periodic recheck; with false when it tries to connect again.
+     * Called on the native client thread.
+     */
+    @Volatile
+    var networkOfflineListener: ((Boolean) -> Unit)? = null
+
+    /**
+     * Invoked with each tunnel event as a JSON object whose "event" field is "connected",
+     * "disconnected", "stalled", "recovered", "reconnecting", "gave_up", "limit_reached",
+     * "session_rotated",
+     * "non_tunnel_response", "resolver_mode_mismatch", "resolver_rejects_encoding" or
+     * "rtt_spike".
+     * Disconnects and stalls are only reported once they outlast the window set by
+     * [setEventGrace]; a "recovered" event carries "from", "after_ms" and "reported", which
+     * is false for blips that were never reported. A "reconnecting" event carries the
+     * "attempt" number, counted from 1 for each outage, and "retry_in_ms", the wait before
+     * that attempt, for a countdown. A "gave_up" event carries the reconnect "attempts"
+     * made and "after_ms" spent since the connection was lost before the client stopped
+     * (see [setReconnectLimit]). A "limit_reached" event carries the "queries" sent when
+     * the lifetime query limit stopped the client (see [setMaxLifetimeQueries]). A
+     * "session_rotated" event carries the
+     * "old" and "new" session ids. A "non_tunnel_response" event carries the "resolver"
+     * and the "record_type" it answered with instead of tunnel data, e.g. "A". A
+     * "resolver_mode_mismatch" event carries the "resolver", its "configured" and
+     * "observed" modes, and whether it was "adapted" (see [setAdaptResolverModes]). A
+     * "resolver_rejects_encoding" event carries the "resolver", the query form it
+     * "rejected" with FORMERR, e.g. "TXT/CH with cookie", and the "fallback" form tried
+     * next, or null (see [setAdaptToFormerr]). An "rtt_spike" event carries the "resolver" whose response time jumped from its usual
+     * "baseline_ms" to "rtt_ms"; its retransmits are held back until it settles.
+     * Called on the native client thread.
+     */
+    @Volatile
+    var tunnelEventListener: ((String) -> Unit)? = null
+
     init {
         try {
             System.loadLibrary("slipstream")
@@ -59,6 +185,123 @@ object SlipstreamBridge {
         return result
     }
 
+    /**
+     * Called from JNI when the active primary resolver changes.
+     */
+    @JvmStatic
+    fun onResolverChanged(old: String, new: String, reason: String) {
+        Log.i(TAG, "Primary resolver changed from $old to $new ($reason)")
+        try {
+            resolverChangedListener?.invoke(old, new, reason)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error in resolver change listener", e)
+        }
+    }
+
+    /**
+     * Called from JNI when the lifetime query limit stops the client.
+     */
+    @JvmStatic
+    fun onLimitReached(queries: Long) {
+        Log.i(TAG, "Lifetime query limit reached after $queries queries")
+        try {
+            limitReachedListener?.invoke(queries)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error in limit reached listener", e)
+        }
+    }
+
+    /**
+     * Called from JNI for each streamed log line. Must not log through the native
+     * library itself.
+     */
+    @JvmStatic
+    fun onLogLine(priority: Int, line: String) {
+        try {
+            logLineListener?.invoke(priority, line)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error in log line listener", e)
+        }
+    }
+
+    /**
+     * Called from JNI when the client detects it was frozen by device sleep.
+     */
+    @JvmStatic
+    fun onWokeFromDoze(sleptMs: Long) {
+        Log.i(TAG, "Woke from doze after ${sleptMs}ms")
+        try {
+            wokeFromDozeListener?.invoke(sleptMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error in woke from doze listener", e)
+        }
+    }
+
+    /**
+     * Called from JNI when the DNS socket's VPN protection is lost.
+     */
+    @JvmStatic
+    fun onProtectionLost() {
+        Log.w(TAG, "VPN protection of the DNS socket was lost")
+        try {
+            protectionLostListener?.invoke()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error in protection lost listener", e)
+        }
+    }
+
+    /**
+     * Called from JNI when reconnects look persistently blocked.
+     */
+    @JvmStatic
+    fun onPersistentBlockSuspected(failure: String, attempts: Int) {
+        Log.w(TAG, "Persistent block suspected after $attempts attempts ($failure)")
+        try {
+            persistentBlockListener?.invoke(failure, attempts)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error in persistent block listener", e)
+        }
+    }
+
+    /**
+     * Called from JNI when the tunnel pauses outside its active windows, or resumes.
+     */
+    @JvmStatic
+    fun onScheduledPause(paused: Boolean) {
+        Log.i(TAG, if (paused) "Paused outside the active windows" else "Active window opened")
+        try {
+            scheduledPauseListener?.invoke(paused)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error in scheduled pause listener", e)
+        }
+    }
+
+    /**
+     * Called from JNI when the device goes offline, or the client tries to reconnect.
+     */
+    @JvmStatic
+    fun onNetworkOffline(offline: Boolean) {
+        Log.i(TAG, if (offline) "Network offline; waiting for connectivity" else "Retrying after going offline")
+        try {
+            networkOfflineListener?.invoke(offline)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error in network offline listener", e)
+        }
+    }
+
+    /**
+     * Called from JNI when the tunnel connects, loses its connection or stalls, or recovers.
+     */
+    @JvmStatic
+    fun onTunnelEvent(json: String) {
+        Log.i(TAG, "Tunnel event: $json")
+        try {
+            tunnelEventListener?.invoke(json)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error in tunnel event listener", e)
+        }
+    }
+
     /**
      * Start the slipstream client (DNS tunnel).
      * The client will listen on the specified host:port for SOCKS5 connections.
@@ -127,6 +370,11 @@ object SlipstreamBridge {
                 -2 -> Result.failure(RuntimeException("Invalid resolver configuration"))
                 -10 -> Result.failure(RuntimeException("Failed to spawn client thread"))
                 -11 -> Result.failure(RuntimeException("Failed to listen on port"))
+                -13 -> Result.failure(IllegalStateException("Previous client is still shutting down"))
+                -14 -> Result.failure(IllegalStateException("Client already running"))
+                -15 -> Result.failure(
+                    IllegalArgumentException("Invalid listen host \"$tcpListenHost\": expected a literal IP address")
+                )
                 else -> Result.failure(RuntimeException("Failed to start client: error $result"))
             }
         } catch (e: Exception) {
@@ -135,6 +383,81 @@ object SlipstreamBridge {
         }
     }
 
+    /**
+     * Check the arguments of [startClient] without starting anything, for inline form
+     * validation. Returns a JSON array of {"severity", "field", "message"} objects, where
+     * severity is "error" or "warning"; "[]" means the configuration is valid. Resolver
+     * hostnames are looked up, so call this off the main thread.
+     */
+    fun validateConfig(
+        domain: String,
+        resolvers: List<ResolverConfig>,
+        congestionControl: String = "bbr",
+        keepAliveInterval: Int = 200,
+        tcpListenPort: Int = DEFAULT_SLIPSTREAM_PORT,
+        tcpListenHost: String = DEFAULT_LISTEN_HOST,
+        gsoEnabled: Boolean = false,
+        debugPoll: Boolean = false,
+        debugStreams: Boolean = false
+    ): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeValidateConfig(
+                domain = domain,
+                resolverHosts = resolvers.map { it.host }.toTypedArray(),
+                resolverPorts = resolvers.map { it.port }.toIntArray(),
+                resolverAuthoritative = resolvers.map { it.authoritative }.toBooleanArray(),
+                listenPort = tcpListenPort,
+                listenHost = tcpListenHost,
+                congestionControl = congestionControl,
+                keepAliveInterval = keepAliveInterval,
+                gsoEnabled = gsoEnabled,
+                debugPoll = debugPoll,
+                debugStreams = debugStreams
+            )
+        } catch (e: Exception) {
+            Log.e(TAG, "Error validating config", e)
+            null
+        }
+    }
+
+    /**
+     * Apply new settings to the running client without reconnecting.
+     * Returns JSON `{applied, restart_required}` listing the changed fields,
+     * or an empty string when no client is running or the settings are invalid.
+     */
+    fun reloadConfig(
+        domain: String,
+        resolvers: List<ResolverConfig>,
+        congestionControl: String = "bbr",
+        keepAliveInterval: Int = 200,
+        tcpListenPort: Int = DEFAULT_SLIPSTREAM_PORT,
+        tcpListenHost: String = DEFAULT_LISTEN_HOST,
+        gsoEnabled: Boolean = false,
+        debugPoll: Boolean = false,
+        debugStreams: Boolean = false
+    ): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeReloadConfig(
+                domain = domain,
+                resolverHosts = resolvers.map { it.host }.toTypedArray(),
+                resolverPorts = resolvers.map { it.port }.toIntArray(),
+                resolverAuthoritative = resolvers.map { it.authoritative }.toBooleanArray(),
+                listenPort = tcpListenPort,
+                listenHost = tcpListenHost,
+                congestionControl = congestionControl,
+                keepAliveInterval = keepAliveInterval,
+                gsoEnabled = gsoEnabled,
+                debugPoll = debugPoll,
+                debugStreams = debugStreams
+            )
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reloading config", e)
+            null
+        }
+    }
+
     private fun waitForPortFree(port: Int, maxWaitMs: Int): Boolean {
         if (!isPortInUse(port)) return true
 
@@ -169,84 +492,1372 @@ object SlipstreamBridge {
     }
 
     /**
-     * Check if the slipstream client is running (native flag).
+     * Close a single tunneled connection by its QUIC stream id.
+     * Other streams and the tunnel itself stay up.
      */
-    fun isClientRunning(): Boolean {
-        if (!isLibraryLoaded) return false
+    fun closeStream(streamId: Long): Result<Unit> {
+        if (!isLibraryLoaded) {
+            return Result.failure(IllegalStateException("Native library not loaded"))
+        }
         return try {
-            nativeIsClientRunning()
+            when (val result = nativeCloseStream(streamId)) {
+                0 -> Result.success(Unit)
+                -1 -> Result.failure(IllegalStateException("Client not running"))
+                -2 -> Result.failure(NoSuchElementException("Unknown stream $streamId"))
+                -3 -> Result.failure(RuntimeException("Timed out closing stream $streamId"))
+                else -> Result.failure(RuntimeException("Failed to close stream: error $result"))
+            }
         } catch (e: Exception) {
-            Log.e(TAG, "Error checking native running state", e)
-            false
+            Log.e(TAG, "Error closing stream $streamId", e)
+            Result.failure(e)
         }
     }
 
     /**
-     * Check if the client is running AND the port is actually listening.
-     * Use this for health checks after connection is established.
+     * Send the next [queryCount] queries through the resolver at [index], in
+     * configured order, regardless of its health, then return to normal selection.
+     * For debugging a misbehaving resolver; 0 ends a pin early.
      */
-    fun isClientHealthy(): Boolean {
-        if (!isClientRunning()) return false
-
-        // Verify the port is actually listening
+    fun forceResolver(index: Int, queryCount: Int): Result<Unit> {
+        if (!isLibraryLoaded) {
+            return Result.failure(IllegalStateException("Native library not loaded"))
+        }
         return try {
-            java.net.Socket().use { socket ->
-                socket.connect(java.net.InetSocketAddress("127.0.0.1", currentPort), 200)
-                true
+            when (val result = nativeForceResolver(index, queryCount)) {
+                0 -> Result.success(Unit)
+                -1 -> Result.failure(IllegalStateException("Client not running"))
+                -2 -> Result.failure(IndexOutOfBoundsException("No resolver at index $index"))
+                else -> Result.failure(RuntimeException("Failed to force resolver: error $result"))
             }
         } catch (e: Exception) {
-            Log.w(TAG, "Client reports running but port $currentPort is not listening")
-            false
+            Log.e(TAG, "Error forcing resolver", e)
+            Result.failure(e)
         }
     }
 
     /**
-     * Get the port the slipstream client is listening on.
+     * Get a JSON snapshot of the running session (connection state, DNS query budget,
+     * and the optional features engaged on the connection under "active_features").
+     * Returns null when the client is not running.
      */
-    fun getClientPort(): Int = currentPort
+    fun getSessionInfo(): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeGetSessionInfo()?.takeIf { it.isNotEmpty() }
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading session info", e)
+            null
+        }
+    }
 
     /**
-     * Check if a port is currently in use (bound by another socket).
-     * This tries to bind a server socket to the port - if it fails, the port is in use.
-     * This is more reliable than trying to connect, because a stuck/abandoned listener
-     * may not be accepting connections but still has the port bound.
+     * Get the id of the current connection, as it appears in client and server logs,
+     * for users to quote when filing issues. A reconnect starts a new session, announced
+     * by a "session_rotated" tunnel event. Returns null before the first connection.
      */
-    private fun isPortInUse(port: Int): Boolean {
+    fun getSessionId(): String? {
+        if (!isLibraryLoaded) return null
         return try {
-            java.net.ServerSocket().use { serverSocket ->
-                serverSocket.reuseAddress = true
-                serverSocket.bind(java.net.InetSocketAddress("127.0.0.1", port))
-                // Successfully bound - port is free
-                false
-            }
-        } catch (e: java.net.BindException) {
-            // Port is in use
-            true
+            nativeGetSessionId()?.takeIf { it.isNotEmpty() }
         } catch (e: Exception) {
-            // Other error - assume port might be in use to be safe
-            Log.w(TAG, "Error checking port $port: ${e.message}")
-            true
+            Log.e(TAG, "Error reading session id", e)
+            null
         }
     }
 
-    // Native methods - matches slipstream-client CLI parameters
-    private external fun nativeStartSlipstreamClient(
-        domain: String,
-        resolverHosts: Array<String>,
-        resolverPorts: IntArray,
-        resolverAuthoritative: BooleanArray,
-        listenPort: Int,
-        listenHost: String,
-        congestionControl: String,
-        keepAliveInterval: Int,
-        gsoEnabled: Boolean,
-        debugPoll: Boolean,
-        debugStreams: Boolean
-    ): Int
+    /**
+     * Get a JSON report of the last client run after it stopped: duration_ms,
+     * queries_sent, bytes_sent, avg_bytes_per_sec, an "errors" breakdown and per-resolver
+     * totals under "resolvers". Returns null until a client has stopped.
+     */
+    fun getLastSessionSummary(): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeGetLastSessionSummary()?.takeIf { it.isNotEmpty() }
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading session summary", e)
+            null
+        }
+    }
 
-    private external fun nativeStopSlipstreamClient()
-    private external fun nativeIsClientRunning(): Boolean
-    private external fun nativeIsQuicReady(): Boolean
+    /**
+     * Get a JSON array with the live state of each resolver (address, mode, health
+     * state, rtt_us, success_ratio, traffic_share, standby, asymmetric).
+     * Returns "[]" when the client is not running.
+     */
+    fun getResolverStates(): String {
+        if (!isLibraryLoaded) return "[]"
+        return try {
+            nativeGetResolverStates() ?: "[]"
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading resolver states", e)
+            "[]"
+        }
+    }
+
+    /**
+     * Result of an end-to-end fetch through the tunnel.
+     */
+    data class ConnectivityResult(
+        val success: Boolean,
+        val latencyMs: Long,
+        val statusCode: Int?,
+        val snippet: String,
+        val error: String?
+    )
+
+    /**
+     * Fetch [url] through the tunnel (SOCKS5 CONNECT via the remote proxy) to verify
+     * that traffic actually flows, not just the QUIC handshake.
+     * Blocks for up to [timeoutMs]; call it off the main thread.
+     */
+    fun verifyConnectivity(
+        url: String,
+        socksUsername: String? = null,
+        socksPassword: String? = null,
+        timeoutMs: Int = 10000
+    ): ConnectivityResult {
+        if (!isLibraryLoaded) {
+            return ConnectivityResult(false, 0, null, "", "Native library not loaded")
+        }
+        return try {
+            val json = nativeVerifyConnectivity(
+                url,
+                currentPort,
+                socksUsername.orEmpty(),
+                socksPassword.orEmpty(),
+                timeoutMs
+            ) ?: return ConnectivityResult(false, 0, null, "", "No result from native check")
+            val obj = JSONObject(json)
+            ConnectivityResult(
+                success = obj.optBoolean("success"),
+                latencyMs = obj.optLong("latency_ms"),
+                statusCode = if (obj.isNull("status_code")) null else obj.optInt("status_code"),
+                snippet = obj.optString("snippet"),
+                error = if (obj.isNull("error")) null else obj.optString("error")
+            )
+        } catch (e: Exception) {
+            Log.e(TAG, "Error verifying connectivity to $url", e)
+            ConnectivityResult(false, 0, null, "", e.message ?: e.toString())
+        }
+    }
+
+    /**
+     * Get a JSON object of latencies seen by [verifyConnectivity], keyed by
+     * "scheme://host[:port]": latency_ms, min_latency_ms, successes and failures.
+     * Probe a set of test URLs first to rank them. Holds at most 32 destinations.
+     */
+    fun getDestinationLatencies(): String {
+        if (!isLibraryLoaded) return "{}"
+        return try {
+            nativeGetDestinationLatencies() ?: "{}"
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading destination latencies", e)
+            "{}"
+        }
+    }
+
+    data class ExitIpResult(
+        val ip: String?,
+        val cached: Boolean,
+        val latencyMs: Long,
+        val error: String?
+    )
+
+    /**
+     * Look up the public IP that traffic leaves the tunnel from, by asking the echo
+     * service at [url] through the tunnel (empty for https://ifconfig.me/ip). An
+     * answer is reused for a minute. Blocks for up to [timeoutMs]; call it off the
+     * main thread.
+     */
+    fun getExitIp(
+        url: String = "",
+        socksUsername: String? = null,
+        socksPassword: String? = null,
+        timeoutMs: Int = 10000
+    ): ExitIpResult {
+        if (!isLibraryLoaded) {
+            return ExitIpResult(null, false, 0, "Native library not loaded")
+        }
+        return try {
+            val json = nativeGetExitIp(
+                url,
+                currentPort,
+                socksUsername.orEmpty(),
+                socksPassword.orEmpty(),
+                timeoutMs
+            ) ?: return ExitIpResult(null, false, 0, "No result from native lookup")
+            val obj = JSONObject(json)
+            ExitIpResult(
+                ip = if (obj.isNull("ip")) null else obj.optString("ip"),
+                cached = obj.optBoolean("cached"),
+                latencyMs = obj.optLong("latency_ms"),
+                error = if (obj.isNull("error")) null else obj.optString("error")
+            )
+        } catch (e: Exception) {
+            Log.e(TAG, "Error looking up exit IP", e)
+            ExitIpResult(null, false, 0, e.message ?: e.toString())
+        }
+    }
+
+    /**
+     * Push data through the tunnel to a TCP echo server for [durationMs] (at most
+     * 30 s) and measure it. Blocks; never call it on the UI thread.
+     * Returns a JSON object: success, latency_ms, upload_bytes, download_bytes,
+     * upload_bps, download_bps, duration_ms and error. Only one test runs at a time.
+     */
+    fun runSpeedTest(
+        echoHost: String,
+        echoPort: Int,
+        durationMs: Int = 10000,
+        socksUsername: String? = null,
+        socksPassword: String? = null
+    ): String {
+        if (!isLibraryLoaded) return "{\"success\":false,\"error\":\"Native library not loaded\"}"
+        return try {
+            nativeRunSpeedTest(
+                echoHost,
+                echoPort,
+                currentPort,
+                socksUsername.orEmpty(),
+                socksPassword.orEmpty(),
+                durationMs
+            ) ?: "{\"success\":false,\"error\":\"No result from native speed test\"}"
+        } catch (e: Exception) {
+            Log.e(TAG, "Error running speed test to $echoHost:$echoPort", e)
+            JSONObject().put("success", false).put("error", e.message ?: e.toString()).toString()
+        }
+    }
+
+    /**
+     * Stop a [verifyConnectivity], [getExitIp] or [runSpeedTest] call running on another thread,
+     * e.g. when the screen that started it is dismissed. The call returns with
+     * error "Cancelled".
+     */
+    fun cancelProbe() {
+        if (!isLibraryLoaded) return
+        try {
+            nativeCancelProbe()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error cancelling probe", e)
+        }
+    }
+
+    /**
+     * Report that a network became available, so a client waiting while offline
+     * reconnects right away instead of at its next recheck. Harmless while online.
+     */
+    fun notifyNetworkChanged() {
+        if (!isLibraryLoaded) return
+        try {
+            nativeNotifyNetworkChanged()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error notifying network change", e)
+        }
+    }
+
+    /**
+     * Export the effective configuration of the running (or last started) client as a
+     * versioned JSON profile. Settings that cannot be shared, such as a pinned
+     * certificate path, are left out and named in "reentry_required".
+     * Returns null when no client has been started.
+     */
+    fun exportProfile(): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeExportProfile()?.takeIf { it.isNotEmpty() }
+        } catch (e: Exception) {
+            Log.e(TAG, "Error exporting profile", e)
+            null
+        }
+    }
+
+    /**
+     * Validate a profile from [exportProfile], possibly written by a newer version.
+     * Unknown fields are dropped and missing ones take their defaults; the result is
+     * the profile re-encoded with the fields this version understands.
+     */
+    fun importProfile(json: String): Result<String> {
+        if (!isLibraryLoaded) {
+            return Result.failure(IllegalStateException("Native library not loaded"))
+        }
+        return try {
+            val profile = nativeImportProfile(json)
+            if (profile.isNullOrEmpty()) {
+                Result.failure(IllegalArgumentException("Invalid profile"))
+            } else {
+                Result.success(profile)
+            }
+        } catch (e: Exception) {
+            Log.e(TAG, "Error importing profile", e)
+            Result.failure(e)
+        }
+    }
+
+    /**
+     * Set the network the client is on, e.g. a hash of the Wi-Fi SSID. Tuning the client
+     * learns (keep-alive interval, per-resolver query rate, dead resolvers) is kept under
+     * this id when a connection ends, and the next connection on the same network starts
+     * from it. Pass null or an empty id to stop keeping tuning.
+     */
+    fun setNetworkId(networkId: String?) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetNetworkId(networkId ?: "")
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting network id", e)
+        }
+    }
+
+    /**
+     * Export the tuning learned on every network as versioned JSON, to be stored and
+     * passed to [importLearnedTuning] on a later start. Returns null on failure.
+     */
+    fun exportLearnedTuning(): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeExportLearnedTuning()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error exporting learned tuning", e)
+            null
+        }
+    }
+
+    /**
+     * Import tuning from [exportLearnedTuning], replacing what is kept for the networks
+     * it covers. Returns the number of networks imported.
+     */
+    fun importLearnedTuning(json: String): Result<Int> {
+        if (!isLibraryLoaded) {
+            return Result.failure(IllegalStateException("Native library not loaded"))
+        }
+        return try {
+            val count = nativeImportLearnedTuning(json)
+            if (count < 0) {
+                Result.failure(IllegalArgumentException("Invalid learned tuning"))
+            } else {
+                Result.success(count)
+            }
+        } catch (e: Exception) {
+            Log.e(TAG, "Error importing learned tuning", e)
+            Result.failure(e)
+        }
+    }
+
+    /**
+     * Choose how a start behaves while a client is already running: one of
+     * [DUPLICATE_START_REJECT] or [DUPLICATE_START_REUSE_OR_RESTART].
+     */
+    fun setDuplicateStartPolicy(policy: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetDuplicateStartPolicy(policy)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting duplicate start policy", e)
+        }
+    }
+
+    /**
+     * Choose whether the next client start protects its DNS socket: one of
+     * [PROTECTION_VPN] or [PROTECTION_NONE].
+     */
+    fun setProtectionMode(mode: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetProtectionMode(mode)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting protection mode", e)
+        }
+    }
+
+    /**
+     * Choose what the next client start does when protection is lost mid-session: one
+     * of [PROTECTION_LOSS_HOLD] or [PROTECTION_LOSS_STOP]. [protectionLostListener] is
+     * told either way.
+     */
+    fun setProtectionLossPolicy(policy: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetProtectionLossPolicy(policy)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting protection loss policy", e)
+        }
+    }
+
+    /**
+     * Log resolver addresses as hashes that stay the same for one run ([enabled], the
+     * default in release builds) or in full, so logs can be shared without revealing
+     * which resolvers are in use. Applies immediately.
+     */
+    fun setLogPrivacy(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetLogPrivacy(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting log privacy", e)
+        }
+    }
+
+    /**
+     * Stop the client after [queries] DNS queries, counted across reconnects, starting
+     * with the next client start. [limitReachedListener] is told when it stops.
+     * 0 means unlimited (the default).
+     */
+    fun setMaxLifetimeQueries(queries: Long) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetMaxLifetimeQueries(queries)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting lifetime query limit", e)
+        }
+    }
+
+    /**
+     * Starting with the next client start, keep at most one DNS query unanswered at a
+     * time and send no duplicate handshake queries. Traffic then looks most like an
+     * ordinary resolver's, at a large cost in throughput. Off by default.
+     */
+    fun setSingleQueryMode(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetSingleQueryMode(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting pipeline mode", e)
+        }
+    }
+
+    /**
+     * Keep query names within [chars] characters, starting with the next client
+     * start, to avoid length-based DNS filters at the cost of less data per query.
+     * 0 allows the DNS maximum of 253 (the default).
+     */
+    fun setMaxQnameLen(chars: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetMaxQnameLen(chars)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting query name limit", e)
+        }
+    }
+
+    /**
+     * Spell query names with only the letters a to p, starting with the next client
+     * start, for resolvers that reject or mangle the default base32 labels. Carries
+     * about a fifth less data per query and needs a server that supports it.
+     */
+    fun setSafeLabelEncoding(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetSafeLabelEncoding(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting label encoding", e)
+        }
+    }
+
+    /**
+     * Starting with the next client start, count a resolver's answers that are not
+     * tunnel data, such as a real website's address under the tunnel domain, as
+     * failures so the client moves off that resolver. Such answers are dropped and
+     * reported with a "non_tunnel_response" event either way.
+     */
+    fun setAvoidNonTunnelResolvers(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetAvoidNonTunnelResolvers(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting non-tunnel response policy", e)
+        }
+    }
+
+    /**
+     * Starting with the next client start, move an authoritative resolver whose answers
+     * show it is a recursive resolver to recursive polling, so paced query bursts do not
+     * get it rate limited. Mismatched modes are reported with a "resolver_mode_mismatch"
+     * event either way.
+     */
+    fun setAdaptResolverModes(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetAdaptResolverModes(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting resolver mode mismatch policy", e)
+        }
+    }
+
+    /**
+     * Starting with the next client start, retry with a plainer query form when a
+     * resolver keeps answering tunnel queries with FORMERR: first without the DNS Cookie,
+     * then in class IN. On by default; disabled, the queries stay as they are. Either way
+     * a "resolver_rejects_encoding" event reports it.
+     */
+    fun setAdaptToFormerr(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetAdaptToFormerr(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting FORMERR policy", e)
+        }
+    }
+
+    /**
+     * Let later client starts give resolvers missing from the ports array port 53,
+     * logging each index, instead of failing with "Invalid resolver configuration".
+     * Off by default, so a ports array shorter than the hosts array is caught.
+     */
+    fun setDefaultMissingPorts(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetDefaultMissingPorts(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting missing port policy", e)
+        }
+    }
+
+    /**
+     * Starting with the next client start, treat [attempts] failed reconnects in a row
+     * with the same failure as a blocked domain and retry only every [backoffMs] from
+     * then on. [persistentBlockListener] is told when that happens. 0 attempts disables
+     * detection (the default).
+     */
+    fun setBlockBackoff(attempts: Int, backoffMs: Long) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetBlockBackoff(attempts, backoffMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting block backoff", e)
+        }
+    }
+
+    /**
+     * Starting with the next client start, stop reconnecting for good after [attempts]
+     * reconnects without a ready connection, or once reconnecting has gone on for
+     * [durationMs], whichever comes first; a "gave_up" tunnel event reports it. 0 lifts a
+     * limit. Defaults to 1000 attempts and no time limit.
+     */
+    fun setReconnectLimit(attempts: Int, durationMs: Long) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetReconnectLimit(attempts, durationMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting reconnect limit", e)
+        }
+    }
+
+    /**
+     * Once no tunnel data has moved for 2 seconds, slow polling down to one poll per up
+     * to [maxIntervalMs], starting with the next client start. Saves battery between
+     * uses; new data or a new connection restores full-rate polling at once. 0 always
+     * polls at full rate (the default).
+     */
+    fun setIdlePolling(maxIntervalMs: Long) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetIdlePolling(maxIntervalMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting idle polling", e)
+        }
+    }
+
+    /**
+     * Start idle polling at one poll per [minIntervalMs], doubling from there up to the
+     * [setIdlePolling] cap while no connection is open, starting with the next client
+     * start. Open but quiet connections stay at this floor, so it bounds how long their
+     * next reply can wait for a poll (default 500).
+     */
+    fun setIdlePollFloor(minIntervalMs: Long) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetIdlePollFloor(minIntervalMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting idle poll floor", e)
+        }
+    }
+
+    /**
+     * Once the tunnel has been quiet for [intervalMs], send a single poll to check that
+     * the session still answers, starting with the next client start. If it goes
+     * unanswered the client reconnects at once instead of waiting for the session to
+     * time out. Lighter than a short keep-alive for holding NAT mappings open between
+     * uses. 0 disables the refresh (the default).
+     */
+    fun setIdleRefresh(intervalMs: Long) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetIdleRefresh(intervalMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting idle refresh", e)
+        }
+    }
+
+    /**
+     * Keep [count] polls waiting at the server, starting with the next client start, so
+     * data it queues for the device (server-initiated or the rest of a slow response) is
+     * sent at once instead of on the next poll. Needs a server started with
+     * `--poll-hold-ms`; against other servers it does nothing. 0 disables the pool (the
+     * default); at most 32.
+     */
+    fun setPollPool(count: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetPollPool(count)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting poll pool", e)
+        }
+    }
+
+    /**
+     * Request [recvBytes]/[sendBytes] socket buffers for the DNS socket, starting with the
+     * next client start. Larger receive buffers stop bursts of responses being dropped on
+     * fast paths; the OS may grant less, and the granted sizes are logged. 0 keeps the OS
+     * default.
+     */
+    fun setUdpBufferSizes(recvBytes: Int, sendBytes: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetUdpBufferSizes(recvBytes, sendBytes)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting UDP buffer sizes", e)
+        }
+    }
+
+    /**
+     * Answer UDP DNS on 127.0.0.1:[port], starting with the next client start. Each query
+     * is resolved at [upstream] (HOST:PORT, empty for 1.1.1.1:53) as DNS over TCP through
+     * the tunnel's SOCKS5 proxy, so lookups never leave the device in the clear. An empty
+     * [username] skips proxy authentication; a [port] of 0 disables the forwarder.
+     */
+    fun setDnsForwarder(port: Int, upstream: String, username: String, password: String) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetDnsForwarder(port, upstream, username, password)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting DNS forwarder", e)
+        }
+    }
+
+    /**
+     * Report the tunnel as ready ([isQuicReady]) only once [count] distinct resolvers
+     * carried tunnel data, starting with the next client start. This avoids a connected
+     * state that rests on one resolver which answered briefly. 1 (the default) reports
+     * ready as soon as the handshake completes.
+     */
+    fun setConfirmResolvers(count: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetConfirmResolvers(count)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting confirm resolvers", e)
+        }
+    }
+
+    /**
+     * Also send each handshake query to up to [copies] other resolvers, starting with
+     * the next client start, so one resolver dropping it does not stall connecting for
+     * a retransmission timeout. Costs a few extra queries per connect; data after the
+     * handshake is sent once. 0 (the default) disables it.
+     */
+    fun setHandshakeRedundancy(copies: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetHandshakeRedundancy(copies)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting handshake redundancy", e)
+        }
+    }
+
+    /**
+     * Send the first [count] connections opened within [durationMs] of the handshake
+     * ahead of later ones, starting with the next client start, so the page that
+     * triggered the connect loads before background traffic. 0 disables the boost.
+     */
+    fun setFirstStreamBoost(count: Int, durationMs: Long) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetFirstStreamBoost(count, durationMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting first stream boost", e)
+        }
+    }
+
+    /**
+     * Send DNS Cookies to every resolver, starting with the next client start, for
+     * resolvers that drop or rate-limit queries without one. Each resolver's server
+     * cookie is learned from its responses and echoed. Off by default.
+     */
+    fun setEdnsCookies(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetEdnsCookies(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting EDNS cookies", e)
+        }
+    }
+
+    /**
+     * Stream native log lines at [minPriority] or more severe to [logLineListener] as
+     * they are logged, at most [maxLinesPerSec] a second (0 for no limit), for a live
+     * debug console. Takes effect at once and replaces any running stream. Returns
+     * false if the stream could not be started.
+     */
+    fun setLogStream(minPriority: Int = Log.INFO, maxLinesPerSec: Int = 50): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetLogStream(minPriority, maxLinesPerSec)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error starting log stream", e)
+            false
+        }
+    }
+
+    /**
+     * Stop the stream started by [setLogStream].
+     */
+    fun clearLogStream() {
+        if (!isLibraryLoaded) return
+        try {
+            nativeClearLogStream()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error stopping log stream", e)
+        }
+    }
+
+    /**
+     * Replace the native logcat filter with [level], in RUST_LOG syntax: "debug", or
+     * per-module levels such as "info,slipstream_client=trace". Applies at once, also to a
+     * running client; [setLogStream] keeps its own level. Returns false when [level] is
+     * invalid.
+     */
+    fun setLogLevel(level: String): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetLogLevel(level)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting log level", e)
+            false
+        }
+    }
+
+    /**
+     * Starting with the next client start, learn how many queries per second each resolver
+     * tolerates from its REFUSED answers and dropped polls, and stay under that rate while
+     * probing slowly back up. The learned rate shows as "learned_qps" in each resolver's
+     * session info. Off by default.
+     */
+    fun setAdaptiveQueryRate(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetAdaptiveQueryRate(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting adaptive query rate", e)
+        }
+    }
+
+    /**
+     * Starting with the next client start, send each resolver [initialQps] queries per second
+     * after connecting, doubling every [doublingMs] while the tunnel asks for more, so the
+     * startup burst does not trip rate limiters. 0 (the default) sends at full rate at once.
+     */
+    fun setQueryRamp(initialQps: Int, doublingMs: Long = 500) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetQueryRamp(initialQps, doublingMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting query ramp", e)
+        }
+    }
+
+    /**
+     * Send streams that queue data slowly, like SSH or chat, ahead of streams
+     * uploading in bulk, starting with the next client start. Off by default.
+     */
+    fun setPrioritizeInteractive(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetPrioritizeInteractive(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting interactive priority", e)
+        }
+    }
+
+    /**
+     * Hold streams sending in bulk to a congestion window of their own, which backs off
+     * on losses and queueing, so a struggling download does not throttle interactive
+     * streams. Starts with the next client start; off by default.
+     */
+    fun setCongestionIsolation(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetCongestionIsolation(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting congestion isolation", e)
+        }
+    }
+
+    /**
+     * Make the resolver that delivers the most tunnel data per second the primary,
+     * starting with the next client start, instead of the first usable one in the
+     * configured order. Useful for bulk transfers over resolvers of uneven capacity.
+     * Off by default.
+     */
+    fun setPreferBandwidth(enabled: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetPreferBandwidth(enabled)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting resolver preference", e)
+        }
+    }
+
+    /**
+     * Keep at most [count] path probes to extra resolvers pending at once, starting with
+     * the next client start, instead of probing every resolver right after the handshake.
+     * 0 (the default) is unlimited.
+     */
+    fun setMaxParallelProbes(count: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetMaxParallelProbes(count)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting parallel probe limit", e)
+        }
+    }
+
+    /**
+     * Keep at most [count] accepted local connections waiting to be set up at once,
+     * starting with the next client start, so a connection storm cannot pile up
+     * sockets. Further connections wait briefly and are then refused. 0 (the default)
+     * is unlimited.
+     */
+    fun setMaxConnectionSetups(count: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetMaxConnectionSetups(count)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting connection setup limit", e)
+        }
+    }
+
+    /**
+     * Cap tunnel data received but not yet read by local apps at [bytes], counting
+     * data that arrived out of order, starting with the next client start. At the cap
+     * the server stops sending until the apps catch up, which bounds memory on
+     * low-RAM devices. Values below 64 KiB are raised to it; 0 (the default) keeps
+     * the built-in 8 MiB.
+     */
+    fun setReassemblyLimit(bytes: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetReassemblyLimit(bytes)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting reassembly limit", e)
+        }
+    }
+
+    /**
+     * Report disconnects and stalls to [tunnelEventListener] only once they lasted
+     * [graceMs], starting with the next client start. Defaults to 3000.
+     */
+    fun setEventGrace(graceMs: Long) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetEventGrace(graceMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting event grace window", e)
+        }
+    }
+
+    /**
+     * Run the tunnel only inside [windows], starting with the next client start. Each
+     * window is "HH:MM-HH:MM" in the device's local time, and one that ends before it
+     * starts runs past midnight. Outside them the client pauses; see
+     * [scheduledPauseListener]. An empty list keeps the tunnel always active.
+     *
+     * @return false if a window is invalid; the previous windows are kept
+     */
+    fun setActiveWindows(windows: List<String>): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetActiveWindows(windows.joinToString(","))
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting active windows", e)
+            false
+        }
+    }
+
+    /**
+     * Keep background activity low while the tunnel is idle inside [windows], from the
+     * next start on, e.g. "23:00-07:00" to save battery overnight. Unlike
+     * [setActiveWindows] the tunnel stays connected: keep-alives go to their longest,
+     * polls are spaced out and the poll pool is not topped up. Any traffic, or the end
+     * of the window, restores normal activity. An empty list turns quiet hours off.
+     *
+     * @return false if a window is invalid; the previous windows are kept
+     */
+    fun setQuietHours(windows: List<String>): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetQuietHours(windows.joinToString(","))
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting quiet hours", e)
+            false
+        }
+    }
+
+    /**
+     * Refuse SOCKS CONNECTs to [entries] before anything is sent through the tunnel.
+     * Each entry is a domain suffix such as "example.com", which also covers its
+     * subdomains, or a CIDR such as "10.0.0.0/8". Takes effect for new connections
+     * right away; an empty list blocks nothing.
+     *
+     * @return false if an entry is invalid; the previous blocklist is kept
+     */
+    fun setDestinationBlocklist(entries: List<String>): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetDestinationBlocklist(entries.joinToString(","))
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting destination blocklist", e)
+            false
+        }
+    }
+
+    /**
+     * Whether a CONNECT to [host], a name or an IP literal, is on the blocklist set by
+     * [setDestinationBlocklist].
+     */
+    fun isDestinationBlocked(host: String): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeIsDestinationBlocked(host)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error checking destination blocklist", e)
+            false
+        }
+    }
+
+    /**
+     * Set what the SOCKS bridge does with a CONNECT to a destination that already has
+     * one in flight, or whose last one failed, within [windowMs]: "allow" (the
+     * default), "coalesce" to wait for the earlier attempt and share its outcome, or
+     * "refuse". Returns false when [policy] is not one of these.
+     */
+    fun setDuplicateConnectPolicy(policy: String, windowMs: Long): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetDuplicateConnectPolicy(policy, windowMs)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting duplicate CONNECT policy", e)
+            false
+        }
+    }
+
+    /**
+     * Whether a CONNECT to [host]:[port] may open a stream: [CONNECT_PROCEED],
+     * [CONNECT_WAIT] or [CONNECT_REFUSE]. See [setDuplicateConnectPolicy].
+     */
+    fun beginConnect(host: String, port: Int): Int {
+        if (!isLibraryLoaded) return CONNECT_PROCEED
+        return try {
+            nativeBeginConnect(host, port)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error checking duplicate CONNECT", e)
+            CONNECT_PROCEED
+        }
+    }
+
+    /** Report whether a CONNECT that [beginConnect] let through [connected]. */
+    fun finishConnect(host: String, port: Int, connected: Boolean) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeFinishConnect(host, port, connected)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reporting CONNECT outcome", e)
+        }
+    }
+
+    /**
+     * Cap the CONNECTs the SOCKS bridge keeps open to one destination host at
+     * [defaultLimit], with per-host [overrides] (host to cap). The bridge refuses a
+     * CONNECT over its host's cap with reply 0x02. 0 is no cap (the default). Open
+     * counts per host show in the session info as "host_connections". Returns false
+     * when an override is invalid.
+     */
+    fun setHostConnectionLimits(defaultLimit: Int, overrides: Map<String, Int> = emptyMap()): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetHostConnectionLimits(
+                defaultLimit,
+                overrides.entries.joinToString(",") { "${it.key}=${it.value}" }
+            )
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting host connection limits", e)
+            false
+        }
+    }
+
+    /**
+     * Count a CONNECT to [host] against its cap; false when it is over the cap. Report
+     * the end of a connection it let through with [closeHostConnection].
+     */
+    fun openHostConnection(host: String): Boolean {
+        if (!isLibraryLoaded) return true
+        return try {
+            nativeOpenHostConnection(host)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error checking host connection limit", e)
+            true
+        }
+    }
+
+    /** Report that a CONNECT [openHostConnection] let through has ended. */
+    fun closeHostConnection(host: String) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeCloseHostConnection(host)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reporting host connection close", e)
+        }
+    }
+
+    /**
+     * Report the TLS [version], e.g. "TLSv1.3", of a successful DoH handshake by the SOCKS
+     * bridge. It shows in the session info as "doh_tls_version".
+     */
+    fun reportDohHandshake(version: String) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeReportDohHandshake(version)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reporting DoH handshake", e)
+        }
+    }
+
+    /**
+     * Report a DoH handshake that could not negotiate the minimum TLS version, counted in
+     * the session info as "doh_tls_below_minimum".
+     */
+    fun reportDohBelowMinimum() {
+        if (!isLibraryLoaded) return
+        try {
+            nativeReportDohBelowMinimum()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reporting DoH TLS failure", e)
+        }
+    }
+
+    /** Forget the DoH TLS state reported so far, e.g. when the SOCKS bridge restarts. */
+    fun resetDohTls() {
+        if (!isLibraryLoaded) return
+        try {
+            nativeResetDohTls()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error resetting DoH TLS state", e)
+        }
+    }
+
+    /**
+     * Set what the SOCKS bridge reports as the bound address of a successful CONNECT:
+     * "relay" (the default) passes on the remote proxy's, "zero" sends 0.0.0.0:0 or
+     * [::]:0 in the family of the request, "destination" echoes the requested address,
+     * and an "IP:PORT" is sent as given. Returns false when [policy] is none of these.
+     */
+    fun setBoundAddressPolicy(policy: String): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeSetBoundAddressPolicy(policy)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error setting bound address policy", e)
+            false
+        }
+    }
+
+    /**
+     * The success reply to a CONNECT for [requested], its ATYP, address and port, when
+     * the remote proxy reported [relayed] as the bound address. See
+     * [setBoundAddressPolicy].
+     */
+    fun socksConnectReply(requested: ByteArray, relayed: ByteArray): ByteArray {
+        val relayReply = byteArrayOf(0x05, 0x00, 0x00) + relayed
+        if (!isLibraryLoaded) return relayReply
+        return try {
+            nativeSocksConnectReply(requested, relayed)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error building CONNECT reply", e)
+            relayReply
+        }
+    }
+
+    /**
+     * Report why a CONNECT to [host]:[port] failed: the SOCKS5 [reply] code the server
+     * sent, or [CONNECT_NO_REPLY]. Read back with [getLastDestinationError].
+     */
+    fun reportConnectFailure(host: String, port: Int, reply: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeReportConnectFailure(host, port, reply)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reporting CONNECT failure", e)
+        }
+    }
+
+    /**
+     * Why the latest CONNECT to [host] failed, as JSON with reason, reply, port and
+     * age_ms, or null when none is recorded. reason is "dns_timeout",
+     * "resolver_refused", "connect_refused", "unreachable", "blocked" or
+     * "proxy_failure". The latest 64 hosts are kept.
+     */
+    fun getLastDestinationError(host: String): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeGetLastDestinationError(host)?.takeIf { it.isNotEmpty() }
+        } catch (e: Exception) {
+            Log.e(TAG, "Error reading destination error", e)
+            null
+        }
+    }
+
+    /**
+     * Keep the last [records] DNS packets in memory, starting with the next client
+     * start, so a support capture can be taken with [exportCapture]. Only wire bytes
+     * are kept; tunneled data stays encrypted. 0 disables the recorder.
+     */
+    fun setFlightRecorder(records: Int) {
+        if (!isLibraryLoaded) return
+        try {
+            nativeSetFlightRecorder(records)
+        } catch (e: Exception) {
+            Log.e(TAG, "Error configuring flight recorder", e)
+        }
+    }
+
+    /**
+     * Export the flight recorder as capture text for the offline replay tool.
+     * Still available after the client stops; returns null when it was not enabled.
+     */
+    fun exportCapture(): String? {
+        if (!isLibraryLoaded) return null
+        return try {
+            nativeExportCapture()?.takeIf { it.isNotEmpty() }
+        } catch (e: Exception) {
+            Log.e(TAG, "Error exporting capture", e)
+            null
+        }
+    }
+
+    /**
+     * Re-resolve this class for native callbacks after a hot reload or class loader
+     * change left the cached reference stale. Returns false, keeping the old
+     * reference, if the class cannot be found.
+     */
+    fun refreshBridgeClass(): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeRefreshBridgeClass()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error refreshing bridge class", e)
+            false
+        }
+    }
+
+    /**
+     * Check if the slipstream client is running (native flag).
+     */
+    fun isClientRunning(): Boolean {
+        if (!isLibraryLoaded) return false
+        return try {
+            nativeIsClientRunning()
+        } catch (e: Exception) {
+            Log.e(TAG, "Error checking native running state", e)
+            false
+        }
+    }
+
+    /**
+     * Check if the client is running AND the port is actually listening.
+     * Use this for health checks after connection is established.
+     */
+    fun isClientHealthy(): Boolean {
+        if (!isClientRunning()) return false
+
+        // Verify the port is actually listening
+        return try {
+            java.net.Socket().use { socket ->
+                socket.connect(java.net.InetSocketAddress("127.0.0.1", currentPort), 200)
+                true
+            }
+        } catch (e: Exception) {
+            Log.w(TAG, "Client reports running but port $currentPort is not listening")
+            false
+        }
+    }
+
+    /**
+     * Get the port the slipstream client is listening on.
+     */
+    fun getClientPort(): Int = currentPort
+
+    /**
+     * Check if a port is currently in use (bound by another socket).
+     * This tries to bind a server socket to the port - if it fails, the port is in use.
+     * This is more reliable than trying to connect, because a stuck/abandoned listener
+     * may not be accepting connections but still has the port bound.
+     */
+    private fun isPortInUse(port: Int): Boolean {
+        return try {
+            java.net.ServerSocket().use { serverSocket ->
+                serverSocket.reuseAddress = true
+                serverSocket.bind(java.net.InetSocketAddress("127.0.0.1", port))
+                // Successfully bound - port is free
+                false
+            }
+        } catch (e: java.net.BindException) {
+            // Port is in use
+            true
+        } catch (e: Exception) {
+            // Other error - assume port might be in use to be safe
+            Log.w(TAG, "Error checking port $port: ${e.message}")
+            true
+        }
+    }
+
+    // Native methods - matches slipstream-client CLI parameters
+    private external fun nativeStartSlipstreamClient(
+        domain: String,
+        resolverHosts: Array<String>,
+        resolverPorts: IntArray,
+        resolverAuthoritative: BooleanArray,
+        listenPort: Int,
+        listenHost: String,
+        congestionControl: String,
+        keepAliveInterval: Int,
+        gsoEnabled: Boolean,
+        debugPoll: Boolean,
+        debugStreams: Boolean
+    ): Int
+
+    private external fun nativeValidateConfig(
+        domain: String,
+        resolverHosts: Array<String>,
+        resolverPorts: IntArray,
+        resolverAuthoritative: BooleanArray,
+        listenPort: Int,
+        listenHost: String,
+        congestionControl: String,
+        keepAliveInterval: Int,
+        gsoEnabled: Boolean,
+        debugPoll: Boolean,
+        debugStreams: Boolean
+    ): String?
+
+    private external fun nativeReloadConfig(
+        domain: String,
+        resolverHosts: Array<String>,
+        resolverPorts: IntArray,
+        resolverAuthoritative: BooleanArray,
+        listenPort: Int,
+        listenHost: String,
+        congestionControl: String,
+        keepAliveInterval: Int,
+        gsoEnabled: Boolean,
+        debugPoll: Boolean,
+        debugStreams: Boolean
+    ): String?
+
+    private external fun nativeStopSlipstreamClient()
+    private external fun nativeIsClientRunning(): Boolean
+    private external fun nativeRefreshBridgeClass(): Boolean
+    private external fun nativeIsQuicReady(): Boolean
+    private external fun nativeCloseStream(streamId: Long): Int
+    private external fun nativeForceResolver(index: Int, queryCount: Int): Int
+    private external fun nativeGetSessionInfo(): String?
+    private external fun nativeGetSessionId(): String?
+    private external fun nativeGetLastSessionSummary(): String?
+    private external fun nativeGetResolverStates(): String?
+    private external fun nativeVerifyConnectivity(
+        url: String,
+        listenPort: Int,
+        socksUsername: String,
+        socksPassword: String,
+        timeoutMs: Int
+    ): String?
+    private external fun nativeGetExitIp(
+        url: String,
+        listenPort: Int,
+        socksUsername: String,
+        socksPassword: String,
+        timeoutMs: Int
+    ): String?
+    private external fun nativeRunSpeedTest(
+        echoHost: String,
+        echoPort: Int,
+        listenPort: Int,
+        socksUsername: String,
+        socksPassword: String,
+        durationMs: Int
+    ): String?
+    private external fun nativeCancelProbe()
+    private external fun nativeNotifyNetworkChanged()
+    private external fun nativeGetDestinationLatencies(): String?
+    private external fun nativeExportProfile(): String?
+    private external fun nativeImportProfile(json: String): String?
+    private external fun nativeSetNetworkId(networkId: String)
+    private external fun nativeExportLearnedTuning(): String?
+    private external fun nativeImportLearnedTuning(json: String): Int
+    private external fun nativeSetFlightRecorder(records: Int)
+    private external fun nativeSetDuplicateStartPolicy(policy: Int)
+    private external fun nativeSetProtectionMode(mode: Int)
+    private external fun nativeSetProtectionLossPolicy(policy: Int)
+    private external fun nativeSetLogPrivacy(enabled: Boolean)
+    private external fun nativeSetMaxLifetimeQueries(queries: Long)
+    private external fun nativeSetSingleQueryMode(enabled: Boolean)
+    private external fun nativeSetMaxQnameLen(chars: Int)
+    private external fun nativeSetSafeLabelEncoding(enabled: Boolean)
+    private external fun nativeSetAvoidNonTunnelResolvers(enabled: Boolean)
+    private external fun nativeSetAdaptResolverModes(enabled: Boolean)
+    private external fun nativeSetAdaptToFormerr(enabled: Boolean)
+    private external fun nativeSetDefaultMissingPorts(enabled: Boolean)
+    private external fun nativeSetBlockBackoff(attempts: Int, backoffMs: Long)
+    private external fun nativeSetReconnectLimit(attempts: Int, durationMs: Long)
+    private external fun nativeSetUdpBufferSizes(recvBytes: Int, sendBytes: Int)
+    private external fun nativeSetDnsForwarder(port: Int, upstream: String, username: String, password: String)
+    private external fun nativeSetHandshakeRedundancy(copies: Int)
+    private external fun nativeSetConfirmResolvers(count: Int)
+    private external fun nativeSetFirstStreamBoost(count: Int, durationMs: Long)
+    private external fun nativeSetEdnsCookies(enabled: Boolean)
+    private external fun nativeSetAdaptiveQueryRate(enabled: Boolean)
+    private external fun nativeSetQueryRamp(initialQps: Int, doublingMs: Long)
+    private external fun nativeSetLogStream(minPriority: Int, maxLinesPerSec: Int): Boolean
+    private external fun nativeClearLogStream()
+    private external fun nativeSetLogLevel(level: String): Boolean
+    private external fun nativeSetPrioritizeInteractive(enabled: Boolean)
+    private external fun nativeSetCongestionIsolation(enabled: Boolean)
+    private external fun nativeSetPreferBandwidth(enabled: Boolean)
+    private external fun nativeSetMaxParallelProbes(count: Int)
+    private external fun nativeSetMaxConnectionSetups(count: Int)
+    private external fun nativeSetReassemblyLimit(bytes: Int)
+    private external fun nativeSetEventGrace(graceMs: Long)
+    private external fun nativeSetActiveWindows(windows: String): Boolean
+    private external fun nativeSetQuietHours(windows: String): Boolean
+    private external fun nativeSetDestinationBlocklist(entries: String): Boolean
+    private external fun nativeIsDestinationBlocked(host: String): Boolean
+    private external fun nativeSetDuplicateConnectPolicy(policy: String, windowMs: Long): Boolean
+    private external fun nativeBeginConnect(host: String, port: Int): Int
+    private external fun nativeFinishConnect(host: String, port: Int, connected: Boolean)
+    private external fun nativeSetHostConnectionLimits(defaultLimit: Int, overrides: String): Boolean
+    private external fun nativeOpenHostConnection(host: String): Boolean
+    private external fun nativeCloseHostConnection(host: String)
+    private external fun nativeReportDohHandshake(version: String)
+    private external fun nativeReportDohBelowMinimum()
+    private external fun nativeResetDohTls()
+    private external fun nativeSetBoundAddressPolicy(policy: String): Boolean
+    private external fun nativeSocksConnectReply(requested: ByteArray, relayed: ByteArray): ByteArray
+    private external fun nativeReportConnectFailure(host: String, port: Int, reply: Int)
+    private external fun nativeGetLastDestinationError(host: String): String?
+    private external fun nativeSetIdlePolling(maxIntervalMs: Long)
+    private external fun nativeSetIdlePollFloor(minIntervalMs: Long)
+    private external fun nativeSetIdleRefresh(intervalMs: Long)
+    private external fun nativeSetPollPool(count: Int)
+    private external fun nativeExportCapture(): String?
 
     /**
      * Check if the native client reports it's running (alias for isClientRunning).
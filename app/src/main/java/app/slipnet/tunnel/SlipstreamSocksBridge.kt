@@ -8,8 +8,13 @@ import java.io.OutputStream
 import java.net.InetSocketAddress
 import java.net.ServerSocket
 import java.net.Socket
+import java.net.SocketTimeoutException
 import java.util.concurrent.CopyOnWriteArrayList
+import java.util.concurrent.CountDownLatch
+import java.util.concurrent.TimeUnit
 import java.util.concurrent.atomic.AtomicBoolean
+import javax.net.ssl.SSLException
+import javax.net.ssl.SSLHandshakeException
 import javax.net.ssl.SSLSocket
 import javax.net.ssl.SSLSocketFactory
 
@@ -37,11 +42,31 @@ object SlipstreamSocksBridge {
     private fun logd(msg: String) { if (debugLogging) logd(msg) }
     private const val BUFFER_SIZE = 32768
     private const val TCP_CONNECT_TIMEOUT_MS = 10000
+    /** Default wait for the remote proxy's CONNECT reply; see [start]. */
+    const val DEFAULT_CONNECT_TIMEOUT_MS = 15000
+    private const val DUPLICATE_CONNECT_WAIT_MS = 50L
+    /** Longest wait for a warmup still authenticating; the request then connects afresh. */
+    private const val WARMUP_WAIT_MS = 10000L
+    private val TLS_VERSIONS = listOf("TLSv1.2", "TLSv1.3")
+    private val PROTOCOL_VERSION_FAILURES = listOf(
+        "protocol_version",         // alert, e.g. TLSV1_ALERT_PROTOCOL_VERSION
+        "unsupported_protocol",     // BoringSSL: the server picked a disabled version
+        "no appropriate protocol",  // JDK: no enabled version in common
+        "no protocols",
+        "not accepted by client preferences"
+    )
 
     private var slipstreamHost: String = "127.0.0.1"
     private var slipstreamPort: Int = 0
     private var socksUsername: String? = null
     private var socksPassword: String? = null
+    private var minTlsVersion: String = "TLSv1.2"
+    @Volatile private var destRewrites: Map<String, String> = emptyMap()
+    @Volatile private var warmupEnabled = false
+    @Volatile private var connectTimeoutMs = DEFAULT_CONNECT_TIMEOUT_MS
+    /** TLS version negotiated by the last successful DoH handshake, null if none yet. */
+    @Volatile var dohTlsVersion: String? = null
+        private set
     private var serverSocket: ServerSocket? = null
     private var acceptorThread: Thread? = null
     private val running = AtomicBoolean(false)
@@ -53,8 +78,15 @@ object SlipstreamSocksBridge {
         listenPort: Int,
         listenHost: String = "127.0.0.1",
         socksUsername: String? = null,
-        socksPassword: String? = null
+        socksPassword: String? = null,
+        minTlsVersion: String = "TLSv1.2",
+        destRewrites: Map<String, String> = emptyMap(),
+        warmup: Boolean = false,
+        connectTimeoutMs: Int = DEFAULT_CONNECT_TIMEOUT_MS
     ): Result<Unit> {
+        if (minTlsVersion !in TLS_VERSIONS) {
+            return Result.failure(IllegalArgumentException("Unsupported minimum TLS version: $minTlsVersion"))
+        }
         Log.i(TAG, "========================================")
         Log.i(TAG, "Starting Slipstream SOCKS5 bridge")
         Log.i(TAG, "  Slipstream: $slipstreamHost:$slipstreamPort")
@@ -66,6 +98,12 @@ object SlipstreamSocksBridge {
         this.slipstreamPort = slipstreamPort
         this.socksUsername = socksUsername
         this.socksPassword = socksPassword
+        this.minTlsVersion = minTlsVersion
+        this.dohTlsVersion = null
+        SlipstreamBridge.resetDohTls()
+        this.destRewrites = destRewrites
+        this.warmupEnabled = warmup
+        this.connectTimeoutMs = connectTimeoutMs.coerceAtLeast(0)
 
         return try {
             val ss = ServerSocket()
@@ -127,6 +165,8 @@ object SlipstreamSocksBridge {
 
     private fun handleConnection(clientSocket: Socket) {
         val thread = Thread({
+            // Closed in finally unless handleConnect took its socket
+            var warmup: Warmup? = null
             try {
                 clientSocket.use { socket ->
                     socket.soTimeout = 30000
@@ -144,6 +184,11 @@ object SlipstreamSocksBridge {
                     val methods = ByteArray(nMethods)
                     input.readFully(methods)
 
+                    // Authenticate with the remote proxy while the client sends its request
+                    if (warmupEnabled) {
+                        warmup = Warmup().also { it.start() }
+                    }
+
                     // Respond: no authentication required
                     output.write(byteArrayOf(0x05, 0x00))
                     output.flush()
@@ -203,13 +248,66 @@ object SlipstreamSocksBridge {
                         return@Thread
                     }
 
-                    // Handle CONNECT (cmd 0x01) — chain through Slipstream
-                    handleConnect(destHost, destPort, rawAddr, portBytes, socket, input, output)
+                    // Refuse blocklisted destinations before anything reaches the tunnel
+                    if (SlipstreamBridge.isDestinationBlocked(destHost)) {
+                        logd("CONNECT: $destHost:$destPort is blocklisted")
+                        output.write(byteArrayOf(0x05, 0x02, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
+                        output.flush()
+                        return@Thread
+                    }
+
+                    // Refuse CONNECTs over the destination host's connection cap
+                    if (!SlipstreamBridge.openHostConnection(destHost)) {
+                        logd("CONNECT: $destHost:$destPort over the host's connection limit")
+                        output.write(byteArrayOf(0x05, 0x02, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
+                        output.flush()
+                        return@Thread
+                    }
+                    try {
+                        // Hold back or refuse rapid duplicates, per the duplicate CONNECT policy
+                        var decision = SlipstreamBridge.beginConnect(destHost, destPort)
+                        while (decision == SlipstreamBridge.CONNECT_WAIT && running.get()) {
+                            Thread.sleep(DUPLICATE_CONNECT_WAIT_MS)
+                            decision = SlipstreamBridge.beginConnect(destHost, destPort)
+                        }
+                        if (decision != SlipstreamBridge.CONNECT_PROCEED) {
+                            logd("CONNECT: $destHost:$destPort refused as a duplicate")
+                            output.write(byteArrayOf(0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
+                            output.flush()
+                            return@Thread
+                        }
+                        var reported = false
+                        val onConnected = {
+                            reported = true
+                            SlipstreamBridge.finishConnect(destHost, destPort, true)
+                        }
+
+                        // Handle CONNECT (cmd 0x01) — chain through Slipstream
+                        try {
+                            val rewrite = rewriteDestination(destHost, destPort)
+                            if (rewrite != null) {
+                                val (host, port) = rewrite
+                                logd("CONNECT: rewriting $destHost:$destPort -> $host:$port")
+                                handleConnect(host, port, encodeSocksAddr(host),
+                                    byteArrayOf((port shr 8).toByte(), port.toByte()), socket, input,
+                                    output, warmup, onConnected)
+                            } else {
+                                handleConnect(destHost, destPort, rawAddr, portBytes, socket, input,
+                                    output, warmup, onConnected)
+                            }
+                        } finally {
+                            if (!reported) SlipstreamBridge.finishConnect(destHost, destPort, false)
+                        }
+                    } finally {
+                        SlipstreamBridge.closeHostConnection(destHost)
+                    }
                 }
             } catch (e: Exception) {
                 if (running.get()) {
                     logd("Connection handler error: ${e.message}")
                 }
+            } finally {
+                warmup?.abort()
             }
         }, "slip-bridge-handler")
         thread.isDaemon = true
@@ -219,8 +317,75 @@ object SlipstreamSocksBridge {
         connectionThreads.removeAll { !it.isAlive }
     }
 
+    /**
+     * A Slipstream connection that is opened and authenticated with the remote
+     * proxy while the client is still sending its SOCKS5 request, so the first
+     * request of a connection saves the greeting and auth round trips through the
+     * tunnel. [abort] closes it unless [take] already handed it over, which also
+     * unblocks a warmup still waiting on the tunnel.
+     */
+    private class Warmup {
+        private val done = CountDownLatch(1)
+        private val lock = Any()
+        private var socket: Socket? = null
+        private var ready = false
+        private var aborted = false
+        private var taken = false
+
+        fun start() {
+            Thread({ run() }, "slip-bridge-warmup").also { it.isDaemon = true; it.start() }
+        }
+
+        private fun run() {
+            val sock = Socket()
+            try {
+                synchronized(lock) {
+                    if (aborted) return
+                    socket = sock
+                }
+                sock.connect(InetSocketAddress(slipstreamHost, slipstreamPort), TCP_CONNECT_TIMEOUT_MS)
+                sock.tcpNoDelay = true
+                if (performSocksAuth(sock.getInputStream(), sock.getOutputStream())) {
+                    synchronized(lock) { ready = !aborted }
+                }
+            } catch (e: Exception) {
+                logd("Warmup: ${e.message}")
+            } finally {
+                synchronized(lock) {
+                    if (!ready) try { sock.close() } catch (_: Exception) {}
+                }
+                done.countDown()
+            }
+        }
+
+        /**
+         * Waits up to [WARMUP_WAIT_MS] for the warmup; the authenticated socket, or null if it
+         * failed or is still waiting on the tunnel, in which case it is aborted.
+         */
+        fun take(): Socket? {
+            if (!done.await(WARMUP_WAIT_MS, TimeUnit.MILLISECONDS)) {
+                logd("Warmup: not authenticated within ${WARMUP_WAIT_MS}ms")
+                abort()
+                return null
+            }
+            synchronized(lock) {
+                if (!ready || aborted) return null
+                taken = true
+                return socket
+            }
+        }
+
+        fun abort() {
+            synchronized(lock) {
+                aborted = true
+                if (!taken) try { socket?.close() } catch (_: Exception) {}
+            }
+        }
+    }
+
     /**
      * Handle SOCKS5 CONNECT by chaining through Slipstream's SOCKS5 proxy.
+     * Uses the [warmup] connection when it authenticated, else connects afresh.
      */
     private fun handleConnect(
         destHost: String,
@@ -229,13 +394,20 @@ object SlipstreamSocksBridge {
         portBytes: ByteArray,
         clientSocket: Socket,
         clientInput: InputStream,
-        clientOutput: OutputStream
+        clientOutput: OutputStream,
+        warmup: Warmup? = null,
+        onConnected: () -> Unit = {}
     ) {
+        val warmSocket = warmup?.take()
+        if (warmSocket != null) {
+            logd("CONNECT: $destHost:$destPort on a warmed-up connection")
+        }
         val remoteSocket: Socket
         try {
-            remoteSocket = Socket()
-            remoteSocket.connect(InetSocketAddress(slipstreamHost, slipstreamPort), TCP_CONNECT_TIMEOUT_MS)
-            remoteSocket.tcpNoDelay = true
+            remoteSocket = warmSocket ?: Socket().also {
+                it.connect(InetSocketAddress(slipstreamHost, slipstreamPort), TCP_CONNECT_TIMEOUT_MS)
+                it.tcpNoDelay = true
+            }
         } catch (e: Exception) {
             logd("CONNECT: failed to connect to Slipstream: ${e.message}")
             clientOutput.write(byteArrayOf(0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
@@ -243,54 +415,58 @@ object SlipstreamSocksBridge {
             return
         }
 
+        var established = false
         try {
             val remoteInput = remoteSocket.getInputStream()
             val remoteOutput = remoteSocket.getOutputStream()
 
-            // SOCKS5 greeting to Slipstream → Dante (user/pass auth)
-            val hasAuth = !socksUsername.isNullOrBlank() && !socksPassword.isNullOrBlank()
-            if (hasAuth) {
-                // Offer method 0x02 (user/pass)
-                remoteOutput.write(byteArrayOf(0x05, 0x01, 0x02))
-            } else {
-                // Offer method 0x00 (no auth)
-                remoteOutput.write(byteArrayOf(0x05, 0x01, 0x00))
-            }
-            remoteOutput.flush()
-
-            val greetResp = ByteArray(2)
-            remoteInput.readFully(greetResp)
-            val selectedMethod = greetResp[1].toInt() and 0xFF
-            if (greetResp[0] != 0x05.toByte() || selectedMethod == 0xFF) {
-                Log.w(TAG, "CONNECT: Slipstream rejected greeting (${greetResp[0]}, ${greetResp[1]})")
-                clientOutput.write(byteArrayOf(0x05, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
-                clientOutput.flush()
-                remoteSocket.close()
-                return
-            }
-
-            // Perform user/pass auth sub-negotiation if server selected method 0x02
-            if (selectedMethod == 0x02) {
-                val user = socksUsername!!.toByteArray()
-                val pass = socksPassword!!.toByteArray()
-                val authReq = ByteArray(3 + user.size + pass.size)
-                authReq[0] = 0x01 // sub-negotiation version
-                authReq[1] = user.size.toByte()
-                System.arraycopy(user, 0, authReq, 2, user.size)
-                authReq[2 + user.size] = pass.size.toByte()
-                System.arraycopy(pass, 0, authReq, 3 + user.size, pass.size)
-                remoteOutput.write(authReq)
+            // A warmed-up connection has already been through this
+            if (warmSocket == null) {
+                // SOCKS5 greeting to Slipstream → Dante (user/pass auth)
+                val hasAuth = !socksUsername.isNullOrBlank() && !socksPassword.isNullOrBlank()
+                if (hasAuth) {
+                    // Offer method 0x02 (user/pass)
+                    remoteOutput.write(byteArrayOf(0x05, 0x01, 0x02))
+                } else {
+                    // Offer method 0x00 (no auth)
+                    remoteOutput.write(byteArrayOf(0x05, 0x01, 0x00))
+                }
                 remoteOutput.flush()
 
-                val authResp = ByteArray(2)
-                remoteInput.readFully(authResp)
-                if (authResp[1] != 0x00.toByte()) {
-                    Log.w(TAG, "CONNECT: Dante auth failed (status=${authResp[1]})")
+                val greetResp = ByteArray(2)
+                remoteInput.readFully(greetResp)
+                val selectedMethod = greetResp[1].toInt() and 0xFF
+                if (greetResp[0] != 0x05.toByte() || selectedMethod == 0xFF) {
+                    Log.w(TAG, "CONNECT: Slipstream rejected greeting (${greetResp[0]}, ${greetResp[1]})")
                     clientOutput.write(byteArrayOf(0x05, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
                     clientOutput.flush()
                     remoteSocket.close()
                     return
                 }
+
+                // Perform user/pass auth sub-negotiation if server selected method 0x02
+                if (selectedMethod == 0x02) {
+                    val user = socksUsername!!.toByteArray()
+                    val pass = socksPassword!!.toByteArray()
+                    val authReq = ByteArray(3 + user.size + pass.size)
+                    authReq[0] = 0x01 // sub-negotiation version
+                    authReq[1] = user.size.toByte()
+                    System.arraycopy(user, 0, authReq, 2, user.size)
+                    authReq[2 + user.size] = pass.size.toByte()
+                    System.arraycopy(pass, 0, authReq, 3 + user.size, pass.size)
+                    remoteOutput.write(authReq)
+                    remoteOutput.flush()
+
+                    val authResp = ByteArray(2)
+                    remoteInput.readFully(authResp)
+                    if (authResp[1] != 0x00.toByte()) {
+                        Log.w(TAG, "CONNECT: Dante auth failed (status=${authResp[1]})")
+                        clientOutput.write(byteArrayOf(0x05, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
+                        clientOutput.flush()
+                        remoteSocket.close()
+                        return
+                    }
+                }
             }
 
             // SOCKS5 CONNECT request to Slipstream
@@ -298,39 +474,62 @@ object SlipstreamSocksBridge {
             remoteOutput.write(connectReq)
             remoteOutput.flush()
 
-            // Read CONNECT response header (4 bytes: ver, rep, rsv, atyp)
+            // Read CONNECT response header (4 bytes: ver, rep, rsv, atyp). The remote
+            // proxy only replies once its connect to the destination finished, so a
+            // dead destination is answered with host unreachable after connectTimeoutMs.
             val connRespHeader = ByteArray(4)
-            remoteInput.readFully(connRespHeader)
+            remoteSocket.soTimeout = connectTimeoutMs
+            try {
+                remoteInput.readFully(connRespHeader)
+            } catch (e: SocketTimeoutException) {
+                logd("CONNECT: no reply for $destHost:$destPort within ${connectTimeoutMs}ms")
+                SlipstreamBridge.reportConnectFailure(destHost, destPort, 0x04)
+                clientOutput.write(byteArrayOf(0x05, 0x04, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
+                clientOutput.flush()
+                remoteSocket.close()
+                return
+            }
 
             if (connRespHeader[1] != 0x00.toByte()) {
-                logd("CONNECT: Slipstream rejected to $destHost:$destPort (rep=${connRespHeader[1]})")
+                val rep = connRespHeader[1].toInt() and 0xFF
+                if (rawAddr[0] == 0x04.toByte() && (rep == 0x03 || rep == 0x04 || rep == 0x08)) {
+                    Log.w(TAG, "CONNECT: IPv6 destination [$destHost]:$destPort unreachable, server has no IPv6 route (rep=$rep)")
+                } else {
+                    logd("CONNECT: Slipstream rejected to $destHost:$destPort (rep=$rep)")
+                }
+                SlipstreamBridge.reportConnectFailure(destHost, destPort, rep)
                 clientOutput.write(byteArrayOf(0x05, connRespHeader[1], 0x00, 0x01, 0, 0, 0, 0, 0, 0))
                 clientOutput.flush()
                 remoteSocket.close()
                 return
             }
 
-            // Read remaining response bytes based on address type
-            when (connRespHeader[3].toInt() and 0xFF) {
-                0x01 -> { // IPv4: 4 addr + 2 port
-                    val rest = ByteArray(6)
-                    remoteInput.readFully(rest)
-                }
+            // Read the bound address so it can be relayed (IPv6 included)
+            val boundAddr: ByteArray = when (connRespHeader[3].toInt() and 0xFF) {
+                0x01 -> ByteArray(6).also { remoteInput.readFully(it) } // IPv4: 4 addr + 2 port
                 0x03 -> { // Domain: 1 len + domain + 2 port
                     val len = remoteInput.read()
-                    val rest = ByteArray(len + 2)
-                    remoteInput.readFully(rest)
+                    byteArrayOf(len.toByte()) + ByteArray(len + 2).also { remoteInput.readFully(it) }
                 }
-                0x04 -> { // IPv6: 16 addr + 2 port
-                    val rest = ByteArray(18)
-                    remoteInput.readFully(rest)
+                0x04 -> ByteArray(18).also { remoteInput.readFully(it) } // IPv6: 16 addr + 2 port
+                else -> {
+                    Log.w(TAG, "CONNECT: unknown bound address type ${connRespHeader[3]} for $destHost:$destPort")
+                    clientOutput.write(byteArrayOf(0x05, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
+                    clientOutput.flush()
+                    remoteSocket.close()
+                    return
                 }
             }
 
             logd("CONNECT: $destHost:$destPort OK (via Slipstream)")
+            remoteSocket.soTimeout = 0
+            established = true
+            onConnected()
 
-            // Send success to hev-socks5-tunnel
-            clientOutput.write(byteArrayOf(0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
+            // Send success to hev-socks5-tunnel with the bound address its policy picks
+            clientOutput.write(
+                SlipstreamBridge.socksConnectReply(rawAddr + portBytes, byteArrayOf(connRespHeader[3]) + boundAddr)
+            )
             clientOutput.flush()
 
             clientSocket.soTimeout = 0
@@ -358,6 +557,9 @@ object SlipstreamSocksBridge {
             }
         } catch (e: Exception) {
             logd("CONNECT: chain error for $destHost:$destPort: ${e.message}")
+            if (!established) {
+                SlipstreamBridge.reportConnectFailure(destHost, destPort, SlipstreamBridge.CONNECT_NO_REPLY)
+            }
             try {
                 clientOutput.write(byteArrayOf(0x05, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0))
                 clientOutput.flush()
@@ -407,7 +609,13 @@ object SlipstreamSocksBridge {
                     // DNS: try DNS-over-TCP through tunnel (uses user's resolver),
                     // fall back to DoH through tunnel (Cloudflare) if TCP fails
                     forwardDnsTcp(addrBytes, payload)
-                        ?: forwardDnsDoH(payload)
+                        ?: try {
+                            forwardDnsDoH(payload)
+                        } catch (e: DohTlsVersionException) {
+                            // Not retried over an older version; the app's lookup times out.
+                            Log.w(TAG, "FWD_UDP: DoH for ${dest.first} refused: ${e.message}")
+                            null
+                        }
                 } else {
                     // Non-DNS UDP (QUIC, etc.): drop silently.
                     // Browser falls back to TCP → CONNECT through Slipstream.
@@ -517,6 +725,8 @@ object SlipstreamSocksBridge {
      *
      * @param payload DNS query payload (raw UDP DNS packet)
      * @return DNS response payload, or null on failure
+     * @throws DohTlsVersionException if no TLS version at or above [minTlsVersion] could be
+     *   negotiated
      */
     private fun forwardDnsDoH(payload: ByteArray): ByteArray? {
         var rawSocket: Socket? = null
@@ -550,7 +760,26 @@ object SlipstreamSocksBridge {
             // Step 4: TLS handshake over the SOCKS tunnel
             val sslFactory = SSLSocketFactory.getDefault() as SSLSocketFactory
             sslSocket = sslFactory.createSocket(rawSocket, "cloudflare-dns.com", 443, true) as SSLSocket
-            sslSocket.startHandshake()
+            // Only offer versions at or above the minimum so a downgrade fails the handshake.
+            val allowed = TLS_VERSIONS.drop(TLS_VERSIONS.indexOf(minTlsVersion))
+            val protocols = sslSocket.supportedProtocols.filter { it in allowed }
+            if (protocols.isEmpty()) {
+                SlipstreamBridge.reportDohBelowMinimum()
+                throw DohTlsVersionException("device does not support $minTlsVersion or newer")
+            }
+            sslSocket.enabledProtocols = protocols.toTypedArray()
+            try {
+                sslSocket.startHandshake()
+            } catch (e: SSLHandshakeException) {
+                // Other handshake failures, such as a bad certificate or a reset, are not
+                // about the minimum and go to the generic failure below.
+                if (!isProtocolVersionFailure(e)) throw e
+                SlipstreamBridge.reportDohBelowMinimum()
+                throw DohTlsVersionException("TLS handshake could not meet minimum $minTlsVersion: ${e.message}")
+            }
+            val negotiated = sslSocket.session.protocol
+            dohTlsVersion = negotiated
+            SlipstreamBridge.reportDohHandshake(negotiated)
 
             val tlsIn = sslSocket.inputStream
             val tlsOut = sslSocket.outputStream
@@ -569,6 +798,8 @@ object SlipstreamSocksBridge {
 
             // Step 6: Read HTTP response
             return readDoHResponse(tlsIn)
+        } catch (e: DohTlsVersionException) {
+            throw e
         } catch (e: Exception) {
             logd("DoH DNS failed: ${e.message}")
             return null
@@ -578,6 +809,21 @@ object SlipstreamSocksBridge {
         }
     }
 
+    /**
+     * Whether a handshake failed because the peer chose, or only offered, a protocol version
+     * that is not enabled: a `protocol_version` alert or a "no protocols" cause anywhere in
+     * the chain, as Conscrypt and the JDK word them.
+     */
+    private fun isProtocolVersionFailure(e: SSLHandshakeException): Boolean {
+        var cause: Throwable? = e
+        while (cause != null) {
+            val message = cause.message?.lowercase().orEmpty()
+            if (PROTOCOL_VERSION_FAILURES.any { it in message }) return true
+            cause = cause.cause.takeIf { it !== cause }
+        }
+        return false
+    }
+
     /**
      * Perform SOCKS5 greeting and user/pass auth with Dante.
      * Returns true on success.
@@ -729,6 +975,56 @@ object SlipstreamSocksBridge {
         }
     }
 
+    /**
+     * Look up a CONNECT destination in [destRewrites].
+     * Keys are "host:port" or "host" (any port); values are "host:port" or "host"
+     * (keep the original port). IPv6 hosts use brackets, e.g. "[2001:db8::1]:443".
+     */
+    private fun rewriteDestination(host: String, port: Int): Pair<String, Int>? {
+        val rewrites = destRewrites
+        if (rewrites.isEmpty()) return null
+        val key = if (host.contains(':')) "[$host]" else host
+        val target = rewrites["$key:$port"] ?: rewrites[key] ?: return null
+        val (newHost, newPort) = splitHostPort(target) ?: run {
+            Log.w(TAG, "Ignoring invalid destination rewrite for $key:$port: $target")
+            return null
+        }
+        return newHost to (newPort ?: port)
+    }
+
+    private fun splitHostPort(value: String): Pair<String, Int?>? {
+        if (value.startsWith("[")) {
+            val end = value.indexOf(']')
+            if (end < 0) return null
+            val host = value.substring(1, end)
+            val tail = value.substring(end + 1)
+            if (tail.isEmpty()) return host to null
+            val port = tail.removePrefix(":").toIntOrNull()?.takeIf { tail.startsWith(":") && it in 1..65535 }
+                ?: return null
+            return host to port
+        }
+        val colon = value.lastIndexOf(':')
+        if (colon < 0) return value.takeIf { it.isNotEmpty() }?.let { it to null }
+        val port = value.substring(colon + 1).toIntOrNull()?.takeIf { it in 1..65535 } ?: return null
+        val host = value.substring(0, colon).takeIf { it.isNotEmpty() } ?: return null
+        return host to port
+    }
+
+    /** Encode a host as a SOCKS5 address (ATYP + address), IP literals as IPv4/IPv6. */
+    private fun encodeSocksAddr(host: String): ByteArray {
+        val ipv4 = host.split('.')
+        if (ipv4.size == 4 && ipv4.all { part -> part.toIntOrNull()?.let { it in 0..255 } == true }) {
+            return byteArrayOf(0x01) + ipv4.map { it.toInt().toByte() }.toByteArray()
+        }
+        if (host.contains(':')) {
+            // Numeric literal only, so this does not trigger a DNS lookup
+            val addr = java.net.InetAddress.getByName(host).address
+            return byteArrayOf(0x04) + addr
+        }
+        val domain = host.toByteArray()
+        return byteArrayOf(0x03, domain.size.toByte()) + domain
+    }
+
     private fun formatIpv6(addr: ByteArray): String {
         val parts = mutableListOf<String>()
         for (i in 0 until 16 step 2) {
@@ -738,3 +1034,6 @@ object SlipstreamSocksBridge {
         return parts.joinToString(":")
     }
 }
+
+/** The DoH server, or the device, could not negotiate the bridge's minimum TLS version. */
+class DohTlsVersionException(message: String) : SSLException(message)
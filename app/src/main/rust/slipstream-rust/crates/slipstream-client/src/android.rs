@@ -5,20 +5,63 @@
 //! - State flags (running, listener ready, QUIC ready)
 //! - Socket protection via VpnService.protect()
 
-use crate::error::ClientError;
-use crate::runtime::run_client;
-use jni::objects::{JBooleanArray, JClass, JIntArray, JObject, JObjectArray, JString, JValue};
-use jni::sys::{jboolean, jbooleanArray, jint, jintArray, JNI_FALSE, JNI_TRUE};
+use crate::blocklist::DestinationBlocklist;
+use crate::dedup::{ConnectDecision, DuplicateConnectPolicy, DuplicateConnects};
+use crate::dns::{
+    export_flight_recorder, DEFAULT_POLL_TIMEOUT_MS, DEFAULT_QUERY_RAMP_DOUBLING_MS,
+    DEFAULT_RESOLVER_FAILURE_THRESHOLD, DEFAULT_RESOLVER_GRACE_PERIOD_MS,
+    DEFAULT_RESOLVER_RECOVERY_PROBE_MS, DEFAULT_RESOLVER_SERVFAIL_COOLDOWN_MS,
+    DEFAULT_RESOLVER_SERVFAIL_THRESHOLD, MAX_POLL_POOL,
+};
+use crate::dns_forward::{DEFAULT_DNS_LISTEN_HOST, DEFAULT_DNS_UPSTREAM};
+use crate::doh_tls::{record_doh_below_minimum, record_doh_handshake, reset_doh_tls};
+use crate::error::{ClientError, ClientErrorKind};
+use crate::events::DEFAULT_EVENT_GRACE_MS;
+use crate::failures::{DestinationError, DestinationErrors};
+use crate::host_limits::{close_host_connection, open_host_connection, set_host_connection_limits};
+use crate::loglevel::{reloadable_filter, set_log_level};
+use crate::logstream::{clear_log_stream, log_stream_layer, set_log_stream, LogLine};
+use crate::profile::{export_profile, import_profile};
+use crate::redact::set_log_privacy;
+use crate::runtime::{
+    force_resolver, parse_active_window, parse_listen_host, reload_config, run_client,
+    DEFAULT_BLOCK_BACKOFF_MS, DEFAULT_IDLE_POLL_MIN_MS, DEFAULT_MAX_RECONNECT_ATTEMPTS,
+    DEFAULT_RECONNECT_MAX_MS, DEFAULT_RECONNECT_MIN_MS,
+};
+use crate::session::{last_session_summary, resolver_statuses, session_id, session_snapshot};
+use crate::socks_reply::BoundAddressPolicy;
+use crate::status::DEFAULT_STATUS_HOST;
+use crate::streams::Command;
+use crate::streams::{DEFAULT_FIRST_STREAM_BOOST_MS, MIN_REASSEMBLY_LIMIT_BYTES};
+use crate::tuning::{export_learned_tuning, import_learned_tuning, set_network_id};
+use crate::validate::{validate_config, ConfigIssue, IssueSeverity};
+use crate::verify::{
+    destination_latencies, exit_ip, run_speed_test, verify_connectivity, ConnectivityResult,
+    ExitIpResult, SocksCredentials, SpeedTestResult,
+};
+use jni::objects::{
+    JBooleanArray, JByteArray, JClass, JIntArray, JObject, JObjectArray, JString, JValue,
+};
+use jni::sys::{
+    jboolean, jbooleanArray, jbyteArray, jint, jintArray, jlong, jstring, JNI_FALSE, JNI_TRUE,
+};
 use jni::JNIEnv;
 use once_cell::sync::OnceCell;
 use slipstream_core::HostPort;
-use slipstream_ffi::{ClientConfig, ResolverMode, ResolverSpec};
+use slipstream_dns::CLASS_IN;
+use slipstream_ffi::{
+    ActiveWindow, ClientConfig, DnsIdStrategy, FormerrPolicy, LabelEncoding, LocalClosePolicy,
+    ModeMismatchPolicy, NonTunnelPolicy, PipelineMode, ProtectionLossPolicy, ProtectionMode,
+    ResolverMode, ResolverPreference, ResolverRolePolicy, ResolverSpec,
+};
 use std::os::unix::io::RawFd;
 use std::panic;
-use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use tokio::runtime::Builder;
+use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
 
 // ============================================================================
@@ -40,21 +83,289 @@ static SHOULD_SHUTDOWN: AtomicBool = AtomicBool::new(false);
 /// Flag indicating the client thread has finished.
 static IS_THREAD_DONE: AtomicBool = AtomicBool::new(true);
 
+/// Wakes `stop_client_locked` as soon as `IS_THREAD_DONE` is set; see
+/// `mark_thread_done`.
+static THREAD_DONE_LOCK: Mutex<()> = Mutex::new(());
+static THREAD_DONE_CHANGED: Condvar = Condvar::new();
+
+/// How long a stop waits for the client thread before abandoning it.
+const STOP_TIMEOUT: Duration = Duration::from_secs(3);
+
 /// Count of consecutive connection failures (connections that never became ready).
 static CONSECUTIVE_FAILURES: AtomicI32 = AtomicI32::new(0);
 
 /// Maximum consecutive failures before giving up.
 const MAX_CONSECUTIVE_FAILURES: i32 = 5;
 
+/// Command channel into the running client loop, used to reach it from JNI threads.
+static COMMAND_TX: Mutex<Option<mpsc::UnboundedSender<Command>>> = Mutex::new(None);
+
+/// How long JNI calls wait for the client loop to answer a command.
+const COMMAND_REPLY_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Set by `nativeCancelProbe` to stop a running connectivity probe.
+static PROBE_CANCEL: AtomicBool = AtomicBool::new(false);
+
+/// Set by `nativeNotifyNetworkChanged`; ends an offline wait early.
+static NETWORK_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Profile JSON of the most recently started client, kept for `nativeExportProfile`.
+static LAST_PROFILE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Flight recorder size for the next client start, set by `nativeSetFlightRecorder`.
+static FLIGHT_RECORDER_RECORDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returned by `nativeStartSlipstreamClient` when a client is already running
+/// and the duplicate start policy does not allow reusing or replacing it.
+const START_ALREADY_RUNNING: jint = -14;
+
+/// Returned by `nativeStartSlipstreamClient` when the listen host is not a
+/// literal IP address.
+const START_INVALID_LISTEN_HOST: jint = -15;
+
+/// Duplicate start policy: refuse with `START_ALREADY_RUNNING`.
+const DUPLICATE_START_REJECT: jint = 0;
+/// Duplicate start policy: succeed without change when the configuration
+/// matches the running client, otherwise stop it and start the new one.
+const DUPLICATE_START_REUSE_OR_RESTART: jint = 1;
+
+/// Set by `nativeSetDuplicateStartPolicy`.
+static DUPLICATE_START_POLICY: AtomicI32 = AtomicI32::new(DUPLICATE_START_REJECT);
+
+/// Protection mode: protect the DNS socket through `VpnService.protect()`.
+const PROTECTION_VPN: jint = 0;
+/// Protection mode: skip protection, for proxy-only use without a `VpnService`.
+const PROTECTION_NONE: jint = 1;
+
+/// Set by `nativeSetProtectionMode`, read at the next client start.
+static PROTECTION_MODE: AtomicI32 = AtomicI32::new(PROTECTION_VPN);
+
+/// Protection loss: drop the tunnel and wait for Java to stop or restart.
+const PROTECTION_LOSS_HOLD: jint = 0;
+/// Protection loss: exit the client with an error.
+const PROTECTION_LOSS_STOP: jint = 1;
+
+/// Set by `nativeSetProtectionLossPolicy`, read at the next client start.
+static PROTECTION_LOSS_POLICY: AtomicI32 = AtomicI32::new(PROTECTION_LOSS_HOLD);
+
+/// Lifetime query cap for the next client start, set by
+/// `nativeSetMaxLifetimeQueries`; 0 is unlimited.
+static MAX_LIFETIME_QUERIES: AtomicU64 = AtomicU64::new(0);
+
+/// One unanswered query at a time from the next client start on, set by
+/// `nativeSetSingleQueryMode`.
+static SINGLE_QUERY_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Query name length limit for the next client start, set by
+/// `nativeSetMaxQnameLen`; 0 allows the DNS maximum.
+static MAX_QNAME_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Safe label encoding for the next client start, set by `nativeSetSafeLabelEncoding`.
+static SAFE_LABEL_ENCODING: AtomicBool = AtomicBool::new(false);
+
+/// Count answers that are not tunnel data against their resolver from the next
+/// client start on, set by `nativeSetAvoidNonTunnelResolvers`.
+static AVOID_NON_TUNNEL_RESOLVERS: AtomicBool = AtomicBool::new(false);
+
+/// Move authoritative paths that answer like recursive resolvers to recursive
+/// polling from the next client start on, set by `nativeSetAdaptResolverModes`.
+static ADAPT_RESOLVER_MODES: AtomicBool = AtomicBool::new(false);
+
+/// Retry plainer query forms on a resolver that answers FORMERR from the next
+/// client start on, set by `nativeSetAdaptToFormerr`.
+static ADAPT_TO_FORMERR: AtomicBool = AtomicBool::new(true);
+
+/// Persistent-block detection for the next client start, set by
+/// `nativeSetBlockBackoff`; an attempt count of 0 disables it.
+static BLOCK_BACKOFF_AFTER: AtomicU32 = AtomicU32::new(0);
+static BLOCK_BACKOFF_MS: AtomicU64 = AtomicU64::new(DEFAULT_BLOCK_BACKOFF_MS);
+
+/// Reconnect limit for the next client start, set by `nativeSetReconnectLimit`;
+/// 0 is no limit.
+static MAX_RECONNECT_ATTEMPTS: AtomicU32 = AtomicU32::new(DEFAULT_MAX_RECONNECT_ATTEMPTS);
+static MAX_RECONNECT_DURATION_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Resolvers that must carry data before the next client start reports
+/// connected, set by `nativeSetConfirmResolvers`.
+static CONFIRM_RESOLVERS: AtomicUsize = AtomicUsize::new(1);
+
+/// Other resolvers each handshake query goes to from the next client start on,
+/// set by `nativeSetHandshakeRedundancy`.
+static HANDSHAKE_REDUNDANCY: AtomicUsize = AtomicUsize::new(0);
+
+/// Idle polling cap for the next client start, set by `nativeSetIdlePolling`;
+/// 0 keeps full-rate polling.
+static IDLE_POLL_MAX_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Idle polling floor for the next client start, set by `nativeSetIdlePollFloor`.
+static IDLE_POLL_MIN_MS: AtomicU64 = AtomicU64::new(DEFAULT_IDLE_POLL_MIN_MS);
+
+/// Idle refresh interval for the next client start, set by `nativeSetIdleRefresh`;
+/// 0 disables it.
+static IDLE_REFRESH_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Long-poll pool size for the next client start, set by `nativeSetPollPool`;
+/// 0 disables it.
+static POLL_POOL: AtomicUsize = AtomicUsize::new(0);
+
+/// First-stream boost for the next client start, set by `nativeSetFirstStreamBoost`;
+/// a count of 0 disables it.
+static FIRST_STREAM_BOOST_COUNT: AtomicUsize = AtomicUsize::new(0);
+static FIRST_STREAM_BOOST_MS: AtomicU64 = AtomicU64::new(DEFAULT_FIRST_STREAM_BOOST_MS);
+
+/// DNS Cookies for the next client start, set by `nativeSetEdnsCookies`.
+static EDNS_COOKIES: AtomicBool = AtomicBool::new(false);
+
+/// Adaptive per-resolver query rate for the next client start, set by
+/// `nativeSetAdaptiveQueryRate`.
+static ADAPTIVE_QUERY_RATE: AtomicBool = AtomicBool::new(false);
+
+/// Startup query ramp for the next client start, set by `nativeSetQueryRamp`;
+/// 0 queries per second disables it.
+static QUERY_RAMP_QPS: AtomicU32 = AtomicU32::new(0);
+static QUERY_RAMP_DOUBLING_MS: AtomicU64 = AtomicU64::new(DEFAULT_QUERY_RAMP_DOUBLING_MS);
+
+/// Interactive stream priority for the next client start, set by
+/// `nativeSetPrioritizeInteractive`.
+static PRIORITIZE_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether resolvers without a port in the ports array use 53 instead of
+/// failing the start; set by `nativeSetDefaultMissingPorts`.
+static DEFAULT_MISSING_PORTS: AtomicBool = AtomicBool::new(false);
+
+/// Separate congestion window for bulk streams for the next client start, set
+/// by `nativeSetCongestionIsolation`.
+static CONGESTION_ISOLATION: AtomicBool = AtomicBool::new(false);
+
+/// Primary resolver choice for the next client start, set by
+/// `nativeSetPreferBandwidth`.
+static PREFER_BANDWIDTH: AtomicBool = AtomicBool::new(false);
+
+/// Grace window for disconnect and stall events from the next client start on,
+/// set by `nativeSetEventGrace`.
+static EVENT_GRACE_MS: AtomicU64 = AtomicU64::new(DEFAULT_EVENT_GRACE_MS);
+
+/// Cap on pending path probes for the next client start, set by
+/// `nativeSetMaxParallelProbes`; 0 is unlimited.
+static MAX_PARALLEL_PROBES: AtomicUsize = AtomicUsize::new(0);
+
+/// Connection setup cap for the next client start, set by
+/// `nativeSetMaxConnectionSetups`; 0 is unlimited.
+static MAX_CONNECTION_SETUPS: AtomicUsize = AtomicUsize::new(0);
+
+/// Reassembly memory cap for the next client start, set by
+/// `nativeSetReassemblyLimit`; 0 keeps the default.
+static REASSEMBLY_LIMIT_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// DNS socket buffer sizes for the next client start, set by
+/// `nativeSetUdpBufferSizes`; 0 keeps the OS default.
+static UDP_RCVBUF_BYTES: AtomicUsize = AtomicUsize::new(0);
+static UDP_SNDBUF_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// DNS forwarder for the next client start, set by `nativeSetDnsForwarder`.
+static DNS_FORWARDER: Mutex<Option<DnsForwarderSettings>> = Mutex::new(None);
+
+#[derive(Clone, Debug)]
+struct DnsForwarderSettings {
+    port: u16,
+    upstream: String,
+    username: String,
+    password: String,
+}
+
+/// Active windows for the next client start, set by `nativeSetActiveWindows`;
+/// empty keeps the tunnel always active.
+static ACTIVE_WINDOWS: Mutex<Vec<ActiveWindow>> = Mutex::new(Vec::new());
+
+/// Quiet hours for the next client start, set by `nativeSetQuietHours`;
+/// empty turns them off.
+static QUIET_HOURS: Mutex<Vec<ActiveWindow>> = Mutex::new(Vec::new());
+
+/// Destinations the SOCKS bridge refuses, set by `nativeSetDestinationBlocklist`;
+/// empty blocks nothing.
+static DESTINATION_BLOCKLIST: Mutex<Option<DestinationBlocklist>> = Mutex::new(None);
+
+/// Duplicate CONNECT tracking for the SOCKS bridge, set by
+/// `nativeSetDuplicateConnectPolicy` with the instant its clock counts from;
+/// `None` allows every CONNECT.
+static DUPLICATE_CONNECTS: Mutex<Option<(Instant, DuplicateConnects)>> = Mutex::new(None);
+
+/// Bound address in the SOCKS bridge's CONNECT replies, set by
+/// `nativeSetBoundAddressPolicy`.
+static BOUND_ADDRESS_POLICY: Mutex<BoundAddressPolicy> = Mutex::new(BoundAddressPolicy::Relay);
+
+/// Latest CONNECT failure per host, read by `nativeGetLastDestinationError`,
+/// with the instant its clock counts from; created by the first failure.
+static DESTINATION_ERRORS: Mutex<Option<(Instant, DestinationErrors)>> = Mutex::new(None);
+
+/// Start arguments of the running client, compared against duplicate starts.
+static RUNNING_START_KEY: Mutex<Option<String>> = Mutex::new(None);
+
+/// Serializes start and stop so the `IS_RUNNING` check-and-set and the state
+/// reset cannot interleave when JNI callers race each other.
+static LIFECYCLE_LOCK: Mutex<()> = Mutex::new(());
+
 /// Handle to the client thread.
 static CLIENT_THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
 
 /// Global JVM reference for callbacks.
+///
+/// Who attaches a thread detaches it:
+/// - JNI entry points run on Java threads, which the JVM owns; attaching there
+///   is a no-op and nothing is detached.
+/// - `protect_socket` and the `notify_*` callbacks run on the client thread and
+///   the threads it spawns. They use the scoped `attach_current_thread`, which
+///   detaches again when the call returns unless the thread was attached
+///   already, so these threads never exit attached.
+/// - The log stream thread calls into Java for every line, so it attaches once,
+///   as a daemon, and detaches itself explicitly before it exits.
+///
+/// jni-rs would detach a permanently attached thread from a thread-local
+/// destructor, but on Android that may run after ART has checked the exiting
+/// thread and logged it as still attached; hence the explicit detach, with
+/// `detach_from_jvm` as the client thread's last act as a safeguard.
 static JAVA_VM: OnceCell<jni::JavaVM> = OnceCell::new();
 
 /// Cached global reference to SlipstreamBridge class.
 /// This is needed because native threads can't find app classes via the system class loader.
-static BRIDGE_CLASS: OnceCell<jni::objects::GlobalRef> = OnceCell::new();
+/// Replaced by `nativeRefreshBridgeClass` when the class loader changes.
+static BRIDGE_CLASS: Mutex<Option<jni::objects::GlobalRef>> = Mutex::new(None);
+
+fn bridge_class() -> Option<jni::objects::GlobalRef> {
+    BRIDGE_CLASS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone()
+}
+
+/// Look up SlipstreamBridge and cache a global reference to it.
+/// Must run on a Java thread that has access to the app class loader.
+/// On failure the previous reference, if any, is kept.
+fn cache_bridge_class(env: &mut JNIEnv) -> bool {
+    let class_name = "app/slipnet/tunnel/SlipstreamBridge";
+    let class = match env.find_class(class_name) {
+        Ok(class) => class,
+        Err(e) => {
+            error!("Failed to find SlipstreamBridge class: {:?}", e);
+            let _ = env.exception_clear();
+            return false;
+        }
+    };
+    match env.new_global_ref(class) {
+        Ok(global_ref) => {
+            *BRIDGE_CLASS
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(global_ref);
+            info!("Cached SlipstreamBridge class for callbacks");
+            true
+        }
+        Err(e) => {
+            error!("Failed to create global ref for SlipstreamBridge: {:?}", e);
+            let _ = env.exception_clear();
+            false
+        }
+    }
+}
 
 // ============================================================================
 // Public API for Rust code
@@ -65,6 +376,16 @@ pub fn should_shutdown() -> bool {
     SHOULD_SHUTDOWN.load(Ordering::SeqCst)
 }
 
+/// Ask the running client to stop at its next shutdown check.
+pub fn request_shutdown() {
+    SHOULD_SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+/// Whether a network change was reported since the last call.
+pub fn take_network_changed() -> bool {
+    NETWORK_CHANGED.swap(false, Ordering::SeqCst)
+}
+
 /// Signal that the TCP listener is ready.
 pub fn signal_listener_ready() {
     IS_LISTENER_READY.store(true, Ordering::SeqCst);
@@ -95,6 +416,40 @@ pub fn exceeded_max_failures() -> bool {
     CONSECUTIVE_FAILURES.load(Ordering::SeqCst) >= MAX_CONSECUTIVE_FAILURES
 }
 
+/// Register the command channel of the current client loop.
+pub(crate) fn register_command_sender(command_tx: mpsc::UnboundedSender<Command>) {
+    *COMMAND_TX.lock().unwrap() = Some(command_tx);
+}
+
+fn clear_command_sender() {
+    COMMAND_TX.lock().unwrap().take();
+}
+
+/// Ask the client loop to close a single stream.
+/// Returns 0 on success, -1 if not running, -2 for an unknown stream, -3 on timeout.
+fn close_stream_impl(stream_id: u64) -> jint {
+    let command_tx = match COMMAND_TX.lock().unwrap().as_ref() {
+        Some(command_tx) => command_tx.clone(),
+        None => return -1,
+    };
+    let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
+    if command_tx
+        .send(Command::CloseStream {
+            stream_id,
+            reply: reply_tx,
+        })
+        .is_err()
+    {
+        return -1;
+    }
+    match reply_rx.recv_timeout(COMMAND_REPLY_TIMEOUT) {
+        Ok(true) => 0,
+        // A dropped reply means the command was discarded while reconnecting.
+        Ok(false) | Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => -2,
+        Err(std::sync::mpsc::RecvTimeoutError::Timeout) => -3,
+    }
+}
+
 /// Protect a socket file descriptor via VpnService.protect().
 /// This MUST be called for the UDP socket used for DNS queries BEFORE sending any data.
 /// Returns true if protection succeeded, false otherwise.
@@ -107,7 +462,7 @@ pub fn protect_socket(fd: RawFd) -> bool {
         }
     };
 
-    let class_ref = match BRIDGE_CLASS.get() {
+    let class_ref = match bridge_class() {
         Some(c) => c,
         None => {
             error!("SlipstreamBridge class not cached, cannot protect socket");
@@ -152,6 +507,235 @@ pub fn protect_socket(fd: RawFd) -> bool {
     }
 }
 
+/// Tell the app that the active primary resolver changed, via
+/// `SlipstreamBridge.onResolverChanged(old, new, reason)`.
+pub fn notify_resolver_changed(old: &str, new: &str, reason: &str) {
+    let (Some(jvm), Some(class_ref)) = (JAVA_VM.get(), bridge_class()) else {
+        return;
+    };
+    let mut env = match jvm.attach_current_thread() {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Failed to attach to JVM: {:?}", e);
+            return;
+        }
+    };
+    let (Ok(old), Ok(new), Ok(reason)) = (
+        env.new_string(old),
+        env.new_string(new),
+        env.new_string(reason),
+    ) else {
+        let _ = env.exception_clear();
+        return;
+    };
+    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+    let result = env.call_static_method(
+        class,
+        "onResolverChanged",
+        "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)V",
+        &[
+            JValue::Object(&old),
+            JValue::Object(&new),
+            JValue::Object(&reason),
+        ],
+    );
+    if let Err(e) = result {
+        error!("Failed to call onResolverChanged: {:?}", e);
+        let _ = env.exception_clear();
+    }
+}
+
+/// Report to Java that the lifetime query limit stopped the client.
+pub fn notify_limit_reached(queries: u64) {
+    let (Some(jvm), Some(class_ref)) = (JAVA_VM.get(), bridge_class()) else {
+        return;
+    };
+    let mut env = match jvm.attach_current_thread() {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Failed to attach to JVM: {:?}", e);
+            return;
+        }
+    };
+    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+    let result = env.call_static_method(
+        class,
+        "onLimitReached",
+        "(J)V",
+        &[JValue::Long(queries.min(i64::MAX as u64) as i64)],
+    );
+    if let Err(e) = result {
+        error!("Failed to call onLimitReached: {:?}", e);
+        let _ = env.exception_clear();
+    }
+}
+
+/// Report to Java that the DNS socket can no longer be protected from the VPN.
+pub fn notify_protection_lost() {
+    let (Some(jvm), Some(class_ref)) = (JAVA_VM.get(), bridge_class()) else {
+        return;
+    };
+    let mut env = match jvm.attach_current_thread() {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Failed to attach to JVM: {:?}", e);
+            return;
+        }
+    };
+    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+    let result = env.call_static_method(class, "onProtectionLost", "()V", &[]);
+    if let Err(e) = result {
+        error!("Failed to call onProtectionLost: {:?}", e);
+        let _ = env.exception_clear();
+    }
+}
+
+/// Report to Java that the device went offline, or that the client is trying
+/// to connect again.
+pub fn notify_network_offline(offline: bool) {
+    let (Some(jvm), Some(class_ref)) = (JAVA_VM.get(), bridge_class()) else {
+        return;
+    };
+    let mut env = match jvm.attach_current_thread() {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Failed to attach to JVM: {:?}", e);
+            return;
+        }
+    };
+    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+    let result = env.call_static_method(
+        class,
+        "onNetworkOffline",
+        "(Z)V",
+        &[JValue::Bool(offline as jboolean)],
+    );
+    if let Err(e) = result {
+        error!("Failed to call onNetworkOffline: {:?}", e);
+        let _ = env.exception_clear();
+    }
+}
+
+/// Report to Java that the tunnel paused outside its active windows, or resumed.
+pub fn notify_scheduled_pause(paused: bool) {
+    let (Some(jvm), Some(class_ref)) = (JAVA_VM.get(), bridge_class()) else {
+        return;
+    };
+    let mut env = match jvm.attach_current_thread() {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Failed to attach to JVM: {:?}", e);
+            return;
+        }
+    };
+    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+    let result = env.call_static_method(
+        class,
+        "onScheduledPause",
+        "(Z)V",
+        &[JValue::Bool(paused as jboolean)],
+    );
+    if let Err(e) = result {
+        error!("Failed to call onScheduledPause: {:?}", e);
+        let _ = env.exception_clear();
+    }
+}
+
+/// Pass a tunnel event to Java as its JSON form, e.g. `{"event":"stalled"}`.
+pub fn notify_tunnel_event(json: &str) {
+    let (Some(jvm), Some(class_ref)) = (JAVA_VM.get(), bridge_class()) else {
+        return;
+    };
+    let mut env = match jvm.attach_current_thread() {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Failed to attach to JVM: {:?}", e);
+            return;
+        }
+    };
+    let Ok(json) = env.new_string(json) else {
+        let _ = env.exception_clear();
+        return;
+    };
+    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+    let result = env.call_static_method(
+        class,
+        "onTunnelEvent",
+        "(Ljava/lang/String;)V",
+        &[JValue::Object(&json)],
+    );
+    if let Err(e) = result {
+        error!("Failed to call onTunnelEvent: {:?}", e);
+        let _ = env.exception_clear();
+    }
+}
+
+/// Report to Java that `attempts` reconnects in a row failed as `failure`, which
+/// looks like a blocked domain; reconnects now use the long block backoff.
+pub fn notify_persistent_block_suspected(failure: &str, attempts: u32) {
+    let (Some(jvm), Some(class_ref)) = (JAVA_VM.get(), bridge_class()) else {
+        return;
+    };
+    let mut env = match jvm.attach_current_thread() {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Failed to attach to JVM: {:?}", e);
+            return;
+        }
+    };
+    let Ok(failure) = env.new_string(failure) else {
+        let _ = env.exception_clear();
+        return;
+    };
+    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+    let result = env.call_static_method(
+        class,
+        "onPersistentBlockSuspected",
+        "(Ljava/lang/String;I)V",
+        &[
+            JValue::Object(&failure),
+            JValue::Int(attempts.min(i32::MAX as u32) as i32),
+        ],
+    );
+    if let Err(e) = result {
+        error!("Failed to call onPersistentBlockSuspected: {:?}", e);
+        let _ = env.exception_clear();
+    }
+}
+
+/// Report to Java that the device woke after sleeping for `slept_ms`.
+pub fn notify_woke_from_doze(slept_ms: u64) {
+    let (Some(jvm), Some(class_ref)) = (JAVA_VM.get(), bridge_class()) else {
+        return;
+    };
+    let mut env = match jvm.attach_current_thread() {
+        Ok(env) => env,
+        Err(e) => {
+            error!("Failed to attach to JVM: {:?}", e);
+            return;
+        }
+    };
+    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+    let result = env.call_static_method(
+        class,
+        "onWokeFromDoze",
+        "(J)V",
+        &[JValue::Long(slept_ms.min(i64::MAX as u64) as i64)],
+    );
+    if let Err(e) = result {
+        error!("Failed to call onWokeFromDoze: {:?}", e);
+        let _ = env.exception_clear();
+    }
+}
+
 // ============================================================================
 // JNI Functions
 // ============================================================================
@@ -171,15 +755,84 @@ fn init_android_logging() {
     }
 
     // Also initialize tracing for the slipstream code
-    use tracing_subscriber::EnvFilter;
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_target(false)
-        .without_time()
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+    use tracing_subscriber::{fmt, Layer};
+    // The log stream picks its own level, so the filter only applies to logcat.
+    let _ = tracing_subscriber::registry()
+        .with(
+            fmt::layer()
+                .with_target(false)
+                .without_time()
+                .with_filter(reloadable_filter()),
+        )
+        .with(log_stream_layer())
         .try_init();
 }
 
+/// Android priority for a tracing level, as `android.util.Log` numbers them.
+fn android_priority(level: tracing::Level) -> jint {
+    match level {
+        tracing::Level::TRACE => 2,
+        tracing::Level::DEBUG => 3,
+        tracing::Level::INFO => 4,
+        tracing::Level::WARN => 5,
+        tracing::Level::ERROR => 6,
+    }
+}
+
+/// Hand one streamed log line to `SlipstreamBridge.onLogLine`.
+///
+/// Runs on the log stream thread, attached as a daemon so it never holds up
+/// VM shutdown, and detached again when the stream ends. Failures are not logged: the error would be streamed again.
+fn forward_log_line(line: LogLine) {
+    let (Some(jvm), Some(class_ref)) = (JAVA_VM.get(), bridge_class()) else {
+        return;
+    };
+    let Ok(mut env) = jvm.attach_current_thread_as_daemon() else {
+        return;
+    };
+    let Ok(text) = env.new_string(&line.message) else {
+        let _ = env.exception_clear();
+        return;
+    };
+    // Safety: GlobalRef holds a valid JNI reference, converting to JClass is safe
+    let class = unsafe { JClass::from_raw(class_ref.as_raw()) };
+    let result = env.call_static_method(
+        class,
+        "onLogLine",
+        "(ILjava/lang/String;)V",
+        &[
+            JValue::Int(android_priority(line.level)),
+            JValue::Object(&text),
+        ],
+    );
+    if result.is_err() {
+        let _ = env.exception_clear();
+    }
+    // The thread stays attached, so local references are never freed for us.
+    let _ = env.delete_local_ref(text);
+}
+
+/// Detaches the current thread from the JVM if it was attached permanently;
+/// a no-op otherwise. Called last on the threads the client owns.
+fn detach_from_jvm() {
+    if let Some(jvm) = JAVA_VM.get() {
+        // Safety: only called as a thread's last act, once no JNIEnv or
+        // guard of it is left to use.
+        unsafe { jvm.detach_current_thread() };
+    }
+}
+
+/// Detaches the thread it is dropped on from the JVM.
+struct DetachOnDrop;
+
+impl Drop for DetachOnDrop {
+    fn drop(&mut self) {
+        detach_from_jvm();
+    }
+}
+
 /// JNI_OnLoad - Called when the library is loaded.
 #[no_mangle]
 pub extern "system" fn JNI_OnLoad(vm: jni::JavaVM, _: *mut std::ffi::c_void) -> jint {
@@ -216,6 +869,9 @@ pub extern "system" fn JNI_OnLoad(vm: jni::JavaVM, _: *mut std::ffi::c_void) ->
 /// - -10: Failed to spawn client thread
 /// - -11: Failed to listen on port
 /// - -12: Exceeded max connection failures
+/// - -13: Previous client thread is still shutting down
+/// - -14: A client is already running (see `nativeSetDuplicateStartPolicy`)
+/// - -15: Listen host is not a literal IP address
 #[no_mangle]
 pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStartSlipstreamClient<'local>(
     mut env: JNIEnv<'local>,
@@ -236,17 +892,19 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStartSlips
     let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
         start_client_impl(
             &mut env,
-            domain,
-            resolver_hosts,
-            resolver_ports,
-            resolver_authoritative,
-            listen_port,
-            listen_host,
-            congestion_control,
-            keep_alive_interval,
-            gso_enabled,
-            debug_poll,
-            debug_streams,
+            JniStartArgs {
+                domain,
+                resolver_hosts,
+                resolver_ports,
+                resolver_authoritative,
+                listen_port,
+                listen_host,
+                congestion_control,
+                keep_alive_interval,
+                gso_enabled,
+                debug_poll,
+                debug_streams,
+            },
         )
     }));
 
@@ -260,51 +918,59 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStartSlips
     }
 }
 
-fn start_client_impl<'local>(
-    env: &mut JNIEnv<'local>,
-    domain: JString<'local>,
-    resolver_hosts: JObjectArray<'local>,
-    resolver_ports: jintArray,
-    resolver_authoritative: jbooleanArray,
-    listen_port: jint,
-    listen_host: JString<'local>,
-    congestion_control: JString<'local>,
-    keep_alive_interval: jint,
-    gso_enabled: jboolean,
-    debug_poll: jboolean,
-    debug_streams: jboolean,
-) -> jint {
+fn start_client_impl<'local>(env: &mut JNIEnv<'local>, args: JniStartArgs<'local>) -> jint {
     info!("nativeStartSlipstreamClient called");
 
-    // Check if already running
-    if IS_RUNNING.load(Ordering::SeqCst) {
+    start_client_with(|| prepare_client_thread(env, args))
+}
+
+/// Claim the running slot and launch the client thread built by `prepare`.
+/// Runs under the lifecycle lock so concurrent starts cannot both spawn a thread.
+///
+/// `prepare` also returns a key describing the start arguments; under the
+/// reuse-or-restart policy a duplicate start with the same key is a no-op.
+fn start_client_with<F, B>(prepare: F) -> jint
+where
+    F: FnOnce() -> Result<(String, B), jint>,
+    B: FnOnce() + Send + 'static,
+{
+    let _lifecycle = lock_lifecycle();
+
+    let running = IS_RUNNING.load(Ordering::SeqCst);
+    if running && DUPLICATE_START_POLICY.load(Ordering::SeqCst) != DUPLICATE_START_REUSE_OR_RESTART
+    {
         warn!("Client already running");
-        return 0;
+        return START_ALREADY_RUNNING;
+    }
+    if !running && !IS_THREAD_DONE.load(Ordering::SeqCst) {
+        warn!("Previous client thread is still exiting");
+        return -13;
     }
 
-    // Cache the SlipstreamBridge class for callbacks from native threads.
-    // This must be done on the Java thread that has access to the app class loader.
-    if BRIDGE_CLASS.get().is_none() {
-        let class_name = "app/slipnet/tunnel/SlipstreamBridge";
-        match env.find_class(class_name) {
-            Ok(class) => {
-                match env.new_global_ref(class) {
-                    Ok(global_ref) => {
-                        let _ = BRIDGE_CLASS.set(global_ref);
-                        info!("Cached SlipstreamBridge class for callbacks");
-                    }
-                    Err(e) => {
-                        error!("Failed to create global ref for SlipstreamBridge: {:?}", e);
-                        return -3;
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Failed to find SlipstreamBridge class: {:?}", e);
-                return -3;
-            }
+    let (key, body) = match prepare() {
+        Ok(prepared) => prepared,
+        Err(code) => return code,
+    };
+
+    if running {
+        let same = RUNNING_START_KEY
+            .lock()
+            .map(|running_key| running_key.as_deref() == Some(key.as_str()))
+            .unwrap_or(false);
+        if same {
+            info!("Client already running with the same configuration");
+            return 0;
+        }
+        info!("Client running with a different configuration, restarting");
+        stop_client_locked();
+        if !IS_THREAD_DONE.load(Ordering::SeqCst) {
+            warn!("Previous client thread is still exiting");
+            return -13;
         }
     }
+    if let Ok(mut running_key) = RUNNING_START_KEY.lock() {
+        *running_key = Some(key);
+    }
 
     // Reset state
     SHOULD_SHUTDOWN.store(false, Ordering::SeqCst);
@@ -313,26 +979,306 @@ fn start_client_impl<'local>(
     IS_THREAD_DONE.store(false, Ordering::SeqCst);
     CONSECUTIVE_FAILURES.store(0, Ordering::SeqCst);
 
+    // Mark as running
+    IS_RUNNING.store(true, Ordering::SeqCst);
+
+    let handle = thread::Builder::new()
+        .name("slipstream-client".to_string())
+        .spawn(move || {
+            body();
+
+            // Cleanup
+            clear_command_sender();
+            IS_RUNNING.store(false, Ordering::SeqCst);
+            IS_LISTENER_READY.store(false, Ordering::SeqCst);
+            IS_QUIC_READY.store(false, Ordering::SeqCst);
+            mark_thread_done();
+
+            info!("Client thread finished");
+            detach_from_jvm();
+        });
+
+    match handle {
+        Ok(h) => {
+            let mut guard = CLIENT_THREAD.lock().unwrap();
+            *guard = Some(h);
+            drop(guard);
+            info!("Client thread spawned successfully");
+
+            // Wait for listener to be ready (up to 5 seconds)
+            for _ in 0..50 {
+                if IS_LISTENER_READY.load(Ordering::SeqCst) {
+                    info!("Listener confirmed ready");
+                    return 0;
+                }
+                if !IS_RUNNING.load(Ordering::SeqCst) {
+                    error!("Client stopped before listener ready");
+                    return -11;
+                }
+                thread::sleep(std::time::Duration::from_millis(100));
+            }
+
+            if IS_LISTENER_READY.load(Ordering::SeqCst) {
+                0
+            } else {
+                error!("Timeout waiting for listener");
+                // Don't stop - the listener might still come up
+                0
+            }
+        }
+        Err(e) => {
+            error!("Failed to spawn client thread: {:?}", e);
+            IS_RUNNING.store(false, Ordering::SeqCst);
+            mark_thread_done();
+            -10
+        }
+    }
+}
+
+/// Sets `IS_THREAD_DONE` and wakes a stop waiting for it. The flag is stored
+/// under the lock so the wakeup cannot slip in between the waiter's check and
+/// its wait.
+fn mark_thread_done() {
+    let _guard = THREAD_DONE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    IS_THREAD_DONE.store(true, Ordering::SeqCst);
+    THREAD_DONE_CHANGED.notify_all();
+}
+
+/// Blocks until the client thread has finished or `timeout` passed; returns
+/// whether it finished.
+fn wait_for_thread_done(timeout: Duration) -> bool {
+    let guard = THREAD_DONE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let _guard = THREAD_DONE_CHANGED
+        .wait_timeout_while(guard, timeout, |_| !IS_THREAD_DONE.load(Ordering::SeqCst))
+        .map(|(guard, _)| guard)
+        .unwrap_or_else(|poisoned| poisoned.into_inner().0);
+    IS_THREAD_DONE.load(Ordering::SeqCst)
+}
+
+fn lock_lifecycle() -> std::sync::MutexGuard<'static, ()> {
+    // A panic while holding the lock leaves no partial state behind it.
+    LIFECYCLE_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Arguments of `nativeStartSlipstreamClient`, `nativeValidateConfig` and
+/// `nativeReloadConfig` as Java passed them.
+struct JniStartArgs<'local> {
+    domain: JString<'local>,
+    resolver_hosts: JObjectArray<'local>,
+    resolver_ports: jintArray,
+    resolver_authoritative: jbooleanArray,
+    listen_port: jint,
+    listen_host: JString<'local>,
+    congestion_control: JString<'local>,
+    keep_alive_interval: jint,
+    gso_enabled: jboolean,
+    debug_poll: jboolean,
+    debug_streams: jboolean,
+}
+
+/// Start arguments from Java; the `Debug` form of their config is the
+/// duplicate start key.
+#[derive(Debug)]
+struct StartArgs {
+    domain: String,
+    resolvers: Vec<ResolverSpec>,
+    listen_port: u16,
+    listen_host: String,
+    congestion_control: Option<String>,
+    keep_alive_interval: usize,
+    gso: bool,
+    debug_poll: bool,
+    debug_streams: bool,
+    dns_forwarder: Option<DnsForwarderSettings>,
+    active_windows: Vec<ActiveWindow>,
+    quiet_hours: Vec<ActiveWindow>,
+}
+
+impl StartArgs {
+    /// Client configuration for these arguments and the current bridge settings.
+    fn config(&self) -> ClientConfig<'_> {
+        ClientConfig {
+            tcp_listen_host: &self.listen_host,
+            tcp_listen_port: self.listen_port,
+            resolvers: &self.resolvers,
+            domain: &self.domain,
+            cert: None, // TODO: Support certificate pinning from Android
+            congestion_control: self.congestion_control.as_deref(),
+            gso: self.gso,
+            keep_alive_interval: self.keep_alive_interval,
+            adaptive_keep_alive: false,
+            debug_poll: self.debug_poll,
+            debug_streams: self.debug_streams,
+            record_dns: None,
+            query_log: None,
+            flight_recorder_records: FLIGHT_RECORDER_RECORDS.load(Ordering::SeqCst),
+            resolver_failure_threshold: DEFAULT_RESOLVER_FAILURE_THRESHOLD,
+            resolver_grace_period_ms: DEFAULT_RESOLVER_GRACE_PERIOD_MS,
+            resolver_recovery_probe_ms: DEFAULT_RESOLVER_RECOVERY_PROBE_MS,
+            resolver_servfail_threshold: DEFAULT_RESOLVER_SERVFAIL_THRESHOLD,
+            resolver_servfail_cooldown_ms: DEFAULT_RESOLVER_SERVFAIL_COOLDOWN_MS,
+            confirm_resolvers: CONFIRM_RESOLVERS.load(Ordering::SeqCst),
+            handshake_redundancy: HANDSHAKE_REDUNDANCY.load(Ordering::SeqCst),
+            status_host: DEFAULT_STATUS_HOST,
+            status_port: None,
+            status_control: false,
+            dns_listen_host: DEFAULT_DNS_LISTEN_HOST,
+            dns_listen_port: self.dns_forwarder.as_ref().map(|dns| dns.port),
+            dns_upstream: self
+                .dns_forwarder
+                .as_ref()
+                .map_or(DEFAULT_DNS_UPSTREAM, |dns| dns.upstream.as_str()),
+            dns_socks_username: self
+                .dns_forwarder
+                .as_ref()
+                .map(|dns| dns.username.as_str())
+                .filter(|username| !username.is_empty()),
+            dns_socks_password: self.dns_forwarder.as_ref().map(|dns| dns.password.as_str()),
+            dns_id_strategy: DnsIdStrategy::Random,
+            query_class: CLASS_IN,
+            edns_cookies: EDNS_COOKIES.load(Ordering::SeqCst),
+            adaptive_query_rate: ADAPTIVE_QUERY_RATE.load(Ordering::SeqCst),
+            query_ramp_qps: QUERY_RAMP_QPS.load(Ordering::SeqCst),
+            query_ramp_doubling_ms: QUERY_RAMP_DOUBLING_MS.load(Ordering::SeqCst),
+            coalesce_delay_ms: 0,
+            reconnect_min_ms: DEFAULT_RECONNECT_MIN_MS,
+            reconnect_max_ms: DEFAULT_RECONNECT_MAX_MS,
+            reconnect_jitter: true,
+            max_reconnect_attempts: MAX_RECONNECT_ATTEMPTS.load(Ordering::SeqCst),
+            max_reconnect_duration_ms: MAX_RECONNECT_DURATION_MS.load(Ordering::SeqCst),
+            block_backoff_after: BLOCK_BACKOFF_AFTER.load(Ordering::SeqCst),
+            block_backoff_ms: BLOCK_BACKOFF_MS.load(Ordering::SeqCst),
+            resolver_role_policy: ResolverRolePolicy::Mixed,
+            resolver_preference: if PREFER_BANDWIDTH.load(Ordering::SeqCst) {
+                ResolverPreference::Bandwidth
+            } else {
+                ResolverPreference::Order
+            },
+            max_parallel_probes: match MAX_PARALLEL_PROBES.load(Ordering::SeqCst) {
+                0 => None,
+                limit => Some(limit),
+            },
+            poll_timeout_ms: DEFAULT_POLL_TIMEOUT_MS,
+            idle_poll_min_ms: IDLE_POLL_MIN_MS.load(Ordering::SeqCst),
+            idle_poll_max_ms: IDLE_POLL_MAX_MS.load(Ordering::SeqCst),
+            idle_refresh_ms: IDLE_REFRESH_MS.load(Ordering::SeqCst),
+            poll_pool: POLL_POOL.load(Ordering::SeqCst),
+            stream_striping: false,
+            prioritize_interactive: PRIORITIZE_INTERACTIVE.load(Ordering::SeqCst),
+            congestion_isolation: CONGESTION_ISOLATION.load(Ordering::SeqCst),
+            first_stream_boost_count: FIRST_STREAM_BOOST_COUNT.load(Ordering::SeqCst),
+            first_stream_boost_ms: FIRST_STREAM_BOOST_MS.load(Ordering::SeqCst),
+            max_connection_setups: match MAX_CONNECTION_SETUPS.load(Ordering::SeqCst) {
+                0 => None,
+                limit => Some(limit),
+            },
+            reassembly_limit_bytes: match REASSEMBLY_LIMIT_BYTES.load(Ordering::SeqCst) {
+                0 => None,
+                bytes => Some(bytes),
+            },
+            // Browsers drop cancelled requests by closing the socket; free the stream at once.
+            local_close_policy: LocalClosePolicy::Reset,
+            protection_mode: if PROTECTION_MODE.load(Ordering::SeqCst) == PROTECTION_NONE {
+                ProtectionMode::None
+            } else {
+                ProtectionMode::VpnProtected
+            },
+            protection_loss_policy: if PROTECTION_LOSS_POLICY.load(Ordering::SeqCst)
+                == PROTECTION_LOSS_STOP
+            {
+                ProtectionLossPolicy::Stop
+            } else {
+                ProtectionLossPolicy::Hold
+            },
+            udp_rcvbuf_bytes: Some(UDP_RCVBUF_BYTES.load(Ordering::SeqCst))
+                .filter(|size| *size > 0),
+            udp_sndbuf_bytes: Some(UDP_SNDBUF_BYTES.load(Ordering::SeqCst))
+                .filter(|size| *size > 0),
+            source_address: None,
+            max_qname_len: match MAX_QNAME_LEN.load(Ordering::SeqCst) {
+                0 => None,
+                limit => Some(limit),
+            },
+            label_encoding: if SAFE_LABEL_ENCODING.load(Ordering::SeqCst) {
+                LabelEncoding::Safe
+            } else {
+                LabelEncoding::Base32
+            },
+            non_tunnel_policy: if AVOID_NON_TUNNEL_RESOLVERS.load(Ordering::SeqCst) {
+                NonTunnelPolicy::Avoid
+            } else {
+                NonTunnelPolicy::Report
+            },
+            mode_mismatch_policy: if ADAPT_RESOLVER_MODES.load(Ordering::SeqCst) {
+                ModeMismatchPolicy::Adapt
+            } else {
+                ModeMismatchPolicy::Report
+            },
+            formerr_policy: if ADAPT_TO_FORMERR.load(Ordering::SeqCst) {
+                FormerrPolicy::Adapt
+            } else {
+                FormerrPolicy::Report
+            },
+            max_lifetime_queries: match MAX_LIFETIME_QUERIES.load(Ordering::SeqCst) {
+                0 => None,
+                limit => Some(limit),
+            },
+            pipeline_mode: if SINGLE_QUERY_MODE.load(Ordering::SeqCst) {
+                PipelineMode::Single
+            } else {
+                PipelineMode::Pipelined
+            },
+            active_windows: &self.active_windows,
+            quiet_hours: &self.quiet_hours,
+            event_grace_ms: EVENT_GRACE_MS.load(Ordering::SeqCst),
+            congestion_controller: None,
+            resolver_transport: None,
+            query_hook: None,
+        }
+    }
+}
+
+/// Read the arguments shared by `nativeStartSlipstreamClient` and
+/// `nativeValidateConfig`. Only JNI failures are errors here; the values
+/// themselves are checked by the caller.
+fn read_start_args<'local>(
+    env: &mut JNIEnv<'local>,
+    args: JniStartArgs<'local>,
+) -> Result<StartArgs, jint> {
+    let JniStartArgs {
+        domain,
+        resolver_hosts,
+        resolver_ports,
+        resolver_authoritative,
+        listen_port,
+        listen_host,
+        congestion_control,
+        keep_alive_interval,
+        gso_enabled,
+        debug_poll,
+        debug_streams,
+    } = args;
     // Extract domain
     let domain_str: String = match env.get_string(&domain) {
         Ok(s) => s.into(),
         Err(e) => {
             error!("Failed to get domain string: {:?}", e);
-            return -1;
+            return Err(-1);
         }
     };
 
-    if domain_str.is_empty() {
-        error!("Domain is empty");
-        return -1;
-    }
-
     // Extract listen host
     let listen_host_str: String = match env.get_string(&listen_host) {
         Ok(s) => s.into(),
         Err(e) => {
             error!("Failed to get listen host string: {:?}", e);
-            return -2;
+            return Err(-2);
         }
     };
 
@@ -341,7 +1287,7 @@ fn start_client_impl<'local>(
         Ok(s) => s.into(),
         Err(e) => {
             error!("Failed to get congestion control string: {:?}", e);
-            return -2;
+            return Err(-2);
         }
     };
     let cc_option = if cc_str.is_empty() { None } else { Some(cc_str) };
@@ -351,31 +1297,45 @@ fn start_client_impl<'local>(
         Ok(len) => len as usize,
         Err(e) => {
             error!("Failed to get resolver hosts length: {:?}", e);
-            return -2;
+            return Err(-2);
         }
     };
 
-    if resolver_count == 0 {
-        error!("No resolvers provided");
-        return -2;
-    }
-
     // Wrap raw arrays in safe JNI types
     let resolver_ports_arr = unsafe { JIntArray::from_raw(resolver_ports) };
     let resolver_auth_arr = unsafe { JBooleanArray::from_raw(resolver_authoritative) };
 
     // Get ports array using get_array_region which is more portable
-    let mut ports: Vec<i32> = vec![0; resolver_count];
+    let port_count = match env.get_array_length(&resolver_ports_arr) {
+        Ok(len) => len as usize,
+        Err(e) => {
+            error!("Failed to get resolver ports length: {:?}", e);
+            return Err(-2);
+        }
+    };
+    let mut ports: Vec<i32> = vec![0; port_count.min(resolver_count)];
     if let Err(e) = env.get_int_array_region(&resolver_ports_arr, 0, &mut ports) {
         error!("Failed to get resolver ports: {:?}", e);
-        return -2;
+        return Err(-2);
     }
+    let ports = match resolver_ports_for_hosts(
+        ports,
+        port_count,
+        resolver_count,
+        DEFAULT_MISSING_PORTS.load(Ordering::SeqCst),
+    ) {
+        Ok(ports) => ports,
+        Err(message) => {
+            error!("{}", message);
+            return Err(-2);
+        }
+    };
 
     // Get authoritative flags using get_array_region
     let mut auth_flags: Vec<u8> = vec![0; resolver_count];
     if let Err(e) = env.get_boolean_array_region(&resolver_auth_arr, 0, &mut auth_flags) {
         error!("Failed to get authoritative flags: {:?}", e);
-        return -2;
+        return Err(-2);
     }
 
     // Build resolver specs
@@ -386,7 +1346,7 @@ fn start_client_impl<'local>(
             Ok(obj) => obj,
             Err(e) => {
                 error!("Failed to get resolver host at index {}: {:?}", i, e);
-                return -2;
+                return Err(-2);
             }
         };
         let host_jstr = JString::from(host_obj);
@@ -394,7 +1354,7 @@ fn start_client_impl<'local>(
             Ok(s) => s.into(),
             Err(e) => {
                 error!("Failed to convert resolver host at index {}: {:?}", i, e);
-                return -2;
+                return Err(-2);
             }
         };
 
@@ -418,98 +1378,108 @@ fn start_client_impl<'local>(
         });
     }
 
-    info!(
-        "Starting client: domain={}, resolvers={}, port={}, host={}",
-        domain_str, resolver_count, listen_port, listen_host_str
-    );
+    Ok(StartArgs {
+        domain: domain_str,
+        resolvers,
+        listen_port: listen_port as u16,
+        listen_host: listen_host_str,
+        congestion_control: cc_option,
+        keep_alive_interval: keep_alive_interval as usize,
+        gso: gso_enabled != JNI_FALSE,
+        debug_poll: debug_poll != JNI_FALSE,
+        debug_streams: debug_streams != JNI_FALSE,
+        dns_forwarder: DNS_FORWARDER.lock().ok().and_then(|dns| dns.clone()),
+        active_windows: ACTIVE_WINDOWS
+            .lock()
+            .map(|windows| windows.clone())
+            .unwrap_or_default(),
+        quiet_hours: QUIET_HOURS
+            .lock()
+            .map(|windows| windows.clone())
+            .unwrap_or_default(),
+    })
+}
 
-    // Mark as running
-    IS_RUNNING.store(true, Ordering::SeqCst);
+/// Port of each of `host_count` resolvers from the `port_count` ports Java
+/// passed, of which `ports` holds the first `host_count`. A count mismatch is an
+/// error naming the first index without a partner, unless `default_missing`
+/// lets resolvers without a port use 53.
+fn resolver_ports_for_hosts(
+    mut ports: Vec<i32>,
+    port_count: usize,
+    host_count: usize,
+    default_missing: bool,
+) -> Result<Vec<i32>, String> {
+    if port_count > host_count {
+        return Err(format!(
+            "Resolver port at index {} has no host ({} ports for {} hosts)",
+            host_count, port_count, host_count
+        ));
+    }
+    if port_count < host_count {
+        if !default_missing {
+            return Err(format!(
+                "Resolver at index {} has no port ({} ports for {} hosts)",
+                port_count, port_count, host_count
+            ));
+        }
+        for index in port_count..host_count {
+            warn!("Resolver at index {} has no port, using 53", index);
+        }
+        ports.resize(host_count, 53);
+    }
+    Ok(ports)
+}
 
-    // Spawn client thread
-    let listen_port_u16 = listen_port as u16;
-    let keep_alive = keep_alive_interval as usize;
-    let gso = gso_enabled != JNI_FALSE;
-    let dbg_poll = debug_poll != JNI_FALSE;
-    let dbg_streams = debug_streams != JNI_FALSE;
+fn prepare_client_thread<'local>(
+    env: &mut JNIEnv<'local>,
+    args: JniStartArgs<'local>,
+) -> Result<(String, impl FnOnce() + Send + 'static), jint> {
+    // Cache the SlipstreamBridge class for callbacks from native threads.
+    // This must be done on the Java thread that has access to the app class loader.
+    if bridge_class().is_none() && !cache_bridge_class(env) {
+        return Err(-3);
+    }
 
-    let handle = thread::Builder::new()
-        .name("slipstream-client".to_string())
-        .spawn(move || {
-            run_client_thread(
-                domain_str,
-                resolvers,
-                listen_port_u16,
-                listen_host_str,
-                cc_option,
-                keep_alive,
-                gso,
-                dbg_poll,
-                dbg_streams,
-            );
-        });
+    let args = read_start_args(env, args)?;
 
-    match handle {
-        Ok(h) => {
-            let mut guard = CLIENT_THREAD.lock().unwrap();
-            *guard = Some(h);
-            info!("Client thread spawned successfully");
+    if args.domain.is_empty() {
+        error!("Domain is empty");
+        return Err(-1);
+    }
 
-            // Wait for listener to be ready (up to 5 seconds)
-            for _ in 0..50 {
-                if IS_LISTENER_READY.load(Ordering::SeqCst) {
-                    info!("Listener confirmed ready");
-                    return 0;
-                }
-                if !IS_RUNNING.load(Ordering::SeqCst) {
-                    error!("Client stopped before listener ready");
-                    return -11;
-                }
-                thread::sleep(std::time::Duration::from_millis(100));
-            }
+    if args.resolvers.is_empty() {
+        error!("No resolvers provided");
+        return Err(-2);
+    }
 
-            if IS_LISTENER_READY.load(Ordering::SeqCst) {
-                0
-            } else {
-                error!("Timeout waiting for listener");
-                // Don't stop - the listener might still come up
-                0
-            }
-        }
-        Err(e) => {
-            error!("Failed to spawn client thread: {:?}", e);
-            IS_RUNNING.store(false, Ordering::SeqCst);
-            -10
-        }
+    if let Err(err) = parse_listen_host(&args.listen_host) {
+        error!("{}", err);
+        return Err(START_INVALID_LISTEN_HOST);
     }
+
+    info!(
+        "Starting client: domain={}, resolvers={}, port={}, host={}",
+        args.domain,
+        args.resolvers.len(),
+        args.listen_port,
+        args.listen_host
+    );
+
+    // The built config also holds what the nativeSet* calls stored, which a
+    // restart would pick up too.
+    let key = format!("{:?}", args.config());
+    Ok((key, move || run_client_thread(args)))
 }
 
-fn run_client_thread(
-    domain: String,
-    resolvers: Vec<ResolverSpec>,
-    listen_port: u16,
-    listen_host: String,
-    congestion_control: Option<String>,
-    keep_alive_interval: usize,
-    gso: bool,
-    debug_poll: bool,
-    debug_streams: bool,
-) {
+fn run_client_thread(args: StartArgs) {
     info!("Client thread started");
 
     let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
-        let config = ClientConfig {
-            tcp_listen_host: &listen_host,
-            tcp_listen_port: listen_port,
-            resolvers: &resolvers,
-            domain: &domain,
-            cert: None, // TODO: Support certificate pinning from Android
-            congestion_control: congestion_control.as_deref(),
-            gso,
-            keep_alive_interval,
-            debug_poll,
-            debug_streams,
-        };
+        let config = args.config();
+        if let Ok(mut profile) = LAST_PROFILE.lock() {
+            *profile = Some(export_profile(&config));
+        }
 
         // Build tokio runtime
         let runtime = match Builder::new_current_thread()
@@ -538,21 +1508,14 @@ fn run_client_thread(
     if let Err(e) = result {
         error!("Panic in client thread: {:?}", e);
     }
-
-    // Cleanup
-    IS_RUNNING.store(false, Ordering::SeqCst);
-    IS_LISTENER_READY.store(false, Ordering::SeqCst);
-    IS_QUIC_READY.store(false, Ordering::SeqCst);
-    IS_THREAD_DONE.store(true, Ordering::SeqCst);
-
-    info!("Client thread finished");
 }
 
 /// Run the client with socket protection.
 /// This wraps run_client and ensures the UDP socket is protected.
 async fn run_client_with_protection(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
     // The socket protection happens inside the modified bind_udp_socket function
-    // which calls protect_socket() after creating the socket.
+    // which calls protect_socket() after creating the socket, unless the
+    // protection mode is None.
     run_client(config).await
 }
 
@@ -563,18 +1526,22 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStopSlipst
     _class: JClass,
 ) {
     info!("nativeStopSlipstreamClient called");
+    stop_client_impl();
+}
+
+fn stop_client_impl() {
+    let _lifecycle = lock_lifecycle();
+    stop_client_locked();
+}
 
+/// Stop the client; the caller holds the lifecycle lock.
+fn stop_client_locked() {
     // Signal shutdown
-    SHOULD_SHUTDOWN.store(true, Ordering::SeqCst);
+    request_shutdown();
 
     // Give the client thread time to exit gracefully
-    let mut waited = 0;
-    while !IS_THREAD_DONE.load(Ordering::SeqCst) && waited < 3000 {
-        thread::sleep(std::time::Duration::from_millis(100));
-        waited += 100;
-    }
-
-    if !IS_THREAD_DONE.load(Ordering::SeqCst) {
+    let thread_done = wait_for_thread_done(STOP_TIMEOUT);
+    if !thread_done {
         warn!("Client thread did not exit within timeout, abandoning");
         // Abandon the thread handle to avoid blocking
         let mut guard = CLIENT_THREAD.lock().unwrap();
@@ -593,47 +1560,1727 @@ pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeStopSlipst
     IS_RUNNING.store(false, Ordering::SeqCst);
     IS_LISTENER_READY.store(false, Ordering::SeqCst);
     IS_QUIC_READY.store(false, Ordering::SeqCst);
-    SHOULD_SHUTDOWN.store(false, Ordering::SeqCst);
+    // An abandoned thread must still see the shutdown flag; the next start
+    // refuses to run until it has exited.
+    if thread_done {
+        SHOULD_SHUTDOWN.store(false, Ordering::SeqCst);
+    }
+    if let Ok(mut running_key) = RUNNING_START_KEY.lock() {
+        running_key.take();
+    }
 
     info!("Client stopped");
 }
 
-/// Check if the client is running.
+/// Close a single tunneled stream.
+///
+/// # Arguments
+/// - streamId: QUIC stream id of the connection, as logged when it was accepted
+///
+/// # Returns
+/// - 0: Stream closed
+/// - -1: Client not running
+/// - -2: No stream with this id
+/// - -3: Client loop did not respond in time
 #[no_mangle]
-pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeIsClientRunning(
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeCloseStream(
     _env: JNIEnv,
     _class: JClass,
-) -> jboolean {
-    if IS_RUNNING.load(Ordering::SeqCst) {
-        JNI_TRUE
-    } else {
-        JNI_FALSE
+    stream_id: jlong,
+) -> jint {
+    if stream_id < 0 {
+        return -2;
     }
+    let code = close_stream_impl(stream_id as u64);
+    info!("nativeCloseStream({}) -> {}", stream_id, code);
+    code
 }
 
-/// Check if the QUIC connection is ready.
+/// Send the next `queryCount` queries through one resolver, whatever its
+/// health, then hand selection back to the scheduler. For debugging a resolver
+/// that misbehaves; 0 ends a pin early.
+///
+/// # Arguments
+/// - index: Resolver position in the configured list, from 0
+/// - queryCount: Queries to send through it
+///
+/// # Returns
+/// - 0: Resolver pinned
+/// - -1: Client not running
+/// - -2: Index out of range
 #[no_mangle]
-pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeIsQuicReady(
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeForceResolver(
     _env: JNIEnv,
     _class: JClass,
-) -> jboolean {
-    if IS_QUIC_READY.load(Ordering::SeqCst) {
-        JNI_TRUE
+    index: jint,
+    query_count: jint,
+) -> jint {
+    let code = if index < 0 {
+        -2
     } else {
-        JNI_FALSE
-    }
+        match force_resolver(index as usize, query_count.max(0) as u64) {
+            Ok(()) => 0,
+            Err(e) if e.kind() == ClientErrorKind::InvalidResolverIndex => -2,
+            Err(_) => -1,
+        }
+    };
+    info!(
+        "nativeForceResolver({}, {}) -> {}",
+        index, query_count, code
+    );
+    code
 }
 
-// ============================================================================
-// Tests
-// ============================================================================
-
+/// Get a JSON snapshot of the running session (query budget, connection state).
+///
+/// # Returns
+/// - JSON object string, or an empty string when the client is not running
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetSessionInfo(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let json = session_snapshot()
+        .and_then(|info| serde_json::to_string(&info).ok())
+        .unwrap_or_default();
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create session info string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get the id of the current connection, as it appears in client and server logs,
+/// for users to quote in reports. A reconnect starts a new session with a new id,
+/// announced by a `session_rotated` tunnel event.
+///
+/// # Returns
+/// - Eight hex digits, or an empty string before the first connection or when
+///   the client is not running
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetSessionId(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    match env.new_string(session_id().unwrap_or_default()) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create session id string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get the report of the last client run: duration, bytes and queries sent,
+/// average throughput, error counts and per-resolver totals.
+///
+/// # Returns
+/// - JSON object string, or an empty string until a client has stopped
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetLastSessionSummary(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let json = last_session_summary()
+        .and_then(|summary| serde_json::to_string(&summary).ok())
+        .unwrap_or_default();
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create session summary string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Get the live state of each resolver: address, mode, health, RTT, success
+/// ratio and share of the query traffic.
+///
+/// # Returns
+/// - JSON array string, empty (`[]`) when the client is not running
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetResolverStates(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let statuses = session_snapshot()
+        .map(|info| resolver_statuses(&info))
+        .unwrap_or_default();
+    let json = serde_json::to_string(&statuses).unwrap_or_else(|_| "[]".to_string());
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create resolver states string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Fetch a URL through the tunnel to prove the whole path works.
+/// Blocks for up to the timeout; never call it on the UI thread.
+///
+/// # Arguments
+/// - url: http:// or https:// URL to fetch
+/// - listenPort: Port of the running tunnel listener
+/// - socksUsername: Remote SOCKS5 username, or empty for no authentication
+/// - socksPassword: Remote SOCKS5 password
+/// - timeoutMs: Connect and read timeout in ms
+///
+/// # Returns
+/// - JSON object with success, latency_ms, status_code, snippet and error
+///   ("Cancelled" after `nativeCancelProbe`)
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeVerifyConnectivity<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    url: JString<'local>,
+    listen_port: jint,
+    socks_username: JString<'local>,
+    socks_password: JString<'local>,
+    timeout_ms: jint,
+) -> jstring {
+    let mut read_string = |value: &JString<'local>| -> String {
+        env.get_string(value).map(Into::into).unwrap_or_default()
+    };
+    let url: String = read_string(&url);
+    let username = read_string(&socks_username);
+    let password = read_string(&socks_password);
+    PROBE_CANCEL.store(false, Ordering::SeqCst);
+
+    let result = if !IS_QUIC_READY.load(Ordering::SeqCst) {
+        ConnectivityResult {
+            error: Some("Tunnel is not ready".to_string()),
+            ..ConnectivityResult::default()
+        }
+    } else {
+        let credentials = (!username.is_empty()).then(|| SocksCredentials {
+            username: &username,
+            password: &password,
+        });
+        let listen_addr = std::net::SocketAddr::from(([127, 0, 0, 1], listen_port as u16));
+        let timeout = Duration::from_millis(timeout_ms.max(1) as u64);
+        verify_connectivity(listen_addr, &url, credentials, timeout, &PROBE_CANCEL)
+    };
+    info!(
+        "nativeVerifyConnectivity({}) -> success={} latency_ms={} status={:?} error={:?}",
+        url, result.success, result.latency_ms, result.status_code, result.error
+    );
+
+    let json = serde_json::to_string(&result).unwrap_or_default();
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create connectivity result string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Look up the public IP the tunnel's traffic leaves from, via an IP echo
+/// service reached through the tunnel. Blocks for up to the timeout unless a
+/// lookup from the last minute is reused; never call it on the UI thread.
+///
+/// # Arguments
+/// - url: http:// or https:// echo service answering with a bare IP, or empty
+///   for https://ifconfig.me/ip
+/// - listenPort: Port of the running tunnel listener
+/// - socksUsername: Remote SOCKS5 username, or empty for no authentication
+/// - socksPassword: Remote SOCKS5 password
+/// - timeoutMs: Connect and read timeout in ms
+///
+/// # Returns
+/// - JSON object with ip, cached, latency_ms and error (set instead of ip when
+///   the lookup failed)
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetExitIp<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    url: JString<'local>,
+    listen_port: jint,
+    socks_username: JString<'local>,
+    socks_password: JString<'local>,
+    timeout_ms: jint,
+) -> jstring {
+    let mut read_string = |value: &JString<'local>| -> String {
+        env.get_string(value).map(Into::into).unwrap_or_default()
+    };
+    let url: String = read_string(&url);
+    let username = read_string(&socks_username);
+    let password = read_string(&socks_password);
+    PROBE_CANCEL.store(false, Ordering::SeqCst);
+
+    let result = if !IS_QUIC_READY.load(Ordering::SeqCst) {
+        ExitIpResult {
+            error: Some("Tunnel is not ready".to_string()),
+            ..ExitIpResult::default()
+        }
+    } else {
+        let credentials = (!username.is_empty()).then(|| SocksCredentials {
+            username: &username,
+            password: &password,
+        });
+        let listen_addr = std::net::SocketAddr::from(([127, 0, 0, 1], listen_port as u16));
+        let timeout = Duration::from_millis(timeout_ms.max(1) as u64);
+        exit_ip(listen_addr, Some(&url), credentials, timeout, &PROBE_CANCEL)
+    };
+    info!(
+        "nativeGetExitIp({}) -> ip={:?} cached={} error={:?}",
+        url, result.ip, result.cached, result.error
+    );
+
+    let json = serde_json::to_string(&result).unwrap_or_default();
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create exit IP result string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Measure throughput through the tunnel against a TCP echo server.
+/// Blocks for about the duration (twice that while echoes drain); never call it
+/// on the UI thread.
+///
+/// # Arguments
+/// - echoHost, echoPort: TCP echo endpoint reached through the remote SOCKS5 proxy
+/// - listenPort: Port of the running tunnel listener
+/// - socksUsername: Remote SOCKS5 username, or empty for no authentication
+/// - socksPassword: Remote SOCKS5 password
+/// - durationMs: How long to push data, capped at 30 s
+///
+/// # Returns
+/// - JSON object with success, latency_ms, upload_bytes, download_bytes,
+///   upload_bps, download_bps, duration_ms and error ("Cancelled" after
+///   `nativeCancelProbe`)
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeRunSpeedTest<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    echo_host: JString<'local>,
+    echo_port: jint,
+    listen_port: jint,
+    socks_username: JString<'local>,
+    socks_password: JString<'local>,
+    duration_ms: jint,
+) -> jstring {
+    let mut read_string = |value: &JString<'local>| -> String {
+        env.get_string(value).map(Into::into).unwrap_or_default()
+    };
+    let echo_host = read_string(&echo_host);
+    let username = read_string(&socks_username);
+    let password = read_string(&socks_password);
+    PROBE_CANCEL.store(false, Ordering::SeqCst);
+
+    let result = if !IS_QUIC_READY.load(Ordering::SeqCst) {
+        SpeedTestResult {
+            error: Some("Tunnel is not ready".to_string()),
+            ..SpeedTestResult::default()
+        }
+    } else {
+        let credentials = (!username.is_empty()).then(|| SocksCredentials {
+            username: &username,
+            password: &password,
+        });
+        let listen_addr = std::net::SocketAddr::from(([127, 0, 0, 1], listen_port as u16));
+        let duration = Duration::from_millis(duration_ms.max(1) as u64);
+        run_speed_test(
+            listen_addr,
+            &echo_host,
+            echo_port as u16,
+            credentials,
+            duration,
+            &PROBE_CANCEL,
+        )
+    };
+    info!(
+        "nativeRunSpeedTest({}:{}) -> success={} latency_ms={} up_bps={} down_bps={} error={:?}",
+        echo_host,
+        echo_port,
+        result.success,
+        result.latency_ms,
+        result.upload_bps,
+        result.download_bps,
+        result.error
+    );
+
+    let json = serde_json::to_string(&result).unwrap_or_default();
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create speed test result string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Latency per destination seen by `nativeVerifyConnectivity`, for ranking exit
+/// options by real-world performance rather than tunnel RTT.
+///
+/// # Returns
+/// - JSON object keyed by `scheme://host[:port]` with latency_ms, min_latency_ms,
+///   successes and failures; at most 32 destinations, least recently probed dropped
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetDestinationLatencies(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let json = serde_json::to_string(&destination_latencies()).unwrap_or_else(|_| "{}".to_string());
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create destination latencies string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Export the configuration of the running (or last started) client.
+///
+/// # Returns
+/// - Versioned profile JSON, or an empty string when no client has been started.
+///   Settings that cannot be shared are omitted and listed in `reentry_required`.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeExportProfile(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let json = LAST_PROFILE
+        .lock()
+        .ok()
+        .and_then(|profile| profile.clone())
+        .unwrap_or_default();
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create profile string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Validate a profile produced by `nativeExportProfile`, possibly by a newer build.
+///
+/// # Arguments
+/// - json: Profile JSON
+///
+/// # Returns
+/// - The profile re-encoded with only the fields this build understands, or an
+///   empty string when it is invalid (the reason is logged)
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeImportProfile<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    json: JString<'local>,
+) -> jstring {
+    let input: String = env.get_string(&json).map(Into::into).unwrap_or_default();
+    let output = match import_profile(&input) {
+        Ok(profile) => serde_json::to_string(&profile).unwrap_or_default(),
+        Err(e) => {
+            warn!("nativeImportProfile rejected profile: {}", e);
+            String::new()
+        }
+    };
+    match env.new_string(output) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create profile string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Set the network the client is on, e.g. a hash of the Wi-Fi SSID.
+///
+/// Tuning learned from now on is kept under this id, and the next connection
+/// on it starts from what was kept. An empty id stops keeping tuning.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetNetworkId<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    network_id: JString<'local>,
+) {
+    let network_id: String = env
+        .get_string(&network_id)
+        .map(Into::into)
+        .unwrap_or_default();
+    set_network_id(Some(&network_id));
+}
+
+/// Export the tuning learned on every network.
+///
+/// # Returns
+/// - Versioned JSON keyed by network id, for `nativeImportLearnedTuning` on a
+///   later start
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeExportLearnedTuning(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    match env.new_string(export_learned_tuning()) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create learned tuning string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Import tuning exported by `nativeExportLearnedTuning`, replacing what is
+/// kept for the networks it covers.
+///
+/// # Returns
+/// - How many networks were imported, or -1 when the JSON is invalid (the
+///   reason is logged)
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeImportLearnedTuning<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    json: JString<'local>,
+) -> jint {
+    let input: String = env.get_string(&json).map(Into::into).unwrap_or_default();
+    match import_learned_tuning(&input) {
+        Ok(count) => count.min(jint::MAX as usize) as jint,
+        Err(e) => {
+            warn!("nativeImportLearnedTuning rejected tuning: {}", e);
+            -1
+        }
+    }
+}
+
+/// Check start arguments without starting anything.
+///
+/// Takes the same arguments as `nativeStartSlipstreamClient` and applies the
+/// bridge settings in effect for the next start.
+///
+/// # Returns
+/// - JSON array of `{severity, field, message}` issues, where severity is
+///   "error" or "warning"; empty (`[]`) when the configuration is valid
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeValidateConfig<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    domain: JString<'local>,
+    resolver_hosts: JObjectArray<'local>,
+    resolver_ports: jintArray,
+    resolver_authoritative: jbooleanArray,
+    listen_port: jint,
+    listen_host: JString<'local>,
+    congestion_control: JString<'local>,
+    keep_alive_interval: jint,
+    gso_enabled: jboolean,
+    debug_poll: jboolean,
+    debug_streams: jboolean,
+) -> jstring {
+    let issues = match read_start_args(
+        &mut env,
+        JniStartArgs {
+            domain,
+            resolver_hosts,
+            resolver_ports,
+            resolver_authoritative,
+            listen_port,
+            listen_host,
+            congestion_control,
+            keep_alive_interval,
+            gso_enabled,
+            debug_poll,
+            debug_streams,
+        },
+    ) {
+        Ok(args) => validate_config(&args.config()),
+        Err(code) => vec![ConfigIssue {
+            severity: IssueSeverity::Error,
+            field: "arguments",
+            message: format!("Could not read start arguments (code {})", code),
+        }],
+    };
+    let json = serde_json::to_string(&issues).unwrap_or_else(|_| "[]".to_string());
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create validation string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Apply new start arguments to the running client without reconnecting.
+///
+/// Takes the same arguments as `nativeStartSlipstreamClient` and applies the
+/// bridge settings in effect for the next start; only some fields change
+/// live, the rest wait for a restart.
+///
+/// # Returns
+/// - JSON `{applied, restart_required}` with the changed field names, or an
+///   empty string when no client is running or the arguments are invalid (the
+///   reason is logged)
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeReloadConfig<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    domain: JString<'local>,
+    resolver_hosts: JObjectArray<'local>,
+    resolver_ports: jintArray,
+    resolver_authoritative: jbooleanArray,
+    listen_port: jint,
+    listen_host: JString<'local>,
+    congestion_control: JString<'local>,
+    keep_alive_interval: jint,
+    gso_enabled: jboolean,
+    debug_poll: jboolean,
+    debug_streams: jboolean,
+) -> jstring {
+    let output = match read_start_args(
+        &mut env,
+        JniStartArgs {
+            domain,
+            resolver_hosts,
+            resolver_ports,
+            resolver_authoritative,
+            listen_port,
+            listen_host,
+            congestion_control,
+            keep_alive_interval,
+            gso_enabled,
+            debug_poll,
+            debug_streams,
+        },
+    ) {
+        Ok(args) => match reload_config(&args.config()) {
+            Ok(report) => serde_json::to_string(&report).unwrap_or_default(),
+            Err(e) => {
+                warn!("nativeReloadConfig failed: {}", e);
+                String::new()
+            }
+        },
+        Err(code) => {
+            warn!(
+                "nativeReloadConfig could not read arguments (code {})",
+                code
+            );
+            String::new()
+        }
+    };
+    match env.new_string(output) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create reload string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Choose what `nativeStartSlipstreamClient` does while a client is running.
+///
+/// # Arguments
+/// - policy: 0 (default) returns -14; 1 returns 0 without touching the running
+///   client when the start arguments are identical, and otherwise stops it and
+///   starts a new client with the new arguments
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetDuplicateStartPolicy(
+    _env: JNIEnv,
+    _class: JClass,
+    policy: jint,
+) {
+    let policy = if policy == DUPLICATE_START_REUSE_OR_RESTART {
+        DUPLICATE_START_REUSE_OR_RESTART
+    } else {
+        DUPLICATE_START_REJECT
+    };
+    DUPLICATE_START_POLICY.store(policy, Ordering::SeqCst);
+}
+
+/// Choose whether the next client start protects its DNS socket.
+///
+/// Pass 1 when only the SOCKS5 proxy runs and no `VpnService` exists to call
+/// `protect()` on; anything else selects the default VPN protection.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetProtectionMode(
+    _env: JNIEnv,
+    _class: JClass,
+    mode: jint,
+) {
+    let mode = if mode == PROTECTION_NONE {
+        PROTECTION_NONE
+    } else {
+        PROTECTION_VPN
+    };
+    PROTECTION_MODE.store(mode, Ordering::SeqCst);
+}
+
+/// Choose what the next client start does when the VPN revokes protection of
+/// its DNS socket mid-session. `onProtectionLost` fires either way.
+///
+/// Pass 1 to exit the client; anything else selects the default, which drops
+/// the tunnel and refuses new connections until the client is stopped.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetProtectionLossPolicy(
+    _env: JNIEnv,
+    _class: JClass,
+    policy: jint,
+) {
+    let policy = if policy == PROTECTION_LOSS_STOP {
+        PROTECTION_LOSS_STOP
+    } else {
+        PROTECTION_LOSS_HOLD
+    };
+    PROTECTION_LOSS_POLICY.store(policy, Ordering::SeqCst);
+}
+
+/// Log resolver addresses as per-run hashes (`enabled` true, the release
+/// default) or in full. Takes effect at once, also for a running client.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetLogPrivacy(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    set_log_privacy(enabled != JNI_FALSE);
+}
+
+/// Stop the client after `queries` DNS queries, counted across reconnects,
+/// from the next client start on. `onLimitReached` fires when it stops.
+/// 0 (the default) is unlimited.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetMaxLifetimeQueries(
+    _env: JNIEnv,
+    _class: JClass,
+    queries: jlong,
+) {
+    MAX_LIFETIME_QUERIES.store(queries.max(0) as u64, Ordering::SeqCst);
+}
+
+/// From the next client start on, keep at most one DNS query unanswered at a
+/// time and send no duplicate handshake queries. Traffic then looks most like
+/// an ordinary resolver's, at a large cost in throughput. Off by default.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetSingleQueryMode(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    SINGLE_QUERY_MODE.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// Keep query names within `chars` characters from the next client start on,
+/// sending less data per query. 0 (the default) allows the DNS maximum of 253.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetMaxQnameLen(
+    _env: JNIEnv,
+    _class: JClass,
+    chars: jint,
+) {
+    MAX_QNAME_LEN.store(chars.max(0) as usize, Ordering::SeqCst);
+}
+
+/// Spell query names with only the letters a to p from the next client start on,
+/// for resolvers that reject or mangle base32 labels. Carries about a fifth less
+/// data per query; the server must support the encoding.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetSafeLabelEncoding(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    SAFE_LABEL_ENCODING.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// From the next client start on, also count a resolver's answers that are not
+/// tunnel data (such as a real website's address under the tunnel domain) as
+/// failures, so the client moves off that resolver. Such answers are always
+/// dropped and reported with a `non_tunnel_response` event.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetAvoidNonTunnelResolvers(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    AVOID_NON_TUNNEL_RESOLVERS.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// From the next client start on, move an authoritative resolver whose answers
+/// show it is a recursive resolver to recursive polling, so paced bursts do not
+/// get it rate limited. Mismatched modes are reported with a
+/// `resolver_mode_mismatch` event either way.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetAdaptResolverModes(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    ADAPT_RESOLVER_MODES.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// From the next client start on, retry with a plainer query form, without the
+/// DNS Cookie and then in class IN, when a resolver answers FORMERR to tunnel
+/// queries (the default). Disabled, the queries stay as they are. Either way a
+/// `resolver_rejects_encoding` event reports it.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetAdaptToFormerr(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    ADAPT_TO_FORMERR.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// From the next client start on, treat `attempts` failed reconnects in a row
+/// with the same failure as a blocked domain and retry only every `backoff_ms`.
+/// `onPersistentBlockSuspected` fires when that happens. 0 attempts (the
+/// default) disables detection.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetBlockBackoff(
+    _env: JNIEnv,
+    _class: JClass,
+    attempts: jint,
+    backoff_ms: jlong,
+) {
+    BLOCK_BACKOFF_AFTER.store(attempts.max(0) as u32, Ordering::SeqCst);
+    BLOCK_BACKOFF_MS.store(backoff_ms.max(1) as u64, Ordering::SeqCst);
+}
+
+/// From the next client start on, stop for good once `attempts` reconnects in
+/// a row never got a ready connection, or once reconnecting has gone on for
+/// `duration_ms`, whichever comes first, with a `gave_up` event. 0 lifts a
+/// limit. Defaults to 1000 attempts and no time limit.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetReconnectLimit(
+    _env: JNIEnv,
+    _class: JClass,
+    attempts: jint,
+    duration_ms: jlong,
+) {
+    MAX_RECONNECT_ATTEMPTS.store(attempts.max(0) as u32, Ordering::SeqCst);
+    MAX_RECONNECT_DURATION_MS.store(duration_ms.max(0) as u64, Ordering::SeqCst);
+}
+
+/// Slow polling down to one poll per up to `max_interval_ms` while no stream is
+/// open, from the next client start on. The next connection restores full-rate
+/// polling at once. 0 (the default) always polls at full rate.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetIdlePolling(
+    _env: JNIEnv,
+    _class: JClass,
+    max_interval_ms: jlong,
+) {
+    IDLE_POLL_MAX_MS.store(max_interval_ms.max(0) as u64, Ordering::SeqCst);
+}
+
+/// Start idle polling at one poll per `min_interval_ms` from the next client
+/// start on; open but quiet streams never wait longer than this for a poll.
+/// Values below 1 are raised to 1.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetIdlePollFloor(
+    _env: JNIEnv,
+    _class: JClass,
+    min_interval_ms: jlong,
+) {
+    IDLE_POLL_MIN_MS.store(min_interval_ms.max(1) as u64, Ordering::SeqCst);
+}
+
+/// Check a session that has been quiet for `interval_ms` with a single poll, from
+/// the next client start on; no answer within the poll timeout reconnects. 0 (the
+/// default) disables the refresh.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetIdleRefresh(
+    _env: JNIEnv,
+    _class: JClass,
+    interval_ms: jlong,
+) {
+    IDLE_REFRESH_MS.store(interval_ms.max(0) as u64, Ordering::SeqCst);
+}
+
+/// Keep `count` polls waiting at the server from the next client start on, so
+/// data it queues is sent at once. Only takes effect against a server that
+/// holds polls (`--poll-hold-ms`). 0 (the default) disables the pool.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetPollPool(
+    _env: JNIEnv,
+    _class: JClass,
+    count: jint,
+) {
+    POLL_POOL.store(
+        count.clamp(0, MAX_POLL_POOL as jint) as usize,
+        Ordering::SeqCst,
+    );
+}
+
+/// Report the tunnel as connected (`isQuicReady`) only once `count` distinct
+/// resolvers carried tunnel data, from the next client start on. 1 (the default)
+/// reports it as soon as the handshake completes.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetConfirmResolvers(
+    _env: JNIEnv,
+    _class: JClass,
+    count: jint,
+) {
+    CONFIRM_RESOLVERS.store(count.max(1) as usize, Ordering::SeqCst);
+}
+
+/// From the next client start on, also send each handshake query to up to
+/// `copies` other resolvers, so a resolver that drops it does not stall the
+/// handshake for a retransmission timeout. Data after the handshake is sent
+/// once. 0 (the default) disables it.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetHandshakeRedundancy(
+    _env: JNIEnv,
+    _class: JClass,
+    copies: jint,
+) {
+    HANDSHAKE_REDUNDANCY.store(copies.max(0) as usize, Ordering::SeqCst);
+}
+
+/// Send the first `count` streams opened within `duration_ms` of the handshake
+/// ahead of later ones, from the next client start on. 0 (the default) disables it.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetFirstStreamBoost(
+    _env: JNIEnv,
+    _class: JClass,
+    count: jint,
+    duration_ms: jlong,
+) {
+    FIRST_STREAM_BOOST_COUNT.store(count.max(0) as usize, Ordering::SeqCst);
+    FIRST_STREAM_BOOST_MS.store(duration_ms.max(0) as u64, Ordering::SeqCst);
+}
+
+/// Send DNS Cookies (RFC 7873) to every resolver from the next client start on,
+/// for resolvers that drop or rate-limit queries without one. Off by default.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetEdnsCookies(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    EDNS_COOKIES.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// Stream log lines at `minPriority` (an `android.util.Log` priority) or more
+/// severe to `SlipstreamBridge.onLogLine` as they are logged, at most
+/// `maxLinesPerSec` a second (0 for no limit). Lines are dropped rather than
+/// slowing the tunnel when the app falls behind. Returns false if the stream
+/// could not be started.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetLogStream(
+    _env: JNIEnv,
+    _class: JClass,
+    min_priority: jint,
+    max_lines_per_sec: jint,
+) -> jboolean {
+    let level = match min_priority {
+        i32::MIN..=2 => tracing::Level::TRACE,
+        3 => tracing::Level::DEBUG,
+        4 => tracing::Level::INFO,
+        5 => tracing::Level::WARN,
+        _ => tracing::Level::ERROR,
+    };
+    // Owned by the callback, so it is dropped, and the thread detached, on the
+    // stream thread once the stream ends.
+    let detach = DetachOnDrop;
+    let forward = move |line| {
+        let _ = &detach;
+        forward_log_line(line);
+    };
+    match set_log_stream(level, max_lines_per_sec.max(0) as u32, forward) {
+        Ok(()) => JNI_TRUE,
+        Err(e) => {
+            error!("Failed to start the log stream: {}", e);
+            JNI_FALSE
+        }
+    }
+}
+
+/// Stop streaming log lines; lines already queued are still delivered.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeClearLogStream(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    clear_log_stream();
+}
+
+/// Replace the logcat filter with `level`, in `RUST_LOG` syntax: `debug`, or
+/// per-module levels such as `info,slipstream_client=trace`. Takes effect at
+/// once, also for a running client; the log stream keeps its own level.
+///
+/// # Returns
+/// - JNI_TRUE when the level was applied
+/// - JNI_FALSE when it is invalid; the previous level is kept
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetLogLevel<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    level: JString<'local>,
+) -> jboolean {
+    let level: String = env.get_string(&level).map(Into::into).unwrap_or_default();
+    match set_log_level(&level) {
+        Ok(()) => JNI_TRUE,
+        Err(err) => {
+            error!("{}", err);
+            JNI_FALSE
+        }
+    }
+}
+
+/// From the next client start on, learn how many queries per second each
+/// resolver tolerates from its REFUSED answers and dropped polls, and stay
+/// under that rate, probing slowly back up. Off by default.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetAdaptiveQueryRate(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    ADAPTIVE_QUERY_RATE.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// From the next client start on, send each resolver `initial_qps` queries per
+/// second after connecting, doubling every `doubling_ms` while the tunnel asks
+/// for more, instead of the whole startup burst at once. 0 (the default)
+/// disables the ramp.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetQueryRamp(
+    _env: JNIEnv,
+    _class: JClass,
+    initial_qps: jint,
+    doubling_ms: jlong,
+) {
+    QUERY_RAMP_QPS.store(initial_qps.max(0) as u32, Ordering::SeqCst);
+    QUERY_RAMP_DOUBLING_MS.store(doubling_ms.max(1) as u64, Ordering::SeqCst);
+}
+
+/// From the next client start on, send streams that queue data slowly ahead of
+/// streams sending in bulk, so interactive traffic is not stuck behind
+/// downloads' uploads. Off by default.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetPrioritizeInteractive(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    PRIORITIZE_INTERACTIVE.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// Let later client starts give resolvers missing from the ports array port 53,
+/// with a warning per index, instead of failing with -2. Off by default, so a
+/// ports array shorter than the hosts array is caught rather than silently
+/// routed to the wrong port.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetDefaultMissingPorts(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    DEFAULT_MISSING_PORTS.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// From the next client start on, hold streams sending in bulk to a congestion
+/// window of their own that backs off on loss and queueing, so a struggling
+/// download does not throttle interactive streams. Off by default.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetCongestionIsolation(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    CONGESTION_ISOLATION.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// Make the resolver with the most measured goodput the primary, instead of the
+/// first one in configured order, from the next client start on. Off by default.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetPreferBandwidth(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jboolean,
+) {
+    PREFER_BANDWIDTH.store(enabled != JNI_FALSE, Ordering::SeqCst);
+}
+
+/// Report disconnects and stalls through `onTunnelEvent` only once they lasted
+/// `grace_ms`, from the next client start on. Shorter ones only produce a
+/// `recovered` event. The default is 3 seconds.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetEventGrace(
+    _env: JNIEnv,
+    _class: JClass,
+    grace_ms: jlong,
+) {
+    EVENT_GRACE_MS.store(grace_ms.max(0) as u64, Ordering::SeqCst);
+}
+
+/// Keep at most `count` path probes to extra resolvers pending at once, from
+/// the next client start on, so many resolvers are not all probed in one burst.
+/// 0 (the default) probes them all right after the handshake.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetMaxParallelProbes(
+    _env: JNIEnv,
+    _class: JClass,
+    count: jint,
+) {
+    MAX_PARALLEL_PROBES.store(count.max(0) as usize, Ordering::SeqCst);
+}
+
+/// Keep at most `count` accepted local connections waiting to be set up as
+/// streams, from the next client start on. Further connections wait briefly and
+/// are then refused. 0 (the default) is unlimited.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetMaxConnectionSetups(
+    _env: JNIEnv,
+    _class: JClass,
+    count: jint,
+) {
+    MAX_CONNECTION_SETUPS.store(count.max(0) as usize, Ordering::SeqCst);
+}
+
+/// Cap stream data received from the server and not yet written to local
+/// sockets, out-of-order data included, from the next client start on. At the
+/// cap the server stops sending until the app reads. Values below 64 KiB are
+/// raised to it; 0 restores the default.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetReassemblyLimit(
+    _env: JNIEnv,
+    _class: JClass,
+    bytes: jint,
+) {
+    let bytes = match bytes.max(0) as u32 {
+        0 => 0,
+        bytes => bytes.max(MIN_REASSEMBLY_LIMIT_BYTES),
+    };
+    REASSEMBLY_LIMIT_BYTES.store(bytes as usize, Ordering::SeqCst);
+}
+
+/// Request `SO_RCVBUF`/`SO_SNDBUF` sizes for the DNS socket from the next client
+/// start on. The OS may grant less; the granted sizes are logged. 0 (the
+/// default) keeps the OS default.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetUdpBufferSizes(
+    _env: JNIEnv,
+    _class: JClass,
+    recv_bytes: jint,
+    send_bytes: jint,
+) {
+    UDP_RCVBUF_BYTES.store(recv_bytes.max(0) as usize, Ordering::SeqCst);
+    UDP_SNDBUF_BYTES.store(send_bytes.max(0) as usize, Ordering::SeqCst);
+}
+
+/// Answer UDP DNS on 127.0.0.1:`port` from the next client start on, resolving
+/// each query at `upstream` (HOST:PORT, empty for the default) as DNS over TCP
+/// through the tunnel's SOCKS5 proxy. An empty `username` skips proxy
+/// authentication; a `port` of 0 (the default) disables the forwarder.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetDnsForwarder<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    port: jint,
+    upstream: JString<'local>,
+    socks_username: JString<'local>,
+    socks_password: JString<'local>,
+) {
+    let mut read_string = |value: &JString<'local>| -> String {
+        env.get_string(value).map(Into::into).unwrap_or_default()
+    };
+    let upstream = read_string(&upstream);
+    let settings = u16::try_from(port)
+        .ok()
+        .filter(|port| *port > 0)
+        .map(|port| DnsForwarderSettings {
+            port,
+            upstream: if upstream.is_empty() {
+                DEFAULT_DNS_UPSTREAM.to_string()
+            } else {
+                upstream
+            },
+            username: read_string(&socks_username),
+            password: read_string(&socks_password),
+        });
+    if let Ok(mut dns) = DNS_FORWARDER.lock() {
+        *dns = settings;
+    }
+}
+
+/// Run the tunnel only inside the given local-time windows from the next client
+/// start on, pausing it outside them.
+///
+/// `windows` is a comma-separated list of `HH:MM-HH:MM`; an empty string keeps
+/// the tunnel always active (the default).
+///
+/// # Returns
+/// - JNI_TRUE when the windows were stored
+/// - JNI_FALSE when one is invalid; the previous windows are kept
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetActiveWindows<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    windows: JString<'local>,
+) -> jboolean {
+    let windows: String = env.get_string(&windows).map(Into::into).unwrap_or_default();
+    let parsed: Result<Vec<ActiveWindow>, String> = windows
+        .split(',')
+        .filter(|window| !window.trim().is_empty())
+        .map(parse_active_window)
+        .collect();
+    match parsed {
+        Ok(parsed) => {
+            if let Ok(mut active_windows) = ACTIVE_WINDOWS.lock() {
+                *active_windows = parsed;
+            }
+            JNI_TRUE
+        }
+        Err(err) => {
+            error!("{}", err);
+            JNI_FALSE
+        }
+    }
+}
+
+/// Keep background activity low while the tunnel is idle inside the given
+/// local-time windows, from the next client start on. The tunnel stays
+/// connected; traffic or the end of a window restores normal activity.
+///
+/// `windows` is a comma-separated list of `HH:MM-HH:MM`; an empty string turns
+/// quiet hours off (the default).
+///
+/// # Returns
+/// - JNI_TRUE when the windows were stored
+/// - JNI_FALSE when one is invalid; the previous windows are kept
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetQuietHours<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    windows: JString<'local>,
+) -> jboolean {
+    let windows: String = env.get_string(&windows).map(Into::into).unwrap_or_default();
+    let parsed: Result<Vec<ActiveWindow>, String> = windows
+        .split(',')
+        .filter(|window| !window.trim().is_empty())
+        .map(parse_active_window)
+        .collect();
+    match parsed {
+        Ok(parsed) => {
+            if let Ok(mut quiet_hours) = QUIET_HOURS.lock() {
+                *quiet_hours = parsed;
+            }
+            JNI_TRUE
+        }
+        Err(err) => {
+            error!("{}", err);
+            JNI_FALSE
+        }
+    }
+}
+
+/// Set the destinations the SOCKS bridge answers with "not allowed by ruleset".
+///
+/// `entries` is a comma-separated list of domain suffixes and CIDRs; an empty
+/// string blocks nothing (the default). Applies to new connections right away.
+///
+/// # Returns
+/// - JNI_TRUE when the blocklist was stored
+/// - JNI_FALSE when an entry is invalid; the previous blocklist is kept
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetDestinationBlocklist<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    entries: JString<'local>,
+) -> jboolean {
+    let entries: String = env.get_string(&entries).map(Into::into).unwrap_or_default();
+    match DestinationBlocklist::parse(&entries) {
+        Ok(parsed) => {
+            if let Ok(mut blocklist) = DESTINATION_BLOCKLIST.lock() {
+                *blocklist = (!parsed.is_empty()).then_some(parsed);
+            }
+            JNI_TRUE
+        }
+        Err(err) => {
+            error!("{}", err);
+            JNI_FALSE
+        }
+    }
+}
+
+/// Whether a CONNECT to `host`, a name or an IP literal, is on the blocklist.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeIsDestinationBlocked<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    host: JString<'local>,
+) -> jboolean {
+    let Ok(blocklist) = DESTINATION_BLOCKLIST.lock() else {
+        return JNI_FALSE;
+    };
+    let Some(blocklist) = blocklist.as_ref() else {
+        return JNI_FALSE;
+    };
+    let host: String = env.get_string(&host).map(Into::into).unwrap_or_default();
+    if blocklist.is_blocked(&host) {
+        record_destination_error(&host, None, DestinationError::Blocked, None);
+        JNI_TRUE
+    } else {
+        JNI_FALSE
+    }
+}
+
+/// Set what the SOCKS bridge does with a CONNECT that duplicates one to the same
+/// destination still in flight, or one that failed, within `window_ms`.
+///
+/// `policy` is `allow` (the default), `coalesce` or `refuse`. Applies to new
+/// connections right away and forgets the connections seen so far.
+///
+/// # Returns
+/// - JNI_TRUE when the policy was stored
+/// - JNI_FALSE when the policy name is invalid; the previous policy is kept
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetDuplicateConnectPolicy<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    policy: JString<'local>,
+    window_ms: jlong,
+) -> jboolean {
+    let policy: String = env.get_string(&policy).map(Into::into).unwrap_or_default();
+    match policy.parse::<DuplicateConnectPolicy>() {
+        Ok(policy) => {
+            if let Ok(mut connects) = DUPLICATE_CONNECTS.lock() {
+                *connects = (policy != DuplicateConnectPolicy::Allow && window_ms > 0).then(|| {
+                    (
+                        Instant::now(),
+                        DuplicateConnects::new(policy, window_ms as u64),
+                    )
+                });
+            }
+            JNI_TRUE
+        }
+        Err(err) => {
+            error!("{}", err);
+            JNI_FALSE
+        }
+    }
+}
+
+/// Ask whether the SOCKS bridge may open a stream for a CONNECT to `host:port`.
+///
+/// # Returns
+/// - 0 to go ahead; report the outcome with `nativeFinishConnect`
+/// - 1 when an earlier CONNECT to the destination is in flight; ask again shortly
+/// - 2 to refuse the CONNECT without opening a stream
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeBeginConnect<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    host: JString<'local>,
+    port: jint,
+) -> jint {
+    let Ok(mut connects) = DUPLICATE_CONNECTS.lock() else {
+        return 0;
+    };
+    let Some((epoch, connects)) = connects.as_mut() else {
+        return 0;
+    };
+    let host: String = env.get_string(&host).map(Into::into).unwrap_or_default();
+    let now_us = epoch.elapsed().as_micros() as u64;
+    match connects.begin(&host, port as u16, now_us) {
+        ConnectDecision::Proceed => 0,
+        ConnectDecision::Wait => 1,
+        ConnectDecision::Refuse => {
+            debug!("Refusing duplicate CONNECT to {}:{}", host, port);
+            2
+        }
+    }
+}
+
+/// Report whether a CONNECT that `nativeBeginConnect` let through connected.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeFinishConnect<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    host: JString<'local>,
+    port: jint,
+    connected: jboolean,
+) {
+    let Ok(mut connects) = DUPLICATE_CONNECTS.lock() else {
+        return;
+    };
+    let Some((epoch, connects)) = connects.as_mut() else {
+        return;
+    };
+    let host: String = env.get_string(&host).map(Into::into).unwrap_or_default();
+    let now_us = epoch.elapsed().as_micros() as u64;
+    connects.finish(&host, port as u16, connected != JNI_FALSE, now_us);
+}
+
+/// Cap the CONNECTs the SOCKS bridge keeps open to one destination host.
+///
+/// `default_limit` applies to every host without an override; `overrides` is
+/// comma-separated `host=COUNT` entries. 0 is no cap, the default. Applies to
+/// new connections right away; connections already open keep counting.
+///
+/// # Returns
+/// - JNI_TRUE when the limits were stored
+/// - JNI_FALSE when an override is invalid; the previous limits are kept
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetHostConnectionLimits<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    default_limit: jint,
+    overrides: JString<'local>,
+) -> jboolean {
+    let overrides: String = env
+        .get_string(&overrides)
+        .map(Into::into)
+        .unwrap_or_default();
+    match set_host_connection_limits(default_limit.max(0) as usize, &overrides) {
+        Ok(()) => JNI_TRUE,
+        Err(err) => {
+            error!("{}", err);
+            JNI_FALSE
+        }
+    }
+}
+
+/// Count a CONNECT to `host` against its cap before the SOCKS bridge opens a
+/// stream for it.
+///
+/// # Returns
+/// - JNI_TRUE to go ahead; report the end of the connection with
+///   `nativeCloseHostConnection`
+/// - JNI_FALSE when the host is at its cap; refuse the CONNECT
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeOpenHostConnection<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    host: JString<'local>,
+) -> jboolean {
+    let host: String = env.get_string(&host).map(Into::into).unwrap_or_default();
+    if open_host_connection(&host) {
+        JNI_TRUE
+    } else {
+        debug!("Refusing CONNECT to {}: at its connection limit", host);
+        JNI_FALSE
+    }
+}
+
+/// Report that a CONNECT `nativeOpenHostConnection` let through has ended.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeCloseHostConnection<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    host: JString<'local>,
+) {
+    let host: String = env.get_string(&host).map(Into::into).unwrap_or_default();
+    close_host_connection(&host);
+}
+
+/// Report the TLS version, e.g. "TLSv1.3", the SOCKS bridge's DoH fallback
+/// negotiated. Shows in the session info as `doh_tls_version`.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeReportDohHandshake<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    version: JString<'local>,
+) {
+    let version: String = env.get_string(&version).map(Into::into).unwrap_or_default();
+    record_doh_handshake(&version);
+}
+
+/// Report a DoH handshake that could not negotiate the bridge's minimum TLS
+/// version. Counted in the session info as `doh_tls_below_minimum`.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeReportDohBelowMinimum(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    record_doh_below_minimum();
+}
+
+/// Forget the DoH TLS state reported so far, when the SOCKS bridge restarts.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeResetDohTls(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    reset_doh_tls();
+}
+
+/// Set the bound address the SOCKS bridge reports in successful CONNECT replies.
+///
+/// `policy` is `relay` (the default), `zero`, `destination` or an `IP:PORT`
+/// placeholder. Applies to new connections right away.
+///
+/// # Returns
+/// - JNI_TRUE when the policy was stored
+/// - JNI_FALSE when the policy is invalid; the previous policy is kept
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetBoundAddressPolicy<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    policy: JString<'local>,
+) -> jboolean {
+    let policy: String = env.get_string(&policy).map(Into::into).unwrap_or_default();
+    match policy.parse::<BoundAddressPolicy>() {
+        Ok(policy) => {
+            if let Ok(mut current) = BOUND_ADDRESS_POLICY.lock() {
+                *current = policy;
+            }
+            JNI_TRUE
+        }
+        Err(err) => {
+            error!("{}", err);
+            JNI_FALSE
+        }
+    }
+}
+
+/// Build the success reply to a CONNECT under the bound address policy.
+///
+/// `requested` is the request's ATYP, DST.ADDR and DST.PORT; `relayed` the
+/// ATYP, BND.ADDR and BND.PORT the remote proxy replied with.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSocksConnectReply<'local>(
+    env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    requested: JByteArray<'local>,
+    relayed: JByteArray<'local>,
+) -> jbyteArray {
+    let requested = env.convert_byte_array(&requested).unwrap_or_default();
+    let relayed = env.convert_byte_array(&relayed).unwrap_or_default();
+    let policy = BOUND_ADDRESS_POLICY
+        .lock()
+        .map(|policy| *policy)
+        .unwrap_or_default();
+    let reply = policy.connect_reply(&requested, &relayed);
+    match env.byte_array_from_slice(&reply) {
+        Ok(array) => array.into_raw(),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+fn record_destination_error(
+    host: &str,
+    port: Option<u16>,
+    reason: DestinationError,
+    reply: Option<u8>,
+) {
+    let Ok(mut errors) = DESTINATION_ERRORS.lock() else {
+        return;
+    };
+    let (epoch, errors) = errors.get_or_insert_with(|| (Instant::now(), DestinationErrors::new()));
+    let now_us = epoch.elapsed().as_micros() as u64;
+    errors.record(host, port, reason, reply, now_us);
+}
+
+/// Report a CONNECT to `host:port` that the SOCKS bridge could not complete.
+///
+/// `reply` is the SOCKS5 reply code the server sent through the tunnel, or -1
+/// when no reply arrived; the cause is then put down to unanswered DNS queries,
+/// or to a resolver answering REFUSED.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeReportConnectFailure<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    host: JString<'local>,
+    port: jint,
+    reply: jint,
+) {
+    let host: String = env.get_string(&host).map(Into::into).unwrap_or_default();
+    let reply = u8::try_from(reply).ok();
+    let resolver_refusing = session_snapshot()
+        .is_some_and(|info| info.resolvers.iter().any(|resolver| resolver.refusing));
+    let reason = DestinationError::classify(reply, resolver_refusing);
+    debug!("CONNECT to {}:{} failed: {:?}", host, port, reason);
+    record_destination_error(&host, Some(port as u16), reason, reply);
+}
+
+/// Why the latest CONNECT to `host` failed.
+///
+/// # Returns
+/// - JSON such as `{"reason":"connect_refused","reply":5,"port":443,"age_ms":1200}`.
+///   `reason` is `dns_timeout`, `resolver_refused`, `connect_refused`,
+///   `unreachable`, `blocked` or `proxy_failure`. The latest 64 hosts are kept.
+/// - Empty string when no failure of `host` is recorded.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeGetLastDestinationError<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    host: JString<'local>,
+) -> jstring {
+    let host: String = env.get_string(&host).map(Into::into).unwrap_or_default();
+    let json = DESTINATION_ERRORS
+        .lock()
+        .ok()
+        .and_then(|errors| {
+            let (epoch, errors) = errors.as_ref()?;
+            errors.last(&host, epoch.elapsed().as_micros() as u64)
+        })
+        .and_then(|failure| serde_json::to_string(&failure).ok())
+        .unwrap_or_default();
+    match env.new_string(json) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create destination error string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Keep the last `records` DNS packets in memory from the next client start on.
+///
+/// Only wire bytes are kept; tunneled data inside them stays QUIC-encrypted.
+/// 0 (the default) disables the recorder.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeSetFlightRecorder(
+    _env: JNIEnv,
+    _class: JClass,
+    records: jint,
+) {
+    FLIGHT_RECORDER_RECORDS.store(records.max(0) as usize, Ordering::SeqCst);
+}
+
+/// Export the flight recorder in the `--record-dns` capture format.
+///
+/// # Returns
+/// - Capture text with a `# unix_us=` comment before each packet, or an empty
+///   string when the recorder was not enabled for the last client start
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeExportCapture(
+    env: JNIEnv,
+    _class: JClass,
+) -> jstring {
+    let capture = export_flight_recorder().unwrap_or_default();
+    match env.new_string(capture) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            error!("Failed to create capture string: {:?}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Stop a connectivity probe running on another thread; it returns promptly
+/// with a "Cancelled" error. Harmless when no probe is running.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeCancelProbe(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    PROBE_CANCEL.store(true, Ordering::SeqCst);
+}
+
+/// Tell a client waiting out a network loss that connectivity changed, so it
+/// tries to connect right away. Harmless while online.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeNotifyNetworkChanged(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    NETWORK_CHANGED.store(true, Ordering::SeqCst);
+}
+
+/// Look up SlipstreamBridge again and replace the cached class reference.
+///
+/// A safety valve for hot reload or a changed class loader, where the cached
+/// reference goes stale and callbacks such as socket protection fail. Keeps the
+/// previous reference when the class cannot be found.
+///
+/// # Returns
+/// - true if the class was found and cached again
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeRefreshBridgeClass(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jboolean {
+    if cache_bridge_class(&mut env) {
+        JNI_TRUE
+    } else {
+        JNI_FALSE
+    }
+}
+
+/// Check if the client is running.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeIsClientRunning(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jboolean {
+    if IS_RUNNING.load(Ordering::SeqCst) {
+        JNI_TRUE
+    } else {
+        JNI_FALSE
+    }
+}
+
+/// Check if the QUIC connection is ready.
+#[no_mangle]
+pub extern "system" fn Java_app_slipnet_tunnel_SlipstreamBridge_nativeIsQuicReady(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jboolean {
+    if IS_QUIC_READY.load(Ordering::SeqCst) {
+        JNI_TRUE
+    } else {
+        JNI_FALSE
+    }
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // The tests below share the global client state.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn lock_test_state() -> std::sync::MutexGuard<'static, ()> {
+        TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    #[test]
+    fn resolver_ports_must_match_hosts_unless_defaulting() {
+        assert_eq!(
+            resolver_ports_for_hosts(vec![53, 5353], 2, 2, false),
+            Ok(vec![53, 5353])
+        );
+        assert_eq!(
+            resolver_ports_for_hosts(vec![5353], 1, 3, false),
+            Err("Resolver at index 1 has no port (1 ports for 3 hosts)".to_string())
+        );
+        assert_eq!(
+            resolver_ports_for_hosts(vec![5353], 1, 3, true),
+            Ok(vec![5353, 53, 53])
+        );
+        assert_eq!(
+            resolver_ports_for_hosts(vec![53], 2, 1, true),
+            Err("Resolver port at index 1 has no host (2 ports for 1 hosts)".to_string())
+        );
+    }
+
     #[test]
     fn test_state_flags() {
+        let _guard = lock_test_state();
         // Initial state
         assert!(!IS_RUNNING.load(Ordering::SeqCst));
         assert!(!IS_LISTENER_READY.load(Ordering::SeqCst));
@@ -657,8 +3304,140 @@ mod tests {
         IS_LISTENER_READY.store(false, Ordering::SeqCst);
     }
 
+    #[test]
+    fn test_close_stream_requires_running_client() {
+        clear_command_sender();
+        assert_eq!(close_stream_impl(4), -1);
+    }
+
+    #[test]
+    fn test_concurrent_start_stop() {
+        let _guard = lock_test_state();
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+
+        let live_threads = Arc::new(AtomicUsize::new(0));
+        let max_live = Arc::new(AtomicUsize::new(0));
+        let workers: Vec<_> = (0..8)
+            .map(|worker| {
+                let live_threads = Arc::clone(&live_threads);
+                let max_live = Arc::clone(&max_live);
+                thread::spawn(move || {
+                    for _ in 0..20 {
+                        if worker % 2 == 0 {
+                            let live_threads = Arc::clone(&live_threads);
+                            let max_live = Arc::clone(&max_live);
+                            let code = start_client_with(move || {
+                                Ok((String::new(), move || {
+                                    let live = live_threads.fetch_add(1, Ordering::SeqCst) + 1;
+                                    max_live.fetch_max(live, Ordering::SeqCst);
+                                    signal_listener_ready();
+                                    while !should_shutdown() {
+                                        thread::sleep(Duration::from_millis(1));
+                                    }
+                                    live_threads.fetch_sub(1, Ordering::SeqCst);
+                                }))
+                            });
+                            assert!(
+                                code == 0 || code == -13 || code == START_ALREADY_RUNNING,
+                                "unexpected start code {}",
+                                code
+                            );
+                        } else {
+                            stop_client_impl();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().expect("worker panicked");
+        }
+        stop_client_impl();
+
+        assert_eq!(
+            max_live.load(Ordering::SeqCst),
+            1,
+            "client thread started twice"
+        );
+        assert_eq!(live_threads.load(Ordering::SeqCst), 0);
+        assert!(!IS_RUNNING.load(Ordering::SeqCst));
+        assert!(IS_THREAD_DONE.load(Ordering::SeqCst));
+        assert!(!should_shutdown());
+        assert!(CLIENT_THREAD.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_stop_returns_once_the_thread_exits() {
+        let _guard = lock_test_state();
+        let code = start_client_with(|| {
+            Ok((String::new(), || {
+                signal_listener_ready();
+                while !should_shutdown() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }))
+        });
+        assert_eq!(code, 0);
+
+        // The old stop loop slept in 100 ms steps; the wait now ends as soon as
+        // the thread reports it is done.
+        let started = std::time::Instant::now();
+        stop_client_impl();
+        let elapsed = started.elapsed();
+        assert!(
+            elapsed < Duration::from_millis(80),
+            "stop took {:?}",
+            elapsed
+        );
+        assert!(IS_THREAD_DONE.load(Ordering::SeqCst));
+        assert!(CLIENT_THREAD.lock().unwrap().is_none());
+        assert!(!IS_RUNNING.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_duplicate_start_policies() {
+        let _guard = lock_test_state();
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Arc;
+
+        let starts = Arc::new(AtomicUsize::new(0));
+        let start = |key: &str| {
+            let starts = Arc::clone(&starts);
+            let key = key.to_string();
+            start_client_with(move || {
+                Ok((key, move || {
+                    starts.fetch_add(1, Ordering::SeqCst);
+                    signal_listener_ready();
+                    while !should_shutdown() {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }))
+            })
+        };
+
+        assert_eq!(start("a"), 0);
+        assert_eq!(start("a"), START_ALREADY_RUNNING);
+
+        DUPLICATE_START_POLICY.store(DUPLICATE_START_REUSE_OR_RESTART, Ordering::SeqCst);
+        assert_eq!(start("a"), 0);
+        assert_eq!(
+            starts.load(Ordering::SeqCst),
+            1,
+            "same config must not restart"
+        );
+        assert_eq!(start("b"), 0);
+        assert_eq!(starts.load(Ordering::SeqCst), 2, "new config must restart");
+        assert!(IS_RUNNING.load(Ordering::SeqCst));
+
+        DUPLICATE_START_POLICY.store(DUPLICATE_START_REJECT, Ordering::SeqCst);
+        stop_client_impl();
+        assert!(RUNNING_START_KEY.lock().unwrap().is_none());
+    }
+
     #[test]
     fn test_failure_tracking() {
+        let _guard = lock_test_state();
         CONSECUTIVE_FAILURES.store(0, Ordering::SeqCst);
 
         assert!(!exceeded_max_failures());
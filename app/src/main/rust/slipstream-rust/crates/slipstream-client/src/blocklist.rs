@@ -0,0 +1,150 @@
+//! Destinations the client refuses to tunnel to.
+//!
+//! The local SOCKS5 edge checks each CONNECT against the blocklist before it
+//! opens a tunnel stream, and answers a match with reply 0x02 ("connection not
+//! allowed by ruleset").
+
+use std::net::IpAddr;
+
+/// Domain suffixes and CIDR ranges; empty blocks nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DestinationBlocklist {
+    /// Lowercase names without a trailing dot.
+    suffixes: Vec<String>,
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl DestinationBlocklist {
+    /// Parses comma-separated entries. `example.com` (or `*.example.com`) blocks
+    /// that name and every name under it; `10.0.0.0/8`, `2001:db8::/32` or a bare
+    /// address blocks IP destinations in that range.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut blocklist = Self::default();
+        for entry in input.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            if let Some(network) = parse_network(entry)? {
+                blocklist.networks.push(network);
+                continue;
+            }
+            let suffix = normalize_name(entry.strip_prefix("*.").unwrap_or(entry));
+            let valid = !suffix.is_empty()
+                && suffix.split('.').all(|label| {
+                    !label.is_empty()
+                        && label
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                });
+            if !valid {
+                return Err(format!(
+                    "Invalid blocklist entry {} (expected a domain suffix or CIDR)",
+                    entry
+                ));
+            }
+            blocklist.suffixes.push(suffix);
+        }
+        Ok(blocklist)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.suffixes.is_empty() && self.networks.is_empty()
+    }
+
+    /// Whether a CONNECT to `host`, a name or an IP literal, is refused.
+    pub fn is_blocked(&self, host: &str) -> bool {
+        let literal = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(addr) = literal.parse::<IpAddr>() {
+            let addr = addr.to_canonical();
+            return self
+                .networks
+                .iter()
+                .any(|(network, prefix)| in_network(addr, *network, *prefix));
+        }
+        let name = normalize_name(host);
+        self.suffixes.iter().any(|suffix| {
+            name == *suffix
+                || (name.len() > suffix.len()
+                    && name.ends_with(suffix.as_str())
+                    && name.as_bytes()[name.len() - suffix.len() - 1] == b'.')
+        })
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// `Ok(None)` when `entry` is not an address, so it is taken as a name.
+fn parse_network(entry: &str) -> Result<Option<(IpAddr, u8)>, String> {
+    let (addr, prefix) = match entry.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (entry, None),
+    };
+    let Ok(addr) = addr.parse::<IpAddr>() else {
+        return match prefix {
+            Some(_) => Err(format!("Invalid blocklist CIDR {}", entry)),
+            None => Ok(None),
+        };
+    };
+    let addr = addr.to_canonical();
+    let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix
+            .parse::<u8>()
+            .ok()
+            .filter(|prefix| *prefix <= max_prefix)
+            .ok_or_else(|| format!("Invalid blocklist CIDR {}", entry))?,
+        None => max_prefix,
+    };
+    Ok(Some((addr, prefix)))
+}
+
+fn in_network(addr: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (addr, network) {
+        (IpAddr::V4(addr), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+            u32::from(addr) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(addr), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+            u128::from(addr) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffixes_block_the_name_and_its_subdomains() {
+        let blocklist = DestinationBlocklist::parse("example.com, *.Ads.Test.").unwrap();
+        assert!(blocklist.is_blocked("example.com"));
+        assert!(blocklist.is_blocked("WWW.example.com."));
+        assert!(blocklist.is_blocked("tracker.ads.test"));
+        assert!(blocklist.is_blocked("ads.test"));
+        assert!(!blocklist.is_blocked("notexample.com"));
+        assert!(!blocklist.is_blocked("example.com.evil"));
+        assert!(!blocklist.is_blocked("test"));
+        assert!(!blocklist.is_blocked("93.184.216.34"));
+    }
+
+    #[test]
+    fn cidrs_block_addresses_in_range() {
+        let blocklist =
+            DestinationBlocklist::parse("10.0.0.0/8,192.0.2.7,2001:db8::/32,0.0.0.0/0").unwrap();
+        assert!(blocklist.is_blocked("10.255.1.2"));
+        assert!(blocklist.is_blocked("192.0.2.7"));
+        assert!(blocklist.is_blocked("[2001:db8::1]"));
+        // IPv4-mapped addresses match their IPv4 range.
+        assert!(blocklist.is_blocked("::ffff:10.0.0.1"));
+        assert!(!blocklist.is_blocked("2001:db9::1"));
+        // A /0 covers every address of its family, and no names.
+        assert!(blocklist.is_blocked("8.8.8.8"));
+        assert!(!blocklist.is_blocked("example.com"));
+
+        assert!(DestinationBlocklist::parse("").unwrap().is_empty());
+        for invalid in ["10.0.0.0/33", "::/129", "bad/8", "exa mple.com", "*."] {
+            assert!(DestinationBlocklist::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
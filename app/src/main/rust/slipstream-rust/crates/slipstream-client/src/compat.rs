@@ -0,0 +1,76 @@
+//! Detects a server build that cannot speak this client's protocol.
+//!
+//! Version, ALPN and transport parameter mismatches all surface as connection
+//! closes during the handshake, which otherwise read like a flaky network. The
+//! close codes are specific enough to name the mismatched feature.
+
+use slipstream_ffi::picoquic::{
+    PICOQUIC_ERROR_VERSION_NEGOTIATION, PICOQUIC_TLS_ALERT_WRONG_ALPN,
+    PICOQUIC_TRANSPORT_PARAMETER_ERROR, PICOQUIC_TRANSPORT_VERSION_NEGOTIATION_ERROR,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ServerIncompatible {
+    /// Protocol feature the two sides disagree on.
+    pub(crate) feature: &'static str,
+    pub(crate) code: u64,
+}
+
+impl ServerIncompatible {
+    /// Classifies the close reasons of a connection; `None` means an ordinary close.
+    pub(crate) fn from_close_reasons(local_error: u64, remote_error: u64) -> Option<Self> {
+        [remote_error, local_error]
+            .into_iter()
+            .find_map(|code| feature_for_code(code).map(|feature| Self { feature, code }))
+    }
+}
+
+impl fmt::Display for ServerIncompatible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Server is incompatible with this client ({} mismatch, error 0x{:x}); update client and server to matching builds",
+            self.feature, self.code
+        )
+    }
+}
+
+fn feature_for_code(code: u64) -> Option<&'static str> {
+    match code {
+        PICOQUIC_TLS_ALERT_WRONG_ALPN => Some("alpn"),
+        PICOQUIC_ERROR_VERSION_NEGOTIATION | PICOQUIC_TRANSPORT_VERSION_NEGOTIATION_ERROR => {
+            Some("quic-version")
+        }
+        PICOQUIC_TRANSPORT_PARAMETER_ERROR => Some("transport-parameters"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_the_mismatched_feature() {
+        let alpn = ServerIncompatible::from_close_reasons(0, PICOQUIC_TLS_ALERT_WRONG_ALPN)
+            .expect("alpn mismatch");
+        assert_eq!(alpn.feature, "alpn");
+        assert!(alpn.to_string().contains("alpn mismatch"));
+
+        let version = ServerIncompatible::from_close_reasons(PICOQUIC_ERROR_VERSION_NEGOTIATION, 0)
+            .expect("version mismatch");
+        assert_eq!(version.feature, "quic-version");
+
+        let params = ServerIncompatible::from_close_reasons(0, PICOQUIC_TRANSPORT_PARAMETER_ERROR)
+            .expect("transport parameter mismatch");
+        assert_eq!(params.feature, "transport-parameters");
+    }
+
+    #[test]
+    fn ordinary_closes_are_not_incompatible() {
+        assert_eq!(ServerIncompatible::from_close_reasons(0, 0), None);
+        // Idle timeout and application errors say nothing about compatibility.
+        assert_eq!(ServerIncompatible::from_close_reasons(0x40e, 0x101), None);
+    }
+}
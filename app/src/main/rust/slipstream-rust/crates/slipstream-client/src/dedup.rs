@@ -0,0 +1,220 @@
+//! Rapid duplicate CONNECTs from apps that retry aggressively.
+//!
+//! Every app reaches the local SOCKS5 edge through the same tun2socks socket, so
+//! a retry shows up as another CONNECT to the same destination while the first
+//! is still in flight, or right after it failed. Each one would open its own
+//! tunnel stream. The edge asks [`DuplicateConnects::begin`] before opening a
+//! stream and reports the outcome with [`DuplicateConnects::finish`].
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// What to do with a CONNECT that duplicates a recent one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateConnectPolicy {
+    /// Open a stream for every CONNECT (the default).
+    #[default]
+    Allow,
+    /// Hold the duplicate until the earlier attempt settles, then share its
+    /// failure or go ahead once it connected.
+    Coalesce,
+    /// Refuse the duplicate at once.
+    Refuse,
+}
+
+impl FromStr for DuplicateConnectPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "allow" => Ok(Self::Allow),
+            "coalesce" => Ok(Self::Coalesce),
+            "refuse" => Ok(Self::Refuse),
+            _ => Err(format!(
+                "Invalid duplicate CONNECT policy {} (expected allow, coalesce or refuse)",
+                value
+            )),
+        }
+    }
+}
+
+/// Answer to [`DuplicateConnects::begin`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectDecision {
+    /// Open the stream, then report the outcome with `finish`.
+    Proceed,
+    /// An earlier attempt is in flight; ask again shortly.
+    Wait,
+    /// Answer the CONNECT with a failure without opening a stream.
+    Refuse,
+}
+
+#[derive(Debug, Default)]
+struct Destination {
+    /// Attempts that proceeded and were not finished yet.
+    in_flight: usize,
+    last_started_us: u64,
+    last_failed_us: Option<u64>,
+}
+
+/// Tracks CONNECTs per destination to spot duplicates within a window.
+///
+/// A CONNECT is a duplicate while an earlier one to the same host and port,
+/// started less than `window` ago, is still in flight, or when the last one
+/// failed less than `window` ago. Attempts in flight for longer no longer hold
+/// others back.
+#[derive(Debug)]
+pub struct DuplicateConnects {
+    policy: DuplicateConnectPolicy,
+    window_us: u64,
+    destinations: HashMap<(String, u16), Destination>,
+}
+
+impl DuplicateConnects {
+    pub fn new(policy: DuplicateConnectPolicy, window_ms: u64) -> Self {
+        Self {
+            policy,
+            window_us: window_ms.saturating_mul(1_000),
+            destinations: HashMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> DuplicateConnectPolicy {
+        self.policy
+    }
+
+    /// Decides on a CONNECT to `host:port` at `now_us`.
+    pub fn begin(&mut self, host: &str, port: u16, now_us: u64) -> ConnectDecision {
+        if self.policy == DuplicateConnectPolicy::Allow || self.window_us == 0 {
+            return ConnectDecision::Proceed;
+        }
+        self.prune(now_us);
+        let window_us = self.window_us;
+        let destination = self.destinations.entry(key(host, port)).or_default();
+        let within = |at: u64| now_us.saturating_sub(at) < window_us;
+        let in_flight = destination.in_flight > 0 && within(destination.last_started_us);
+        let just_failed = destination.last_failed_us.is_some_and(within);
+        if in_flight && self.policy == DuplicateConnectPolicy::Coalesce {
+            return ConnectDecision::Wait;
+        }
+        if in_flight || just_failed {
+            return ConnectDecision::Refuse;
+        }
+        destination.in_flight += 1;
+        destination.last_started_us = now_us;
+        ConnectDecision::Proceed
+    }
+
+    /// Reports how an attempt that got [`ConnectDecision::Proceed`] ended.
+    pub fn finish(&mut self, host: &str, port: u16, connected: bool, now_us: u64) {
+        let Some(destination) = self.destinations.get_mut(&key(host, port)) else {
+            return;
+        };
+        destination.in_flight = destination.in_flight.saturating_sub(1);
+        destination.last_failed_us = (!connected).then_some(now_us);
+    }
+
+    fn prune(&mut self, now_us: u64) {
+        let window_us = self.window_us;
+        self.destinations.retain(|_, destination| {
+            destination.in_flight > 0
+                || destination
+                    .last_failed_us
+                    .is_some_and(|at| now_us.saturating_sub(at) < window_us)
+        });
+    }
+}
+
+fn key(host: &str, port: u16) -> (String, u16) {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    (host.trim_end_matches('.').to_ascii_lowercase(), port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000;
+
+    #[test]
+    fn duplicate_connects_follow_the_policy() {
+        let mut allow = DuplicateConnects::new(DuplicateConnectPolicy::Allow, 2_000);
+        for _ in 0..3 {
+            assert_eq!(allow.begin("example.com", 443, 0), ConnectDecision::Proceed);
+        }
+
+        let mut refuse = DuplicateConnects::new(DuplicateConnectPolicy::Refuse, 2_000);
+        assert_eq!(
+            refuse.begin("example.com", 443, 0),
+            ConnectDecision::Proceed
+        );
+        assert_eq!(
+            refuse.begin("Example.COM.", 443, 100 * MS),
+            ConnectDecision::Refuse
+        );
+        assert_eq!(
+            refuse.begin("example.com", 80, 100 * MS),
+            ConnectDecision::Proceed
+        );
+        refuse.finish("example.com", 443, false, 500 * MS);
+        // A retry right after the failure is refused too, a later one is not.
+        assert_eq!(
+            refuse.begin("example.com", 443, 900 * MS),
+            ConnectDecision::Refuse
+        );
+        assert_eq!(
+            refuse.begin("example.com", 443, 2_500 * MS),
+            ConnectDecision::Proceed
+        );
+        refuse.finish("example.com", 443, true, 2_600 * MS);
+        assert_eq!(
+            refuse.begin("example.com", 443, 2_700 * MS),
+            ConnectDecision::Proceed
+        );
+
+        let mut coalesce = DuplicateConnects::new(DuplicateConnectPolicy::Coalesce, 2_000);
+        assert_eq!(
+            coalesce.begin("[2001:db8::1]", 443, 0),
+            ConnectDecision::Proceed
+        );
+        assert_eq!(
+            coalesce.begin("2001:db8::1", 443, 10 * MS),
+            ConnectDecision::Wait
+        );
+        coalesce.finish("2001:db8::1", 443, false, 300 * MS);
+        // The waiting duplicate shares the failure instead of opening a stream.
+        assert_eq!(
+            coalesce.begin("2001:db8::1", 443, 350 * MS),
+            ConnectDecision::Refuse
+        );
+
+        assert_eq!(
+            coalesce.begin("example.com", 443, 0),
+            ConnectDecision::Proceed
+        );
+        assert_eq!(
+            coalesce.begin("example.com", 443, 10 * MS),
+            ConnectDecision::Wait
+        );
+        coalesce.finish("example.com", 443, true, 200 * MS);
+        assert_eq!(
+            coalesce.begin("example.com", 443, 250 * MS),
+            ConnectDecision::Proceed
+        );
+        // An attempt stuck past the window no longer holds others back.
+        assert_eq!(
+            coalesce.begin("example.com", 443, 2_300 * MS),
+            ConnectDecision::Proceed
+        );
+    }
+
+    #[test]
+    fn parses_policy_names() {
+        assert_eq!(
+            "Coalesce".parse::<DuplicateConnectPolicy>(),
+            Ok(DuplicateConnectPolicy::Coalesce)
+        );
+        assert_eq!("refuse".parse(), Ok(DuplicateConnectPolicy::Refuse));
+        assert!("drop".parse::<DuplicateConnectPolicy>().is_err());
+    }
+}
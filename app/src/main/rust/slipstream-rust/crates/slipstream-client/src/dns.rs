@@ -1,13 +1,49 @@
+mod capture;
+mod clock;
 mod debug;
+mod formerr;
+mod goodput;
+mod health;
+mod latency;
+mod loss;
+mod mode;
 mod path;
 mod poll;
+mod query_id;
+mod rate;
 mod resolver;
 mod response;
+mod route;
+mod spike;
+mod transport;
 
+// Read by the Android bridge and library callers; the CLI only records.
+#[allow(unused_imports)]
+pub use capture::export_flight_recorder;
+pub(crate) use capture::DnsRecorder;
+pub(crate) use clock::ClockOffsetEstimator;
 pub(crate) use debug::maybe_report_debug;
+pub(crate) use health::HealthPolicy;
+pub use health::{
+    ResolverHealthState, DEFAULT_RESOLVER_FAILURE_THRESHOLD, DEFAULT_RESOLVER_GRACE_PERIOD_MS,
+    DEFAULT_RESOLVER_RECOVERY_PROBE_MS, DEFAULT_RESOLVER_SERVFAIL_COOLDOWN_MS,
+    DEFAULT_RESOLVER_SERVFAIL_THRESHOLD,
+};
+pub use latency::{ResponseTimeHistogram, RESPONSE_TIME_BUCKETS_MS};
+pub(crate) use mode::mode_name;
 pub(crate) use path::{add_paths, refresh_resolver_path, resolver_mode_to_c};
-pub(crate) use poll::{expire_inflight_polls, send_poll_queries};
+pub(crate) use poll::{
+    expire_inflight_polls, poll_pool_deficit, send_poll_queries, MAX_POLL_BURST,
+};
+pub use poll::{DEFAULT_POLL_TIMEOUT_MS, MAX_POLL_POOL};
+pub(crate) use query_id::DnsIdGenerator;
+pub use rate::DEFAULT_QUERY_RAMP_DOUBLING_MS;
 pub(crate) use resolver::{
-    reset_resolver_path, resolve_resolvers, sockaddr_storage_to_socket_addr, ResolverState,
+    reset_resolver_path, resolve_added_resolvers, resolve_resolvers,
+    sockaddr_storage_to_socket_addr, ResolverState,
 };
 pub(crate) use response::{handle_dns_response, DnsResponseContext};
+pub(crate) use route::all_unreachable;
+#[cfg(test)]
+pub(crate) use route::RouteTracker;
+pub(crate) use transport::{recv_response, send_inspected_query, try_recv_response, UdpTransport};
@@ -0,0 +1,258 @@
+use super::clock::unix_time_us;
+use crate::error::ClientError;
+use slipstream_dns::{format_capture_record, CaptureDirection, CAPTURE_HEADER};
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{LineWriter, Write};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Ceiling on the flight recorder's retained wire bytes, whatever the record count.
+const FLIGHT_RECORDER_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// First line of a query log, naming its columns.
+const QUERY_LOG_HEADER: &str = "# slipstream query log: unix_us resolver dns_id qname";
+
+// Outlives the client so a capture can still be exported after a failed session.
+static FLIGHT_RECORDER: Mutex<Option<CaptureRing>> = Mutex::new(None);
+
+/// Appends raw DNS wire bytes to a capture file for offline replay, and keeps
+/// the most recent exchanges in memory when the flight recorder is enabled.
+///
+/// The optional query log holds one line per query sent, with the resolver,
+/// DNS ID and query name as they went on the wire, so it can be matched against
+/// an authoritative server's query log. It never holds decoded payloads.
+pub(crate) struct DnsRecorder {
+    writer: Option<LineWriter<File>>,
+    query_log: Option<LineWriter<File>>,
+    flight_recorder: bool,
+}
+
+impl DnsRecorder {
+    pub(crate) fn open(
+        path: Option<&str>,
+        flight_records: usize,
+        query_log: Option<&str>,
+    ) -> Result<Self, ClientError> {
+        let writer = match path {
+            Some(path) => {
+                info!("Recording DNS wire exchange to {}", path);
+                Some(open_log(path, "DNS capture", CAPTURE_HEADER)?)
+            }
+            None => None,
+        };
+        let query_log = match query_log {
+            Some(path) => {
+                info!("Logging DNS queries to {}", path);
+                Some(open_log(path, "query log", QUERY_LOG_HEADER)?)
+            }
+            None => None,
+        };
+        let flight_recorder = flight_records > 0;
+        if let Ok(mut ring) = FLIGHT_RECORDER.lock() {
+            *ring = flight_recorder.then(|| CaptureRing::new(flight_records));
+        }
+        Ok(Self {
+            writer,
+            query_log,
+            flight_recorder,
+        })
+    }
+
+    /// Adds a sent query to the query log, if one is open.
+    pub(crate) fn log_query(&mut self, resolver: SocketAddr, id: u16, qname: &str) {
+        let Some(writer) = self.query_log.as_mut() else {
+            return;
+        };
+        if let Err(err) = writeln!(
+            writer,
+            "{} {} 0x{:04x} {}",
+            unix_time_us(),
+            resolver,
+            id,
+            qname
+        ) {
+            warn!("Query log write failed; disabling it: {}", err);
+            self.query_log = None;
+        }
+    }
+
+    pub(crate) fn record_query(&mut self, packet: &[u8]) {
+        self.record(CaptureDirection::Query, packet);
+    }
+
+    pub(crate) fn record_response(&mut self, packet: &[u8]) {
+        self.record(CaptureDirection::Response, packet);
+    }
+
+    fn record(&mut self, direction: CaptureDirection, packet: &[u8]) {
+        if self.flight_recorder {
+            if let Ok(mut ring) = FLIGHT_RECORDER.lock() {
+                if let Some(ring) = ring.as_mut() {
+                    ring.push(unix_time_us(), direction, packet);
+                }
+            }
+        }
+        let Some(writer) = self.writer.as_mut() else {
+            return;
+        };
+        let line = format_capture_record(direction, packet);
+        if let Err(err) = writeln!(writer, "{}", line) {
+            warn!("DNS capture write failed; disabling recording: {}", err);
+            self.writer = None;
+        }
+    }
+}
+
+/// Opens `path` for appending and writes `header` if the file is new.
+fn open_log(path: &str, what: &str, header: &str) -> Result<LineWriter<File>, ClientError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| ClientError::new(format!("Failed to open {} {}: {}", what, path, err)))?;
+    let mut writer = LineWriter::new(file);
+    // Appending to an existing file keeps its header.
+    let is_new = writer.get_ref().metadata().map(|meta| meta.len() == 0);
+    if is_new.unwrap_or(false) {
+        writeln!(writer, "{}", header).map_err(|err| {
+            ClientError::new(format!("Failed to write {} {}: {}", what, path, err))
+        })?;
+    }
+    Ok(writer)
+}
+
+/// Renders the flight recorder in the capture file format, oldest record first.
+///
+/// Returns `None` when the recorder was never enabled.
+// Read by the Android bridge and library callers; the CLI only records.
+#[allow(dead_code)]
+pub fn export_flight_recorder() -> Option<String> {
+    FLIGHT_RECORDER
+        .lock()
+        .ok()
+        .and_then(|ring| ring.as_ref().map(CaptureRing::export))
+}
+
+struct CapturedPacket {
+    at_unix_us: u64,
+    direction: CaptureDirection,
+    packet: Vec<u8>,
+}
+
+/// Last `max_records` packets, also capped at `FLIGHT_RECORDER_MAX_BYTES`.
+struct CaptureRing {
+    records: VecDeque<CapturedPacket>,
+    max_records: usize,
+    bytes: usize,
+}
+
+impl CaptureRing {
+    fn new(max_records: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            max_records,
+            bytes: 0,
+        }
+    }
+
+    fn push(&mut self, at_unix_us: u64, direction: CaptureDirection, packet: &[u8]) {
+        self.records.push_back(CapturedPacket {
+            at_unix_us,
+            direction,
+            packet: packet.to_vec(),
+        });
+        self.bytes += packet.len();
+        while self.records.len() > self.max_records || self.bytes > FLIGHT_RECORDER_MAX_BYTES {
+            let Some(evicted) = self.records.pop_front() else {
+                break;
+            };
+            self.bytes -= evicted.packet.len();
+        }
+    }
+
+    /// Timestamps go in comment lines so the export replays like any capture file.
+    fn export(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", CAPTURE_HEADER);
+        let _ = writeln!(
+            out,
+            "# slipstream flight recorder: {} records",
+            self.records.len()
+        );
+        for record in &self.records {
+            let _ = writeln!(out, "# unix_us={}", record.at_unix_us);
+            let _ = writeln!(
+                out,
+                "{}",
+                format_capture_record(record.direction, &record.packet)
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slipstream_dns::parse_capture;
+
+    #[test]
+    fn keeps_only_the_latest_records() {
+        let mut ring = CaptureRing::new(2);
+        ring.push(1, CaptureDirection::Query, &[1]);
+        ring.push(2, CaptureDirection::Response, &[2, 2]);
+        ring.push(3, CaptureDirection::Query, &[3, 3, 3]);
+        assert_eq!(ring.records.len(), 2);
+        assert_eq!(ring.bytes, 5);
+
+        let export = ring.export();
+        assert!(export.contains("# unix_us=2\nR 0202\n# unix_us=3\nQ 030303\n"));
+        let records = parse_capture(&export).expect("export parses as a capture");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, CaptureDirection::Response);
+        assert_eq!(records[1].packet, vec![3, 3, 3]);
+    }
+
+    #[test]
+    fn byte_budget_evicts_old_records() {
+        let mut ring = CaptureRing::new(usize::MAX);
+        let packet = vec![0u8; FLIGHT_RECORDER_MAX_BYTES / 2];
+        for at in 0..3 {
+            ring.push(at, CaptureDirection::Response, &packet);
+        }
+        assert_eq!(ring.records.len(), 2);
+        assert!(ring.bytes <= FLIGHT_RECORDER_MAX_BYTES);
+        assert_eq!(ring.records[0].at_unix_us, 1);
+    }
+
+    #[test]
+    fn query_log_has_one_line_per_query() {
+        let path =
+            std::env::temp_dir().join(format!("slipstream-query-log-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path_str = path.to_str().unwrap();
+        let resolver: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        for id in [0x1a2b, 7] {
+            // Reopening appends without repeating the header.
+            let mut recorder = DnsRecorder::open(None, 0, Some(path_str)).unwrap();
+            recorder.record_query(&[0xde, 0xad]);
+            recorder.log_query(resolver, id, "aaaa.bbbb.t.example.com.");
+        }
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], QUERY_LOG_HEADER);
+        for (line, id) in lines[1..].iter().zip(["0x1a2b", "0x0007"]) {
+            let fields: Vec<&str> = line.split(' ').collect();
+            assert!(fields[0].parse::<u64>().unwrap() > 0);
+            assert_eq!(
+                &fields[1..],
+                ["192.0.2.1:53", id, "aaaa.bbbb.t.example.com."]
+            );
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Each window contributes its lowest-RTT sample, so the estimate follows drift
+// without being dragged around by queueing on any single round trip.
+const CLOCK_SAMPLE_WINDOW_US: u64 = 30_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ClockSample {
+    /// Server clock minus client clock.
+    pub(crate) offset_us: i64,
+    pub(crate) rtt_us: u64,
+}
+
+impl ClockSample {
+    /// NTP-style offset, assuming the server stamped the response halfway
+    /// through the round trip. The error is at most half the RTT.
+    fn new(server_time_ms: u64, received_at_unix_us: u64, rtt_us: u64) -> Self {
+        let midpoint_us = received_at_unix_us.saturating_sub(rtt_us / 2);
+        Self {
+            offset_us: (server_time_ms as i64)
+                .saturating_mul(1_000)
+                .saturating_sub(midpoint_us as i64),
+            rtt_us,
+        }
+    }
+
+    pub(crate) fn offset_ms(&self) -> i64 {
+        self.offset_us / 1_000
+    }
+
+    pub(crate) fn uncertainty_ms(&self) -> u64 {
+        self.rtt_us.div_ceil(2_000)
+    }
+}
+
+/// Estimates the client-server clock offset from time-stamped DNS responses.
+pub(crate) struct ClockOffsetEstimator {
+    previous_best: Option<ClockSample>,
+    window_best: Option<ClockSample>,
+    window_started_at: u64,
+}
+
+impl ClockOffsetEstimator {
+    pub(crate) fn new() -> Self {
+        Self {
+            previous_best: None,
+            window_best: None,
+            window_started_at: 0,
+        }
+    }
+
+    /// Adds one sample; `now` is the monotonic loop clock in microseconds.
+    pub(crate) fn record(
+        &mut self,
+        server_time_ms: u64,
+        received_at_unix_us: u64,
+        rtt_us: u64,
+        now: u64,
+    ) {
+        if now.saturating_sub(self.window_started_at) >= CLOCK_SAMPLE_WINDOW_US {
+            if self.window_best.is_some() {
+                self.previous_best = self.window_best.take();
+            }
+            self.window_started_at = now;
+        }
+        let sample = ClockSample::new(server_time_ms, received_at_unix_us, rtt_us);
+        if self
+            .window_best
+            .is_none_or(|best| sample.rtt_us <= best.rtt_us)
+        {
+            self.window_best = Some(sample);
+        }
+    }
+
+    /// Best sample of the last complete window, or of the first one while it is
+    /// still open.
+    pub(crate) fn estimate(&self) -> Option<ClockSample> {
+        self.previous_best.or(self.window_best)
+    }
+}
+
+pub(crate) fn unix_time_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_uses_the_round_trip_midpoint() {
+        let mut clock = ClockOffsetEstimator::new();
+        // Sent at 1_000.000 s local, answered 200 ms later; the server read
+        // 1_002.100 s at the midpoint, so it runs 2 s ahead.
+        clock.record(1_002_100, 1_000_200_000, 200_000, 1);
+        let estimate = clock.estimate().expect("estimate");
+        assert_eq!(estimate.offset_ms(), 2_000);
+        assert_eq!(estimate.uncertainty_ms(), 100);
+
+        clock.record(999_000, 1_000_000_000, 0, 2);
+        assert_eq!(clock.estimate().unwrap().offset_ms(), -1_000);
+    }
+
+    #[test]
+    fn keeps_the_lowest_rtt_sample_per_window() {
+        let mut clock = ClockOffsetEstimator::new();
+        clock.record(10_000, 10_000_000, 40_000, 1);
+        clock.record(10_500, 10_000_000, 400_000, 2);
+        assert_eq!(clock.estimate().unwrap().rtt_us, 40_000);
+
+        // A new window takes over once it closes, even with a slower best sample.
+        let next_window = 1 + CLOCK_SAMPLE_WINDOW_US;
+        clock.record(20_050, 20_000_000, 100_000, next_window);
+        assert_eq!(clock.estimate().unwrap().rtt_us, 40_000);
+        clock.record(
+            30_050,
+            30_000_000,
+            100_000,
+            next_window + CLOCK_SAMPLE_WINDOW_US,
+        );
+        let estimate = clock.estimate().unwrap();
+        assert_eq!(estimate.rtt_us, 100_000);
+        assert_eq!(estimate.offset_ms(), 100);
+    }
+}
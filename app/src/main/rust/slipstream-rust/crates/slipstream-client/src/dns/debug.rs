@@ -58,6 +58,7 @@ pub(crate) fn maybe_report_debug(
     pacing_snapshot: Option<PacingBudgetSnapshot>,
 ) {
     let label = resolver.label();
+    let response_counts = resolver.response_timer.histogram().counts;
     let debug = &mut resolver.debug;
     if !debug.enabled {
         return;
@@ -99,7 +100,7 @@ pub(crate) fn maybe_report_debug(
         String::new()
     };
     debug!(
-        "debug: {} dns+={} send_pkts+={} send_bytes+={} polls+={} zero_send+={} zero_send_streams+={} streams={} enqueued+={} last_enqueue_ms={} pending_polls={} inflight_polls={}{}",
+        "debug: {} dns+={} send_pkts+={} send_bytes+={} polls+={} zero_send+={} zero_send_streams+={} streams={} enqueued+={} last_enqueue_ms={} pending_polls={} inflight_polls={} response_time_buckets={:?}{}",
         label,
         dns_delta,
         send_pkt_delta,
@@ -112,6 +113,7 @@ pub(crate) fn maybe_report_debug(
         enqueue_ms,
         pending_polls,
         inflight_polls,
+        response_counts,
         pacing_summary
     );
     debug.last_report_at = now;
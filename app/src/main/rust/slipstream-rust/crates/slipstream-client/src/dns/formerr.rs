@@ -0,0 +1,249 @@
+use super::resolver::ResolverState;
+use crate::events::TunnelEvent;
+use crate::redact::redact;
+use slipstream_dns::{Rcode, CLASS_CH, CLASS_IN};
+use slipstream_ffi::FormerrPolicy;
+use tracing::warn;
+
+// FORMERR answers in a row, with no other answer between, before the query
+// form counts as rejected.
+const FORMERR_RUN: u32 = 3;
+
+/// FORMERR answers a resolver gave to the query form in use.
+#[derive(Debug, Default)]
+pub(crate) struct FormerrTracker {
+    run: u32,
+    /// Queries go out in class IN, whatever `--query-class` says.
+    class_in: bool,
+    /// Nothing more is tried: no plainer form is left, or the policy keeps
+    /// the queries as they are.
+    gave_up: bool,
+}
+
+impl FormerrTracker {
+    /// Class to send queries to this resolver in.
+    pub(crate) fn query_class(&self, configured: u16) -> u16 {
+        if self.class_in {
+            CLASS_IN
+        } else {
+            configured
+        }
+    }
+
+    pub(crate) fn gave_up(&self) -> bool {
+        self.gave_up
+    }
+}
+
+/// Watches a resolver's answers for a run of FORMERR.
+///
+/// The tunnel's queries are well formed, so a resolver that keeps answering
+/// them with FORMERR objects to something about their form: an EDNS option it
+/// does not understand, or a class other than IN. Under
+/// [`FormerrPolicy::Adapt`] each run moves the resolver to the next plainer
+/// form, first without the DNS Cookie and then in class IN; the QTYPE stays
+/// TXT, the only one the tunnel server answers. Returns the event for a run
+/// that was acted on.
+pub(crate) fn check_formerr(
+    resolver: &mut ResolverState,
+    rcode: Option<Rcode>,
+    query_class: u16,
+    policy: FormerrPolicy,
+) -> Option<TunnelEvent> {
+    if rcode != Some(Rcode::FormatError) {
+        resolver.formerr.run = 0;
+        return None;
+    }
+    if resolver.formerr.gave_up {
+        return None;
+    }
+    resolver.formerr.run += 1;
+    if resolver.formerr.run < FORMERR_RUN {
+        return None;
+    }
+    resolver.formerr.run = 0;
+    let rejected = query_form(resolver, query_class);
+    let stepped = policy == FormerrPolicy::Adapt && step_to_plainer_form(resolver, query_class);
+    let fallback = stepped.then(|| query_form(resolver, query_class));
+    match &fallback {
+        Some(fallback) => warn!(
+            "Resolver {} answers FORMERR to tunnel queries as {}; retrying as {}",
+            redact(resolver.addr),
+            rejected,
+            fallback
+        ),
+        None => {
+            resolver.formerr.gave_up = true;
+            warn!(
+                "Resolver {} answers FORMERR to tunnel queries as {}; {}",
+                redact(resolver.addr),
+                rejected,
+                if policy == FormerrPolicy::Adapt {
+                    "no plainer form is left, counting its answers as failures"
+                } else {
+                    "check --query-class and --edns-cookies for this path"
+                }
+            );
+        }
+    }
+    Some(TunnelEvent::ResolverRejectsEncoding {
+        resolver: redact(resolver.addr).to_string(),
+        rejected,
+        fallback,
+    })
+}
+
+fn step_to_plainer_form(resolver: &mut ResolverState, query_class: u16) -> bool {
+    if resolver.cookie.take().is_some() {
+        return true;
+    }
+    if resolver.formerr.query_class(query_class) != CLASS_IN {
+        resolver.formerr.class_in = true;
+        return true;
+    }
+    false
+}
+
+/// The query form sent to `resolver`, e.g. `TXT/CH with cookie`.
+fn query_form(resolver: &ResolverState, query_class: u16) -> String {
+    let class = match resolver.formerr.query_class(query_class) {
+        CLASS_IN => "IN".to_string(),
+        CLASS_CH => "CH".to_string(),
+        class => format!("CLASS{}", class),
+    };
+    let cookie = if resolver.cookie.is_some() {
+        " with cookie"
+    } else {
+        ""
+    };
+    format!("TXT/{}{}", class, cookie)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{resolve_resolvers, HealthPolicy};
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_dns::{build_qname, decode_query, QueryParams, RR_TXT};
+    use slipstream_ffi::{ResolverMode, ResolverSpec};
+
+    fn resolver() -> ResolverState {
+        let spec = ResolverSpec {
+            resolver: HostPort {
+                host: "192.0.2.1".to_string(),
+                port: 53,
+                family: AddressFamily::V4,
+            },
+            mode: ResolverMode::Recursive,
+        };
+        let mut resolver = resolve_resolvers(&[spec], 900, false, HealthPolicy::default())
+            .unwrap()
+            .remove(0);
+        resolver.enable_cookie();
+        resolver
+    }
+
+    /// A resolver that takes any EDNS option but rejects class CH.
+    fn rejects_chaos(packet: &[u8]) -> Rcode {
+        let query = decode_query(packet, "example.com").expect("well-formed query");
+        if query.question.qclass == CLASS_CH {
+            Rcode::FormatError
+        } else {
+            Rcode::Ok
+        }
+    }
+
+    /// Sends `count` CH queries to `resolver` and feeds back the answers of
+    /// `server`; returns the events and the last answer.
+    fn exchange(
+        resolver: &mut ResolverState,
+        count: usize,
+        policy: FormerrPolicy,
+        server: fn(&[u8]) -> Rcode,
+    ) -> (Vec<TunnelEvent>, Rcode) {
+        let qname = build_qname(b"tunnel data", "example.com").unwrap();
+        let mut events = Vec::new();
+        let mut rcode = Rcode::Ok;
+        for id in 0..count {
+            let packet = resolver
+                .encode_query(&QueryParams {
+                    id: id as u16,
+                    qname: &qname,
+                    qtype: RR_TXT,
+                    qclass: CLASS_CH,
+                    rd: true,
+                    cd: false,
+                    qdcount: 1,
+                    is_query: true,
+                })
+                .unwrap();
+            rcode = server(&packet);
+            events.extend(check_formerr(resolver, Some(rcode), CLASS_CH, policy));
+        }
+        (events, rcode)
+    }
+
+    fn fallbacks(events: &[TunnelEvent]) -> Vec<(String, Option<String>)> {
+        events
+            .iter()
+            .map(|event| match event {
+                TunnelEvent::ResolverRejectsEncoding {
+                    rejected, fallback, ..
+                } => (rejected.clone(), fallback.clone()),
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_resolver_rejecting_one_class_gets_queries_in_another() {
+        let mut adapting = resolver();
+        let (events, rcode) = exchange(&mut adapting, 10, FormerrPolicy::Adapt, rejects_chaos);
+        assert_eq!(
+            fallbacks(&events),
+            [
+                ("TXT/CH with cookie".to_string(), Some("TXT/CH".to_string())),
+                ("TXT/CH".to_string(), Some("TXT/IN".to_string())),
+            ]
+        );
+        assert_eq!(rcode, Rcode::Ok);
+        assert!(!adapting.formerr.gave_up());
+        assert_eq!(adapting.formerr.query_class(CLASS_CH), CLASS_IN);
+
+        let mut reporting = resolver();
+        let (events, rcode) = exchange(&mut reporting, 10, FormerrPolicy::Report, rejects_chaos);
+        assert_eq!(
+            fallbacks(&events),
+            [("TXT/CH with cookie".to_string(), None)]
+        );
+        assert_eq!(rcode, Rcode::FormatError);
+        assert!(reporting.formerr.gave_up());
+        assert!(reporting.cookie.is_some());
+    }
+
+    #[test]
+    fn formerr_on_every_form_gives_up_once() {
+        let mut resolver = resolver();
+        let (events, _) = exchange(&mut resolver, 20, FormerrPolicy::Adapt, |_| {
+            Rcode::FormatError
+        });
+        let steps = fallbacks(&events);
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[2], ("TXT/IN".to_string(), None));
+        assert!(resolver.formerr.gave_up());
+    }
+
+    #[test]
+    fn other_answers_break_the_run() {
+        let mut resolver = resolver();
+        for _ in 0..10 {
+            for rcode in [Rcode::FormatError, Rcode::FormatError, Rcode::NameError] {
+                let event =
+                    check_formerr(&mut resolver, Some(rcode), CLASS_IN, FormerrPolicy::Adapt);
+                assert!(event.is_none());
+            }
+        }
+        assert!(resolver.cookie.is_some());
+        assert!(!resolver.formerr.gave_up());
+    }
+}
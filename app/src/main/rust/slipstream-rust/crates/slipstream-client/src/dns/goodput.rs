@@ -0,0 +1,81 @@
+// Tunnel bytes are summed over windows of this length before they become a sample.
+const GOODPUT_WINDOW_US: u64 = 1_000_000;
+// Windows that carried less than this, or that only closed after an idle gap,
+// say nothing about capacity and leave the estimate alone.
+const GOODPUT_MIN_WINDOW_BYTES: u64 = 4 * 1024;
+const GOODPUT_MAX_WINDOW_US: u64 = 2 * GOODPUT_WINDOW_US;
+// Weight of the newest sample in the moving average, as 1/N.
+const GOODPUT_SMOOTHING: u64 = 4;
+
+/// Estimates the QUIC bytes per second a resolver delivers downstream.
+///
+/// A response's payload is counted in the window it arrived in. Each busy
+/// window becomes a rate sample that is folded into a moving average, so the
+/// estimate follows what the resolver carries while data is flowing and does
+/// not decay while the tunnel is idle.
+pub(crate) struct GoodputEstimator {
+    window_start_us: Option<u64>,
+    window_bytes: u64,
+    estimate: Option<u64>,
+}
+
+impl GoodputEstimator {
+    pub(crate) fn new() -> Self {
+        Self {
+            window_start_us: None,
+            window_bytes: 0,
+            estimate: None,
+        }
+    }
+
+    /// Smoothed rate in bytes per second; `None` before the first busy window.
+    pub(crate) fn estimate(&self) -> Option<u64> {
+        self.estimate
+    }
+
+    pub(crate) fn record(&mut self, payload_bytes: u64, now_us: u64) {
+        let start = *self.window_start_us.get_or_insert(now_us);
+        let elapsed = now_us.saturating_sub(start);
+        if elapsed >= GOODPUT_WINDOW_US {
+            if self.window_bytes >= GOODPUT_MIN_WINDOW_BYTES && elapsed <= GOODPUT_MAX_WINDOW_US {
+                let sample = self.window_bytes.saturating_mul(1_000_000) / elapsed;
+                self.estimate = Some(match self.estimate {
+                    Some(estimate) => {
+                        (estimate * (GOODPUT_SMOOTHING - 1) + sample) / GOODPUT_SMOOTHING
+                    }
+                    None => sample,
+                });
+            }
+            self.window_start_us = Some(now_us);
+            self.window_bytes = 0;
+        }
+        self.window_bytes = self.window_bytes.saturating_add(payload_bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busy_windows_set_the_rate_and_idle_ones_keep_it() {
+        let mut goodput = GoodputEstimator::new();
+        // 10 KB per 100 ms: 100 KB/s.
+        for step in 0..=10 {
+            goodput.record(10_000, step * 100_000);
+        }
+        assert_eq!(goodput.estimate(), Some(100_000));
+
+        // Neither a window cut by an idle gap nor a trickle measures capacity.
+        for step in 0..=10 {
+            goodput.record(100, 5_000_000 + step * 100_000);
+        }
+        assert_eq!(goodput.estimate(), Some(100_000));
+
+        // A faster window moves the average a quarter of the way.
+        for step in 0..=10 {
+            goodput.record(30_000, 7_000_000 + step * 100_000);
+        }
+        assert_eq!(goodput.estimate(), Some(150_000));
+    }
+}
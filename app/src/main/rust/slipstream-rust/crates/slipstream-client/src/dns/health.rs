@@ -0,0 +1,424 @@
+use crate::redact::redact;
+use serde::Serialize;
+use std::net::SocketAddr;
+use tracing::{info, warn};
+
+pub const DEFAULT_RESOLVER_FAILURE_THRESHOLD: u32 = 3;
+pub const DEFAULT_RESOLVER_GRACE_PERIOD_MS: u64 = 5_000;
+pub const DEFAULT_RESOLVER_RECOVERY_PROBE_MS: u64 = 10_000;
+pub const DEFAULT_RESOLVER_SERVFAIL_THRESHOLD: u32 = 8;
+pub const DEFAULT_RESOLVER_SERVFAIL_COOLDOWN_MS: u64 = 30_000;
+
+// Each window of unanswered queries counts as one failure.
+const RESOLVER_SILENCE_WINDOW_US: u64 = 1_000_000;
+// Queries a resolver may take without answering before it is reported as asymmetric.
+const ASYMMETRY_UNANSWERED_QUERIES: u64 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolverHealthState {
+    Healthy,
+    Probation,
+    Dead,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HealthPolicy {
+    pub(crate) failure_threshold: u32,
+    pub(crate) grace_period_us: u64,
+    pub(crate) recovery_probe_interval_us: u64,
+    pub(crate) servfail_threshold: u32,
+    pub(crate) servfail_cooldown_us: u64,
+}
+
+impl HealthPolicy {
+    pub(crate) fn new(
+        failure_threshold: u32,
+        grace_period_ms: u64,
+        recovery_probe_ms: u64,
+        servfail_threshold: u32,
+        servfail_cooldown_ms: u64,
+    ) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            grace_period_us: grace_period_ms.saturating_mul(1_000),
+            recovery_probe_interval_us: recovery_probe_ms.max(1).saturating_mul(1_000),
+            servfail_threshold,
+            servfail_cooldown_us: servfail_cooldown_ms.max(1).saturating_mul(1_000),
+        }
+    }
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_RESOLVER_FAILURE_THRESHOLD,
+            DEFAULT_RESOLVER_GRACE_PERIOD_MS,
+            DEFAULT_RESOLVER_RECOVERY_PROBE_MS,
+            DEFAULT_RESOLVER_SERVFAIL_THRESHOLD,
+            DEFAULT_RESOLVER_SERVFAIL_COOLDOWN_MS,
+        )
+    }
+}
+
+/// Tracks whether a resolver is answering, with hysteresis before it is dropped.
+///
+/// A failure moves a healthy resolver into probation. It is only marked dead after
+/// both `failure_threshold` consecutive failures and `grace_period` in probation, so
+/// a brief glitch does not take a usable resolver out of rotation. Any response
+/// restores it to healthy.
+///
+/// SERVFAIL answers are the exception: a run of `servfail_threshold` of them
+/// means the resolver cannot reach the tunnel server right now, so it is marked
+/// dead at once and left alone for `servfail_cooldown` before the next probe.
+pub(crate) struct ResolverHealth {
+    policy: HealthPolicy,
+    state: ResolverHealthState,
+    consecutive_failures: u32,
+    probation_since: u64,
+    unanswered_since: Option<u64>,
+    next_recovery_probe_at: u64,
+    unanswered_queries: u64,
+    answered: bool,
+    asymmetric: bool,
+    consecutive_servfails: u32,
+    servfail_storms: u64,
+}
+
+impl ResolverHealth {
+    pub(crate) fn new(policy: HealthPolicy) -> Self {
+        Self {
+            policy,
+            state: ResolverHealthState::Healthy,
+            consecutive_failures: 0,
+            probation_since: 0,
+            unanswered_since: None,
+            next_recovery_probe_at: 0,
+            unanswered_queries: 0,
+            answered: false,
+            asymmetric: false,
+            consecutive_servfails: 0,
+            servfail_storms: 0,
+        }
+    }
+
+    pub(crate) fn state(&self) -> ResolverHealthState {
+        self.state
+    }
+
+    pub(crate) fn is_dead(&self) -> bool {
+        self.state == ResolverHealthState::Dead
+    }
+
+    /// Starts out dead, as the resolver was when this network was last used;
+    /// recovery probes bring it back if it answers again.
+    pub(crate) fn seed_dead(&mut self, now: u64) {
+        self.state = ResolverHealthState::Dead;
+        self.next_recovery_probe_at = now.saturating_add(self.policy.recovery_probe_interval_us);
+    }
+
+    pub(crate) fn is_asymmetric(&self) -> bool {
+        self.asymmetric
+    }
+
+    /// SERVFAIL runs that paused this resolver.
+    pub(crate) fn servfail_storms(&self) -> u64 {
+        self.servfail_storms
+    }
+
+    /// Whether this resolver is currently returning responses.
+    pub(crate) fn is_answering(&self) -> bool {
+        self.answered && self.unanswered_queries < ASYMMETRY_UNANSWERED_QUERIES
+    }
+
+    pub(crate) fn record_send(&mut self, now: u64) {
+        self.unanswered_queries = self.unanswered_queries.saturating_add(1);
+        if self.unanswered_since.is_none() {
+            self.unanswered_since = Some(now);
+        }
+    }
+
+    pub(crate) fn record_response(&mut self, addr: SocketAddr) {
+        self.unanswered_since = None;
+        self.consecutive_failures = 0;
+        self.unanswered_queries = 0;
+        self.answered = true;
+        self.consecutive_servfails = 0;
+        if self.asymmetric {
+            info!("Resolver {} is answering again", redact(addr));
+            self.asymmetric = false;
+        }
+        if self.state != ResolverHealthState::Healthy {
+            info!(
+                "Resolver {} recovered ({:?} -> healthy)",
+                redact(addr),
+                self.state
+            );
+            self.state = ResolverHealthState::Healthy;
+        }
+    }
+
+    /// Counts a SERVFAIL answer; a full run pauses the resolver for the cooldown.
+    pub(crate) fn record_servfail(&mut self, addr: SocketAddr, now: u64) {
+        if self.policy.servfail_threshold == 0 {
+            self.record_response(addr);
+            return;
+        }
+        // The resolver is reachable, so this is not silence.
+        self.unanswered_since = None;
+        self.unanswered_queries = 0;
+        self.answered = true;
+        if self.is_dead() {
+            // A recovery probe that still fails waits out another cooldown.
+            self.next_recovery_probe_at = now.saturating_add(self.policy.servfail_cooldown_us);
+            return;
+        }
+        self.consecutive_servfails = self.consecutive_servfails.saturating_add(1);
+        if self.consecutive_servfails < self.policy.servfail_threshold {
+            return;
+        }
+        self.consecutive_servfails = 0;
+        self.servfail_storms = self.servfail_storms.saturating_add(1);
+        self.state = ResolverHealthState::Dead;
+        self.next_recovery_probe_at = now.saturating_add(self.policy.servfail_cooldown_us);
+        warn!(
+            "Resolver {} returned {} SERVFAILs in a row; pausing it for {}ms",
+            redact(addr),
+            self.policy.servfail_threshold,
+            self.policy.servfail_cooldown_us / 1_000
+        );
+    }
+
+    pub(crate) fn record_failure(&mut self, addr: SocketAddr, now: u64) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+        match self.state {
+            ResolverHealthState::Healthy => {
+                self.state = ResolverHealthState::Probation;
+                self.probation_since = now;
+                warn!("Resolver {} entered probation", redact(addr));
+            }
+            ResolverHealthState::Probation => {}
+            ResolverHealthState::Dead => return,
+        }
+        if self.consecutive_failures >= self.policy.failure_threshold
+            && now.saturating_sub(self.probation_since) >= self.policy.grace_period_us
+        {
+            self.state = ResolverHealthState::Dead;
+            self.next_recovery_probe_at =
+                now.saturating_add(self.policy.recovery_probe_interval_us);
+            warn!(
+                "Resolver {} marked dead after {} consecutive failures",
+                redact(addr),
+                self.consecutive_failures
+            );
+        }
+    }
+
+    /// Counts unanswered query windows as failures.
+    pub(crate) fn tick(&mut self, addr: SocketAddr, now: u64) {
+        let Some(since) = self.unanswered_since else {
+            return;
+        };
+        if now.saturating_sub(since) >= RESOLVER_SILENCE_WINDOW_US {
+            self.unanswered_since = Some(now);
+            self.record_failure(addr, now);
+        }
+    }
+
+    /// Flags a resolver that keeps accepting queries without answering any while
+    /// another resolver answers the same traffic.
+    ///
+    /// Sends succeed, so the resolver is reachable; with the tunnel server alive on
+    /// the other path, the likely cause is responses routed elsewhere, as happens
+    /// with anycast resolvers whose instances differ by direction.
+    pub(crate) fn check_asymmetry(&mut self, addr: SocketAddr, others_answering: bool) {
+        if self.asymmetric
+            || !others_answering
+            || self.unanswered_queries < ASYMMETRY_UNANSWERED_QUERIES
+        {
+            return;
+        }
+        self.asymmetric = true;
+        warn!(
+            "Resolver {} accepted {} queries without answering while other resolvers respond; \
+             its responses may be taking a different route (anycast asymmetry)",
+            redact(addr),
+            self.unanswered_queries
+        );
+    }
+
+    /// Dead resolvers are skipped except for one recovery probe per interval.
+    /// This only holds back polls; `apply_resolver_roles` moves a dead path's
+    /// stream data to the others.
+    pub(crate) fn allow_poll(&mut self, now: u64) -> bool {
+        if !self.is_dead() {
+            return true;
+        }
+        if now < self.next_recovery_probe_at {
+            return false;
+        }
+        self.next_recovery_probe_at = now.saturating_add(self.policy.recovery_probe_interval_us);
+        true
+    }
+
+    pub(crate) fn probe_delay(&self, backoff_us: u64) -> u64 {
+        if self.is_dead() {
+            backoff_us.max(self.policy.recovery_probe_interval_us)
+        } else {
+            backoff_us
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:53".parse().expect("addr")
+    }
+
+    fn policy() -> HealthPolicy {
+        HealthPolicy::new(3, 2_000, 10_000, 4, 30_000)
+    }
+
+    #[test]
+    fn single_failure_enters_probation_only() {
+        let mut health = ResolverHealth::new(policy());
+        health.record_failure(addr(), 1_000_000);
+        assert_eq!(health.state(), ResolverHealthState::Probation);
+        assert!(health.allow_poll(1_000_000));
+    }
+
+    #[test]
+    fn dead_requires_threshold_and_grace_period() {
+        let mut health = ResolverHealth::new(policy());
+        health.record_failure(addr(), 0);
+        health.record_failure(addr(), 100_000);
+        health.record_failure(addr(), 200_000);
+        assert_eq!(
+            health.state(),
+            ResolverHealthState::Probation,
+            "threshold reached but still within the grace period"
+        );
+        health.record_failure(addr(), 2_000_000);
+        assert_eq!(health.state(), ResolverHealthState::Dead);
+    }
+
+    #[test]
+    fn grace_period_alone_does_not_kill() {
+        let mut health = ResolverHealth::new(policy());
+        health.record_failure(addr(), 0);
+        health.record_failure(addr(), 5_000_000);
+        assert_eq!(health.state(), ResolverHealthState::Probation);
+    }
+
+    #[test]
+    fn response_recovers_from_probation_and_dead() {
+        let mut health = ResolverHealth::new(policy());
+        health.record_failure(addr(), 0);
+        health.record_response(addr());
+        assert_eq!(health.state(), ResolverHealthState::Healthy);
+
+        for step in 0..4 {
+            health.record_failure(addr(), step * 1_000_000);
+        }
+        assert_eq!(health.state(), ResolverHealthState::Dead);
+        health.record_response(addr());
+        assert_eq!(health.state(), ResolverHealthState::Healthy);
+    }
+
+    #[test]
+    fn silence_counts_as_failures() {
+        let mut health = ResolverHealth::new(policy());
+        health.record_send(0);
+        health.tick(addr(), 500_000);
+        assert_eq!(health.state(), ResolverHealthState::Healthy);
+        for now in [1_000_000, 2_000_000, 3_000_000] {
+            health.tick(addr(), now);
+        }
+        assert_eq!(health.state(), ResolverHealthState::Dead);
+    }
+
+    #[test]
+    fn flags_asymmetry_only_while_others_answer() {
+        let mut health = ResolverHealth::new(policy());
+        for now in 0..ASYMMETRY_UNANSWERED_QUERIES {
+            health.record_send(now);
+        }
+        health.check_asymmetry(addr(), false);
+        assert!(!health.is_asymmetric(), "no other path to compare against");
+        health.check_asymmetry(addr(), true);
+        assert!(health.is_asymmetric());
+        assert!(!health.is_answering());
+        health.record_response(addr());
+        assert!(!health.is_asymmetric());
+        assert!(health.is_answering());
+    }
+
+    #[test]
+    fn few_unanswered_queries_are_not_asymmetry() {
+        let mut health = ResolverHealth::new(policy());
+        health.record_response(addr());
+        for now in 1..ASYMMETRY_UNANSWERED_QUERIES {
+            health.record_send(now);
+        }
+        health.check_asymmetry(addr(), true);
+        assert!(!health.is_asymmetric());
+        assert!(health.is_answering());
+    }
+
+    #[test]
+    fn servfail_burst_pauses_resolver_for_cooldown() {
+        let mut health = ResolverHealth::new(policy());
+        health.record_servfail(addr(), 0);
+        health.record_servfail(addr(), 1);
+        health.record_response(addr());
+        for now in 2..5 {
+            health.record_servfail(addr(), now);
+        }
+        assert_eq!(
+            health.state(),
+            ResolverHealthState::Healthy,
+            "run was broken"
+        );
+
+        health.record_servfail(addr(), 5);
+        assert!(health.is_dead());
+        assert_eq!(health.servfail_storms(), 1);
+        assert!(!health.allow_poll(29_999_999));
+        assert!(health.allow_poll(30_000_005));
+
+        // The probe is answered with SERVFAIL again: another full cooldown.
+        health.record_servfail(addr(), 30_100_000);
+        assert!(!health.allow_poll(60_000_000));
+        assert!(health.allow_poll(60_100_000));
+        health.record_response(addr());
+        assert_eq!(health.state(), ResolverHealthState::Healthy);
+    }
+
+    #[test]
+    fn servfail_handling_can_be_disabled() {
+        let mut health = ResolverHealth::new(HealthPolicy::new(3, 2_000, 10_000, 0, 30_000));
+        health.record_failure(addr(), 0);
+        for now in 1..100 {
+            health.record_servfail(addr(), now);
+        }
+        assert_eq!(health.state(), ResolverHealthState::Healthy);
+        assert_eq!(health.servfail_storms(), 0);
+    }
+
+    #[test]
+    fn dead_resolver_gets_periodic_recovery_probe() {
+        let mut health = ResolverHealth::new(policy());
+        for step in 0..4 {
+            health.record_failure(addr(), step * 1_000_000);
+        }
+        assert!(health.is_dead());
+        let dead_at = 3_000_000;
+        assert!(!health.allow_poll(dead_at + 1));
+        assert!(health.allow_poll(dead_at + 10_000_000));
+        assert!(!health.allow_poll(dead_at + 10_000_001));
+        assert_eq!(health.probe_delay(250_000), 10_000_000);
+    }
+}
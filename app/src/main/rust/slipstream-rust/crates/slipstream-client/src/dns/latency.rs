@@ -0,0 +1,115 @@
+use serde::Serialize;
+
+/// Upper bounds of the response time buckets; a final overflow bucket catches the rest.
+pub const RESPONSE_TIME_BUCKETS_MS: [u64; 9] = [25, 50, 100, 200, 400, 800, 1_600, 3_200, 6_400];
+
+// Queries older than this are forgotten when the slot is reused.
+const TRACKED_QUERIES: usize = 64;
+
+/// Fixed-bucket histogram of DNS response times for one resolver.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ResponseTimeHistogram {
+    /// Per-bucket counts matching `RESPONSE_TIME_BUCKETS_MS`, plus one overflow bucket.
+    pub counts: [u64; RESPONSE_TIME_BUCKETS_MS.len() + 1],
+    pub count: u64,
+    pub sum_us: u64,
+}
+
+impl ResponseTimeHistogram {
+    pub(crate) fn record(&mut self, elapsed_us: u64) {
+        let elapsed_ms = elapsed_us / 1_000;
+        let bucket = RESPONSE_TIME_BUCKETS_MS
+            .iter()
+            .position(|bound| elapsed_ms < *bound)
+            .unwrap_or(RESPONSE_TIME_BUCKETS_MS.len());
+        self.counts[bucket] = self.counts[bucket].saturating_add(1);
+        self.count = self.count.saturating_add(1);
+        self.sum_us = self.sum_us.saturating_add(elapsed_us);
+    }
+}
+
+/// Matches responses to recent queries by DNS ID to time each round trip.
+///
+/// Only the last `TRACKED_QUERIES` sends are remembered, which keeps the cost to a
+/// small fixed array; queries that fall out of the window are simply not timed.
+pub(crate) struct ResponseTimer {
+    sent: [(u16, u64); TRACKED_QUERIES],
+    next_slot: usize,
+    histogram: ResponseTimeHistogram,
+}
+
+impl ResponseTimer {
+    pub(crate) fn new() -> Self {
+        Self {
+            sent: [(0, 0); TRACKED_QUERIES],
+            next_slot: 0,
+            histogram: ResponseTimeHistogram::default(),
+        }
+    }
+
+    pub(crate) fn histogram(&self) -> &ResponseTimeHistogram {
+        &self.histogram
+    }
+
+    pub(crate) fn record_query(&mut self, id: u16, now: u64) {
+        self.sent[self.next_slot] = (id, now);
+        self.next_slot = (self.next_slot + 1) % TRACKED_QUERIES;
+    }
+
+    /// Returns the round-trip time when the response matches a tracked query.
+    pub(crate) fn record_response(&mut self, id: u16, now: u64) -> Option<u64> {
+        // A zero send time marks an empty or already answered slot.
+        let slot = self
+            .sent
+            .iter_mut()
+            .find(|(sent_id, sent_at)| *sent_id == id && *sent_at != 0)?;
+        let elapsed = now.saturating_sub(slot.1);
+        self.histogram.record(elapsed);
+        slot.1 = 0;
+        Some(elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_response_times() {
+        let mut timer = ResponseTimer::new();
+        timer.record_query(1, 1_000);
+        timer.record_query(2, 1_000);
+        timer.record_query(3, 1_000);
+        timer.record_response(1, 11_000);
+        timer.record_response(2, 301_000);
+        timer.record_response(3, 10_001_000);
+        let histogram = timer.histogram();
+        assert_eq!(histogram.counts[0], 1);
+        assert_eq!(histogram.counts[4], 1);
+        assert_eq!(histogram.counts[RESPONSE_TIME_BUCKETS_MS.len()], 1);
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.sum_us, 10_000 + 300_000 + 10_000_000);
+    }
+
+    #[test]
+    fn ignores_unknown_and_duplicate_responses() {
+        let mut timer = ResponseTimer::new();
+        timer.record_query(7, 1_000);
+        timer.record_response(8, 2_000);
+        timer.record_response(7, 2_000);
+        timer.record_response(7, 3_000);
+        assert_eq!(timer.histogram().count, 1);
+    }
+
+    #[test]
+    fn forgets_queries_outside_the_window() {
+        let mut timer = ResponseTimer::new();
+        for id in 0..=TRACKED_QUERIES as u16 {
+            timer.record_query(id, 1_000);
+        }
+        timer.record_response(0, 2_000);
+        assert_eq!(timer.histogram().count, 0);
+        timer.record_response(TRACKED_QUERIES as u16, 2_000);
+        assert_eq!(timer.histogram().count, 1);
+    }
+}
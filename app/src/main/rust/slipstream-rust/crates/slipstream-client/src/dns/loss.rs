@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+
+// Unanswered queries remembered at once; the oldest are forgotten, not counted.
+const TRACKED_QUERIES: usize = 256;
+// Settled queries the estimate covers, so it follows the current conditions.
+const LOSS_WINDOW: usize = 256;
+// Fewer settled queries than this give no estimate yet.
+const LOSS_MIN_SAMPLES: usize = 20;
+
+/// Estimates the share of DNS queries to one resolver that go unanswered.
+///
+/// A query counts as lost once it has gone unanswered past the response
+/// deadline, and as delivered when any response comes back in time, SERVFAIL
+/// included: an error answer still made the round trip. A lost query that is
+/// answered late after all was only slow, so its loss is taken back. The rate
+/// covers the last `LOSS_WINDOW` settled queries.
+pub(crate) struct LossEstimator {
+    /// Unanswered queries in send order.
+    inflight: VecDeque<(u16, u64)>,
+    /// Settled queries, oldest first, and whether each was lost.
+    outcomes: VecDeque<(u16, bool)>,
+    lost: usize,
+}
+
+impl LossEstimator {
+    pub(crate) fn new() -> Self {
+        Self {
+            inflight: VecDeque::new(),
+            outcomes: VecDeque::new(),
+            lost: 0,
+        }
+    }
+
+    pub(crate) fn record_query(&mut self, id: u16, now: u64) {
+        if self.inflight.len() == TRACKED_QUERIES {
+            self.inflight.pop_front();
+        }
+        self.inflight.push_back((id, now));
+    }
+
+    pub(crate) fn record_response(&mut self, id: u16) {
+        if let Some(index) = self.inflight.iter().position(|(sent, _)| *sent == id) {
+            self.inflight.remove(index);
+            self.settle(id, false);
+            return;
+        }
+        // A late answer to a query already counted as lost.
+        if let Some(outcome) = self
+            .outcomes
+            .iter_mut()
+            .rev()
+            .find(|(settled, lost)| *settled == id && *lost)
+        {
+            outcome.1 = false;
+            self.lost -= 1;
+        }
+    }
+
+    /// Counts queries unanswered for `deadline_us` as lost.
+    pub(crate) fn expire(&mut self, now: u64, deadline_us: u64) {
+        while let Some(&(id, sent_at)) = self.inflight.front() {
+            if now.saturating_sub(sent_at) < deadline_us {
+                break;
+            }
+            self.inflight.pop_front();
+            self.settle(id, true);
+        }
+    }
+
+    /// Lost share of recent queries, from 0 to 1; `None` until enough settled.
+    pub(crate) fn loss_rate(&self) -> Option<f64> {
+        (self.outcomes.len() >= LOSS_MIN_SAMPLES)
+            .then(|| self.lost as f64 / self.outcomes.len() as f64)
+    }
+
+    fn settle(&mut self, id: u16, lost: bool) {
+        if self.outcomes.len() == LOSS_WINDOW {
+            if let Some((_, true)) = self.outcomes.pop_front() {
+                self.lost -= 1;
+            }
+        }
+        self.outcomes.push_back((id, lost));
+        self.lost += usize::from(lost);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEADLINE_US: u64 = 1_000_000;
+
+    /// A resolver that drops every `drop_every`-th query and answers the rest
+    /// after 50ms, except every `late_every`-th, which it answers after 3s.
+    fn run_mock_resolver(queries: u16, drop_every: u16, late_every: u16) -> LossEstimator {
+        let mut loss = LossEstimator::new();
+        let mut late = Vec::new();
+        for id in 0..queries {
+            let sent_at = u64::from(id) * 100_000;
+            loss.record_query(id, sent_at);
+            loss.expire(sent_at, DEADLINE_US);
+            if id % drop_every == 0 {
+                continue;
+            }
+            if id % late_every == 0 {
+                late.push((id, sent_at + 3_000_000));
+            } else {
+                loss.expire(sent_at + 50_000, DEADLINE_US);
+                loss.record_response(id);
+            }
+            while let Some(&(late_id, due)) = late.first() {
+                if due > sent_at {
+                    break;
+                }
+                loss.expire(due, DEADLINE_US);
+                loss.record_response(late_id);
+                late.remove(0);
+            }
+        }
+        loss.expire(u64::from(queries) * 100_000 + 10_000_000, DEADLINE_US);
+        for (late_id, _) in late {
+            loss.record_response(late_id);
+        }
+        loss
+    }
+
+    #[test]
+    fn estimates_the_share_of_dropped_queries() {
+        // Drops one query in five; some answers are slow but arrive.
+        let loss = run_mock_resolver(1_000, 5, 7);
+        let rate = loss.loss_rate().expect("enough samples");
+        assert!((rate - 0.2).abs() < 0.01, "rate={}", rate);
+
+        let clean = run_mock_resolver(1_000, u16::MAX, 7);
+        assert_eq!(clean.loss_rate(), Some(0.0));
+    }
+
+    #[test]
+    fn needs_samples_before_estimating() {
+        let mut loss = LossEstimator::new();
+        for id in 0..(LOSS_MIN_SAMPLES as u16 - 1) {
+            loss.record_query(id, 0);
+        }
+        loss.expire(DEADLINE_US, DEADLINE_US);
+        assert_eq!(loss.loss_rate(), None);
+        loss.record_query(100, DEADLINE_US);
+        loss.record_response(100);
+        assert_eq!(
+            loss.loss_rate(),
+            Some((LOSS_MIN_SAMPLES - 1) as f64 / LOSS_MIN_SAMPLES as f64)
+        );
+    }
+}
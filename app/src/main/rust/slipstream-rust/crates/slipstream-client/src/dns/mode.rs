@@ -0,0 +1,163 @@
+use super::resolver::ResolverState;
+use crate::events::TunnelEvent;
+use crate::redact::redact;
+use slipstream_ffi::{ModeMismatchPolicy, ResolverMode};
+use tracing::warn;
+
+pub(crate) fn mode_name(mode: ResolverMode) -> &'static str {
+    match mode {
+        ResolverMode::Recursive => "recursive",
+        ResolverMode::Authoritative => "authoritative",
+    }
+}
+
+/// Compares the mode a resolver was configured with to the one its tunnel
+/// answers show.
+///
+/// Each path is polled by its own mode: authoritative paths send paced bursts
+/// sized to the congestion window, recursive paths add one poll per answer.
+/// Recursive resolvers set RA on their answers and the tunnel server does not,
+/// so `recursion_available` reveals a path that was set up in the wrong mode.
+/// Paced bursts at a public resolver get rate limited or blocked, which is
+/// what makes mixed sets break. Under [`ModeMismatchPolicy::Adapt`] such a
+/// path drops to recursive polling, and `path_mode_stale` asks the caller to
+/// re-apply the QUIC path mode. The opposite mismatch only costs speed and is
+/// only reported. Returns the event for the first mismatch of the connection.
+pub(crate) fn check_resolver_mode(
+    resolver: &mut ResolverState,
+    recursion_available: bool,
+    policy: ModeMismatchPolicy,
+) -> Option<TunnelEvent> {
+    let observed = if recursion_available {
+        ResolverMode::Recursive
+    } else {
+        ResolverMode::Authoritative
+    };
+    if observed == resolver.mode || resolver.mode_mismatch_reported {
+        return None;
+    }
+    resolver.mode_mismatch_reported = true;
+    let configured = resolver.mode;
+    let adapted = policy == ModeMismatchPolicy::Adapt && configured == ResolverMode::Authoritative;
+    if adapted {
+        resolver.mode = ResolverMode::Recursive;
+        resolver.pacing_budget = None;
+        resolver.last_pacing_snapshot = None;
+        resolver.path_mode_stale = true;
+    }
+    match configured {
+        ResolverMode::Authoritative => warn!(
+            "Resolver {} is configured as authoritative but answers as a recursive resolver; {}",
+            redact(resolver.addr),
+            if adapted {
+                "switched it to recursive polling"
+            } else {
+                "paced polling may get it rate limited, list it with --resolver"
+            }
+        ),
+        ResolverMode::Recursive => warn!(
+            "Resolver {} is configured as recursive but the tunnel server answers it directly; list it with --authoritative for faster polling",
+            redact(resolver.addr)
+        ),
+    }
+    Some(TunnelEvent::ResolverModeMismatch {
+        resolver: redact(resolver.addr).to_string(),
+        configured: mode_name(configured).to_string(),
+        observed: mode_name(observed).to_string(),
+        adapted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{resolve_resolvers, HealthPolicy};
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_ffi::ResolverSpec;
+
+    fn resolver(mode: ResolverMode) -> ResolverState {
+        let spec = ResolverSpec {
+            resolver: HostPort {
+                host: "192.0.2.1".to_string(),
+                port: 53,
+                family: AddressFamily::V4,
+            },
+            mode,
+        };
+        resolve_resolvers(&[spec], 900, false, HealthPolicy::default())
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn matching_modes_raise_nothing() {
+        for policy in [ModeMismatchPolicy::Report, ModeMismatchPolicy::Adapt] {
+            let mut recursive = resolver(ResolverMode::Recursive);
+            assert!(check_resolver_mode(&mut recursive, true, policy).is_none());
+            let mut authoritative = resolver(ResolverMode::Authoritative);
+            assert!(check_resolver_mode(&mut authoritative, false, policy).is_none());
+            assert_eq!(authoritative.mode, ResolverMode::Authoritative);
+            assert!(authoritative.pacing_budget.is_some());
+            assert!(!authoritative.path_mode_stale);
+        }
+    }
+
+    #[test]
+    fn authoritative_path_through_a_recursive_resolver() {
+        let mut reported = resolver(ResolverMode::Authoritative);
+        let event = check_resolver_mode(&mut reported, true, ModeMismatchPolicy::Report);
+        let Some(TunnelEvent::ResolverModeMismatch {
+            configured,
+            observed,
+            adapted,
+            ..
+        }) = event
+        else {
+            panic!("expected a mode mismatch event");
+        };
+        assert_eq!(
+            (configured.as_str(), observed.as_str()),
+            ("authoritative", "recursive")
+        );
+        assert!(!adapted);
+        assert_eq!(reported.mode, ResolverMode::Authoritative);
+        assert!(reported.pacing_budget.is_some());
+        // Once per connection.
+        assert!(check_resolver_mode(&mut reported, true, ModeMismatchPolicy::Report).is_none());
+
+        let mut adapted = resolver(ResolverMode::Authoritative);
+        let event = check_resolver_mode(&mut adapted, true, ModeMismatchPolicy::Adapt);
+        assert!(matches!(
+            event,
+            Some(TunnelEvent::ResolverModeMismatch { adapted: true, .. })
+        ));
+        assert_eq!(adapted.mode, ResolverMode::Recursive);
+        assert!(adapted.pacing_budget.is_none());
+        assert!(adapted.path_mode_stale);
+        assert!(check_resolver_mode(&mut adapted, true, ModeMismatchPolicy::Adapt).is_none());
+    }
+
+    #[test]
+    fn recursive_path_answered_by_the_server_is_only_reported() {
+        for policy in [ModeMismatchPolicy::Report, ModeMismatchPolicy::Adapt] {
+            let mut direct = resolver(ResolverMode::Recursive);
+            let event = check_resolver_mode(&mut direct, false, policy);
+            let Some(TunnelEvent::ResolverModeMismatch {
+                configured,
+                observed,
+                adapted,
+                ..
+            }) = event
+            else {
+                panic!("expected a mode mismatch event");
+            };
+            assert_eq!(
+                (configured.as_str(), observed.as_str()),
+                ("recursive", "authoritative")
+            );
+            assert!(!adapted);
+            assert_eq!(direct.mode, ResolverMode::Recursive);
+            assert!(!direct.path_mode_stale);
+        }
+    }
+}
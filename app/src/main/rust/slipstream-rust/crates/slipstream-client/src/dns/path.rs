@@ -1,4 +1,5 @@
 use crate::error::ClientError;
+use crate::redact::redact;
 use slipstream_ffi::picoquic::{
     picoquic_cnx_t, picoquic_current_time, picoquic_get_path_addr, picoquic_probe_new_path_ex,
     slipstream_find_path_id_by_addr, slipstream_get_path_id_from_unique,
@@ -43,9 +44,35 @@ pub(crate) fn refresh_resolver_path(
     true
 }
 
+/// Extra resolvers whose path probe may start now, in configured order.
+///
+/// With `max_parallel` set, probes that have not been validated yet count
+/// against it, so the startup burst over many resolvers stays bounded. A probe
+/// that fails is dropped by picoquic and frees its slot.
+fn probe_candidates(
+    resolvers: &[ResolverState],
+    max_parallel: Option<usize>,
+    now: u64,
+) -> Vec<usize> {
+    let in_flight = resolvers
+        .iter()
+        .filter(|resolver| resolver.added && resolver.unique_path_id.is_none())
+        .count();
+    let slots = max_parallel.map_or(usize::MAX, |max| max.saturating_sub(in_flight));
+    resolvers
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter(|(_, resolver)| !resolver.added && resolver.next_probe_at <= now)
+        .map(|(idx, _)| idx)
+        .take(slots)
+        .collect()
+}
+
 pub(crate) fn add_paths(
     cnx: *mut picoquic_cnx_t,
     resolvers: &mut [ResolverState],
+    max_parallel: Option<usize>,
 ) -> Result<(), ClientError> {
     if resolvers.len() <= 1 {
         return Ok(());
@@ -60,13 +87,8 @@ pub(crate) fn add_paths(
     let primary_mode = resolvers[0].mode;
     let mut default_mode = primary_mode;
 
-    for resolver in resolvers.iter_mut().skip(1) {
-        if resolver.added {
-            continue;
-        }
-        if resolver.next_probe_at > now {
-            continue;
-        }
+    for idx in probe_candidates(resolvers, max_parallel, now) {
+        let resolver = &mut resolvers[idx];
         if resolver.mode != default_mode {
             unsafe { slipstream_set_default_path_mode(resolver_mode_to_c(resolver.mode)) };
             default_mode = resolver.mode;
@@ -86,15 +108,18 @@ pub(crate) fn add_paths(
         if ret == 0 && path_id >= 0 {
             resolver.added = true;
             resolver.path_id = path_id;
-            info!("Added path {}", resolver.addr);
+            info!("Added path {}", redact(resolver.addr));
             continue;
         }
         resolver.probe_attempts = resolver.probe_attempts.saturating_add(1);
-        let delay = path_probe_backoff(resolver.probe_attempts);
+        resolver.health.record_failure(resolver.addr, now);
+        let delay = resolver
+            .health
+            .probe_delay(path_probe_backoff(resolver.probe_attempts));
         resolver.next_probe_at = now.saturating_add(delay);
         warn!(
             "Failed adding path {} (attempt {}), retrying in {}ms",
-            resolver.addr,
+            redact(resolver.addr),
             resolver.probe_attempts,
             delay / 1000
         );
@@ -119,3 +144,55 @@ fn path_probe_backoff(attempts: u32) -> u64 {
     let delay = PATH_PROBE_INITIAL_DELAY_US.saturating_mul(1u64 << shift);
     delay.min(PATH_PROBE_MAX_DELAY_US)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::probe_candidates;
+    use crate::dns::health::HealthPolicy;
+    use crate::dns::resolve_resolvers;
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_ffi::{ResolverMode, ResolverSpec};
+
+    #[test]
+    fn startup_probes_respect_the_parallel_cap() {
+        let specs: Vec<ResolverSpec> = (1..=6)
+            .map(|host| ResolverSpec {
+                resolver: HostPort {
+                    host: format!("192.0.2.{}", host),
+                    port: 53,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+            })
+            .collect();
+        let mut resolvers = resolve_resolvers(&specs, 900, false, HealthPolicy::default()).unwrap();
+        let in_flight = |resolvers: &[crate::dns::ResolverState]| {
+            resolvers
+                .iter()
+                .filter(|resolver| resolver.added && resolver.unique_path_id.is_none())
+                .count()
+        };
+
+        let mut started = Vec::new();
+        for round in 0..10 {
+            for idx in probe_candidates(&resolvers, Some(2), 0) {
+                // What add_paths does once picoquic accepted the probe.
+                resolvers[idx].added = true;
+                started.push(idx);
+            }
+            assert!(in_flight(&resolvers) <= 2, "round {}", round);
+            // One probe validates per round and frees its slot.
+            if let Some(resolver) = resolvers
+                .iter_mut()
+                .find(|resolver| resolver.added && resolver.unique_path_id.is_none())
+            {
+                resolver.unique_path_id = Some(round);
+            }
+        }
+        assert_eq!(started, vec![1, 2, 3, 4, 5]);
+
+        // Without a cap every extra resolver is probed at once.
+        let fresh = resolve_resolvers(&specs, 900, false, HealthPolicy::default()).unwrap();
+        assert_eq!(probe_candidates(&fresh, None, 0), vec![1, 2, 3, 4, 5]);
+    }
+}
@@ -1,42 +1,123 @@
 use crate::error::ClientError;
+use crate::runtime::{build_query_name, qname_limit, QueryBudget};
 use slipstream_core::net::is_transient_udp_error;
-use slipstream_dns::{build_qname, encode_query, QueryParams, CLASS_IN, RR_TXT};
+use slipstream_dns::{QueryParams, RR_TXT};
 use slipstream_ffi::picoquic::{
     picoquic_cnx_t, picoquic_current_time, picoquic_prepare_packet_ex, slipstream_request_poll,
+    PICOQUIC_PACKET_LOOP_RECV_MAX,
 };
-use slipstream_ffi::{ClientConfig, ResolverMode};
-use std::collections::HashMap;
-use tokio::net::UdpSocket as TokioUdpSocket;
+use slipstream_ffi::{ClientConfig, QueryInfo, ResolverTransport};
+use std::collections::{HashMap, VecDeque};
 
+use super::capture::DnsRecorder;
 use super::path::refresh_resolver_path;
+use super::query_id::DnsIdGenerator;
 use super::resolver::{sockaddr_storage_to_socket_addr, ResolverState};
+use super::transport::send_inspected_query;
 use slipstream_core::normalize_dual_stack_addr;
 
-const AUTHORITATIVE_POLL_TIMEOUT_US: u64 = 5_000_000;
+pub const DEFAULT_POLL_TIMEOUT_MS: u64 = 5_000;
 
-pub(crate) fn expire_inflight_polls(inflight_poll_ids: &mut HashMap<u16, u64>, now: u64) {
+/// Most recursive polls queued for one resolver at a time.
+pub(crate) const MAX_POLL_BURST: usize = PICOQUIC_PACKET_LOOP_RECV_MAX;
+
+/// Largest long-poll pool a resolver can be given.
+pub const MAX_POLL_POOL: usize = 32;
+
+// Expired poll IDs remembered so that a late answer is still recognized.
+const EXPIRED_POLLS_TRACKED: usize = 64;
+
+/// Polls that passed the response deadline but may still be answered.
+#[derive(Default)]
+pub(crate) struct ExpiredPolls {
+    ids: VecDeque<u16>,
+}
+
+impl ExpiredPolls {
+    fn push(&mut self, id: u16) {
+        if self.ids.len() == EXPIRED_POLLS_TRACKED {
+            self.ids.pop_front();
+        }
+        self.ids.push_back(id);
+    }
+
+    fn take(&mut self, id: u16) -> bool {
+        match self.ids.iter().position(|expired| *expired == id) {
+            Some(idx) => {
+                self.ids.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.ids.clear();
+    }
+}
+
+/// Stops waiting for polls older than `timeout_us` and returns how many expired.
+///
+/// This is a soft deadline: the caller can send replacement polls, but an expired
+/// poll is remembered so its answer is still accepted if it turns up late.
+pub(crate) fn expire_inflight_polls(
+    inflight_poll_ids: &mut HashMap<u16, u64>,
+    expired_polls: &mut ExpiredPolls,
+    timeout_us: u64,
+    now: u64,
+) -> usize {
     if inflight_poll_ids.is_empty() {
-        return;
+        return 0;
     }
-    let expire_before = now.saturating_sub(AUTHORITATIVE_POLL_TIMEOUT_US);
+    let expire_before = now.saturating_sub(timeout_us);
     let mut expired = Vec::new();
     for (id, sent_at) in inflight_poll_ids.iter() {
         if *sent_at <= expire_before {
             expired.push(*id);
         }
     }
-    for id in expired {
-        inflight_poll_ids.remove(&id);
+    for id in &expired {
+        inflight_poll_ids.remove(id);
+        expired_polls.push(*id);
+    }
+    expired.len()
+}
+
+/// Matches a response to a poll; returns true when the poll had already expired,
+/// meaning the replacement sent for it was spurious.
+pub(crate) fn settle_poll_response(
+    inflight_poll_ids: &mut HashMap<u16, u64>,
+    expired_polls: &mut ExpiredPolls,
+    id: u16,
+) -> bool {
+    if inflight_poll_ids.remove(&id).is_some() {
+        return false;
+    }
+    expired_polls.take(id)
+}
+
+/// Polls to send so that `pool` polls wait at the server behind `resolver`.
+///
+/// Zero until the server announced that it holds polls: one that answers them
+/// at once would turn the pool into a busy loop. Queued recursive polls count
+/// towards the pool.
+pub(crate) fn poll_pool_deficit(resolver: &ResolverState, pool: usize) -> usize {
+    if resolver.poll_hold_ms.is_none() {
+        return 0;
     }
+    pool.min(MAX_POLL_POOL)
+        .saturating_sub(resolver.inflight_poll_ids.len() + resolver.pending_polls)
 }
 
 #[allow(clippy::too_many_arguments)]
 pub(crate) async fn send_poll_queries(
     cnx: *mut picoquic_cnx_t,
-    udp: &TokioUdpSocket,
+    transport: &dyn ResolverTransport,
     config: &ClientConfig<'_>,
     local_addr_storage: &mut libc::sockaddr_storage,
-    dns_id: &mut u16,
+    dns_ids: &mut DnsIdGenerator,
+    recorder: &mut DnsRecorder,
+    budget: &mut QueryBudget,
     resolver: &mut ResolverState,
     remaining: &mut usize,
     send_buf: &mut [u8],
@@ -49,6 +130,10 @@ pub(crate) async fn send_poll_queries(
 
     while remaining_count > 0 {
         let current_time = unsafe { picoquic_current_time() };
+        if !budget.can_send() || !resolver.query_rate.admit(current_time) {
+            *remaining = remaining_count;
+            break;
+        }
         unsafe {
             slipstream_request_poll(cnx);
         }
@@ -86,25 +171,40 @@ pub(crate) async fn send_poll_queries(
         resolver.debug.send_bytes = resolver.debug.send_bytes.saturating_add(send_length as u64);
         resolver.debug.polls_sent = resolver.debug.polls_sent.saturating_add(1);
 
-        let poll_id = *dns_id;
-        let qname = build_qname(&send_buf[..send_length], config.domain)
+        let poll_id = dns_ids.next_id();
+        let qname = build_query_name(config, &send_buf[..send_length], qname_limit(config))
             .map_err(|err| ClientError::new(err.to_string()))?;
         let params = QueryParams {
             id: poll_id,
             qname: &qname,
             qtype: RR_TXT,
-            qclass: CLASS_IN,
+            qclass: config.query_class,
             rd: true,
             cd: false,
             qdcount: 1,
             is_query: true,
         };
-        *dns_id = dns_id.wrapping_add(1);
-        let packet = encode_query(&params).map_err(|err| ClientError::new(err.to_string()))?;
+        let packet = resolver
+            .encode_query(&params)
+            .map_err(|err| ClientError::new(err.to_string()))?;
+        recorder.record_query(&packet);
+        resolver.record_query_bytes(packet.len());
 
         let dest = sockaddr_storage_to_socket_addr(&addr_to)?;
         let dest = normalize_dual_stack_addr(dest);
-        if let Err(err) = udp.send_to(&packet, dest).await {
+        recorder.log_query(dest, poll_id, &qname);
+        let query = QueryInfo {
+            resolver: dest,
+            id: poll_id,
+            qname: &qname,
+            qtype: RR_TXT,
+            size: packet.len(),
+            poll: true,
+        };
+        let sent =
+            send_inspected_query(transport, config.query_hook.as_ref(), &query, &packet).await;
+        resolver.route.record(&sent);
+        if let Err(err) = sent {
             if is_transient_udp_error(&err) {
                 remaining_count = remaining_count.saturating_add(1);
                 *remaining = remaining_count;
@@ -112,10 +212,92 @@ pub(crate) async fn send_poll_queries(
             }
             return Err(ClientError::new(err.to_string()));
         }
-        if resolver.mode == ResolverMode::Authoritative {
-            resolver.inflight_poll_ids.insert(poll_id, current_time);
-        }
+        budget.record_query(poll_id, current_time);
+        resolver.health.record_send(current_time);
+        resolver.response_timer.record_query(poll_id, current_time);
+        resolver.loss.record_query(poll_id, current_time);
+        resolver
+            .query_spans
+            .start(poll_id, current_time, resolver.addr, "poll");
+        resolver.inflight_poll_ids.insert(poll_id, current_time);
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{resolve_resolvers, HealthPolicy};
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_ffi::{ResolverMode, ResolverSpec};
+
+    #[test]
+    fn pool_keeps_polls_waiting_for_pushed_data() {
+        let spec = ResolverSpec {
+            resolver: HostPort {
+                host: "192.0.2.1".to_string(),
+                port: 53,
+                family: AddressFamily::V4,
+            },
+            mode: ResolverMode::Recursive,
+        };
+        let mut resolvers =
+            resolve_resolvers(&[spec], 900, false, HealthPolicy::default()).unwrap();
+        let resolver = &mut resolvers[0];
+        // A server that answers polls at once gets no pool.
+        assert_eq!(poll_pool_deficit(resolver, 4), 0);
+
+        resolver.poll_hold_ms = Some(2_000);
+        assert_eq!(poll_pool_deficit(resolver, 4), 4);
+        resolver.inflight_poll_ids.insert(1, 0);
+        resolver.inflight_poll_ids.insert(2, 0);
+        resolver.pending_polls = 1;
+        assert_eq!(poll_pool_deficit(resolver, 4), 1);
+
+        // The server pushes data on a held poll: its answer frees a slot that
+        // is refilled, so a poll is always waiting for the next push.
+        resolver.pending_polls = 0;
+        resolver.inflight_poll_ids.insert(3, 0);
+        assert_eq!(poll_pool_deficit(resolver, 4), 1);
+        assert!(!settle_poll_response(
+            &mut resolver.inflight_poll_ids,
+            &mut resolver.expired_polls,
+            2
+        ));
+        assert_eq!(poll_pool_deficit(resolver, 4), 2);
+        assert_eq!(poll_pool_deficit(resolver, 100), MAX_POLL_POOL - 2);
+    }
+
+    #[test]
+    fn late_but_valid_response_is_accepted_as_spurious() {
+        let mut inflight = HashMap::new();
+        let mut expired = ExpiredPolls::default();
+        inflight.insert(1, 0);
+        inflight.insert(2, 900_000);
+        assert_eq!(
+            expire_inflight_polls(&mut inflight, &mut expired, 1_000_000, 1_500_000),
+            1
+        );
+        assert!(inflight.contains_key(&2));
+        assert!(settle_poll_response(&mut inflight, &mut expired, 1));
+        assert!(!settle_poll_response(&mut inflight, &mut expired, 1));
+        assert!(!settle_poll_response(&mut inflight, &mut expired, 2));
+        assert!(inflight.is_empty());
+    }
+
+    #[test]
+    fn forgets_oldest_expired_polls() {
+        let mut inflight = HashMap::new();
+        let mut expired = ExpiredPolls::default();
+        for id in 0..=EXPIRED_POLLS_TRACKED as u16 {
+            inflight.insert(id, 0);
+        }
+        let count = expire_inflight_polls(&mut inflight, &mut expired, 1, 10);
+        assert_eq!(count, EXPIRED_POLLS_TRACKED + 1);
+        let remembered = (0..=EXPIRED_POLLS_TRACKED as u16)
+            .filter(|id| settle_poll_response(&mut inflight, &mut expired, *id))
+            .count();
+        assert_eq!(remembered, EXPIRED_POLLS_TRACKED);
+    }
+}
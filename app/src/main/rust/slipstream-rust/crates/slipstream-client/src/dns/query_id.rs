@@ -0,0 +1,78 @@
+use slipstream_ffi::DnsIdStrategy;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// Produces DNS transaction IDs for queries and polls.
+pub(crate) struct DnsIdGenerator {
+    strategy: DnsIdStrategy,
+    next: u16,
+    rng_state: u64,
+}
+
+impl DnsIdGenerator {
+    pub(crate) fn new(strategy: DnsIdStrategy) -> Self {
+        Self::with_seed(strategy, RandomState::new().build_hasher().finish())
+    }
+
+    pub(crate) fn with_seed(strategy: DnsIdStrategy, seed: u64) -> Self {
+        Self {
+            strategy,
+            next: 1,
+            // xorshift never leaves the all-zero state.
+            rng_state: seed | 1,
+        }
+    }
+
+    pub(crate) fn next_id(&mut self) -> u16 {
+        match self.strategy {
+            DnsIdStrategy::Random => {
+                let mut x = self.rng_state;
+                x ^= x >> 12;
+                x ^= x << 25;
+                x ^= x >> 27;
+                self.rng_state = x;
+                (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 48) as u16
+            }
+            DnsIdStrategy::Sequential => {
+                let id = self.next;
+                self.next = self.next.wrapping_add(1);
+                id
+            }
+            DnsIdStrategy::Fixed(id) => id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequential_ids_start_at_one_and_wrap() {
+        let mut ids = DnsIdGenerator::new(DnsIdStrategy::Sequential);
+        assert_eq!(ids.next_id(), 1);
+        assert_eq!(ids.next_id(), 2);
+        ids.next = u16::MAX;
+        assert_eq!(ids.next_id(), u16::MAX);
+        assert_eq!(ids.next_id(), 0);
+    }
+
+    #[test]
+    fn fixed_ids_never_change() {
+        let mut ids = DnsIdGenerator::new(DnsIdStrategy::Fixed(0x1234));
+        assert!((0..8).all(|_| ids.next_id() == 0x1234));
+    }
+
+    #[test]
+    fn random_ids_are_deterministic_for_a_seed() {
+        let mut first = DnsIdGenerator::with_seed(DnsIdStrategy::Random, 42);
+        let mut second = DnsIdGenerator::with_seed(DnsIdStrategy::Random, 42);
+        let first: Vec<u16> = (0..64).map(|_| first.next_id()).collect();
+        let second: Vec<u16> = (0..64).map(|_| second.next_id()).collect();
+        assert_eq!(first, second);
+        let mut distinct = first.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert!(distinct.len() > 60, "expected mostly distinct ids");
+    }
+}
@@ -0,0 +1,374 @@
+// The learned rate never drops below this, so a resolver is never starved.
+const MIN_QPS: f64 = 1.0;
+// Each push back from the resolver halves the rate.
+const DECREASE_FACTOR: f64 = 0.5;
+// Signals within this long of a cut belong to the same overload.
+const DECREASE_HOLD_US: u64 = 1_000_000;
+// While the rate is what holds queries back, it grows this much per interval.
+const INCREASE_QPS: f64 = 1.0;
+const INCREASE_INTERVAL_US: u64 = 1_000_000;
+// The send rate is measured over windows this long.
+const SEND_WINDOW_US: u64 = 1_000_000;
+// At most this share of a second's queries go out in one burst.
+const BURST_SECONDS: f64 = 0.1;
+/// How often the startup ramp doubles its rate unless configured otherwise.
+pub const DEFAULT_QUERY_RAMP_DOUBLING_MS: u64 = 500;
+
+/// Query slow start for a new connection: the rate starts low and doubles
+/// every interval in which it held queries back. The first interval that
+/// held nothing back shows the tunnel wants less than the ramp allows, and
+/// ends it.
+struct StartupRamp {
+    qps: f64,
+    doubling_us: u64,
+    doubled_at: u64,
+    /// A query was held back since the last doubling.
+    limited: bool,
+}
+
+/// Learns how many queries per second one resolver tolerates, AIMD style.
+///
+/// A resolver that rate-limits answers REFUSED or drops queries once it is
+/// sent too much. Each such signal halves the rate, starting from the rate
+/// that was actually sent; while the learned rate is what holds queries back
+/// it grows again by `INCREASE_QPS` per second, probing for the limit. Until
+/// the first signal nothing is limited. Disabled controllers admit everything
+/// but for the startup ramp, which applies either way.
+pub(crate) struct QueryRateController {
+    enabled: bool,
+    ramp: Option<StartupRamp>,
+    /// Learned queries per second; `None` until the resolver first pushed back.
+    rate: Option<f64>,
+    tokens: f64,
+    last_refill_at: u64,
+    last_decrease_at: u64,
+    last_increase_at: u64,
+    /// A query was held back since the last increase.
+    limited: bool,
+    window_start: u64,
+    window_queries: u64,
+    /// Send rate of the last full window.
+    sent_qps: f64,
+    /// Queries held back over the learned rate or the startup ramp.
+    held_back: u64,
+}
+
+impl QueryRateController {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: false,
+            ramp: None,
+            rate: None,
+            tokens: 0.0,
+            last_refill_at: 0,
+            last_decrease_at: 0,
+            last_increase_at: 0,
+            limited: false,
+            window_start: 0,
+            window_queries: 0,
+            sent_qps: 0.0,
+            held_back: 0,
+        }
+    }
+
+    pub(crate) fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    /// Starts the connection at `initial_qps`, doubling every `doubling_ms`
+    /// while the tunnel asks for more, instead of sending its first burst at
+    /// once.
+    pub(crate) fn start_ramp(&mut self, now: u64, initial_qps: u32, doubling_ms: u64) {
+        if initial_qps == 0 {
+            return;
+        }
+        self.ramp = Some(StartupRamp {
+            qps: f64::from(initial_qps),
+            doubling_us: doubling_ms.max(1).saturating_mul(1_000),
+            doubled_at: now,
+            limited: false,
+        });
+        self.tokens = 1.0;
+        self.last_refill_at = now;
+    }
+
+    /// Rate of the startup ramp while it lasts.
+    pub(crate) fn ramp_qps(&self) -> Option<f64> {
+        self.ramp.as_ref().map(|ramp| ramp.qps)
+    }
+
+    /// Learned rate in queries per second; `None` while unlimited.
+    pub(crate) fn learned_qps(&self) -> Option<f64> {
+        self.rate
+    }
+
+    /// Starts from a rate learned earlier on this network. It still grows
+    /// while it holds queries back, in case the resolver tolerates more now.
+    pub(crate) fn seed(&mut self, qps: f64, now: u64) {
+        if !self.enabled || !qps.is_finite() {
+            return;
+        }
+        self.rate = Some(qps.max(MIN_QPS));
+        self.tokens = self.tokens.min(1.0);
+        self.last_refill_at = now;
+        self.last_increase_at = now;
+    }
+
+    pub(crate) fn held_back(&self) -> u64 {
+        self.held_back
+    }
+
+    /// Whether a query may go to the resolver now; an admitted query is counted
+    /// as sent.
+    pub(crate) fn admit(&mut self, now: u64) -> bool {
+        self.advance_ramp(now);
+        let learned = self.rate.filter(|_| self.enabled);
+        let ramp = self.ramp_qps();
+        let limit = match (learned, ramp) {
+            (Some(learned), Some(ramp)) => Some(learned.min(ramp)),
+            (learned, ramp) => learned.or(ramp),
+        };
+        if let Some(rate) = limit {
+            let elapsed = now.saturating_sub(self.last_refill_at) as f64 / 1_000_000.0;
+            let burst = (rate * BURST_SECONDS).max(1.0);
+            self.tokens = (self.tokens + rate * elapsed).min(burst);
+            self.last_refill_at = now;
+            if self.tokens < 1.0 {
+                match self.ramp.as_mut() {
+                    Some(startup) if ramp == limit => startup.limited = true,
+                    _ => self.limited = true,
+                }
+                self.held_back = self.held_back.saturating_add(1);
+                return false;
+            }
+            self.tokens -= 1.0;
+        }
+        if self.enabled {
+            self.count_query(now);
+        }
+        true
+    }
+
+    fn advance_ramp(&mut self, now: u64) {
+        let Some(ramp) = self.ramp.as_mut() else {
+            return;
+        };
+        if now.saturating_sub(ramp.doubled_at) < ramp.doubling_us {
+            return;
+        }
+        if !ramp.limited {
+            self.ramp = None;
+            return;
+        }
+        ramp.qps *= 2.0;
+        ramp.doubled_at = now;
+        ramp.limited = false;
+    }
+
+    /// The resolver pushed back: a REFUSED answer or a query it dropped.
+    /// Returns the new rate when it was cut.
+    pub(crate) fn record_limit_signal(&mut self, now: u64) -> Option<f64> {
+        if !self.enabled {
+            return None;
+        }
+        // Like a loss in slow start: the learned rate takes over from the ramp.
+        self.ramp = None;
+        if self.rate.is_some() && now.saturating_sub(self.last_decrease_at) < DECREASE_HOLD_US {
+            return None;
+        }
+        let base = self.rate.unwrap_or_else(|| self.send_rate(now));
+        let rate = (base * DECREASE_FACTOR).max(MIN_QPS);
+        self.rate = Some(rate);
+        self.tokens = self.tokens.min(1.0);
+        self.last_refill_at = now;
+        self.last_decrease_at = now;
+        self.last_increase_at = now;
+        self.limited = false;
+        Some(rate)
+    }
+
+    /// Raises the rate once per interval in which it held queries back.
+    pub(crate) fn tick(&mut self, now: u64) {
+        let Some(rate) = self.rate else {
+            return;
+        };
+        if now.saturating_sub(self.last_increase_at) < INCREASE_INTERVAL_US {
+            return;
+        }
+        if self.limited {
+            self.rate = Some(rate + INCREASE_QPS);
+        }
+        self.limited = false;
+        self.last_increase_at = now;
+    }
+
+    fn count_query(&mut self, now: u64) {
+        let elapsed = now.saturating_sub(self.window_start);
+        if elapsed >= SEND_WINDOW_US {
+            self.sent_qps = self.window_queries as f64 * 1_000_000.0 / elapsed as f64;
+            self.window_start = now;
+            self.window_queries = 0;
+        }
+        self.window_queries = self.window_queries.saturating_add(1);
+    }
+
+    /// Queries per second sent lately, counting the window in progress.
+    fn send_rate(&self, now: u64) -> f64 {
+        // A window just started says little; give it at least a tenth of one.
+        let elapsed = now
+            .saturating_sub(self.window_start)
+            .max(SEND_WINDOW_US / 10);
+        let current = self.window_queries as f64 * 1_000_000.0 / elapsed as f64;
+        current.max(self.sent_qps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    const STEP_US: u64 = 10_000;
+
+    /// Refuses every query that brings the last second's count above `limit`.
+    struct LimitingResolver {
+        limit: usize,
+        accepted: VecDeque<u64>,
+    }
+
+    impl LimitingResolver {
+        fn answer(&mut self, now: u64) -> bool {
+            while self
+                .accepted
+                .front()
+                .is_some_and(|sent_at| now - sent_at >= 1_000_000)
+            {
+                self.accepted.pop_front();
+            }
+            if self.accepted.len() >= self.limit {
+                return false;
+            }
+            self.accepted.push_back(now);
+            true
+        }
+    }
+
+    #[test]
+    fn settles_under_a_resolver_that_refuses_above_its_limit() {
+        let mut controller = QueryRateController::new();
+        controller.enable();
+        let mut resolver = LimitingResolver {
+            limit: 20,
+            accepted: VecDeque::new(),
+        };
+        let (mut sent, mut refused) = (0u64, 0u64);
+        // The tunnel wants 100 queries per second for two minutes.
+        for step in 1..=12_000u64 {
+            let now = step * STEP_US;
+            controller.tick(now);
+            if !controller.admit(now) {
+                continue;
+            }
+            let answered = resolver.answer(now);
+            if !answered {
+                controller.record_limit_signal(now);
+            }
+            // Judge the second minute only, once the rate was learned.
+            if step > 6_000 {
+                sent += 1;
+                refused += u64::from(!answered);
+            }
+        }
+        let rate = controller.learned_qps().expect("a learned rate");
+        assert!((1.0..=21.0).contains(&rate), "rate {}", rate);
+        // About the limit on average, and hardly ever refused.
+        let sent_qps = sent as f64 / 60.0;
+        assert!((8.0..=21.0).contains(&sent_qps), "sent {} qps", sent_qps);
+        assert!(refused * 20 <= sent, "{} of {} refused", refused, sent);
+        assert!(controller.held_back() > 0);
+    }
+
+    #[test]
+    fn probes_back_up_only_while_it_holds_queries_back() {
+        let mut controller = QueryRateController::new();
+        controller.enable();
+        for step in 0..50 {
+            assert!(controller.admit(step * STEP_US));
+        }
+        // 100 queries per second were sent when the resolver pushed back.
+        assert_eq!(controller.record_limit_signal(500_000), Some(50.0));
+        // The same overload is not counted twice.
+        assert_eq!(controller.record_limit_signal(600_000), None);
+
+        // Demand above the rate: one step per second.
+        for step in 51..250 {
+            controller.tick(step * STEP_US);
+            controller.admit(step * STEP_US);
+        }
+        assert_eq!(controller.learned_qps(), Some(51.0));
+
+        // Demand well below the rate leaves it where it is, once the last busy
+        // interval has been counted.
+        for second in 3..10 {
+            controller.tick(second * 1_000_000);
+            assert!(controller.admit(second * 1_000_000));
+        }
+        assert_eq!(controller.learned_qps(), Some(52.0));
+    }
+
+    /// Queries admitted in each 500 ms of the first six seconds while the
+    /// tunnel tries to send one every millisecond.
+    fn startup_half_seconds(ramp: bool) -> Vec<u64> {
+        let mut controller = QueryRateController::new();
+        if ramp {
+            controller.start_ramp(0, 5, DEFAULT_QUERY_RAMP_DOUBLING_MS);
+        }
+        let mut counts = vec![0u64; 12];
+        for now in (0..6_000_000u64).step_by(1_000) {
+            if controller.admit(now) {
+                counts[(now / 500_000) as usize] += 1;
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn startup_query_rate_ramps_instead_of_spiking() {
+        let spiking = startup_half_seconds(false);
+        assert_eq!(spiking[0], 500);
+
+        let ramped = startup_half_seconds(true);
+        // 5 queries per second at first, doubling each half second.
+        assert!(ramped[0] <= 4, "{:?}", ramped);
+        for pair in ramped[..8].windows(2) {
+            assert!(
+                pair[1] >= pair[0] * 3 / 2 && pair[1] <= pair[0] * 3 + 1,
+                "{:?}",
+                ramped
+            );
+        }
+        // Once the ramp outgrew what the tunnel asks for, it is gone.
+        assert_eq!(ramped[10..], [500, 500]);
+    }
+
+    #[test]
+    fn a_limit_signal_hands_the_ramp_over_to_the_learned_rate() {
+        let mut controller = QueryRateController::new();
+        controller.enable();
+        controller.start_ramp(0, 20, 1_000);
+        assert!(controller.admit(0));
+        assert_eq!(controller.ramp_qps(), Some(20.0));
+        assert!(controller.record_limit_signal(10_000).is_some());
+        assert_eq!(controller.ramp_qps(), None);
+    }
+
+    #[test]
+    fn disabled_controller_admits_everything() {
+        let mut controller = QueryRateController::new();
+        for step in 0..1_000 {
+            assert!(controller.admit(step));
+            assert_eq!(controller.record_limit_signal(step), None);
+        }
+        assert_eq!(controller.learned_qps(), None);
+    }
+}
@@ -1,12 +1,26 @@
 use crate::error::ClientError;
 use crate::pacing::{PacingBudgetSnapshot, PacingPollBudget};
+use crate::redact::redact;
+use crate::spans::QuerySpans;
 use slipstream_core::{normalize_dual_stack_addr, resolve_host_port};
+use slipstream_dns::{encode_query, encode_query_with_cookie, DnsCookie, DnsError, QueryParams};
 use slipstream_ffi::{socket_addr_to_storage, ResolverMode, ResolverSpec};
+use std::collections::hash_map::RandomState;
 use std::collections::HashMap;
+use std::hash::BuildHasher;
 use std::net::SocketAddr;
-use tracing::warn;
+use tracing::{debug, warn};
 
 use super::debug::DebugMetrics;
+use super::formerr::FormerrTracker;
+use super::goodput::GoodputEstimator;
+use super::health::{HealthPolicy, ResolverHealth};
+use super::latency::ResponseTimer;
+use super::loss::LossEstimator;
+use super::poll::ExpiredPolls;
+use super::rate::QueryRateController;
+use super::route::RouteTracker;
+use super::spike::RttSpikeDetector;
 
 pub(crate) struct ResolverState {
     pub(crate) addr: SocketAddr,
@@ -23,21 +37,115 @@ pub(crate) struct ResolverState {
     pub(crate) pacing_budget: Option<PacingPollBudget>,
     pub(crate) last_pacing_snapshot: Option<PacingBudgetSnapshot>,
     pub(crate) debug: DebugMetrics,
+    pub(crate) health: ResolverHealth,
+    pub(crate) response_timer: ResponseTimer,
+    pub(crate) loss: LossEstimator,
+    pub(crate) route: RouteTracker,
+    /// Path is on standby under the split resolver role policy, or while the
+    /// resolver is dead and another path can carry data.
+    pub(crate) standby: bool,
+    /// Answer TTL of the latest response, as delivered by the resolver.
+    pub(crate) response_ttl: Option<u32>,
+    /// Poll hold time the server announced; `None` while it answers polls at once.
+    pub(crate) poll_hold_ms: Option<u32>,
+    pub(crate) expired_polls: ExpiredPolls,
+    /// Polls that passed the response deadline.
+    pub(crate) poll_timeouts: u64,
+    /// Expired polls answered after all; each one made its replacement spurious.
+    pub(crate) late_poll_responses: u64,
+    /// Responses that carried a QUIC packet, as opposed to errors or empty answers.
+    pub(crate) tunnel_responses: u64,
+    /// Answers that were ordinary records, e.g. a website's address, not tunnel data.
+    pub(crate) non_tunnel_responses: u64,
+    /// The latest answer was REFUSED.
+    pub(crate) refusing: bool,
+    /// A mismatch between `mode` and the answers was reported this connection.
+    pub(crate) mode_mismatch_reported: bool,
+    /// `mode` changed and the QUIC path mode must be applied again.
+    pub(crate) path_mode_stale: bool,
+    /// DNS bytes on the wire: encoded queries sent, responses received.
+    pub(crate) query_wire_bytes: u64,
+    pub(crate) response_wire_bytes: u64,
+    /// Largest encoded query sent, polls included.
+    pub(crate) largest_query_bytes: usize,
+    /// QUIC bytes unpacked from responses.
+    pub(crate) response_payload_bytes: u64,
+    /// Downstream tunnel bytes per second while data flows.
+    pub(crate) goodput: GoodputEstimator,
+    /// Query rate the resolver tolerates, learned when adaptive rate control is on.
+    pub(crate) query_rate: QueryRateController,
+    /// Spans of the queries in flight, while query spans are enabled.
+    pub(crate) query_spans: QuerySpans,
+    /// Holds back retransmits while the resolver's RTT jumped.
+    pub(crate) rtt_spike: RttSpikeDetector,
+    /// DNS Cookie state when cookies are enabled.
+    pub(crate) cookie: Option<DnsCookie>,
+    /// FORMERR answers to the current query form, and the plainer form in use.
+    pub(crate) formerr: FormerrTracker,
 }
 
 impl ResolverState {
     pub(crate) fn label(&self) -> String {
         format!(
             "path_id={} unique_id={:?} resolver={} mode={:?}",
-            self.path_id, self.unique_path_id, self.addr, self.mode
+            self.path_id,
+            self.unique_path_id,
+            redact(self.addr),
+            self.mode
         )
     }
+
+    /// Starts sending DNS Cookies, with a fresh random client cookie.
+    pub(crate) fn enable_cookie(&mut self) {
+        let client = RandomState::new().hash_one(self.addr).to_be_bytes();
+        self.cookie = Some(DnsCookie::new(client));
+    }
+
+    /// Counts a REFUSED answer or a dropped query against the learned query rate.
+    pub(crate) fn record_rate_limit_signal(&mut self, now: u64) {
+        let first = self.query_rate.learned_qps().is_none();
+        if let Some(rate) = self.query_rate.record_limit_signal(now) {
+            if first {
+                warn!(
+                    "Resolver {} is pushing back; limiting it to {:.1} queries/s and probing upwards",
+                    redact(self.addr),
+                    rate
+                );
+            } else {
+                debug!(
+                    "Resolver {} pushed back again; limiting it to {:.1} queries/s",
+                    redact(self.addr),
+                    rate
+                );
+            }
+        }
+    }
+
+    /// Encodes a query to this resolver, carrying its cookie when enabled and
+    /// in class IN once the resolver rejected the configured class.
+    /// Counts an encoded query of `len` bytes sent to this resolver.
+    pub(crate) fn record_query_bytes(&mut self, len: usize) {
+        self.query_wire_bytes = self.query_wire_bytes.saturating_add(len as u64);
+        self.largest_query_bytes = self.largest_query_bytes.max(len);
+    }
+
+    pub(crate) fn encode_query(&self, params: &QueryParams<'_>) -> Result<Vec<u8>, DnsError> {
+        let params = QueryParams {
+            qclass: self.formerr.query_class(params.qclass),
+            ..*params
+        };
+        match &self.cookie {
+            Some(cookie) => encode_query_with_cookie(&params, cookie),
+            None => encode_query(&params),
+        }
+    }
 }
 
 pub(crate) fn resolve_resolvers(
     resolvers: &[ResolverSpec],
     mtu: u32,
     debug_poll: bool,
+    health_policy: HealthPolicy,
 ) -> Result<Vec<ResolverState>, ClientError> {
     let mut resolved = Vec::with_capacity(resolvers.len());
     let mut seen = HashMap::new();
@@ -71,15 +179,57 @@ pub(crate) fn resolve_resolvers(
             },
             last_pacing_snapshot: None,
             debug: DebugMetrics::new(debug_poll),
+            health: ResolverHealth::new(health_policy),
+            response_timer: ResponseTimer::new(),
+            loss: LossEstimator::new(),
+            route: RouteTracker::default(),
+            standby: false,
+            response_ttl: None,
+            poll_hold_ms: None,
+            expired_polls: ExpiredPolls::default(),
+            poll_timeouts: 0,
+            late_poll_responses: 0,
+            tunnel_responses: 0,
+            non_tunnel_responses: 0,
+            refusing: false,
+            mode_mismatch_reported: false,
+            path_mode_stale: false,
+            query_wire_bytes: 0,
+            response_wire_bytes: 0,
+            largest_query_bytes: 0,
+            response_payload_bytes: 0,
+            goodput: GoodputEstimator::new(),
+            query_rate: QueryRateController::new(),
+            query_spans: QuerySpans::new(),
+            rtt_spike: RttSpikeDetector::new(),
+            cookie: None,
+            formerr: FormerrTracker::default(),
         });
     }
     Ok(resolved)
 }
 
+/// Like [`resolve_resolvers`], for resolvers joining a running connection: none
+/// of them takes the primary path, so each is probed as an extra path.
+pub(crate) fn resolve_added_resolvers(
+    resolvers: &[ResolverSpec],
+    mtu: u32,
+    debug_poll: bool,
+    health_policy: HealthPolicy,
+) -> Result<Vec<ResolverState>, ClientError> {
+    let mut resolved = resolve_resolvers(resolvers, mtu, debug_poll, health_policy)?;
+    for resolver in resolved.iter_mut() {
+        resolver.added = false;
+        resolver.path_id = -1;
+        resolver.unique_path_id = None;
+    }
+    Ok(resolved)
+}
+
 pub(crate) fn reset_resolver_path(resolver: &mut ResolverState) {
     warn!(
         "Path for resolver {} became unavailable; resetting state",
-        resolver.addr
+        redact(resolver.addr)
     );
     resolver.added = false;
     resolver.path_id = -1;
@@ -87,9 +237,11 @@ pub(crate) fn reset_resolver_path(resolver: &mut ResolverState) {
     resolver.local_addr_storage = None;
     resolver.pending_polls = 0;
     resolver.inflight_poll_ids.clear();
+    resolver.expired_polls.clear();
     resolver.last_pacing_snapshot = None;
     resolver.probe_attempts = 0;
     resolver.next_probe_at = 0;
+    resolver.standby = false;
 }
 
 pub(crate) fn sockaddr_storage_to_socket_addr(
@@ -101,6 +253,7 @@ pub(crate) fn sockaddr_storage_to_socket_addr(
 #[cfg(test)]
 mod tests {
     use super::resolve_resolvers;
+    use crate::dns::health::HealthPolicy;
     use slipstream_core::{AddressFamily, HostPort};
     use slipstream_ffi::{ResolverMode, ResolverSpec};
 
@@ -125,9 +278,31 @@ mod tests {
             },
         ];
 
-        match resolve_resolvers(&resolvers, 900, false) {
+        match resolve_resolvers(&resolvers, 900, false, HealthPolicy::default()) {
             Ok(_) => panic!("expected duplicate resolver error"),
             Err(err) => assert!(err.to_string().contains("Duplicate resolver address")),
         }
     }
+
+    #[test]
+    fn query_bytes_keep_the_total_and_the_largest() {
+        let spec = ResolverSpec {
+            resolver: HostPort {
+                host: "127.0.0.1".to_string(),
+                port: 53,
+                family: AddressFamily::V4,
+            },
+            mode: ResolverMode::Recursive,
+        };
+        let mut resolvers =
+            resolve_resolvers(&[spec], 900, false, HealthPolicy::default()).unwrap();
+        let resolver = &mut resolvers[0];
+        // A data query, then a smaller poll.
+        resolver.record_query_bytes(240);
+        resolver.record_query_bytes(90);
+        assert_eq!(resolver.query_wire_bytes, 330);
+        assert_eq!(resolver.largest_query_bytes, 240);
+        resolver.record_query_bytes(250);
+        assert_eq!(resolver.largest_query_bytes, 250);
+    }
 }
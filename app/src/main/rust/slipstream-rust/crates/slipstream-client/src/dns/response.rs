@@ -1,21 +1,42 @@
 use crate::error::ClientError;
-use slipstream_dns::decode_response;
+use crate::events::TunnelEvent;
+use crate::redact::redact;
+use crate::runtime::QueryBudget;
+use slipstream_dns::{
+    decode_poll_hold, decode_response_with_ttl, decode_server_time, non_tunnel_answer_type,
+    response_rcode, response_recursion_available, Rcode,
+};
 use slipstream_ffi::picoquic::{
     picoquic_cnx_t, picoquic_current_time, picoquic_incoming_packet_ex, picoquic_quic_t,
-    PICOQUIC_PACKET_LOOP_RECV_MAX,
 };
-use slipstream_ffi::{socket_addr_to_storage, ResolverMode};
+use slipstream_ffi::{
+    socket_addr_to_storage, FormerrPolicy, ModeMismatchPolicy, NonTunnelPolicy, ResolverMode,
+};
 use std::net::SocketAddr;
+use tracing::{debug, warn};
 
+use super::capture::DnsRecorder;
+use super::clock::{unix_time_us, ClockOffsetEstimator};
+use super::formerr::check_formerr;
+use super::mode::check_resolver_mode;
+use super::poll::{settle_poll_response, MAX_POLL_BURST};
 use super::resolver::ResolverState;
 use slipstream_core::normalize_dual_stack_addr;
 
-const MAX_POLL_BURST: usize = PICOQUIC_PACKET_LOOP_RECV_MAX;
-
 pub(crate) struct DnsResponseContext<'a> {
     pub(crate) quic: *mut picoquic_quic_t,
     pub(crate) local_addr_storage: &'a libc::sockaddr_storage,
     pub(crate) resolvers: &'a mut [ResolverState],
+    pub(crate) recorder: &'a mut DnsRecorder,
+    pub(crate) clock: &'a mut ClockOffsetEstimator,
+    pub(crate) non_tunnel_policy: NonTunnelPolicy,
+    pub(crate) mode_mismatch_policy: ModeMismatchPolicy,
+    pub(crate) formerr_policy: FormerrPolicy,
+    /// Class queries are configured to go out in.
+    pub(crate) query_class: u16,
+    pub(crate) query_budget: &'a mut QueryBudget,
+    /// Events raised while handling responses, for the caller to emit.
+    pub(crate) events: &'a mut Vec<TunnelEvent>,
 }
 
 pub(crate) fn handle_dns_response(
@@ -24,12 +45,102 @@ pub(crate) fn handle_dns_response(
     ctx: &mut DnsResponseContext<'_>,
 ) -> Result<(), ClientError> {
     let peer = normalize_dual_stack_addr(peer);
+    ctx.recorder.record_response(buf);
     let response_id = dns_response_id(buf);
-    if let Some(payload) = decode_response(buf) {
+    let non_tunnel = non_tunnel_answer_type(buf);
+    if let Some(response_id) = response_id {
+        ctx.query_budget.record_response(response_id);
+        if let Some(resolver) = find_resolver_by_addr(ctx.resolvers, peer) {
+            resolver.response_wire_bytes = resolver
+                .response_wire_bytes
+                .saturating_add(buf.len() as u64);
+            if let Some(cookie) = resolver.cookie.as_mut() {
+                if cookie.observe_response(buf) {
+                    debug!("Learned DNS server cookie for {}", redact(resolver.addr));
+                }
+            }
+            let now = unsafe { picoquic_current_time() };
+            let rcode = response_rcode(buf);
+            resolver.refusing = rcode == Some(Rcode::Refused);
+            if resolver.refusing {
+                resolver.record_rate_limit_signal(now);
+            }
+            if let Some(event) = check_formerr(resolver, rcode, ctx.query_class, ctx.formerr_policy)
+            {
+                ctx.events.push(event);
+            }
+            // No query form the resolver accepts is left.
+            let rejected = rcode == Some(Rcode::FormatError)
+                && resolver.formerr.gave_up()
+                && ctx.formerr_policy == FormerrPolicy::Adapt;
+            let avoided = non_tunnel.is_some() && ctx.non_tunnel_policy == NonTunnelPolicy::Avoid;
+            if rcode == Some(Rcode::ServerFailure) {
+                resolver.health.record_servfail(resolver.addr, now);
+            } else if rejected || avoided {
+                resolver.health.record_failure(resolver.addr, now);
+            } else {
+                resolver.health.record_response(resolver.addr);
+            }
+            if let Some(hold_ms) = decode_poll_hold(buf) {
+                resolver.poll_hold_ms = Some(hold_ms);
+            }
+            let rtt_us = resolver.response_timer.record_response(response_id, now);
+            resolver.loss.record_response(response_id);
+            resolver.query_spans.answered(response_id, rcode);
+            if let Some(spike) =
+                rtt_us.and_then(|rtt_us| resolver.rtt_spike.record_response(rtt_us, now))
+            {
+                let (baseline_ms, rtt_ms) = (spike.baseline_us / 1_000, spike.rtt_us / 1_000);
+                warn!(
+                    "Resolver {} RTT jumped from {} ms to {} ms; holding back retransmits",
+                    redact(resolver.addr),
+                    baseline_ms,
+                    rtt_ms
+                );
+                ctx.events.push(TunnelEvent::RttSpike {
+                    resolver: redact(resolver.addr).to_string(),
+                    baseline_ms,
+                    rtt_ms,
+                });
+            }
+            if let (Some(rtt_us), Some(server_time_ms)) = (rtt_us, decode_server_time(buf)) {
+                ctx.clock
+                    .record(server_time_ms, unix_time_us(), rtt_us, now);
+            }
+        }
+    }
+    if let Some(rtype) = non_tunnel {
+        // Not a QUIC packet: handing it to picoquic could only be rejected or
+        // misread, so it is dropped here.
+        if let Some(resolver) = find_resolver_by_addr(ctx.resolvers, peer) {
+            resolver.debug.dns_responses = resolver.debug.dns_responses.saturating_add(1);
+            resolver.non_tunnel_responses = resolver.non_tunnel_responses.saturating_add(1);
+            if let Some(response_id) = response_id {
+                settle_late_poll(resolver, response_id);
+            }
+            if resolver.non_tunnel_responses == 1 {
+                let record_type = record_type_name(rtype);
+                warn!(
+                    "Resolver {} answered with a {} record instead of tunnel data; check that the domain is delegated to the tunnel server",
+                    redact(resolver.addr),
+                    record_type
+                );
+                ctx.events.push(TunnelEvent::NonTunnelResponse {
+                    resolver: redact(resolver.addr).to_string(),
+                    record_type,
+                });
+            }
+        }
+        return Ok(());
+    }
+    if let Some((payload, ttl)) = decode_response_with_ttl(buf) {
         let resolver_index = ctx
             .resolvers
             .iter()
             .position(|resolver| resolver.addr == peer);
+        if let Some(index) = resolver_index {
+            ctx.resolvers[index].response_ttl = Some(ttl);
+        }
         let mut peer_storage = socket_addr_to_storage(peer);
         let mut local_storage = if let Some(index) = resolver_index {
             ctx.resolvers[index]
@@ -71,12 +182,21 @@ pub(crate) fn handle_dns_response(
                 resolver.added = true;
             }
             resolver.debug.dns_responses = resolver.debug.dns_responses.saturating_add(1);
-            if let Some(response_id) = response_id {
-                if resolver.mode == ResolverMode::Authoritative {
-                    resolver.inflight_poll_ids.remove(&response_id);
+            resolver.tunnel_responses = resolver.tunnel_responses.saturating_add(1);
+            resolver.response_payload_bytes = resolver
+                .response_payload_bytes
+                .saturating_add(payload.len() as u64);
+            resolver.goodput.record(payload.len() as u64, current_time);
+            if let Some(recursion_available) = response_recursion_available(buf) {
+                if let Some(event) =
+                    check_resolver_mode(resolver, recursion_available, ctx.mode_mismatch_policy)
+                {
+                    ctx.events.push(event);
                 }
             }
-            if resolver.mode == ResolverMode::Recursive {
+            let late = response_id.is_some_and(|id| settle_late_poll(resolver, id));
+            // A late poll already had its replacement queued, so it must not add another.
+            if resolver.mode == ResolverMode::Recursive && !late {
                 resolver.pending_polls =
                     resolver.pending_polls.saturating_add(1).min(MAX_POLL_BURST);
             }
@@ -84,14 +204,39 @@ pub(crate) fn handle_dns_response(
     } else if let Some(response_id) = response_id {
         if let Some(resolver) = find_resolver_by_addr(ctx.resolvers, peer) {
             resolver.debug.dns_responses = resolver.debug.dns_responses.saturating_add(1);
-            if resolver.mode == ResolverMode::Authoritative {
-                resolver.inflight_poll_ids.remove(&response_id);
-            }
+            settle_late_poll(resolver, response_id);
         }
     }
     Ok(())
 }
 
+/// Mnemonic of a DNS record type, or `TYPEn` (RFC 3597) for the rest.
+fn record_type_name(rtype: u16) -> String {
+    match rtype {
+        1 => "A".to_string(),
+        2 => "NS".to_string(),
+        5 => "CNAME".to_string(),
+        6 => "SOA".to_string(),
+        15 => "MX".to_string(),
+        16 => "TXT".to_string(),
+        28 => "AAAA".to_string(),
+        65 => "HTTPS".to_string(),
+        other => format!("TYPE{}", other),
+    }
+}
+
+fn settle_late_poll(resolver: &mut ResolverState, response_id: u16) -> bool {
+    let late = settle_poll_response(
+        &mut resolver.inflight_poll_ids,
+        &mut resolver.expired_polls,
+        response_id,
+    );
+    if late {
+        resolver.late_poll_responses = resolver.late_poll_responses.saturating_add(1);
+    }
+    late
+}
+
 fn find_resolver_by_path_id(
     resolvers: &mut [ResolverState],
     path_id: libc::c_int,
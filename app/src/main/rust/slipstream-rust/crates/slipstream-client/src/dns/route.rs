@@ -0,0 +1,83 @@
+use std::io::{self, ErrorKind};
+
+// Sends in a row that found no route before a resolver counts as unreachable.
+const NO_ROUTE_SENDS: u32 = 3;
+
+/// Whether a send failed because the device has no route to the resolver.
+pub(crate) fn is_no_route(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::NetworkUnreachable | ErrorKind::HostUnreachable | ErrorKind::NetworkDown
+    )
+}
+
+/// Tracks whether sends to one resolver still find a route.
+///
+/// Sends that fail with no route are retried like any transient error, so on
+/// their own they never end a session. A run of them on every resolver means
+/// the device itself is offline rather than one path being down.
+#[derive(Debug, Default)]
+pub(crate) struct RouteTracker {
+    sent: bool,
+    no_route_sends: u32,
+}
+
+impl RouteTracker {
+    pub(crate) fn record(&mut self, sent: &io::Result<()>) {
+        self.sent = true;
+        match sent {
+            Ok(()) => self.no_route_sends = 0,
+            Err(err) if is_no_route(err) => {
+                self.no_route_sends = self.no_route_sends.saturating_add(1)
+            }
+            Err(_) => {}
+        }
+    }
+
+    pub(crate) fn is_unreachable(&self) -> bool {
+        self.no_route_sends >= NO_ROUTE_SENDS
+    }
+}
+
+/// True when every resolver sent to so far has lost its route.
+pub(crate) fn all_unreachable<'a>(routes: impl IntoIterator<Item = &'a RouteTracker>) -> bool {
+    let mut any = false;
+    for route in routes.into_iter().filter(|route| route.sent) {
+        if !route.is_unreachable() {
+            return false;
+        }
+        any = true;
+    }
+    any
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unreachable() -> io::Result<()> {
+        Err(io::Error::from(ErrorKind::NetworkUnreachable))
+    }
+
+    #[test]
+    fn offline_needs_every_resolver_without_a_route() {
+        let mut first = RouteTracker::default();
+        let mut second = RouteTracker::default();
+        let idle = RouteTracker::default();
+        assert!(!all_unreachable([&first, &second, &idle]));
+        for _ in 0..NO_ROUTE_SENDS {
+            first.record(&unreachable());
+        }
+        second.record(&Ok(()));
+        assert!(!all_unreachable([&first, &second, &idle]));
+        // Other errors neither count nor clear the run.
+        second.record(&Err(io::Error::from(ErrorKind::PermissionDenied)));
+        for _ in 0..NO_ROUTE_SENDS {
+            second.record(&unreachable());
+        }
+        // A resolver that never sent says nothing either way.
+        assert!(all_unreachable([&first, &second, &idle]));
+        first.record(&Ok(()));
+        assert!(!all_unreachable([&first, &second, &idle]));
+    }
+}
@@ -0,0 +1,283 @@
+//! Rides out sudden jumps in a resolver's round-trip time.
+//!
+//! When a resolver suddenly takes many times longer to answer, e.g. on a
+//! network handoff or a congested link, picoquic's retransmission timer still
+//! reflects the old RTT: every packet in flight times out and is sent again,
+//! and the spurious losses collapse the congestion window. [`RttSpikeDetector`]
+//! notices the jump and, while it lasts, keeps the path's timer above the RTT
+//! it sees, so the tunnel waits for the slow answers instead of flooding the
+//! resolver with retransmits.
+//!
+//! A spike is suspected as soon as picoquic declares a timer loss on a
+//! resolver that has been silent since, and confirmed by the first answer that
+//! took several times the usual RTT. Answers at the usual RTT end it.
+
+// Response times needed before anything counts as a jump.
+const BASELINE_SAMPLES: u32 = 8;
+// A response this many times the baseline RTT, and at least SPIKE_MIN_JUMP_US
+// slower, is a spike.
+const SPIKE_FACTOR: u64 = 3;
+const SPIKE_MIN_JUMP_US: u64 = 200_000;
+// A confirmed spike holds for this many spike RTTs past its first slow answer.
+const SPIKE_HOLD_RTTS: u64 = 4;
+// A silent resolver stops holding back retransmits after this long; by then it
+// is more likely down than slow.
+const SPIKE_MAX_SILENCE_US: u64 = 5_000_000;
+
+/// A confirmed jump in a resolver's response time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RttSpike {
+    pub(crate) baseline_us: u64,
+    pub(crate) rtt_us: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpikeState {
+    None,
+    /// A timer loss fired while the resolver was silent.
+    Suspected,
+    /// A slow answer arrived; holds until `until` unless answers return to
+    /// the usual RTT of `baseline_us` first.
+    Confirmed {
+        baseline_us: u64,
+        rtt_us: u64,
+        until: u64,
+    },
+}
+
+pub(crate) struct RttSpikeDetector {
+    /// Smoothed response time, like QUIC's smoothed RTT.
+    baseline_us: u64,
+    samples: u32,
+    last_response_at: u64,
+    timer_losses: u64,
+    state: SpikeState,
+}
+
+impl RttSpikeDetector {
+    pub(crate) fn new() -> Self {
+        Self {
+            baseline_us: 0,
+            samples: 0,
+            last_response_at: 0,
+            timer_losses: 0,
+            state: SpikeState::None,
+        }
+    }
+
+    /// Adds the round-trip time of one answered query. Returns the spike when
+    /// this answer is the first to confirm one.
+    pub(crate) fn record_response(&mut self, rtt_us: u64, now: u64) -> Option<RttSpike> {
+        self.last_response_at = now;
+        let baseline_us = self.baseline_us;
+        let is_spike = self.samples >= BASELINE_SAMPLES && is_jump(baseline_us, rtt_us);
+        // Spike samples count too, so a lasting change becomes the new baseline.
+        self.baseline_us = if self.samples == 0 {
+            rtt_us
+        } else {
+            (baseline_us.saturating_mul(7) + rtt_us) / 8
+        };
+        self.samples = self.samples.saturating_add(1);
+        if let SpikeState::Confirmed {
+            baseline_us: spike_baseline_us,
+            rtt_us: held_rtt_us,
+            until,
+        } = self.state
+        {
+            if now < until {
+                self.state = if is_jump(spike_baseline_us, rtt_us) {
+                    SpikeState::Confirmed {
+                        baseline_us: spike_baseline_us,
+                        rtt_us: held_rtt_us.max(rtt_us),
+                        until,
+                    }
+                } else {
+                    SpikeState::None
+                };
+                return None;
+            }
+        }
+        if !is_spike {
+            self.state = SpikeState::None;
+            return None;
+        }
+        self.state = SpikeState::Confirmed {
+            baseline_us,
+            rtt_us,
+            until: now.saturating_add(rtt_us.saturating_mul(SPIKE_HOLD_RTTS)),
+        };
+        Some(RttSpike {
+            baseline_us,
+            rtt_us,
+        })
+    }
+
+    /// Feeds the path's running count of losses declared by the
+    /// retransmission timer.
+    pub(crate) fn record_timer_losses(&mut self, timer_losses: u64, now: u64) {
+        let fired = timer_losses > self.timer_losses;
+        self.timer_losses = timer_losses;
+        if fired
+            && !self.holding(now)
+            && self.samples >= BASELINE_SAMPLES
+            && now.saturating_sub(self.last_response_at) < SPIKE_MAX_SILENCE_US
+        {
+            self.state = SpikeState::Suspected;
+        }
+    }
+
+    /// Lowest retransmission timer for the path while a spike lasts.
+    pub(crate) fn rto_floor_us(&self, now: u64) -> Option<u64> {
+        match self.state {
+            SpikeState::None => None,
+            SpikeState::Suspected => {
+                // Twice the silence, so nothing sent since the last answer times out.
+                let silence = now.saturating_sub(self.last_response_at);
+                (silence < SPIKE_MAX_SILENCE_US).then(|| silence.saturating_mul(2))
+            }
+            SpikeState::Confirmed { rtt_us, until, .. } => {
+                (now < until).then(|| rtt_us.saturating_mul(2))
+            }
+        }
+    }
+
+    pub(crate) fn holding(&self, now: u64) -> bool {
+        self.rto_floor_us(now).is_some()
+    }
+}
+
+fn is_jump(baseline_us: u64, rtt_us: u64) -> bool {
+    rtt_us >= baseline_us.saturating_mul(SPIKE_FACTOR)
+        && rtt_us >= baseline_us.saturating_add(SPIKE_MIN_JUMP_US)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000;
+
+    fn warmed_up(now: u64) -> RttSpikeDetector {
+        let mut detector = RttSpikeDetector::new();
+        for index in 0..BASELINE_SAMPLES as u64 {
+            detector.record_response(50 * MS, now - (BASELINE_SAMPLES as u64 - index) * MS);
+        }
+        detector
+    }
+
+    /// picoquic's retransmission timer: smoothed RTT plus three variances,
+    /// recomputed on every sample.
+    struct PathTimer {
+        smoothed_us: u64,
+        variant_us: u64,
+        timer_us: u64,
+    }
+
+    impl PathTimer {
+        fn sample(&mut self, rtt_us: u64) {
+            let delta = self.smoothed_us.abs_diff(rtt_us);
+            self.variant_us = (3 * self.variant_us + delta) / 4;
+            self.smoothed_us = (7 * self.smoothed_us + rtt_us) / 8;
+            self.timer_us = self.smoothed_us + 3 * self.variant_us;
+        }
+    }
+
+    /// Sends a packet every 20 ms; the RTT jumps from 50 ms to 800 ms at one
+    /// second. Returns the packets whose timer fired before their answer came.
+    fn spurious_retransmits(mut detector: Option<&mut RttSpikeDetector>) -> (u64, Vec<RttSpike>) {
+        let mut timer = PathTimer {
+            smoothed_us: 50 * MS,
+            variant_us: 5 * MS,
+            timer_us: 65 * MS,
+        };
+        // (sent, answered, declared lost)
+        let mut inflight: Vec<(u64, u64, bool)> = Vec::new();
+        let (mut timer_losses, mut spikes) = (0, Vec::new());
+        for now in (0..4_000 * MS).step_by(5 * MS as usize) {
+            if now % (20 * MS) == 0 {
+                let jitter = (now / (20 * MS)) % 3 * 5 * MS;
+                let rtt = if now < 1_000 * MS {
+                    45 * MS + jitter
+                } else {
+                    800 * MS
+                };
+                inflight.push((now, now + rtt, false));
+            }
+            for &(sent, answered, _) in inflight.iter().filter(|(_, answered, _)| *answered == now)
+            {
+                timer.sample(answered - sent);
+                if let Some(detector) = detector.as_deref_mut() {
+                    spikes.extend(detector.record_response(answered - sent, now));
+                }
+            }
+            inflight.retain(|(_, answered, _)| *answered > now);
+            for packet in inflight.iter_mut().filter(|packet| !packet.2) {
+                let floor = detector
+                    .as_deref()
+                    .and_then(|detector| detector.rto_floor_us(now))
+                    .unwrap_or(0);
+                if now >= packet.0 + timer.timer_us.max(floor) {
+                    packet.2 = true;
+                    timer_losses += 1;
+                    if let Some(detector) = detector.as_deref_mut() {
+                        detector.record_timer_losses(timer_losses, now);
+                    }
+                }
+            }
+        }
+        (timer_losses, spikes)
+    }
+
+    #[test]
+    fn retransmits_do_not_storm_through_an_rtt_jump() {
+        let (unguarded, _) = spurious_retransmits(None);
+        assert!(
+            unguarded > 20,
+            "the jump should storm unguarded: {}",
+            unguarded
+        );
+
+        // The first second of answers makes the baseline.
+        let mut detector = RttSpikeDetector::new();
+        let (guarded, spikes) = spurious_retransmits(Some(&mut detector));
+        assert!(guarded <= 2, "{} spurious retransmits", guarded);
+        assert_eq!(spikes.len(), 1);
+        assert_eq!(spikes[0].rtt_us, 800 * MS);
+        assert!(spikes[0].baseline_us < 60 * MS);
+    }
+
+    #[test]
+    fn usual_answers_end_the_spike() {
+        let mut detector = warmed_up(10_000 * MS);
+        assert_eq!(detector.record_response(60 * MS, 10_000 * MS), None);
+        let spike = detector.record_response(900 * MS, 10_100 * MS).unwrap();
+        assert_eq!(spike.rtt_us, 900 * MS);
+        assert_eq!(detector.rto_floor_us(10_200 * MS), Some(1_800 * MS));
+        // A second slow answer belongs to the same spike.
+        assert_eq!(detector.record_response(850 * MS, 10_300 * MS), None);
+        assert!(detector.holding(13_000 * MS));
+        detector.record_response(50 * MS, 10_400 * MS);
+        assert!(!detector.holding(10_400 * MS));
+    }
+
+    #[test]
+    fn a_confirmed_spike_expires_on_its_own() {
+        let mut detector = warmed_up(10_000 * MS);
+        detector.record_response(500 * MS, 10_000 * MS).unwrap();
+        assert!(detector.holding(11_999 * MS));
+        assert!(!detector.holding(12_000 * MS));
+    }
+
+    #[test]
+    fn silence_holds_retransmits_only_for_a_while() {
+        let mut detector = warmed_up(10_000 * MS);
+        // Timer losses before the baseline or while answers flow are left to picoquic.
+        let mut cold = RttSpikeDetector::new();
+        cold.record_timer_losses(1, 10_000 * MS);
+        assert!(!cold.holding(10_000 * MS));
+
+        detector.record_timer_losses(3, 10_100 * MS);
+        assert_eq!(detector.rto_floor_us(10_500 * MS), Some(1_002 * MS));
+        assert!(!detector.holding(10_000 * MS + SPIKE_MAX_SILENCE_US));
+    }
+}
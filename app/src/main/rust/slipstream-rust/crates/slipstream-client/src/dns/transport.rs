@@ -0,0 +1,244 @@
+use slipstream_ffi::{CustomQueryHook, QueryInfo, QueryVerdict, ResolverTransport};
+use std::future::poll_fn;
+use std::io;
+use std::net::SocketAddr;
+use std::task::{Context, Poll, Waker};
+use tokio::io::ReadBuf;
+use tokio::net::UdpSocket as TokioUdpSocket;
+
+/// The built-in transport: one UDP socket shared by all resolvers.
+pub(crate) struct UdpTransport {
+    socket: TokioUdpSocket,
+}
+
+impl UdpTransport {
+    pub(crate) fn new(socket: TokioUdpSocket) -> Self {
+        Self { socket }
+    }
+
+    pub(crate) fn socket(&self) -> &TokioUdpSocket {
+        &self.socket
+    }
+}
+
+impl ResolverTransport for UdpTransport {
+    fn poll_send(
+        &self,
+        cx: &mut Context<'_>,
+        message: &[u8],
+        resolver: SocketAddr,
+    ) -> Poll<io::Result<()>> {
+        self.socket
+            .poll_send_to(cx, message, resolver)
+            .map_ok(|_| ())
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>> {
+        let mut read = ReadBuf::new(buf);
+        self.socket
+            .poll_recv_from(cx, &mut read)
+            .map_ok(|peer| (read.filled().len(), peer))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+pub(crate) async fn send_query(
+    transport: &dyn ResolverTransport,
+    message: &[u8],
+    resolver: SocketAddr,
+) -> io::Result<()> {
+    poll_fn(|cx| transport.poll_send(cx, message, resolver)).await
+}
+
+// Drop verdicts change what reaches the network, so they stay out of releases.
+const HOOK_MAY_DROP: bool = cfg!(any(test, feature = "test-hooks"));
+
+/// Shows `query` to the query hook, if any, then sends `message` to its resolver.
+/// A query the hook drops is reported as sent, like one lost on the way.
+pub(crate) async fn send_inspected_query(
+    transport: &dyn ResolverTransport,
+    hook: Option<&CustomQueryHook>,
+    query: &QueryInfo<'_>,
+    message: &[u8],
+) -> io::Result<()> {
+    if let Some(hook) = hook {
+        if hook.hook().inspect(query) == QueryVerdict::Drop && HOOK_MAY_DROP {
+            return Ok(());
+        }
+    }
+    send_query(transport, message, query.resolver).await
+}
+
+pub(crate) async fn recv_response(
+    transport: &dyn ResolverTransport,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr)> {
+    poll_fn(|cx| transport.poll_recv(cx, buf)).await
+}
+
+/// Takes a response that is already waiting; `WouldBlock` when there is none.
+pub(crate) fn try_recv_response(
+    transport: &dyn ResolverTransport,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr)> {
+    match transport.poll_recv(&mut Context::from_waker(Waker::noop()), buf) {
+        Poll::Ready(result) => result,
+        Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slipstream_ffi::QueryHook;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    /// Answers every query by echoing it back from the resolver it was sent to.
+    #[derive(Default)]
+    struct EchoTransport {
+        responses: Mutex<VecDeque<(Vec<u8>, SocketAddr)>>,
+        waker: Mutex<Option<Waker>>,
+    }
+
+    impl ResolverTransport for EchoTransport {
+        fn poll_send(
+            &self,
+            _cx: &mut Context<'_>,
+            message: &[u8],
+            resolver: SocketAddr,
+        ) -> Poll<io::Result<()>> {
+            self.responses
+                .lock()
+                .unwrap()
+                .push_back((message.to_vec(), resolver));
+            if let Some(waker) = self.waker.lock().unwrap().take() {
+                waker.wake();
+            }
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_recv(
+            &self,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<(usize, SocketAddr)>> {
+            let Some((message, resolver)) = self.responses.lock().unwrap().pop_front() else {
+                *self.waker.lock().unwrap() = Some(cx.waker().clone());
+                return Poll::Pending;
+            };
+            let len = message.len().min(buf.len());
+            buf[..len].copy_from_slice(&message[..len]);
+            Poll::Ready(Ok((len, resolver)))
+        }
+
+        fn local_addr(&self) -> io::Result<SocketAddr> {
+            Ok("0.0.0.0:0".parse().unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn custom_and_builtin_transports_carry_queries() {
+        let resolver: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let echo = EchoTransport::default();
+        let mut buf = [0u8; 64];
+        assert_eq!(
+            try_recv_response(&echo, &mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        send_query(&echo, b"query", resolver).await.unwrap();
+        assert_eq!(recv_response(&echo, &mut buf).await.unwrap(), (5, resolver));
+        assert_eq!(&buf[..5], b"query");
+
+        // The UDP transport keeps the same contract against a real socket.
+        let peer = TokioUdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let udp = UdpTransport::new(TokioUdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let peer_addr = peer.local_addr().unwrap();
+        send_query(&udp, b"ping", peer_addr).await.unwrap();
+        let (len, from) = peer.recv_from(&mut buf).await.unwrap();
+        assert_eq!(
+            (&buf[..len], from),
+            (&b"ping"[..], udp.local_addr().unwrap())
+        );
+        peer.send_to(b"pong", from).await.unwrap();
+        assert_eq!(recv_response(&udp, &mut buf).await.unwrap(), (4, peer_addr));
+        assert_eq!(&buf[..4], b"pong");
+    }
+
+    /// Records every query and loses every other one.
+    struct LossyHook {
+        seen: Arc<Mutex<Vec<(u16, usize, usize)>>>,
+    }
+
+    impl QueryHook for LossyHook {
+        fn inspect(&self, query: &QueryInfo<'_>) -> QueryVerdict {
+            assert!(!query.poll);
+            let mut seen = self.seen.lock().unwrap();
+            seen.push((query.id, query.qname.len(), query.size));
+            if seen.len().is_multiple_of(2) {
+                QueryVerdict::Drop
+            } else {
+                QueryVerdict::Send
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn query_hook_sees_every_query_and_can_drop_them() {
+        let resolver: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let echo = EchoTransport::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook = CustomQueryHook::new(Box::new(LossyHook { seen: seen.clone() }));
+        for id in 1..=4u16 {
+            let message = vec![id as u8; 10 + id as usize];
+            let query = QueryInfo {
+                resolver,
+                id,
+                qname: "a.t.example.com.",
+                qtype: 16,
+                size: message.len(),
+                poll: false,
+            };
+            send_inspected_query(&echo, Some(&hook), &query, &message)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [(1, 16, 11), (2, 16, 12), (3, 16, 13), (4, 16, 14)]
+        );
+        // Only the first and third query reached the resolver.
+        let mut buf = [0u8; 64];
+        assert_eq!(
+            recv_response(&echo, &mut buf).await.unwrap(),
+            (11, resolver)
+        );
+        assert_eq!(
+            recv_response(&echo, &mut buf).await.unwrap(),
+            (13, resolver)
+        );
+        assert!(try_recv_response(&echo, &mut buf).is_err());
+
+        // Without a hook every query is sent.
+        let query = QueryInfo {
+            resolver,
+            id: 5,
+            qname: "b.t.example.com.",
+            qtype: 16,
+            size: 3,
+            poll: true,
+        };
+        send_inspected_query(&echo, None, &query, b"abc")
+            .await
+            .unwrap();
+        assert_eq!(recv_response(&echo, &mut buf).await.unwrap(), (3, resolver));
+    }
+}
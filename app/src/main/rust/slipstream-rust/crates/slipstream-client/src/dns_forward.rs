@@ -0,0 +1,255 @@
+//! Local DNS forwarder that resolves queries on the far side of the tunnel.
+//!
+//! Apps that send plain UDP DNS would otherwise leak their lookups to the local
+//! network. The forwarder answers them on a UDP port and carries each query as
+//! DNS over TCP through the client's own listener: a SOCKS5 CONNECT to the
+//! upstream resolver, then the length-prefixed query. The upstream is reached
+//! from the tunnel server, so lookups see the server's view of DNS.
+
+use crate::error::ClientError;
+use crate::socks5::{self, ConnectReply};
+use slipstream_core::{parse_host_port, AddressKind, HostPort};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream as TokioTcpStream, UdpSocket as TokioUdpSocket};
+use tokio::sync::Semaphore;
+use tokio::time::{timeout, Duration};
+use tracing::{debug, info};
+
+pub const DEFAULT_DNS_LISTEN_HOST: &str = "127.0.0.1";
+pub const DEFAULT_DNS_UPSTREAM: &str = "1.1.1.1:53";
+
+// Largest DNS message; answers over TCP may exceed the classic 512 bytes.
+const DNS_MESSAGE_MAX_BYTES: usize = 65_535;
+// Queries resolved at once; further queries are dropped and the stub retries.
+const DNS_FORWARD_MAX_INFLIGHT: usize = 64;
+const DNS_FORWARD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Username and password for the SOCKS5 proxy behind the tunnel.
+#[derive(Clone, Debug)]
+pub(crate) struct SocksAuth {
+    pub(crate) username: String,
+    pub(crate) password: String,
+}
+
+struct Forwarder {
+    tunnel_addr: SocketAddr,
+    upstream: HostPort,
+    auth: Option<SocksAuth>,
+}
+
+/// Binds the DNS port and forwards queries through the listener at `tunnel_addr`.
+pub(crate) async fn spawn_dns_forwarder(
+    host: &str,
+    port: u16,
+    tunnel_addr: SocketAddr,
+    upstream: &str,
+    auth: Option<SocksAuth>,
+) -> Result<(), ClientError> {
+    let upstream = parse_host_port(upstream, 53, AddressKind::Target)
+        .map_err(|err| ClientError::new(format!("Invalid DNS upstream: {}", err)))?;
+    let socket = TokioUdpSocket::bind((host, port)).await.map_err(|err| {
+        ClientError::new(format!(
+            "Failed to bind DNS forwarder on {}:{}: {}",
+            host, port, err
+        ))
+    })?;
+    let local_addr = socket
+        .local_addr()
+        .map_err(|err| ClientError::new(err.to_string()))?;
+    info!(
+        "DNS forwarder listening on {}, resolving via {}:{} through the tunnel",
+        local_addr, upstream.host, upstream.port
+    );
+    let forwarder = Forwarder {
+        tunnel_addr: loopback_for(tunnel_addr),
+        upstream,
+        auth,
+    };
+    tokio::spawn(run_dns_forwarder(Arc::new(socket), Arc::new(forwarder)));
+    Ok(())
+}
+
+/// The tunnel listener as seen from this host; wildcard binds map to loopback.
+fn loopback_for(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), addr.port())
+        }
+        IpAddr::V6(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), addr.port())
+        }
+        _ => addr,
+    }
+}
+
+async fn run_dns_forwarder(socket: Arc<TokioUdpSocket>, forwarder: Arc<Forwarder>) {
+    let inflight = Arc::new(Semaphore::new(DNS_FORWARD_MAX_INFLIGHT));
+    let mut buf = vec![0u8; DNS_MESSAGE_MAX_BYTES];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(err) => {
+                debug!("DNS forwarder receive failed: {}", err);
+                continue;
+            }
+        };
+        let Ok(permit) = Arc::clone(&inflight).try_acquire_owned() else {
+            debug!("DNS forwarder busy; dropping query from {}", peer);
+            continue;
+        };
+        let query = buf[..len].to_vec();
+        let socket = Arc::clone(&socket);
+        let forwarder = Arc::clone(&forwarder);
+        tokio::spawn(async move {
+            let _permit = permit;
+            match timeout(DNS_FORWARD_TIMEOUT, forwarder.resolve(&query)).await {
+                Ok(Ok(answer)) => {
+                    if let Err(err) = socket.send_to(&answer, peer).await {
+                        debug!("DNS forwarder reply to {} failed: {}", peer, err);
+                    }
+                }
+                Ok(Err(err)) => debug!("DNS forwarder query failed: {}", err),
+                Err(_) => debug!("DNS forwarder query timed out"),
+            }
+        });
+    }
+}
+
+impl Forwarder {
+    async fn resolve(&self, query: &[u8]) -> Result<Vec<u8>, String> {
+        let mut stream = TokioTcpStream::connect(self.tunnel_addr)
+            .await
+            .map_err(|err| format!("connect to tunnel listener: {}", err))?;
+        let _ = stream.set_nodelay(true);
+        forward_query(&mut stream, &self.upstream, self.auth.as_ref(), query).await
+    }
+}
+
+/// Resolves `query` over `stream`, which leads to the SOCKS5 proxy behind the tunnel.
+async fn forward_query<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    upstream: &HostPort,
+    auth: Option<&SocksAuth>,
+    query: &[u8],
+) -> Result<Vec<u8>, String> {
+    let io_err = |err: std::io::Error| format!("DNS over SOCKS5 failed: {}", err);
+    let query_len = u16::try_from(query.len()).map_err(|_| "DNS query is too long")?;
+    socks5_connect(stream, upstream, auth).await?;
+
+    let mut framed = Vec::with_capacity(query.len() + 2);
+    framed.extend_from_slice(&query_len.to_be_bytes());
+    framed.extend_from_slice(query);
+    stream.write_all(&framed).await.map_err(io_err)?;
+
+    let mut len = [0u8; 2];
+    stream.read_exact(&mut len).await.map_err(io_err)?;
+    let mut answer = vec![0u8; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut answer).await.map_err(io_err)?;
+    Ok(answer)
+}
+
+async fn socks5_connect<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    upstream: &HostPort,
+    auth: Option<&SocksAuth>,
+) -> Result<(), String> {
+    let io_err = |err: std::io::Error| format!("SOCKS5 handshake failed: {}", err);
+    stream
+        .write_all(socks5::greeting(auth.is_some()))
+        .await
+        .map_err(io_err)?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await.map_err(io_err)?;
+    if let (true, Some(auth)) = (socks5::parse_method(reply, auth.is_some())?, auth) {
+        let login = socks5::login_request(&auth.username, &auth.password)?;
+        stream.write_all(&login).await.map_err(io_err)?;
+        stream.read_exact(&mut reply).await.map_err(io_err)?;
+        socks5::parse_login(reply)?;
+    }
+
+    let request = socks5::connect_request(&upstream.host, upstream.port)?;
+    stream.write_all(&request).await.map_err(io_err)?;
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await.map_err(io_err)?;
+    let bound_len = match socks5::parse_connect_reply(header)? {
+        ConnectReply::Failed(reply) => {
+            return Err(format!("SOCKS5 CONNECT failed (reply {})", reply));
+        }
+        ConnectReply::Bound(len) => len,
+        ConnectReply::BoundName => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).await.map_err(io_err)?;
+            len[0] as usize + 2
+        }
+    };
+    let mut bound = vec![0u8; bound_len];
+    stream.read_exact(&mut bound).await.map_err(io_err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::duplex;
+
+    #[test]
+    fn query_goes_through_socks5_as_dns_over_tcp() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            let (mut client, mut proxy) = duplex(1024);
+            let upstream = parse_host_port("9.9.9.9:53", 53, AddressKind::Target).unwrap();
+            let auth = SocksAuth {
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            };
+            let query = [0x12, 0x34, 0x01, 0x00, 0x00, 0x01];
+            let answer = [0x12, 0x34, 0x81, 0x80, 0x00, 0x01, 0x00, 0x01];
+
+            let server = tokio::spawn(async move {
+                let mut greeting = [0u8; 4];
+                proxy.read_exact(&mut greeting).await.unwrap();
+                assert_eq!(greeting, [0x05, 0x02, 0x00, 0x02]);
+                proxy.write_all(&[0x05, 0x02]).await.unwrap();
+                let mut login = [0u8; 11];
+                proxy.read_exact(&mut login).await.unwrap();
+                assert_eq!(&login, b"\x01\x04user\x04pass");
+                proxy.write_all(&[0x01, 0x00]).await.unwrap();
+                let mut connect = [0u8; 10];
+                proxy.read_exact(&mut connect).await.unwrap();
+                assert_eq!(connect, [0x05, 0x01, 0x00, 0x01, 9, 9, 9, 9, 0, 53]);
+                proxy
+                    .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                    .await
+                    .unwrap();
+                let mut framed = [0u8; 8];
+                proxy.read_exact(&mut framed).await.unwrap();
+                assert_eq!(framed[..2], [0, 6]);
+                assert_eq!(framed[2..], query);
+                proxy.write_all(&[0, answer.len() as u8]).await.unwrap();
+                proxy.write_all(&answer).await.unwrap();
+            });
+
+            let got = forward_query(&mut client, &upstream, Some(&auth), &query)
+                .await
+                .expect("forward query");
+            assert_eq!(got, answer);
+            server.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn wildcard_listener_maps_to_loopback() {
+        let v6: SocketAddr = "[::]:5201".parse().unwrap();
+        let v4: SocketAddr = "0.0.0.0:5201".parse().unwrap();
+        let bound: SocketAddr = "192.0.2.1:5201".parse().unwrap();
+        assert_eq!(loopback_for(v6), "[::1]:5201".parse().unwrap());
+        assert_eq!(loopback_for(v4), "127.0.0.1:5201".parse().unwrap());
+        assert_eq!(loopback_for(bound), bound);
+    }
+}
@@ -0,0 +1,66 @@
+//! TLS of the SOCKS edge's DNS-over-HTTPS fallback.
+//!
+//! The Android SOCKS bridge sends DNS it cannot forward over TCP to a DoH
+//! server and only offers TLS versions at or above its configured minimum. It
+//! reports each handshake here, so the session snapshot shows the version in
+//! use and how often a server could not meet the minimum.
+
+use std::sync::Mutex;
+
+#[derive(Debug, Default)]
+struct DohTls {
+    version: Option<String>,
+    below_minimum: u64,
+}
+
+static DOH_TLS: Mutex<DohTls> = Mutex::new(DohTls {
+    version: None,
+    below_minimum: 0,
+});
+
+/// Records the TLS version, e.g. `TLSv1.3`, of a successful DoH handshake.
+// Called by the Android bridge and library callers; the CLI has no SOCKS edge.
+#[allow(dead_code)]
+pub fn record_doh_handshake(version: &str) {
+    DOH_TLS.lock().expect("lock DoH TLS").version = Some(version.to_string());
+}
+
+/// Records a DoH handshake that failed because the server, or the device,
+/// could not negotiate the minimum TLS version.
+// Called by the Android bridge and library callers; the CLI has no SOCKS edge.
+#[allow(dead_code)]
+pub fn record_doh_below_minimum() {
+    DOH_TLS.lock().expect("lock DoH TLS").below_minimum += 1;
+}
+
+/// Forgets what earlier handshakes reported, e.g. when the bridge restarts.
+// Called by the Android bridge and library callers; the CLI has no SOCKS edge.
+#[allow(dead_code)]
+pub fn reset_doh_tls() {
+    *DOH_TLS.lock().expect("lock DoH TLS") = DohTls::default();
+}
+
+/// Negotiated version of the last successful handshake and the handshakes
+/// below the minimum, for the session snapshot.
+pub(crate) fn doh_tls() -> (Option<String>, u64) {
+    let tls = DOH_TLS.lock().expect("lock DoH TLS");
+    (tls.version.clone(), tls.below_minimum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_the_last_version_and_failures_below_the_minimum() {
+        reset_doh_tls();
+        assert_eq!(doh_tls(), (None, 0));
+        record_doh_handshake("TLSv1.2");
+        record_doh_handshake("TLSv1.3");
+        record_doh_below_minimum();
+        record_doh_below_minimum();
+        assert_eq!(doh_tls(), (Some("TLSv1.3".to_string()), 2));
+        reset_doh_tls();
+        assert_eq!(doh_tls(), (None, 0));
+    }
+}
@@ -1,16 +1,52 @@
 use std::fmt;
 
+/// Failures that callers may want to tell apart; everything else is `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientErrorKind {
+    /// The TCP listen host is not a literal IP address.
+    InvalidListenHost,
+    /// A resolver index past the end of the configured resolvers.
+    InvalidResolverIndex,
+    Other,
+}
+
 #[derive(Debug)]
 pub struct ClientError {
+    kind: ClientErrorKind,
     message: String,
 }
 
 impl ClientError {
     pub(crate) fn new(message: impl Into<String>) -> Self {
         Self {
+            kind: ClientErrorKind::Other,
             message: message.into(),
         }
     }
+
+    pub(crate) fn invalid_listen_host(host: &str) -> Self {
+        Self {
+            kind: ClientErrorKind::InvalidListenHost,
+            message: format!(
+                "Invalid listen host {:?}: expected a literal IP address such as 127.0.0.1, ::1, 0.0.0.0 or ::",
+                host
+            ),
+        }
+    }
+
+    pub(crate) fn invalid_resolver_index(index: usize, resolvers: usize) -> Self {
+        Self {
+            kind: ClientErrorKind::InvalidResolverIndex,
+            message: format!(
+                "Resolver index {} is out of range; {} resolvers are configured",
+                index, resolvers
+            ),
+        }
+    }
+
+    pub fn kind(&self) -> ClientErrorKind {
+        self.kind
+    }
 }
 
 impl fmt::Display for ClientError {
@@ -0,0 +1,374 @@
+//! Tunnel state changes as events for UIs and supervisors.
+//!
+//! The runtime feeds the current condition into an [`EventDebouncer`] on every
+//! loop turn. Losses only become events once they outlast the grace window, so
+//! a brief blip does not flash "disconnected" in a UI.
+//!
+//! Events go to the Android callback and, when set, to an event stream as
+//! newline-delimited JSON for supervisors of the CLI.
+
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Grace window used when none is configured.
+pub const DEFAULT_EVENT_GRACE_MS: u64 = 3_000;
+
+/// Something wrong with the tunnel that may warrant an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TunnelCondition {
+    /// The connection was lost and no new one is ready yet.
+    Disconnected,
+    /// Connected, but no resolver is answering.
+    Stalled,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TunnelEvent {
+    /// The first connection of the run is ready.
+    Connected,
+    Disconnected,
+    Stalled,
+    /// A condition cleared. `reported` is false when it cleared within the
+    /// grace window, so no `disconnected` or `stalled` event went out for it.
+    Recovered {
+        from: TunnelCondition,
+        after_ms: u64,
+        reported: bool,
+    },
+    /// The connection was lost and the client waits `retry_in_ms` before
+    /// reconnecting. `attempt` counts the retries of this outage, starting
+    /// at 1.
+    Reconnecting {
+        attempt: u32,
+        retry_in_ms: u64,
+    },
+    /// The client stopped reconnecting after `attempts` reconnects over
+    /// `after_ms` since the connection was lost, and exits.
+    GaveUp {
+        attempts: u32,
+        after_ms: u64,
+    },
+    /// The client sent its last query allowed by the lifetime query limit,
+    /// `queries` in all, and exits.
+    LimitReached {
+        queries: u64,
+    },
+    /// A new connection replaced the previous one, e.g. after a reconnect.
+    /// Both ids are session ids as found in client and server logs.
+    SessionRotated {
+        old: String,
+        new: String,
+    },
+    /// A resolver answered with an ordinary record, such as a website's
+    /// address, instead of tunnel data; the domain or resolver is likely
+    /// misconfigured. Sent once per resolver and connection.
+    NonTunnelResponse {
+        resolver: String,
+        record_type: String,
+    },
+    /// A resolver answers unlike its configured mode, e.g. an `--authoritative`
+    /// entry that is a recursive resolver. `adapted` is true when the path was
+    /// moved to `observed`. Sent once per resolver and connection.
+    ResolverModeMismatch {
+        resolver: String,
+        configured: String,
+        observed: String,
+        adapted: bool,
+    },
+    /// A resolver kept answering FORMERR to tunnel queries sent as
+    /// `rejected`, e.g. `TXT/CH with cookie`. `fallback` is the plainer form
+    /// tried next, `None` when the queries stay as they are.
+    ResolverRejectsEncoding {
+        resolver: String,
+        rejected: String,
+        fallback: Option<String>,
+    },
+    /// A resolver's response time jumped from its usual `baseline_ms` to
+    /// `rtt_ms`; retransmits on its path are held back until it settles.
+    RttSpike {
+        resolver: String,
+        baseline_ms: u64,
+        rtt_ms: u64,
+    },
+}
+
+impl TunnelEvent {
+    /// One-line JSON form, e.g. `{"event":"stalled"}`.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+static EVENT_STREAM: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Also write every tunnel event to `stream`, one JSON object per line.
+pub fn set_event_stream(stream: Box<dyn Write + Send>) {
+    if let Ok(mut current) = EVENT_STREAM.lock() {
+        *current = Some(stream);
+    }
+}
+
+/// Writes one event to the event stream, if any. A stream that fails, e.g.
+/// because the reader went away, is dropped.
+pub(crate) fn write_event_line(json: &str) {
+    let Ok(mut current) = EVENT_STREAM.lock() else {
+        return;
+    };
+    let Some(stream) = current.as_mut() else {
+        return;
+    };
+    if let Err(err) = writeln!(stream, "{}", json).and_then(|_| stream.flush()) {
+        tracing::warn!("Closing the event stream after a write error: {}", err);
+        *current = None;
+    }
+}
+
+/// Holds back `Disconnected` and `Stalled` until the condition has lasted
+/// `grace_us`.
+pub(crate) struct EventDebouncer {
+    grace_us: u64,
+    connected: bool,
+    /// Current condition, when it started, and whether it was reported.
+    pending: Option<(TunnelCondition, u64, bool)>,
+}
+
+impl EventDebouncer {
+    pub(crate) fn new(grace_ms: u64) -> Self {
+        Self {
+            grace_us: grace_ms.saturating_mul(1_000),
+            connected: false,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn set_grace_ms(&mut self, grace_ms: u64) {
+        self.grace_us = grace_ms.saturating_mul(1_000);
+    }
+
+    /// Feeds the condition as of `now`; returns the event to emit, if any.
+    pub(crate) fn update(
+        &mut self,
+        condition: Option<TunnelCondition>,
+        now: u64,
+    ) -> Option<TunnelEvent> {
+        if !self.connected {
+            // Nothing can be lost before the first connection.
+            self.connected = condition.is_none();
+            return self.connected.then_some(TunnelEvent::Connected);
+        }
+        let Some(condition) = condition else {
+            let (from, since, reported) = self.pending.take()?;
+            return Some(TunnelEvent::Recovered {
+                from,
+                after_ms: now.saturating_sub(since) / 1_000,
+                reported,
+            });
+        };
+        let (current, since, reported) = self.pending.get_or_insert((condition, now, false));
+        if *current != condition {
+            // A stall that turns into a disconnect is one outage; its clock keeps running.
+            *current = condition;
+            *reported = false;
+        }
+        if *reported || now.saturating_sub(*since) < self.grace_us {
+            return None;
+        }
+        *reported = true;
+        Some(match condition {
+            TunnelCondition::Disconnected => TunnelEvent::Disconnected,
+            TunnelCondition::Stalled => TunnelEvent::Stalled,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRACE_MS: u64 = 2_000;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn transient_blip_recovers_without_a_disconnect_event() {
+        let mut events = EventDebouncer::new(GRACE_MS);
+        assert_eq!(events.update(None, 0), Some(TunnelEvent::Connected));
+        assert_eq!(events.update(None, 100_000), None);
+
+        // Lost for half a second, then back: only a silent recovery.
+        let lost = Some(TunnelCondition::Disconnected);
+        assert_eq!(events.update(lost, 1_000_000), None);
+        assert_eq!(events.update(lost, 1_500_000), None);
+        assert_eq!(
+            events.update(None, 1_500_000),
+            Some(TunnelEvent::Recovered {
+                from: TunnelCondition::Disconnected,
+                after_ms: 500,
+                reported: false,
+            })
+        );
+        assert_eq!(events.update(None, 1_600_000), None);
+    }
+
+    #[test]
+    fn lasting_conditions_are_reported_once() {
+        let mut events = EventDebouncer::new(GRACE_MS);
+        // Disconnected before the first connection is just connecting.
+        let lost = Some(TunnelCondition::Disconnected);
+        assert_eq!(events.update(lost, 0), None);
+        assert_eq!(
+            events.update(None, 10_000_000),
+            Some(TunnelEvent::Connected)
+        );
+
+        let stalled = Some(TunnelCondition::Stalled);
+        assert_eq!(events.update(stalled, 11_000_000), None);
+        assert_eq!(
+            events.update(stalled, 13_000_000),
+            Some(TunnelEvent::Stalled)
+        );
+        assert_eq!(events.update(stalled, 14_000_000), None);
+        // The stall ends in a disconnect: reported right away, the outage is old.
+        assert_eq!(
+            events.update(lost, 14_000_000),
+            Some(TunnelEvent::Disconnected)
+        );
+        let recovered = events.update(None, 20_000_000);
+        assert_eq!(
+            recovered,
+            Some(TunnelEvent::Recovered {
+                from: TunnelCondition::Disconnected,
+                after_ms: 9_000,
+                reported: true,
+            })
+        );
+        assert_eq!(
+            recovered.unwrap().to_json(),
+            r#"{"event":"recovered","from":"disconnected","after_ms":9000,"reported":true}"#
+        );
+    }
+
+    #[test]
+    fn reconnecting_carries_attempt_and_delay() {
+        let event = TunnelEvent::Reconnecting {
+            attempt: 3,
+            retry_in_ms: 1_000,
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"reconnecting","attempt":3,"retry_in_ms":1000}"#
+        );
+    }
+
+    #[test]
+    fn gave_up_carries_attempts_and_time() {
+        let event = TunnelEvent::GaveUp {
+            attempts: 1_000,
+            after_ms: 5_400_000,
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"gave_up","attempts":1000,"after_ms":5400000}"#
+        );
+    }
+
+    #[test]
+    fn limit_reached_carries_the_query_count() {
+        let event = TunnelEvent::LimitReached { queries: 50_000 };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"limit_reached","queries":50000}"#
+        );
+    }
+
+    #[test]
+    fn session_rotation_names_both_sessions() {
+        let event = TunnelEvent::SessionRotated {
+            old: "1a2b3c4d".to_string(),
+            new: "5e6f7a8b".to_string(),
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"session_rotated","old":"1a2b3c4d","new":"5e6f7a8b"}"#
+        );
+    }
+
+    #[test]
+    fn non_tunnel_response_names_resolver_and_record() {
+        let event = TunnelEvent::NonTunnelResponse {
+            resolver: "192.0.2.53:53".to_string(),
+            record_type: "A".to_string(),
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"non_tunnel_response","resolver":"192.0.2.53:53","record_type":"A"}"#
+        );
+    }
+
+    #[test]
+    fn resolver_mode_mismatch_names_both_modes() {
+        let event = TunnelEvent::ResolverModeMismatch {
+            resolver: "192.0.2.53:53".to_string(),
+            configured: "authoritative".to_string(),
+            observed: "recursive".to_string(),
+            adapted: true,
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"resolver_mode_mismatch","resolver":"192.0.2.53:53","configured":"authoritative","observed":"recursive","adapted":true}"#
+        );
+    }
+
+    #[test]
+    fn rejected_encoding_names_the_rejected_and_the_next_form() {
+        let event = TunnelEvent::ResolverRejectsEncoding {
+            resolver: "192.0.2.53:53".to_string(),
+            rejected: "TXT/CH".to_string(),
+            fallback: Some("TXT/IN".to_string()),
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"resolver_rejects_encoding","resolver":"192.0.2.53:53","rejected":"TXT/CH","fallback":"TXT/IN"}"#
+        );
+    }
+
+    #[test]
+    fn rtt_spike_names_both_round_trip_times() {
+        let event = TunnelEvent::RttSpike {
+            resolver: "192.0.2.53:53".to_string(),
+            baseline_ms: 80,
+            rtt_ms: 900,
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"event":"rtt_spike","resolver":"192.0.2.53:53","baseline_ms":80,"rtt_ms":900}"#
+        );
+    }
+
+    #[test]
+    fn event_stream_gets_one_json_line_per_event() {
+        let buffer = SharedBuffer::default();
+        set_event_stream(Box::new(buffer.clone()));
+        write_event_line(&TunnelEvent::Connected.to_json());
+        write_event_line(&TunnelEvent::Stalled.to_json());
+        let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            written,
+            "{\"event\":\"connected\"}\n{\"event\":\"stalled\"}\n"
+        );
+    }
+}
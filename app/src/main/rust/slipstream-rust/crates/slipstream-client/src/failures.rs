@@ -0,0 +1,207 @@
+//! Why connections to a destination failed.
+//!
+//! The local SOCKS5 edge reports each failed CONNECT with the reply that came
+//! back through the tunnel, or with none when the tunnel never answered. The
+//! edge's own blocklist refusals are recorded too. [`DestinationErrors`] keeps
+//! the latest cause per host, so "site X does not load" can be answered with a
+//! cause instead of a guess.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+
+/// Hosts remembered at most; the one that failed longest ago is dropped first.
+pub const DESTINATION_ERRORS_CAPACITY: usize = 64;
+
+/// Cause of a failed CONNECT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DestinationError {
+    /// The tunnel never answered: its DNS queries went unanswered.
+    DnsTimeout,
+    /// The tunnel never answered and a resolver answers REFUSED.
+    ResolverRefused,
+    /// The destination refused the connection.
+    ConnectRefused,
+    /// The server found no route to the destination, could not resolve its
+    /// name, or timed out connecting.
+    Unreachable,
+    /// Refused by the local blocklist or the server's ruleset.
+    Blocked,
+    /// The server's proxy failed for another reason.
+    ProxyFailure,
+}
+
+impl DestinationError {
+    /// Classifies a failed CONNECT from the SOCKS5 reply code the server sent,
+    /// or `None` when no reply came through the tunnel. `resolver_refusing`
+    /// tells whether a resolver currently answers REFUSED.
+    pub fn classify(reply: Option<u8>, resolver_refusing: bool) -> Self {
+        match reply {
+            None if resolver_refusing => Self::ResolverRefused,
+            None => Self::DnsTimeout,
+            Some(0x02) => Self::Blocked,
+            Some(0x03 | 0x04 | 0x06 | 0x08) => Self::Unreachable,
+            Some(0x05) => Self::ConnectRefused,
+            Some(_) => Self::ProxyFailure,
+        }
+    }
+}
+
+/// Last failure of one host.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DestinationFailure {
+    pub reason: DestinationError,
+    /// SOCKS5 reply code from the server; `None` when it sent none.
+    pub reply: Option<u8>,
+    /// `None` for blocklist refusals, which are checked by host alone.
+    pub port: Option<u16>,
+    /// Time since the failure, filled in when the entry is read.
+    pub age_ms: u64,
+    #[serde(skip)]
+    at_us: u64,
+}
+
+/// The latest failure per host, bounded to [`DESTINATION_ERRORS_CAPACITY`].
+#[derive(Debug, Default)]
+pub struct DestinationErrors {
+    /// Oldest failure first.
+    entries: VecDeque<(String, DestinationFailure)>,
+}
+
+impl DestinationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that a CONNECT to `host` and `port` failed at `now_us`, replacing the
+    /// host's earlier failure.
+    pub fn record(
+        &mut self,
+        host: &str,
+        port: Option<u16>,
+        reason: DestinationError,
+        reply: Option<u8>,
+        now_us: u64,
+    ) {
+        let host = key(host);
+        self.entries.retain(|(entry, _)| *entry != host);
+        if self.entries.len() == DESTINATION_ERRORS_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((
+            host,
+            DestinationFailure {
+                reason,
+                reply,
+                port,
+                age_ms: 0,
+                at_us: now_us,
+            },
+        ));
+    }
+
+    /// The latest failure of `host`, on any port.
+    pub fn last(&self, host: &str, now_us: u64) -> Option<DestinationFailure> {
+        let host = key(host);
+        self.entries
+            .iter()
+            .find(|(entry, _)| *entry == host)
+            .map(|(_, failure)| DestinationFailure {
+                age_ms: now_us.saturating_sub(failure.at_us) / 1_000,
+                ..failure.clone()
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn key(host: &str) -> String {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_cause_per_host_within_the_bound() {
+        assert_eq!(
+            DestinationError::classify(None, false),
+            DestinationError::DnsTimeout
+        );
+        assert_eq!(
+            DestinationError::classify(None, true),
+            DestinationError::ResolverRefused
+        );
+        assert_eq!(
+            DestinationError::classify(Some(0x05), true),
+            DestinationError::ConnectRefused
+        );
+        assert_eq!(
+            DestinationError::classify(Some(0x04), false),
+            DestinationError::Unreachable
+        );
+        assert_eq!(
+            DestinationError::classify(Some(0x02), false),
+            DestinationError::Blocked
+        );
+        assert_eq!(
+            DestinationError::classify(Some(0x01), false),
+            DestinationError::ProxyFailure
+        );
+
+        let mut errors = DestinationErrors::new();
+        errors.record(
+            "Example.com.",
+            Some(443),
+            DestinationError::DnsTimeout,
+            None,
+            1_000_000,
+        );
+        errors.record(
+            "example.com",
+            Some(80),
+            DestinationError::ConnectRefused,
+            Some(0x05),
+            3_000_000,
+        );
+        let last = errors.last("EXAMPLE.com", 5_500_000).expect("recorded");
+        assert_eq!(last.reason, DestinationError::ConnectRefused);
+        assert_eq!(
+            (last.port, last.reply, last.age_ms),
+            (Some(80), Some(0x05), 2_500)
+        );
+        assert_eq!(errors.len(), 1);
+        assert!(errors.last("other.example", 5_500_000).is_none());
+
+        let json = serde_json::to_string(&last).unwrap();
+        assert_eq!(
+            json,
+            r#"{"reason":"connect_refused","reply":5,"port":80,"age_ms":2500}"#
+        );
+
+        for index in 0..DESTINATION_ERRORS_CAPACITY {
+            errors.record(
+                &format!("host{}.example", index),
+                Some(443),
+                DestinationError::Unreachable,
+                Some(0x04),
+                4_000_000,
+            );
+        }
+        assert_eq!(errors.len(), DESTINATION_ERRORS_CAPACITY);
+        assert!(errors.last("example.com", 5_000_000).is_none());
+        assert!(errors.last("host0.example", 5_000_000).is_some());
+        assert!(errors.last("[2001:db8::1]", 5_000_000).is_none());
+        errors.record("[2001:DB8::1]", None, DestinationError::Blocked, None, 0);
+        assert!(errors.last("2001:db8::1", 5_000_000).is_some());
+        assert!(errors.last("host0.example", 5_000_000).is_none());
+    }
+}
@@ -0,0 +1,196 @@
+//! Open CONNECTs per destination host.
+//!
+//! A single site can open dozens of parallel connections and starve every
+//! other app of a tunnel that only moves a few kilobytes per second. The local
+//! SOCKS5 edge asks [`HostConnectionLimits::open`] before it opens a tunnel
+//! stream, answers a CONNECT over the cap with reply 0x02 ("connection not
+//! allowed by ruleset") and reports with [`HostConnectionLimits::close`] once
+//! the connection ended.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Cap on open CONNECTs per destination host, with per-host overrides.
+#[derive(Debug, Default)]
+pub struct HostConnectionLimits {
+    /// Cap for hosts without an override; 0 is no cap.
+    default_limit: usize,
+    overrides: HashMap<String, usize>,
+    open: HashMap<String, usize>,
+}
+
+impl HostConnectionLimits {
+    /// `overrides` is comma-separated `host=COUNT` entries, e.g.
+    /// `example.com=2,cdn.example.net=0`; a count of 0 lifts the cap for that
+    /// host.
+    pub fn parse(default_limit: usize, overrides: &str) -> Result<Self, String> {
+        let mut limits = Self {
+            default_limit,
+            ..Self::default()
+        };
+        for entry in overrides
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let parsed = entry.split_once('=').and_then(|(host, limit)| {
+                let host = key(host);
+                let limit = limit.trim().parse::<usize>().ok()?;
+                (!host.is_empty()).then_some((host, limit))
+            });
+            let Some((host, limit)) = parsed else {
+                return Err(format!(
+                    "Invalid connection limit {} (expected host=COUNT)",
+                    entry
+                ));
+            };
+            limits.overrides.insert(host, limit);
+        }
+        Ok(limits)
+    }
+
+    /// Cap for `host`; 0 is no cap.
+    pub fn limit(&self, host: &str) -> usize {
+        self.overrides
+            .get(&key(host))
+            .copied()
+            .unwrap_or(self.default_limit)
+    }
+
+    /// Counts a new CONNECT to `host`; `false`, without counting it, when the
+    /// host is at its cap.
+    pub fn open(&mut self, host: &str) -> bool {
+        let limit = self.limit(host);
+        let host = key(host);
+        let open = self.open.get(&host).copied().unwrap_or(0);
+        if limit > 0 && open >= limit {
+            return false;
+        }
+        self.open.insert(host, open + 1);
+        true
+    }
+
+    /// Reports that a CONNECT [`open`](Self::open) let through has ended.
+    pub fn close(&mut self, host: &str) {
+        let host = key(host);
+        let Some(open) = self.open.get_mut(&host) else {
+            return;
+        };
+        *open = open.saturating_sub(1);
+        if *open == 0 {
+            self.open.remove(&host);
+        }
+    }
+
+    /// Open CONNECTs per host, for hosts with any open.
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        self.open
+            .iter()
+            .map(|(host, open)| (host.clone(), *open))
+            .collect()
+    }
+}
+
+fn key(host: &str) -> String {
+    let host = host.trim().trim_start_matches('[').trim_end_matches(']');
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Limits of the local SOCKS edge, shared with the session snapshot.
+static HOST_CONNECTIONS: Mutex<Option<HostConnectionLimits>> = Mutex::new(None);
+
+/// Sets the cap on open CONNECTs per host; see [`HostConnectionLimits::parse`].
+/// CONNECTs already open keep counting against the new caps. An invalid
+/// override keeps the previous limits.
+// Called by the Android bridge and library callers; the CLI has no SOCKS edge.
+#[allow(dead_code)]
+pub fn set_host_connection_limits(default_limit: usize, overrides: &str) -> Result<(), String> {
+    let mut limits = HostConnectionLimits::parse(default_limit, overrides)?;
+    let mut current = HOST_CONNECTIONS.lock().expect("lock host connections");
+    // Connections opened under the old limits are still released.
+    if let Some(previous) = current.take() {
+        limits.open = previous.open;
+    }
+    *current = Some(limits);
+    Ok(())
+}
+
+/// Counts a new CONNECT to `host` against the limits set by
+/// [`set_host_connection_limits`]; `false` when it is over its host's cap.
+// Called by the Android bridge and library callers; the CLI has no SOCKS edge.
+#[allow(dead_code)]
+pub fn open_host_connection(host: &str) -> bool {
+    HOST_CONNECTIONS
+        .lock()
+        .expect("lock host connections")
+        .get_or_insert_with(HostConnectionLimits::default)
+        .open(host)
+}
+
+/// Reports that a CONNECT [`open_host_connection`] let through has ended.
+// Called by the Android bridge and library callers; the CLI has no SOCKS edge.
+#[allow(dead_code)]
+pub fn close_host_connection(host: &str) {
+    if let Some(limits) = HOST_CONNECTIONS
+        .lock()
+        .expect("lock host connections")
+        .as_mut()
+    {
+        limits.close(host);
+    }
+}
+
+/// Open CONNECTs per host, for the session snapshot.
+pub(crate) fn host_connection_counts() -> BTreeMap<String, usize> {
+    HOST_CONNECTIONS
+        .lock()
+        .expect("lock host connections")
+        .as_ref()
+        .map(HostConnectionLimits::counts)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connects_over_a_hosts_cap_are_refused() {
+        let mut limits = HostConnectionLimits::parse(2, "video.example=1, cdn.example.=0").unwrap();
+        assert!(limits.open("Example.com"));
+        assert!(limits.open("example.com."));
+        assert!(!limits.open("example.com"));
+        assert!(limits.open("other.example"));
+        assert!(limits.open("video.example"));
+        assert!(!limits.open("VIDEO.example"));
+        for _ in 0..10 {
+            assert!(limits.open("cdn.example"));
+        }
+        assert_eq!(limits.counts().get("example.com"), Some(&2));
+        assert_eq!(limits.counts().get("cdn.example"), Some(&10));
+
+        // A closed connection makes room for the next one.
+        limits.close("example.com");
+        assert!(limits.open("example.com"));
+        limits.close("video.example");
+        assert!(!limits.counts().contains_key("video.example"));
+        limits.close("never.opened");
+
+        let mut unlimited = HostConnectionLimits::parse(0, "").unwrap();
+        for _ in 0..100 {
+            assert!(unlimited.open("[2001:db8::1]"));
+        }
+        assert_eq!(unlimited.counts().get("2001:db8::1"), Some(&100));
+    }
+
+    #[test]
+    fn parses_overrides() {
+        let limits = HostConnectionLimits::parse(4, "a.example=1,b.example = 8").unwrap();
+        assert_eq!(limits.limit("a.example"), 1);
+        assert_eq!(limits.limit("b.example"), 8);
+        assert_eq!(limits.limit("c.example"), 4);
+        assert!(HostConnectionLimits::parse(4, "a.example").is_err());
+        assert!(HostConnectionLimits::parse(4, "a.example=-1").is_err());
+        assert!(HostConnectionLimits::parse(4, "=2").is_err());
+    }
+}
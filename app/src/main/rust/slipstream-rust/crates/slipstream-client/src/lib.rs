@@ -3,16 +3,62 @@
 //! This module provides the core functionality for the slipstream DNS tunnel client,
 //! including Android JNI bindings for mobile deployment.
 
+pub mod blocklist;
+pub mod compat;
+pub mod dedup;
 pub mod dns;
+pub mod dns_forward;
+pub mod doh_tls;
 pub mod error;
+pub mod events;
+pub mod failures;
+pub mod host_limits;
+pub mod loglevel;
+pub mod logstream;
 pub mod pacing;
 pub mod pinning;
+pub mod profile;
+pub mod redact;
 pub mod runtime;
+pub mod session;
+pub mod socks5;
+pub mod socks_reply;
+pub mod spans;
+pub mod status;
 pub mod streams;
+#[cfg(test)]
+mod test_config;
+pub mod tuning;
+pub mod validate;
+pub mod verify;
 
 #[cfg(target_os = "android")]
 pub mod android;
 
 // Re-export key types for library users
-pub use error::ClientError;
-pub use runtime::run_client;
+pub use blocklist::DestinationBlocklist;
+pub use dedup::{ConnectDecision, DuplicateConnectPolicy, DuplicateConnects};
+pub use dns::export_flight_recorder;
+pub use doh_tls::{record_doh_below_minimum, record_doh_handshake, reset_doh_tls};
+pub use error::{ClientError, ClientErrorKind};
+pub use events::{TunnelCondition, TunnelEvent};
+pub use failures::{DestinationError, DestinationErrors, DestinationFailure};
+pub use host_limits::{
+    close_host_connection, open_host_connection, set_host_connection_limits, HostConnectionLimits,
+};
+pub use loglevel::set_log_level;
+pub use logstream::{clear_log_stream, log_stream_layer, set_log_stream, LogLine, LogStreamLayer};
+pub use profile::{export_profile, import_profile, ClientProfile};
+pub use redact::set_log_privacy;
+pub use runtime::{
+    append_resolvers, force_resolver, reload_config, run_client, ReloadReport, HOT_RELOAD_FIELDS,
+};
+pub use session::{
+    last_session_summary, resolver_statuses, session_id, session_snapshot, CongestionDetails,
+    CongestionState, ResolverInfo, ResolverStatus, SessionInfo, SessionSummary,
+};
+pub use socks_reply::BoundAddressPolicy;
+pub use tuning::{
+    export_learned_tuning, import_learned_tuning, set_network_id, NetworkTuning, ResolverTuning,
+};
+pub use validate::{validate_config, ConfigIssue, IssueSeverity};
@@ -0,0 +1,121 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Append-only log file that rotates by size.
+///
+/// When a write would push the file past `max_bytes`, `path` is renamed to
+/// `path.1`, older files shift up by one, and anything beyond `max_files`
+/// rotated copies is deleted. With `max_files` of 0 the file is truncated instead.
+pub(crate) struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    pub(crate) fn open(path: &Path, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes: max_bytes.max(1),
+            max_files,
+            file,
+            written,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Each call carries one formatted event, so events never straddle files.
+        if self.written > 0 && self.written.saturating_add(buf.len() as u64) > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written = self.written.saturating_add(written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "slipstream-logfile-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create temp dir");
+        dir.join("client.log")
+    }
+
+    #[test]
+    fn rotates_and_keeps_max_files() {
+        let path = temp_log("rotate");
+        let mut log = RotatingFile::open(&path, 10, 2).expect("open");
+        for line in ["first---\n", "second--\n", "third---\n", "fourth--\n"] {
+            log.write_all(line.as_bytes()).expect("write");
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth--\n");
+        assert_eq!(
+            fs::read_to_string(log.rotated_path(1)).unwrap(),
+            "third---\n"
+        );
+        assert_eq!(
+            fs::read_to_string(log.rotated_path(2)).unwrap(),
+            "second--\n"
+        );
+        assert!(!log.rotated_path(3).exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn truncates_without_rotated_files_and_resumes_size() {
+        let path = temp_log("truncate");
+        fs::write(&path, "existing\n").expect("seed");
+        let mut log = RotatingFile::open(&path, 12, 0).expect("open");
+        log.write_all(b"next\n").expect("write");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "next\n");
+        assert!(!log.rotated_path(1).exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
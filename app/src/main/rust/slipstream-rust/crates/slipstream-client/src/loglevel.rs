@@ -0,0 +1,57 @@
+//! Log level of a running client, changeable without a restart.
+//!
+//! The formatters' filters are built by [`reloadable_filter`], which keeps a
+//! handle to each; [`set_log_level`] replaces all of them at once. The live
+//! log stream picks its own level and is not affected.
+
+use crate::error::ClientError;
+use std::sync::Mutex;
+use tracing_subscriber::{reload, EnvFilter};
+
+type Reload = Box<dyn Fn(&str) -> Result<(), String> + Send>;
+
+/// One entry per filter [`reloadable_filter`] built.
+static FILTERS: Mutex<Vec<Reload>> = Mutex::new(Vec::new());
+
+/// A filter from `RUST_LOG`, or `info` when it is unset or invalid, that
+/// [`set_log_level`] can replace later.
+// Used by the binary's and the Android bridge's logging setup.
+#[allow(dead_code)]
+pub(crate) fn reloadable_filter<S: 'static>() -> reload::Layer<EnvFilter, S> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+    let reload: Reload = Box::new(move |directives| {
+        let filter = EnvFilter::try_new(directives).map_err(|err| err.to_string())?;
+        handle.reload(filter).map_err(|err| err.to_string())
+    });
+    FILTERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(reload);
+    filter
+}
+
+/// Replace the log filter with `directives`, in `RUST_LOG` syntax: a level
+/// such as `debug`, or per-module levels such as `info,slipstream_client=trace`.
+// Called by the status endpoint, the Android bridge and library callers.
+#[allow(dead_code)]
+pub fn set_log_level(directives: &str) -> Result<(), ClientError> {
+    let directives = directives.trim();
+    if directives.is_empty() {
+        return Err(ClientError::new("Empty log level"));
+    }
+    EnvFilter::try_new(directives)
+        .map_err(|err| ClientError::new(format!("Invalid log level {}: {}", directives, err)))?;
+    let filters = FILTERS
+        .lock()
+        .map_err(|_| ClientError::new("Log filter state is poisoned"))?;
+    if filters.is_empty() {
+        return Err(ClientError::new("Logging is not set up"));
+    }
+    for reload in filters.iter() {
+        reload(directives).map_err(|err| {
+            ClientError::new(format!("Failed to set log level {}: {}", directives, err))
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,362 @@
+//! Live log lines for in-app consoles.
+//!
+//! [`LogStreamLayer`] sits in the tracing subscriber next to the usual
+//! formatter. While a stream is set, every event at or above its level is
+//! formatted into a [`LogLine`] and queued for a forwarder thread that hands
+//! it to the callback. Logging never waits for the consumer: past the line
+//! rate or with the queue full, lines are dropped and counted, and the next
+//! delivered line is preceded by a notice saying how many were lost.
+
+use std::fmt::{self, Write as _};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Lines waiting for the callback; more are dropped.
+const LOG_STREAM_QUEUE: usize = 256;
+
+static STREAM: Mutex<Option<LogStream>> = Mutex::new(None);
+
+/// Most verbose level any stream wants, as `level_rank`; 0 while none is set.
+static STREAM_LEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// One formatted log event: the message followed by its fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub level: Level,
+    pub message: String,
+}
+
+struct LogStream {
+    max_level: Level,
+    sender: SyncSender<LogLine>,
+    max_lines_per_sec: u32,
+    window_start: Instant,
+    window_lines: u32,
+    /// Lines dropped since the last one delivered.
+    dropped: u64,
+}
+
+impl LogStream {
+    fn within_rate(&mut self, now: Instant) -> bool {
+        if self.max_lines_per_sec == 0 {
+            return true;
+        }
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.window_lines = 0;
+        }
+        if self.window_lines >= self.max_lines_per_sec {
+            return false;
+        }
+        self.window_lines += 1;
+        true
+    }
+
+    fn offer(&mut self, line: LogLine) {
+        if self.dropped > 0 {
+            let notice = LogLine {
+                level: Level::WARN,
+                message: format!("{} log lines dropped", self.dropped),
+            };
+            if self.sender.try_send(notice).is_err() {
+                self.dropped = self.dropped.saturating_add(1);
+                return;
+            }
+            self.dropped = 0;
+        }
+        match self.sender.try_send(line) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.dropped = self.dropped.saturating_add(1);
+            }
+        }
+    }
+}
+
+/// Sends every log line at `max_level` or more severe to `callback`, on a
+/// thread of its own, replacing any previous stream. At most
+/// `max_lines_per_sec` lines are passed on per second; 0 is unlimited. Lines
+/// only show up once [`LogStreamLayer`] is part of the global subscriber.
+///
+/// `callback` is dropped on the stream thread as its last act, so whatever it
+/// owns can clean up per-thread state there, e.g. detach from a JVM.
+pub fn set_log_stream(
+    max_level: Level,
+    max_lines_per_sec: u32,
+    callback: impl Fn(LogLine) + Send + 'static,
+) -> std::io::Result<()> {
+    let (sender, receiver) = sync_channel::<LogLine>(LOG_STREAM_QUEUE);
+    thread::Builder::new()
+        .name("slipstream-log-stream".to_string())
+        .spawn(move || {
+            // Ends once the stream is replaced or cleared and the queue drained.
+            for line in receiver {
+                callback(line);
+            }
+            drop(callback);
+        })?;
+    let stream = LogStream {
+        max_level,
+        sender,
+        max_lines_per_sec,
+        window_start: Instant::now(),
+        window_lines: 0,
+        dropped: 0,
+    };
+    *STREAM
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(stream);
+    set_stream_level(level_rank(max_level));
+    Ok(())
+}
+
+/// Stops the log stream; lines already queued are still delivered.
+pub fn clear_log_stream() {
+    STREAM
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .take();
+    set_stream_level(0);
+}
+
+/// Layer that feeds the log stream; a no-op while none is set.
+pub fn log_stream_layer() -> LogStreamLayer {
+    LogStreamLayer { _private: () }
+}
+
+pub struct LogStreamLayer {
+    _private: (),
+}
+
+impl<S: Subscriber> Layer<S> for LogStreamLayer {
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        // The level changes at run time, so every callsite is asked again.
+        Interest::sometimes()
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(match STREAM_LEVEL.load(Ordering::Relaxed) {
+            0 => LevelFilter::OFF,
+            1 => LevelFilter::ERROR,
+            2 => LevelFilter::WARN,
+            3 => LevelFilter::INFO,
+            4 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        })
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level_rank(level) > STREAM_LEVEL.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut guard = STREAM
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(stream) = guard.as_mut() else {
+            return;
+        };
+        if level > stream.max_level {
+            return;
+        }
+        if !stream.within_rate(Instant::now()) {
+            stream.dropped = stream.dropped.saturating_add(1);
+            return;
+        }
+        let mut message = String::new();
+        event.record(&mut LineVisitor(&mut message));
+        stream.offer(LogLine { level, message });
+    }
+}
+
+fn set_stream_level(rank: u8) {
+    STREAM_LEVEL.store(rank, Ordering::Relaxed);
+    // Callsites disabled for the old level must be looked at again.
+    tracing::callsite::rebuild_interest_cache();
+}
+
+/// 1 for ERROR up to 5 for TRACE.
+fn level_rank(level: Level) -> u8 {
+    match level {
+        Level::ERROR => 1,
+        Level::WARN => 2,
+        Level::INFO => 3,
+        Level::DEBUG => 4,
+        Level::TRACE => 5,
+    }
+}
+
+/// Writes the message first and the other fields after it as `name=value`.
+struct LineVisitor<'a>(&'a mut String);
+
+impl Visit for LineVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let fields = std::mem::take(self.0);
+            let _ = write!(self.0, "{:?}", value);
+            self.0.push_str(&fields);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.record_debug(field, &format_args!("{}", value));
+        } else {
+            let _ = write!(self.0, " {}={}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::Arc;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    // The tests below share the global stream.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn lock_test_state() -> std::sync::MutexGuard<'static, ()> {
+        TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn collect(max_level: Level, max_lines_per_sec: u32) -> Receiver<LogLine> {
+        let (sender, receiver) = channel();
+        let sender = Mutex::new(sender);
+        set_log_stream(max_level, max_lines_per_sec, move |line| {
+            let _ = sender.lock().unwrap().send(line);
+        })
+        .expect("log stream thread");
+        receiver
+    }
+
+    fn received(receiver: &Receiver<LogLine>) -> Vec<LogLine> {
+        let mut lines = Vec::new();
+        while let Ok(line) = receiver.recv_timeout(Duration::from_millis(200)) {
+            lines.push(line);
+        }
+        lines
+    }
+
+    #[test]
+    fn lines_reach_the_callback_at_the_chosen_level() {
+        let _guard = lock_test_state();
+        let receiver = collect(Level::INFO, 0);
+        let subscriber = tracing_subscriber::registry().with(log_stream_layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(resolver = "192.0.2.1:53", "Resolver answered");
+            tracing::debug!("Too verbose for the stream");
+            tracing::warn!("Resolver {} is slow", 2);
+        });
+        clear_log_stream();
+        assert_eq!(
+            received(&receiver),
+            [
+                LogLine {
+                    level: Level::INFO,
+                    message: "Resolver answered resolver=192.0.2.1:53".to_string(),
+                },
+                LogLine {
+                    level: Level::WARN,
+                    message: "Resolver 2 is slow".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_slow_consumer_loses_lines_instead_of_blocking() {
+        let _guard = lock_test_state();
+        let (release, blocked) = channel::<()>();
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&delivered);
+        let blocked = Mutex::new(blocked);
+        set_log_stream(Level::INFO, 0, move |line| {
+            // The consumer is stuck until the test lets it go.
+            let _ = blocked.lock().unwrap().recv();
+            sink.lock().unwrap().push(line);
+        })
+        .expect("log stream thread");
+        let subscriber = tracing_subscriber::registry().with(log_stream_layer());
+        let started = Instant::now();
+        tracing::subscriber::with_default(subscriber, || {
+            for index in 0..LOG_STREAM_QUEUE * 4 {
+                tracing::info!("line {}", index);
+            }
+            // A line after the consumer caught up carries the drop notice.
+            for _ in 0..LOG_STREAM_QUEUE * 4 {
+                let _ = release.send(());
+            }
+            thread::sleep(Duration::from_millis(100));
+            tracing::info!("caught up");
+        });
+        assert!(started.elapsed() < Duration::from_secs(5));
+        clear_log_stream();
+        let caught_up =
+            |lines: &Vec<LogLine>| lines.last().is_some_and(|line| line.message == "caught up");
+        for _ in 0..200 {
+            if caught_up(&delivered.lock().unwrap()) {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let delivered = delivered.lock().unwrap();
+        assert!(delivered.len() <= LOG_STREAM_QUEUE + 3);
+        assert_eq!(delivered[0].message, "line 0");
+        let notice = &delivered[delivered.len() - 2];
+        assert_eq!(notice.level, Level::WARN);
+        assert!(notice.message.ends_with("log lines dropped"));
+        assert_eq!(delivered[delivered.len() - 1].message, "caught up");
+    }
+
+    #[test]
+    fn the_callback_is_dropped_on_the_stream_thread() {
+        let _guard = lock_test_state();
+        struct OnDrop(std::sync::mpsc::Sender<thread::ThreadId>);
+        impl Drop for OnDrop {
+            fn drop(&mut self) {
+                let _ = self.0.send(thread::current().id());
+            }
+        }
+        let (sender, dropped) = channel();
+        let on_drop = OnDrop(sender);
+        set_log_stream(Level::INFO, 0, move |_line| {
+            let _ = &on_drop;
+        })
+        .expect("log stream thread");
+        clear_log_stream();
+        let dropped_on = dropped
+            .recv_timeout(Duration::from_secs(2))
+            .expect("callback dropped");
+        assert_ne!(dropped_on, thread::current().id());
+    }
+
+    #[test]
+    fn lines_over_the_rate_are_dropped() {
+        let _guard = lock_test_state();
+        let receiver = collect(Level::TRACE, 5);
+        let subscriber = tracing_subscriber::registry().with(log_stream_layer());
+        tracing::subscriber::with_default(subscriber, || {
+            for index in 0..20 {
+                tracing::trace!("line {}", index);
+            }
+        });
+        clear_log_stream();
+        let lines = received(&receiver);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[4].message, "line 4");
+    }
+}
@@ -1,19 +1,108 @@
+mod compat;
 mod dns;
+mod dns_forward;
+mod doh_tls;
 mod error;
+mod events;
+mod host_limits;
+mod logfile;
+mod loglevel;
+#[cfg(feature = "otel")]
+mod otel;
 mod pacing;
 mod pinning;
+mod redact;
 mod runtime;
+mod session;
+mod socks5;
+mod spans;
+mod status;
 mod streams;
+#[cfg(test)]
+mod test_config;
+mod tuning;
+mod validate;
 
 use clap::{parser::ValueSource, ArgGroup, CommandFactory, FromArgMatches, Parser};
 use slipstream_core::{
     normalize_domain, parse_host_port, parse_host_port_parts, sip003, AddressKind, HostPort,
 };
-use slipstream_ffi::{ClientConfig, ResolverMode, ResolverSpec};
+use slipstream_dns::{parse_capture, replay_record, ReplayOutcome, CLASS_CH, CLASS_IN};
+use slipstream_ffi::{
+    ActiveWindow, ClientConfig, DnsIdStrategy, FormerrPolicy, LabelEncoding, LocalClosePolicy,
+    ModeMismatchPolicy, NonTunnelPolicy, PipelineMode, ProtectionLossPolicy, ProtectionMode,
+    ResolverMode, ResolverPreference, ResolverRolePolicy, ResolverSpec,
+};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::Mutex;
 use tokio::runtime::Builder;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, Layer, Registry};
+
+use dns::{
+    DEFAULT_POLL_TIMEOUT_MS, DEFAULT_QUERY_RAMP_DOUBLING_MS, DEFAULT_RESOLVER_FAILURE_THRESHOLD,
+    DEFAULT_RESOLVER_GRACE_PERIOD_MS, DEFAULT_RESOLVER_RECOVERY_PROBE_MS,
+    DEFAULT_RESOLVER_SERVFAIL_COOLDOWN_MS, DEFAULT_RESOLVER_SERVFAIL_THRESHOLD, MAX_POLL_POOL,
+};
+use dns_forward::{DEFAULT_DNS_LISTEN_HOST, DEFAULT_DNS_UPSTREAM};
+use error::ClientErrorKind;
+use events::DEFAULT_EVENT_GRACE_MS;
+use logfile::RotatingFile;
+use loglevel::reloadable_filter;
+use runtime::{
+    parse_active_window, run_client, DEFAULT_BLOCK_BACKOFF_MS, DEFAULT_IDLE_POLL_MIN_MS,
+    DEFAULT_MAX_RECONNECT_ATTEMPTS, DEFAULT_RECONNECT_MAX_MS, DEFAULT_RECONNECT_MIN_MS,
+};
+use status::DEFAULT_STATUS_HOST;
+use streams::{DEFAULT_FIRST_STREAM_BOOST_MS, MIN_REASSEMBLY_LIMIT_BYTES};
+
+/// What `--optimize-for` tunes the lower-level knobs toward.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OptimizeFor {
+    Latency,
+    Balanced,
+    Throughput,
+}
 
-use runtime::run_client;
+/// Values `--optimize-for` fills in for flags the user did not pass.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Tuning {
+    coalesce_delay_ms: u64,
+    keep_alive_interval: u16,
+    congestion_control: Option<&'static str>,
+    resolver_preference: ResolverPreference,
+}
+
+impl OptimizeFor {
+    fn tuning(self) -> Tuning {
+        match self {
+            // Poll more often so downstream data is picked up sooner, and send
+            // every write as soon as it arrives.
+            OptimizeFor::Latency => Tuning {
+                coalesce_delay_ms: 0,
+                keep_alive_interval: 200,
+                congestion_control: None,
+                resolver_preference: ResolverPreference::Order,
+            },
+            // The plain defaults.
+            OptimizeFor::Balanced => Tuning {
+                coalesce_delay_ms: 0,
+                keep_alive_interval: 400,
+                congestion_control: None,
+                resolver_preference: ResolverPreference::Order,
+            },
+            // Fill queries before sending them, let bbr probe for bandwidth
+            // on every path and make the fastest resolver the primary.
+            OptimizeFor::Throughput => Tuning {
+                coalesce_delay_ms: 20,
+                keep_alive_interval: 400,
+                congestion_control: Some("bbr"),
+                resolver_preference: ResolverPreference::Bandwidth,
+            },
+        }
+    }
+}
 
 #[derive(Parser, Debug)]
 #[command(
@@ -54,16 +143,393 @@ struct Args {
     cert: Option<String>,
     #[arg(long = "keep-alive-interval", short = 't', default_value_t = 400)]
     keep_alive_interval: u16,
+    #[arg(
+        long = "optimize-for",
+        value_name = "latency|balanced|throughput",
+        default_value = "balanced",
+        value_parser = parse_optimize_for
+    )]
+    optimize_for: OptimizeFor,
+    #[arg(long = "adaptive-keep-alive")]
+    adaptive_keep_alive: bool,
     #[arg(long = "debug-poll")]
     debug_poll: bool,
     #[arg(long = "debug-streams")]
     debug_streams: bool,
+    #[arg(long = "record-dns", value_name = "PATH")]
+    record_dns: Option<String>,
+    #[arg(long = "query-log", value_name = "PATH")]
+    query_log: Option<String>,
+    #[arg(long = "replay-dns", value_name = "PATH")]
+    replay_dns: Option<String>,
+    #[arg(
+        long = "resolver-failure-threshold",
+        value_name = "COUNT",
+        default_value_t = DEFAULT_RESOLVER_FAILURE_THRESHOLD
+    )]
+    resolver_failure_threshold: u32,
+    #[arg(
+        long = "resolver-grace-period-ms",
+        value_name = "MS",
+        default_value_t = DEFAULT_RESOLVER_GRACE_PERIOD_MS
+    )]
+    resolver_grace_period_ms: u64,
+    #[arg(
+        long = "resolver-recovery-probe-ms",
+        value_name = "MS",
+        default_value_t = DEFAULT_RESOLVER_RECOVERY_PROBE_MS
+    )]
+    resolver_recovery_probe_ms: u64,
+    #[arg(
+        long = "resolver-servfail-threshold",
+        value_name = "COUNT",
+        default_value_t = DEFAULT_RESOLVER_SERVFAIL_THRESHOLD
+    )]
+    resolver_servfail_threshold: u32,
+    #[arg(
+        long = "resolver-servfail-cooldown-ms",
+        value_name = "MS",
+        default_value_t = DEFAULT_RESOLVER_SERVFAIL_COOLDOWN_MS
+    )]
+    resolver_servfail_cooldown_ms: u64,
+    #[arg(
+        long = "confirm-resolvers",
+        value_name = "COUNT",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    confirm_resolvers: u32,
+    /// Also send each handshake query to this many other resolvers.
+    #[arg(
+        long = "handshake-redundancy",
+        value_name = "COUNT",
+        default_value_t = 0
+    )]
+    handshake_redundancy: u16,
+    /// Bind address of the status endpoint, or unix:PATH to serve it on a Unix socket.
+    #[arg(long = "status-host", value_name = "HOST|unix:PATH", default_value = DEFAULT_STATUS_HOST)]
+    status_host: String,
+    #[arg(long = "status-port")]
+    status_port: Option<u16>,
+    /// Accept POST /stop on the status endpoint (loopback hosts only).
+    #[arg(long = "status-control")]
+    status_control: bool,
+    #[arg(long = "dns-listen-host", default_value = DEFAULT_DNS_LISTEN_HOST)]
+    dns_listen_host: String,
+    /// Answer UDP DNS on this port and resolve the queries through the tunnel.
+    #[arg(long = "dns-listen-port")]
+    dns_listen_port: Option<u16>,
+    #[arg(long = "dns-upstream", value_name = "HOST:PORT", default_value = DEFAULT_DNS_UPSTREAM)]
+    dns_upstream: String,
+    #[arg(long = "dns-socks-user", requires = "dns_socks_pass")]
+    dns_socks_user: Option<String>,
+    #[arg(long = "dns-socks-pass", requires = "dns_socks_user")]
+    dns_socks_pass: Option<String>,
+    #[arg(
+        long = "dns-id",
+        value_name = "random|sequential|fixed:ID",
+        default_value = "random",
+        value_parser = parse_dns_id_strategy
+    )]
+    dns_id: DnsIdStrategy,
+    #[arg(
+        long = "query-class",
+        value_name = "in|ch|NUMBER",
+        default_value = "in",
+        value_parser = parse_query_class
+    )]
+    query_class: u16,
+    #[arg(long = "edns-cookies")]
+    edns_cookies: bool,
+    #[arg(long = "adaptive-query-rate")]
+    adaptive_query_rate: bool,
+    /// Start each resolver at QPS queries per second after connecting, doubling while the tunnel asks for more.
+    #[arg(long = "query-ramp", value_name = "QPS", default_value_t = 0)]
+    query_ramp: u32,
+    #[arg(
+        long = "query-ramp-doubling-ms",
+        value_name = "MS",
+        default_value_t = DEFAULT_QUERY_RAMP_DOUBLING_MS
+    )]
+    query_ramp_doubling_ms: u64,
+    #[arg(long = "coalesce-delay-ms", value_name = "MS", default_value_t = 0)]
+    coalesce_delay_ms: u64,
+    #[arg(long = "reconnect-min-ms", value_name = "MS", default_value_t = DEFAULT_RECONNECT_MIN_MS)]
+    reconnect_min_ms: u64,
+    #[arg(long = "reconnect-max-ms", value_name = "MS", default_value_t = DEFAULT_RECONNECT_MAX_MS)]
+    reconnect_max_ms: u64,
+    #[arg(long = "reconnect-jitter")]
+    reconnect_jitter: bool,
+    #[arg(
+        long = "max-reconnect-attempts",
+        value_name = "COUNT",
+        default_value_t = DEFAULT_MAX_RECONNECT_ATTEMPTS
+    )]
+    max_reconnect_attempts: u32,
+    #[arg(
+        long = "max-reconnect-duration-ms",
+        value_name = "MS",
+        default_value_t = 0
+    )]
+    max_reconnect_duration_ms: u64,
+    #[arg(
+        long = "block-backoff-after",
+        value_name = "COUNT",
+        default_value_t = 0
+    )]
+    block_backoff_after: u32,
+    #[arg(long = "block-backoff-ms", value_name = "MS", default_value_t = DEFAULT_BLOCK_BACKOFF_MS)]
+    block_backoff_ms: u64,
+    #[arg(
+        long = "resolver-role-policy",
+        value_name = "mixed|split",
+        default_value = "mixed",
+        value_parser = parse_resolver_role_policy
+    )]
+    resolver_role_policy: ResolverRolePolicy,
+    #[arg(
+        long = "resolver-preference",
+        value_name = "order|bandwidth",
+        default_value = "order",
+        value_parser = parse_resolver_preference
+    )]
+    resolver_preference: ResolverPreference,
+    /// Keep at most N path probes to extra resolvers pending at once.
+    #[arg(
+        long = "max-parallel-probes",
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    max_parallel_probes: Option<u32>,
+    #[arg(long = "poll-timeout-ms", value_name = "MS", default_value_t = DEFAULT_POLL_TIMEOUT_MS)]
+    poll_timeout_ms: u64,
+    #[arg(
+        long = "idle-poll-min-ms",
+        value_name = "MS",
+        default_value_t = DEFAULT_IDLE_POLL_MIN_MS,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    idle_poll_min_ms: u64,
+    #[arg(long = "idle-poll-max-ms", value_name = "MS", default_value_t = 0)]
+    idle_poll_max_ms: u64,
+    /// After MS without a tunnel response, check the session with one poll.
+    #[arg(long = "idle-refresh-ms", value_name = "MS", default_value_t = 0)]
+    idle_refresh_ms: u64,
+    /// Keep N polls waiting at a server started with --poll-hold-ms.
+    #[arg(
+        long = "poll-pool",
+        value_name = "N",
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u16).range(0..=MAX_POLL_POOL as i64)
+    )]
+    poll_pool: u16,
+    #[arg(long = "stream-striping")]
+    stream_striping: bool,
+    #[arg(long = "prioritize-interactive")]
+    prioritize_interactive: bool,
+    /// Give bulk streams a congestion window of their own.
+    #[arg(long = "congestion-isolation")]
+    congestion_isolation: bool,
+    /// Send the first COUNT streams after the handshake ahead of later ones.
+    #[arg(long = "first-stream-boost", value_name = "COUNT", default_value_t = 0)]
+    first_stream_boost: u32,
+    #[arg(
+        long = "first-stream-boost-ms",
+        value_name = "MS",
+        default_value_t = DEFAULT_FIRST_STREAM_BOOST_MS
+    )]
+    first_stream_boost_ms: u64,
+    /// Keep at most N accepted connections waiting to be set up at once.
+    #[arg(
+        long = "max-connection-setups",
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    max_connection_setups: Option<u32>,
+    /// Cap received stream data not yet written to local sockets, in bytes.
+    #[arg(
+        long = "reassembly-limit",
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u32).range(MIN_REASSEMBLY_LIMIT_BYTES as i64..)
+    )]
+    reassembly_limit: Option<u32>,
+    #[arg(
+        long = "local-close",
+        value_name = "half-close|reset",
+        default_value = "half-close",
+        value_parser = parse_local_close_policy
+    )]
+    local_close: LocalClosePolicy,
+    #[arg(
+        long = "max-lifetime-queries",
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    max_lifetime_queries: Option<u64>,
+    #[arg(
+        long = "active-window",
+        value_name = "HH:MM-HH:MM",
+        value_parser = parse_active_window
+    )]
+    active_windows: Vec<ActiveWindow>,
+    /// Keep background activity low while idle during this local-time window.
+    #[arg(
+        long = "quiet-hours",
+        value_name = "HH:MM-HH:MM",
+        value_parser = parse_active_window
+    )]
+    quiet_hours: Vec<ActiveWindow>,
+    /// Report a disconnect or stall only once it lasted this long.
+    #[arg(
+        long = "event-grace-ms",
+        value_name = "MS",
+        default_value_t = DEFAULT_EVENT_GRACE_MS
+    )]
+    event_grace_ms: u64,
+    #[arg(
+        long = "udp-rcvbuf",
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    udp_rcvbuf: Option<u32>,
+    #[arg(
+        long = "udp-sndbuf",
+        value_name = "BYTES",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    udp_sndbuf: Option<u32>,
+    #[arg(long = "source-address", value_name = "IP")]
+    source_address: Option<std::net::IpAddr>,
+    #[arg(
+        long = "max-qname-len",
+        value_name = "CHARS",
+        value_parser = clap::value_parser!(u16).range(1..=253)
+    )]
+    max_qname_len: Option<u16>,
+    #[arg(
+        long = "label-encoding",
+        value_name = "base32|safe",
+        default_value = "base32",
+        value_parser = parse_label_encoding
+    )]
+    label_encoding: LabelEncoding,
+    #[arg(
+        long = "non-tunnel-responses",
+        value_name = "report|avoid",
+        default_value = "report",
+        value_parser = parse_non_tunnel_policy
+    )]
+    non_tunnel_responses: NonTunnelPolicy,
+    #[arg(
+        long = "mode-mismatch",
+        value_name = "report|adapt",
+        default_value = "report",
+        value_parser = parse_mode_mismatch_policy
+    )]
+    mode_mismatch: ModeMismatchPolicy,
+    #[arg(
+        long = "formerr",
+        value_name = "adapt|report",
+        default_value = "adapt",
+        value_parser = parse_formerr_policy
+    )]
+    formerr: FormerrPolicy,
+    #[arg(
+        long = "pipeline-mode",
+        value_name = "pipelined|single",
+        default_value = "pipelined",
+        value_parser = parse_pipeline_mode
+    )]
+    pipeline_mode: PipelineMode,
+    #[arg(
+        long = "log-privacy",
+        value_name = "hash|off",
+        value_parser = parse_log_privacy
+    )]
+    log_privacy: Option<bool>,
+    #[arg(long = "log-file", value_name = "PATH")]
+    log_file: Option<String>,
+    #[arg(long = "log-max-size-mb", value_name = "MB", default_value_t = 10)]
+    log_max_size_mb: u64,
+    #[arg(long = "log-max-files", value_name = "COUNT", default_value_t = 5)]
+    log_max_files: usize,
+    /// Write tunnel events as newline-delimited JSON to stderr or an open file descriptor.
+    #[arg(
+        long = "events-json",
+        value_name = "stderr|FD",
+        value_parser = parse_event_stream
+    )]
+    events_json: Option<EventStream>,
+    /// Export connection, stream and query spans as OTLP over HTTP to this traces URL.
+    #[cfg(feature = "otel")]
+    #[arg(long = "otel-endpoint", value_name = "URL")]
+    otel_endpoint: Option<String>,
+}
+
+/// Where `--events-json` writes tunnel events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventStream {
+    Stderr,
+    Fd(RawFd),
+}
+
+impl EventStream {
+    fn open(self) -> std::io::Result<Box<dyn std::io::Write + Send>> {
+        match self {
+            EventStream::Stderr => Ok(Box::new(std::io::stderr())),
+            EventStream::Fd(fd) => {
+                // SAFETY: F_GETFD only reads the descriptor flags.
+                if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // SAFETY: the descriptor is open and was handed to us for this
+                // stream; nothing else in the process uses it.
+                Ok(Box::new(unsafe { std::fs::File::from_raw_fd(fd) }))
+            }
+        }
+    }
 }
 
 fn main() {
-    init_logging();
     let matches = Args::command().get_matches();
     let args = Args::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let log_file = args.log_file.as_deref().map(|path| {
+        RotatingFile::open(
+            std::path::Path::new(path),
+            args.log_max_size_mb.saturating_mul(1024 * 1024),
+            args.log_max_files,
+        )
+        .unwrap_or_else(|err| {
+            init_logging(None, None);
+            tracing::error!("Failed to open log file {}: {}", path, err);
+            std::process::exit(2);
+        })
+    });
+    #[cfg(feature = "otel")]
+    let (span_layer, otel_guard) = match args.otel_endpoint.as_deref().map(otel::otel_layer) {
+        Some(Ok((layer, guard))) => (Some(layer.boxed()), Some(guard)),
+        Some(Err(err)) => {
+            init_logging(None, None);
+            tracing::error!("{}", err);
+            std::process::exit(2);
+        }
+        None => (None, None),
+    };
+    #[cfg(not(feature = "otel"))]
+    let span_layer = None;
+    init_logging(log_file, span_layer);
+    if let Some(target) = args.events_json {
+        match target.open() {
+            Ok(stream) => events::set_event_stream(stream),
+            Err(err) => {
+                tracing::error!("Failed to open event stream {:?}: {}", target, err);
+                std::process::exit(2);
+            }
+        }
+    }
+    if let Some(enabled) = args.log_privacy {
+        redact::set_log_privacy(enabled);
+    }
     let sip003_env = sip003::read_sip003_env().unwrap_or_else(|err| {
         tracing::error!("SIP003 env error: {}", err);
         std::process::exit(2);
@@ -103,6 +569,16 @@ fn main() {
         }
     };
 
+    if let Some(path) = args.replay_dns.as_deref() {
+        match replay_capture_file(path, &domain) {
+            Ok(()) => std::process::exit(0),
+            Err(err) => {
+                tracing::error!("Replay error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+
     let cli_has_resolvers = has_cli_resolvers(&matches);
     let resolvers = if cli_has_resolvers {
         build_resolvers(&matches, true).unwrap_or_else(|err| {
@@ -147,13 +623,24 @@ fn main() {
         }
     };
 
+    let tuning = args.optimize_for.tuning();
+    if args.optimize_for != OptimizeFor::Balanced {
+        tracing::info!(
+            "Optimizing for {:?}: {:?} (explicit flags take precedence)",
+            args.optimize_for,
+            tuning
+        );
+    }
+
     let congestion_control = if args.congestion_control.is_some() {
         args.congestion_control.clone()
     } else {
-        parse_congestion_control(&sip003_env.plugin_options).unwrap_or_else(|err| {
-            tracing::error!("SIP003 env error: {}", err);
-            std::process::exit(2);
-        })
+        parse_congestion_control(&sip003_env.plugin_options)
+            .unwrap_or_else(|err| {
+                tracing::error!("SIP003 env error: {}", err);
+                std::process::exit(2);
+            })
+            .or_else(|| tuning.congestion_control.map(str::to_string))
     };
 
     let cert = if args.cert.is_some() {
@@ -175,7 +662,17 @@ fn main() {
                 tracing::error!("SIP003 env error: {}", err);
                 std::process::exit(2);
             });
-        keep_alive_override.unwrap_or(args.keep_alive_interval)
+        keep_alive_override.unwrap_or(tuning.keep_alive_interval)
+    };
+    let coalesce_delay_ms = if cli_provided(&matches, "coalesce_delay_ms") {
+        args.coalesce_delay_ms
+    } else {
+        tuning.coalesce_delay_ms
+    };
+    let resolver_preference = if cli_provided(&matches, "resolver_preference") {
+        args.resolver_preference
+    } else {
+        tuning.resolver_preference
     };
 
     let config = ClientConfig {
@@ -187,8 +684,75 @@ fn main() {
         domain: &domain,
         cert: cert.as_deref(),
         keep_alive_interval: keep_alive_interval as usize,
+        adaptive_keep_alive: args.adaptive_keep_alive,
         debug_poll: args.debug_poll,
         debug_streams: args.debug_streams,
+        record_dns: args.record_dns.as_deref(),
+        query_log: args.query_log.as_deref(),
+        flight_recorder_records: 0,
+        resolver_failure_threshold: args.resolver_failure_threshold,
+        resolver_grace_period_ms: args.resolver_grace_period_ms,
+        resolver_recovery_probe_ms: args.resolver_recovery_probe_ms,
+        resolver_servfail_threshold: args.resolver_servfail_threshold,
+        resolver_servfail_cooldown_ms: args.resolver_servfail_cooldown_ms,
+        confirm_resolvers: args.confirm_resolvers as usize,
+        handshake_redundancy: args.handshake_redundancy as usize,
+        status_host: &args.status_host,
+        status_port: args.status_port,
+        status_control: args.status_control,
+        dns_listen_host: &args.dns_listen_host,
+        dns_listen_port: args.dns_listen_port,
+        dns_upstream: &args.dns_upstream,
+        dns_socks_username: args.dns_socks_user.as_deref(),
+        dns_socks_password: args.dns_socks_pass.as_deref(),
+        dns_id_strategy: args.dns_id,
+        query_class: args.query_class,
+        edns_cookies: args.edns_cookies,
+        adaptive_query_rate: args.adaptive_query_rate,
+        query_ramp_qps: args.query_ramp,
+        query_ramp_doubling_ms: args.query_ramp_doubling_ms,
+        coalesce_delay_ms,
+        reconnect_min_ms: args.reconnect_min_ms,
+        reconnect_max_ms: args.reconnect_max_ms,
+        reconnect_jitter: args.reconnect_jitter,
+        max_reconnect_attempts: args.max_reconnect_attempts,
+        max_reconnect_duration_ms: args.max_reconnect_duration_ms,
+        block_backoff_after: args.block_backoff_after,
+        block_backoff_ms: args.block_backoff_ms,
+        resolver_role_policy: args.resolver_role_policy,
+        resolver_preference,
+        max_parallel_probes: args.max_parallel_probes.map(|count| count as usize),
+        poll_timeout_ms: args.poll_timeout_ms,
+        idle_poll_min_ms: args.idle_poll_min_ms,
+        idle_poll_max_ms: args.idle_poll_max_ms,
+        idle_refresh_ms: args.idle_refresh_ms,
+        poll_pool: usize::from(args.poll_pool),
+        stream_striping: args.stream_striping,
+        prioritize_interactive: args.prioritize_interactive,
+        congestion_isolation: args.congestion_isolation,
+        first_stream_boost_count: args.first_stream_boost as usize,
+        first_stream_boost_ms: args.first_stream_boost_ms,
+        max_connection_setups: args.max_connection_setups.map(|limit| limit as usize),
+        reassembly_limit_bytes: args.reassembly_limit.map(|bytes| bytes as usize),
+        local_close_policy: args.local_close,
+        protection_mode: ProtectionMode::VpnProtected,
+        protection_loss_policy: ProtectionLossPolicy::Hold,
+        udp_rcvbuf_bytes: args.udp_rcvbuf.map(|bytes| bytes as usize),
+        udp_sndbuf_bytes: args.udp_sndbuf.map(|bytes| bytes as usize),
+        source_address: args.source_address,
+        max_qname_len: args.max_qname_len.map(usize::from),
+        label_encoding: args.label_encoding,
+        non_tunnel_policy: args.non_tunnel_responses,
+        mode_mismatch_policy: args.mode_mismatch,
+        formerr_policy: args.formerr,
+        max_lifetime_queries: args.max_lifetime_queries,
+        pipeline_mode: args.pipeline_mode,
+        active_windows: &args.active_windows,
+        quiet_hours: &args.quiet_hours,
+        event_grace_ms: args.event_grace_ms,
+        congestion_controller: None,
+        resolver_transport: None,
+        query_hook: None,
     };
 
     let runtime = Builder::new_current_thread()
@@ -196,28 +760,245 @@ fn main() {
         .enable_time()
         .build()
         .expect("Failed to build Tokio runtime");
-    match runtime.block_on(run_client(&config)) {
-        Ok(code) => std::process::exit(code),
+    let code = match runtime.block_on(run_client(&config)) {
+        Ok(code) => code,
         Err(err) => {
             tracing::error!("Client error: {}", err);
-            std::process::exit(1);
+            // A bad listen host is a usage error, like the argument checks above.
+            match err.kind() {
+                ClientErrorKind::InvalidListenHost => 2,
+                ClientErrorKind::InvalidResolverIndex | ClientErrorKind::Other => 1,
+            }
         }
-    }
+    };
+    // Exiting skips destructors, so queued spans are flushed first.
+    #[cfg(feature = "otel")]
+    drop(otel_guard);
+    std::process::exit(code);
 }
 
-fn init_logging() {
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    let _ = tracing_subscriber::fmt()
-        .with_env_filter(filter)
+/// `span_layer` gets the spans whatever `RUST_LOG` says, so the log output
+/// has a filter of its own.
+fn init_logging(
+    log_file: Option<RotatingFile>,
+    span_layer: Option<Box<dyn Layer<Registry> + Send + Sync>>,
+) {
+    let stdout = fmt::layer()
         .with_target(false)
         .without_time()
+        .with_filter(reloadable_filter());
+    // Files are read after the fact, so they keep timestamps and drop colors.
+    let file = log_file.map(|file| {
+        fmt::layer()
+            .with_target(false)
+            .with_ansi(false)
+            .with_writer(Mutex::new(file))
+            .with_filter(reloadable_filter())
+    });
+    let _ = tracing_subscriber::registry()
+        .with(span_layer)
+        .with(stdout)
+        .with(file)
         .try_init();
 }
 
+fn replay_capture_file(path: &str, domain: &str) -> Result<(), String> {
+    let input = std::fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read DNS capture {}: {}", path, err))?;
+    let records = parse_capture(&input).map_err(|err| err.to_string())?;
+    let mut decoded = 0usize;
+    for (idx, record) in records.iter().enumerate() {
+        match replay_record(record, &[domain]) {
+            ReplayOutcome::Payload(len) => {
+                decoded += 1;
+                tracing::info!(
+                    "record {}: {:?} {} bytes -> payload {} bytes",
+                    idx,
+                    record.direction,
+                    record.packet.len(),
+                    len
+                );
+            }
+            outcome => {
+                tracing::warn!(
+                    "record {}: {:?} {} bytes -> {:?}",
+                    idx,
+                    record.direction,
+                    record.packet.len(),
+                    outcome
+                );
+            }
+        }
+    }
+    tracing::info!(
+        "Replayed {} records: decoded={} failed={}",
+        records.len(),
+        decoded,
+        records.len() - decoded
+    );
+    Ok(())
+}
+
 fn parse_domain(input: &str) -> Result<String, String> {
     normalize_domain(input).map_err(|err| err.to_string())
 }
 
+fn parse_dns_id_strategy(input: &str) -> Result<DnsIdStrategy, String> {
+    match input {
+        "random" => Ok(DnsIdStrategy::Random),
+        "sequential" => Ok(DnsIdStrategy::Sequential),
+        _ => match input.strip_prefix("fixed:") {
+            Some(id) => id
+                .parse::<u16>()
+                .map(DnsIdStrategy::Fixed)
+                .map_err(|_| format!("Invalid fixed DNS ID: {}", id)),
+            None => Err(format!(
+                "Invalid DNS ID strategy {} (expected random, sequential, or fixed:ID)",
+                input
+            )),
+        },
+    }
+}
+
+fn parse_query_class(input: &str) -> Result<u16, String> {
+    match input.to_ascii_lowercase().as_str() {
+        "in" => Ok(CLASS_IN),
+        "ch" => Ok(CLASS_CH),
+        other => match other.parse::<u16>() {
+            Ok(class) if class != 0 => Ok(class),
+            _ => Err(format!(
+                "Invalid query class {} (expected in, ch, or a class number)",
+                input
+            )),
+        },
+    }
+}
+
+fn parse_optimize_for(input: &str) -> Result<OptimizeFor, String> {
+    match input {
+        "latency" => Ok(OptimizeFor::Latency),
+        "balanced" => Ok(OptimizeFor::Balanced),
+        "throughput" => Ok(OptimizeFor::Throughput),
+        _ => Err(format!(
+            "Invalid optimize-for mode {} (expected latency, balanced, or throughput)",
+            input
+        )),
+    }
+}
+
+fn parse_resolver_role_policy(input: &str) -> Result<ResolverRolePolicy, String> {
+    match input {
+        "mixed" => Ok(ResolverRolePolicy::Mixed),
+        "split" => Ok(ResolverRolePolicy::Split),
+        _ => Err(format!(
+            "Invalid resolver role policy {} (expected mixed or split)",
+            input
+        )),
+    }
+}
+
+fn parse_resolver_preference(input: &str) -> Result<ResolverPreference, String> {
+    match input {
+        "order" => Ok(ResolverPreference::Order),
+        "bandwidth" => Ok(ResolverPreference::Bandwidth),
+        _ => Err(format!(
+            "Invalid resolver preference {} (expected order or bandwidth)",
+            input
+        )),
+    }
+}
+
+fn parse_label_encoding(input: &str) -> Result<LabelEncoding, String> {
+    match input {
+        "base32" => Ok(LabelEncoding::Base32),
+        "safe" => Ok(LabelEncoding::Safe),
+        _ => Err(format!(
+            "Invalid label encoding {} (expected base32 or safe)",
+            input
+        )),
+    }
+}
+
+fn parse_non_tunnel_policy(input: &str) -> Result<NonTunnelPolicy, String> {
+    match input {
+        "report" => Ok(NonTunnelPolicy::Report),
+        "avoid" => Ok(NonTunnelPolicy::Avoid),
+        _ => Err(format!(
+            "Invalid non-tunnel response policy {} (expected report or avoid)",
+            input
+        )),
+    }
+}
+
+fn parse_mode_mismatch_policy(input: &str) -> Result<ModeMismatchPolicy, String> {
+    match input {
+        "report" => Ok(ModeMismatchPolicy::Report),
+        "adapt" => Ok(ModeMismatchPolicy::Adapt),
+        _ => Err(format!(
+            "Invalid resolver mode mismatch policy {} (expected report or adapt)",
+            input
+        )),
+    }
+}
+
+fn parse_formerr_policy(input: &str) -> Result<FormerrPolicy, String> {
+    match input {
+        "adapt" => Ok(FormerrPolicy::Adapt),
+        "report" => Ok(FormerrPolicy::Report),
+        _ => Err(format!(
+            "Invalid FORMERR policy {} (expected adapt or report)",
+            input
+        )),
+    }
+}
+
+fn parse_pipeline_mode(input: &str) -> Result<PipelineMode, String> {
+    match input {
+        "pipelined" => Ok(PipelineMode::Pipelined),
+        "single" => Ok(PipelineMode::Single),
+        _ => Err(format!(
+            "Invalid pipeline mode {} (expected pipelined or single)",
+            input
+        )),
+    }
+}
+
+fn parse_local_close_policy(input: &str) -> Result<LocalClosePolicy, String> {
+    match input {
+        "half-close" => Ok(LocalClosePolicy::HalfClose),
+        "reset" => Ok(LocalClosePolicy::Reset),
+        _ => Err(format!(
+            "Invalid local close policy {} (expected half-close or reset)",
+            input
+        )),
+    }
+}
+
+fn parse_log_privacy(input: &str) -> Result<bool, String> {
+    match input {
+        "hash" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!(
+            "Invalid log privacy mode {} (expected hash or off)",
+            input
+        )),
+    }
+}
+
+fn parse_event_stream(input: &str) -> Result<EventStream, String> {
+    if input == "stderr" {
+        return Ok(EventStream::Stderr);
+    }
+    match input.parse::<RawFd>() {
+        // 0 is stdin.
+        Ok(fd) if fd >= 1 => Ok(EventStream::Fd(fd)),
+        _ => Err(format!(
+            "Invalid event stream {} (expected stderr or a file descriptor number)",
+            input
+        )),
+    }
+}
+
 fn parse_resolver(input: &str) -> Result<HostPort, String> {
     parse_host_port(input, 53, AddressKind::Resolver).map_err(|err| err.to_string())
 }
@@ -442,6 +1223,109 @@ mod tests {
         assert!(!parsed.authoritative_remote);
     }
 
+    #[test]
+    fn parses_dns_id_strategies() {
+        assert_eq!(parse_dns_id_strategy("random"), Ok(DnsIdStrategy::Random));
+        assert_eq!(
+            parse_dns_id_strategy("sequential"),
+            Ok(DnsIdStrategy::Sequential)
+        );
+        assert_eq!(
+            parse_dns_id_strategy("fixed:4660"),
+            Ok(DnsIdStrategy::Fixed(4660))
+        );
+        assert!(parse_dns_id_strategy("fixed:70000").is_err());
+        assert!(parse_dns_id_strategy("counter").is_err());
+    }
+
+    #[test]
+    fn parses_resolver_role_policies() {
+        assert_eq!(
+            parse_resolver_role_policy("mixed"),
+            Ok(ResolverRolePolicy::Mixed)
+        );
+        assert_eq!(
+            parse_resolver_role_policy("split"),
+            Ok(ResolverRolePolicy::Split)
+        );
+        assert!(parse_resolver_role_policy("authoritative").is_err());
+    }
+
+    #[test]
+    fn parses_local_close_policies() {
+        assert_eq!(
+            parse_local_close_policy("half-close"),
+            Ok(LocalClosePolicy::HalfClose)
+        );
+        assert_eq!(
+            parse_local_close_policy("reset"),
+            Ok(LocalClosePolicy::Reset)
+        );
+        assert!(parse_local_close_policy("abort").is_err());
+    }
+
+    #[test]
+    fn parses_label_encodings() {
+        assert_eq!(parse_label_encoding("base32"), Ok(LabelEncoding::Base32));
+        assert_eq!(parse_label_encoding("safe"), Ok(LabelEncoding::Safe));
+        assert!(parse_label_encoding("hex").is_err());
+    }
+
+    #[test]
+    fn parses_non_tunnel_policies() {
+        assert_eq!(
+            parse_non_tunnel_policy("report"),
+            Ok(NonTunnelPolicy::Report)
+        );
+        assert_eq!(parse_non_tunnel_policy("avoid"), Ok(NonTunnelPolicy::Avoid));
+        assert!(parse_non_tunnel_policy("fail").is_err());
+    }
+
+    #[test]
+    fn parses_mode_mismatch_policies() {
+        assert_eq!(
+            parse_mode_mismatch_policy("report"),
+            Ok(ModeMismatchPolicy::Report)
+        );
+        assert_eq!(
+            parse_mode_mismatch_policy("adapt"),
+            Ok(ModeMismatchPolicy::Adapt)
+        );
+        assert!(parse_mode_mismatch_policy("avoid").is_err());
+    }
+
+    #[test]
+    fn parses_formerr_policies() {
+        assert_eq!(parse_formerr_policy("adapt"), Ok(FormerrPolicy::Adapt));
+        assert_eq!(parse_formerr_policy("report"), Ok(FormerrPolicy::Report));
+        assert!(parse_formerr_policy("ignore").is_err());
+    }
+
+    #[test]
+    fn parses_pipeline_modes() {
+        assert_eq!(
+            parse_pipeline_mode("pipelined"),
+            Ok(PipelineMode::Pipelined)
+        );
+        assert_eq!(parse_pipeline_mode("single"), Ok(PipelineMode::Single));
+        assert!(parse_pipeline_mode("serial").is_err());
+    }
+
+    #[test]
+    fn parses_event_streams() {
+        assert_eq!(parse_event_stream("stderr"), Ok(EventStream::Stderr));
+        assert_eq!(parse_event_stream("3"), Ok(EventStream::Fd(3)));
+        assert!(parse_event_stream("0").is_err());
+        assert!(parse_event_stream("stdout").is_err());
+    }
+
+    #[test]
+    fn parses_log_privacy_modes() {
+        assert_eq!(parse_log_privacy("hash"), Ok(true));
+        assert_eq!(parse_log_privacy("off"), Ok(false));
+        assert!(parse_log_privacy("redact").is_err());
+    }
+
     #[test]
     fn plugin_domain_single_entry() {
         let options = vec![sip003::Sip003Option {
@@ -488,4 +1372,30 @@ mod tests {
         assert!(parsed.resolvers.is_empty());
         assert!(parsed.authoritative_remote);
     }
+
+    #[test]
+    fn balanced_tuning_matches_flag_defaults() {
+        let args = Args::try_parse_from(["slipstream-client", "--domain", "example.com"])
+            .expect("args should parse");
+        assert_eq!(args.optimize_for, OptimizeFor::Balanced);
+        let tuning = args.optimize_for.tuning();
+        assert_eq!(tuning.coalesce_delay_ms, args.coalesce_delay_ms);
+        assert_eq!(tuning.keep_alive_interval, args.keep_alive_interval);
+        assert_eq!(tuning.congestion_control, None);
+        assert_eq!(tuning.resolver_preference, args.resolver_preference);
+        assert_eq!(
+            OptimizeFor::Throughput.tuning().resolver_preference,
+            ResolverPreference::Bandwidth
+        );
+        assert!(parse_optimize_for("speed").is_err());
+    }
+
+    #[test]
+    fn parses_query_class() {
+        assert_eq!(parse_query_class("in"), Ok(CLASS_IN));
+        assert_eq!(parse_query_class("CH"), Ok(CLASS_CH));
+        assert_eq!(parse_query_class("254"), Ok(254));
+        assert!(parse_query_class("0").is_err());
+        assert!(parse_query_class("hs").is_err());
+    }
 }
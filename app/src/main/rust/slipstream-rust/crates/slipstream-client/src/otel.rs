@@ -0,0 +1,52 @@
+//! OTLP export of the spans in [`crate::spans`].
+
+use crate::spans::SPAN_TARGET;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{Protocol, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use tracing::{Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Flushes the spans still queued for export when dropped.
+pub struct OtelGuard {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for OtelGuard {
+    fn drop(&mut self) {
+        if let Err(err) = self.provider.shutdown() {
+            tracing::warn!("Failed to flush OpenTelemetry spans: {}", err);
+        }
+    }
+}
+
+/// Layer exporting the tunnel's spans, and nothing else, as OTLP over HTTP
+/// to `endpoint`, the collector's full traces URL (such as
+/// `http://127.0.0.1:4318/v1/traces`). Spans are batched and sent from a
+/// thread of their own.
+pub fn otel_layer<S>(endpoint: &str) -> Result<(impl Layer<S>, OtelGuard), String>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpBinary)
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|err| format!("Invalid OpenTelemetry endpoint {}: {}", endpoint, err))?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name("slipstream-client")
+                .build(),
+        )
+        .build();
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("slipstream"))
+        .with_filter(Targets::new().with_target(SPAN_TARGET, Level::TRACE));
+    Ok((layer, OtelGuard { provider }))
+}
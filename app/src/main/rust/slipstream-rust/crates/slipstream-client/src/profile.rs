@@ -0,0 +1,389 @@
+use crate::dns::{
+    DEFAULT_POLL_TIMEOUT_MS, DEFAULT_RESOLVER_FAILURE_THRESHOLD, DEFAULT_RESOLVER_GRACE_PERIOD_MS,
+    DEFAULT_RESOLVER_RECOVERY_PROBE_MS, DEFAULT_RESOLVER_SERVFAIL_COOLDOWN_MS,
+    DEFAULT_RESOLVER_SERVFAIL_THRESHOLD,
+};
+use crate::error::ClientError;
+use crate::runtime::{DEFAULT_RECONNECT_MAX_MS, DEFAULT_RECONNECT_MIN_MS};
+use serde::{Deserialize, Serialize};
+use slipstream_core::{normalize_domain, parse_host_port_parts, AddressKind};
+use slipstream_dns::CLASS_IN;
+use slipstream_ffi::{
+    ClientConfig, DnsIdStrategy, LabelEncoding, LocalClosePolicy, ResolverMode, ResolverRolePolicy,
+    ResolverSpec,
+};
+
+/// Format version written by `export_profile`.
+pub const PROFILE_VERSION: u32 = 1;
+
+/// Shareable snapshot of the settings that shape a tunnel session.
+///
+/// Every field has a default, and unknown fields are ignored on import, so a
+/// profile written by a newer build still loads here with the parts we know.
+/// Machine-local and secret settings are never written; their names are listed
+/// in `reentry_required` so the importer can prompt for them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClientProfile {
+    // Defaults to 0 rather than the current version so a missing field is caught.
+    #[serde(default)]
+    pub version: u32,
+    pub domain: String,
+    pub resolvers: Vec<ProfileResolver>,
+    pub listen_host: String,
+    pub listen_port: u16,
+    pub congestion_control: Option<String>,
+    pub gso: bool,
+    pub keep_alive_interval: usize,
+    pub adaptive_keep_alive: bool,
+    pub resolver_failure_threshold: u32,
+    pub resolver_grace_period_ms: u64,
+    pub resolver_recovery_probe_ms: u64,
+    pub resolver_servfail_threshold: u32,
+    pub resolver_servfail_cooldown_ms: u64,
+    /// "random", "sequential" or "fixed:ID", as accepted by `--dns-id`.
+    pub dns_id_strategy: String,
+    pub query_class: u16,
+    pub edns_cookies: bool,
+    pub coalesce_delay_ms: u64,
+    pub reconnect_min_ms: u64,
+    pub reconnect_max_ms: u64,
+    pub reconnect_jitter: bool,
+    /// "mixed" or "split", as accepted by `--resolver-role-policy`.
+    pub resolver_role_policy: String,
+    pub poll_timeout_ms: u64,
+    pub stream_striping: bool,
+    /// "half-close" or "reset", as accepted by `--local-close`.
+    pub local_close_policy: String,
+    /// "base32" or "safe", as accepted by `--label-encoding`.
+    pub label_encoding: String,
+    pub reentry_required: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfileResolver {
+    pub host: String,
+    pub port: u16,
+    pub authoritative: bool,
+}
+
+impl Default for ClientProfile {
+    fn default() -> Self {
+        Self {
+            version: PROFILE_VERSION,
+            domain: String::new(),
+            resolvers: Vec::new(),
+            listen_host: "::".to_string(),
+            listen_port: 5201,
+            congestion_control: None,
+            gso: false,
+            keep_alive_interval: 400,
+            adaptive_keep_alive: false,
+            resolver_failure_threshold: DEFAULT_RESOLVER_FAILURE_THRESHOLD,
+            resolver_grace_period_ms: DEFAULT_RESOLVER_GRACE_PERIOD_MS,
+            resolver_recovery_probe_ms: DEFAULT_RESOLVER_RECOVERY_PROBE_MS,
+            resolver_servfail_threshold: DEFAULT_RESOLVER_SERVFAIL_THRESHOLD,
+            resolver_servfail_cooldown_ms: DEFAULT_RESOLVER_SERVFAIL_COOLDOWN_MS,
+            dns_id_strategy: "random".to_string(),
+            query_class: CLASS_IN,
+            edns_cookies: false,
+            coalesce_delay_ms: 0,
+            reconnect_min_ms: DEFAULT_RECONNECT_MIN_MS,
+            reconnect_max_ms: DEFAULT_RECONNECT_MAX_MS,
+            reconnect_jitter: false,
+            resolver_role_policy: "mixed".to_string(),
+            poll_timeout_ms: DEFAULT_POLL_TIMEOUT_MS,
+            stream_striping: false,
+            local_close_policy: "half-close".to_string(),
+            label_encoding: "base32".to_string(),
+            reentry_required: Vec::new(),
+        }
+    }
+}
+
+impl Default for ProfileResolver {
+    fn default() -> Self {
+        Self {
+            host: String::new(),
+            port: 53,
+            authoritative: false,
+        }
+    }
+}
+
+impl ClientProfile {
+    pub fn from_config(config: &ClientConfig<'_>) -> Self {
+        let mut reentry_required = Vec::new();
+        // The pinned certificate is a path on this machine, so it cannot travel.
+        if config.cert.is_some() {
+            reentry_required.push("cert".to_string());
+        }
+        Self {
+            version: PROFILE_VERSION,
+            domain: config.domain.to_string(),
+            resolvers: config
+                .resolvers
+                .iter()
+                .map(|spec| ProfileResolver {
+                    host: spec.resolver.host.clone(),
+                    port: spec.resolver.port,
+                    authoritative: spec.mode == ResolverMode::Authoritative,
+                })
+                .collect(),
+            listen_host: config.tcp_listen_host.to_string(),
+            listen_port: config.tcp_listen_port,
+            congestion_control: config.congestion_control.map(str::to_string),
+            gso: config.gso,
+            keep_alive_interval: config.keep_alive_interval,
+            adaptive_keep_alive: config.adaptive_keep_alive,
+            resolver_failure_threshold: config.resolver_failure_threshold,
+            resolver_grace_period_ms: config.resolver_grace_period_ms,
+            resolver_recovery_probe_ms: config.resolver_recovery_probe_ms,
+            resolver_servfail_threshold: config.resolver_servfail_threshold,
+            resolver_servfail_cooldown_ms: config.resolver_servfail_cooldown_ms,
+            dns_id_strategy: match config.dns_id_strategy {
+                DnsIdStrategy::Random => "random".to_string(),
+                DnsIdStrategy::Sequential => "sequential".to_string(),
+                DnsIdStrategy::Fixed(id) => format!("fixed:{}", id),
+            },
+            query_class: config.query_class,
+            edns_cookies: config.edns_cookies,
+            coalesce_delay_ms: config.coalesce_delay_ms,
+            reconnect_min_ms: config.reconnect_min_ms,
+            reconnect_max_ms: config.reconnect_max_ms,
+            reconnect_jitter: config.reconnect_jitter,
+            resolver_role_policy: match config.resolver_role_policy {
+                ResolverRolePolicy::Mixed => "mixed".to_string(),
+                ResolverRolePolicy::Split => "split".to_string(),
+            },
+            poll_timeout_ms: config.poll_timeout_ms,
+            stream_striping: config.stream_striping,
+            local_close_policy: match config.local_close_policy {
+                LocalClosePolicy::HalfClose => "half-close".to_string(),
+                LocalClosePolicy::Reset => "reset".to_string(),
+            },
+            label_encoding: match config.label_encoding {
+                LabelEncoding::Base32 => "base32".to_string(),
+                LabelEncoding::Safe => "safe".to_string(),
+            },
+            reentry_required,
+        }
+    }
+
+    pub fn resolver_specs(&self) -> Result<Vec<ResolverSpec>, ClientError> {
+        self.resolvers
+            .iter()
+            .map(|resolver| {
+                let address =
+                    parse_host_port_parts(&resolver.host, resolver.port, AddressKind::Resolver)
+                        .map_err(|err| ClientError::new(err.to_string()))?;
+                Ok(ResolverSpec {
+                    resolver: address,
+                    mode: if resolver.authoritative {
+                        ResolverMode::Authoritative
+                    } else {
+                        ResolverMode::Recursive
+                    },
+                })
+            })
+            .collect()
+    }
+
+    pub fn dns_id_strategy(&self) -> Result<DnsIdStrategy, ClientError> {
+        match self.dns_id_strategy.as_str() {
+            "random" => Ok(DnsIdStrategy::Random),
+            "sequential" => Ok(DnsIdStrategy::Sequential),
+            other => other
+                .strip_prefix("fixed:")
+                .and_then(|id| id.parse::<u16>().ok())
+                .map(DnsIdStrategy::Fixed)
+                .ok_or_else(|| ClientError::new(format!("Invalid DNS ID strategy: {}", other))),
+        }
+    }
+
+    pub fn resolver_role_policy(&self) -> Result<ResolverRolePolicy, ClientError> {
+        match self.resolver_role_policy.as_str() {
+            "mixed" => Ok(ResolverRolePolicy::Mixed),
+            "split" => Ok(ResolverRolePolicy::Split),
+            other => Err(ClientError::new(format!(
+                "Invalid resolver role policy: {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn local_close_policy(&self) -> Result<LocalClosePolicy, ClientError> {
+        match self.local_close_policy.as_str() {
+            "half-close" => Ok(LocalClosePolicy::HalfClose),
+            "reset" => Ok(LocalClosePolicy::Reset),
+            other => Err(ClientError::new(format!(
+                "Invalid local close policy: {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn label_encoding(&self) -> Result<LabelEncoding, ClientError> {
+        match self.label_encoding.as_str() {
+            "base32" => Ok(LabelEncoding::Base32),
+            "safe" => Ok(LabelEncoding::Safe),
+            other => Err(ClientError::new(format!(
+                "Invalid label encoding: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Serialize the effective configuration as a versioned JSON profile.
+pub fn export_profile(config: &ClientConfig<'_>) -> String {
+    serde_json::to_string(&ClientProfile::from_config(config)).unwrap_or_default()
+}
+
+/// Parse and validate a JSON profile.
+///
+/// Newer versions are accepted as long as the fields known here are valid.
+pub fn import_profile(json: &str) -> Result<ClientProfile, ClientError> {
+    let mut profile: ClientProfile = serde_json::from_str(json)
+        .map_err(|err| ClientError::new(format!("Invalid profile: {}", err)))?;
+    if profile.version == 0 {
+        return Err(ClientError::new("Invalid profile: missing version"));
+    }
+    profile.domain =
+        normalize_domain(&profile.domain).map_err(|err| ClientError::new(err.to_string()))?;
+    if profile.resolvers.is_empty() {
+        return Err(ClientError::new("Invalid profile: no resolvers"));
+    }
+    profile.resolver_specs()?;
+    profile.dns_id_strategy()?;
+    profile.resolver_role_policy()?;
+    profile.local_close_policy()?;
+    profile.label_encoding()?;
+    if profile.reconnect_min_ms > profile.reconnect_max_ms {
+        return Err(ClientError::new(
+            "Invalid profile: reconnect_min_ms exceeds reconnect_max_ms",
+        ));
+    }
+    Ok(profile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_config;
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_ffi::NonTunnelPolicy;
+
+    fn config<'a>(resolvers: &'a [ResolverSpec], cert: Option<&'a str>) -> ClientConfig<'a> {
+        // Away from the defaults wherever the profile has a field, so a
+        // round trip that drops one shows.
+        ClientConfig {
+            cert,
+            congestion_control: Some("bbr"),
+            adaptive_keep_alive: true,
+            resolver_grace_period_ms: 1_000,
+            resolver_recovery_probe_ms: 2_000,
+            resolver_servfail_threshold: 4,
+            resolver_servfail_cooldown_ms: 60_000,
+            dns_id_strategy: DnsIdStrategy::Fixed(4660),
+            query_class: 3,
+            edns_cookies: true,
+            coalesce_delay_ms: 5,
+            reconnect_min_ms: 100,
+            reconnect_max_ms: 1_000,
+            reconnect_jitter: true,
+            resolver_role_policy: ResolverRolePolicy::Split,
+            poll_timeout_ms: 8_000,
+            stream_striping: true,
+            local_close_policy: LocalClosePolicy::Reset,
+            label_encoding: LabelEncoding::Safe,
+            non_tunnel_policy: NonTunnelPolicy::Avoid,
+            ..test_config::config("t.example.com", resolvers)
+        }
+    }
+
+    fn resolvers() -> Vec<ResolverSpec> {
+        vec![
+            ResolverSpec {
+                resolver: HostPort {
+                    host: "1.1.1.1".to_string(),
+                    port: 53,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+            },
+            ResolverSpec {
+                resolver: HostPort {
+                    host: "2001:db8::1".to_string(),
+                    port: 5353,
+                    family: AddressFamily::V6,
+                },
+                mode: ResolverMode::Authoritative,
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trips_exported_profile() {
+        let resolvers = resolvers();
+        let json = export_profile(&config(&resolvers, None));
+        let profile = import_profile(&json).expect("import");
+        assert_eq!(
+            profile,
+            ClientProfile::from_config(&config(&resolvers, None))
+        );
+        assert_eq!(
+            profile.dns_id_strategy().unwrap(),
+            DnsIdStrategy::Fixed(4660)
+        );
+        assert_eq!(
+            profile.resolver_role_policy().unwrap(),
+            ResolverRolePolicy::Split
+        );
+        assert_eq!(
+            profile.local_close_policy().unwrap(),
+            LocalClosePolicy::Reset
+        );
+        assert_eq!(profile.label_encoding().unwrap(), LabelEncoding::Safe);
+        let specs = profile.resolver_specs().unwrap();
+        assert_eq!(specs[1].resolver.family, AddressFamily::V6);
+        assert_eq!(specs[1].mode, ResolverMode::Authoritative);
+    }
+
+    #[test]
+    fn redacts_certificate_path() {
+        let resolvers = resolvers();
+        let json = export_profile(&config(&resolvers, Some("/home/user/cert.pem")));
+        assert!(!json.contains("cert.pem"));
+        let profile = import_profile(&json).expect("import");
+        assert_eq!(profile.reentry_required, vec!["cert".to_string()]);
+    }
+
+    #[test]
+    fn ignores_unknown_fields_and_fills_defaults() {
+        let json = r#"{"version":7,"domain":"t.example.com.","resolvers":[{"host":"8.8.8.8"}],
+            "routing_rules":[{"match":"*"}]}"#;
+        let profile = import_profile(json).expect("import");
+        assert_eq!(profile.version, 7);
+        assert_eq!(profile.domain, "t.example.com");
+        assert_eq!(profile.resolvers[0].port, 53);
+        assert_eq!(profile.listen_port, 5201);
+        assert_eq!(profile.dns_id_strategy().unwrap(), DnsIdStrategy::Random);
+        assert_eq!(profile.label_encoding().unwrap(), LabelEncoding::Base32);
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        assert!(import_profile("not json").is_err());
+        assert!(
+            import_profile(r#"{"domain":"t.example.com","resolvers":[{"host":"1.1.1.1"}]}"#)
+                .is_err()
+        );
+        assert!(import_profile(r#"{"version":1,"domain":"t.example.com"}"#).is_err());
+        assert!(import_profile(
+            r#"{"version":1,"domain":"t.example.com","resolvers":[{"host":"1.1.1.1"}],"resolver_role_policy":"all"}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! Redaction of resolver addresses in log output.
+//!
+//! With log privacy on, an address is logged as a short keyed hash instead. The
+//! key is drawn once per process, so one resolver keeps the same hash for the
+//! whole run and can still be followed through a shared log, but hashes cannot
+//! be reversed or matched across runs. Release builds redact by default.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+static LOG_PRIVACY: AtomicBool = AtomicBool::new(!cfg!(debug_assertions));
+static HASH_KEY: OnceLock<RandomState> = OnceLock::new();
+
+/// Turns address redaction in log output on or off.
+pub fn set_log_privacy(enabled: bool) {
+    LOG_PRIVACY.store(enabled, Ordering::Relaxed);
+}
+
+pub fn log_privacy() -> bool {
+    LOG_PRIVACY.load(Ordering::Relaxed)
+}
+
+/// Wraps a value so it formats as a per-run hash while log privacy is on.
+pub(crate) fn redact<T: fmt::Display>(value: T) -> Redacted<T> {
+    Redacted(value)
+}
+
+pub(crate) struct Redacted<T>(T);
+
+impl<T: fmt::Display> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if log_privacy() {
+            f.write_str(&hashed(&self.0, HASH_KEY.get_or_init(RandomState::new)))
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+fn hashed(value: &impl fmt::Display, key: &RandomState) -> String {
+    format!("#{:08x}", key.hash_one(value.to_string()) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn hash_is_stable_within_a_run_and_hides_the_address() {
+        let key = RandomState::new();
+        let addr: SocketAddr = "192.0.2.7:53".parse().unwrap();
+        let other: SocketAddr = "192.0.2.8:53".parse().unwrap();
+
+        let label = hashed(&addr, &key);
+        assert_eq!(label.len(), 9);
+        assert!(label.starts_with('#'));
+        assert!(!label.contains("192.0.2"));
+        assert_eq!(hashed(&addr, &key), label);
+        assert_ne!(hashed(&other, &key), label);
+    }
+}
@@ -1,23 +1,88 @@
+mod backoff;
+mod block;
+mod confirm;
+mod doze;
+mod force;
+mod idle;
+mod keepalive;
+mod learned;
+mod limit;
+mod offline;
 mod path;
+mod primary;
+mod protection;
+mod quiet;
+mod redundancy;
+mod refresh;
+mod reload;
+mod schedule;
 mod setup;
 
+use self::backoff::{ReconnectBackoff, ReconnectLimit};
+pub use self::backoff::{
+    DEFAULT_MAX_RECONNECT_ATTEMPTS, DEFAULT_RECONNECT_MAX_MS, DEFAULT_RECONNECT_MIN_MS,
+};
+pub use self::block::DEFAULT_BLOCK_BACKOFF_MS;
+use self::block::{AttemptFailure, BlockDetector};
+use self::confirm::ConnectConfirmation;
+use self::doze::{SleepDetector, DOZE_RECONNECT_AFTER};
+// Called by the Android bridge, the status endpoint and library callers.
+#[allow(unused_imports)]
+pub use self::force::force_resolver;
+use self::force::{take_force_request, ForceTarget, ResolverPin};
+use self::idle::IdlePolling;
+pub use self::idle::DEFAULT_IDLE_POLL_MIN_MS;
+use self::keepalive::AdaptiveKeepAlive;
+use self::learned::{learned_tuning, seed_learned_tuning};
+pub(crate) use self::limit::QueryBudget;
+use self::offline::{OfflineWait, OFFLINE_RECHECK};
 use self::path::{
-    apply_path_mode, drain_path_events, fetch_path_quality, find_resolver_by_addr_mut,
-    loop_burst_total, path_poll_burst_max,
+    apply_path_mode, apply_resolver_roles, drain_path_events, fetch_congestion_state,
+    fetch_path_quality, find_resolver_by_addr_mut, hold_spiking_paths, loop_burst_total,
+    path_poll_burst_max, update_bulk_window, StreamStriper,
+};
+use self::primary::PrimaryTracker;
+use self::protection::ProtectionMonitor;
+use self::quiet::QuietHours;
+use self::redundancy::HandshakeRedundancy;
+use self::refresh::{IdleRefresh, RefreshAction};
+// Called by the Android bridge, the status endpoint and library callers.
+#[allow(unused_imports)]
+pub use self::reload::{append_resolvers, reload_config, ReloadReport, HOT_RELOAD_FIELDS};
+use self::reload::{take_reload, LiveSettings, ReloadTarget};
+pub use self::schedule::parse_active_window;
+use self::schedule::{format_minute_of_day, local_minute_of_day, ActiveSchedule};
+use self::setup::{
+    bind_tcp_listener, bind_udp_socket, map_io, reprotect_udp_socket, UdpBufferSizes,
+};
+pub(crate) use self::setup::{
+    build_query_name, compute_mtu, max_query_payload, parse_listen_host, qname_limit,
 };
-use self::setup::{bind_tcp_listener, bind_udp_socket, compute_mtu, map_io};
 
 // Android-specific imports for state signaling
 #[cfg(target_os = "android")]
+pub(crate) use crate::android::request_shutdown;
+#[cfg(target_os = "android")]
 use crate::android::{
-    exceeded_max_failures, record_connection_failure, reset_quic_ready, should_shutdown,
-    signal_listener_ready, signal_quic_ready,
+    exceeded_max_failures, notify_limit_reached, notify_network_offline,
+    notify_persistent_block_suspected, notify_protection_lost, notify_resolver_changed,
+    notify_scheduled_pause, notify_tunnel_event, notify_woke_from_doze, record_connection_failure,
+    register_command_sender, reset_quic_ready, should_shutdown, signal_listener_ready,
+    signal_quic_ready, take_network_changed,
 };
 
 // No-op implementations for non-Android platforms
 #[cfg(not(target_os = "android"))]
+static SHUTDOWN_REQUESTED: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+#[cfg(not(target_os = "android"))]
 fn should_shutdown() -> bool {
-    false
+    SHUTDOWN_REQUESTED.load(std::sync::atomic::Ordering::SeqCst)
+}
+/// Ask the runtime to stop at its next shutdown check.
+#[cfg(not(target_os = "android"))]
+pub(crate) fn request_shutdown() {
+    SHUTDOWN_REQUESTED.store(true, std::sync::atomic::Ordering::SeqCst);
 }
 #[cfg(not(target_os = "android"))]
 fn signal_listener_ready() {}
@@ -31,20 +96,53 @@ fn record_connection_failure() {}
 fn exceeded_max_failures() -> bool {
     false
 }
+#[cfg(not(target_os = "android"))]
+fn register_command_sender(_command_tx: mpsc::UnboundedSender<Command>) {}
+#[cfg(not(target_os = "android"))]
+fn notify_resolver_changed(_old: &str, _new: &str, _reason: &str) {}
+#[cfg(not(target_os = "android"))]
+fn notify_limit_reached(_queries: u64) {}
+#[cfg(not(target_os = "android"))]
+fn notify_woke_from_doze(_slept_ms: u64) {}
+#[cfg(not(target_os = "android"))]
+fn notify_protection_lost() {}
+#[cfg(not(target_os = "android"))]
+fn notify_scheduled_pause(_paused: bool) {}
+#[cfg(not(target_os = "android"))]
+fn notify_persistent_block_suspected(_failure: &str, _attempts: u32) {}
+#[cfg(not(target_os = "android"))]
+fn notify_tunnel_event(_json: &str) {}
+#[cfg(not(target_os = "android"))]
+fn notify_network_offline(_offline: bool) {}
+#[cfg(not(target_os = "android"))]
+fn take_network_changed() -> bool {
+    false
+}
 use crate::dns::{
-    add_paths, expire_inflight_polls, handle_dns_response, maybe_report_debug,
-    refresh_resolver_path, resolve_resolvers, resolver_mode_to_c, send_poll_queries,
-    sockaddr_storage_to_socket_addr, DnsResponseContext,
+    add_paths, all_unreachable, expire_inflight_polls, handle_dns_response, maybe_report_debug,
+    mode_name, poll_pool_deficit, recv_response, refresh_resolver_path, resolve_added_resolvers,
+    resolve_resolvers, resolver_mode_to_c, send_inspected_query, send_poll_queries,
+    sockaddr_storage_to_socket_addr, try_recv_response, ClockOffsetEstimator, DnsIdGenerator,
+    DnsRecorder, DnsResponseContext, HealthPolicy, ResolverState, UdpTransport, MAX_POLL_BURST,
 };
+use crate::dns_forward::{spawn_dns_forwarder, SocksAuth};
 use crate::error::ClientError;
+use crate::events::{write_event_line, EventDebouncer, TunnelCondition, TunnelEvent};
 use crate::pacing::{cwnd_target_polls, inflight_packet_estimate};
 use crate::pinning::configure_pinned_certificate;
+use crate::redact::redact;
+use crate::session::{ResolverInfo, SessionInfo, SessionPublisher};
+use crate::spans::handshake_span;
+use crate::status::{spawn_status_server, StatusListen};
 use crate::streams::{
     acceptor::ClientAcceptor, client_callback, drain_commands, drain_stream_data, handle_command,
-    ClientState, Command,
+    ClientState, Command, ReadCoalesce,
+};
+use crate::tuning::{current_network_tuning, record_current_network, NetworkTuning};
+use slipstream_core::{
+    net::is_transient_udp_error, normalize_dual_stack_addr, tcp::stream_write_buffer_bytes,
 };
-use slipstream_core::{net::is_transient_udp_error, normalize_dual_stack_addr};
-use slipstream_dns::{build_qname, encode_query, QueryParams, CLASS_IN, RR_TXT};
+use slipstream_dns::{encode_query, QueryParams, RR_TXT};
 use slipstream_ffi::{
     configure_quic_with_custom,
     picoquic::{
@@ -52,15 +150,18 @@ use slipstream_ffi::{
         picoquic_create_client_cnx, picoquic_current_time, picoquic_disable_keep_alive,
         picoquic_enable_keep_alive, picoquic_enable_path_callbacks,
         picoquic_enable_path_callbacks_default, picoquic_get_next_wake_delay,
-        picoquic_prepare_next_packet_ex, picoquic_set_callback, slipstream_has_ready_stream,
+        picoquic_prepare_next_packet_ex, picoquic_set_callback, picoquic_set_max_data_control,
+        session_id, slipstream_get_unconsumed_data, slipstream_has_ready_stream,
         slipstream_is_flow_blocked, slipstream_mixed_cc_algorithm, slipstream_set_cc_override,
         slipstream_set_default_path_mode, PICOQUIC_CONNECTION_ID_MAX_SIZE,
         PICOQUIC_MAX_PACKET_SIZE, PICOQUIC_PACKET_LOOP_RECV_MAX, PICOQUIC_PACKET_LOOP_SEND_MAX,
     },
-    socket_addr_to_storage, take_crypto_errors, ClientConfig, QuicGuard, ResolverMode,
+    socket_addr_to_storage, take_crypto_errors, ClientConfig, PipelineMode, ProtectionLossPolicy,
+    QueryInfo, QuicGuard, ResolverMode, ResolverTransport,
 };
 use std::ffi::CString;
-use std::net::Ipv6Addr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, Notify};
@@ -72,16 +173,8 @@ const SLIPSTREAM_ALPN: &str = "picoquic_sample";
 const SLIPSTREAM_SNI: &str = "test.example.com";
 const DNS_WAKE_DELAY_MAX_US: i64 = 10_000_000;
 const DNS_POLL_SLICE_US: u64 = 50_000;
-const RECONNECT_SLEEP_MIN_MS: u64 = 250;
-const RECONNECT_SLEEP_MAX_MS: u64 = 5_000;
 const FLOW_BLOCKED_LOG_INTERVAL_US: u64 = 1_000_000;
 
-fn is_ipv6_unspecified(host: &str) -> bool {
-    host.parse::<Ipv6Addr>()
-        .map(|addr| addr.is_unspecified())
-        .unwrap_or(false)
-}
-
 fn drain_disconnected_commands(command_rx: &mut mpsc::UnboundedReceiver<Command>) -> usize {
     let mut dropped = 0usize;
     while let Ok(command) = command_rx.try_recv() {
@@ -93,27 +186,220 @@ fn drain_disconnected_commands(command_rx: &mut mpsc::UnboundedReceiver<Command>
     dropped
 }
 
+fn emit_tunnel_event(event: &TunnelEvent) {
+    let json = event.to_json();
+    info!("TunnelEvent: {}", json);
+    write_event_line(&json);
+    notify_tunnel_event(&json);
+}
+
+/// Tunnel responses on this connection across all resolvers.
+fn tunnel_responses(resolvers: &[ResolverState]) -> u64 {
+    resolvers
+        .iter()
+        .map(|resolver| resolver.tunnel_responses)
+        .sum()
+}
+
+/// Final accounting of a run that stops while `cnx` and `state_ptr` are
+/// still alive: the last connection's counters go into the session, what was
+/// learned about the network is kept for the next start, and the session
+/// summary is logged.
+fn end_run(
+    session: &mut SessionPublisher,
+    cnx: *mut picoquic_cnx_t,
+    state_ptr: *mut ClientState,
+    resolvers: &[ResolverState],
+    keep_alive: &AdaptiveKeepAlive,
+    network_tuning: Option<&NetworkTuning>,
+) {
+    session.info_mut().buffer_cap_drops = unsafe { (*state_ptr).backpressure() }
+        .buffer_cap_drops
+        .load(Ordering::Relaxed);
+    let max_query_bytes = &mut session.info_mut().max_query_bytes;
+    *max_query_bytes = (*max_query_bytes).max(largest_query(resolvers));
+    session.end_connection(resolver_infos(cnx, resolvers));
+    record_current_network(learned_tuning(keep_alive, resolvers, network_tuning));
+    session.finish();
+}
+
+/// Largest query sent to any resolver on this connection, polls included.
+fn largest_query(resolvers: &[ResolverState]) -> usize {
+    resolvers
+        .iter()
+        .map(|resolver| resolver.largest_query_bytes)
+        .max()
+        .unwrap_or(0)
+}
+
+fn resolver_infos(cnx: *mut picoquic_cnx_t, resolvers: &[ResolverState]) -> Vec<ResolverInfo> {
+    resolvers
+        .iter()
+        .map(|resolver| {
+            let (quality, congestion) = if resolver.added {
+                let quality = fetch_path_quality(cnx, resolver);
+                let congestion = fetch_congestion_state(cnx, resolver, &quality);
+                (quality, congestion)
+            } else {
+                Default::default()
+            };
+            ResolverInfo {
+                addr: resolver.addr.to_string(),
+                mode: mode_name(resolver.mode).to_string(),
+                state: resolver.health.state(),
+                asymmetric: resolver.health.is_asymmetric(),
+                servfail_storms: resolver.health.servfail_storms(),
+                standby: resolver.standby,
+                response_ttl: resolver.response_ttl,
+                poll_timeouts: resolver.poll_timeouts,
+                late_poll_responses: resolver.late_poll_responses,
+                non_tunnel_responses: resolver.non_tunnel_responses,
+                refusing: resolver.refusing,
+                queries_sent: resolver.debug.send_packets,
+                bytes_sent: resolver.debug.send_bytes,
+                query_wire_bytes: resolver.query_wire_bytes,
+                responses: resolver.debug.dns_responses,
+                tunnel_responses: resolver.tunnel_responses,
+                response_wire_bytes: resolver.response_wire_bytes,
+                bytes_received: resolver.response_payload_bytes,
+                goodput_bytes_per_sec: resolver.goodput.estimate(),
+                lost_packets: quality.lost,
+                packets_sent: quality.sent,
+                spurious_losses: quality.spurious_losses,
+                rtt_us: quality.rtt,
+                response_time: resolver.response_timer.histogram().clone(),
+                query_loss_rate: resolver.loss.loss_rate(),
+                learned_qps: resolver.query_rate.learned_qps(),
+                queries_held_back: resolver.query_rate.held_back(),
+                congestion,
+            }
+        })
+        .collect()
+}
+
+/// Optional features that are actually engaged on the current connection.
+fn active_features(config: &ClientConfig<'_>, resolvers: &[ResolverState]) -> Vec<String> {
+    let mut features = Vec::new();
+    if config.congestion_controller.is_some() {
+        features.push("congestion-control:custom".to_string());
+    } else if let Some(congestion_control) = config.congestion_control {
+        features.push(format!("congestion-control:{}", congestion_control));
+    }
+    if resolvers.iter().filter(|resolver| resolver.added).count() > 1 {
+        features.push("multipath".to_string());
+        if config.stream_striping {
+            features.push("stream-striping".to_string());
+        }
+    }
+    if config.prioritize_interactive {
+        features.push("interactive-priority".to_string());
+    }
+    if config.congestion_isolation {
+        features.push("congestion-isolation".to_string());
+    }
+    if resolvers
+        .iter()
+        .any(|resolver| resolver.added && resolver.mode == ResolverMode::Authoritative)
+    {
+        features.push("authoritative-pacing".to_string());
+    }
+    if config.cert.is_some() {
+        features.push("cert-pinning".to_string());
+    }
+    if config.edns_cookies {
+        features.push("edns-cookies".to_string());
+    }
+    if config.adaptive_query_rate {
+        features.push("adaptive-query-rate".to_string());
+    }
+    if config.query_ramp_qps > 0 {
+        features.push("query-ramp".to_string());
+    }
+    if config.keep_alive_interval > 0 {
+        features.push("keep-alive".to_string());
+        if config.adaptive_keep_alive {
+            features.push("adaptive-keep-alive".to_string());
+        }
+    }
+    if config.idle_refresh_ms > 0 {
+        features.push("idle-refresh".to_string());
+    }
+    if !config.quiet_hours.is_empty() {
+        features.push("quiet-hours".to_string());
+    }
+    if config.coalesce_delay_ms > 0 {
+        features.push("write-coalescing".to_string());
+    }
+    features
+}
+
 pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
+    // A stop asked of an earlier run in this process must not end this one; the
+    // Android bridge clears its own flag when it starts a client.
+    #[cfg(not(target_os = "android"))]
+    SHUTDOWN_REQUESTED.store(false, std::sync::atomic::Ordering::SeqCst);
     let domain_len = config.domain.len();
-    let mtu = compute_mtu(domain_len)?;
-    let udp = bind_udp_socket().await?;
+    let max_qname_len = qname_limit(config);
+    let mtu = compute_mtu(domain_len, max_qname_len, config.label_encoding)?;
+    let listen_ip = parse_listen_host(config.tcp_listen_host)?;
+    let max_query_payload_bytes = max_query_payload(config, max_qname_len)
+        .map_err(|err| ClientError::new(err.to_string()))?;
+    info!(
+        "DNS query budget: mtu={} max_query_payload_bytes={} max_qname_len={}",
+        mtu, max_query_payload_bytes, max_qname_len
+    );
+    let mut session = SessionPublisher::new(SessionInfo {
+        domain: config.domain.to_string(),
+        mtu,
+        max_query_payload_bytes,
+        keep_alive_interval_ms: config.keep_alive_interval as u64,
+        ..SessionInfo::default()
+    });
+    let udp = match config.resolver_transport {
+        Some(_) => None,
+        None => Some(UdpTransport::new(
+            bind_udp_socket(
+                config.protection_mode,
+                UdpBufferSizes {
+                    recv: config.udp_rcvbuf_bytes,
+                    send: config.udp_sndbuf_bytes,
+                },
+                config.source_address,
+            )
+            .await?,
+        )),
+    };
+    let transport: &dyn ResolverTransport = match (&config.resolver_transport, &udp) {
+        (Some(custom), _) => custom.transport(),
+        (None, Some(udp)) => udp,
+        (None, None) => unreachable!("the UDP socket is bound without a custom transport"),
+    };
+    if let Some(listen) = StatusListen::from_config(config.status_host, config.status_port) {
+        spawn_status_server(listen, config.status_control).await?;
+    }
+    let mut recorder = DnsRecorder::open(
+        config.record_dns,
+        config.flight_recorder_records,
+        config.query_log,
+    )?;
 
     let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+    register_command_sender(command_tx.clone());
     let data_notify = Arc::new(Notify::new());
-    let acceptor = ClientAcceptor::new();
+    let acceptor = ClientAcceptor::with_setup_limit(config.max_connection_setups);
     let debug_streams = config.debug_streams;
     let tcp_host = config.tcp_listen_host;
     let tcp_port = config.tcp_listen_port;
     let mut bound_host = tcp_host.to_string();
-    let listener = match bind_tcp_listener(tcp_host, tcp_port).await {
+    let listener = match bind_tcp_listener(SocketAddr::new(listen_ip, tcp_port)) {
         Ok(listener) => listener,
         Err(err) => {
-            if is_ipv6_unspecified(tcp_host) {
+            if matches!(listen_ip, IpAddr::V6(addr) if addr.is_unspecified()) {
                 warn!(
                     "Failed to bind TCP listener on {}:{} ({}); falling back to 0.0.0.0",
                     tcp_host, tcp_port, err
                 );
-                match bind_tcp_listener("0.0.0.0", tcp_port).await {
+                match bind_tcp_listener(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), tcp_port)) {
                     Ok(listener) => {
                         bound_host = "0.0.0.0".to_string();
                         listener
@@ -130,8 +416,23 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
             }
         }
     };
+    let tunnel_addr = listener.local_addr().map_err(map_io)?;
     acceptor.spawn(listener, command_tx.clone());
     info!("Listening on TCP port {} (host {})", tcp_port, bound_host);
+    if let Some(dns_port) = config.dns_listen_port {
+        let auth = config.dns_socks_username.map(|username| SocksAuth {
+            username: username.to_string(),
+            password: config.dns_socks_password.unwrap_or_default().to_string(),
+        });
+        spawn_dns_forwarder(
+            config.dns_listen_host,
+            dns_port,
+            tunnel_addr,
+            config.dns_upstream,
+            auth,
+        )
+        .await?;
+    }
 
     // Signal to Android that the TCP listener is ready
     signal_listener_ready();
@@ -153,20 +454,123 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         debug_streams,
         acceptor,
     ));
+    if config.coalesce_delay_ms > 0 {
+        info!(
+            "Coalescing small TCP reads for up to {}ms",
+            config.coalesce_delay_ms
+        );
+    }
+    state.set_read_coalesce(ReadCoalesce {
+        delay: Duration::from_millis(config.coalesce_delay_ms),
+        flush_bytes: mtu as usize,
+    });
+    state.set_local_close_policy(config.local_close_policy);
+    state.set_prioritize_interactive(config.prioritize_interactive);
+    state.set_congestion_isolation(config.congestion_isolation);
+    state.set_first_stream_boost(
+        config.first_stream_boost_count,
+        config.first_stream_boost_ms,
+    );
     let state_ptr: *mut ClientState = &mut *state;
     let _state = state;
 
-    let mut reconnect_delay = Duration::from_millis(RECONNECT_SLEEP_MIN_MS);
+    let mut live = LiveSettings::new(config);
+    let _reload_target = ReloadTarget::register(config, &live);
+    let force_target = ForceTarget::register(live.resolvers.len());
+    let mut pin = ResolverPin::default();
+    let mut reconnect_backoff = ReconnectBackoff::new(
+        live.reconnect_min_ms,
+        live.reconnect_max_ms,
+        live.reconnect_jitter,
+    );
+    let mut reconnect_limit = ReconnectLimit::new(
+        config.max_reconnect_attempts,
+        config.max_reconnect_duration_ms,
+    );
+    let mut block_detector = BlockDetector::new(config.block_backoff_after);
+    let mut dns_ids = DnsIdGenerator::new(config.dns_id_strategy);
+    let mut keep_alive = AdaptiveKeepAlive::new(
+        config.keep_alive_interval as u64,
+        config.adaptive_keep_alive,
+    );
+    let health_policy = HealthPolicy::new(
+        config.resolver_failure_threshold,
+        config.resolver_grace_period_ms,
+        config.resolver_recovery_probe_ms,
+        config.resolver_servfail_threshold,
+        config.resolver_servfail_cooldown_ms,
+    );
+    let mut poll_timeout_us = live.poll_timeout_ms.max(1).saturating_mul(1_000);
+    let mut primary = PrimaryTracker::new(config.resolver_preference);
+    let mut query_budget = QueryBudget::new(config.max_lifetime_queries, config.pipeline_mode);
+    // Times ready stream data was held back by QUIC flow control.
+    let mut tunnel_send_blocked = 0u64;
+    let reassembly_limit = config
+        .reassembly_limit_bytes
+        .unwrap_or_else(stream_write_buffer_bytes) as u64;
+    let mut protection = ProtectionMonitor::new(unsafe { picoquic_current_time() });
+    let mut schedule = ActiveSchedule::new(config.active_windows);
+    let mut events = EventDebouncer::new(live.event_grace_ms);
 
     loop {
-        let mut resolvers = resolve_resolvers(config.resolvers, mtu, config.debug_poll)?;
+        if !schedule.is_active(local_minute_of_day()) {
+            let next = schedule
+                .next_start(local_minute_of_day())
+                .map(format_minute_of_day)
+                .unwrap_or_default();
+            info!(
+                "ScheduledPause: outside the active windows; resuming at {}",
+                next
+            );
+            session.set_scheduled_pause(true, unsafe { picoquic_current_time() });
+            notify_scheduled_pause(true);
+            // Like a reconnect wait: send nothing and refuse local connections.
+            while !schedule.is_active(local_minute_of_day()) {
+                if should_shutdown() {
+                    info!("Shutdown signal received during scheduled pause, exiting");
+                    // The last connection was counted when it closed.
+                    session.finish();
+                    return Ok(0);
+                }
+                sleep(Duration::from_millis(100)).await;
+                let _ = drain_disconnected_commands(&mut command_rx);
+            }
+            info!("Active window opened; resuming the tunnel");
+            session.set_scheduled_pause(false, unsafe { picoquic_current_time() });
+            notify_scheduled_pause(false);
+            reconnect_backoff.reset();
+            reconnect_limit.reset();
+        }
+
+        let mut resolvers =
+            resolve_resolvers(&live.resolvers, mtu, config.debug_poll, health_policy)?;
         if resolvers.is_empty() {
             return Err(ClientError::new("At least one resolver is required"));
         }
+        for resolver in resolvers.iter_mut() {
+            if config.edns_cookies {
+                resolver.enable_cookie();
+            }
+            if config.adaptive_query_rate {
+                resolver.query_rate.enable();
+            }
+        }
 
-        let mut local_addr_storage = socket_addr_to_storage(udp.local_addr().map_err(map_io)?);
+        let mut local_addr_storage =
+            socket_addr_to_storage(transport.local_addr().map_err(map_io)?);
 
         let current_time = unsafe { picoquic_current_time() };
+        for resolver in resolvers.iter_mut() {
+            resolver.query_rate.start_ramp(
+                current_time,
+                config.query_ramp_qps,
+                config.query_ramp_doubling_ms,
+            );
+        }
+        let network_tuning = current_network_tuning();
+        if let Some(tuning) = network_tuning.as_ref() {
+            seed_learned_tuning(tuning, &mut keep_alive, &mut resolvers, current_time);
+        }
         let quic = unsafe {
             picoquic_create(
                 8,
@@ -203,6 +607,9 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         }
         unsafe {
             configure_quic_with_custom(quic, mixed_cc, mtu);
+            if let Some(limit) = config.reassembly_limit_bytes {
+                picoquic_set_max_data_control(quic, limit as u64);
+            }
             picoquic_enable_path_callbacks_default(quic, 1);
             let override_ptr = cc_override
                 .as_ref()
@@ -210,6 +617,12 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 .unwrap_or(std::ptr::null());
             slipstream_set_cc_override(override_ptr);
         }
+        // SAFETY: the mixed algorithm is configured above, and the guard uninstalls
+        // the controller before this connection attempt's QUIC context is freed.
+        let _cc_guard = config
+            .congestion_controller
+            .as_ref()
+            .map(|controller| unsafe { controller.install() });
         unsafe {
             slipstream_set_default_path_mode(resolver_mode_to_c(resolvers[0].mode));
         }
@@ -233,14 +646,27 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         if cnx.is_null() {
             return Err(ClientError::new("Could not create QUIC connection"));
         }
+        session.begin_connection(resolver_infos(cnx, &resolvers));
+        let new_session = unsafe { session_id(cnx) };
+        let mut handshake_span = Some(handshake_span(&new_session, resolvers.len()));
+        match session.set_session_id(new_session.clone(), current_time) {
+            Some(old) => {
+                info!("Session {} replaces session {}", new_session, old);
+                emit_tunnel_event(&TunnelEvent::SessionRotated {
+                    old,
+                    new: new_session,
+                });
+            }
+            None => info!("Session {}", new_session),
+        }
 
         apply_path_mode(cnx, &mut resolvers[0])?;
 
         unsafe {
             picoquic_set_callback(cnx, Some(client_callback), state_ptr as *mut _);
             picoquic_enable_path_callbacks(cnx, 1);
-            if config.keep_alive_interval > 0 {
-                picoquic_enable_keep_alive(cnx, config.keep_alive_interval as u64 * 1000);
+            if keep_alive.interval_ms() > 0 {
+                picoquic_enable_keep_alive(cnx, keep_alive.interval_ms() * 1000);
             } else {
                 picoquic_disable_keep_alive(cnx);
             }
@@ -250,7 +676,6 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
             warn!("GSO is not implemented in the Rust client loop yet.");
         }
 
-        let mut dns_id = 1u16;
         let mut recv_buf = vec![0u8; 4096];
         let mut send_buf = vec![0u8; PICOQUIC_MAX_PACKET_SIZE];
         let packet_loop_send_max = loop_burst_total(&resolvers, PICOQUIC_PACKET_LOOP_SEND_MAX);
@@ -259,15 +684,171 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         let mut zero_send_with_streams = 0u64;
         let mut last_flow_block_log_at = 0u64;
         let mut quic_ready_signaled = false;
+        let mut idle_polls = IdlePolling::new(live.idle_poll_min_ms, live.idle_poll_max_ms);
+        let mut quiet_hours = QuietHours::new(config.quiet_hours);
+        let mut idle_refresh = IdleRefresh::new(live.idle_refresh_ms, live.poll_timeout_ms);
+        let mut striper = StreamStriper::new(config.stream_striping);
+        let redundancy = HandshakeRedundancy::new(match config.pipeline_mode {
+            PipelineMode::Pipelined => config.handshake_redundancy,
+            PipelineMode::Single => 0,
+        });
+        let mut clock = ClockOffsetEstimator::new();
+        let mut sleep_detector = SleepDetector::new(
+            unsafe { picoquic_current_time() },
+            std::time::Instant::now(),
+        );
+        let mut closed_after_sleep = false;
+        let mut send_was_blocked = false;
+        let mut protection_lost = false;
+        let mut scheduled_pause = false;
+        let mut network_offline = false;
+        let confirmation = ConnectConfirmation::new(config.confirm_resolvers, resolvers.len());
+        let mut confirm_wait_logged = false;
 
         loop {
             // Check for shutdown signal from Android
             if should_shutdown() {
                 info!("Shutdown signal received, exiting");
+                end_run(
+                    &mut session,
+                    cnx,
+                    state_ptr,
+                    &resolvers,
+                    &keep_alive,
+                    network_tuning.as_ref(),
+                );
+                return Ok(0);
+            }
+
+            if query_budget.is_exhausted() {
+                let queries = query_budget.spent();
+                info!(
+                    "Lifetime query limit reached after {} queries, stopping",
+                    queries
+                );
+                emit_tunnel_event(&TunnelEvent::LimitReached { queries });
+                notify_limit_reached(queries);
+                // A close frame would need another query, so just stop.
+                end_run(
+                    &mut session,
+                    cnx,
+                    state_ptr,
+                    &resolvers,
+                    &keep_alive,
+                    network_tuning.as_ref(),
+                );
                 return Ok(0);
             }
 
             let current_time = unsafe { picoquic_current_time() };
+            if let Some(update) = take_reload() {
+                let added = update.added_resolvers(&live);
+                if !added.is_empty() {
+                    match resolve_added_resolvers(added, mtu, config.debug_poll, health_policy) {
+                        Ok(added) => {
+                            for mut resolver in added {
+                                if config.edns_cookies {
+                                    resolver.enable_cookie();
+                                }
+                                if config.adaptive_query_rate {
+                                    resolver.query_rate.enable();
+                                }
+                                resolver.query_rate.start_ramp(
+                                    current_time,
+                                    config.query_ramp_qps,
+                                    config.query_ramp_doubling_ms,
+                                );
+                                info!("Reload: adding resolver {}", redact(resolver.addr));
+                                resolvers.push(resolver);
+                            }
+                        }
+                        Err(err) => warn!("Reload: could not add resolvers: {}", err),
+                    }
+                }
+                poll_timeout_us = update.poll_timeout_ms.max(1).saturating_mul(1_000);
+                if (update.idle_poll_min_ms, update.idle_poll_max_ms)
+                    != (live.idle_poll_min_ms, live.idle_poll_max_ms)
+                {
+                    idle_polls = IdlePolling::new(
+                        update.idle_poll_min_ms,
+                        quiet_hours.idle_poll_max_ms(update.idle_poll_max_ms),
+                    );
+                }
+                if (update.idle_refresh_ms, update.poll_timeout_ms)
+                    != (live.idle_refresh_ms, live.poll_timeout_ms)
+                {
+                    idle_refresh = IdleRefresh::new(update.idle_refresh_ms, update.poll_timeout_ms);
+                    if quic_ready_signaled {
+                        idle_refresh.on_connected(current_time, tunnel_responses(&resolvers));
+                    }
+                }
+                if (
+                    update.reconnect_min_ms,
+                    update.reconnect_max_ms,
+                    update.reconnect_jitter,
+                ) != (
+                    live.reconnect_min_ms,
+                    live.reconnect_max_ms,
+                    live.reconnect_jitter,
+                ) {
+                    reconnect_backoff = ReconnectBackoff::new(
+                        update.reconnect_min_ms,
+                        update.reconnect_max_ms,
+                        update.reconnect_jitter,
+                    );
+                }
+                events.set_grace_ms(update.event_grace_ms);
+                info!("Reload: new settings are in effect");
+                force_target.set_resolvers(resolvers.len());
+                live = update;
+            }
+            if let Some(request) = take_force_request() {
+                if let Some(resolver) = resolvers.get(request.index) {
+                    info!(
+                        "Forcing resolver {} for the next {} queries",
+                        redact(resolver.addr),
+                        request.queries
+                    );
+                }
+                pin.start(request);
+            }
+            if let Some(slept) = sleep_detector.check(current_time, std::time::Instant::now()) {
+                let slept_ms = slept.as_millis() as u64;
+                session.info_mut().doze_wakeups += 1;
+                notify_woke_from_doze(slept_ms);
+                if quic_ready_signaled && slept >= DOZE_RECONNECT_AFTER {
+                    warn!(
+                        "Device slept for {}s; reconnecting instead of waiting for the old session to time out",
+                        slept.as_secs()
+                    );
+                    reconnect_backoff.reset();
+                    closed_after_sleep = true;
+                    break;
+                }
+                // Shorter sleeps: picoquic's wall-clock timers are already due, so
+                // the keep-alive goes out now and shows whether the path survived.
+                info!("Woke after sleeping {}ms", slept_ms);
+            }
+            if protection.is_due(current_time) {
+                let protected = udp
+                    .as_ref()
+                    .is_none_or(|udp| reprotect_udp_socket(udp.socket(), config.protection_mode));
+                if !protected {
+                    warn!("VPN refused to re-protect the DNS socket");
+                }
+                if protection.record(current_time, protected) {
+                    protection_lost = true;
+                    break;
+                }
+            }
+            if schedule.is_due(current_time) && !schedule.is_active(local_minute_of_day()) {
+                scheduled_pause = true;
+                break;
+            }
+            if all_unreachable(resolvers.iter().map(|resolver| &resolver.route)) {
+                network_offline = true;
+                break;
+            }
             drain_commands(cnx, state_ptr, &mut command_rx);
             drain_stream_data(cnx, state_ptr);
             let closing = unsafe { (*state_ptr).is_closing() };
@@ -277,19 +858,43 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
 
             let ready = unsafe { (*state_ptr).is_ready() };
             if ready {
+                if !quic_ready_signaled
+                    && !confirm_wait_logged
+                    && !confirmation.is_confirmed(&resolvers)
+                {
+                    info!(
+                        "Handshake complete; waiting for tunnel data through {} resolvers before reporting connected",
+                        confirmation.required()
+                    );
+                    confirm_wait_logged = true;
+                }
                 // Signal QUIC ready to Android (only once per connection)
-                if !quic_ready_signaled {
+                if !quic_ready_signaled && confirmation.is_confirmed(&resolvers) {
                     signal_quic_ready();
+                    block_detector.reset();
+                    session.info_mut().persistent_block_suspected = None;
+                    session.set_connected(true, current_time);
+                    keep_alive.on_connected(current_time);
+                    idle_refresh.on_connected(current_time, tunnel_responses(&resolvers));
                     quic_ready_signaled = true;
+                    if let Some(span) = handshake_span.take() {
+                        span.record("outcome", "ready");
+                    }
+                }
+                let idle = unsafe { (*state_ptr).streams_len() } == 0;
+                if let Some(interval_ms) = keep_alive.maybe_grow(current_time, idle) {
+                    debug!("Probing keep-alive interval {}ms", interval_ms);
+                    let interval_ms = quiet_hours.keep_alive_ms(interval_ms);
+                    unsafe { picoquic_enable_keep_alive(cnx, interval_ms * 1000) };
+                    session.info_mut().keep_alive_interval_ms = interval_ms;
                 }
 
                 unsafe {
                     (*state_ptr).update_acceptor_limit(cnx);
                 }
-                if reconnect_delay != Duration::from_millis(RECONNECT_SLEEP_MIN_MS) {
-                    reconnect_delay = Duration::from_millis(RECONNECT_SLEEP_MIN_MS);
-                }
-                add_paths(cnx, &mut resolvers)?;
+                reconnect_backoff.reset();
+                reconnect_limit.reset();
+                add_paths(cnx, &mut resolvers, live.max_parallel_probes)?;
                 for resolver in resolvers.iter_mut() {
                     if resolver.added {
                         apply_path_mode(cnx, resolver)?;
@@ -297,17 +902,94 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 }
             }
             drain_path_events(cnx, &mut resolvers, state_ptr);
+            if ready {
+                apply_resolver_roles(cnx, &mut resolvers, live.resolver_role_policy);
+            }
 
+            let answering = resolvers
+                .iter()
+                .filter(|resolver| resolver.health.is_answering())
+                .count();
+            let condition = if !quic_ready_signaled {
+                Some(TunnelCondition::Disconnected)
+            } else if answering == 0 {
+                Some(TunnelCondition::Stalled)
+            } else {
+                None
+            };
+            if let Some(event) = events.update(condition, current_time) {
+                emit_tunnel_event(&event);
+            }
+            let mut refresh_probe = false;
+            match idle_refresh.update(current_time, tunnel_responses(&resolvers)) {
+                RefreshAction::Wait => {}
+                RefreshAction::Probe => {
+                    debug!("Idle refresh: checking the quiet session with one poll");
+                    refresh_probe = true;
+                }
+                RefreshAction::Failed => {
+                    warn!(
+                        "Idle refresh got no answer within {}ms; reconnecting",
+                        live.poll_timeout_ms
+                    );
+                    break;
+                }
+            }
             for resolver in resolvers.iter_mut() {
-                if resolver.mode == ResolverMode::Authoritative {
-                    expire_inflight_polls(&mut resolver.inflight_poll_ids, current_time);
+                let expired = expire_inflight_polls(
+                    &mut resolver.inflight_poll_ids,
+                    &mut resolver.expired_polls,
+                    poll_timeout_us,
+                    current_time,
+                );
+                if expired > 0 {
+                    resolver.poll_timeouts = resolver.poll_timeouts.saturating_add(expired as u64);
+                    resolver.record_rate_limit_signal(current_time);
+                    // Recursive polls are only re-armed by answers, so replace lost ones.
+                    if resolver.mode == ResolverMode::Recursive {
+                        resolver.pending_polls = resolver
+                            .pending_polls
+                            .saturating_add(expired)
+                            .min(MAX_POLL_BURST);
+                    }
                 }
+                resolver.loss.expire(current_time, poll_timeout_us);
+                resolver.query_spans.expire(current_time, poll_timeout_us);
+                resolver.query_rate.tick(current_time);
+                resolver.health.tick(resolver.addr, current_time);
+                let others_answering = answering > usize::from(resolver.health.is_answering());
+                resolver
+                    .health
+                    .check_asymmetry(resolver.addr, others_answering);
+            }
+            query_budget.expire(current_time, poll_timeout_us);
+            if let Some(change) = primary.update(&resolvers, current_time) {
+                let old = resolvers[change.old].addr.to_string();
+                let new = resolvers[change.new].addr.to_string();
+                info!(
+                    "Primary resolver changed from {} to {} ({})",
+                    redact(&old),
+                    redact(&new),
+                    change.reason.as_str()
+                );
+                notify_resolver_changed(&old, &new, change.reason.as_str());
             }
 
             let delay_us =
                 unsafe { picoquic_get_next_wake_delay(quic, current_time, DNS_WAKE_DELAY_MAX_US) };
             let delay_us = if delay_us < 0 { 0 } else { delay_us as u64 };
+            unsafe { (*state_ptr).expire_stream_boosts(cnx, current_time) };
+            update_bulk_window(cnx, &resolvers, unsafe { &mut *state_ptr }, current_time);
             let streams_len_for_sleep = unsafe { (*state_ptr).streams_len() };
+            // A handshake in progress needs its polls, so only a ready tunnel idles.
+            if ready {
+                idle_polls.update(current_time, streams_len_for_sleep, unsafe {
+                    (*state_ptr).stream_bytes()
+                });
+            } else {
+                idle_polls.wake(current_time);
+            }
+            let idle_throttling = idle_polls.is_throttling(current_time);
             let mut has_work = streams_len_for_sleep > 0;
             for resolver in resolvers.iter_mut() {
                 if !refresh_resolver_path(cnx, resolver) {
@@ -330,7 +1012,8 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                     }
                     ResolverMode::Recursive => resolver.pending_polls,
                 };
-                if pending_for_sleep > 0 {
+                // Held-back idle polls wait for their slot below, not the short slice.
+                if pending_for_sleep > 0 && !idle_throttling {
                     has_work = true;
                 }
                 if resolver.mode == ResolverMode::Authoritative
@@ -345,6 +1028,14 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
             } else {
                 delay_us.max(1)
             };
+            let timeout_us = match idle_polls.wait_us(current_time) {
+                Some(wait_us) => timeout_us.min(wait_us.max(1)),
+                None => timeout_us,
+            };
+            let timeout_us = match idle_refresh.wait_us(current_time) {
+                Some(wait_us) => timeout_us.min(wait_us.max(1)),
+                None => timeout_us,
+            };
             let timeout = Duration::from_micros(timeout_us);
 
             tokio::select! {
@@ -354,17 +1045,26 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                     }
                 }
                 _ = data_notify.notified() => {}
-                recv = udp.recv_from(&mut recv_buf) => {
+                recv = recv_response(transport, &mut recv_buf) => {
                     match recv {
                         Ok((size, peer)) => {
+                            let mut response_events = Vec::new();
                             let mut response_ctx = DnsResponseContext {
                                 quic,
                                 local_addr_storage: &local_addr_storage,
                                 resolvers: &mut resolvers,
+                                recorder: &mut recorder,
+                                clock: &mut clock,
+                                non_tunnel_policy: config.non_tunnel_policy,
+                                mode_mismatch_policy: config.mode_mismatch_policy,
+                                formerr_policy: config.formerr_policy,
+                                query_class: config.query_class,
+                                query_budget: &mut query_budget,
+                                events: &mut response_events,
                             };
                             handle_dns_response(&recv_buf[..size], peer, &mut response_ctx)?;
                             for _ in 1..packet_loop_recv_max {
-                                match udp.try_recv_from(&mut recv_buf) {
+                                match try_recv_response(transport, &mut recv_buf) {
                                     Ok((size, peer)) => {
                                         handle_dns_response(&recv_buf[..size], peer, &mut response_ctx)?;
                                     }
@@ -378,6 +1078,15 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                                     }
                                 }
                             }
+                            for event in &response_events {
+                                emit_tunnel_event(event);
+                            }
+                            for resolver in resolvers.iter_mut().filter(|r| r.path_mode_stale) {
+                                resolver.path_mode_stale = false;
+                                if resolver.added {
+                                    apply_path_mode(cnx, resolver)?;
+                                }
+                            }
                         }
                         Err(err) => {
                             if !is_transient_udp_error(&err) {
@@ -392,8 +1101,16 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
             drain_commands(cnx, state_ptr, &mut command_rx);
             drain_stream_data(cnx, state_ptr);
             drain_path_events(cnx, &mut resolvers, state_ptr);
+            hold_spiking_paths(cnx, &mut resolvers, unsafe { picoquic_current_time() });
 
+            let queries_before_send = query_budget.spent();
             for _ in 0..packet_loop_send_max {
+                if !query_budget.can_send() {
+                    break;
+                }
+                if !pin.before_packet(cnx, unsafe { &*state_ptr }, &resolvers) {
+                    striper.before_packet(cnx, unsafe { &*state_ptr }, &resolvers);
+                }
                 let current_time = unsafe { picoquic_current_time() };
                 let mut send_length: libc::size_t = 0;
                 let mut addr_to: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
@@ -443,6 +1160,17 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                 if addr_to.ss_family == 0 {
                     break;
                 }
+                if let Ok(dest) = sockaddr_storage_to_socket_addr(&addr_to) {
+                    let dest = normalize_dual_stack_addr(dest);
+                    if let Some(resolver) = find_resolver_by_addr_mut(&mut resolvers, dest) {
+                        // Over the learned rate the packet is dropped; QUIC counts it
+                        // lost, slows that path and resends the data.
+                        if !resolver.query_rate.admit(current_time) {
+                            continue;
+                        }
+                    }
+                }
+                let query_id = dns_ids.next_id();
                 if let Ok(dest) = sockaddr_storage_to_socket_addr(&addr_to) {
                     let dest = normalize_dual_stack_addr(dest);
                     if let Some(resolver) = find_resolver_by_addr_mut(&mut resolvers, dest) {
@@ -450,39 +1178,127 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                         resolver.debug.send_packets = resolver.debug.send_packets.saturating_add(1);
                         resolver.debug.send_bytes =
                             resolver.debug.send_bytes.saturating_add(send_length as u64);
+                        resolver.health.record_send(current_time);
+                        resolver.response_timer.record_query(query_id, current_time);
+                        resolver.loss.record_query(query_id, current_time);
+                        resolver
+                            .query_spans
+                            .start(query_id, current_time, resolver.addr, "data");
+                    }
+                    if let Some(index) = pin.record_query(&resolvers, dest) {
+                        info!(
+                            "Resolver {} is no longer forced",
+                            redact(resolvers[index].addr)
+                        );
                     }
                 }
 
-                let qname = build_qname(&send_buf[..send_length], config.domain)
+                let qname = build_query_name(config, &send_buf[..send_length], max_qname_len)
                     .map_err(|err| ClientError::new(err.to_string()))?;
                 let params = QueryParams {
-                    id: dns_id,
+                    id: query_id,
                     qname: &qname,
                     qtype: RR_TXT,
-                    qclass: CLASS_IN,
+                    qclass: config.query_class,
                     rd: true,
                     cd: false,
                     qdcount: 1,
                     is_query: true,
                 };
-                dns_id = dns_id.wrapping_add(1);
-                let packet =
-                    encode_query(&params).map_err(|err| ClientError::new(err.to_string()))?;
-
                 let dest = sockaddr_storage_to_socket_addr(&addr_to)?;
                 let dest = normalize_dual_stack_addr(dest);
+                let resolver = find_resolver_by_addr_mut(&mut resolvers, dest);
+                let packet = match &resolver {
+                    Some(resolver) => resolver.encode_query(&params),
+                    None => encode_query(&params),
+                }
+                .map_err(|err| ClientError::new(err.to_string()))?;
+                if let Some(resolver) = resolver {
+                    resolver.record_query_bytes(packet.len());
+                }
+                recorder.record_query(&packet);
+                recorder.log_query(dest, query_id, &qname);
                 local_addr_storage = addr_from;
-                if let Err(err) = udp.send_to(&packet, dest).await {
+                let query = QueryInfo {
+                    resolver: dest,
+                    id: query_id,
+                    qname: &qname,
+                    qtype: RR_TXT,
+                    size: packet.len(),
+                    poll: false,
+                };
+                let sent =
+                    send_inspected_query(transport, config.query_hook.as_ref(), &query, &packet)
+                        .await;
+                if let Some(resolver) = find_resolver_by_addr_mut(&mut resolvers, dest) {
+                    resolver.route.record(&sent);
+                }
+                if let Err(err) = sent {
                     if !is_transient_udp_error(&err) {
                         return Err(map_io(err));
                     }
                 }
+                query_budget.record_query(query_id, current_time);
+
+                for index in redundancy.targets(&send_buf[..send_length], dest, &resolvers) {
+                    if !query_budget.can_send() {
+                        break;
+                    }
+                    let resolver = &mut resolvers[index];
+                    // Copies would only queue up behind a resolver in an RTT spike.
+                    if resolver.rtt_spike.holding(current_time)
+                        || !resolver.query_rate.admit(current_time)
+                    {
+                        continue;
+                    }
+                    let copy_id = dns_ids.next_id();
+                    let packet = resolver
+                        .encode_query(&QueryParams {
+                            id: copy_id,
+                            ..params
+                        })
+                        .map_err(|err| ClientError::new(err.to_string()))?;
+                    resolver.debug.send_packets = resolver.debug.send_packets.saturating_add(1);
+                    resolver.debug.send_bytes =
+                        resolver.debug.send_bytes.saturating_add(send_length as u64);
+                    resolver.health.record_send(current_time);
+                    resolver.response_timer.record_query(copy_id, current_time);
+                    resolver.loss.record_query(copy_id, current_time);
+                    resolver
+                        .query_spans
+                        .start(copy_id, current_time, resolver.addr, "redundant");
+                    resolver.record_query_bytes(packet.len());
+                    recorder.record_query(&packet);
+                    recorder.log_query(resolver.addr, copy_id, &qname);
+                    let query = QueryInfo {
+                        resolver: resolver.addr,
+                        id: copy_id,
+                        qname: &qname,
+                        qtype: RR_TXT,
+                        size: packet.len(),
+                        poll: false,
+                    };
+                    let hook = config.query_hook.as_ref();
+                    let sent = send_inspected_query(transport, hook, &query, &packet).await;
+                    resolver.route.record(&sent);
+                    if let Err(err) = sent {
+                        if !is_transient_udp_error(&err) {
+                            return Err(map_io(err));
+                        }
+                    }
+                    query_budget.record_query(copy_id, current_time);
+                }
             }
 
             let has_ready_stream = unsafe { slipstream_has_ready_stream(cnx) != 0 };
             let flow_blocked = unsafe { slipstream_is_flow_blocked(cnx) != 0 };
             let streams_len = unsafe { (*state_ptr).streams_len() };
-            if streams_len > 0 && has_ready_stream && flow_blocked {
+            let send_blocked = streams_len > 0 && has_ready_stream && flow_blocked;
+            if send_blocked && !send_was_blocked {
+                tunnel_send_blocked += 1;
+            }
+            send_was_blocked = send_blocked;
+            if send_blocked {
                 let now = unsafe { picoquic_current_time() };
                 if now.saturating_sub(last_flow_block_log_at) >= FLOW_BLOCKED_LOG_INTERVAL_US {
                     let metrics = unsafe { (*state_ptr).stream_debug_metrics() };
@@ -514,10 +1330,46 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                     last_flow_block_log_at = now;
                 }
             }
+            if ready {
+                let state = unsafe { &*state_ptr };
+                if let Some(quiet) = quiet_hours.update(
+                    current_time,
+                    local_minute_of_day,
+                    state.streams_len(),
+                    state.stream_bytes(),
+                ) {
+                    if quiet {
+                        info!("Quiet hours: tunnel idle; minimizing background activity");
+                    } else {
+                        info!("Quiet hours: back to normal activity");
+                    }
+                    let interval_ms = quiet_hours.keep_alive_ms(keep_alive.interval_ms());
+                    if interval_ms > 0 {
+                        unsafe { picoquic_enable_keep_alive(cnx, interval_ms * 1000) };
+                    }
+                    session.info_mut().keep_alive_interval_ms = interval_ms;
+                    idle_polls = IdlePolling::new(
+                        live.idle_poll_min_ms,
+                        quiet_hours.idle_poll_max_ms(live.idle_poll_max_ms),
+                    );
+                }
+                idle_polls.update(current_time, state.streams_len(), state.stream_bytes());
+            } else {
+                idle_polls.wake(current_time);
+            }
+            session.info_mut().poll_interval_ms = idle_polls.interval_ms(current_time);
+            let mut idle_allowance = idle_polls.allowance(current_time);
+            let idle_allowed = idle_allowance;
             for resolver in resolvers.iter_mut() {
+                if idle_allowance == 0 {
+                    break;
+                }
                 if !refresh_resolver_path(cnx, resolver) {
                     continue;
                 }
+                if !resolver.health.allow_poll(current_time) {
+                    continue;
+                }
                 match resolver.mode {
                     ResolverMode::Authoritative => {
                         let quality = fetch_path_quality(cnx, resolver);
@@ -544,60 +1396,111 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                         }
                         if poll_deficit > 0 {
                             let burst_max = path_poll_burst_max(resolver);
-                            let mut to_send = poll_deficit.min(burst_max);
+                            let batch = poll_deficit.min(burst_max).min(idle_allowance);
+                            let mut to_send = batch;
                             send_poll_queries(
                                 cnx,
-                                &udp,
+                                transport,
                                 config,
                                 &mut local_addr_storage,
-                                &mut dns_id,
+                                &mut dns_ids,
+                                &mut recorder,
+                                &mut query_budget,
                                 resolver,
                                 &mut to_send,
                                 &mut send_buf,
                             )
                             .await?;
+                            idle_allowance = idle_allowance.saturating_sub(batch - to_send);
                         }
                     }
                     ResolverMode::Recursive => {
                         resolver.last_pacing_snapshot = None;
                         if resolver.pending_polls > 0 {
                             let burst_max = path_poll_burst_max(resolver);
-                            if resolver.pending_polls > burst_max {
-                                let mut to_send = burst_max;
-                                send_poll_queries(
-                                    cnx,
-                                    &udp,
-                                    config,
-                                    &mut local_addr_storage,
-                                    &mut dns_id,
-                                    resolver,
-                                    &mut to_send,
-                                    &mut send_buf,
-                                )
-                                .await?;
-                                resolver.pending_polls = resolver
-                                    .pending_polls
-                                    .saturating_sub(burst_max)
-                                    .saturating_add(to_send);
-                            } else {
-                                let mut pending = resolver.pending_polls;
-                                send_poll_queries(
-                                    cnx,
-                                    &udp,
-                                    config,
-                                    &mut local_addr_storage,
-                                    &mut dns_id,
-                                    resolver,
-                                    &mut pending,
-                                    &mut send_buf,
-                                )
-                                .await?;
-                                resolver.pending_polls = pending;
-                            }
+                            let batch = resolver.pending_polls.min(burst_max).min(idle_allowance);
+                            let mut to_send = batch;
+                            send_poll_queries(
+                                cnx,
+                                transport,
+                                config,
+                                &mut local_addr_storage,
+                                &mut dns_ids,
+                                &mut recorder,
+                                &mut query_budget,
+                                resolver,
+                                &mut to_send,
+                                &mut send_buf,
+                            )
+                            .await?;
+                            resolver.pending_polls = resolver
+                                .pending_polls
+                                .saturating_sub(batch)
+                                .saturating_add(to_send);
+                            idle_allowance = idle_allowance.saturating_sub(batch - to_send);
                         }
                     }
                 }
             }
+            if idle_allowance < idle_allowed {
+                idle_polls.on_polls_sent(current_time);
+            }
+            // Pool polls bypass idle polling: each one waits at the server until
+            // it has data, so a quiet session costs one query per hold time.
+            if config.poll_pool > 0 && !quiet_hours.is_quiet() {
+                for resolver in resolvers.iter_mut() {
+                    if !refresh_resolver_path(cnx, resolver)
+                        || !resolver.health.allow_poll(current_time)
+                    {
+                        continue;
+                    }
+                    let mut to_send = poll_pool_deficit(resolver, config.poll_pool)
+                        .min(path_poll_burst_max(resolver));
+                    if to_send > 0 {
+                        send_poll_queries(
+                            cnx,
+                            transport,
+                            config,
+                            &mut local_addr_storage,
+                            &mut dns_ids,
+                            &mut recorder,
+                            &mut query_budget,
+                            resolver,
+                            &mut to_send,
+                            &mut send_buf,
+                        )
+                        .await?;
+                    }
+                }
+            }
+            // The refresh poll bypasses idle polling; it is the one query a quiet
+            // session is allowed.
+            let refresh_target = primary
+                .current()
+                .filter(|index| resolvers.get(*index).is_some_and(|resolver| resolver.added))
+                .or_else(|| resolvers.iter().position(|resolver| resolver.added));
+            if let (true, Some(index)) = (refresh_probe, refresh_target) {
+                let mut to_send = 1;
+                send_poll_queries(
+                    cnx,
+                    transport,
+                    config,
+                    &mut local_addr_storage,
+                    &mut dns_ids,
+                    &mut recorder,
+                    &mut query_budget,
+                    &mut resolvers[index],
+                    &mut to_send,
+                    &mut send_buf,
+                )
+                .await?;
+            }
+
+            if query_budget.spent() > queries_before_send {
+                if let Some(latency) = unsafe { (*state_ptr).take_queue_latency() } {
+                    session.info_mut().internal_queue_latency_ms = Some(latency.as_millis() as u64);
+                }
+            }
 
             let report_time = unsafe { picoquic_current_time() };
             let (enqueued_bytes, last_enqueue_at) = unsafe { (*state_ptr).debug_snapshot() };
@@ -634,16 +1537,119 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
                     resolver.last_pacing_snapshot,
                 );
             }
+            session.maybe_publish(report_time, |info| {
+                info.active_streams = streams_len;
+                info.resolvers = resolver_infos(cnx, &resolvers);
+                info.max_query_bytes = info.max_query_bytes.max(largest_query(&resolvers));
+                info.primary_resolver = primary
+                    .current()
+                    .and_then(|index| resolvers.get(index))
+                    .map(|resolver| resolver.addr.to_string());
+                info.active_features = active_features(config, &resolvers);
+                info.refresh_retransmit_stats();
+                info.refresh_direction_stats();
+                info.stream_bytes = unsafe { (*state_ptr).stream_bytes() };
+                info.lifetime_queries = query_budget.spent();
+                let backpressure = unsafe { (*state_ptr).backpressure() };
+                info.local_read_pauses = backpressure.local_read_pauses.load(Ordering::Relaxed);
+                info.tunnel_send_blocked = tunnel_send_blocked;
+                info.buffer_cap_drops = backpressure.buffer_cap_drops.load(Ordering::Relaxed);
+                if let Some((window, held_back)) = unsafe { (*state_ptr).bulk_window_stats() } {
+                    info.bulk_window_bytes = Some(window);
+                    info.bulk_held_back = held_back;
+                }
+                info.reassembly_bytes = unsafe { slipstream_get_unconsumed_data(cnx) };
+                info.reassembly_limit_bytes = reassembly_limit;
+                (info.connection_setups, info.connection_setups_refused) =
+                    unsafe { (*state_ptr).setup_stats() };
+                let clock_estimate = clock.estimate();
+                info.clock_offset_ms = clock_estimate.map(|sample| sample.offset_ms());
+                info.clock_offset_uncertainty_ms =
+                    clock_estimate.map(|sample| sample.uncertainty_ms());
+            });
         }
 
+        session.info_mut().buffer_cap_drops = unsafe { (*state_ptr).backpressure() }
+            .buffer_cap_drops
+            .load(Ordering::Relaxed);
+        let max_query_bytes = &mut session.info_mut().max_query_bytes;
+        *max_query_bytes = (*max_query_bytes).max(largest_query(&resolvers));
+        session.end_connection(resolver_infos(cnx, &resolvers));
+        if let Some(span) = handshake_span.take() {
+            span.record("outcome", "failed");
+        }
+        let was_idle = unsafe { (*state_ptr).streams_len() } == 0;
         unsafe {
             picoquic_close(cnx, 0);
         }
+        if protection_lost {
+            error!("VPN protection of the DNS socket was lost; dropping the tunnel");
+            reset_quic_ready();
+            session.set_protection_lost(unsafe { picoquic_current_time() });
+            unsafe {
+                (*state_ptr).reset_for_reconnect();
+            }
+            notify_protection_lost();
+            if config.protection_loss_policy == ProtectionLossPolicy::Stop {
+                session.finish();
+                return Err(ClientError::new(
+                    "VPN protection of the DNS socket was lost",
+                ));
+            }
+            // Hold: send nothing and refuse local connections until stopped.
+            loop {
+                if should_shutdown() {
+                    info!("Shutdown signal received while protection was lost, exiting");
+                    session.finish();
+                    return Ok(0);
+                }
+                sleep(Duration::from_millis(100)).await;
+                let _ = drain_disconnected_commands(&mut command_rx);
+            }
+        }
+        // A drop we forced says nothing about the keep-alive interval or the network.
+        let forced_close = closed_after_sleep || scheduled_pause || network_offline;
+        if quic_ready_signaled && !forced_close {
+            keep_alive.on_connection_lost(was_idle);
+            session.info_mut().keep_alive_interval_ms = keep_alive.interval_ms();
+        }
+        record_current_network(learned_tuning(
+            &keep_alive,
+            &resolvers,
+            network_tuning.as_ref(),
+        ));
+
+        if let Some(incompatible) = unsafe { (*state_ptr).take_server_incompatible() } {
+            error!("{}", incompatible);
+            session.info_mut().server_incompatible = Some(incompatible.feature.to_string());
+        }
 
         // Track connection failures - if we never became ready, count as failure
-        if !quic_ready_signaled {
+        if !quic_ready_signaled && !forced_close {
+            let responses = resolvers
+                .iter()
+                .map(|resolver| resolver.debug.dns_responses)
+                .sum();
+            let tunnel_responses = resolvers
+                .iter()
+                .map(|resolver| resolver.tunnel_responses)
+                .sum();
+            let failure = AttemptFailure::classify(responses, tunnel_responses);
+            if block_detector.record_failure(failure) {
+                warn!(
+                    "PersistentBlockSuspected: {} attempts in a row failed with {}; retrying every {}ms",
+                    config.block_backoff_after,
+                    failure.as_str(),
+                    live.block_backoff_ms
+                );
+                session.info_mut().persistent_block_suspected = Some(failure.as_str().to_string());
+                notify_persistent_block_suspected(failure.as_str(), config.block_backoff_after);
+            }
+        }
+        if !quic_ready_signaled && !scheduled_pause && !network_offline {
             record_connection_failure();
-            if exceeded_max_failures() {
+            // A suspected block backs off instead of giving up.
+            if exceeded_max_failures() && !block_detector.is_suspected() {
                 error!("Exceeded max consecutive connection failures, giving up");
                 return Err(ClientError::new(
                     "Connection failed repeatedly - check network and server availability",
@@ -653,6 +1659,7 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
 
         // Reset QUIC ready state for reconnection
         reset_quic_ready();
+        session.set_connected(false, unsafe { picoquic_current_time() });
 
         unsafe {
             (*state_ptr).reset_for_reconnect();
@@ -665,26 +1672,95 @@ pub async fn run_client(config: &ClientConfig<'_>) -> Result<i32, ClientError> {
         // Check for shutdown before reconnecting
         if should_shutdown() {
             info!("Shutdown signal received during reconnect, exiting");
+            session.finish();
             return Ok(0);
         }
+        if scheduled_pause {
+            // The next attempt waits for the active window first.
+            continue;
+        }
+        if network_offline {
+            let now = unsafe { picoquic_current_time() };
+            warn!(
+                "NetworkOffline: no route to any resolver; waiting for connectivity (rechecking every {}s)",
+                OFFLINE_RECHECK.as_secs()
+            );
+            session.set_network_offline(true, now);
+            notify_network_offline(true);
+            // A change reported while online says nothing about this outage.
+            let _ = take_network_changed();
+            let wait = OfflineWait::new(now, OFFLINE_RECHECK);
+            loop {
+                if should_shutdown() {
+                    info!("Shutdown signal received while offline, exiting");
+                    session.finish();
+                    return Ok(0);
+                }
+                sleep(Duration::from_millis(100)).await;
+                let _ = drain_disconnected_commands(&mut command_rx);
+                let now = unsafe { picoquic_current_time() };
+                if let Some(event) = events.update(Some(TunnelCondition::Disconnected), now) {
+                    emit_tunnel_event(&event);
+                }
+                let changed = take_network_changed();
+                if wait.should_resume(now, changed) {
+                    info!(
+                        "{} after {}ms offline; reconnecting",
+                        if changed {
+                            "Network changed"
+                        } else {
+                            "Rechecking connectivity"
+                        },
+                        wait.elapsed_ms(now)
+                    );
+                    break;
+                }
+            }
+            session.set_network_offline(false, unsafe { picoquic_current_time() });
+            notify_network_offline(false);
+            reconnect_backoff.reset();
+            reconnect_limit.reset();
+            continue;
+        }
 
+        if let Some(event) = reconnect_limit.record_attempt(unsafe { picoquic_current_time() }) {
+            error!("GaveUp: reconnect limit reached, stopping");
+            emit_tunnel_event(&event);
+            // This connection's resolvers and tuning were recorded before the close.
+            session.finish();
+            return Err(ClientError::new(
+                "Gave up reconnecting - check the domain, resolvers and server",
+            ));
+        }
+        session.info_mut().reconnects += 1;
+        let reconnect_delay = if block_detector.is_suspected() {
+            Duration::from_millis(live.block_backoff_ms)
+        } else {
+            reconnect_backoff.delay()
+        };
         warn!(
             "Connection closed; reconnecting in {}ms",
             reconnect_delay.as_millis()
         );
+        emit_tunnel_event(&reconnect_backoff.reconnecting_event(reconnect_delay));
         // Sleep in small chunks and drop commands that arrive while disconnected.
         let mut remaining_sleep = reconnect_delay;
         while remaining_sleep > Duration::ZERO {
             // Check shutdown during sleep
             if should_shutdown() {
                 info!("Shutdown signal received during reconnect sleep, exiting");
+                session.finish();
                 return Ok(0);
             }
             let chunk = remaining_sleep.min(Duration::from_millis(100));
             sleep(chunk).await;
             remaining_sleep -= chunk;
+            let now = unsafe { picoquic_current_time() };
+            if let Some(event) = events.update(Some(TunnelCondition::Disconnected), now) {
+                emit_tunnel_event(&event);
+            }
             let _ = drain_disconnected_commands(&mut command_rx);
         }
-        reconnect_delay = (reconnect_delay * 2).min(Duration::from_millis(RECONNECT_SLEEP_MAX_MS));
+        reconnect_backoff.advance();
     }
 }
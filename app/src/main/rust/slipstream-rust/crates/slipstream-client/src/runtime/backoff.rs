@@ -0,0 +1,300 @@
+use crate::events::TunnelEvent;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+pub const DEFAULT_RECONNECT_MIN_MS: u64 = 250;
+pub const DEFAULT_RECONNECT_MAX_MS: u64 = 5_000;
+/// Reconnects in a row without a ready connection before the client gives up;
+/// well over an hour of retries at the default delay cap.
+pub const DEFAULT_MAX_RECONNECT_ATTEMPTS: u32 = 1_000;
+
+/// Reconnect delay schedule.
+///
+/// Without jitter the delay doubles from `min_ms` up to `max_ms`. With jitter it
+/// follows "decorrelated jitter": each delay is drawn uniformly from
+/// `[min_ms, previous * 3]` and capped, so clients that lost the tunnel at the same
+/// moment drift apart instead of retrying in lockstep.
+pub(crate) struct ReconnectBackoff {
+    min_ms: u64,
+    max_ms: u64,
+    jitter: bool,
+    current_ms: u64,
+    /// Retries waited out since the last reset.
+    attempts: u32,
+    rng_state: u64,
+}
+
+impl ReconnectBackoff {
+    pub(crate) fn new(min_ms: u64, max_ms: u64, jitter: bool) -> Self {
+        Self::with_seed(
+            min_ms,
+            max_ms,
+            jitter,
+            RandomState::new().build_hasher().finish(),
+        )
+    }
+
+    pub(crate) fn with_seed(min_ms: u64, max_ms: u64, jitter: bool, seed: u64) -> Self {
+        let min_ms = min_ms.max(1);
+        let max_ms = max_ms.max(min_ms);
+        Self {
+            min_ms,
+            max_ms,
+            jitter,
+            current_ms: min_ms,
+            attempts: 0,
+            // xorshift never leaves the all-zero state.
+            rng_state: seed | 1,
+        }
+    }
+
+    pub(crate) fn delay(&self) -> Duration {
+        Duration::from_millis(self.current_ms)
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.current_ms = self.min_ms;
+        self.attempts = 0;
+    }
+
+    /// The `reconnecting` event for the next retry, which waits `delay`: the
+    /// current [`delay`](Self::delay) unless something else set the wait.
+    pub(crate) fn reconnecting_event(&self, delay: Duration) -> TunnelEvent {
+        TunnelEvent::Reconnecting {
+            attempt: self.attempts.saturating_add(1),
+            retry_in_ms: delay.as_millis() as u64,
+        }
+    }
+
+    pub(crate) fn advance(&mut self) {
+        let next = if self.jitter {
+            let upper = self.current_ms.saturating_mul(3).max(self.min_ms);
+            self.min_ms + self.next_random() % (upper - self.min_ms + 1)
+        } else {
+            self.current_ms.saturating_mul(2)
+        };
+        self.current_ms = next.min(self.max_ms);
+        self.attempts = self.attempts.saturating_add(1);
+    }
+
+    fn next_random(&mut self) -> u64 {
+        let mut x = self.rng_state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.rng_state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// Hard stop for reconnects that keep failing.
+///
+/// Counts the reconnects made since the last ready connection and the time
+/// since that connection was lost; a limit of 0 is no limit. Block backoff
+/// only spaces attempts out, so this is what ends them. Waiting for the
+/// network or for an active window is not an attempt, and starts the count
+/// over like a ready connection.
+pub(crate) struct ReconnectLimit {
+    max_attempts: u32,
+    max_duration_us: u64,
+    attempts: u32,
+    lost_at: Option<u64>,
+}
+
+impl ReconnectLimit {
+    pub(crate) fn new(max_attempts: u32, max_duration_ms: u64) -> Self {
+        Self {
+            max_attempts,
+            max_duration_us: max_duration_ms.saturating_mul(1_000),
+            attempts: 0,
+            lost_at: None,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.attempts = 0;
+        self.lost_at = None;
+    }
+
+    /// Counts the reconnect about to be made at `now`. Returns the `gave_up`
+    /// event instead when the attempts or the time are used up.
+    pub(crate) fn record_attempt(&mut self, now: u64) -> Option<TunnelEvent> {
+        let lost_at = *self.lost_at.get_or_insert(now);
+        let elapsed_us = now.saturating_sub(lost_at);
+        let out_of_attempts = self.max_attempts > 0 && self.attempts >= self.max_attempts;
+        let out_of_time = self.max_duration_us > 0 && elapsed_us >= self.max_duration_us;
+        if out_of_attempts || out_of_time {
+            return Some(TunnelEvent::GaveUp {
+                attempts: self.attempts,
+                after_ms: elapsed_us / 1_000,
+            });
+        }
+        self.attempts = self.attempts.saturating_add(1);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn without_jitter_doubles_to_the_cap() {
+        let mut backoff = ReconnectBackoff::new(250, 5_000, false);
+        let delays: Vec<u64> = (0..7)
+            .map(|_| {
+                let delay = backoff.delay().as_millis() as u64;
+                backoff.advance();
+                delay
+            })
+            .collect();
+        assert_eq!(delays, [250, 500, 1_000, 2_000, 4_000, 5_000, 5_000]);
+        backoff.reset();
+        assert_eq!(backoff.delay(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn decorrelated_jitter_stays_in_bounds_and_spreads() {
+        let mut buckets = [0u32; 10];
+        let mut samples = 0u32;
+        for seed in 0..200u64 {
+            let mut backoff = ReconnectBackoff::with_seed(250, 5_000, true, seed);
+            let mut previous = backoff.delay().as_millis() as u64;
+            for _ in 0..20 {
+                backoff.advance();
+                let delay = backoff.delay().as_millis() as u64;
+                assert!(delay >= 250, "delay {} below min", delay);
+                assert!(delay <= 5_000, "delay {} above cap", delay);
+                assert!(delay <= (previous * 3).min(5_000));
+                buckets[((delay - 250) * 10 / 4_751) as usize] += 1;
+                samples += 1;
+                previous = delay;
+            }
+        }
+        // Every tenth of the range is hit, and no single bucket dominates, which
+        // rules out lockstep retries at the cap.
+        for (idx, count) in buckets.iter().enumerate() {
+            assert!(*count > 0, "bucket {} never hit", idx);
+            assert!(
+                *count < samples / 2,
+                "bucket {} holds {} of {}",
+                idx,
+                count,
+                samples
+            );
+        }
+    }
+
+    #[test]
+    fn reconnecting_events_count_attempts_and_carry_each_delay() {
+        let mut backoff = ReconnectBackoff::with_seed(250, 5_000, true, 7);
+        for attempt in 1..=6 {
+            let delay = backoff.delay();
+            assert_eq!(
+                backoff.reconnecting_event(delay),
+                TunnelEvent::Reconnecting {
+                    attempt,
+                    retry_in_ms: delay.as_millis() as u64,
+                }
+            );
+            backoff.advance();
+        }
+        let blocked = Duration::from_millis(60_000);
+        assert_eq!(
+            backoff.reconnecting_event(blocked),
+            TunnelEvent::Reconnecting {
+                attempt: 7,
+                retry_in_ms: 60_000,
+            }
+        );
+        backoff.reset();
+        assert_eq!(
+            backoff.reconnecting_event(backoff.delay()),
+            TunnelEvent::Reconnecting {
+                attempt: 1,
+                retry_in_ms: 250,
+            }
+        );
+    }
+
+    /// Reconnects made before the client gives up, with every attempt failing
+    /// after `attempt_ms`, and the `gave_up` event.
+    fn reconnect_until_giving_up(
+        limit: &mut ReconnectLimit,
+        attempt_ms: u64,
+    ) -> (u32, Option<TunnelEvent>) {
+        let mut backoff = ReconnectBackoff::new(250, 5_000, false);
+        let mut now = 0u64;
+        let mut made = 0;
+        while made < 100_000 {
+            if let Some(event) = limit.record_attempt(now) {
+                return (made, Some(event));
+            }
+            now += (backoff.delay().as_millis() as u64 + attempt_ms) * 1_000;
+            backoff.advance();
+            made += 1;
+        }
+        (made, None)
+    }
+
+    #[test]
+    fn the_client_gives_up_after_the_configured_attempts() {
+        let mut limit = ReconnectLimit::new(5, 0);
+        let (made, event) = reconnect_until_giving_up(&mut limit, 1_000);
+        assert_eq!(made, 5);
+        // 250 + 500 + 1000 + 2000 + 4000ms of waits and five 1s attempts.
+        assert_eq!(
+            event,
+            Some(TunnelEvent::GaveUp {
+                attempts: 5,
+                after_ms: 12_750,
+            })
+        );
+
+        // A ready connection, or a network wait, starts the count over.
+        limit.reset();
+        assert_eq!(reconnect_until_giving_up(&mut limit, 1_000).0, 5);
+
+        let mut default = ReconnectLimit::new(DEFAULT_MAX_RECONNECT_ATTEMPTS, 0);
+        let (made, _) = reconnect_until_giving_up(&mut default, 1_000);
+        assert_eq!(made, DEFAULT_MAX_RECONNECT_ATTEMPTS);
+        let mut unlimited = ReconnectLimit::new(0, 0);
+        assert_eq!(
+            reconnect_until_giving_up(&mut unlimited, 1_000),
+            (100_000, None)
+        );
+    }
+
+    #[test]
+    fn the_client_gives_up_once_reconnecting_took_too_long() {
+        let mut limit = ReconnectLimit::new(0, 60_000);
+        let (made, event) = reconnect_until_giving_up(&mut limit, 1_000);
+        // Waits of 250ms to 5s plus 1s per attempt pass the minute after 13.
+        assert_eq!(made, 13);
+        assert_eq!(
+            event,
+            Some(TunnelEvent::GaveUp {
+                attempts: 13,
+                after_ms: 60_750,
+            })
+        );
+
+        // Whichever limit is reached first applies.
+        let mut both = ReconnectLimit::new(3, 60_000);
+        assert_eq!(reconnect_until_giving_up(&mut both, 1_000).0, 3);
+    }
+
+    #[test]
+    fn jittered_clients_diverge() {
+        let mut first = ReconnectBackoff::with_seed(250, 5_000, true, 1);
+        let mut second = ReconnectBackoff::with_seed(250, 5_000, true, 2);
+        let mut differs = false;
+        for _ in 0..5 {
+            first.advance();
+            second.advance();
+            differs |= first.delay() != second.delay();
+        }
+        assert!(differs);
+    }
+}
@@ -0,0 +1,113 @@
+pub const DEFAULT_BLOCK_BACKOFF_MS: u64 = 300_000;
+
+/// Why a connection attempt never became ready.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum AttemptFailure {
+    /// No resolver answered a single query.
+    NoAnswers,
+    /// Resolvers answered, but no answer carried tunnel data (NXDOMAIN, REFUSED,
+    /// rewritten or empty answers).
+    NoTunnelData,
+    /// Tunnel data came back, but the QUIC handshake never completed.
+    Handshake,
+}
+
+impl AttemptFailure {
+    pub(crate) fn classify(responses: u64, tunnel_responses: u64) -> Self {
+        if responses == 0 {
+            AttemptFailure::NoAnswers
+        } else if tunnel_responses == 0 {
+            AttemptFailure::NoTunnelData
+        } else {
+            AttemptFailure::Handshake
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            AttemptFailure::NoAnswers => "no_answers",
+            AttemptFailure::NoTunnelData => "no_tunnel_data",
+            AttemptFailure::Handshake => "handshake",
+        }
+    }
+}
+
+/// Spots a domain that is blocked rather than briefly unreachable.
+///
+/// A block fails every attempt the same way, so `threshold` failed attempts in a
+/// row with one failure kind count as a persistent block. Any other kind restarts
+/// the run, and a ready connection clears it. A threshold of 0 disables it.
+pub(crate) struct BlockDetector {
+    threshold: u32,
+    last: Option<AttemptFailure>,
+    streak: u32,
+}
+
+impl BlockDetector {
+    pub(crate) fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            last: None,
+            streak: 0,
+        }
+    }
+
+    /// Records a failed attempt and returns true when it starts a suspected block.
+    pub(crate) fn record_failure(&mut self, failure: AttemptFailure) -> bool {
+        if self.last == Some(failure) {
+            self.streak = self.streak.saturating_add(1);
+        } else {
+            self.last = Some(failure);
+            self.streak = 1;
+        }
+        self.threshold > 0 && self.streak == self.threshold
+    }
+
+    pub(crate) fn is_suspected(&self) -> bool {
+        self.threshold > 0 && self.streak >= self.threshold
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.last = None;
+        self.streak = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_failures_in_a_row_suspect_a_block() {
+        let mut detector = BlockDetector::new(3);
+        assert!(!detector.record_failure(AttemptFailure::NoTunnelData));
+        assert!(!detector.record_failure(AttemptFailure::NoTunnelData));
+        // A different failure kind looks like a flaky network, not a block.
+        assert!(!detector.record_failure(AttemptFailure::NoAnswers));
+        assert!(!detector.record_failure(AttemptFailure::NoAnswers));
+        assert!(detector.record_failure(AttemptFailure::NoAnswers));
+        assert!(detector.is_suspected());
+        // Reported once per run, but the long backoff stays in force.
+        assert!(!detector.record_failure(AttemptFailure::NoAnswers));
+        assert!(detector.is_suspected());
+
+        detector.reset();
+        assert!(!detector.is_suspected());
+
+        let mut disabled = BlockDetector::new(0);
+        for _ in 0..10 {
+            assert!(!disabled.record_failure(AttemptFailure::Handshake));
+        }
+        assert!(!disabled.is_suspected());
+    }
+
+    #[test]
+    fn classifies_failures_by_what_came_back() {
+        assert_eq!(AttemptFailure::classify(0, 0), AttemptFailure::NoAnswers);
+        assert_eq!(
+            AttemptFailure::classify(12, 0),
+            AttemptFailure::NoTunnelData
+        );
+        assert_eq!(AttemptFailure::classify(12, 3), AttemptFailure::Handshake);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::dns::ResolverState;
+
+/// Holds back the connected signal until several resolvers carried tunnel data.
+///
+/// A handshake can finish through one resolver that answers only briefly. With
+/// `required` above 1, the connection counts as up only once that many distinct
+/// resolvers returned QUIC packets, which also makes sure their paths were
+/// primed. The requirement is capped at the number of resolvers.
+pub(crate) struct ConnectConfirmation {
+    required: usize,
+}
+
+impl ConnectConfirmation {
+    pub(crate) fn new(required: usize, resolver_count: usize) -> Self {
+        Self {
+            required: required.clamp(1, resolver_count.max(1)),
+        }
+    }
+
+    pub(crate) fn required(&self) -> usize {
+        self.required
+    }
+
+    pub(crate) fn is_confirmed(&self, resolvers: &[ResolverState]) -> bool {
+        self.observe(
+            resolvers
+                .iter()
+                .map(|resolver| resolver.tunnel_responses > 0),
+        )
+    }
+
+    /// `carried_data` tells, per resolver, whether it returned a QUIC packet on
+    /// this connection.
+    fn observe(&self, carried_data: impl Iterator<Item = bool>) -> bool {
+        carried_data.filter(|carried| *carried).count() >= self.required
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_working_resolver_does_not_confirm_two() {
+        let confirmation = ConnectConfirmation::new(2, 3);
+        // The handshake finished through the first resolver; the others are silent.
+        assert!(!confirmation.observe([true, false, false].into_iter()));
+        assert!(confirmation.observe([true, false, true].into_iter()));
+
+        // The default needs one resolver; larger counts are capped.
+        assert!(ConnectConfirmation::new(0, 2).observe([false, true].into_iter()));
+        let capped = ConnectConfirmation::new(5, 2);
+        assert_eq!(capped.required(), 2);
+        assert!(!capped.observe([true, false].into_iter()));
+        assert!(capped.observe([true, true].into_iter()));
+    }
+}
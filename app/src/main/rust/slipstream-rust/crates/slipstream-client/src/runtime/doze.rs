@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+// Wall-clock lead over the monotonic clock that counts as a suspend.
+const SLEEP_DETECT_THRESHOLD_US: u64 = 10_000_000;
+// Past this, NAT bindings and the server's idle timer have likely expired.
+pub(crate) const DOZE_RECONNECT_AFTER: Duration = Duration::from_secs(60);
+
+/// Spots device suspend (Android Doze) from clock divergence.
+///
+/// picoquic's clock is wall time, which keeps running while the device sleeps,
+/// but `Instant` is `CLOCK_MONOTONIC`, which stops. A wall-clock lead well over
+/// the monotonic time between two loop iterations means the process was frozen.
+/// A manual clock change forward looks the same; backward jumps are ignored.
+pub(crate) struct SleepDetector {
+    last_wall_us: u64,
+    last_monotonic: Instant,
+}
+
+impl SleepDetector {
+    pub(crate) fn new(wall_us: u64, monotonic: Instant) -> Self {
+        Self {
+            last_wall_us: wall_us,
+            last_monotonic: monotonic,
+        }
+    }
+
+    /// Returns how long the device slept since the previous call, if it did.
+    pub(crate) fn check(&mut self, wall_us: u64, monotonic: Instant) -> Option<Duration> {
+        let wall_elapsed_us = wall_us.saturating_sub(self.last_wall_us);
+        let monotonic_elapsed_us = monotonic
+            .saturating_duration_since(self.last_monotonic)
+            .as_micros() as u64;
+        self.last_wall_us = wall_us;
+        self.last_monotonic = monotonic;
+        let slept_us = wall_elapsed_us.saturating_sub(monotonic_elapsed_us);
+        (slept_us >= SLEEP_DETECT_THRESHOLD_US).then(|| Duration::from_micros(slept_us))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_wall_clock_lead_as_sleep() {
+        let start = Instant::now();
+        let mut detector = SleepDetector::new(1_000_000, start);
+        // Both clocks advance together while awake, however long the wait.
+        assert_eq!(
+            detector.check(31_000_000, start + Duration::from_secs(30)),
+            None
+        );
+        // Two minutes of wall time pass in one monotonic second.
+        let slept = detector.check(152_000_000, start + Duration::from_secs(31));
+        assert_eq!(slept, Some(Duration::from_secs(120)));
+        // Small scheduling jitter and backward clock steps are not sleeps.
+        assert_eq!(
+            detector.check(153_500_000, start + Duration::from_secs(32)),
+            None
+        );
+        assert_eq!(
+            detector.check(100_000_000, start + Duration::from_secs(33)),
+            None
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use crate::dns::ResolverState;
+use crate::error::ClientError;
+use crate::streams::ClientState;
+use slipstream_ffi::picoquic::{picoquic_cnx_t, picoquic_set_stream_path_affinity};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+/// A request to send the next `queries` queries through resolver `index`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ForceRequest {
+    pub(crate) index: usize,
+    pub(crate) queries: u64,
+}
+
+struct ForceSlot {
+    resolvers: usize,
+    pending: Option<ForceRequest>,
+}
+
+static FORCE: Mutex<Option<ForceSlot>> = Mutex::new(None);
+
+/// Makes the running client the target of [`force_resolver`] until dropped.
+pub(crate) struct ForceTarget(());
+
+impl ForceTarget {
+    pub(crate) fn register(resolvers: usize) -> Self {
+        if let Ok(mut slot) = FORCE.lock() {
+            *slot = Some(ForceSlot {
+                resolvers,
+                pending: None,
+            });
+        }
+        Self(())
+    }
+
+    /// Updates the resolver count requests are checked against.
+    pub(crate) fn set_resolvers(&self, resolvers: usize) {
+        if let Ok(mut slot) = FORCE.lock() {
+            if let Some(slot) = slot.as_mut() {
+                slot.resolvers = resolvers;
+            }
+        }
+    }
+}
+
+impl Drop for ForceTarget {
+    fn drop(&mut self) {
+        if let Ok(mut slot) = FORCE.lock() {
+            *slot = None;
+        }
+    }
+}
+
+/// Sends the next `queries` queries through the resolver at `index`, in
+/// configured order, whatever its health; the scheduler takes over again
+/// afterwards. 0 ends a running pin early. Meant for debugging one resolver.
+///
+/// Fails when no client is running or `index` is out of range.
+// Called by the Android bridge, the status endpoint and library callers.
+#[allow(dead_code)]
+pub fn force_resolver(index: usize, queries: u64) -> Result<(), ClientError> {
+    let mut slot = FORCE
+        .lock()
+        .map_err(|_| ClientError::new("Resolver pin state is poisoned"))?;
+    let Some(slot) = slot.as_mut() else {
+        return Err(ClientError::new("No client is running"));
+    };
+    if index >= slot.resolvers {
+        return Err(ClientError::invalid_resolver_index(index, slot.resolvers));
+    }
+    slot.pending = Some(ForceRequest { index, queries });
+    Ok(())
+}
+
+/// A pin request the runtime has not picked up yet.
+pub(crate) fn take_force_request() -> Option<ForceRequest> {
+    FORCE.lock().ok()?.as_mut()?.pending.take()
+}
+
+/// Keeps stream data on one resolver's path for a number of queries.
+///
+/// Every stream's path affinity points at the pinned path before each packet,
+/// which takes precedence over health, roles and striping. Acknowledgements and
+/// polls still go to the other resolvers. A pinned resolver without a path yet
+/// holds the pin until its path is added.
+#[derive(Debug, Default)]
+pub(crate) struct ResolverPin {
+    target: Option<ForceRequest>,
+    pinned: bool,
+}
+
+impl ResolverPin {
+    pub(crate) fn start(&mut self, request: ForceRequest) {
+        self.target = (request.queries > 0).then_some(request);
+    }
+
+    /// Pins the streams for the next packet; false when no pin is active.
+    pub(crate) fn before_packet(
+        &mut self,
+        cnx: *mut picoquic_cnx_t,
+        state: &ClientState,
+        resolvers: &[ResolverState],
+    ) -> bool {
+        let target = self.target.map(|target| {
+            resolvers
+                .get(target.index)
+                .filter(|resolver| resolver.added)
+                .and_then(|resolver| resolver.unique_path_id)
+        });
+        let affinity = match target {
+            Some(Some(unique_path_id)) => unique_path_id,
+            Some(None) => return true,
+            None if self.pinned => u64::MAX,
+            None => return false,
+        };
+        for stream_id in state.stream_ids() {
+            unsafe {
+                picoquic_set_stream_path_affinity(cnx, stream_id, affinity);
+            }
+        }
+        self.pinned = self.target.is_some();
+        self.pinned
+    }
+
+    /// Counts a query sent to `dest`; returns the resolver index once its pin
+    /// has used up its queries.
+    pub(crate) fn record_query(
+        &mut self,
+        resolvers: &[ResolverState],
+        dest: SocketAddr,
+    ) -> Option<usize> {
+        let target = self.target.as_mut()?;
+        if resolvers.get(target.index)?.addr != dest {
+            return None;
+        }
+        target.queries -= 1;
+        if target.queries > 0 {
+            return None;
+        }
+        let index = target.index;
+        self.target = None;
+        Some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{resolve_resolvers, HealthPolicy};
+    use crate::error::ClientErrorKind;
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_ffi::{ResolverMode, ResolverSpec};
+
+    #[test]
+    fn pin_counts_queries_to_its_resolver_then_ends() {
+        let specs: Vec<_> = ["192.0.2.1", "192.0.2.2"]
+            .iter()
+            .map(|host| ResolverSpec {
+                resolver: HostPort {
+                    host: host.to_string(),
+                    port: 53,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+            })
+            .collect();
+        let resolvers = resolve_resolvers(&specs, 900, false, HealthPolicy::default()).unwrap();
+        assert!(force_resolver(1, 2).is_err());
+
+        let target = ForceTarget::register(resolvers.len());
+        let err = force_resolver(2, 2).expect_err("out of range");
+        assert_eq!(err.kind(), ClientErrorKind::InvalidResolverIndex);
+        force_resolver(1, 2).expect("force");
+        let request = take_force_request().expect("pending request");
+        assert_eq!(
+            request,
+            ForceRequest {
+                index: 1,
+                queries: 2
+            }
+        );
+        assert!(take_force_request().is_none());
+
+        let mut pin = ResolverPin::default();
+        pin.start(request);
+        assert_eq!(pin.record_query(&resolvers, resolvers[0].addr), None);
+        assert_eq!(pin.record_query(&resolvers, resolvers[1].addr), None);
+        assert_eq!(pin.record_query(&resolvers, resolvers[1].addr), Some(1));
+        assert_eq!(pin.record_query(&resolvers, resolvers[1].addr), None);
+
+        pin.start(ForceRequest {
+            index: 0,
+            queries: 5,
+        });
+        pin.start(ForceRequest {
+            index: 0,
+            queries: 0,
+        });
+        assert_eq!(pin.record_query(&resolvers, resolvers[0].addr), None);
+
+        drop(target);
+        assert!(force_resolver(0, 1).is_err());
+    }
+}
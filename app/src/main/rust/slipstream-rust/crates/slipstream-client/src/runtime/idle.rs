@@ -0,0 +1,219 @@
+// Quiet time after the last stream data before polling slows down, so the
+// gaps between page loads keep full-speed polling.
+const IDLE_POLL_GRACE_US: u64 = 2_000_000;
+/// Default first gap between idle polls; it doubles from here up to the cap.
+pub const DEFAULT_IDLE_POLL_MIN_MS: u64 = 500;
+
+/// Spaces out polls while no stream data moves.
+///
+/// When nothing is being fetched every answer still re-arms another poll, so a
+/// quiet client keeps querying at the path's round-trip rate. Once no stream
+/// data has moved either way for the grace period, at most one poll is let
+/// through per interval. The interval starts at `min_interval_us` and doubles
+/// up to `max_interval_us`, or stays at the minimum while streams are open, so
+/// a slow answer on an open stream waits at most that long. Data on any stream,
+/// or a new stream, lifts the limit at once. Only polls are held back; QUIC
+/// data and keep-alives go out as usual. A cap of 0 disables it.
+pub(crate) struct IdlePolling {
+    min_interval_us: u64,
+    max_interval_us: u64,
+    idle_since_us: Option<u64>,
+    interval_us: u64,
+    next_poll_at_us: u64,
+    active_streams: usize,
+    stream_bytes: u64,
+}
+
+impl IdlePolling {
+    pub(crate) fn new(min_interval_ms: u64, max_interval_ms: u64) -> Self {
+        let max_interval_us = max_interval_ms.saturating_mul(1_000);
+        Self {
+            min_interval_us: min_interval_ms
+                .saturating_mul(1_000)
+                .clamp(1, max_interval_us.max(1)),
+            max_interval_us,
+            idle_since_us: None,
+            interval_us: 0,
+            next_poll_at_us: 0,
+            active_streams: 0,
+            stream_bytes: 0,
+        }
+    }
+
+    /// Feeds the open stream count and the bytes streams moved so far.
+    pub(crate) fn update(&mut self, now_us: u64, active_streams: usize, stream_bytes: u64) {
+        if self.max_interval_us == 0 {
+            return;
+        }
+        let opened = active_streams > self.active_streams;
+        let moved = stream_bytes != self.stream_bytes;
+        self.active_streams = active_streams;
+        self.stream_bytes = stream_bytes;
+        if opened || moved {
+            self.wake(now_us);
+        } else {
+            self.idle_since_us.get_or_insert(now_us);
+        }
+    }
+
+    /// Polls at full rate again, e.g. while a handshake needs its polls.
+    pub(crate) fn wake(&mut self, now_us: u64) {
+        self.idle_since_us = Some(now_us);
+        self.interval_us = 0;
+        self.next_poll_at_us = 0;
+    }
+
+    pub(crate) fn is_throttling(&self, now_us: u64) -> bool {
+        self.idle_since_us
+            .is_some_and(|since| now_us.saturating_sub(since) >= IDLE_POLL_GRACE_US)
+    }
+
+    /// Polls that may go out now; unlimited unless idle polling is in force.
+    pub(crate) fn allowance(&self, now_us: u64) -> usize {
+        if !self.is_throttling(now_us) {
+            usize::MAX
+        } else if now_us >= self.next_poll_at_us {
+            1
+        } else {
+            0
+        }
+    }
+
+    /// Time until the next idle poll is due, if polls are being held back.
+    pub(crate) fn wait_us(&self, now_us: u64) -> Option<u64> {
+        self.is_throttling(now_us)
+            .then(|| self.next_poll_at_us.saturating_sub(now_us))
+    }
+
+    /// Gap between polls in force, in ms; 0 while polling at full rate.
+    pub(crate) fn interval_ms(&self, now_us: u64) -> u64 {
+        if self.is_throttling(now_us) {
+            self.interval_us.max(self.min_interval_us) / 1_000
+        } else {
+            0
+        }
+    }
+
+    pub(crate) fn on_polls_sent(&mut self, now_us: u64) {
+        if !self.is_throttling(now_us) {
+            return;
+        }
+        let cap = if self.active_streams > 0 {
+            self.min_interval_us
+        } else {
+            self.max_interval_us
+        };
+        self.interval_us = if self.interval_us == 0 {
+            self.min_interval_us
+        } else {
+            self.interval_us.saturating_mul(2)
+        }
+        .min(cap);
+        self.next_poll_at_us = now_us.saturating_add(self.interval_us);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ramps_down_when_idle_and_back_up_on_the_next_stream() {
+        let mut idle = IdlePolling::new(DEFAULT_IDLE_POLL_MIN_MS, 4_000);
+        idle.update(0, 1, 0);
+        assert_eq!(idle.allowance(0), usize::MAX);
+
+        // The last stream closes; polling stays unthrottled through the grace.
+        idle.update(1_000_000, 0, 100);
+        assert_eq!(
+            idle.allowance(1_000_000 + IDLE_POLL_GRACE_US - 1),
+            usize::MAX
+        );
+
+        let mut now = 1_000_000 + IDLE_POLL_GRACE_US;
+        let mut gaps = Vec::new();
+        for _ in 0..5 {
+            idle.update(now, 0, 100);
+            assert_eq!(idle.allowance(now), 1);
+            idle.on_polls_sent(now);
+            let wait = idle.wait_us(now).expect("throttling");
+            assert_eq!(idle.allowance(now + wait - 1), 0);
+            assert_eq!(idle.interval_ms(now), wait / 1_000);
+            gaps.push(wait / 1_000);
+            now += wait;
+        }
+        assert_eq!(gaps, [500, 1_000, 2_000, 4_000, 4_000]);
+
+        // A new stream lifts the limit within the same loop turn.
+        idle.update(now + 1, 1, 100);
+        assert_eq!(idle.allowance(now + 1), usize::MAX);
+        assert_eq!(idle.wait_us(now + 1), None);
+        assert_eq!(idle.interval_ms(now + 1), 0);
+
+        // Going idle again restarts the grace and the ramp from the floor.
+        idle.update(now + 2, 0, 200);
+        let restart = now + 2 + IDLE_POLL_GRACE_US;
+        idle.update(restart, 0, 200);
+        idle.on_polls_sent(restart);
+        assert_eq!(
+            idle.wait_us(restart),
+            Some(DEFAULT_IDLE_POLL_MIN_MS * 1_000)
+        );
+    }
+
+    #[test]
+    fn adapts_to_bursty_downstream_traffic() {
+        const MS: u64 = 1_000;
+        let mut idle = IdlePolling::new(250, 8_000);
+        let mut bytes = 0;
+        let mut interval_at_burst = Vec::new();
+        let mut now = 0;
+        // One open stream gets a 300ms burst of data every 10s, or every 30s
+        // once the stream closes.
+        for (streams, period_ms) in [(1, 10_000), (0, 30_000), (1, 10_000)] {
+            for _ in 0..3 {
+                let burst_end = now + 300 * MS;
+                interval_at_burst.push(idle.interval_ms(now));
+                while now < burst_end {
+                    bytes += 1_200;
+                    idle.update(now, streams, bytes);
+                    assert_eq!(
+                        idle.allowance(now),
+                        usize::MAX,
+                        "full rate while data flows"
+                    );
+                    now += 20 * MS;
+                }
+                let quiet_end = now + period_ms * MS - 300 * MS;
+                let mut spaced_polls = 0;
+                while now < quiet_end {
+                    idle.update(now, streams, bytes);
+                    // Polls at full rate are not counted; 1 is a spaced-out one.
+                    if idle.allowance(now) == 1 {
+                        idle.on_polls_sent(now);
+                        spaced_polls += 1;
+                    }
+                    now += 10 * MS;
+                }
+                // After the grace, polls on an open stream stay at the floor;
+                // with no stream they ramp up to the cap.
+                let expected = if streams > 0 { 31 } else { 8 };
+                assert_eq!(spaced_polls, expected);
+            }
+        }
+        assert_eq!(
+            interval_at_burst,
+            [0, 250, 250, 250, 8_000, 8_000, 8_000, 250, 250]
+        );
+    }
+
+    #[test]
+    fn zero_cap_disables_idle_polling() {
+        let mut idle = IdlePolling::new(DEFAULT_IDLE_POLL_MIN_MS, 0);
+        idle.update(0, 0, 0);
+        idle.on_polls_sent(60_000_000);
+        assert_eq!(idle.allowance(60_000_000), usize::MAX);
+        assert_eq!(idle.wait_us(60_000_000), None);
+        assert_eq!(idle.interval_ms(60_000_000), 0);
+    }
+}
@@ -0,0 +1,166 @@
+use tracing::info;
+
+// Upper bound for probing; stays below common QUIC and NAT idle timeouts.
+const ADAPTIVE_KEEP_ALIVE_MAX_MS: u64 = 25_000;
+// How long an idle session must survive at an interval before probing longer.
+const ADAPTIVE_KEEP_ALIVE_STABLE_US: u64 = 30_000_000;
+// Margin kept under the last interval that was known to survive.
+const ADAPTIVE_KEEP_ALIVE_MARGIN_PERCENT: u64 = 90;
+
+/// Learns the longest keep-alive interval the network tolerates.
+///
+/// While the session is idle the interval is stretched by half after each stable
+/// window. If an idle connection then dies, the middlebox timeout lies between the
+/// last surviving interval and the probe, so the interval settles just under the
+/// surviving one and probing stops.
+pub(crate) struct AdaptiveKeepAlive {
+    enabled: bool,
+    initial_ms: u64,
+    current_ms: u64,
+    last_good_ms: u64,
+    learned: bool,
+    stable_since: Option<u64>,
+}
+
+impl AdaptiveKeepAlive {
+    pub(crate) fn new(initial_ms: u64, enabled: bool) -> Self {
+        Self {
+            enabled: enabled && initial_ms > 0,
+            initial_ms,
+            current_ms: initial_ms,
+            last_good_ms: initial_ms,
+            learned: false,
+            stable_since: None,
+        }
+    }
+
+    pub(crate) fn interval_ms(&self) -> u64 {
+        self.current_ms
+    }
+
+    /// Interval adaptive keep-alive settled on; `None` while still probing.
+    pub(crate) fn learned_ms(&self) -> Option<u64> {
+        self.learned.then_some(self.current_ms)
+    }
+
+    /// Starts from an interval learned earlier on this network, without
+    /// probing again.
+    pub(crate) fn seed(&mut self, interval_ms: u64) {
+        if !self.enabled {
+            return;
+        }
+        let interval_ms = interval_ms.clamp(self.initial_ms, ADAPTIVE_KEEP_ALIVE_MAX_MS);
+        self.last_good_ms = interval_ms;
+        self.current_ms = interval_ms;
+        self.learned = true;
+    }
+
+    pub(crate) fn on_connected(&mut self, now: u64) {
+        self.stable_since = Some(now);
+    }
+
+    /// Returns a new interval to apply once the current one has proven stable.
+    pub(crate) fn maybe_grow(&mut self, now: u64, idle: bool) -> Option<u64> {
+        if !self.enabled || self.learned {
+            return None;
+        }
+        if !idle {
+            // Active traffic keeps the path warm, so it says nothing about timeouts.
+            self.stable_since = Some(now);
+            return None;
+        }
+        let since = *self.stable_since.get_or_insert(now);
+        if now.saturating_sub(since) < ADAPTIVE_KEEP_ALIVE_STABLE_US {
+            return None;
+        }
+        self.stable_since = Some(now);
+        self.last_good_ms = self.current_ms;
+        if self.current_ms >= ADAPTIVE_KEEP_ALIVE_MAX_MS {
+            self.settle(self.current_ms);
+            return None;
+        }
+        self.current_ms = (self.current_ms + self.current_ms / 2).min(ADAPTIVE_KEEP_ALIVE_MAX_MS);
+        Some(self.current_ms)
+    }
+
+    /// Records a dropped connection; only idle drops while probing are attributed
+    /// to the keep-alive interval.
+    pub(crate) fn on_connection_lost(&mut self, was_idle: bool) {
+        self.stable_since = None;
+        if !self.enabled || self.learned || !was_idle || self.current_ms == self.last_good_ms {
+            return;
+        }
+        let settled =
+            (self.last_good_ms * ADAPTIVE_KEEP_ALIVE_MARGIN_PERCENT / 100).max(self.initial_ms);
+        self.settle(settled);
+    }
+
+    fn settle(&mut self, interval_ms: u64) {
+        self.current_ms = interval_ms;
+        self.learned = true;
+        info!("Learned keep-alive interval: {}ms", interval_ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STABLE: u64 = ADAPTIVE_KEEP_ALIVE_STABLE_US;
+
+    #[test]
+    fn disabled_never_changes_interval() {
+        let mut keep_alive = AdaptiveKeepAlive::new(400, false);
+        keep_alive.on_connected(0);
+        assert_eq!(keep_alive.maybe_grow(STABLE * 4, true), None);
+        keep_alive.on_connection_lost(true);
+        assert_eq!(keep_alive.interval_ms(), 400);
+    }
+
+    #[test]
+    fn grows_only_after_idle_stable_window() {
+        let mut keep_alive = AdaptiveKeepAlive::new(1_000, true);
+        keep_alive.on_connected(0);
+        assert_eq!(keep_alive.maybe_grow(STABLE - 1, true), None);
+        assert_eq!(keep_alive.maybe_grow(STABLE + 1, false), None);
+        assert_eq!(keep_alive.maybe_grow(STABLE * 2, true), None);
+        assert_eq!(
+            keep_alive.maybe_grow(STABLE * 2 + STABLE + 1, true),
+            Some(1_500)
+        );
+    }
+
+    #[test]
+    fn idle_drop_while_probing_settles_under_last_good() {
+        let mut keep_alive = AdaptiveKeepAlive::new(1_000, true);
+        keep_alive.on_connected(0);
+        assert_eq!(keep_alive.maybe_grow(STABLE, true), Some(1_500));
+        assert_eq!(keep_alive.maybe_grow(STABLE * 2, true), Some(2_250));
+        keep_alive.on_connection_lost(true);
+        assert_eq!(keep_alive.interval_ms(), 1_350);
+        keep_alive.on_connected(0);
+        assert_eq!(keep_alive.maybe_grow(STABLE * 10, true), None);
+    }
+
+    #[test]
+    fn busy_drop_does_not_count() {
+        let mut keep_alive = AdaptiveKeepAlive::new(1_000, true);
+        keep_alive.on_connected(0);
+        assert_eq!(keep_alive.maybe_grow(STABLE, true), Some(1_500));
+        keep_alive.on_connection_lost(false);
+        assert_eq!(keep_alive.interval_ms(), 1_500);
+    }
+
+    #[test]
+    fn settles_at_the_probe_ceiling() {
+        let mut keep_alive = AdaptiveKeepAlive::new(20_000, true);
+        keep_alive.on_connected(0);
+        assert_eq!(
+            keep_alive.maybe_grow(STABLE, true),
+            Some(ADAPTIVE_KEEP_ALIVE_MAX_MS)
+        );
+        assert_eq!(keep_alive.maybe_grow(STABLE * 2, true), None);
+        assert_eq!(keep_alive.interval_ms(), ADAPTIVE_KEEP_ALIVE_MAX_MS);
+        assert_eq!(keep_alive.maybe_grow(STABLE * 3, true), None);
+    }
+}
@@ -0,0 +1,183 @@
+use super::keepalive::AdaptiveKeepAlive;
+use crate::dns::ResolverState;
+use crate::tuning::{NetworkTuning, ResolverTuning};
+use std::net::SocketAddr;
+
+/// Starts a connection from the tuning learned earlier on this network.
+///
+/// The first resolver is never started dead: the handshake goes through it,
+/// and a network where it stopped answering last time may well have changed.
+pub(super) fn seed_learned_tuning(
+    tuning: &NetworkTuning,
+    keep_alive: &mut AdaptiveKeepAlive,
+    resolvers: &mut [ResolverState],
+    now: u64,
+) {
+    if let Some(interval_ms) = tuning.keep_alive_interval_ms {
+        keep_alive.seed(interval_ms);
+    }
+    for (index, resolver) in resolvers.iter_mut().enumerate() {
+        let Some(learned) = tuning.resolver(&resolver_key(resolver)) else {
+            continue;
+        };
+        if let Some(qps) = learned.learned_qps {
+            resolver.query_rate.seed(qps, now);
+        }
+        if learned.dead && index > 0 {
+            resolver.health.seed_dead(now);
+        }
+    }
+}
+
+/// What this connection learned, on top of what was kept from earlier ones.
+pub(super) fn learned_tuning(
+    keep_alive: &AdaptiveKeepAlive,
+    resolvers: &[ResolverState],
+    previous: Option<&NetworkTuning>,
+) -> NetworkTuning {
+    let resolvers = resolvers
+        .iter()
+        .map(|resolver| {
+            let addr = resolver_key(resolver);
+            let kept_qps = previous
+                .and_then(|tuning| tuning.resolver(&addr))
+                .and_then(|kept| kept.learned_qps);
+            ResolverTuning {
+                learned_qps: resolver.query_rate.learned_qps().or(kept_qps),
+                dead: resolver.health.is_dead(),
+                resolver: addr,
+            }
+        })
+        .collect();
+    NetworkTuning {
+        keep_alive_interval_ms: keep_alive
+            .learned_ms()
+            .or_else(|| previous.and_then(|tuning| tuning.keep_alive_interval_ms)),
+        resolvers,
+    }
+}
+
+/// The resolver's address as kept, without the IPv4-mapped form the
+/// dual-stack socket uses.
+fn resolver_key(resolver: &ResolverState) -> String {
+    SocketAddr::new(resolver.addr.ip().to_canonical(), resolver.addr.port()).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{resolve_resolvers, HealthPolicy};
+    use crate::tuning::TuningStore;
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_ffi::{ResolverMode, ResolverSpec};
+
+    const STABLE: u64 = 30_000_000;
+
+    fn resolvers() -> Vec<ResolverState> {
+        let specs: Vec<ResolverSpec> = (1..=3)
+            .map(|host| ResolverSpec {
+                resolver: HostPort {
+                    host: format!("192.0.2.{}", host),
+                    port: 53,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+            })
+            .collect();
+        let mut resolvers = resolve_resolvers(&specs, 900, false, HealthPolicy::default()).unwrap();
+        for resolver in resolvers.iter_mut() {
+            resolver.query_rate.enable();
+        }
+        resolvers
+    }
+
+    /// One session's worth of learning: the keep-alive settles after an idle
+    /// drop, the second resolver pushes back on the query rate and the third
+    /// stops answering.
+    fn learn(keep_alive: &mut AdaptiveKeepAlive, resolvers: &mut [ResolverState]) {
+        keep_alive.on_connected(0);
+        assert_eq!(keep_alive.maybe_grow(STABLE, true), Some(1_500));
+        assert_eq!(keep_alive.maybe_grow(STABLE * 2, true), Some(2_250));
+        keep_alive.on_connection_lost(true);
+
+        for now in (0..40).map(|index| index * 25_000) {
+            assert!(resolvers[1].query_rate.admit(now));
+        }
+        assert!(resolvers[1]
+            .query_rate
+            .record_limit_signal(1_000_000)
+            .is_some());
+
+        let addr = resolvers[2].addr;
+        for now in [0, 1_000_000, 2_000_000, 6_000_000] {
+            resolvers[2].health.record_failure(addr, now);
+        }
+        assert!(resolvers[2].health.is_dead());
+    }
+
+    #[test]
+    fn an_imported_profile_seeds_the_next_connection() {
+        let mut keep_alive = AdaptiveKeepAlive::new(1_000, true);
+        let mut first = resolvers();
+        learn(&mut keep_alive, &mut first);
+        let learned_qps = first[1].query_rate.learned_qps().unwrap();
+
+        let mut store = TuningStore::new();
+        store.record("home", learned_tuning(&keep_alive, &first, None));
+        let json = store.export();
+
+        // A fresh start, as after the app was restarted.
+        let mut imported = TuningStore::new();
+        assert_eq!(imported.import(&json).expect("import"), 1);
+        let tuning = imported.get("home").expect("home network");
+        let mut keep_alive = AdaptiveKeepAlive::new(1_000, true);
+        let mut next = resolvers();
+        seed_learned_tuning(tuning, &mut keep_alive, &mut next, 10_000_000);
+
+        assert_eq!(keep_alive.interval_ms(), 1_350);
+        assert_eq!(keep_alive.learned_ms(), Some(1_350));
+        // Learned, so the seeded interval is not probed again.
+        keep_alive.on_connected(10_000_000);
+        assert_eq!(keep_alive.maybe_grow(10_000_000 + STABLE * 2, true), None);
+        assert_eq!(next[0].query_rate.learned_qps(), None);
+        assert_eq!(next[1].query_rate.learned_qps(), Some(learned_qps));
+        assert!(!next[1].health.is_dead());
+        assert!(next[2].health.is_dead());
+        assert!(!next[2].health.allow_poll(10_000_000));
+    }
+
+    #[test]
+    fn the_first_resolver_is_never_seeded_dead() {
+        let mut keep_alive = AdaptiveKeepAlive::new(1_000, true);
+        let mut first = resolvers();
+        let addr = first[0].addr;
+        for now in [0, 1_000_000, 2_000_000, 6_000_000] {
+            first[0].health.record_failure(addr, now);
+        }
+        let tuning = learned_tuning(&keep_alive, &first, None);
+        assert!(tuning.resolvers[0].dead);
+
+        let mut next = resolvers();
+        seed_learned_tuning(&tuning, &mut keep_alive, &mut next, 0);
+        assert!(!next[0].health.is_dead());
+    }
+
+    #[test]
+    fn what_a_connection_did_not_learn_is_kept_from_before() {
+        let keep_alive = AdaptiveKeepAlive::new(1_000, true);
+        let previous = NetworkTuning {
+            keep_alive_interval_ms: Some(4_000),
+            resolvers: vec![ResolverTuning {
+                resolver: "192.0.2.2:53".to_string(),
+                learned_qps: Some(6.0),
+                dead: true,
+            }],
+        };
+        let tuning = learned_tuning(&keep_alive, &resolvers(), Some(&previous));
+        assert_eq!(tuning.keep_alive_interval_ms, Some(4_000));
+        let second = tuning.resolver("192.0.2.2:53").unwrap();
+        assert_eq!(second.learned_qps, Some(6.0));
+        // Health is what this connection saw.
+        assert!(!second.dead);
+    }
+}
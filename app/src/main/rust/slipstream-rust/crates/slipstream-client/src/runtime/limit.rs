@@ -0,0 +1,142 @@
+use slipstream_ffi::PipelineMode;
+use std::collections::VecDeque;
+
+/// Caps on DNS queries: a lifetime total counted across reconnects and, in
+/// single-query mode, one unanswered query at a time.
+///
+/// Both data packets and polls are charged. Once the budget is spent no further
+/// query is sent and the runtime stops the client. A query stops counting as
+/// unanswered when any response with its ID arrives or the poll timeout passes.
+pub(crate) struct QueryBudget {
+    limit: Option<u64>,
+    spent: u64,
+    /// `None` pipelines freely and tracks nothing.
+    max_inflight: Option<usize>,
+    /// Unanswered queries by DNS ID and send time, oldest first.
+    inflight: VecDeque<(u16, u64)>,
+}
+
+impl QueryBudget {
+    pub(crate) fn new(limit: Option<u64>, pipeline_mode: PipelineMode) -> Self {
+        Self {
+            limit,
+            spent: 0,
+            max_inflight: match pipeline_mode {
+                PipelineMode::Pipelined => None,
+                PipelineMode::Single => Some(1),
+            },
+            inflight: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn spent(&self) -> u64 {
+        self.spent
+    }
+
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.limit.is_some_and(|limit| self.spent >= limit)
+    }
+
+    pub(crate) fn spend(&mut self, queries: u64) {
+        self.spent = self.spent.saturating_add(queries);
+    }
+
+    /// Whether another query may go out now.
+    pub(crate) fn can_send(&self) -> bool {
+        !self.is_exhausted()
+            && self
+                .max_inflight
+                .is_none_or(|max| self.inflight.len() < max)
+    }
+
+    /// Charges a sent query and, when capped, holds its slot until answered.
+    pub(crate) fn record_query(&mut self, id: u16, now: u64) {
+        self.spend(1);
+        if self.max_inflight.is_some() {
+            self.inflight.push_back((id, now));
+        }
+    }
+
+    pub(crate) fn record_response(&mut self, id: u16) {
+        if let Some(index) = self.inflight.iter().position(|(sent, _)| *sent == id) {
+            self.inflight.remove(index);
+        }
+    }
+
+    /// Frees the slots of queries unanswered for `deadline_us`.
+    pub(crate) fn expire(&mut self, now: u64, deadline_us: u64) {
+        self.inflight
+            .retain(|(_, sent_at)| now.saturating_sub(*sent_at) < deadline_us);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_the_limit() {
+        let mut budget = QueryBudget::new(Some(3), PipelineMode::Pipelined);
+        let mut sent = 0;
+        while !budget.is_exhausted() {
+            budget.spend(1);
+            sent += 1;
+        }
+        assert_eq!(sent, 3);
+        assert_eq!(budget.spent(), 3);
+    }
+
+    #[test]
+    fn unlimited_never_exhausts() {
+        let mut budget = QueryBudget::new(None, PipelineMode::Pipelined);
+        budget.spend(u64::MAX);
+        assert!(!budget.is_exhausted());
+    }
+
+    #[test]
+    fn single_mode_never_has_two_queries_outstanding() {
+        const DEADLINE_US: u64 = 1_000_000;
+        let mut budget = QueryBudget::new(None, PipelineMode::Single);
+        let mut next_id = 0u16;
+        let mut sent = 0;
+        // Each round offers a burst of queries, as polling and QUIC would, then
+        // either answers one, answers a stray ID or lets the deadline pass.
+        for round in 0..30u64 {
+            let now = round * 400_000;
+            budget.expire(now, DEADLINE_US);
+            for _ in 0..8 {
+                if !budget.can_send() {
+                    break;
+                }
+                budget.record_query(next_id, now);
+                next_id += 1;
+                sent += 1;
+                assert!(budget.inflight.len() <= 1);
+            }
+            match round % 3 {
+                0 => budget.record_response(next_id - 1),
+                1 => budget.record_response(next_id.wrapping_add(100)),
+                _ => {}
+            }
+            assert!(budget.inflight.len() <= 1);
+        }
+        assert!(sent > 10);
+        assert_eq!(budget.spent(), sent);
+
+        let mut pipelined = QueryBudget::new(None, PipelineMode::Pipelined);
+        for id in 0..8 {
+            assert!(pipelined.can_send());
+            pipelined.record_query(id, 0);
+        }
+        assert!(pipelined.inflight.is_empty());
+    }
+
+    #[test]
+    fn single_mode_still_honors_the_lifetime_limit() {
+        let mut budget = QueryBudget::new(Some(1), PipelineMode::Single);
+        budget.record_query(7, 0);
+        budget.record_response(7);
+        assert!(budget.is_exhausted());
+        assert!(!budget.can_send());
+    }
+}
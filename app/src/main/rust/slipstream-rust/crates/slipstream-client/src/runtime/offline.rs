@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+/// How often an offline client tries to connect anyway, for networks that come
+/// back without a change being reported.
+pub(crate) const OFFLINE_RECHECK: Duration = Duration::from_secs(30);
+
+/// The wait while the device has no network at all.
+///
+/// Reconnect attempts would only fail at once, so instead of backing off
+/// between them the client sends nothing until the platform reports a network
+/// change, or every `OFFLINE_RECHECK` to find out for itself.
+pub(crate) struct OfflineWait {
+    since_us: u64,
+    recheck_us: u64,
+}
+
+impl OfflineWait {
+    pub(crate) fn new(now_us: u64, recheck: Duration) -> Self {
+        Self {
+            since_us: now_us,
+            recheck_us: recheck.as_micros() as u64,
+        }
+    }
+
+    /// Whether to leave the wait and try connecting again.
+    pub(crate) fn should_resume(&self, now_us: u64, network_changed: bool) -> bool {
+        network_changed || now_us.saturating_sub(self.since_us) >= self.recheck_us
+    }
+
+    pub(crate) fn elapsed_ms(&self, now_us: u64) -> u64 {
+        now_us.saturating_sub(self.since_us) / 1_000
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{all_unreachable, RouteTracker};
+    use std::io;
+
+    const SECOND: u64 = 1_000_000;
+
+    #[test]
+    fn offline_wait_ends_on_a_network_change_or_the_recheck() {
+        // Airplane mode: every send finds no route.
+        let mut route = RouteTracker::default();
+        while !all_unreachable([&route]) {
+            route.record(&Err(io::Error::from(io::ErrorKind::NetworkUnreachable)));
+        }
+        let wait = OfflineWait::new(10 * SECOND, OFFLINE_RECHECK);
+        assert!(!wait.should_resume(11 * SECOND, false));
+        assert!(!wait.should_resume(39 * SECOND, false));
+        assert!(wait.should_resume(11 * SECOND, true));
+        assert!(wait.should_resume(40 * SECOND, false));
+        assert_eq!(wait.elapsed_ms(11 * SECOND), 1_000);
+
+        // Back online, the next attempt's sends go out again.
+        let mut route = RouteTracker::default();
+        route.record(&Ok(()));
+        assert!(!all_unreachable([&route]));
+    }
+}
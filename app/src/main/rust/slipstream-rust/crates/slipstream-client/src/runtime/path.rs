@@ -3,15 +3,21 @@ use crate::dns::{
     sockaddr_storage_to_socket_addr, ResolverState,
 };
 use crate::error::ClientError;
+use crate::redact::redact;
+use crate::session::{CongestionDetails, CongestionState};
 use crate::streams::{ClientState, PathEvent};
 use slipstream_core::normalize_dual_stack_addr;
 use slipstream_ffi::picoquic::{
     picoquic_cnx_t, picoquic_get_default_path_quality, picoquic_get_path_addr,
-    picoquic_get_path_quality, slipstream_get_path_id_from_unique, slipstream_set_path_ack_delay,
-    slipstream_set_path_mode, PICOQUIC_PACKET_LOOP_SEND_MAX,
+    picoquic_get_path_quality, picoquic_set_path_status, picoquic_set_stream_path_affinity,
+    slipstream_cc_observation_t, slipstream_get_path_id_from_unique,
+    slipstream_hold_path_retransmit_timer, slipstream_observe_path_cc,
+    slipstream_set_path_ack_delay, slipstream_set_path_mode, PICOQUIC_PACKET_LOOP_SEND_MAX,
+    PICOQUIC_PATH_STATUS_AVAILABLE, PICOQUIC_PATH_STATUS_STANDBY,
 };
-use slipstream_ffi::ResolverMode;
+use slipstream_ffi::{ResolverMode, ResolverRolePolicy};
 use std::net::SocketAddr;
+use tracing::debug;
 
 const AUTHORITATIVE_LOOP_MULTIPLIER: usize = 4;
 
@@ -30,6 +36,160 @@ pub(crate) fn apply_path_mode(
     Ok(())
 }
 
+/// Moves recursive paths to standby while an authoritative path can carry data,
+/// and dead resolvers' paths while any other path can, and back to available
+/// once none can, so data never loses its last path.
+pub(crate) fn apply_resolver_roles(
+    cnx: *mut picoquic_cnx_t,
+    resolvers: &mut [ResolverState],
+    policy: ResolverRolePolicy,
+) {
+    let standby_paths = standby_paths(resolvers, policy);
+    for (resolver, standby) in resolvers.iter_mut().zip(standby_paths) {
+        if !resolver.added || resolver.standby == standby {
+            continue;
+        }
+        let Some(unique_path_id) = resolver.unique_path_id else {
+            continue;
+        };
+        let status = if standby {
+            PICOQUIC_PATH_STATUS_STANDBY
+        } else {
+            PICOQUIC_PATH_STATUS_AVAILABLE
+        };
+        if unsafe { picoquic_set_path_status(cnx, unique_path_id, status) } == 0 {
+            resolver.standby = standby;
+            debug!(
+                "Resolver {} path {}",
+                redact(resolver.addr),
+                if standby { "on standby" } else { "available" }
+            );
+        }
+    }
+}
+
+/// Which resolvers' paths belong on standby; see [`apply_resolver_roles`].
+fn standby_paths(resolvers: &[ResolverState], policy: ResolverRolePolicy) -> Vec<bool> {
+    let live = |resolver: &ResolverState| resolver.added && !resolver.health.is_dead();
+    let has_authoritative = policy != ResolverRolePolicy::Mixed
+        && resolvers
+            .iter()
+            .any(|resolver| live(resolver) && resolver.mode == ResolverMode::Authoritative);
+    let by_role =
+        |resolver: &ResolverState| has_authoritative && resolver.mode == ResolverMode::Recursive;
+    let has_data_path = resolvers
+        .iter()
+        .any(|resolver| live(resolver) && !by_role(resolver));
+    resolvers
+        .iter()
+        .map(|resolver| by_role(resolver) || (resolver.health.is_dead() && has_data_path))
+        .collect()
+}
+
+/// Spreads stream data over resolver paths when stream striping is enabled.
+///
+/// Before each packet every stream's path affinity moves to the next usable path,
+/// so consecutive packets of one stream leave on different resolvers. The QUIC
+/// stream offsets put the data back in order on the far side regardless of how
+/// much each path's latency differs. A path that runs out of congestion window
+/// holds back the stream until its turn passes, so striping pays off only when
+/// the resolvers have similar capacity.
+pub(crate) struct StreamStriper {
+    enabled: bool,
+    cursor: usize,
+    pinned: bool,
+}
+
+impl StreamStriper {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            cursor: 0,
+            pinned: false,
+        }
+    }
+
+    pub(crate) fn before_packet(
+        &mut self,
+        cnx: *mut picoquic_cnx_t,
+        state: &ClientState,
+        resolvers: &[ResolverState],
+    ) {
+        if !self.enabled {
+            return;
+        }
+        let usable: Vec<u64> = resolvers
+            .iter()
+            .filter(|resolver| resolver.added && !resolver.standby && !resolver.health.is_dead())
+            .filter_map(|resolver| resolver.unique_path_id)
+            .collect();
+        let target = next_stripe_path(&usable, &mut self.cursor);
+        if target.is_none() && !self.pinned {
+            return;
+        }
+        // With fewer than two usable paths, hand scheduling back to picoquic.
+        let affinity = target.unwrap_or(u64::MAX);
+        for stream_id in state.stream_ids() {
+            unsafe {
+                picoquic_set_stream_path_affinity(cnx, stream_id, affinity);
+            }
+        }
+        self.pinned = target.is_some();
+    }
+}
+
+fn next_stripe_path(usable: &[u64], cursor: &mut usize) -> Option<u64> {
+    if usable.len() < 2 {
+        return None;
+    }
+    let unique_path_id = usable[*cursor % usable.len()];
+    *cursor = cursor.wrapping_add(1);
+    Some(unique_path_id)
+}
+
+/// Keeps the retransmission timer of each path whose resolver is in an RTT
+/// spike above the spike's RTT. Runs right before packets are prepared, as
+/// every acknowledgement resets the timer.
+pub(crate) fn hold_spiking_paths(
+    cnx: *mut picoquic_cnx_t,
+    resolvers: &mut [ResolverState],
+    now_us: u64,
+) {
+    for resolver in resolvers.iter_mut().filter(|resolver| resolver.added) {
+        let quality = fetch_path_quality(cnx, resolver);
+        resolver
+            .rtt_spike
+            .record_timer_losses(quality.timer_losses, now_us);
+        if let Some(floor_us) = resolver.rtt_spike.rto_floor_us(now_us) {
+            unsafe { slipstream_hold_path_retransmit_timer(cnx, resolver.path_id, floor_us) };
+        }
+    }
+}
+
+/// Hands the paths' losses and bytes in flight to the bulk window, with the
+/// round trips of the path carrying the most, which bulk data is filling.
+pub(crate) fn update_bulk_window(
+    cnx: *mut picoquic_cnx_t,
+    resolvers: &[ResolverState],
+    state: &mut ClientState,
+    now_us: u64,
+) {
+    if !state.congestion_isolation() {
+        return;
+    }
+    let (mut lost, mut bytes_in_transit) = (0u64, 0u64);
+    let mut busiest = slipstream_ffi::picoquic::picoquic_path_quality_t::default();
+    for resolver in resolvers.iter().filter(|resolver| resolver.added) {
+        let quality = fetch_path_quality(cnx, resolver);
+        lost = lost.saturating_add(quality.lost);
+        bytes_in_transit = bytes_in_transit.saturating_add(quality.bytes_in_transit);
+        if quality.bytes_in_transit >= busiest.bytes_in_transit {
+            busiest = quality;
+        }
+    }
+    state.update_bulk_window(lost, busiest.rtt, busiest.rtt_min, bytes_in_transit, now_us);
+}
+
 pub(crate) fn fetch_path_quality(
     cnx: *mut picoquic_cnx_t,
     resolver: &ResolverState,
@@ -47,6 +207,91 @@ pub(crate) fn fetch_path_quality(
     quality
 }
 
+const CC_ALGO_NUMBER_NEW_RENO: u64 = 1;
+const CC_ALGO_NUMBER_CUBIC: u64 = 2;
+const CC_ALGO_NUMBER_DCUBIC: u64 = 3;
+const CC_ALGO_NUMBER_FAST: u64 = 4;
+const CC_ALGO_NUMBER_BBR: u64 = 5;
+const CC_ALGO_NUMBER_PRAGUE: u64 = 6;
+const CC_ALGO_NUMBER_BBR1: u64 = 7;
+const CC_ALGO_NUMBER_SLIPSTREAM_CUSTOM: u64 = 12;
+
+// picoquic_bbr_alg_state_t and picoquic_cubic_alg_state_t, in order.
+const BBR_PHASES: [&str; 9] = [
+    "startup",
+    "drain",
+    "probe_bw_down",
+    "probe_bw_cruise",
+    "probe_bw_refill",
+    "probe_bw_up",
+    "probe_rtt",
+    "startup_long_rtt",
+    "startup_resume",
+];
+const CUBIC_PHASES: [&str; 3] = ["slow_start", "recovery", "congestion_avoidance"];
+
+/// Controller state of the resolver's path, combined with the shared values
+/// from `quality`. `None` when the path is gone.
+pub(crate) fn fetch_congestion_state(
+    cnx: *mut picoquic_cnx_t,
+    resolver: &ResolverState,
+    quality: &slipstream_ffi::picoquic::picoquic_path_quality_t,
+) -> Option<CongestionState> {
+    let unique_path_id = resolver.unique_path_id?;
+    let mut observation = slipstream_cc_observation_t::default();
+    let ret = unsafe { slipstream_observe_path_cc(cnx, unique_path_id, &mut observation) };
+    if ret != 0 {
+        return None;
+    }
+    let (algorithm, details) = describe_congestion(&observation);
+    Some(CongestionState {
+        algorithm: algorithm.to_string(),
+        cwnd_bytes: quality.cwin,
+        bytes_in_transit: quality.bytes_in_transit,
+        pacing_rate_bytes_per_sec: quality.pacing_rate,
+        min_rtt_us: quality.rtt_min,
+        details,
+    })
+}
+
+fn describe_congestion(
+    observation: &slipstream_cc_observation_t,
+) -> (&'static str, CongestionDetails) {
+    let phase = |phases: &[&str], state: u64| {
+        phases
+            .get(state as usize)
+            .map_or_else(|| state.to_string(), |phase| phase.to_string())
+    };
+    match observation.algorithm_number {
+        CC_ALGO_NUMBER_BBR => (
+            "bbr",
+            CongestionDetails::Bbr {
+                phase: phase(&BBR_PHASES, observation.cc_state),
+                bandwidth_bytes_per_sec: observation.cc_param,
+            },
+        ),
+        algorithm @ (CC_ALGO_NUMBER_CUBIC | CC_ALGO_NUMBER_DCUBIC) => (
+            if algorithm == CC_ALGO_NUMBER_CUBIC {
+                "cubic"
+            } else {
+                "dcubic"
+            },
+            CongestionDetails::Cubic {
+                phase: phase(&CUBIC_PHASES, observation.cc_state),
+                // W_max starts out as "infinite" and only becomes real on loss.
+                w_max_packets: (observation.cc_param < u64::from(u32::MAX))
+                    .then_some(observation.cc_param),
+            },
+        ),
+        CC_ALGO_NUMBER_NEW_RENO => ("newreno", CongestionDetails::Other),
+        CC_ALGO_NUMBER_FAST => ("fast", CongestionDetails::Other),
+        CC_ALGO_NUMBER_PRAGUE => ("prague", CongestionDetails::Other),
+        CC_ALGO_NUMBER_BBR1 => ("bbr1", CongestionDetails::Other),
+        CC_ALGO_NUMBER_SLIPSTREAM_CUSTOM => ("custom", CongestionDetails::Other),
+        _ => ("unknown", CongestionDetails::Other),
+    }
+}
+
 pub(crate) fn drain_path_events(
     cnx: *mut picoquic_cnx_t,
     resolvers: &mut [ResolverState],
@@ -127,3 +372,126 @@ fn find_resolver_by_unique_id_mut(
         .iter_mut()
         .find(|resolver| resolver.unique_path_id == Some(unique_path_id))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{resolve_resolvers, HealthPolicy};
+    use crate::test_config::resolver;
+    use slipstream_core::stream::{insert_stream_chunk, StreamRecvState};
+    use slipstream_ffi::ResolverSpec;
+
+    #[test]
+    fn dead_paths_go_on_standby_while_another_path_carries_data() {
+        let mut specs = vec![resolver("192.0.2.1"), resolver("192.0.2.2")];
+        specs.push(ResolverSpec {
+            mode: ResolverMode::Authoritative,
+            ..resolver("192.0.2.3")
+        });
+        let mut resolvers = resolve_resolvers(&specs, 900, false, HealthPolicy::default()).unwrap();
+        for resolver in resolvers.iter_mut() {
+            resolver.added = true;
+        }
+        let kill = |resolver: &mut ResolverState| {
+            let addr = resolver.addr;
+            for now in [0, 1_000_000, 2_000_000, 6_000_000] {
+                resolver.health.record_failure(addr, now);
+            }
+            assert!(resolver.health.is_dead());
+        };
+        let mixed = ResolverRolePolicy::Mixed;
+        let split = ResolverRolePolicy::Split;
+        assert_eq!(standby_paths(&resolvers, mixed), [false, false, false]);
+        assert_eq!(standby_paths(&resolvers, split), [true, true, false]);
+
+        kill(&mut resolvers[0]);
+        assert_eq!(standby_paths(&resolvers, mixed), [true, false, false]);
+        // With the authoritative path dead too, the live recursive path takes data.
+        kill(&mut resolvers[2]);
+        assert_eq!(standby_paths(&resolvers, split), [true, false, true]);
+        // With every path dead, none is held back.
+        kill(&mut resolvers[1]);
+        assert_eq!(standby_paths(&resolvers, mixed), [false, false, false]);
+        assert_eq!(standby_paths(&resolvers, split), [false, false, false]);
+    }
+
+    #[test]
+    fn congestion_observations_name_their_phase() {
+        let observe = |algorithm_number, cc_state, cc_param| {
+            describe_congestion(&slipstream_cc_observation_t {
+                algorithm_number,
+                cc_state,
+                cc_param,
+            })
+        };
+        assert_eq!(
+            observe(CC_ALGO_NUMBER_BBR, 3, 250_000),
+            (
+                "bbr",
+                CongestionDetails::Bbr {
+                    phase: "probe_bw_cruise".to_string(),
+                    bandwidth_bytes_per_sec: 250_000,
+                }
+            )
+        );
+        assert_eq!(
+            observe(CC_ALGO_NUMBER_DCUBIC, 0, u64::MAX),
+            (
+                "dcubic",
+                CongestionDetails::Cubic {
+                    phase: "slow_start".to_string(),
+                    w_max_packets: None,
+                }
+            )
+        );
+        assert_eq!(
+            observe(CC_ALGO_NUMBER_CUBIC, 2, 40).1,
+            CongestionDetails::Cubic {
+                phase: "congestion_avoidance".to_string(),
+                w_max_packets: Some(40),
+            }
+        );
+        assert_eq!(
+            observe(CC_ALGO_NUMBER_SLIPSTREAM_CUSTOM, 0, 0),
+            ("custom", CongestionDetails::Other)
+        );
+    }
+
+    #[test]
+    fn stripes_round_robin_over_two_or_more_paths() {
+        let mut cursor = 0;
+        assert_eq!(next_stripe_path(&[7], &mut cursor), None);
+        let picks: Vec<_> = (0..5)
+            .map(|_| next_stripe_path(&[1, 2, 3], &mut cursor).unwrap())
+            .collect();
+        assert_eq!(picks, vec![1, 2, 3, 1, 2]);
+    }
+
+    #[test]
+    fn striped_chunks_reassemble_despite_latency_differences() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(4_000).collect();
+        let chunk_len = 300;
+        let latencies_us = [20_000u64, 180_000, 65_000];
+        let paths = [0u64, 1, 2];
+        let mut cursor = 0;
+        let mut arrivals = Vec::new();
+        for (idx, chunk) in data.chunks(chunk_len).enumerate() {
+            let path = next_stripe_path(&paths, &mut cursor).unwrap() as usize;
+            let sent_at = idx as u64 * 1_000;
+            arrivals.push((sent_at + latencies_us[path], idx * chunk_len, chunk));
+        }
+        arrivals.sort_by_key(|(arrival, _, _)| *arrival);
+        assert_ne!(arrivals[1].1, chunk_len, "chunks must arrive out of order");
+
+        let mut state = StreamRecvState::new();
+        for (_, offset, chunk) in &arrivals {
+            insert_stream_chunk(&mut state.chunks, state.sent_offset, *offset as u64, chunk);
+        }
+        let mut reassembled = Vec::new();
+        for (offset, chunk) in &state.chunks {
+            assert_eq!(*offset as usize, reassembled.len(), "no gaps or overlaps");
+            reassembled.extend_from_slice(chunk);
+        }
+        assert_eq!(reassembled, data);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::dns::{ResolverHealthState, ResolverState};
+use slipstream_ffi::ResolverPreference;
+
+// A primary that flaps is reported at most this often; the switches in between
+// collapse into one event from the last reported resolver to the current one.
+const PRIMARY_CHANGE_MIN_INTERVAL_US: u64 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PrimaryChangeReason {
+    /// The previous primary was declared dead.
+    Failover,
+    /// A resolver earlier in the configured order became usable again.
+    Health,
+    /// Another resolver measured clearly more goodput.
+    Bandwidth,
+}
+
+impl PrimaryChangeReason {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            PrimaryChangeReason::Failover => "failover",
+            PrimaryChangeReason::Health => "health",
+            PrimaryChangeReason::Bandwidth => "bandwidth",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PrimaryChange {
+    pub(crate) old: usize,
+    pub(crate) new: usize,
+    pub(crate) reason: PrimaryChangeReason,
+}
+
+/// Goodput discounted by the query loss rate. Goodput is measured while data
+/// flows, so a lossy resolver can look fast in a burst yet stall on retransmits.
+fn loss_adjusted_goodput(goodput: Option<u64>, loss_rate: Option<f64>) -> Option<u64> {
+    let goodput = goodput?;
+    Some(match loss_rate {
+        Some(rate) => (goodput as f64 * (1.0 - rate.clamp(0.0, 1.0))) as u64,
+        None => goodput,
+    })
+}
+
+/// Follows which resolver is the active primary: the first one in configured
+/// order that is neither dead nor on standby, else the first one not dead.
+///
+/// With `ResolverPreference::Bandwidth` the measured resolver with the most
+/// goodput, net of query loss, wins instead. The primary only moves to one that delivers at least
+/// a quarter more, so resolvers of similar speed do not trade places on noise.
+pub(crate) struct PrimaryTracker {
+    preference: ResolverPreference,
+    reported: Option<usize>,
+    last_change_at: Option<u64>,
+}
+
+/// Health state, standby flag and goodput estimate of one resolver.
+type PrimaryInput = (ResolverHealthState, bool, Option<u64>);
+
+impl PrimaryTracker {
+    pub(crate) fn new(preference: ResolverPreference) -> Self {
+        Self {
+            preference,
+            reported: None,
+            last_change_at: None,
+        }
+    }
+
+    /// Index of the primary as last reported.
+    pub(crate) fn current(&self) -> Option<usize> {
+        self.reported
+    }
+
+    pub(crate) fn update(
+        &mut self,
+        resolvers: &[ResolverState],
+        now: u64,
+    ) -> Option<PrimaryChange> {
+        self.observe(
+            resolvers.iter().map(|resolver| {
+                (
+                    resolver.health.state(),
+                    resolver.standby,
+                    loss_adjusted_goodput(resolver.goodput.estimate(), resolver.loss.loss_rate()),
+                )
+            }),
+            now,
+        )
+    }
+
+    fn observe<I>(&mut self, resolvers: I, now: u64) -> Option<PrimaryChange>
+    where
+        I: Iterator<Item = PrimaryInput> + Clone,
+    {
+        let usable = |(state, _, _): &PrimaryInput| *state != ResolverHealthState::Dead;
+        let in_order = resolvers
+            .clone()
+            .position(|resolver| usable(&resolver) && !resolver.1)
+            .or_else(|| resolvers.clone().position(|resolver| usable(&resolver)))?;
+        let by_bandwidth = match self.preference {
+            ResolverPreference::Order => None,
+            ResolverPreference::Bandwidth => self.fastest(resolvers.clone()),
+        };
+        let candidate = by_bandwidth.unwrap_or(in_order);
+        let Some(old) = self.reported else {
+            // The first pick is where the session starts, not a switch.
+            self.reported = Some(candidate);
+            return None;
+        };
+        if candidate == old {
+            return None;
+        }
+        if self
+            .last_change_at
+            .is_some_and(|at| now.saturating_sub(at) < PRIMARY_CHANGE_MIN_INTERVAL_US)
+        {
+            return None;
+        }
+        let old_dead = resolvers
+            .clone()
+            .nth(old)
+            .is_none_or(|resolver| !usable(&resolver));
+        self.reported = Some(candidate);
+        self.last_change_at = Some(now);
+        Some(PrimaryChange {
+            old,
+            new: candidate,
+            reason: if old_dead {
+                PrimaryChangeReason::Failover
+            } else if by_bandwidth.is_some() {
+                PrimaryChangeReason::Bandwidth
+            } else {
+                PrimaryChangeReason::Health
+            },
+        })
+    }
+
+    /// The measured, usable resolver with the most goodput, or the current
+    /// primary while nothing beats it by the switching margin.
+    fn fastest(&self, resolvers: impl Iterator<Item = PrimaryInput>) -> Option<usize> {
+        let measured: Vec<(usize, u64)> = resolvers
+            .enumerate()
+            .filter(|(_, (state, standby, _))| *state != ResolverHealthState::Dead && !standby)
+            .filter_map(|(index, (_, _, goodput))| goodput.map(|goodput| (index, goodput)))
+            .collect();
+        // The earliest resolver wins a tie, like in configured order.
+        let (best, best_goodput) =
+            measured
+                .iter()
+                .copied()
+                .reduce(|best, next| if next.1 > best.1 { next } else { best })?;
+        let current = self
+            .reported
+            .and_then(|reported| measured.iter().find(|(index, _)| *index == reported));
+        if let Some(&(current, goodput)) = current {
+            if best_goodput.saturating_mul(4) < goodput.saturating_mul(5) {
+                return Some(current);
+            }
+        }
+        Some(best)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ResolverHealthState::{Dead, Healthy, Probation};
+
+    fn observe(
+        tracker: &mut PrimaryTracker,
+        states: &[(ResolverHealthState, bool)],
+        now: u64,
+    ) -> Option<PrimaryChange> {
+        tracker.observe(
+            states
+                .iter()
+                .map(|&(state, standby)| (state, standby, None)),
+            now,
+        )
+    }
+
+    #[test]
+    fn reports_failover_and_recovery_with_reasons() {
+        let mut tracker = PrimaryTracker::new(ResolverPreference::Order);
+        assert_eq!(
+            observe(&mut tracker, &[(Healthy, false), (Healthy, false)], 0),
+            None
+        );
+        assert_eq!(tracker.current(), Some(0));
+
+        let failover = observe(&mut tracker, &[(Dead, false), (Healthy, false)], 1);
+        assert_eq!(
+            failover,
+            Some(PrimaryChange {
+                old: 0,
+                new: 1,
+                reason: PrimaryChangeReason::Failover,
+            })
+        );
+
+        let recovered = observe(
+            &mut tracker,
+            &[(Probation, false), (Healthy, false)],
+            1 + PRIMARY_CHANGE_MIN_INTERVAL_US,
+        );
+        assert_eq!(
+            recovered.map(|change| (change.new, change.reason)),
+            Some((0, PrimaryChangeReason::Health))
+        );
+    }
+
+    #[test]
+    fn throttles_a_flapping_primary() {
+        let mut tracker = PrimaryTracker::new(ResolverPreference::Order);
+        observe(&mut tracker, &[(Healthy, false), (Healthy, false)], 0);
+        assert!(observe(&mut tracker, &[(Dead, false), (Healthy, false)], 1).is_some());
+        // Back and forth inside the window: nothing is reported.
+        assert!(observe(&mut tracker, &[(Healthy, false), (Healthy, false)], 2).is_none());
+        assert!(observe(&mut tracker, &[(Dead, false), (Healthy, false)], 3).is_none());
+        assert_eq!(tracker.current(), Some(1));
+        // Settled where it was last reported, so no event once the window ends.
+        let later = 1 + PRIMARY_CHANGE_MIN_INTERVAL_US;
+        assert!(observe(&mut tracker, &[(Dead, false), (Healthy, false)], later).is_none());
+    }
+
+    #[test]
+    fn bandwidth_preference_moves_to_clearly_faster_resolvers() {
+        fn measured(
+            tracker: &mut PrimaryTracker,
+            goodput: [Option<u64>; 3],
+            now: u64,
+        ) -> Option<PrimaryChange> {
+            tracker.observe(
+                goodput.into_iter().map(|goodput| (Healthy, false, goodput)),
+                now,
+            )
+        }
+        let interval = PRIMARY_CHANGE_MIN_INTERVAL_US;
+
+        let mut tracker = PrimaryTracker::new(ResolverPreference::Bandwidth);
+        assert!(measured(&mut tracker, [None, None, None], 0).is_none());
+        assert_eq!(tracker.current(), Some(0));
+        let faster = measured(&mut tracker, [Some(100_000), Some(300_000), None], interval);
+        assert_eq!(
+            faster.map(|change| (change.new, change.reason)),
+            Some((1, PrimaryChangeReason::Bandwidth))
+        );
+        // Within the margin the primary stays put.
+        let close = [Some(360_000), Some(300_000), None];
+        assert!(measured(&mut tracker, close, 2 * interval).is_none());
+        let fastest = measured(
+            &mut tracker,
+            [Some(100_000), Some(300_000), Some(500_000)],
+            3 * interval,
+        );
+        assert_eq!(fastest.map(|change| change.new), Some(2));
+
+        // Configured order ignores the measurements.
+        let mut tracker = PrimaryTracker::new(ResolverPreference::Order);
+        measured(&mut tracker, [None, None, None], 0);
+        let ignored = [Some(100_000), Some(300_000), Some(500_000)];
+        assert!(measured(&mut tracker, ignored, interval).is_none());
+    }
+
+    #[test]
+    fn lossy_resolvers_score_lower() {
+        assert_eq!(
+            loss_adjusted_goodput(Some(300_000), Some(0.5)),
+            Some(150_000)
+        );
+        assert_eq!(loss_adjusted_goodput(Some(300_000), None), Some(300_000));
+        assert_eq!(loss_adjusted_goodput(None, Some(0.1)), None);
+    }
+
+    #[test]
+    fn skips_standby_paths_unless_nothing_else_is_up() {
+        let mut tracker = PrimaryTracker::new(ResolverPreference::Order);
+        observe(&mut tracker, &[(Healthy, true), (Healthy, false)], 0);
+        assert_eq!(tracker.current(), Some(1));
+        let change = observe(&mut tracker, &[(Healthy, true), (Dead, false)], 1);
+        assert_eq!(change.map(|change| change.new), Some(0));
+        // With every resolver dead the last primary stays reported.
+        assert!(observe(&mut tracker, &[(Dead, true), (Dead, false)], u64::MAX).is_none());
+        assert_eq!(tracker.current(), Some(0));
+    }
+}
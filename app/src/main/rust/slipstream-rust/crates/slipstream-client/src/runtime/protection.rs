@@ -0,0 +1,59 @@
+// How often the DNS socket's VPN protection is re-checked.
+const PROTECTION_CHECK_INTERVAL_US: u64 = 10_000_000;
+// Refused re-checks in a row before protection counts as lost.
+const PROTECTION_FAILURE_LIMIT: u32 = 3;
+
+/// Watches for the OS tearing down the `VpnService` under a running client.
+///
+/// `VpnService.protect()` is idempotent on a protected socket, so asking again
+/// is a cheap liveness check. A single refusal can race a VPN restart; a run of
+/// them means the socket can no longer be kept out of the tunnel.
+pub(crate) struct ProtectionMonitor {
+    failures: u32,
+    last_check_us: u64,
+}
+
+impl ProtectionMonitor {
+    pub(crate) fn new(now_us: u64) -> Self {
+        Self {
+            failures: 0,
+            last_check_us: now_us,
+        }
+    }
+
+    pub(crate) fn is_due(&self, now_us: u64) -> bool {
+        now_us.saturating_sub(self.last_check_us) >= PROTECTION_CHECK_INTERVAL_US
+    }
+
+    /// Records a re-check and returns true once protection counts as lost.
+    pub(crate) fn record(&mut self, now_us: u64, protected: bool) -> bool {
+        self.last_check_us = now_us;
+        if protected {
+            self.failures = 0;
+        } else {
+            self.failures = self.failures.saturating_add(1);
+        }
+        self.failures >= PROTECTION_FAILURE_LIMIT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_of_refused_protection_counts_as_lost() {
+        let mut monitor = ProtectionMonitor::new(0);
+        assert!(!monitor.is_due(PROTECTION_CHECK_INTERVAL_US - 1));
+        assert!(monitor.is_due(PROTECTION_CHECK_INTERVAL_US));
+
+        // A refusal between successes is a VPN restart race, not a loss.
+        assert!(!monitor.record(1, false));
+        assert!(!monitor.record(2, false));
+        assert!(!monitor.record(3, true));
+        assert!(!monitor.record(4, false));
+        assert!(!monitor.record(5, false));
+        assert!(monitor.record(6, false));
+        assert!(!monitor.is_due(6 + PROTECTION_CHECK_INTERVAL_US - 1));
+    }
+}
@@ -0,0 +1,183 @@
+use super::schedule::window_contains;
+use slipstream_ffi::ActiveWindow;
+
+// How often the window is checked against the local time.
+const QUIET_CHECK_INTERVAL_US: u64 = 5_000_000;
+// No stream may open or move data for this long before quiet mode applies.
+const QUIET_AFTER_IDLE_US: u64 = 10_000_000;
+// Keep-alive interval while quiet; stays below common NAT idle timeouts.
+const QUIET_KEEP_ALIVE_MS: u64 = 25_000;
+// Gap between idle polls while quiet, at the least.
+const QUIET_IDLE_POLL_MAX_MS: u64 = 30_000;
+
+/// Daily windows in which an idle tunnel keeps background activity low.
+///
+/// Unlike active windows the tunnel stays connected: once a quiet window has
+/// begun and no stream has opened or moved data for a while, the keep-alive
+/// interval goes up to its longest, idle polling spaces polls out up to
+/// `QUIET_IDLE_POLL_MAX_MS` and the poll pool is no longer topped up. Any
+/// stream activity, or the end of the window, restores the usual settings at
+/// once.
+pub(crate) struct QuietHours {
+    windows: Vec<ActiveWindow>,
+    in_window: bool,
+    last_check_us: Option<u64>,
+    active_streams: usize,
+    stream_bytes: u64,
+    last_activity_us: Option<u64>,
+    quiet: bool,
+}
+
+impl QuietHours {
+    pub(crate) fn new(windows: &[ActiveWindow]) -> Self {
+        Self {
+            windows: windows.to_vec(),
+            in_window: false,
+            last_check_us: None,
+            active_streams: 0,
+            stream_bytes: 0,
+            last_activity_us: None,
+            quiet: false,
+        }
+    }
+
+    /// Feeds the open stream count and the bytes streams moved so far;
+    /// `minute` gives the local minute of the day when the window is due for a
+    /// check. Returns whether quiet mode now applies when that changed.
+    pub(crate) fn update(
+        &mut self,
+        now_us: u64,
+        minute: impl FnOnce() -> u16,
+        active_streams: usize,
+        stream_bytes: u64,
+    ) -> Option<bool> {
+        if self.windows.is_empty() {
+            return None;
+        }
+        if self
+            .last_check_us
+            .is_none_or(|last| now_us.saturating_sub(last) >= QUIET_CHECK_INTERVAL_US)
+        {
+            let minute = minute();
+            self.in_window = self
+                .windows
+                .iter()
+                .any(|window| window_contains(window, minute));
+            self.last_check_us = Some(now_us);
+        }
+        let opened = active_streams > self.active_streams;
+        let moved = stream_bytes != self.stream_bytes;
+        self.active_streams = active_streams;
+        self.stream_bytes = stream_bytes;
+        if opened || moved || self.last_activity_us.is_none() {
+            self.last_activity_us = Some(now_us);
+        }
+        let idle = self
+            .last_activity_us
+            .is_some_and(|since| now_us.saturating_sub(since) >= QUIET_AFTER_IDLE_US);
+        let quiet = self.in_window && idle;
+        if quiet == self.quiet {
+            return None;
+        }
+        self.quiet = quiet;
+        Some(quiet)
+    }
+
+    /// Quiet mode applies; the poll pool is not topped up.
+    pub(crate) fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Keep-alive interval to use; 0 keeps keep-alives off.
+    pub(crate) fn keep_alive_ms(&self, interval_ms: u64) -> u64 {
+        if self.quiet && interval_ms > 0 {
+            interval_ms.max(QUIET_KEEP_ALIVE_MS)
+        } else {
+            interval_ms
+        }
+    }
+
+    /// Idle polling cap to use; quiet mode turns idle polling on if it is off.
+    pub(crate) fn idle_poll_max_ms(&self, max_ms: u64) -> u64 {
+        if self.quiet {
+            max_ms.max(QUIET_IDLE_POLL_MAX_MS)
+        } else {
+            max_ms
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::idle::IdlePolling;
+    use crate::runtime::parse_active_window;
+
+    const MS: u64 = 1_000;
+
+    /// Polls sent over `duration_ms` of an idle session at `minute`, with
+    /// idle polling off, answers taking 100ms and each answer re-arming a poll.
+    fn idle_session(quiet: &mut QuietHours, minute: u16, duration_ms: u64) -> u64 {
+        let mut idle_polls = IdlePolling::new(500, quiet.idle_poll_max_ms(0));
+        let mut polls = 0;
+        for now in (0..duration_ms * MS).step_by(100 * MS as usize) {
+            if quiet.update(now, || minute, 0, 0).is_some() {
+                idle_polls = IdlePolling::new(500, quiet.idle_poll_max_ms(0));
+            }
+            idle_polls.update(now, 0, 0);
+            if idle_polls.allowance(now) > 0 {
+                polls += 1;
+                idle_polls.on_polls_sent(now);
+            }
+        }
+        polls
+    }
+
+    #[test]
+    fn an_idle_tunnel_is_quieter_inside_the_window() {
+        let windows = [parse_active_window("23:00-07:00").unwrap()];
+        let mut outside = QuietHours::new(&windows);
+        let normal = idle_session(&mut outside, 22 * 60, 600_000);
+        assert!(!outside.is_quiet());
+        assert_eq!(outside.keep_alive_ms(5_000), 5_000);
+        assert_eq!(normal, 6_000);
+
+        let mut inside = QuietHours::new(&windows);
+        let quiet = idle_session(&mut inside, 3 * 60, 600_000);
+        assert!(inside.is_quiet());
+        assert_eq!(inside.keep_alive_ms(5_000), QUIET_KEEP_ALIVE_MS);
+        assert_eq!(inside.keep_alive_ms(0), 0);
+        assert_eq!(inside.idle_poll_max_ms(60_000), 60_000);
+        // Full rate until the tunnel counts as idle, then a poll every 30s.
+        assert!(quiet < normal / 20, "{} polls while quiet", quiet);
+    }
+
+    #[test]
+    fn traffic_or_the_window_ending_restores_normal_activity() {
+        let windows = [parse_active_window("23:00-07:00").unwrap()];
+        let mut quiet = QuietHours::new(&windows);
+        let night = || 3 * 60;
+        assert_eq!(quiet.update(0, night, 0, 0), None);
+        assert_eq!(quiet.update(QUIET_AFTER_IDLE_US - 1, night, 0, 0), None);
+        assert_eq!(quiet.update(QUIET_AFTER_IDLE_US, night, 0, 0), Some(true));
+
+        // A new stream wakes it on the same update.
+        let now = QUIET_AFTER_IDLE_US + MS;
+        assert_eq!(quiet.update(now, night, 1, 0), Some(false));
+        assert_eq!(quiet.update(now + MS, night, 1, 0), None);
+        // Quiet again only after the stream stayed silent for a while.
+        let later = now + QUIET_AFTER_IDLE_US;
+        assert_eq!(quiet.update(later, night, 1, 0), Some(true));
+        assert_eq!(quiet.update(later + MS, night, 1, 512), Some(false));
+
+        // The window ends while idle.
+        let idle = later + MS + QUIET_AFTER_IDLE_US;
+        assert_eq!(quiet.update(idle, night, 1, 512), Some(true));
+        let morning = idle + QUIET_CHECK_INTERVAL_US;
+        assert_eq!(quiet.update(morning, || 7 * 60, 1, 512), Some(false));
+
+        let mut unset = QuietHours::new(&[]);
+        assert_eq!(unset.update(QUIET_AFTER_IDLE_US * 10, night, 0, 0), None);
+        assert!(!unset.is_quiet());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::dns::ResolverState;
+use std::net::SocketAddr;
+
+/// Sends handshake queries to several resolvers at once.
+///
+/// Until the handshake completes, one lost query stalls the connection for a
+/// full retransmission timeout. With `copies` above 0, each query carrying a
+/// long-header QUIC packet (Initial or Handshake) also goes to up to that many
+/// other resolvers that are not dead, in configured order. Whichever copy the
+/// server answers first moves the handshake on; QUIC drops the duplicate
+/// packets by packet number on both sides. 1-RTT packets, including key
+/// updates, are never copied, so bulk data costs nothing extra.
+pub(crate) struct HandshakeRedundancy {
+    copies: usize,
+}
+
+impl HandshakeRedundancy {
+    pub(crate) fn new(copies: usize) -> Self {
+        Self { copies }
+    }
+
+    /// Indexes of the resolvers that also get the query carrying `packet`,
+    /// which is sent to `dest`.
+    pub(crate) fn targets(
+        &self,
+        packet: &[u8],
+        dest: SocketAddr,
+        resolvers: &[ResolverState],
+    ) -> Vec<usize> {
+        if self.copies == 0 || !is_long_header(packet) {
+            return Vec::new();
+        }
+        resolvers
+            .iter()
+            .enumerate()
+            .filter(|(_, resolver)| resolver.addr != dest && !resolver.health.is_dead())
+            .map(|(index, _)| index)
+            .take(self.copies)
+            .collect()
+    }
+}
+
+/// Long headers carry the handshake; a coalesced datagram starts with one too.
+fn is_long_header(packet: &[u8]) -> bool {
+    packet.first().is_some_and(|first| first & 0x80 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{resolve_resolvers, HealthPolicy};
+    use slipstream_core::{AddressFamily, HostPort};
+    use slipstream_ffi::{ResolverMode, ResolverSpec};
+
+    const INITIAL: &[u8] = &[0xc3, 0x00, 0x00, 0x00, 0x01];
+    const SHORT: &[u8] = &[0x43, 0x12, 0x34];
+
+    fn resolvers(count: u8) -> Vec<ResolverState> {
+        let specs: Vec<ResolverSpec> = (1..=count)
+            .map(|host| ResolverSpec {
+                resolver: HostPort {
+                    host: format!("192.0.2.{}", host),
+                    port: 53,
+                    family: AddressFamily::V4,
+                },
+                mode: ResolverMode::Recursive,
+            })
+            .collect();
+        resolve_resolvers(&specs, 900, false, HealthPolicy::default()).unwrap()
+    }
+
+    /// Whether a handshake query reaches the server in the first round when the
+    /// resolvers at `dropping` lose it, instead of after a retransmission.
+    fn first_round_completes(
+        redundancy: &HandshakeRedundancy,
+        resolvers: &[ResolverState],
+        dropping: &[usize],
+    ) -> bool {
+        let dest = resolvers[0].addr;
+        std::iter::once(0)
+            .chain(redundancy.targets(INITIAL, dest, resolvers))
+            .any(|index| !dropping.contains(&index))
+    }
+
+    #[test]
+    fn handshake_completes_through_resolvers_that_did_not_drop_it() {
+        let mut resolvers = resolvers(4);
+        // The primary and the next resolver drop the query.
+        let dropping = [0, 1];
+        assert!(!first_round_completes(
+            &HandshakeRedundancy::new(0),
+            &resolvers,
+            &dropping
+        ));
+        assert!(!first_round_completes(
+            &HandshakeRedundancy::new(1),
+            &resolvers,
+            &dropping
+        ));
+        assert!(first_round_completes(
+            &HandshakeRedundancy::new(2),
+            &resolvers,
+            &dropping
+        ));
+
+        let redundancy = HandshakeRedundancy::new(2);
+        assert_eq!(
+            redundancy.targets(INITIAL, resolvers[0].addr, &resolvers),
+            [1, 2]
+        );
+        assert_eq!(
+            redundancy.targets(INITIAL, resolvers[2].addr, &resolvers),
+            [0, 1]
+        );
+        // Data after the handshake is not copied.
+        assert!(redundancy
+            .targets(SHORT, resolvers[0].addr, &resolvers)
+            .is_empty());
+        assert_eq!(
+            HandshakeRedundancy::new(8).targets(INITIAL, resolvers[0].addr, &resolvers),
+            [1, 2, 3]
+        );
+
+        // A dead resolver gets no copies.
+        let addr = resolvers[1].addr;
+        for attempt in 0..10 {
+            resolvers[1]
+                .health
+                .record_failure(addr, attempt * 60_000_000);
+        }
+        assert!(resolvers[1].health.is_dead());
+        assert_eq!(
+            redundancy.targets(INITIAL, resolvers[0].addr, &resolvers),
+            [2, 3]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+/// What the idle refresh wants the runtime to do this turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RefreshAction {
+    Wait,
+    /// Send one poll through the primary resolver.
+    Probe,
+    /// The probe went unanswered; the session is dead.
+    Failed,
+}
+
+/// Checks a quiet session with a single poll instead of keeping it busy.
+///
+/// Keep-alives hold the QUIC session open from picoquic's side but say nothing
+/// about whether answers still come back. Once no tunnel response has arrived
+/// for `interval_us`, one poll goes out; any tunnel response clears it, and none
+/// within `timeout_us` means the resolver path or the session is gone, so the
+/// runtime reconnects instead of waiting for the QUIC idle timeout. An interval
+/// of 0 disables it.
+pub(crate) struct IdleRefresh {
+    interval_us: u64,
+    timeout_us: u64,
+    heard: u64,
+    last_heard_at_us: Option<u64>,
+    probe_sent_at_us: Option<u64>,
+}
+
+impl IdleRefresh {
+    pub(crate) fn new(interval_ms: u64, timeout_ms: u64) -> Self {
+        Self {
+            interval_us: interval_ms.saturating_mul(1_000),
+            timeout_us: timeout_ms.max(1).saturating_mul(1_000),
+            heard: 0,
+            last_heard_at_us: None,
+            probe_sent_at_us: None,
+        }
+    }
+
+    /// Starts the quiet clock; `heard` counts tunnel responses so far.
+    pub(crate) fn on_connected(&mut self, now_us: u64, heard: u64) {
+        self.heard = heard;
+        self.last_heard_at_us = Some(now_us);
+        self.probe_sent_at_us = None;
+    }
+
+    pub(crate) fn update(&mut self, now_us: u64, heard: u64) -> RefreshAction {
+        if self.interval_us == 0 {
+            return RefreshAction::Wait;
+        }
+        let Some(last_heard_at) = self.last_heard_at_us else {
+            return RefreshAction::Wait;
+        };
+        if heard != self.heard {
+            self.on_connected(now_us, heard);
+            return RefreshAction::Wait;
+        }
+        match self.probe_sent_at_us {
+            Some(sent_at) if now_us.saturating_sub(sent_at) >= self.timeout_us => {
+                RefreshAction::Failed
+            }
+            Some(_) => RefreshAction::Wait,
+            None if now_us.saturating_sub(last_heard_at) >= self.interval_us => {
+                self.probe_sent_at_us = Some(now_us);
+                RefreshAction::Probe
+            }
+            None => RefreshAction::Wait,
+        }
+    }
+
+    /// Time until the next probe or probe deadline, if the refresh is running.
+    pub(crate) fn wait_us(&self, now_us: u64) -> Option<u64> {
+        if self.interval_us == 0 {
+            return None;
+        }
+        let due_at = match self.probe_sent_at_us {
+            Some(sent_at) => sent_at.saturating_add(self.timeout_us),
+            None => self.last_heard_at_us?.saturating_add(self.interval_us),
+        };
+        Some(due_at.saturating_sub(now_us))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000;
+
+    #[test]
+    fn answered_refresh_keeps_the_session() {
+        let mut refresh = IdleRefresh::new(20_000, 5_000);
+        assert_eq!(refresh.update(100 * SECOND, 0), RefreshAction::Wait);
+        refresh.on_connected(0, 3);
+        assert_eq!(refresh.wait_us(0), Some(20 * SECOND));
+        // Traffic pushes the refresh back.
+        assert_eq!(refresh.update(15 * SECOND, 4), RefreshAction::Wait);
+        assert_eq!(refresh.update(30 * SECOND, 4), RefreshAction::Wait);
+        assert_eq!(refresh.update(35 * SECOND, 4), RefreshAction::Probe);
+        assert_eq!(refresh.update(36 * SECOND, 4), RefreshAction::Wait);
+        assert_eq!(refresh.wait_us(36 * SECOND), Some(4 * SECOND));
+        assert_eq!(refresh.update(37 * SECOND, 5), RefreshAction::Wait);
+        assert_eq!(refresh.update(56 * SECOND, 5), RefreshAction::Wait);
+        assert_eq!(refresh.update(57 * SECOND, 5), RefreshAction::Probe);
+    }
+
+    #[test]
+    fn unanswered_refresh_fails_the_session() {
+        let mut refresh = IdleRefresh::new(20_000, 5_000);
+        refresh.on_connected(0, 0);
+        assert_eq!(refresh.update(20 * SECOND, 0), RefreshAction::Probe);
+        assert_eq!(refresh.update(24 * SECOND, 0), RefreshAction::Wait);
+        assert_eq!(refresh.update(25 * SECOND, 0), RefreshAction::Failed);
+
+        let mut disabled = IdleRefresh::new(0, 5_000);
+        disabled.on_connected(0, 0);
+        assert_eq!(disabled.update(1_000 * SECOND, 0), RefreshAction::Wait);
+        assert_eq!(disabled.wait_us(0), None);
+    }
+}
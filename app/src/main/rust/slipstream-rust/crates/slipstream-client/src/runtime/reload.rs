@@ -0,0 +1,409 @@
+use crate::error::ClientError;
+use crate::validate::{validate_config, IssueSeverity};
+use serde::Serialize;
+use slipstream_ffi::{ClientConfig, ResolverRolePolicy, ResolverSpec};
+use std::sync::Mutex;
+
+/// Fields [`reload_config`] applies to a running client without a reconnect.
+/// `resolvers` only counts when resolvers were appended to the list.
+pub const HOT_RELOAD_FIELDS: &[&str] = &[
+    "resolvers",
+    "resolver_role_policy",
+    "max_parallel_probes",
+    "poll_timeout_ms",
+    "idle_poll_min_ms",
+    "idle_poll_max_ms",
+    "idle_refresh_ms",
+    "reconnect_min_ms",
+    "reconnect_max_ms",
+    "reconnect_jitter",
+    "block_backoff_ms",
+    "event_grace_ms",
+];
+
+/// What a reload changed, by `ClientConfig` field name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ReloadReport {
+    /// Changed fields now in effect.
+    pub applied: Vec<&'static str>,
+    /// Changed fields that keep their old value until the client is restarted.
+    pub restart_required: Vec<&'static str>,
+}
+
+/// The settings the runtime reads as it goes, which a reload can replace.
+#[derive(Debug, Clone)]
+pub(crate) struct LiveSettings {
+    pub(crate) resolvers: Vec<ResolverSpec>,
+    pub(crate) resolver_role_policy: ResolverRolePolicy,
+    pub(crate) max_parallel_probes: Option<usize>,
+    pub(crate) poll_timeout_ms: u64,
+    pub(crate) idle_poll_min_ms: u64,
+    pub(crate) idle_poll_max_ms: u64,
+    pub(crate) idle_refresh_ms: u64,
+    pub(crate) reconnect_min_ms: u64,
+    pub(crate) reconnect_max_ms: u64,
+    pub(crate) reconnect_jitter: bool,
+    pub(crate) block_backoff_ms: u64,
+    pub(crate) event_grace_ms: u64,
+}
+
+impl LiveSettings {
+    pub(crate) fn new(config: &ClientConfig<'_>) -> Self {
+        Self {
+            resolvers: config.resolvers.to_vec(),
+            resolver_role_policy: config.resolver_role_policy,
+            max_parallel_probes: config.max_parallel_probes,
+            poll_timeout_ms: config.poll_timeout_ms,
+            idle_poll_min_ms: config.idle_poll_min_ms,
+            idle_poll_max_ms: config.idle_poll_max_ms,
+            idle_refresh_ms: config.idle_refresh_ms,
+            reconnect_min_ms: config.reconnect_min_ms,
+            reconnect_max_ms: config.reconnect_max_ms,
+            reconnect_jitter: config.reconnect_jitter,
+            block_backoff_ms: config.block_backoff_ms,
+            event_grace_ms: config.event_grace_ms,
+        }
+    }
+
+    fn take_field(&mut self, from: &Self, field: &str) {
+        match field {
+            "resolvers" => self.resolvers = from.resolvers.clone(),
+            "resolver_role_policy" => self.resolver_role_policy = from.resolver_role_policy,
+            "max_parallel_probes" => self.max_parallel_probes = from.max_parallel_probes,
+            "poll_timeout_ms" => self.poll_timeout_ms = from.poll_timeout_ms,
+            "idle_poll_min_ms" => self.idle_poll_min_ms = from.idle_poll_min_ms,
+            "idle_poll_max_ms" => self.idle_poll_max_ms = from.idle_poll_max_ms,
+            "idle_refresh_ms" => self.idle_refresh_ms = from.idle_refresh_ms,
+            "reconnect_min_ms" => self.reconnect_min_ms = from.reconnect_min_ms,
+            "reconnect_max_ms" => self.reconnect_max_ms = from.reconnect_max_ms,
+            "reconnect_jitter" => self.reconnect_jitter = from.reconnect_jitter,
+            "block_backoff_ms" => self.block_backoff_ms = from.block_backoff_ms,
+            "event_grace_ms" => self.event_grace_ms = from.event_grace_ms,
+            _ => {}
+        }
+    }
+
+    /// Resolvers in `self` past the end of `before`'s list.
+    pub(crate) fn added_resolvers(&self, before: &Self) -> &[ResolverSpec] {
+        self.resolvers
+            .get(before.resolvers.len()..)
+            .unwrap_or_default()
+    }
+}
+
+struct RunningConfig {
+    /// Every field of the config in effect, as `Debug` text.
+    fields: Vec<(&'static str, String)>,
+    live: LiveSettings,
+}
+
+static RUNNING: Mutex<Option<RunningConfig>> = Mutex::new(None);
+static PENDING: Mutex<Option<LiveSettings>> = Mutex::new(None);
+
+/// Makes the running client the target of [`reload_config`] until dropped.
+pub(crate) struct ReloadTarget(());
+
+impl ReloadTarget {
+    pub(crate) fn register(config: &ClientConfig<'_>, live: &LiveSettings) -> Self {
+        if let Ok(mut running) = RUNNING.lock() {
+            *running = Some(RunningConfig {
+                fields: config_fields(config),
+                live: live.clone(),
+            });
+        }
+        if let Ok(mut pending) = PENDING.lock() {
+            *pending = None;
+        }
+        Self(())
+    }
+}
+
+impl Drop for ReloadTarget {
+    fn drop(&mut self) {
+        if let Ok(mut running) = RUNNING.lock() {
+            *running = None;
+        }
+        if let Ok(mut pending) = PENDING.lock() {
+            *pending = None;
+        }
+    }
+}
+
+/// Settings from a reload the runtime has not picked up yet.
+pub(crate) fn take_reload() -> Option<LiveSettings> {
+    PENDING.lock().ok()?.take()
+}
+
+/// Applies `config` to the running client where that is safe, without dropping
+/// its connection or streams; the report says which changes took effect and
+/// which wait for a restart. See [`HOT_RELOAD_FIELDS`].
+///
+/// Fails when no client is running or `config` would not pass
+/// [`validate_config`](crate::validate_config); then nothing changes.
+// Called by the Android bridge and library callers; the CLI cannot reload.
+#[allow(dead_code)]
+pub fn reload_config(config: &ClientConfig<'_>) -> Result<ReloadReport, ClientError> {
+    if let Some(issue) = validate_config(config)
+        .into_iter()
+        .find(|issue| issue.severity == IssueSeverity::Error)
+    {
+        return Err(ClientError::new(format!(
+            "Invalid {}: {}",
+            issue.field, issue.message
+        )));
+    }
+    let mut running = RUNNING
+        .lock()
+        .map_err(|_| ClientError::new("Reload state is poisoned"))?;
+    let Some(running) = running.as_mut() else {
+        return Err(ClientError::new("No client is running"));
+    };
+    Ok(apply_reload(
+        running,
+        &LiveSettings::new(config),
+        config_fields(config),
+    ))
+}
+
+/// Adds `resolvers` after those of the running client, as [`reload_config`]
+/// does for a config whose resolver list grew and nothing else changed.
+// Called by the status endpoint and library callers.
+#[allow(dead_code)]
+pub fn append_resolvers(resolvers: &[ResolverSpec]) -> Result<ReloadReport, ClientError> {
+    if resolvers.is_empty() {
+        return Err(ClientError::new("No resolvers to add"));
+    }
+    let mut running = RUNNING
+        .lock()
+        .map_err(|_| ClientError::new("Reload state is poisoned"))?;
+    let Some(running) = running.as_mut() else {
+        return Err(ClientError::new("No client is running"));
+    };
+    let mut requested = running.live.clone();
+    requested.resolvers.extend_from_slice(resolvers);
+    let mut fields = running.fields.clone();
+    for (field, value) in fields.iter_mut() {
+        if *field == "resolvers" {
+            *value = format!("{:?}", requested.resolvers);
+        }
+    }
+    Ok(apply_reload(running, &requested, fields))
+}
+
+/// Takes the hot fields of `requested` whose text in `fields` differs from
+/// the running config.
+fn apply_reload(
+    running: &mut RunningConfig,
+    requested: &LiveSettings,
+    fields: Vec<(&'static str, String)>,
+) -> ReloadReport {
+    let mut report = ReloadReport::default();
+    let mut live = running.live.clone();
+    for ((field, current), (_, new)) in running.fields.iter_mut().zip(fields) {
+        if *current == new {
+            continue;
+        }
+        let hot = match *field {
+            "resolvers" => appends_resolvers(&live.resolvers, &requested.resolvers),
+            field => HOT_RELOAD_FIELDS.contains(&field),
+        };
+        if hot {
+            live.take_field(requested, field);
+            *current = new;
+            report.applied.push(*field);
+        } else {
+            report.restart_required.push(*field);
+        }
+    }
+    if !report.applied.is_empty() {
+        running.live = live.clone();
+        if let Ok(mut pending) = PENDING.lock() {
+            *pending = Some(live);
+        }
+    }
+    report
+}
+
+/// Whether `new` keeps every resolver of `old`, in order, and adds more after them.
+fn appends_resolvers(old: &[ResolverSpec], new: &[ResolverSpec]) -> bool {
+    new.len() > old.len()
+        && old
+            .iter()
+            .zip(new)
+            .all(|(old, new)| format!("{:?}", old) == format!("{:?}", new))
+}
+
+/// Every field, named as in `ClientConfig`, as `Debug` text to compare configs.
+fn config_fields(config: &ClientConfig<'_>) -> Vec<(&'static str, String)> {
+    // The destructuring has no `..`, so a new field must be listed to compile.
+    macro_rules! fields {
+        ($($field:ident),* $(,)?) => {{
+            let ClientConfig { $($field),* } = config;
+            vec![$((stringify!($field), format!("{:?}", $field))),*]
+        }};
+    }
+    fields![
+        tcp_listen_host,
+        tcp_listen_port,
+        resolvers,
+        domain,
+        cert,
+        congestion_control,
+        gso,
+        keep_alive_interval,
+        adaptive_keep_alive,
+        debug_poll,
+        debug_streams,
+        record_dns,
+        query_log,
+        flight_recorder_records,
+        resolver_failure_threshold,
+        resolver_grace_period_ms,
+        resolver_recovery_probe_ms,
+        resolver_servfail_threshold,
+        resolver_servfail_cooldown_ms,
+        confirm_resolvers,
+        handshake_redundancy,
+        status_host,
+        status_port,
+        status_control,
+        dns_listen_host,
+        dns_listen_port,
+        dns_upstream,
+        dns_socks_username,
+        dns_socks_password,
+        dns_id_strategy,
+        query_class,
+        edns_cookies,
+        adaptive_query_rate,
+        query_ramp_qps,
+        query_ramp_doubling_ms,
+        coalesce_delay_ms,
+        reconnect_min_ms,
+        reconnect_max_ms,
+        reconnect_jitter,
+        max_reconnect_attempts,
+        max_reconnect_duration_ms,
+        block_backoff_after,
+        block_backoff_ms,
+        resolver_role_policy,
+        resolver_preference,
+        max_parallel_probes,
+        poll_timeout_ms,
+        idle_poll_min_ms,
+        idle_poll_max_ms,
+        idle_refresh_ms,
+        poll_pool,
+        stream_striping,
+        prioritize_interactive,
+        congestion_isolation,
+        first_stream_boost_count,
+        first_stream_boost_ms,
+        max_connection_setups,
+        reassembly_limit_bytes,
+        local_close_policy,
+        protection_mode,
+        protection_loss_policy,
+        udp_rcvbuf_bytes,
+        udp_sndbuf_bytes,
+        source_address,
+        max_qname_len,
+        label_encoding,
+        non_tunnel_policy,
+        mode_mismatch_policy,
+        formerr_policy,
+        max_lifetime_queries,
+        pipeline_mode,
+        active_windows,
+        quiet_hours,
+        event_grace_ms,
+        congestion_controller,
+        resolver_transport,
+        query_hook,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_config::{config, resolver};
+
+    #[test]
+    fn only_appended_resolvers_are_hot() {
+        let old = [resolver("1.1.1.1"), resolver("8.8.8.8")];
+        let appended = [
+            resolver("1.1.1.1"),
+            resolver("8.8.8.8"),
+            resolver("9.9.9.9"),
+        ];
+        let reordered = [
+            resolver("8.8.8.8"),
+            resolver("1.1.1.1"),
+            resolver("9.9.9.9"),
+        ];
+        assert!(appends_resolvers(&old, &appended));
+        assert!(!appends_resolvers(&old, &reordered));
+        assert!(!appends_resolvers(&old, &old[..1]));
+        assert!(!appends_resolvers(&old, &old));
+    }
+
+    #[test]
+    fn reloads_apply_hot_fields_and_report_the_rest() {
+        let resolvers = [resolver("1.1.1.1"), resolver("8.8.8.8")];
+        let base = config("t.example.com", &resolvers);
+        let err = reload_config(&base).expect_err("nothing is running");
+        assert!(err.to_string().contains("No client is running"), "{}", err);
+
+        let target = ReloadTarget::register(&base, &LiveSettings::new(&base));
+        let mut update = config("u.example.com", &resolvers);
+        update.poll_timeout_ms = 1_000;
+        let report = reload_config(&update).expect("reload");
+        assert_eq!(report.applied, ["poll_timeout_ms"]);
+        assert_eq!(report.restart_required, ["domain"]);
+
+        // A second reload before the runtime picks up the first builds on it.
+        let more = [
+            resolver("1.1.1.1"),
+            resolver("8.8.8.8"),
+            resolver("9.9.9.9"),
+        ];
+        let mut update = config("u.example.com", &more);
+        update.poll_timeout_ms = 1_000;
+        update.reconnect_jitter = true;
+        let report = reload_config(&update).expect("reload");
+        assert_eq!(report.applied, ["resolvers", "reconnect_jitter"]);
+        assert_eq!(report.restart_required, ["domain"]);
+        let live = take_reload().expect("pending settings");
+        assert_eq!(live.poll_timeout_ms, 1_000);
+        assert!(live.reconnect_jitter);
+        assert_eq!(live.resolvers.len(), 3);
+        assert!(take_reload().is_none());
+
+        let mut removed = config("t.example.com", &resolvers[..1]);
+        removed.poll_timeout_ms = 1_000;
+        removed.reconnect_jitter = true;
+        let report = reload_config(&removed).expect("reload");
+        assert!(report.applied.is_empty());
+        assert_eq!(report.restart_required, ["resolvers"]);
+        assert!(take_reload().is_none());
+
+        let mut invalid = config("t.example.com", &more);
+        invalid.reconnect_min_ms = 10_000;
+        let err = reload_config(&invalid).expect_err("invalid config");
+        assert!(err.to_string().contains("reconnect_min_ms"), "{}", err);
+        assert!(take_reload().is_none());
+
+        // Appending resolvers on their own goes the same way.
+        let report = append_resolvers(&[resolver("149.112.112.112")]).expect("append");
+        assert_eq!(report.applied, ["resolvers"]);
+        assert!(report.restart_required.is_empty());
+        let live = take_reload().expect("pending settings");
+        assert_eq!(live.resolvers.len(), 4);
+        assert_eq!(live.poll_timeout_ms, 1_000);
+        assert!(append_resolvers(&[]).is_err());
+
+        drop(target);
+        assert!(reload_config(&update).is_err());
+        assert!(append_resolvers(&[resolver("9.9.9.9")]).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use slipstream_ffi::ActiveWindow;
+
+// How often a running connection checks whether its window has closed.
+const SCHEDULE_CHECK_INTERVAL_US: u64 = 5_000_000;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// Parses `HH:MM-HH:MM` in the device's local time. An end before the start
+/// wraps past midnight; equal times cover the whole day.
+pub fn parse_active_window(input: &str) -> Result<ActiveWindow, String> {
+    let invalid = || format!("Invalid active window {} (expected HH:MM-HH:MM)", input);
+    let (start, end) = input.trim().split_once('-').ok_or_else(invalid)?;
+    let start_minute = parse_minute_of_day(start).ok_or_else(invalid)?;
+    let end_minute = parse_minute_of_day(end).ok_or_else(invalid)?;
+    Ok(ActiveWindow {
+        start_minute,
+        end_minute,
+    })
+}
+
+fn parse_minute_of_day(input: &str) -> Option<u16> {
+    let (hours, minutes) = input.trim().split_once(':')?;
+    if hours.is_empty() || hours.len() > 2 || minutes.len() != 2 {
+        return None;
+    }
+    let hours: u16 = hours.parse().ok()?;
+    let minutes: u16 = minutes.parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+pub(crate) fn format_minute_of_day(minute: u16) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+/// Minutes since local midnight, in the device's time zone.
+pub(crate) fn local_minute_of_day() -> u16 {
+    // SAFETY: `time` and `localtime_r` only write to the locals passed in.
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return 0;
+        }
+        (tm.tm_hour * 60 + tm.tm_min) as u16
+    }
+}
+
+/// Daily windows outside which the tunnel is paused.
+///
+/// With no windows the tunnel is always active.
+pub(crate) struct ActiveSchedule {
+    windows: Vec<ActiveWindow>,
+    last_check_us: u64,
+}
+
+impl ActiveSchedule {
+    pub(crate) fn new(windows: &[ActiveWindow]) -> Self {
+        Self {
+            windows: windows.to_vec(),
+            last_check_us: 0,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !self.windows.is_empty()
+    }
+
+    pub(crate) fn is_active(&self, minute: u16) -> bool {
+        !self.is_enabled()
+            || self
+                .windows
+                .iter()
+                .any(|window| window_contains(window, minute))
+    }
+
+    /// Rate-limits the checks made while connected.
+    pub(crate) fn is_due(&mut self, now_us: u64) -> bool {
+        if !self.is_enabled()
+            || now_us.saturating_sub(self.last_check_us) < SCHEDULE_CHECK_INTERVAL_US
+        {
+            return false;
+        }
+        self.last_check_us = now_us;
+        true
+    }
+
+    /// Start of the next window after `minute`, for logging.
+    pub(crate) fn next_start(&self, minute: u16) -> Option<u16> {
+        self.windows
+            .iter()
+            .map(|window| window.start_minute)
+            .min_by_key(|start| (start + MINUTES_PER_DAY - minute - 1) % MINUTES_PER_DAY)
+    }
+}
+
+pub(super) fn window_contains(window: &ActiveWindow, minute: u16) -> bool {
+    let (start, end) = (window.start_minute, window.end_minute);
+    match start.cmp(&end) {
+        std::cmp::Ordering::Less => start <= minute && minute < end,
+        std::cmp::Ordering::Greater => minute >= start || minute < end,
+        std::cmp::Ordering::Equal => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_cover_their_hours_including_past_midnight() {
+        let windows = [
+            parse_active_window("08:00-12:30").unwrap(),
+            parse_active_window("22:00-06:00").unwrap(),
+        ];
+        let schedule = ActiveSchedule::new(&windows);
+        assert!(schedule.is_active(8 * 60));
+        assert!(schedule.is_active(12 * 60 + 29));
+        assert!(!schedule.is_active(12 * 60 + 30));
+        assert!(!schedule.is_active(21 * 60 + 59));
+        assert!(schedule.is_active(23 * 60));
+        assert!(schedule.is_active(5 * 60 + 59));
+        assert!(!schedule.is_active(6 * 60));
+        assert_eq!(schedule.next_start(13 * 60), Some(22 * 60));
+        assert_eq!(schedule.next_start(23 * 60), Some(8 * 60));
+
+        assert!(ActiveSchedule::new(&[]).is_active(0));
+        for invalid in ["8-12", "24:00-01:00", "08:60-09:00", "08:00", "8:0-9:00"] {
+            assert!(parse_active_window(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -1,18 +1,70 @@
 use crate::error::ClientError;
+use slipstream_dns::{
+    build_qname_with_limit, build_safe_qname_with_limit, max_payload_len_for_name_len,
+    max_safe_payload_len_for_name_len, DnsError, MAX_DNS_NAME_LEN,
+};
+use slipstream_ffi::{ClientConfig, LabelEncoding, ProtectionMode};
 use socket2::{Domain, Protocol, SockAddr, Socket, Type};
-use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
 #[cfg(target_os = "android")]
 use std::os::unix::io::AsRawFd;
-use tokio::net::{lookup_host, TcpListener as TokioTcpListener, UdpSocket as TokioUdpSocket};
+use tokio::net::{TcpListener as TokioTcpListener, UdpSocket as TokioUdpSocket};
 use tracing::{info, warn};
 
-pub(crate) fn compute_mtu(domain_len: usize) -> Result<u32, ClientError> {
-    if domain_len >= 240 {
+/// Longest query name the client sends: `max_qname_len`, or the DNS maximum.
+pub(crate) fn qname_limit(config: &ClientConfig<'_>) -> usize {
+    config.max_qname_len.unwrap_or(MAX_DNS_NAME_LEN)
+}
+
+/// Query name carrying `payload` under the configured domain, in the configured
+/// label encoding, within `max_qname_len`.
+pub(crate) fn build_query_name(
+    config: &ClientConfig<'_>,
+    payload: &[u8],
+    max_qname_len: usize,
+) -> Result<String, DnsError> {
+    match config.label_encoding {
+        LabelEncoding::Base32 => build_qname_with_limit(payload, config.domain, max_qname_len),
+        LabelEncoding::Safe => build_safe_qname_with_limit(payload, config.domain, max_qname_len),
+    }
+}
+
+/// Largest payload [`build_query_name`] fits within `max_qname_len`.
+pub(crate) fn max_query_payload(
+    config: &ClientConfig<'_>,
+    max_qname_len: usize,
+) -> Result<usize, DnsError> {
+    match config.label_encoding {
+        LabelEncoding::Base32 => max_payload_len_for_name_len(config.domain, max_qname_len),
+        LabelEncoding::Safe => max_safe_payload_len_for_name_len(config.domain, max_qname_len),
+    }
+}
+
+/// QUIC packet size whose query names, encoded with `encoding` and dotted under
+/// a domain of `domain_len`, stay within `max_qname_len`.
+pub(crate) fn compute_mtu(
+    domain_len: usize,
+    max_qname_len: usize,
+    encoding: LabelEncoding,
+) -> Result<u32, ClientError> {
+    if max_qname_len > MAX_DNS_NAME_LEN {
+        return Err(ClientError::new(format!(
+            "Query name limit {} exceeds the DNS maximum of {}",
+            max_qname_len, MAX_DNS_NAME_LEN
+        )));
+    }
+    // 13 characters of slack cover the label dots and rounding.
+    let name_budget = max_qname_len.saturating_sub(13);
+    if domain_len >= name_budget {
         return Err(ClientError::new(
             "Domain name is too long for DNS transport",
         ));
     }
-    let mtu = ((240.0 - domain_len as f64) / 1.6) as u32;
+    let mtu = match encoding {
+        LabelEncoding::Base32 => ((name_budget - domain_len) as f64 / 1.6) as u32,
+        // Two characters per byte, after the two-character marker label.
+        LabelEncoding::Safe => ((name_budget - domain_len).saturating_sub(2) / 2) as u32,
+    };
     if mtu == 0 {
         return Err(ClientError::new(
             "MTU computed to zero; check domain length",
@@ -21,35 +73,102 @@ pub(crate) fn compute_mtu(domain_len: usize) -> Result<u32, ClientError> {
     Ok(mtu)
 }
 
-pub(crate) async fn bind_udp_socket() -> Result<TokioUdpSocket, ClientError> {
-    let bind_addr = SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0));
-    bind_udp_socket_addr(bind_addr)
+/// Binds the DNS socket, to `source` when set and otherwise to every address.
+///
+/// The socket stays dual-stack, so an IPv4 source is bound in its IPv4-mapped
+/// form and the socket then only reaches IPv4 resolvers.
+pub(crate) async fn bind_udp_socket(
+    protection: ProtectionMode,
+    buffers: UdpBufferSizes,
+    source: Option<IpAddr>,
+) -> Result<TokioUdpSocket, ClientError> {
+    let ip = match source {
+        Some(IpAddr::V4(v4)) => v4.to_ipv6_mapped(),
+        Some(IpAddr::V6(v6)) => v6,
+        None => Ipv6Addr::UNSPECIFIED,
+    };
+    let bind_addr = SocketAddr::V6(SocketAddrV6::new(ip, 0, 0, 0));
+    bind_udp_socket_addr(bind_addr, protection, buffers).map_err(|err| match source {
+        Some(source) if err.kind() == std::io::ErrorKind::AddrNotAvailable => {
+            ClientError::new(format!(
+                "Source address {} is not assigned to a local interface",
+                source
+            ))
+        }
+        Some(source) => ClientError::new(format!(
+            "Failed to bind the DNS socket to source address {}: {}",
+            source, err
+        )),
+        None => map_io(err),
+    })
 }
 
-pub(crate) async fn bind_tcp_listener(
-    host: &str,
-    port: u16,
-) -> Result<TokioTcpListener, ClientError> {
-    let addrs: Vec<SocketAddr> = lookup_host((host, port)).await.map_err(map_io)?.collect();
-    if addrs.is_empty() {
-        return Err(ClientError::new(format!(
-            "No addresses resolved for {}:{}",
-            host, port
-        )));
+/// Requested `SO_RCVBUF`/`SO_SNDBUF` sizes for the DNS socket; `None` keeps the
+/// OS default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct UdpBufferSizes {
+    pub(crate) recv: Option<usize>,
+    pub(crate) send: Option<usize>,
+}
+
+/// Applies the requested buffer sizes and returns what the OS actually granted.
+///
+/// The kernel silently caps requests at its own limit (`net.core.rmem_max` and
+/// `wmem_max` on Linux, which also doubles the value for bookkeeping), so the
+/// size read back is the one that matters. Requests are clamped to what
+/// `setsockopt` can express first.
+fn apply_udp_buffer_sizes(socket: &Socket, requested: UdpBufferSizes) -> UdpBufferSizes {
+    let clamp = |size: usize| size.clamp(1, libc::c_int::MAX as usize);
+    if let Some(size) = requested.recv {
+        if let Err(err) = socket.set_recv_buffer_size(clamp(size)) {
+            warn!(
+                "Failed to set UDP receive buffer to {} bytes: {}",
+                size, err
+            );
+        }
     }
-    let mut last_err = None;
-    for addr in addrs {
-        match bind_tcp_listener_addr(addr) {
-            Ok(listener) => return Ok(listener),
-            Err(err) => last_err = Some(err),
+    if let Some(size) = requested.send {
+        if let Err(err) = socket.set_send_buffer_size(clamp(size)) {
+            warn!("Failed to set UDP send buffer to {} bytes: {}", size, err);
         }
     }
-    Err(last_err.unwrap_or_else(|| {
-        ClientError::new(format!("Failed to bind TCP listener on {}:{}", host, port))
-    }))
+    UdpBufferSizes {
+        recv: socket.recv_buffer_size().ok(),
+        send: socket.send_buffer_size().ok(),
+    }
+}
+
+/// Asks the VPN again to keep the DNS socket out of the tunnel.
+///
+/// Returns true when protection is not wanted or was granted.
+pub(crate) fn reprotect_udp_socket(udp: &TokioUdpSocket, protection: ProtectionMode) -> bool {
+    #[cfg(target_os = "android")]
+    if protection == ProtectionMode::VpnProtected {
+        return crate::android::protect_socket(udp.as_raw_fd());
+    }
+    #[cfg(not(target_os = "android"))]
+    let _ = (udp, protection);
+    true
+}
+
+/// Parses the TCP listen host, which must be a literal IP address.
+///
+/// Hostnames are rejected rather than looked up: a name that resolves to a
+/// non-loopback address would quietly expose the proxy to the network. IPv6
+/// addresses may be bracketed.
+pub fn parse_listen_host(host: &str) -> Result<IpAddr, ClientError> {
+    let trimmed = host.trim();
+    let literal = trimmed
+        .strip_prefix('[')
+        .and_then(|inner| inner.strip_suffix(']'));
+    match literal {
+        Some(inner) => inner.parse::<Ipv6Addr>().map(IpAddr::V6).ok(),
+        None => trimmed.parse::<IpAddr>().ok(),
+    }
+    .ok_or_else(|| ClientError::invalid_listen_host(host))
 }
 
-fn bind_tcp_listener_addr(addr: SocketAddr) -> Result<TokioTcpListener, ClientError> {
+pub(crate) fn bind_tcp_listener(addr: SocketAddr) -> Result<TokioTcpListener, ClientError> {
     let domain = match addr {
         SocketAddr::V4(_) => Domain::IPV4,
         SocketAddr::V6(_) => Domain::IPV6,
@@ -75,12 +194,16 @@ fn bind_tcp_listener_addr(addr: SocketAddr) -> Result<TokioTcpListener, ClientEr
     TokioTcpListener::from_std(std_listener).map_err(map_io)
 }
 
-fn bind_udp_socket_addr(addr: SocketAddr) -> Result<TokioUdpSocket, ClientError> {
+fn bind_udp_socket_addr(
+    addr: SocketAddr,
+    protection: ProtectionMode,
+    buffers: UdpBufferSizes,
+) -> std::io::Result<TokioUdpSocket> {
     let domain = match addr {
         SocketAddr::V4(_) => Domain::IPV4,
         SocketAddr::V6(_) => Domain::IPV6,
     };
-    let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP)).map_err(map_io)?;
+    let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
     if let SocketAddr::V6(_) = addr {
         if let Err(err) = socket.set_only_v6(false) {
             warn!(
@@ -89,29 +212,188 @@ fn bind_udp_socket_addr(addr: SocketAddr) -> Result<TokioUdpSocket, ClientError>
             );
         }
     }
+    if buffers != UdpBufferSizes::default() {
+        let achieved = apply_udp_buffer_sizes(&socket, buffers);
+        info!(
+            "UDP socket buffers: receive {:?} bytes (requested {:?}), send {:?} bytes (requested {:?})",
+            achieved.recv, buffers.recv, achieved.send, buffers.send
+        );
+    }
     let sock_addr = SockAddr::from(addr);
-    socket.bind(&sock_addr).map_err(map_io)?;
+    socket.bind(&sock_addr)?;
 
     // CRITICAL: On Android, protect the UDP socket BEFORE setting non-blocking
     // and converting to tokio. This prevents the VPN from capturing DNS queries
-    // to the resolver, which would create a routing loop.
+    // to the resolver, which would create a routing loop. Proxy-only use has
+    // no VpnService to ask, so ProtectionMode::None skips this entirely.
     #[cfg(target_os = "android")]
-    {
+    if protection == ProtectionMode::VpnProtected {
         let fd = socket.as_raw_fd();
         info!("Protecting UDP socket fd={} for DNS queries", fd);
         if !crate::android::protect_socket(fd) {
-            return Err(ClientError::new(
+            return Err(std::io::Error::other(
                 "Failed to protect UDP socket - DNS queries will fail due to routing loop",
             ));
         }
         info!("UDP socket fd={} protected successfully", fd);
     }
+    #[cfg(not(target_os = "android"))]
+    let _ = protection;
 
-    socket.set_nonblocking(true).map_err(map_io)?;
+    socket.set_nonblocking(true)?;
     let std_socket: std::net::UdpSocket = socket.into();
-    TokioUdpSocket::from_std(std_socket).map_err(map_io)
+    TokioUdpSocket::from_std(std_socket)
 }
 
 pub(crate) fn map_io(err: std::io::Error) -> ClientError {
     ClientError::new(err.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ClientErrorKind;
+    use slipstream_core::normalize_dual_stack_addr;
+    use std::net::Ipv4Addr;
+
+    fn udp_socket() -> Socket {
+        Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).expect("udp socket")
+    }
+
+    #[test]
+    fn listen_host_accepts_only_ip_literals() {
+        for (host, expected) in [
+            ("127.0.0.1", "127.0.0.1"),
+            ("0.0.0.0", "0.0.0.0"),
+            ("::", "::"),
+            ("::1", "::1"),
+            ("[::1]", "::1"),
+            ("fe80::2", "fe80::2"),
+        ] {
+            let addr = parse_listen_host(host).expect(host);
+            assert_eq!(addr, expected.parse::<IpAddr>().unwrap(), "{}", host);
+        }
+        for host in [
+            "localhost",
+            "proxy.example.com",
+            "",
+            "256.0.0.1",
+            "127.0.0.1:5201",
+            "[127.0.0.1]",
+            "[::1",
+        ] {
+            let err = parse_listen_host(host).expect_err(host);
+            assert_eq!(err.kind(), ClientErrorKind::InvalidListenHost);
+            assert!(err.to_string().contains(&format!("{:?}", host)), "{}", err);
+        }
+    }
+
+    #[test]
+    fn query_names_fit_the_limit_at_full_mtu() {
+        for encoding in [LabelEncoding::Base32, LabelEncoding::Safe] {
+            for domain in ["t.co", "tunnel.example.com"] {
+                for limit in [64, 100, 150, 200, MAX_DNS_NAME_LEN] {
+                    let mtu = compute_mtu(domain.len(), limit, encoding).expect("mtu") as usize;
+                    let max_payload = match encoding {
+                        LabelEncoding::Base32 => max_payload_len_for_name_len(domain, limit),
+                        LabelEncoding::Safe => max_safe_payload_len_for_name_len(domain, limit),
+                    }
+                    .expect("payload");
+                    assert!(
+                        mtu <= max_payload,
+                        "{:?} {} {}: {} > {}",
+                        encoding,
+                        domain,
+                        limit,
+                        mtu,
+                        max_payload
+                    );
+                    let payload = vec![0xff; mtu];
+                    let qname = match encoding {
+                        LabelEncoding::Base32 => build_qname_with_limit(&payload, domain, limit),
+                        LabelEncoding::Safe => build_safe_qname_with_limit(&payload, domain, limit),
+                    }
+                    .expect("qname");
+                    assert!(qname.trim_end_matches('.').len() <= limit, "{}", qname);
+                }
+            }
+        }
+        assert_eq!(
+            compute_mtu(10, MAX_DNS_NAME_LEN, LabelEncoding::Base32).expect("mtu"),
+            143
+        );
+        assert_eq!(
+            compute_mtu(10, MAX_DNS_NAME_LEN, LabelEncoding::Safe).expect("mtu"),
+            114
+        );
+        assert!(compute_mtu(10, 20, LabelEncoding::Base32).is_err());
+        assert!(compute_mtu(10, MAX_DNS_NAME_LEN + 1, LabelEncoding::Base32).is_err());
+    }
+
+    #[test]
+    fn requested_buffer_sizes_are_applied_or_clamped() {
+        let socket = udp_socket();
+        let achieved = apply_udp_buffer_sizes(
+            &socket,
+            UdpBufferSizes {
+                recv: Some(32 * 1024),
+                send: Some(32 * 1024),
+            },
+        );
+        // Linux reports double the request; other systems report it as is.
+        assert!(achieved.recv.expect("recv size") >= 32 * 1024);
+        assert!(achieved.send.expect("send size") >= 32 * 1024);
+
+        // Far past any OS limit: clamped rather than rejected.
+        let socket = udp_socket();
+        let untouched = socket.recv_buffer_size().expect("default size");
+        let achieved = apply_udp_buffer_sizes(
+            &socket,
+            UdpBufferSizes {
+                recv: Some(usize::MAX),
+                send: None,
+            },
+        );
+        let recv = achieved.recv.expect("recv size");
+        assert!(recv >= untouched.min(32 * 1024));
+        assert!(recv <= libc::c_int::MAX as usize);
+    }
+
+    #[tokio::test]
+    async fn dns_socket_binds_to_the_source_address() {
+        let source: IpAddr = "127.0.0.1".parse().unwrap();
+        let socket = bind_udp_socket(
+            ProtectionMode::None,
+            UdpBufferSizes::default(),
+            Some(source),
+        )
+        .await
+        .expect("loopback is local");
+        let local = socket.local_addr().unwrap();
+        assert_eq!(local.ip(), IpAddr::V6(Ipv4Addr::LOCALHOST.to_ipv6_mapped()));
+
+        // Resolvers see queries coming from the pinned address.
+        let resolver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let resolver_addr = normalize_dual_stack_addr(resolver.local_addr().unwrap());
+        socket.send_to(b"query", resolver_addr).await.unwrap();
+        let mut buf = [0u8; 16];
+        let (len, from) = resolver.recv_from(&mut buf).unwrap();
+        assert_eq!(
+            (&buf[..len], from.ip(), from.port()),
+            (&b"query"[..], source, local.port())
+        );
+
+        // TEST-NET-1 is never assigned to this host.
+        let err = bind_udp_socket(
+            ProtectionMode::None,
+            UdpBufferSizes::default(),
+            Some("192.0.2.1".parse().unwrap()),
+        )
+        .await
+        .expect_err("not a local address");
+        assert_eq!(
+            err.to_string(),
+            "Source address 192.0.2.1 is not assigned to a local interface"
+        );
+    }
+}
@@ -0,0 +1,916 @@
+//! Diagnostic snapshot of the running client session.
+//!
+//! The runtime owns the live values and publishes a copy here at most once per
+//! interval so that other threads (the Android bridge, library callers) can read
+//! it without touching the event loop.
+
+use crate::dns::{ResolverHealthState, ResponseTimeHistogram};
+use crate::doh_tls::doh_tls;
+use crate::host_limits::host_connection_counts;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::info;
+
+const SESSION_PUBLISH_INTERVAL_US: u64 = 1_000_000;
+// Span `efficiency_ratio` is measured over.
+const EFFICIENCY_WINDOW_US: u64 = 30_000_000;
+
+static SESSION: Mutex<Option<SessionInfo>> = Mutex::new(None);
+static LAST_SUMMARY: Mutex<Option<SessionSummary>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SessionInfo {
+    pub connected: bool,
+    /// Id of the current connection, shared with the server's logs; `None`
+    /// until the first connection is created.
+    pub session_id: Option<String>,
+    pub domain: String,
+    /// QUIC packet budget per DNS query, derived from the domain length. A
+    /// configured ceiling, set at startup.
+    pub mtu: u32,
+    /// Largest payload a single QNAME can carry for the configured domain. A
+    /// configured ceiling, set at startup, not what queries actually carried.
+    pub max_query_payload_bytes: usize,
+    /// Largest DNS query sent on the wire during this session, polls and
+    /// redundant copies included.
+    pub max_query_bytes: usize,
+    /// Reconnect attempts since the client started.
+    pub reconnects: u64,
+    /// Times the client noticed the device had been asleep (e.g. Android Doze).
+    pub doze_wakeups: u64,
+    /// Set once the VPN stopped protecting the DNS socket and the tunnel was dropped.
+    pub protection_lost: bool,
+    /// Set while the tunnel is paused outside its active windows.
+    pub scheduled_pause: bool,
+    /// Set while the device has no network and the client waits for one.
+    pub network_offline: bool,
+    /// Failure kind of a suspected persistent block (`no_answers`,
+    /// `no_tunnel_data` or `handshake`); cleared by the next ready connection.
+    pub persistent_block_suspected: Option<String>,
+    /// DNS queries sent since the client started, counted against
+    /// `max_lifetime_queries`.
+    pub lifetime_queries: u64,
+    /// Local TCP reads paused because a stream's tunnel queue was full.
+    pub local_read_pauses: u64,
+    /// Times queued stream data was held back by QUIC flow control.
+    pub tunnel_send_blocked: u64,
+    /// Streams whose incoming tunnel data was dropped at the receive buffer cap.
+    pub buffer_cap_drops: u64,
+    /// Congestion window of bulk streams under congestion isolation; `None`
+    /// while isolation is off.
+    pub bulk_window_bytes: Option<u64>,
+    /// Times bulk stream data was held back by its congestion window.
+    pub bulk_held_back: u64,
+    /// Stream data received from the server and not yet written to local
+    /// sockets, counting gaps still waiting for retransmitted data.
+    pub reassembly_bytes: u64,
+    /// Cap on `reassembly_bytes`; at the cap the server stops sending.
+    pub reassembly_limit_bytes: u64,
+    pub active_streams: usize,
+    /// Accepted local connections waiting to be set up as streams.
+    pub connection_setups: usize,
+    /// Local connections refused because `max_connection_setups` were in progress.
+    pub connection_setups_refused: u64,
+    /// Open CONNECTs per destination host at the local SOCKS edge, for hosts
+    /// with any open; empty without one. See `host_limits`.
+    pub host_connections: BTreeMap<String, usize>,
+    /// TLS version of the SOCKS edge's last successful DoH handshake, e.g.
+    /// `TLSv1.3`; `None` before one or without a SOCKS edge. See `doh_tls`.
+    pub doh_tls_version: Option<String>,
+    /// DoH handshakes that failed because the minimum TLS version could not
+    /// be negotiated.
+    pub doh_tls_below_minimum: u64,
+    /// Latest measured wait from accepting a TCP connection to the first DNS query
+    /// sent after its first data was queued. High values on a fast network mean
+    /// the client's own backlog is the bottleneck.
+    pub internal_queue_latency_ms: Option<u64>,
+    /// Keep-alive interval in use; changes when adaptive keep-alive is learning.
+    pub keep_alive_interval_ms: u64,
+    /// Gap between polls while idle polling spaces them out; 0 while polling at
+    /// full rate.
+    pub poll_interval_ms: u64,
+    pub resolvers: Vec<ResolverInfo>,
+    /// First resolver in configured order that is up and not on standby.
+    pub primary_resolver: Option<String>,
+    /// Optional features engaged on the current connection, e.g. `multipath`.
+    pub active_features: Vec<String>,
+    /// QUIC packets sent and declared lost (and so retransmitted) across all paths.
+    pub packets_sent: u64,
+    pub packets_retransmitted: u64,
+    /// Losses later found to be false alarms; high values point at reordering.
+    pub spurious_retransmits: u64,
+    /// `packets_retransmitted / packets_sent`, 0 before anything was sent.
+    pub retransmit_ratio: f64,
+    /// Queries toward the server and responses back, summed over all resolvers.
+    pub upstream: DirectionStats,
+    pub downstream: DirectionStats,
+    /// Stream bytes moved either way on the current connection.
+    pub stream_bytes: u64,
+    /// Stream bytes per DNS wire byte, both directions together, over the last
+    /// 30s. The tunnel does not compress, so this is what the encoding, DNS
+    /// framing, QUIC overhead, polls and retransmissions leave of the wire
+    /// bytes. `None` until DNS traffic was sent in the window.
+    pub efficiency_ratio: Option<f64>,
+    /// Feature named by the last close that pointed to a mismatched server build.
+    pub server_incompatible: Option<String>,
+    /// Server clock minus client clock, estimated from time-stamped responses.
+    /// `None` unless the server runs with `--time-stamps` and a resolver keeps
+    /// the EDNS option.
+    pub clock_offset_ms: Option<i64>,
+    /// Worst-case error of `clock_offset_ms`: half the sample's round trip.
+    pub clock_offset_uncertainty_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolverInfo {
+    pub addr: String,
+    pub mode: String,
+    pub state: ResolverHealthState,
+    /// Takes queries but stopped answering while other resolvers still do.
+    pub asymmetric: bool,
+    /// SERVFAIL runs that paused this resolver for the cooldown.
+    pub servfail_storms: u64,
+    /// Put on standby by the split resolver role policy, or while dead and
+    /// another path can carry data; carries only polls.
+    pub standby: bool,
+    /// Answer TTL of the latest response; lower than the server's setting when
+    /// the resolver served it from cache or clamped it.
+    pub response_ttl: Option<u32>,
+    /// Polls unanswered past `--poll-timeout-ms`, each replaced by a new poll.
+    pub poll_timeouts: u64,
+    /// Timed-out polls answered after all, i.e. spurious poll retransmits.
+    pub late_poll_responses: u64,
+    /// Answers that were ordinary records rather than tunnel data.
+    pub non_tunnel_responses: u64,
+    /// The latest answer was REFUSED, e.g. the resolver does not serve this
+    /// network or the tunnel domain.
+    pub refusing: bool,
+    // Counters cover the current connection; they restart after a reconnect.
+    pub queries_sent: u64,
+    pub bytes_sent: u64,
+    /// Encoded size of those queries on the wire.
+    pub query_wire_bytes: u64,
+    pub responses: u64,
+    /// Responses that carried a QUIC packet.
+    pub tunnel_responses: u64,
+    pub response_wire_bytes: u64,
+    /// QUIC bytes carried in responses from the resolver.
+    pub bytes_received: u64,
+    /// Downstream QUIC bytes per second while data flows; `None` until measured.
+    pub goodput_bytes_per_sec: Option<u64>,
+    pub lost_packets: u64,
+    pub packets_sent: u64,
+    pub spurious_losses: u64,
+    pub rtt_us: u64,
+    /// DNS query-to-response times, bucketed by `RESPONSE_TIME_BUCKETS_MS`.
+    pub response_time: ResponseTimeHistogram,
+    /// Share of recent DNS queries left unanswered past `--poll-timeout-ms`,
+    /// from 0 to 1; late answers do not count. `None` until enough queries
+    /// settled.
+    pub query_loss_rate: Option<f64>,
+    /// Queries per second adaptive rate control learned the resolver tolerates;
+    /// `None` while it is off or the resolver never pushed back.
+    pub learned_qps: Option<f64>,
+    /// Queries held back, or data packets dropped, to stay under `learned_qps`.
+    pub queries_held_back: u64,
+    /// Congestion controller state of the resolver's path; `None` until the
+    /// path exists.
+    pub congestion: Option<CongestionState>,
+}
+
+/// What the congestion controller of one path currently believes, for tuning.
+/// Read only; nothing here feeds back into the controller.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CongestionState {
+    /// Controller running on the path, e.g. `bbr`, `dcubic` or `custom`.
+    pub algorithm: String,
+    pub cwnd_bytes: u64,
+    pub bytes_in_transit: u64,
+    pub pacing_rate_bytes_per_sec: u64,
+    pub min_rtt_us: u64,
+    pub details: CongestionDetails,
+}
+
+/// Controller-specific state, as far as picoquic exposes it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CongestionDetails {
+    Bbr {
+        /// State machine phase, e.g. `startup` or `probe_bw_cruise`.
+        phase: String,
+        bandwidth_bytes_per_sec: u64,
+    },
+    Cubic {
+        /// `slow_start`, `recovery` or `congestion_avoidance`.
+        phase: String,
+        /// Window before the last reduction, in packets; `None` before the
+        /// first loss. picoquic keeps ssthresh private, so this stands in.
+        w_max_packets: Option<u64>,
+    },
+    /// Controllers that report nothing beyond the shared fields.
+    Other,
+}
+
+/// How much tunnel data one direction carries for the DNS bytes it costs.
+///
+/// Queries carry little in the QNAME while a TXT answer holds much more, so the
+/// two directions differ; comparing them shows which way is the bottleneck.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct DirectionStats {
+    /// DNS messages this way: queries upstream, responses downstream.
+    pub messages: u64,
+    pub wire_bytes: u64,
+    /// QUIC bytes carried inside those messages.
+    pub payload_bytes: u64,
+    /// `payload_bytes / messages`, 0 before any message.
+    pub payload_bytes_per_message: f64,
+    /// `payload_bytes / wire_bytes`, 0 before any message.
+    pub payload_ratio: f64,
+}
+
+impl DirectionStats {
+    fn new(messages: u64, wire_bytes: u64, payload_bytes: u64) -> Self {
+        let ratio = |denominator: u64| {
+            if denominator == 0 {
+                0.0
+            } else {
+                payload_bytes as f64 / denominator as f64
+            }
+        };
+        Self {
+            messages,
+            wire_bytes,
+            payload_bytes,
+            payload_bytes_per_message: ratio(messages),
+            payload_ratio: ratio(wire_bytes),
+        }
+    }
+}
+
+impl SessionInfo {
+    /// Recomputes the per-direction totals from the per-resolver counters.
+    pub(crate) fn refresh_direction_stats(&mut self) {
+        let sum = |field: fn(&ResolverInfo) -> u64| self.resolvers.iter().map(field).sum::<u64>();
+        self.upstream = DirectionStats::new(
+            sum(|resolver| resolver.queries_sent),
+            sum(|resolver| resolver.query_wire_bytes),
+            sum(|resolver| resolver.bytes_sent),
+        );
+        self.downstream = DirectionStats::new(
+            sum(|resolver| resolver.responses),
+            sum(|resolver| resolver.response_wire_bytes),
+            sum(|resolver| resolver.bytes_received),
+        );
+    }
+
+    /// Recomputes the session retransmission totals from the per-resolver counters.
+    pub(crate) fn refresh_retransmit_stats(&mut self) {
+        let (sent, lost, spurious) =
+            self.resolvers
+                .iter()
+                .fold((0u64, 0u64, 0u64), |(sent, lost, spurious), resolver| {
+                    (
+                        sent.saturating_add(resolver.packets_sent),
+                        lost.saturating_add(resolver.lost_packets),
+                        spurious.saturating_add(resolver.spurious_losses),
+                    )
+                });
+        self.packets_sent = sent;
+        self.packets_retransmitted = lost;
+        self.spurious_retransmits = spurious;
+        self.retransmit_ratio = if sent == 0 {
+            0.0
+        } else {
+            lost as f64 / sent as f64
+        };
+    }
+}
+
+/// Report of a finished client run, kept after stop for `last_session_summary`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SessionSummary {
+    pub domain: String,
+    /// Unix time in ms when the client started.
+    pub started_at_ms: u64,
+    /// Wall-clock run time, including time the device slept.
+    pub duration_ms: u64,
+    pub reconnects: u64,
+    pub queries_sent: u64,
+    /// DNS query bytes put on the wire across all resolvers and connections.
+    pub bytes_sent: u64,
+    pub responses: u64,
+    /// `bytes_sent` averaged over `duration_ms`.
+    pub avg_bytes_per_sec: f64,
+    pub upstream: DirectionStats,
+    pub downstream: DirectionStats,
+    /// QUIC bytes per second each way, averaged over `duration_ms`.
+    pub upstream_goodput_bytes_per_sec: f64,
+    pub downstream_goodput_bytes_per_sec: f64,
+    pub errors: SessionErrors,
+    /// Per-resolver totals, in configured order.
+    pub resolvers: Vec<ResolverUsage>,
+}
+
+/// What went wrong during a run, summed over every connection.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SessionErrors {
+    pub lost_packets: u64,
+    pub poll_timeouts: u64,
+    pub servfail_storms: u64,
+    pub buffer_cap_drops: u64,
+    pub doze_wakeups: u64,
+    pub protection_lost: bool,
+    pub server_incompatible: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResolverUsage {
+    pub addr: String,
+    pub mode: String,
+    pub queries_sent: u64,
+    pub bytes_sent: u64,
+    pub query_wire_bytes: u64,
+    pub responses: u64,
+    pub response_wire_bytes: u64,
+    pub bytes_received: u64,
+    pub lost_packets: u64,
+    pub poll_timeouts: u64,
+    pub servfail_storms: u64,
+}
+
+/// Counter samples behind `efficiency_ratio`.
+#[derive(Debug, Default)]
+struct EfficiencyWindow {
+    /// Time, stream bytes and wire bytes at each publish, oldest first; the
+    /// first is the newest sample at least a window old.
+    samples: VecDeque<(u64, u64, u64)>,
+}
+
+impl EfficiencyWindow {
+    fn record(&mut self, now: u64, stream_bytes: u64, wire_bytes: u64) -> Option<f64> {
+        let restarted = self
+            .samples
+            .back()
+            .is_none_or(|&(_, stream, wire)| stream_bytes < stream || wire_bytes < wire);
+        if restarted {
+            // Both counters start from zero with each connection.
+            self.samples.clear();
+            self.samples.push_back((now, 0, 0));
+        }
+        self.samples.push_back((now, stream_bytes, wire_bytes));
+        let cutoff = now.saturating_sub(EFFICIENCY_WINDOW_US);
+        while self.samples.len() > 2 && self.samples[1].0 <= cutoff {
+            self.samples.pop_front();
+        }
+        let &(_, first_stream, first_wire) = self.samples.front()?;
+        let wire = wire_bytes - first_wire;
+        (wire > 0).then(|| (stream_bytes - first_stream) as f64 / wire as f64)
+    }
+}
+
+pub(crate) struct SessionPublisher {
+    info: SessionInfo,
+    last_publish_at: u64,
+    efficiency: EfficiencyWindow,
+    started_at: SystemTime,
+    /// Resolver counters of connections that already ended.
+    totals: Vec<ResolverUsage>,
+    /// The current connection's counters were already added to `totals`.
+    connection_counted: bool,
+    /// The run summary was already stored.
+    finished: bool,
+}
+
+impl SessionPublisher {
+    pub(crate) fn new(info: SessionInfo) -> Self {
+        publish(&info);
+        Self {
+            info,
+            last_publish_at: 0,
+            efficiency: EfficiencyWindow::default(),
+            started_at: SystemTime::now(),
+            totals: Vec::new(),
+            connection_counted: false,
+            finished: false,
+        }
+    }
+
+    /// Starts a connection whose per-resolver counters are `resolvers`, fresh
+    /// from zero, to be added to the run totals when it ends.
+    pub(crate) fn begin_connection(&mut self, resolvers: Vec<ResolverInfo>) {
+        self.info.resolvers = resolvers;
+        self.connection_counted = false;
+    }
+
+    /// Adds the final counters of a connection to the run totals; per-resolver
+    /// counters restart with the next connection.
+    pub(crate) fn end_connection(&mut self, resolvers: Vec<ResolverInfo>) {
+        self.info.resolvers = resolvers;
+        if !self.connection_counted {
+            add_usage(&mut self.totals, &self.info.resolvers);
+            self.connection_counted = true;
+        }
+    }
+
+    pub(crate) fn info_mut(&mut self) -> &mut SessionInfo {
+        &mut self.info
+    }
+
+    pub(crate) fn set_connected(&mut self, connected: bool, now: u64) {
+        if self.info.connected != connected {
+            self.info.connected = connected;
+            self.publish_now(now);
+        }
+    }
+
+    /// Records the id of a new connection; returns the previous one.
+    pub(crate) fn set_session_id(&mut self, id: String, now: u64) -> Option<String> {
+        let previous = self.info.session_id.replace(id);
+        self.publish_now(now);
+        previous
+    }
+
+    pub(crate) fn set_scheduled_pause(&mut self, paused: bool, now: u64) {
+        self.info.scheduled_pause = paused;
+        if paused {
+            self.info.connected = false;
+        }
+        self.publish_now(now);
+    }
+
+    pub(crate) fn set_network_offline(&mut self, offline: bool, now: u64) {
+        self.info.network_offline = offline;
+        self.publish_now(now);
+    }
+
+    pub(crate) fn set_protection_lost(&mut self, now: u64) {
+        self.info.protection_lost = true;
+        self.info.connected = false;
+        self.publish_now(now);
+    }
+
+    /// Publishes at most once per interval; `refresh` fills in values that are
+    /// only worth collecting when a snapshot is actually taken, including the
+    /// direction stats and stream bytes `efficiency_ratio` is taken from.
+    pub(crate) fn maybe_publish(&mut self, now: u64, refresh: impl FnOnce(&mut SessionInfo)) {
+        if now.saturating_sub(self.last_publish_at) >= SESSION_PUBLISH_INTERVAL_US {
+            refresh(&mut self.info);
+            self.info.efficiency_ratio = self.efficiency.record(
+                now,
+                self.info.stream_bytes,
+                self.info
+                    .upstream
+                    .wire_bytes
+                    .saturating_add(self.info.downstream.wire_bytes),
+            );
+            self.publish_now(now);
+        }
+    }
+
+    fn publish_now(&mut self, now: u64) {
+        self.last_publish_at = now;
+        publish(&self.info);
+    }
+
+    /// Stores the run summary for `last_session_summary`. Called on a graceful
+    /// stop before the connection and runtime are torn down, so the summary is
+    /// in place even if teardown outlasts the stop timeout; later counter
+    /// changes are ignored. Dropping the publisher finishes it otherwise.
+    pub(crate) fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        if !self.connection_counted {
+            add_usage(&mut self.totals, &self.info.resolvers);
+        }
+        let started_at_ms = self
+            .started_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+        let duration_ms = self
+            .started_at
+            .elapsed()
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+        let summary = summarize(&self.info, &self.totals, started_at_ms, duration_ms);
+        info!(
+            "Session summary: duration={}s reconnects={} queries={} bytes_sent={} responses={} lost_packets={} poll_timeouts={}",
+            summary.duration_ms / 1_000,
+            summary.reconnects,
+            summary.queries_sent,
+            summary.bytes_sent,
+            summary.responses,
+            summary.errors.lost_packets,
+            summary.errors.poll_timeouts
+        );
+        if let Ok(mut guard) = LAST_SUMMARY.lock() {
+            *guard = Some(summary);
+        }
+    }
+}
+
+impl Drop for SessionPublisher {
+    fn drop(&mut self) {
+        self.finish();
+        if let Ok(mut guard) = SESSION.lock() {
+            guard.take();
+        }
+    }
+}
+
+fn add_usage(totals: &mut Vec<ResolverUsage>, resolvers: &[ResolverInfo]) {
+    for resolver in resolvers {
+        let index = match totals.iter().position(|usage| usage.addr == resolver.addr) {
+            Some(index) => index,
+            None => {
+                totals.push(ResolverUsage {
+                    addr: resolver.addr.clone(),
+                    mode: resolver.mode.clone(),
+                    queries_sent: 0,
+                    bytes_sent: 0,
+                    query_wire_bytes: 0,
+                    responses: 0,
+                    response_wire_bytes: 0,
+                    bytes_received: 0,
+                    lost_packets: 0,
+                    poll_timeouts: 0,
+                    servfail_storms: 0,
+                });
+                totals.len() - 1
+            }
+        };
+        let usage = &mut totals[index];
+        usage.queries_sent = usage.queries_sent.saturating_add(resolver.queries_sent);
+        usage.bytes_sent = usage.bytes_sent.saturating_add(resolver.bytes_sent);
+        usage.query_wire_bytes = usage
+            .query_wire_bytes
+            .saturating_add(resolver.query_wire_bytes);
+        usage.responses = usage.responses.saturating_add(resolver.responses);
+        usage.response_wire_bytes = usage
+            .response_wire_bytes
+            .saturating_add(resolver.response_wire_bytes);
+        usage.bytes_received = usage.bytes_received.saturating_add(resolver.bytes_received);
+        usage.lost_packets = usage.lost_packets.saturating_add(resolver.lost_packets);
+        usage.poll_timeouts = usage.poll_timeouts.saturating_add(resolver.poll_timeouts);
+        usage.servfail_storms = usage
+            .servfail_storms
+            .saturating_add(resolver.servfail_storms);
+    }
+}
+
+fn summarize(
+    info: &SessionInfo,
+    totals: &[ResolverUsage],
+    started_at_ms: u64,
+    duration_ms: u64,
+) -> SessionSummary {
+    let sum = |field: fn(&ResolverUsage) -> u64| totals.iter().map(field).sum::<u64>();
+    let bytes_sent = sum(|usage| usage.bytes_sent);
+    let bytes_received = sum(|usage| usage.bytes_received);
+    let per_sec = |bytes: u64| {
+        if duration_ms == 0 {
+            0.0
+        } else {
+            bytes as f64 * 1_000.0 / duration_ms as f64
+        }
+    };
+    SessionSummary {
+        domain: info.domain.clone(),
+        started_at_ms,
+        duration_ms,
+        reconnects: info.reconnects,
+        queries_sent: sum(|usage| usage.queries_sent),
+        bytes_sent,
+        responses: sum(|usage| usage.responses),
+        avg_bytes_per_sec: per_sec(bytes_sent),
+        upstream: DirectionStats::new(
+            sum(|usage| usage.queries_sent),
+            sum(|usage| usage.query_wire_bytes),
+            bytes_sent,
+        ),
+        downstream: DirectionStats::new(
+            sum(|usage| usage.responses),
+            sum(|usage| usage.response_wire_bytes),
+            bytes_received,
+        ),
+        upstream_goodput_bytes_per_sec: per_sec(bytes_sent),
+        downstream_goodput_bytes_per_sec: per_sec(bytes_received),
+        errors: SessionErrors {
+            lost_packets: sum(|usage| usage.lost_packets),
+            poll_timeouts: sum(|usage| usage.poll_timeouts),
+            servfail_storms: sum(|usage| usage.servfail_storms),
+            buffer_cap_drops: info.buffer_cap_drops,
+            doze_wakeups: info.doze_wakeups,
+            protection_lost: info.protection_lost,
+            server_incompatible: info.server_incompatible.clone(),
+        },
+        resolvers: totals.to_vec(),
+    }
+}
+
+fn publish(info: &SessionInfo) {
+    if let Ok(mut guard) = SESSION.lock() {
+        *guard = Some(info.clone());
+    }
+}
+
+/// Live state of one resolver, for debugging UIs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResolverStatus {
+    pub addr: String,
+    pub mode: String,
+    pub state: ResolverHealthState,
+    pub rtt_us: u64,
+    pub goodput_bytes_per_sec: Option<u64>,
+    /// Responses per query sent; `None` until a query has gone out.
+    pub success_ratio: Option<f64>,
+    /// This resolver's fraction of all queries sent on the connection.
+    pub traffic_share: f64,
+    pub standby: bool,
+    pub asymmetric: bool,
+}
+
+/// Derives the per-resolver view from a session snapshot.
+// Read by the Android bridge and library callers; the CLI only publishes.
+#[allow(dead_code)]
+pub fn resolver_statuses(info: &SessionInfo) -> Vec<ResolverStatus> {
+    let total_queries: u64 = info
+        .resolvers
+        .iter()
+        .map(|resolver| resolver.queries_sent)
+        .sum();
+    info.resolvers
+        .iter()
+        .map(|resolver| ResolverStatus {
+            addr: resolver.addr.clone(),
+            mode: resolver.mode.clone(),
+            state: resolver.state,
+            rtt_us: resolver.rtt_us,
+            goodput_bytes_per_sec: resolver.goodput_bytes_per_sec,
+            success_ratio: (resolver.queries_sent > 0)
+                .then(|| (resolver.responses as f64 / resolver.queries_sent as f64).min(1.0)),
+            traffic_share: if total_queries == 0 {
+                0.0
+            } else {
+                resolver.queries_sent as f64 / total_queries as f64
+            },
+            standby: resolver.standby,
+            asymmetric: resolver.asymmetric,
+        })
+        .collect()
+}
+
+/// Returns the latest published session snapshot, or `None` when no client is running.
+// Read by the Android bridge and library callers; the CLI only publishes.
+#[allow(dead_code)]
+pub fn session_snapshot() -> Option<SessionInfo> {
+    let mut info = SESSION.lock().ok().and_then(|guard| guard.clone())?;
+    info.host_connections = host_connection_counts();
+    (info.doh_tls_version, info.doh_tls_below_minimum) = doh_tls();
+    Some(info)
+}
+
+/// Returns the id of the running client's current connection, as logged by
+/// client and server, or `None` before the first connection.
+// Read by the Android bridge and library callers; the CLI logs it instead.
+#[allow(dead_code)]
+pub fn session_id() -> Option<String> {
+    session_snapshot().and_then(|info| info.session_id)
+}
+
+/// Returns the summary of the most recent client run once it has stopped.
+// Read by the Android bridge and library callers; the CLI logs it on exit.
+#[allow(dead_code)]
+pub fn last_session_summary() -> Option<SessionSummary> {
+    LAST_SUMMARY.lock().ok().and_then(|guard| guard.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The publisher tests share the global snapshot and summary.
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    fn lock_test_state() -> std::sync::MutexGuard<'static, ()> {
+        TEST_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    #[test]
+    fn publisher_clears_snapshot_on_drop() {
+        let _guard = lock_test_state();
+        {
+            let mut publisher = SessionPublisher::new(SessionInfo {
+                domain: "example.com".to_string(),
+                mtu: 140,
+                ..SessionInfo::default()
+            });
+            publisher.info_mut().max_query_bytes = 250;
+            publisher.set_connected(true, 1);
+            let snapshot = session_snapshot().expect("snapshot while running");
+            assert!(snapshot.connected);
+            assert_eq!(snapshot.mtu, 140);
+            assert_eq!(snapshot.max_query_bytes, 250);
+        }
+        assert!(session_snapshot().is_none());
+    }
+
+    fn resolver(packets_sent: u64, lost_packets: u64) -> ResolverInfo {
+        ResolverInfo {
+            addr: "127.0.0.1:53".to_string(),
+            mode: "recursive".to_string(),
+            state: ResolverHealthState::Healthy,
+            asymmetric: false,
+            servfail_storms: 0,
+            standby: false,
+            response_ttl: None,
+            poll_timeouts: 0,
+            late_poll_responses: 0,
+            non_tunnel_responses: 0,
+            refusing: false,
+            queries_sent: 0,
+            bytes_sent: 0,
+            query_wire_bytes: 0,
+            responses: 0,
+            tunnel_responses: 0,
+            response_wire_bytes: 0,
+            bytes_received: 0,
+            goodput_bytes_per_sec: None,
+            lost_packets,
+            packets_sent,
+            spurious_losses: 1,
+            rtt_us: 0,
+            response_time: ResponseTimeHistogram::default(),
+            query_loss_rate: None,
+            learned_qps: None,
+            queries_held_back: 0,
+            congestion: None,
+        }
+    }
+
+    #[test]
+    fn retransmit_stats_sum_all_paths() {
+        let mut info = SessionInfo::default();
+        info.refresh_retransmit_stats();
+        assert_eq!(info.retransmit_ratio, 0.0);
+
+        info.resolvers = vec![resolver(300, 6), resolver(100, 14)];
+        info.refresh_retransmit_stats();
+        assert_eq!(info.packets_sent, 400);
+        assert_eq!(info.packets_retransmitted, 20);
+        assert_eq!(info.spurious_retransmits, 2);
+        assert!((info.retransmit_ratio - 0.05).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn efficiency_follows_the_last_window_and_restarts_with_the_connection() {
+        const S: u64 = 1_000_000;
+        let mut window = EfficiencyWindow::default();
+        assert_eq!(window.record(0, 0, 0), None);
+        // Bulk download: 3 stream bytes per 4 wire bytes.
+        for second in 1..=30 {
+            let ratio = window.record(second * S, second * 3_000, second * 4_000);
+            assert_eq!(ratio, Some(0.75));
+        }
+        // Then idle polling: wire bytes only. The bulk phase ages out.
+        assert_eq!(window.record(45 * S, 90_000, 150_000), Some(0.5));
+        assert_eq!(window.record(80 * S, 90_000, 160_000), Some(0.0));
+
+        // A reconnect restarts both counters.
+        assert_eq!(window.record(81 * S, 500, 1_000), Some(0.5));
+        assert_eq!(window.record(82 * S, 500, 1_000), Some(0.5));
+    }
+
+    #[test]
+    fn summary_adds_up_every_connection() {
+        let mut first = resolver(100, 4);
+        first.queries_sent = 100;
+        first.bytes_sent = 20_000;
+        first.query_wire_bytes = 40_000;
+        first.responses = 90;
+        first.response_wire_bytes = 72_000;
+        first.bytes_received = 54_000;
+        first.poll_timeouts = 3;
+        let mut second = resolver(0, 0);
+        second.addr = "127.0.0.2:53".to_string();
+        second.queries_sent = 10;
+        second.bytes_sent = 2_000;
+
+        let mut totals = Vec::new();
+        add_usage(&mut totals, &[first.clone(), second]);
+        // A reconnect starts the counters over on the same resolver.
+        add_usage(&mut totals, &[first]);
+        assert_eq!(totals.len(), 2);
+        assert_eq!(totals[0].queries_sent, 200);
+
+        let info = SessionInfo {
+            domain: "example.com".to_string(),
+            reconnects: 1,
+            buffer_cap_drops: 2,
+            ..SessionInfo::default()
+        };
+        let summary = summarize(&info, &totals, 1_000, 4_000);
+        assert_eq!(summary.queries_sent, 210);
+        assert_eq!(summary.bytes_sent, 42_000);
+        assert_eq!(summary.responses, 180);
+        assert_eq!(summary.avg_bytes_per_sec, 10_500.0);
+        // Queries carried 200 QUIC bytes each, responses 600: downloads win.
+        assert_eq!(summary.upstream.payload_bytes_per_message, 200.0);
+        assert_eq!(summary.upstream.payload_ratio, 0.525);
+        assert_eq!(summary.downstream.payload_bytes_per_message, 600.0);
+        assert_eq!(summary.downstream.payload_ratio, 0.75);
+        assert_eq!(summary.upstream_goodput_bytes_per_sec, 10_500.0);
+        assert_eq!(summary.downstream_goodput_bytes_per_sec, 27_000.0);
+        assert_eq!(summary.errors.lost_packets, 8);
+        assert_eq!(summary.errors.poll_timeouts, 6);
+        assert_eq!(summary.errors.buffer_cap_drops, 2);
+        assert_eq!(summary.reconnects, 1);
+    }
+
+    #[test]
+    fn graceful_stop_stores_the_full_summary_before_teardown() {
+        let _guard = lock_test_state();
+        let mut publisher = SessionPublisher::new(SessionInfo {
+            domain: "stop.example.com".to_string(),
+            ..SessionInfo::default()
+        });
+        let mut first = resolver(100, 4);
+        first.queries_sent = 100;
+        first.bytes_sent = 20_000;
+        first.responses = 90;
+        first.poll_timeouts = 3;
+        publisher.maybe_publish(SESSION_PUBLISH_INTERVAL_US, |info| {
+            info.resolvers = vec![first.clone()];
+        });
+        publisher.end_connection(vec![first.clone()]);
+        publisher.info_mut().reconnects = 1;
+        publisher.info_mut().doze_wakeups = 2;
+        publisher.info_mut().buffer_cap_drops = 5;
+        publisher.info_mut().protection_lost = true;
+        publisher.info_mut().server_incompatible = Some("multipath".to_string());
+        let mut second = first;
+        second.queries_sent = 10;
+        // The reconnected run ends before the next publish is due.
+        publisher.begin_connection(Vec::new());
+        publisher.maybe_publish(SESSION_PUBLISH_INTERVAL_US + 1, |_| {
+            panic!("published within the interval")
+        });
+        publisher.end_connection(vec![second]);
+
+        publisher.finish();
+        // Stored while the publisher, like the runtime, is still alive.
+        let summary = last_session_summary().expect("summary after a graceful stop");
+        assert_eq!(summary.domain, "stop.example.com");
+        assert_eq!(summary.reconnects, 1);
+        assert_eq!(summary.queries_sent, 110);
+        assert_eq!(summary.bytes_sent, 40_000);
+        assert_eq!(summary.responses, 180);
+        assert_eq!(summary.errors.lost_packets, 8);
+        assert_eq!(summary.errors.poll_timeouts, 6);
+        assert_eq!(summary.errors.buffer_cap_drops, 5);
+        assert_eq!(summary.errors.doze_wakeups, 2);
+        assert!(summary.errors.protection_lost);
+        assert_eq!(
+            summary.errors.server_incompatible.as_deref(),
+            Some("multipath")
+        );
+        assert_eq!(summary.resolvers.len(), 1);
+
+        // Teardown neither counts the last connection twice nor replaces it.
+        publisher.info_mut().reconnects = 9;
+        drop(publisher);
+        let after_drop = last_session_summary().expect("summary after teardown");
+        assert_eq!(after_drop.queries_sent, 110);
+        assert_eq!(after_drop.reconnects, 1);
+        assert!(session_snapshot().is_none());
+    }
+
+    #[test]
+    fn resolver_statuses_report_share_and_success() {
+        let mut busy = resolver(0, 0);
+        busy.queries_sent = 30;
+        busy.responses = 15;
+        let mut idle = resolver(0, 0);
+        idle.state = ResolverHealthState::Dead;
+        idle.queries_sent = 10;
+        let unused = resolver(0, 0);
+        let info = SessionInfo {
+            resolvers: vec![busy, idle, unused],
+            ..SessionInfo::default()
+        };
+        let statuses = resolver_statuses(&info);
+        assert_eq!(statuses[0].success_ratio, Some(0.5));
+        assert_eq!(statuses[0].traffic_share, 0.75);
+        assert_eq!(statuses[1].state, ResolverHealthState::Dead);
+        assert_eq!(statuses[1].success_ratio, Some(0.0));
+        assert_eq!(statuses[2].success_ratio, None);
+        assert_eq!(statuses[2].traffic_share, 0.0);
+        assert!(resolver_statuses(&SessionInfo::default()).is_empty());
+    }
+}
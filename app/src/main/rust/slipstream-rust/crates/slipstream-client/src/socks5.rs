@@ -0,0 +1,152 @@
+//! SOCKS5 client messages (RFC 1928, RFC 1929) for CONNECTs through the tunnel.
+//!
+//! The remote proxy is reached through the client's own listener by the DNS
+//! forwarder and by `verify`. This module builds their requests and parses the
+//! proxy's replies; each caller does its own reads and writes, blocking or
+//! async.
+
+use std::net::IpAddr;
+
+/// Method-selection greeting offering no auth, and username/password when
+/// credentials are given.
+pub(crate) fn greeting(with_credentials: bool) -> &'static [u8] {
+    if with_credentials {
+        &[0x05, 0x02, 0x00, 0x02]
+    } else {
+        &[0x05, 0x01, 0x00]
+    }
+}
+
+/// Checks the proxy's method selection; `true` when it asks for the
+/// username/password login.
+pub(crate) fn parse_method(reply: [u8; 2], with_credentials: bool) -> Result<bool, String> {
+    if reply[0] != 0x05 {
+        return Err(format!("Unexpected SOCKS version {}", reply[0]));
+    }
+    match reply[1] {
+        0x00 => Ok(false),
+        0x02 if with_credentials => Ok(true),
+        0x02 => Err("SOCKS5 proxy requires credentials".to_string()),
+        method => Err(format!("SOCKS5 proxy refused auth methods ({})", method)),
+    }
+}
+
+/// Username/password login request.
+pub(crate) fn login_request(username: &str, password: &str) -> Result<Vec<u8>, String> {
+    let user = username.as_bytes();
+    let pass = password.as_bytes();
+    if user.len() > 255 || pass.len() > 255 {
+        return Err("SOCKS5 credentials are too long".to_string());
+    }
+    let mut request = vec![0x01, user.len() as u8];
+    request.extend_from_slice(user);
+    request.push(pass.len() as u8);
+    request.extend_from_slice(pass);
+    Ok(request)
+}
+
+pub(crate) fn parse_login(reply: [u8; 2]) -> Result<(), String> {
+    if reply[1] != 0x00 {
+        return Err("SOCKS5 authentication rejected".to_string());
+    }
+    Ok(())
+}
+
+/// CONNECT request to `host`, an IP literal or a host name, on `port`.
+pub(crate) fn connect_request(host: &str, port: u16) -> Result<Vec<u8>, String> {
+    let mut request = vec![0x05, 0x01, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(addr)) => {
+            request.push(0x01);
+            request.extend_from_slice(&addr.octets());
+        }
+        Ok(IpAddr::V6(addr)) => {
+            request.push(0x04);
+            request.extend_from_slice(&addr.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err("Host name is too long".to_string());
+            }
+            request.push(0x03);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+}
+
+/// What follows the first four bytes of a CONNECT reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConnectReply {
+    /// The CONNECT failed with this reply code.
+    Failed(u8),
+    /// Succeeded; this many bytes of bound address and port follow.
+    Bound(usize),
+    /// Succeeded with a host name as bound address: one length byte, then
+    /// that many bytes and the port.
+    BoundName,
+}
+
+pub(crate) fn parse_connect_reply(header: [u8; 4]) -> Result<ConnectReply, String> {
+    if header[1] != 0x00 {
+        return Ok(ConnectReply::Failed(header[1]));
+    }
+    match header[3] {
+        0x01 => Ok(ConnectReply::Bound(4 + 2)),
+        0x04 => Ok(ConnectReply::Bound(16 + 2)),
+        0x03 => Ok(ConnectReply::BoundName),
+        atyp => Err(format!("Unexpected SOCKS5 address type {}", atyp)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_requests_and_parses_replies() {
+        assert_eq!(greeting(false), [0x05, 0x01, 0x00]);
+        assert_eq!(parse_method([0x05, 0x02], true), Ok(true));
+        assert_eq!(parse_method([0x05, 0x00], true), Ok(false));
+        assert!(parse_method([0x05, 0x02], false).is_err());
+        assert!(parse_method([0x05, 0xFF], false).is_err());
+        assert!(parse_method([0x04, 0x00], false).is_err());
+
+        assert_eq!(
+            login_request("user", "pass").unwrap(),
+            b"\x01\x04user\x04pass"
+        );
+        assert!(login_request(&"u".repeat(256), "pass").is_err());
+        assert!(parse_login([0x01, 0x01]).is_err());
+
+        assert_eq!(
+            connect_request("192.0.2.1", 443).unwrap(),
+            [0x05, 0x01, 0x00, 0x01, 192, 0, 2, 1, 0x01, 0xBB]
+        );
+        assert_eq!(
+            connect_request("2001:db8::1", 53).unwrap()[3..5],
+            [0x04, 0x20]
+        );
+        assert_eq!(
+            connect_request("example.com", 80).unwrap(),
+            b"\x05\x01\x00\x03\x0bexample.com\x00\x50"
+        );
+        assert!(connect_request(&"a".repeat(256), 80).is_err());
+
+        assert_eq!(
+            parse_connect_reply([0x05, 0x00, 0x00, 0x04]),
+            Ok(ConnectReply::Bound(18))
+        );
+        assert_eq!(
+            parse_connect_reply([0x05, 0x00, 0x00, 0x03]),
+            Ok(ConnectReply::BoundName)
+        );
+        assert_eq!(
+            parse_connect_reply([0x05, 0x05, 0x00, 0x00]),
+            Ok(ConnectReply::Failed(0x05))
+        );
+        assert!(parse_connect_reply([0x05, 0x00, 0x00, 0x09]).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! The bound address in the local SOCKS5 edge's CONNECT replies.
+//!
+//! A successful CONNECT reply carries BND.ADDR and BND.PORT, the address the
+//! proxy connected from. Behind the tunnel only the remote proxy knows it, and
+//! what it reports is an address on the far side that means nothing locally.
+//! Most apps ignore the field, but some reject a reply whose address family or
+//! kind differs from their request, or one with an address they cannot parse.
+//! [`BoundAddressPolicy`] picks what the edge puts there instead.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+
+const SOCKS_VERSION: u8 = 0x05;
+const REPLY_SUCCEEDED: u8 = 0x00;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// What a successful CONNECT reply reports as the bound address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BoundAddressPolicy {
+    /// Pass on the address the remote proxy reported (the default).
+    #[default]
+    Relay,
+    /// The unspecified address with port 0, in the family of the request:
+    /// `::` for an IPv6 destination, `0.0.0.0` otherwise.
+    Zero,
+    /// The destination as requested, domain names included.
+    Destination,
+    /// A fixed address.
+    Placeholder(SocketAddr),
+}
+
+impl FromStr for BoundAddressPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        match value.to_ascii_lowercase().as_str() {
+            "relay" => Ok(Self::Relay),
+            "zero" => Ok(Self::Zero),
+            "destination" => Ok(Self::Destination),
+            _ => value.parse().map(Self::Placeholder).map_err(|_| {
+                format!(
+                    "Invalid bound address policy {} (expected relay, zero, destination or IP:PORT)",
+                    value
+                )
+            }),
+        }
+    }
+}
+
+impl BoundAddressPolicy {
+    /// The full success reply to a CONNECT. `requested` is the request's
+    /// ATYP, DST.ADDR and DST.PORT; `relayed` the ATYP, BND.ADDR and BND.PORT
+    /// of the remote proxy's reply. A malformed address is replaced by a
+    /// zeroed one rather than sent on.
+    pub fn connect_reply(&self, requested: &[u8], relayed: &[u8]) -> Vec<u8> {
+        let mut reply = vec![SOCKS_VERSION, REPLY_SUCCEEDED, 0x00];
+        match self {
+            Self::Relay if is_address(relayed) => reply.extend_from_slice(relayed),
+            Self::Destination if is_address(requested) => reply.extend_from_slice(requested),
+            Self::Placeholder(addr) => push_socket_addr(&mut reply, *addr),
+            Self::Relay | Self::Destination | Self::Zero => {
+                let zero = if requested.first() == Some(&ATYP_IPV6) {
+                    IpAddr::V6(Ipv6Addr::UNSPECIFIED)
+                } else {
+                    IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+                };
+                push_socket_addr(&mut reply, SocketAddr::new(zero, 0));
+            }
+        }
+        reply
+    }
+}
+
+/// Whether `bytes` is exactly one ATYP, address and port.
+fn is_address(bytes: &[u8]) -> bool {
+    let expected = match bytes.first() {
+        Some(&ATYP_IPV4) => 1 + 4 + 2,
+        Some(&ATYP_IPV6) => 1 + 16 + 2,
+        Some(&ATYP_DOMAIN) => match bytes.get(1) {
+            Some(&len) if len > 0 => 2 + len as usize + 2,
+            _ => return false,
+        },
+        _ => return false,
+    };
+    bytes.len() == expected
+}
+
+fn push_socket_addr(reply: &mut Vec<u8>, addr: SocketAddr) {
+    match addr.ip() {
+        IpAddr::V4(ip) => {
+            reply.push(ATYP_IPV4);
+            reply.extend_from_slice(&ip.octets());
+        }
+        IpAddr::V6(ip) => {
+            reply.push(ATYP_IPV6);
+            reply.extend_from_slice(&ip.octets());
+        }
+    }
+    reply.extend_from_slice(&addr.port().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ipv4_request() -> Vec<u8> {
+        vec![ATYP_IPV4, 93, 184, 215, 14, 0x01, 0xbb]
+    }
+
+    fn ipv6_request() -> Vec<u8> {
+        let mut request = vec![ATYP_IPV6];
+        request.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        request.extend_from_slice(&[0x01, 0xbb]);
+        request
+    }
+
+    fn domain_request() -> Vec<u8> {
+        let mut request = vec![ATYP_DOMAIN, 11];
+        request.extend_from_slice(b"example.com");
+        request.extend_from_slice(&[0x00, 0x50]);
+        request
+    }
+
+    /// What a remote proxy typically reports: its own outbound address.
+    fn relayed() -> Vec<u8> {
+        vec![ATYP_IPV4, 10, 0, 0, 7, 0xd4, 0x31]
+    }
+
+    fn assert_well_formed(reply: &[u8]) {
+        assert_eq!(&reply[..3], [SOCKS_VERSION, REPLY_SUCCEEDED, 0x00]);
+        assert!(is_address(&reply[3..]), "malformed address in {:?}", reply);
+    }
+
+    #[test]
+    fn every_policy_answers_every_address_type_with_a_well_formed_reply() {
+        let policies = [
+            BoundAddressPolicy::Relay,
+            BoundAddressPolicy::Zero,
+            BoundAddressPolicy::Destination,
+            BoundAddressPolicy::Placeholder("127.0.0.1:1080".parse().unwrap()),
+            BoundAddressPolicy::Placeholder("[::1]:1080".parse().unwrap()),
+        ];
+        for policy in policies {
+            for request in [ipv4_request(), ipv6_request(), domain_request()] {
+                let reply = policy.connect_reply(&request, &relayed());
+                assert_well_formed(&reply);
+                match policy {
+                    BoundAddressPolicy::Relay => assert_eq!(reply[3..], relayed()),
+                    BoundAddressPolicy::Destination => assert_eq!(reply[3..], request),
+                    BoundAddressPolicy::Zero | BoundAddressPolicy::Placeholder(_) => {}
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn zeroed_addresses_follow_the_requested_family() {
+        let zero = BoundAddressPolicy::Zero;
+        assert_eq!(
+            zero.connect_reply(&ipv4_request(), &relayed()),
+            [5, 0, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0]
+        );
+        let reply = zero.connect_reply(&ipv6_request(), &relayed());
+        assert_eq!(reply[3], ATYP_IPV6);
+        assert!(reply[4..].iter().all(|byte| *byte == 0));
+        assert_eq!(reply.len(), 3 + 1 + 16 + 2);
+        assert_eq!(
+            zero.connect_reply(&domain_request(), &relayed()),
+            [5, 0, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn placeholders_are_encoded_in_their_own_family() {
+        let policy: BoundAddressPolicy = "[::1]:1080".parse().unwrap();
+        let reply = policy.connect_reply(&ipv4_request(), &relayed());
+        let mut expected = vec![5, 0, 0, ATYP_IPV6];
+        expected.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        expected.extend_from_slice(&1080u16.to_be_bytes());
+        assert_eq!(reply, expected);
+    }
+
+    #[test]
+    fn malformed_addresses_are_zeroed_instead_of_relayed() {
+        for bad in [
+            vec![],
+            vec![ATYP_IPV4, 10, 0, 0],
+            vec![ATYP_DOMAIN, 0, 0, 80],
+            vec![ATYP_DOMAIN, 5, b'a', 0, 80],
+            vec![0x02, 1, 2, 3, 4, 0, 80],
+        ] {
+            let reply = BoundAddressPolicy::Relay.connect_reply(&ipv4_request(), &bad);
+            assert_eq!(reply, [5, 0, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0]);
+            let reply = BoundAddressPolicy::Destination.connect_reply(&bad, &relayed());
+            assert_well_formed(&reply);
+        }
+    }
+
+    #[test]
+    fn parses_policy_names_and_placeholders() {
+        assert_eq!("Relay".parse(), Ok(BoundAddressPolicy::Relay));
+        assert_eq!(" zero ".parse(), Ok(BoundAddressPolicy::Zero));
+        assert_eq!("destination".parse(), Ok(BoundAddressPolicy::Destination));
+        assert_eq!(
+            "192.0.2.1:0".parse(),
+            Ok(BoundAddressPolicy::Placeholder(
+                "192.0.2.1:0".parse().unwrap()
+            ))
+        );
+        assert!("192.0.2.1".parse::<BoundAddressPolicy>().is_err());
+        assert!("echo".parse::<BoundAddressPolicy>().is_err());
+    }
+}
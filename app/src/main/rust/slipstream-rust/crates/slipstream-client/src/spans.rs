@@ -0,0 +1,184 @@
+//! Tracing spans around the tunnel's main operations.
+//!
+//! Every span uses the `slipstream::spans` target, so a subscriber can pick
+//! them out for a tracing backend without the log lines; with the `otel`
+//! feature the client exports them over OTLP (see docs/config.md for the span
+//! names and attributes). Spans are held, never entered, and close when the
+//! operation ends. Disabled spans cost a level check and nothing is kept.
+
+use crate::redact::redact;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use tracing::field::{debug, Empty};
+use tracing::Span;
+
+pub const SPAN_TARGET: &str = "slipstream::spans";
+
+// Open query spans kept per resolver; the oldest are closed unsettled.
+const TRACKED_QUERY_SPANS: usize = 256;
+
+/// One QUIC connection attempt, from creating the connection until it was
+/// confirmed ready or given up.
+pub(crate) fn handshake_span(session: &str, resolvers: usize) -> Span {
+    tracing::debug_span!(
+        target: SPAN_TARGET,
+        "handshake",
+        session = %session,
+        resolvers,
+        outcome = Empty,
+    )
+}
+
+/// One local TCP connection carried as a QUIC stream.
+pub(crate) fn stream_span(stream_id: u64) -> Span {
+    tracing::debug_span!(
+        target: SPAN_TARGET,
+        "stream",
+        stream_id,
+        bytes_sent = Empty,
+        bytes_received = Empty,
+    )
+}
+
+/// Closes a stream's span with what it carried.
+pub(crate) fn finish_stream_span(span: &Span, bytes_sent: u64, bytes_received: u64) {
+    span.record("bytes_sent", bytes_sent);
+    span.record("bytes_received", bytes_received);
+}
+
+/// Open `dns_query` spans of one resolver, from send until answered or past
+/// the response deadline.
+pub(crate) struct QuerySpans {
+    open: VecDeque<(u16, u64, Span)>,
+}
+
+impl QuerySpans {
+    pub(crate) fn new() -> Self {
+        Self {
+            open: VecDeque::new(),
+        }
+    }
+
+    /// `kind` is `data`, `poll` or `redundant`.
+    pub(crate) fn start(&mut self, id: u16, now: u64, resolver: SocketAddr, kind: &'static str) {
+        let span = tracing::trace_span!(
+            target: SPAN_TARGET,
+            "dns_query",
+            resolver = %redact(resolver),
+            query_id = id,
+            kind,
+            outcome = Empty,
+            rcode = Empty,
+        );
+        if span.is_disabled() {
+            return;
+        }
+        if self.open.len() == TRACKED_QUERY_SPANS {
+            self.open.pop_front();
+        }
+        self.open.push_back((id, now, span));
+    }
+
+    pub(crate) fn answered(&mut self, id: u16, rcode: impl std::fmt::Debug) {
+        if let Some(index) = self.open.iter().position(|(open, _, _)| *open == id) {
+            if let Some((_, _, span)) = self.open.remove(index) {
+                span.record("outcome", "answered");
+                span.record("rcode", debug(rcode));
+            }
+        }
+    }
+
+    /// Closes the spans of queries unanswered for `deadline_us`.
+    pub(crate) fn expire(&mut self, now: u64, deadline_us: u64) {
+        while self
+            .open
+            .front()
+            .is_some_and(|(_, sent_at, _)| now.saturating_sub(*sent_at) >= deadline_us)
+        {
+            if let Some((_, _, span)) = self.open.pop_front() {
+                span.record("outcome", "timeout");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::Subscriber;
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+
+    /// Collects each closed span as its name and `name=value` fields.
+    #[derive(Clone, Default)]
+    struct ClosedSpans(Arc<Mutex<Vec<String>>>);
+
+    struct Fields(String);
+
+    impl tracing::field::Visit for Fields {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for ClosedSpans {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let mut fields = Fields(String::new());
+            attrs.record(&mut fields);
+            ctx.span(id).unwrap().extensions_mut().insert(fields);
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            let mut extensions = span.extensions_mut();
+            values.record(extensions.get_mut::<Fields>().unwrap());
+        }
+
+        fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+            let span = ctx.span(&id).unwrap();
+            let fields = span.extensions().get::<Fields>().unwrap().0.clone();
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{}{}", span.name(), fields));
+        }
+    }
+
+    #[test]
+    fn query_spans_close_when_answered_or_expired() {
+        let closed = ClosedSpans::default();
+        let subscriber = tracing_subscriber::registry().with(closed.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let resolver: SocketAddr = "192.0.2.1:53".parse().unwrap();
+            let mut spans = QuerySpans::new();
+            spans.start(1, 0, resolver, "data");
+            spans.start(2, 100, resolver, "poll");
+            spans.answered(2, "NoError");
+            spans.expire(1_000, 500);
+            assert!(spans.open.is_empty());
+
+            let stream = stream_span(4);
+            finish_stream_span(&stream, 10, 20);
+        });
+        let closed = closed.0.lock().unwrap();
+        assert_eq!(closed.len(), 3);
+        assert!(closed[0].starts_with("dns_query"));
+        assert!(closed[0].contains(" query_id=2 kind=\"poll\""));
+        assert!(closed[0].ends_with(" outcome=\"answered\" rcode=\"NoError\""));
+        assert!(closed[1].contains(" query_id=1 "));
+        assert!(closed[1].ends_with(" outcome=\"timeout\""));
+        assert_eq!(
+            closed[2],
+            "stream stream_id=4 bytes_sent=10 bytes_received=20"
+        );
+    }
+
+    #[test]
+    fn disabled_spans_keep_nothing() {
+        let mut spans = QuerySpans::new();
+        spans.start(1, 0, "192.0.2.1:53".parse().unwrap(), "data");
+        assert!(spans.open.is_empty());
+    }
+}
@@ -0,0 +1,1210 @@
+//! Optional HTTP status endpoint for long-running clients.
+//!
+//! Serves the published session snapshot as JSON on `/` and as Prometheus text
+//! on `/metrics`. It only reads the snapshot, so a slow scraper never stalls the
+//! DNS event loop.
+//!
+//! With control enabled, which requires a loopback bind, `POST /stop` also asks
+//! the client to shut down through the same flag the Android bridge sets,
+//! `POST /resolvers` pins or adds resolvers and `POST /loglevel` replaces the
+//! log filter, through the same functions the Android bridge calls.
+//! Control requests must carry an `X-Slipstream-Control` header: browsers do
+//! not send custom headers cross-site without a CORS preflight, which the
+//! server never answers.
+//!
+//! That does not stop DNS rebinding, where a page's own name resolves to the
+//! status host and its requests count as same-origin. Every request must
+//! therefore name the endpoint by `localhost` or an IP address in its `Host`
+//! header; a rebound page always sends its own DNS name there.
+//!
+//! A status host of the form `unix:/path` serves the same endpoints on a Unix
+//! domain socket instead, readable and writable by the owner only.
+
+use crate::dns::{ResolverHealthState, RESPONSE_TIME_BUCKETS_MS};
+use crate::error::ClientError;
+use crate::loglevel::set_log_level;
+use crate::runtime::{append_resolvers, force_resolver, request_shutdown};
+use crate::session::{
+    session_snapshot, CongestionState, DirectionStats, ResolverInfo, SessionInfo,
+};
+use serde::Deserialize;
+use slipstream_core::{parse_host_port, AddressKind};
+use slipstream_ffi::{ResolverMode, ResolverSpec};
+use std::fmt::Write as _;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener as TokioTcpListener, UnixListener};
+use tokio::time::{timeout, Duration};
+use tracing::{debug, info};
+
+pub const DEFAULT_STATUS_HOST: &str = "127.0.0.1";
+
+const STATUS_REQUEST_MAX_BYTES: usize = 4096;
+const STATUS_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Prefix of a status host naming a Unix socket path.
+const UNIX_STATUS_PREFIX: &str = "unix:";
+/// Only the client's own user may connect to a Unix status socket.
+const UNIX_SOCKET_MODE: u32 = 0o600;
+/// Header every control request must carry, with any value.
+const CONTROL_HEADER: &str = "x-slipstream-control";
+
+/// Where the status endpoint listens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StatusListen<'a> {
+    Tcp(&'a str, u16),
+    Unix(&'a Path),
+}
+
+impl<'a> StatusListen<'a> {
+    /// A `unix:/path` host is served without a port; a network host only
+    /// with one.
+    pub(crate) fn from_config(host: &'a str, port: Option<u16>) -> Option<Self> {
+        match unix_status_path(host) {
+            Some(path) => Some(Self::Unix(Path::new(path))),
+            None => port.map(|port| Self::Tcp(host, port)),
+        }
+    }
+}
+
+/// The socket path of a `unix:/path` status host.
+pub(crate) fn unix_status_path(host: &str) -> Option<&str> {
+    host.strip_prefix(UNIX_STATUS_PREFIX)
+}
+
+/// Action a control request asks for, run once its response is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ControlAction {
+    Stop,
+}
+
+/// Body of `POST /resolvers`: pin the resolver at `pin` for `queries`
+/// queries, as `force_resolver` does, or add the `add` resolvers after the
+/// configured ones.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ResolversRequest {
+    pin: Option<usize>,
+    queries: Option<u64>,
+    add: Vec<String>,
+}
+
+pub(crate) async fn spawn_status_server(
+    listen: StatusListen<'_>,
+    control: bool,
+) -> Result<(), ClientError> {
+    let (host, port) = match listen {
+        StatusListen::Tcp(host, port) => (host, port),
+        StatusListen::Unix(path) => return spawn_unix_status_server(path, control),
+    };
+    let listener = TokioTcpListener::bind((host, port)).await.map_err(|err| {
+        ClientError::new(format!(
+            "Failed to bind status endpoint on {}:{}: {}",
+            host, port, err
+        ))
+    })?;
+    let local_addr = listener
+        .local_addr()
+        .map_err(|err| ClientError::new(err.to_string()))?;
+    if control && !local_addr.ip().is_loopback() {
+        return Err(ClientError::new(format!(
+            "Status control endpoints need a loopback --status-host, not {}",
+            local_addr.ip()
+        )));
+    }
+    info!(
+        "Status endpoint listening on http://{}{}",
+        local_addr,
+        if control { " (control enabled)" } else { "" }
+    );
+    tokio::spawn(run_status_server(listener, control));
+    Ok(())
+}
+
+/// Local users reach a Unix socket only through its file mode, so control
+/// needs no further check there.
+fn spawn_unix_status_server(path: &Path, control: bool) -> Result<(), ClientError> {
+    let bind_error = |err: io::Error| {
+        ClientError::new(format!(
+            "Failed to bind status endpoint on {}{}: {}",
+            UNIX_STATUS_PREFIX,
+            path.display(),
+            err
+        ))
+    };
+    remove_stale_socket(path).map_err(bind_error)?;
+    let listener = UnixListener::bind(path).map_err(bind_error)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(UNIX_SOCKET_MODE))
+        .map_err(bind_error)?;
+    info!(
+        "Status endpoint listening on {}{}{}",
+        UNIX_STATUS_PREFIX,
+        path.display(),
+        if control { " (control enabled)" } else { "" }
+    );
+    tokio::spawn(run_unix_status_server(listener, control));
+    Ok(())
+}
+
+/// A socket left behind by an earlier run would make the bind fail; any other
+/// file at the path is left alone.
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "the path exists and is not a socket",
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+async fn run_status_server(listener: TokioTcpListener, control: bool) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(serve_status_request(stream, control));
+            }
+            Err(err) => {
+                debug!("Status endpoint accept failed: {}", err);
+            }
+        }
+    }
+}
+
+async fn run_unix_status_server(listener: UnixListener, control: bool) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(serve_status_request(stream, control));
+            }
+            Err(err) => {
+                debug!("Status endpoint accept failed: {}", err);
+            }
+        }
+    }
+}
+
+async fn serve_status_request<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, control: bool) {
+    let mut buf = vec![0u8; STATUS_REQUEST_MAX_BYTES];
+    let mut len = 0usize;
+    let read = timeout(STATUS_REQUEST_TIMEOUT, async {
+        while len < buf.len() {
+            let n = stream.read(&mut buf[len..]).await?;
+            if n == 0 {
+                break;
+            }
+            len += n;
+            if request_complete(&buf[..len]) {
+                break;
+            }
+        }
+        Ok::<(), std::io::Error>(())
+    })
+    .await;
+    if !matches!(read, Ok(Ok(()))) {
+        return;
+    }
+    let (response, action) = status_response(&buf[..len], session_snapshot(), control);
+    if let Err(err) = stream.write_all(&response).await {
+        debug!("Status endpoint write failed: {}", err);
+    }
+    let _ = stream.shutdown().await;
+    if action == Some(ControlAction::Stop) {
+        info!("Stop requested through the status endpoint");
+        request_shutdown();
+    }
+}
+
+fn status_response(
+    request: &[u8],
+    snapshot: Option<SessionInfo>,
+    control: bool,
+) -> (Vec<u8>, Option<ControlAction>) {
+    let request = String::from_utf8_lossy(request);
+    let mut parts = request.lines().next().unwrap_or("").split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    let path = path.split('?').next().unwrap_or("");
+
+    if !local_host(&request) {
+        return (
+            http_response(
+                "403 Forbidden",
+                "text/plain",
+                "requests must use localhost or an IP address as Host\n",
+            ),
+            None,
+        );
+    }
+    if control && method == "POST" && matches!(path, "/stop" | "/resolvers" | "/loglevel") {
+        if !has_header(&request, CONTROL_HEADER) {
+            return (
+                http_response(
+                    "403 Forbidden",
+                    "text/plain",
+                    "control requests need an X-Slipstream-Control header\n",
+                ),
+                None,
+            );
+        }
+        let body = request.split_once("\r\n\r\n").map_or("", |(_, body)| body);
+        return match path {
+            "/stop" => (
+                http_response("202 Accepted", "text/plain", "stopping\n"),
+                Some(ControlAction::Stop),
+            ),
+            "/resolvers" => (control_resolvers(body), None),
+            _ => (control_log_level(body), None),
+        };
+    }
+    if method != "GET" {
+        return (
+            http_response(
+                "405 Method Not Allowed",
+                "text/plain",
+                "method not allowed\n",
+            ),
+            None,
+        );
+    }
+    let response = match path {
+        "/" | "/status" => match snapshot {
+            Some(info) => match serde_json::to_string(&info) {
+                Ok(body) => http_response("200 OK", "application/json", &body),
+                Err(_) => http_response("500 Internal Server Error", "text/plain", "error\n"),
+            },
+            None => http_response("503 Service Unavailable", "text/plain", "not running\n"),
+        },
+        "/metrics" => match snapshot {
+            Some(info) => http_response(
+                "200 OK",
+                "text/plain; version=0.0.4",
+                &render_metrics(&info),
+            ),
+            None => http_response("503 Service Unavailable", "text/plain", "not running\n"),
+        },
+        _ => http_response("404 Not Found", "text/plain", "not found\n"),
+    };
+    (response, None)
+}
+
+fn control_resolvers(body: &str) -> Vec<u8> {
+    let request = match serde_json::from_str::<ResolversRequest>(body) {
+        Ok(request) => request,
+        Err(err) => return bad_request(&format!("Invalid resolvers request: {}", err)),
+    };
+    match (request.pin, request.add.is_empty()) {
+        (Some(index), true) => {
+            let queries = request.queries.unwrap_or(1);
+            match force_resolver(index, queries) {
+                Ok(()) => {
+                    info!(
+                        "Resolver {} pinned for {} queries through the status endpoint",
+                        index, queries
+                    );
+                    http_response(
+                        "200 OK",
+                        "application/json",
+                        &format!("{{\"pinned\":{},\"queries\":{}}}", index, queries),
+                    )
+                }
+                Err(err) => control_failed(&err),
+            }
+        }
+        (None, false) if request.queries.is_none() => {
+            let mut resolvers = Vec::with_capacity(request.add.len());
+            for entry in &request.add {
+                match parse_host_port(entry, 53, AddressKind::Resolver) {
+                    Ok(resolver) => resolvers.push(ResolverSpec {
+                        resolver,
+                        mode: ResolverMode::Recursive,
+                    }),
+                    Err(err) => return bad_request(&err.to_string()),
+                }
+            }
+            match append_resolvers(&resolvers) {
+                Ok(report) => {
+                    info!(
+                        "Added {} resolvers through the status endpoint",
+                        resolvers.len()
+                    );
+                    match serde_json::to_string(&report) {
+                        Ok(body) => http_response("200 OK", "application/json", &body),
+                        Err(_) => {
+                            http_response("500 Internal Server Error", "text/plain", "error\n")
+                        }
+                    }
+                }
+                Err(err) => control_failed(&err),
+            }
+        }
+        _ => bad_request("Expected {\"pin\":INDEX,\"queries\":COUNT} or {\"add\":[RESOLVER, ...]}"),
+    }
+}
+
+fn control_log_level(body: &str) -> Vec<u8> {
+    let level = body.trim();
+    match set_log_level(level) {
+        Ok(()) => {
+            info!("Log level set to {} through the status endpoint", level);
+            http_response("200 OK", "text/plain", "ok\n")
+        }
+        Err(err) if level.is_empty() || err.to_string().starts_with("Invalid") => {
+            bad_request(&err.to_string())
+        }
+        Err(err) => control_failed(&err),
+    }
+}
+
+fn bad_request(message: &str) -> Vec<u8> {
+    http_response("400 Bad Request", "text/plain", &format!("{}\n", message))
+}
+
+/// The client refused the request, e.g. because none is running.
+fn control_failed(err: &ClientError) -> Vec<u8> {
+    http_response("409 Conflict", "text/plain", &format!("{}\n", err))
+}
+
+/// Whether `buf` holds the headers and, going by `Content-Length`, the whole
+/// body of a request.
+fn request_complete(buf: &[u8]) -> bool {
+    let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") else {
+        return false;
+    };
+    let headers = String::from_utf8_lossy(&buf[..end]);
+    let content_length = headers
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(header, _)| header.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    buf.len() >= end + 4 + content_length
+}
+
+/// Whether the request's headers include `name`, matched case-insensitively.
+fn has_header(request: &str, name: &str) -> bool {
+    request
+        .lines()
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .any(|(header, _)| header.trim().eq_ignore_ascii_case(name))
+}
+
+/// Whether the `Host` header names `localhost` or an IP address, with an
+/// optional port.
+fn local_host(request: &str) -> bool {
+    let Some(host) = request
+        .lines()
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .find(|(header, _)| header.trim().eq_ignore_ascii_case("host"))
+        .map(|(_, value)| value.trim())
+    else {
+        return false;
+    };
+    let name = if let Some(rest) = host.strip_prefix('[') {
+        match rest.split_once(']') {
+            Some((addr, port))
+                if port.is_empty() || port.strip_prefix(':').is_some_and(valid_port) =>
+            {
+                return addr.parse::<Ipv6Addr>().is_ok();
+            }
+            _ => return false,
+        }
+    } else {
+        match host.rsplit_once(':') {
+            Some((name, port)) if valid_port(port) => name,
+            Some(_) => return false,
+            None => host,
+        }
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<Ipv4Addr>().is_ok()
+}
+
+fn valid_port(port: &str) -> bool {
+    port.parse::<u16>().is_ok()
+}
+
+fn http_response(status: &str, content_type: &str, body: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+    .into_bytes()
+}
+
+/// Renders the session snapshot in the Prometheus text exposition format.
+pub(crate) fn render_metrics(info: &SessionInfo) -> String {
+    let mut out = String::new();
+    write_metric(
+        &mut out,
+        "slipstream_connected",
+        "gauge",
+        "Whether the QUIC tunnel is established.",
+        &[("", info.connected as u64 as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_scheduled_pause",
+        "gauge",
+        "Whether the tunnel is paused outside its active windows.",
+        &[("", info.scheduled_pause as u64 as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_network_offline",
+        "gauge",
+        "Whether the device has no network and the client waits for one.",
+        &[("", info.network_offline as u64 as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_reconnects_total",
+        "counter",
+        "Reconnect attempts since the client started.",
+        &[("", info.reconnects as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_doze_wakeups_total",
+        "counter",
+        "Wake-ups after the device slept, detected from clock divergence.",
+        &[("", info.doze_wakeups as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_local_read_pauses_total",
+        "counter",
+        "Local TCP reads paused because a stream's tunnel queue was full.",
+        &[("", info.local_read_pauses as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_tunnel_send_blocked_total",
+        "counter",
+        "Times queued stream data was held back by QUIC flow control.",
+        &[("", info.tunnel_send_blocked as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_buffer_cap_drops_total",
+        "counter",
+        "Streams whose incoming tunnel data was dropped at the receive buffer cap.",
+        &[("", info.buffer_cap_drops as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_bulk_window_bytes",
+        "gauge",
+        "Congestion window of bulk streams under congestion isolation.",
+        &info
+            .bulk_window_bytes
+            .map(|bytes| ("", bytes as f64))
+            .into_iter()
+            .collect::<Vec<_>>(),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_bulk_held_back_total",
+        "counter",
+        "Times bulk stream data was held back by its congestion window.",
+        &[("", info.bulk_held_back as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_reassembly_bytes",
+        "gauge",
+        "Received stream data not yet written to local sockets, gaps included.",
+        &[("", info.reassembly_bytes as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_reassembly_limit_bytes",
+        "gauge",
+        "Cap on received stream data not yet written to local sockets.",
+        &[("", info.reassembly_limit_bytes as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_active_streams",
+        "gauge",
+        "Tunneled TCP connections currently open.",
+        &[("", info.active_streams as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_connection_setups",
+        "gauge",
+        "Accepted local connections waiting to be set up as streams.",
+        &[("", info.connection_setups as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_connection_setups_refused_total",
+        "counter",
+        "Local connections refused because the setup limit was reached.",
+        &[("", info.connection_setups_refused as f64)],
+    );
+    if let Some(latency_ms) = info.internal_queue_latency_ms {
+        write_metric(
+            &mut out,
+            "slipstream_internal_queue_latency_seconds",
+            "gauge",
+            "Wait from accepting a TCP connection to the first DNS query carrying its data.",
+            &[("", latency_ms as f64 / 1_000.0)],
+        );
+    }
+    write_metric(
+        &mut out,
+        "slipstream_poll_interval_seconds",
+        "gauge",
+        "Gap between polls while idle polling spaces them out; 0 at full rate.",
+        &[("", info.poll_interval_ms as f64 / 1_000.0)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_max_query_bytes",
+        "gauge",
+        "Largest DNS query sent on the wire, polls included.",
+        &[("", info.max_query_bytes as f64)],
+    );
+
+    write_metric(
+        &mut out,
+        "slipstream_packets_sent_total",
+        "counter",
+        "QUIC packets sent on all paths.",
+        &[("", info.packets_sent as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_packets_retransmitted_total",
+        "counter",
+        "QUIC packets declared lost and retransmitted on all paths.",
+        &[("", info.packets_retransmitted as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_spurious_retransmits_total",
+        "counter",
+        "Retransmissions that turned out to be unnecessary.",
+        &[("", info.spurious_retransmits as f64)],
+    );
+    write_metric(
+        &mut out,
+        "slipstream_retransmit_ratio",
+        "gauge",
+        "Retransmitted packets over packets sent.",
+        &[("", info.retransmit_ratio)],
+    );
+    let directions = [
+        ("direction=\"up\"", &info.upstream),
+        ("direction=\"down\"", &info.downstream),
+    ];
+    let per_direction = |value: fn(&DirectionStats) -> f64| {
+        directions
+            .iter()
+            .map(|(labels, stats)| (*labels, value(stats)))
+            .collect::<Vec<_>>()
+    };
+    write_metric(
+        &mut out,
+        "slipstream_tunnel_wire_bytes_total",
+        "counter",
+        "DNS bytes on the wire: queries up, responses down.",
+        &per_direction(|stats| stats.wire_bytes as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_tunnel_payload_bytes_total",
+        "counter",
+        "QUIC bytes carried inside those DNS messages.",
+        &per_direction(|stats| stats.payload_bytes as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_tunnel_payload_bytes_per_message",
+        "gauge",
+        "QUIC bytes carried per query (up) or per response (down).",
+        &per_direction(|stats| stats.payload_bytes_per_message),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_efficiency_ratio",
+        "gauge",
+        "Stream bytes per DNS wire byte over the last 30s.",
+        &info
+            .efficiency_ratio
+            .map(|ratio| ("", ratio))
+            .into_iter()
+            .collect::<Vec<_>>(),
+    );
+
+    let labels: Vec<String> = info
+        .resolvers
+        .iter()
+        .map(|resolver| {
+            format!(
+                "resolver=\"{}\",mode=\"{}\"",
+                escape_label(&resolver.addr),
+                escape_label(&resolver.mode)
+            )
+        })
+        .collect();
+    let per_resolver = |value: fn(&ResolverInfo) -> f64| {
+        labels
+            .iter()
+            .zip(info.resolvers.iter())
+            .map(|(labels, resolver)| (labels.as_str(), value(resolver)))
+            .collect::<Vec<_>>()
+    };
+    write_metric(
+        &mut out,
+        "slipstream_resolver_queries_total",
+        "counter",
+        "DNS queries sent to the resolver.",
+        &per_resolver(|resolver| resolver.queries_sent as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_sent_bytes_total",
+        "counter",
+        "QUIC bytes carried in queries to the resolver.",
+        &per_resolver(|resolver| resolver.bytes_sent as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_received_bytes_total",
+        "counter",
+        "QUIC bytes carried in responses from the resolver.",
+        &per_resolver(|resolver| resolver.bytes_received as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_responses_total",
+        "counter",
+        "DNS responses received from the resolver.",
+        &per_resolver(|resolver| resolver.responses as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_lost_packets_total",
+        "counter",
+        "QUIC packets declared lost on the resolver path.",
+        &per_resolver(|resolver| resolver.lost_packets as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_packets_sent_total",
+        "counter",
+        "QUIC packets sent on the resolver path.",
+        &per_resolver(|resolver| resolver.packets_sent as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_rtt_seconds",
+        "gauge",
+        "Smoothed RTT of the resolver path.",
+        &per_resolver(|resolver| resolver.rtt_us as f64 / 1_000_000.0),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_query_loss_ratio",
+        "gauge",
+        "Share of recent DNS queries to the resolver left unanswered.",
+        &labels
+            .iter()
+            .zip(info.resolvers.iter())
+            .filter_map(|(labels, resolver)| Some((labels.as_str(), resolver.query_loss_rate?)))
+            .collect::<Vec<_>>(),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_learned_qps",
+        "gauge",
+        "Queries per second the resolver was found to tolerate.",
+        &labels
+            .iter()
+            .zip(info.resolvers.iter())
+            .filter_map(|(labels, resolver)| Some((labels.as_str(), resolver.learned_qps?)))
+            .collect::<Vec<_>>(),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_queries_held_back_total",
+        "counter",
+        "Queries held back or dropped to stay under the learned query rate.",
+        &per_resolver(|resolver| resolver.queries_held_back as f64),
+    );
+    // Only resolvers whose path exists have a controller to report.
+    let per_congestion = |value: fn(&CongestionState) -> f64| {
+        labels
+            .iter()
+            .zip(info.resolvers.iter())
+            .filter_map(|(labels, resolver)| {
+                let congestion = resolver.congestion.as_ref()?;
+                Some((labels.as_str(), value(congestion)))
+            })
+            .collect::<Vec<_>>()
+    };
+    write_metric(
+        &mut out,
+        "slipstream_resolver_cwnd_bytes",
+        "gauge",
+        "Congestion window of the resolver path.",
+        &per_congestion(|congestion| congestion.cwnd_bytes as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_pacing_rate_bytes",
+        "gauge",
+        "Pacing rate of the resolver path, in bytes per second.",
+        &per_congestion(|congestion| congestion.pacing_rate_bytes_per_sec as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_poll_timeouts_total",
+        "counter",
+        "Polls that passed the response deadline and were replaced.",
+        &per_resolver(|resolver| resolver.poll_timeouts as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_late_poll_responses_total",
+        "counter",
+        "Timed-out polls that were answered after all (spurious poll retransmits).",
+        &per_resolver(|resolver| resolver.late_poll_responses as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_non_tunnel_responses_total",
+        "counter",
+        "Answers that were ordinary records rather than tunnel data.",
+        &per_resolver(|resolver| resolver.non_tunnel_responses as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_servfail_storms_total",
+        "counter",
+        "SERVFAIL runs that paused the resolver for the cooldown.",
+        &per_resolver(|resolver| resolver.servfail_storms as f64),
+    );
+    write_metric(
+        &mut out,
+        "slipstream_resolver_asymmetric",
+        "gauge",
+        "1 when the resolver takes queries but has stopped answering while others still do.",
+        &per_resolver(|resolver| resolver.asymmetric as u64 as f64),
+    );
+
+    let mut buckets = Vec::new();
+    for (labels, resolver) in labels.iter().zip(info.resolvers.iter()) {
+        let histogram = &resolver.response_time;
+        let mut cumulative = 0u64;
+        for (idx, count) in histogram.counts.iter().enumerate() {
+            cumulative = cumulative.saturating_add(*count);
+            let le = match RESPONSE_TIME_BUCKETS_MS.get(idx) {
+                Some(bound) => format!("{}", *bound as f64 / 1_000.0),
+                None => "+Inf".to_string(),
+            };
+            buckets.push((
+                format!(
+                    "slipstream_resolver_response_time_seconds_bucket{{{},le=\"{}\"}}",
+                    labels, le
+                ),
+                cumulative as f64,
+            ));
+        }
+        buckets.push((
+            format!(
+                "slipstream_resolver_response_time_seconds_sum{{{}}}",
+                labels
+            ),
+            histogram.sum_us as f64 / 1_000_000.0,
+        ));
+        buckets.push((
+            format!(
+                "slipstream_resolver_response_time_seconds_count{{{}}}",
+                labels
+            ),
+            histogram.count as f64,
+        ));
+    }
+    let _ = writeln!(
+        out,
+        "# HELP slipstream_resolver_response_time_seconds DNS query-to-response time per resolver."
+    );
+    let _ = writeln!(
+        out,
+        "# TYPE slipstream_resolver_response_time_seconds histogram"
+    );
+    for (series, value) in &buckets {
+        let _ = writeln!(out, "{} {}", series, value);
+    }
+
+    let mut states = Vec::new();
+    for (labels, resolver) in labels.iter().zip(info.resolvers.iter()) {
+        for (state, name) in [
+            (ResolverHealthState::Healthy, "healthy"),
+            (ResolverHealthState::Probation, "probation"),
+            (ResolverHealthState::Dead, "dead"),
+        ] {
+            states.push((
+                format!("{},state=\"{}\"", labels, name),
+                (resolver.state == state) as u64 as f64,
+            ));
+        }
+    }
+    let states: Vec<(&str, f64)> = states
+        .iter()
+        .map(|(labels, value)| (labels.as_str(), *value))
+        .collect();
+    write_metric(
+        &mut out,
+        "slipstream_resolver_state",
+        "gauge",
+        "Resolver health state (1 for the current state).",
+        &states,
+    );
+    out
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(&str, f64)]) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dns::ResponseTimeHistogram;
+    use crate::session::CongestionDetails;
+
+    fn sample_info() -> SessionInfo {
+        SessionInfo {
+            connected: true,
+            reconnects: 2,
+            internal_queue_latency_ms: Some(120),
+            resolvers: vec![ResolverInfo {
+                addr: "[2001:db8::1]:53".to_string(),
+                mode: "recursive".to_string(),
+                state: ResolverHealthState::Probation,
+                asymmetric: false,
+                servfail_storms: 0,
+                standby: false,
+                response_ttl: None,
+                poll_timeouts: 0,
+                late_poll_responses: 0,
+                non_tunnel_responses: 0,
+                refusing: false,
+                queries_sent: 10,
+                bytes_sent: 1200,
+                query_wire_bytes: 2600,
+                responses: 9,
+                tunnel_responses: 9,
+                response_wire_bytes: 4500,
+                bytes_received: 3600,
+                goodput_bytes_per_sec: Some(3_600),
+                lost_packets: 1,
+                packets_sent: 20,
+                spurious_losses: 0,
+                rtt_us: 250_000,
+                response_time: ResponseTimeHistogram {
+                    counts: [2, 0, 0, 1, 0, 0, 0, 0, 0, 1],
+                    count: 4,
+                    sum_us: 7_000_000,
+                },
+                query_loss_rate: Some(0.1),
+                learned_qps: Some(12.5),
+                queries_held_back: 4,
+                congestion: Some(CongestionState {
+                    algorithm: "dcubic".to_string(),
+                    cwnd_bytes: 15_000,
+                    bytes_in_transit: 4_000,
+                    pacing_rate_bytes_per_sec: 60_000,
+                    min_rtt_us: 180_000,
+                    details: CongestionDetails::Cubic {
+                        phase: "congestion_avoidance".to_string(),
+                        w_max_packets: Some(12),
+                    },
+                }),
+            }],
+            bulk_window_bytes: Some(6_144),
+            efficiency_ratio: Some(0.5),
+            ..SessionInfo::default()
+        }
+    }
+
+    #[test]
+    fn metrics_include_session_and_resolver_samples() {
+        let mut info = sample_info();
+        info.refresh_direction_stats();
+        let text = render_metrics(&info);
+        assert!(text.contains("# TYPE slipstream_connected gauge\nslipstream_connected 1\n"));
+        assert!(text.contains("slipstream_reconnects_total 2\n"));
+        assert!(text.contains("slipstream_internal_queue_latency_seconds 0.12\n"));
+        assert!(text.contains("slipstream_bulk_window_bytes 6144\n"));
+        assert!(text.contains("slipstream_efficiency_ratio 0.5\n"));
+        let labels = "resolver=\"[2001:db8::1]:53\",mode=\"recursive\"";
+        assert!(text.contains(&format!(
+            "slipstream_resolver_queries_total{{{}}} 10\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "slipstream_resolver_rtt_seconds{{{}}} 0.25\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "slipstream_resolver_cwnd_bytes{{{}}} 15000\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "slipstream_resolver_learned_qps{{{}}} 12.5\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "slipstream_resolver_state{{{},state=\"probation\"}} 1\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "slipstream_resolver_state{{{},state=\"dead\"}} 0\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "slipstream_resolver_response_time_seconds_bucket{{{},le=\"0.2\"}} 3\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "slipstream_resolver_response_time_seconds_bucket{{{},le=\"+Inf\"}} 4\n",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "slipstream_resolver_response_time_seconds_sum{{{}}} 7\n",
+            labels
+        )));
+        assert!(
+            text.contains("slipstream_tunnel_payload_bytes_per_message{direction=\"up\"} 120\n")
+        );
+        assert!(
+            text.contains("slipstream_tunnel_payload_bytes_per_message{direction=\"down\"} 400\n")
+        );
+    }
+
+    fn respond(request: &[u8], snapshot: Option<SessionInfo>) -> Vec<u8> {
+        status_response(request, snapshot, false).0
+    }
+
+    #[test]
+    fn status_routes_requests() {
+        let response = respond(
+            b"GET /metrics HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n",
+            Some(sample_info()),
+        );
+        let response = String::from_utf8(response).expect("utf8");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("slipstream_connected 1"));
+
+        let response = respond(
+            b"GET / HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n",
+            Some(sample_info()),
+        );
+        assert!(String::from_utf8_lossy(&response).contains("\"connected\":true"));
+
+        let response = respond(b"GET /metrics HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", None);
+        assert!(response.starts_with(b"HTTP/1.1 503"));
+        let response = respond(
+            b"GET /nope HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n",
+            Some(sample_info()),
+        );
+        assert!(response.starts_with(b"HTTP/1.1 404"));
+        let response = respond(
+            b"POST /metrics HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n",
+            Some(sample_info()),
+        );
+        assert!(response.starts_with(b"HTTP/1.1 405"));
+    }
+
+    #[tokio::test]
+    async fn unix_socket_serves_the_same_endpoints_to_its_owner() {
+        let dir = std::env::temp_dir().join(format!("slipstream-status-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("create temp dir");
+        let path = dir.join("status.sock");
+        let host = format!("unix:{}", path.display());
+        let listen = StatusListen::from_config(&host, None).expect("a unix listener");
+        assert_eq!(listen, StatusListen::Unix(&path));
+        spawn_status_server(listen, false).await.expect("bind");
+        // A restart replaces the socket the last run left behind.
+        spawn_status_server(listen, false).await.expect("rebind");
+        let mode = std::fs::metadata(&path)
+            .expect("socket")
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut stream = tokio::net::UnixStream::connect(&path)
+            .await
+            .expect("connect");
+        stream
+            .write_all(b"GET /nope HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .expect("write");
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await.expect("read");
+        assert!(response.starts_with(b"HTTP/1.1 404"));
+
+        // Anything but a socket at the path is not overwritten.
+        let file = dir.join("status.txt");
+        std::fs::write(&file, b"keep").expect("write file");
+        let host = format!("unix:{}", file.display());
+        let listen = StatusListen::from_config(&host, Some(8080)).expect("a unix listener");
+        assert!(spawn_status_server(listen, false).await.is_err());
+        assert_eq!(std::fs::read(&file).expect("file"), b"keep");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(StatusListen::from_config("127.0.0.1", None), None);
+    }
+
+    #[test]
+    fn stop_is_only_served_with_control_enabled() {
+        let request = b"POST /stop HTTP/1.1\r\nHost: 127.0.0.1\r\nX-Slipstream-Control: 1\r\n\r\n";
+        let (response, action) = status_response(request, Some(sample_info()), false);
+        assert!(response.starts_with(b"HTTP/1.1 405"));
+        assert_eq!(action, None);
+
+        let (response, action) = status_response(request, None, true);
+        assert!(response.starts_with(b"HTTP/1.1 202"));
+        assert_eq!(action, Some(ControlAction::Stop));
+        // What a cross-site form or fetch without a preflight can send.
+        let simple = b"POST /stop HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Type: text/plain\r\n\r\n";
+        let (response, action) = status_response(simple, None, true);
+        assert!(response.starts_with(b"HTTP/1.1 403"));
+        assert_eq!(action, None);
+        let lowercase =
+            b"POST /stop HTTP/1.1\r\nHost: 127.0.0.1\r\nx-slipstream-control: yes\r\n\r\n";
+        assert_eq!(
+            status_response(lowercase, None, true).1,
+            Some(ControlAction::Stop)
+        );
+        let (response, action) =
+            status_response(b"GET /stop HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n", None, true);
+        assert!(response.starts_with(b"HTTP/1.1 404"));
+        assert_eq!(action, None);
+    }
+
+    #[test]
+    fn requests_must_name_a_local_host() {
+        // What a rebound page sends: its own name, with the control header.
+        let request =
+            b"POST /stop HTTP/1.1\r\nHost: attacker.example\r\nX-Slipstream-Control: 1\r\n\r\n";
+        let (response, action) = status_response(request, None, true);
+        assert!(response.starts_with(b"HTTP/1.1 403"));
+        assert_eq!(action, None);
+        let response = respond(
+            b"GET / HTTP/1.1\r\nHost: attacker.example:8080\r\n\r\n",
+            None,
+        );
+        assert!(response.starts_with(b"HTTP/1.1 403"));
+        assert!(respond(b"GET / HTTP/1.1\r\n\r\n", None).starts_with(b"HTTP/1.1 403"));
+
+        for host in [
+            "127.0.0.1",
+            "127.0.0.1:8080",
+            "LOCALHOST:8080",
+            "[::1]",
+            "[::1]:8080",
+            "192.0.2.10:8080",
+        ] {
+            let request = format!("GET /metrics HTTP/1.1\r\nHost: {}\r\n\r\n", host);
+            let response = respond(request.as_bytes(), None);
+            assert!(response.starts_with(b"HTTP/1.1 503"), "{}", host);
+        }
+        for host in [
+            "localhost.attacker.example",
+            "127.0.0.1:http",
+            "[::1]8080",
+            "::1",
+        ] {
+            let request = format!("GET /metrics HTTP/1.1\r\nHost: {}\r\n\r\n", host);
+            let response = respond(request.as_bytes(), None);
+            assert!(response.starts_with(b"HTTP/1.1 403"), "{}", host);
+        }
+    }
+
+    fn control_post(path: &str, body: &str) -> Vec<u8> {
+        let request = format!(
+            "POST {} HTTP/1.1\r\nHost: [::1]:8080\r\nX-Slipstream-Control: 1\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            body.len(),
+            body
+        );
+        let (response, action) = status_response(request.as_bytes(), None, true);
+        assert_eq!(action, None);
+        response
+    }
+
+    #[test]
+    fn resolvers_and_log_level_requests_are_checked() {
+        // No client is running in tests.
+        let response = control_post("/resolvers", r#"{"pin":1,"queries":50}"#);
+        assert!(response.starts_with(b"HTTP/1.1 409"));
+        for body in [
+            "",
+            "{}",
+            r#"{"pin":"one"}"#,
+            r#"{"pin":1,"add":["192.0.2.1"]}"#,
+            r#"{"add":["192.0.2.1"],"queries":5}"#,
+            r#"{"add":["192.0.2.1:notaport"]}"#,
+            r#"{"resolver":"192.0.2.1"}"#,
+        ] {
+            let response = control_post("/resolvers", body);
+            assert!(response.starts_with(b"HTTP/1.1 400"), "{}", body);
+        }
+
+        assert!(control_post("/loglevel", "").starts_with(b"HTTP/1.1 400"));
+        assert!(control_post("/loglevel", "info,[=bad").starts_with(b"HTTP/1.1 400"));
+        // Logging is only set up by the binaries.
+        assert!(control_post("/loglevel", "debug\n").starts_with(b"HTTP/1.1 409"));
+
+        let request =
+            b"POST /loglevel HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 5\r\n\r\ndebug";
+        let (response, _) = status_response(request, None, true);
+        assert!(response.starts_with(b"HTTP/1.1 403"));
+    }
+
+    #[test]
+    fn requests_are_read_through_their_body() {
+        assert!(!request_complete(b"POST /loglevel HTTP/1.1\r\n"));
+        assert!(request_complete(b"GET / HTTP/1.1\r\n\r\n"));
+        assert!(!request_complete(
+            b"POST /loglevel HTTP/1.1\r\ncontent-length: 5\r\n\r\ndeb"
+        ));
+        assert!(request_complete(
+            b"POST /loglevel HTTP/1.1\r\ncontent-length: 5\r\n\r\ndebug"
+        ));
+    }
+}
@@ -1,3 +1,9 @@
+mod boost;
+mod interactive;
+mod isolation;
+
+use crate::compat::ServerIncompatible;
+use crate::spans::{finish_stream_span, stream_span};
 use slipstream_core::flow_control::{
     conn_reserve_bytes, consume_error_log_message, consume_stream_data, handle_stream_receive,
     overflow_log_message, promote_error_log_message, promote_streams, reserve_target_offset,
@@ -9,15 +15,29 @@ use slipstream_ffi::picoquic::{
     picoquic_add_to_stream, picoquic_call_back_event_t, picoquic_cnx_t, picoquic_current_time,
     picoquic_get_close_reasons, picoquic_get_cnx_state, picoquic_get_next_local_stream_id,
     picoquic_mark_active_stream, picoquic_provide_stream_data_buffer, picoquic_reset_stream,
-    picoquic_stop_sending, picoquic_stream_data_consumed,
+    picoquic_set_stream_priority, picoquic_stop_sending, picoquic_stream_data_consumed, session_id,
+};
+use slipstream_ffi::{
+    abort_stream_bidi, LocalClosePolicy, SLIPSTREAM_FILE_CANCEL_ERROR, SLIPSTREAM_INTERNAL_ERROR,
 };
-use slipstream_ffi::{abort_stream_bidi, SLIPSTREAM_FILE_CANCEL_ERROR, SLIPSTREAM_INTERNAL_ERROR};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream as TokioTcpStream;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{mpsc, oneshot, Notify};
-use tracing::{debug, error, info, warn};
+use tokio::time::{timeout_at, Duration, Instant};
+use tracing::{debug, error, info, warn, Span};
+
+pub use boost::DEFAULT_FIRST_STREAM_BOOST_MS;
+use boost::{FirstStreamBoost, BOOSTED_STREAM_PRIORITY, DEFAULT_STREAM_PRIORITY};
+use interactive::SendRate;
+use isolation::BulkWindow;
+
+/// Smallest accepted `reassembly_limit_bytes`; with less, the server stalls on
+/// flow control after every few queries.
+pub const MIN_REASSEMBLY_LIMIT_BYTES: u32 = 64 * 1024;
 
 const STREAM_READ_CHUNK_BYTES: usize = 4096;
 const DEFAULT_TCP_RCVBUF_BYTES: usize = 256 * 1024;
@@ -37,6 +57,39 @@ pub(crate) struct ClientState {
     debug_enqueued_bytes: u64,
     debug_last_enqueue_at: u64,
     acceptor_limit_logged: bool,
+    read_coalesce: ReadCoalesce,
+    local_close_policy: LocalClosePolicy,
+    server_incompatible: Option<ServerIncompatible>,
+    /// Accept times of streams whose first data is queued but not yet sent.
+    first_writes: Vec<Instant>,
+    backpressure: Arc<BackpressureCounters>,
+    first_stream_boost: FirstStreamBoost,
+    /// Demote streams that send in bulk so interactive ones go out first.
+    prioritize_interactive: bool,
+    /// Window of their own for bulk streams; `None` unless congestion isolation is on.
+    bulk_window: Option<BulkWindow>,
+    /// Bytes in flight on the paths at the last bulk window update, plus bulk
+    /// data handed to QUIC since.
+    bulk_in_transit: u64,
+    /// Stream bytes moved either way on this connection; idle polling watches it.
+    stream_bytes: u64,
+}
+
+/// Buffer caps hit by the stream layer, counted since the client started.
+#[derive(Debug, Default)]
+pub(crate) struct BackpressureCounters {
+    /// Local TCP reads held back because the stream's tunnel queue was full.
+    pub(crate) local_read_pauses: AtomicU64,
+    /// Streams whose tunnel data was dropped because the receive queue hit its cap.
+    pub(crate) buffer_cap_drops: AtomicU64,
+}
+
+/// Batches small TCP reads so chatty clients produce fewer DNS queries.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ReadCoalesce {
+    pub(crate) delay: Duration,
+    /// Flush as soon as this many bytes are buffered (one query's worth).
+    pub(crate) flush_bytes: usize,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -97,12 +150,15 @@ pub(crate) struct ClientBacklogSummary {
 pub(crate) mod acceptor {
     use super::Command;
     use slipstream_ffi::picoquic::{picoquic_cnx_t, slipstream_get_max_streams_bidir_remote};
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
     use std::sync::Arc;
     use tokio::net::TcpListener as TokioTcpListener;
-    use tokio::sync::{mpsc, Notify};
-    use tokio::time::{sleep, Duration};
-    use tracing::warn;
+    use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore};
+    use tokio::time::{sleep, timeout, Duration, Instant};
+    use tracing::{debug, warn};
+
+    /// How long an accepted connection may wait for a setup slot before it is refused.
+    const SETUP_WAIT: Duration = Duration::from_millis(500);
 
     #[derive(Clone)]
     /// Gate local TCP accepts on remote QUIC MAX_STREAMS credit.
@@ -112,13 +168,22 @@ pub(crate) mod acceptor {
     /// stale accepts never leak across reconnect boundaries.
     pub(crate) struct ClientAcceptor {
         limiter: Arc<AcceptorLimiter>,
+        setups: Arc<SetupLimiter>,
     }
 
     impl ClientAcceptor {
+        #[cfg(test)]
         pub(crate) fn new() -> Self {
+            Self::with_setup_limit(None)
+        }
+
+        /// `max_setups` caps connections accepted but not yet set up as streams;
+        /// `None` leaves them unbounded.
+        pub(crate) fn with_setup_limit(max_setups: Option<usize>) -> Self {
             let limit = initial_acceptor_limit();
             Self {
                 limiter: Arc::new(AcceptorLimiter::new(limit)),
+                setups: Arc::new(SetupLimiter::new(max_setups)),
             }
         }
 
@@ -127,7 +192,23 @@ pub(crate) mod acceptor {
             listener: TokioTcpListener,
             command_tx: mpsc::UnboundedSender<Command>,
         ) {
-            TcpAcceptor::new(listener, command_tx, Arc::clone(&self.limiter)).spawn();
+            TcpAcceptor::new(
+                listener,
+                command_tx,
+                Arc::clone(&self.limiter),
+                Arc::clone(&self.setups),
+            )
+            .spawn();
+        }
+
+        /// Connections accepted and waiting to be set up as streams.
+        pub(crate) fn setups_in_progress(&self) -> usize {
+            self.setups.in_progress()
+        }
+
+        /// Connections refused because no setup slot freed up in time.
+        pub(crate) fn setups_refused(&self) -> u64 {
+            self.setups.refused.load(Ordering::Relaxed)
         }
 
         pub(crate) fn update_limit(&self, cnx: *mut picoquic_cnx_t) -> usize {
@@ -150,6 +231,11 @@ pub(crate) mod acceptor {
         pub(crate) async fn reserve_for_test(&self) -> AcceptorReservation {
             self.limiter.reserve().await
         }
+
+        #[cfg(test)]
+        pub(crate) fn set_max_streams_for_test(&self, limit: usize) {
+            self.limiter.set_max(limit);
+        }
     }
 
     pub(super) fn initial_acceptor_limit() -> usize {
@@ -227,6 +313,7 @@ pub(crate) mod acceptor {
                             limiter: Arc::clone(self),
                             generation: current_generation,
                             committed: false,
+                            setup: None,
                         };
                     }
                     continue;
@@ -273,10 +360,73 @@ pub(crate) mod acceptor {
         }
     }
 
+    /// Bounds how many accepted connections wait for the runtime to set them up.
+    ///
+    /// The acceptor cap follows QUIC stream credit, which can be large; this one
+    /// keeps a local connection storm from piling up sockets in the command queue.
+    struct SetupLimiter {
+        slots: Option<Arc<Semaphore>>,
+        max: usize,
+        refused: AtomicU64,
+        in_progress: Arc<AtomicUsize>,
+    }
+
+    impl SetupLimiter {
+        fn new(max: Option<usize>) -> Self {
+            let max = max.map(|max| max.max(1));
+            Self {
+                slots: max.map(|max| Arc::new(Semaphore::new(max))),
+                max: max.unwrap_or(usize::MAX),
+                refused: AtomicU64::new(0),
+                in_progress: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        fn in_progress(&self) -> usize {
+            self.in_progress.load(Ordering::SeqCst)
+        }
+
+        /// Waits up to `SETUP_WAIT` for a slot; `None` means the connection is refused.
+        async fn admit(&self) -> Option<SetupSlot> {
+            let permit = match &self.slots {
+                Some(slots) => match timeout(SETUP_WAIT, Arc::clone(slots).acquire_owned()).await {
+                    Ok(Ok(permit)) => Some(permit),
+                    _ => {
+                        self.refused.fetch_add(1, Ordering::Relaxed);
+                        debug!(
+                            "acceptor: {} connection setups in progress; refusing connection",
+                            self.max
+                        );
+                        return None;
+                    }
+                },
+                None => None,
+            };
+            self.in_progress.fetch_add(1, Ordering::SeqCst);
+            Some(SetupSlot {
+                _permit: permit,
+                in_progress: Arc::clone(&self.in_progress),
+            })
+        }
+    }
+
+    /// Held from accept until the stream is set up or the connection dropped.
+    struct SetupSlot {
+        _permit: Option<OwnedSemaphorePermit>,
+        in_progress: Arc<AtomicUsize>,
+    }
+
+    impl Drop for SetupSlot {
+        fn drop(&mut self) {
+            self.in_progress.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
     pub(crate) struct AcceptorReservation {
         limiter: Arc<AcceptorLimiter>,
         generation: usize,
         committed: bool,
+        setup: Option<SetupSlot>,
     }
 
     impl AcceptorReservation {
@@ -284,7 +434,9 @@ pub(crate) mod acceptor {
             self.limiter.generation() == self.generation
         }
 
+        /// Ends the setup: the stream holds its credit from here on.
         pub(crate) fn commit(mut self) -> bool {
+            self.setup = None;
             if !self.is_fresh() {
                 return false;
             }
@@ -303,11 +455,12 @@ pub(crate) mod acceptor {
 
     struct AcceptorGate {
         limiter: Arc<AcceptorLimiter>,
+        setups: Arc<SetupLimiter>,
     }
 
     impl AcceptorGate {
-        fn new(limiter: Arc<AcceptorLimiter>) -> Self {
-            Self { limiter }
+        fn new(limiter: Arc<AcceptorLimiter>, setups: Arc<SetupLimiter>) -> Self {
+            Self { limiter, setups }
         }
 
         async fn accept_and_dispatch(
@@ -315,17 +468,23 @@ pub(crate) mod acceptor {
             listener: &TokioTcpListener,
             command_tx: &mpsc::UnboundedSender<Command>,
         ) -> bool {
-            let reservation = self.limiter.reserve().await;
+            let mut reservation = self.limiter.reserve().await;
             match listener.accept().await {
                 Ok((stream, _)) => {
                     if !reservation.is_fresh() {
                         drop(stream);
                         return true;
                     };
+                    let Some(setup) = self.setups.admit().await else {
+                        drop(stream);
+                        return true;
+                    };
+                    reservation.setup = Some(setup);
                     if command_tx
                         .send(Command::NewStream {
                             stream,
                             reservation,
+                            accepted_at: Instant::now(),
                         })
                         .is_err()
                     {
@@ -362,11 +521,12 @@ pub(crate) mod acceptor {
             listener: TokioTcpListener,
             command_tx: mpsc::UnboundedSender<Command>,
             acceptor_backpressure: Arc<AcceptorLimiter>,
+            setups: Arc<SetupLimiter>,
         ) -> Self {
             Self {
                 listener,
                 command_tx,
-                gate: AcceptorGate::new(acceptor_backpressure),
+                gate: AcceptorGate::new(acceptor_backpressure, setups),
             }
         }
 
@@ -448,6 +608,82 @@ impl ClientState {
             debug_enqueued_bytes: 0,
             debug_last_enqueue_at: 0,
             acceptor_limit_logged: false,
+            read_coalesce: ReadCoalesce::default(),
+            local_close_policy: LocalClosePolicy::default(),
+            server_incompatible: None,
+            first_writes: Vec::new(),
+            backpressure: Arc::new(BackpressureCounters::default()),
+            first_stream_boost: FirstStreamBoost::new(0, 0),
+            prioritize_interactive: false,
+            bulk_window: None,
+            bulk_in_transit: 0,
+            stream_bytes: 0,
+        }
+    }
+
+    pub(crate) fn backpressure(&self) -> &BackpressureCounters {
+        &self.backpressure
+    }
+
+    pub(crate) fn set_read_coalesce(&mut self, read_coalesce: ReadCoalesce) {
+        self.read_coalesce = read_coalesce;
+    }
+
+    pub(crate) fn set_local_close_policy(&mut self, policy: LocalClosePolicy) {
+        self.local_close_policy = policy;
+    }
+
+    pub(crate) fn set_first_stream_boost(&mut self, count: usize, duration_ms: u64) {
+        self.first_stream_boost = FirstStreamBoost::new(count, duration_ms);
+    }
+
+    pub(crate) fn set_prioritize_interactive(&mut self, enabled: bool) {
+        self.prioritize_interactive = enabled;
+    }
+
+    pub(crate) fn set_congestion_isolation(&mut self, enabled: bool) {
+        self.bulk_window = enabled.then(BulkWindow::new);
+    }
+
+    pub(crate) fn congestion_isolation(&self) -> bool {
+        self.bulk_window.is_some()
+    }
+
+    /// Feeds the paths' congestion state to the bulk window; a no-op unless
+    /// congestion isolation is on.
+    pub(crate) fn update_bulk_window(
+        &mut self,
+        lost: u64,
+        rtt_us: u64,
+        rtt_min_us: u64,
+        bytes_in_transit: u64,
+        now_us: u64,
+    ) {
+        if let Some(bulk_window) = self.bulk_window.as_mut() {
+            bulk_window.update(lost, rtt_us, rtt_min_us, now_us);
+            self.bulk_in_transit = bytes_in_transit;
+        }
+    }
+
+    /// Bulk window in bytes and the bulk reads it held back; `None` unless
+    /// congestion isolation is on.
+    pub(crate) fn bulk_window_stats(&self) -> Option<(u64, u64)> {
+        self.bulk_window
+            .as_ref()
+            .map(|bulk_window| (bulk_window.window(), bulk_window.held_back()))
+    }
+
+    /// Returns boosted streams to their normal priority once the boost window closed.
+    pub(crate) fn expire_stream_boosts(&mut self, cnx: *mut picoquic_cnx_t, now_us: u64) {
+        for stream_id in self.first_stream_boost.expire(now_us) {
+            if let Some(stream) = self.streams.get(&stream_id) {
+                let priority = if self.prioritize_interactive {
+                    stream.send_rate.priority()
+                } else {
+                    DEFAULT_STREAM_PRIORITY
+                };
+                unsafe { picoquic_set_stream_priority(cnx, stream_id, priority) };
+            }
         }
     }
 
@@ -463,6 +699,23 @@ impl ClientState {
         self.streams.len()
     }
 
+    pub(crate) fn stream_bytes(&self) -> u64 {
+        self.stream_bytes
+    }
+
+    pub(crate) fn stream_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.streams.keys().copied()
+    }
+
+    /// Accepted connections waiting to become streams, and those refused for
+    /// lack of a setup slot.
+    pub(crate) fn setup_stats(&self) -> (usize, u64) {
+        (
+            self.acceptor.setups_in_progress(),
+            self.acceptor.setups_refused(),
+        )
+    }
+
     pub(crate) fn update_acceptor_limit(&mut self, cnx: *mut picoquic_cnx_t) {
         let max_streams = self.acceptor.update_limit(cnx);
         if !self.acceptor_limit_logged && max_streams > 0 {
@@ -540,6 +793,20 @@ impl ClientState {
         summaries
     }
 
+    /// Longest wait from accepting a connection to the first DNS query after its
+    /// first data was queued; the runtime calls this once queries went out.
+    pub(crate) fn take_queue_latency(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        self.first_writes
+            .drain(..)
+            .map(|accepted_at| now.saturating_duration_since(accepted_at))
+            .max()
+    }
+
+    pub(crate) fn take_server_incompatible(&mut self) -> Option<ServerIncompatible> {
+        self.server_incompatible.take()
+    }
+
     pub(crate) fn take_path_events(&mut self) -> Vec<PathEvent> {
         std::mem::take(&mut self.path_events)
     }
@@ -562,7 +829,11 @@ impl ClientState {
         self.acceptor.reset();
         self.debug_enqueued_bytes = 0;
         self.debug_last_enqueue_at = 0;
+        self.stream_bytes = 0;
         self.acceptor_limit_logged = false;
+        self.server_incompatible = None;
+        self.first_writes.clear();
+        self.first_stream_boost.reset();
     }
 }
 
@@ -628,6 +899,16 @@ struct ClientStream {
     recv_state: StreamRecvState,
     send_state: StreamSendState,
     flow: FlowControlState,
+    /// Cleared when the stream's first data is handed to QUIC.
+    accepted_at: Option<Instant>,
+    send_rate: SendRate,
+    span: Span,
+}
+
+impl Drop for ClientStream {
+    fn drop(&mut self) {
+        finish_stream_span(&self.span, self.tx_bytes, self.flow.rx_bytes);
+    }
 }
 
 impl HasFlowControlState for ClientStream {
@@ -649,6 +930,7 @@ pub(crate) enum Command {
     NewStream {
         stream: TokioTcpStream,
         reservation: acceptor::AcceptorReservation,
+        accepted_at: Instant,
     },
     StreamData {
         stream_id: u64,
@@ -667,6 +949,12 @@ pub(crate) enum Command {
         stream_id: u64,
         bytes: usize,
     },
+    // Only sent from the Android bridge today.
+    #[cfg_attr(not(target_os = "android"), allow(dead_code))]
+    CloseStream {
+        stream_id: u64,
+        reply: std::sync::mpsc::SyncSender<bool>,
+    },
 }
 
 pub(crate) enum PathEvent {
@@ -700,7 +988,8 @@ pub(crate) unsafe extern "C" fn client_callback(
     match fin_or_event {
         picoquic_call_back_event_t::picoquic_callback_ready => {
             state.ready = true;
-            info!("Connection ready");
+            state.first_stream_boost.on_ready(picoquic_current_time());
+            info!("Connection ready (session {})", session_id(cnx));
             state.update_acceptor_limit(cnx);
         }
         picoquic_call_back_event_t::picoquic_callback_stream_data
@@ -763,7 +1052,8 @@ pub(crate) unsafe extern "C" fn client_callback(
                 );
             }
             warn!(
-                "Connection closed event={} state={:?} local_error=0x{:x} remote_error=0x{:x} local_app=0x{:x} remote_app=0x{:x} ready={}",
+                "Connection closed session={} event={} state={:?} local_error=0x{:x} remote_error=0x{:x} local_app=0x{:x} remote_app=0x{:x} ready={}",
+                session_id(cnx),
                 close_event_label(fin_or_event),
                 cnx_state,
                 local_reason,
@@ -772,6 +1062,8 @@ pub(crate) unsafe extern "C" fn client_callback(
                 remote_app_reason,
                 state.ready
             );
+            state.server_incompatible =
+                ServerIncompatible::from_close_reasons(local_reason, remote_reason);
         }
         picoquic_call_back_event_t::picoquic_callback_prepare_to_send => {
             if !bytes.is_null() {
@@ -801,6 +1093,8 @@ fn handle_stream_data(
     let mut reset_stream = false;
     let mut remove_stream = false;
     let multi_stream = state.multi_stream_mode;
+    state.stream_bytes = state.stream_bytes.saturating_add(data.len() as u64);
+    let backpressure = state.backpressure.clone();
     let reserve_bytes = if multi_stream {
         0
     } else {
@@ -843,6 +1137,9 @@ fn handle_stream_data(
                     }
                 },
                 on_overflow: |stream: &mut ClientStream| {
+                    backpressure
+                        .buffer_cap_drops
+                        .fetch_add(1, Ordering::Relaxed);
                     let (drain_tx, _drain_rx) = mpsc::unbounded_channel();
                     stream.write_tx = drain_tx;
                 },
@@ -920,6 +1217,7 @@ fn handle_stream_data(
 #[cfg(test)]
 mod test_hooks {
     use slipstream_core::test_support::FailureCounter;
+    use std::sync::Mutex;
 
     pub(super) const FORCED_ADD_TO_STREAM_ERROR: i32 = -1;
     pub(super) const FORCED_MARK_ACTIVE_STREAM_ERROR: i32 = 0x400 + 36;
@@ -941,6 +1239,16 @@ mod test_hooks {
     pub(super) fn take_mark_active_stream_failure() -> bool {
         MARK_ACTIVE_STREAM_FAILS_LEFT.take()
     }
+
+    pub(super) static RESET_STREAMS: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+    pub(super) fn record_stream_reset(stream_id: u64) {
+        RESET_STREAMS.lock().unwrap().push(stream_id);
+    }
+
+    pub(super) fn was_stream_reset(stream_id: u64) -> bool {
+        RESET_STREAMS.lock().unwrap().contains(&stream_id)
+    }
 }
 
 #[cfg(test)]
@@ -952,6 +1260,21 @@ mod tests {
     use tokio::sync::{mpsc, oneshot, Notify};
     use tokio::time::{sleep, timeout, Duration};
 
+    #[test]
+    fn queue_latency_reports_longest_wait_once() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, acceptor);
+        let now = Instant::now();
+        state.first_writes.push(now - Duration::from_millis(40));
+        state.first_writes.push(now - Duration::from_millis(10));
+
+        let latency = state.take_queue_latency().expect("queue latency");
+        assert!(latency >= Duration::from_millis(40));
+        assert!(state.take_queue_latency().is_none());
+    }
+
     #[test]
     fn add_to_stream_fin_failure_removes_stream() {
         let _guard = ResetOnDrop::new(|| test_hooks::set_add_to_stream_failures(0));
@@ -973,6 +1296,9 @@ mod tests {
                 recv_state: StreamRecvState::Open,
                 send_state: StreamSendState::Open,
                 flow: FlowControlState::default(),
+                accepted_at: None,
+                send_rate: SendRate::default(),
+                span: Span::none(),
             },
         );
 
@@ -990,6 +1316,93 @@ mod tests {
         );
     }
 
+    #[test]
+    fn local_close_resets_tunnel_stream_promptly() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            let listener = TokioTcpListener::bind("127.0.0.1:0")
+                .await
+                .expect("bind listener");
+            let addr = listener.local_addr().expect("listener addr");
+            let local_client = tokio::net::TcpStream::connect(addr).await.expect("connect");
+            let (accepted, _) = listener.accept().await.expect("accept");
+            let (read_half, _write_half) = accepted.into_split();
+
+            let (command_tx, _command_rx) = mpsc::unbounded_channel();
+            let data_notify = Arc::new(Notify::new());
+            let acceptor = acceptor::ClientAcceptor::new();
+            let mut state =
+                ClientState::new(command_tx.clone(), data_notify.clone(), false, acceptor);
+            state.set_local_close_policy(LocalClosePolicy::Reset);
+            let stream_id = 12;
+            let (write_tx, mut write_rx) = mpsc::unbounded_channel();
+            let (read_abort_tx, read_abort_rx) = oneshot::channel();
+            let (data_tx, data_rx) = mpsc::channel(1);
+            state.streams.insert(
+                stream_id,
+                ClientStream {
+                    write_tx,
+                    read_abort_tx: Some(read_abort_tx),
+                    data_rx: Some(data_rx),
+                    tx_bytes: 0,
+                    recv_state: StreamRecvState::Open,
+                    send_state: StreamSendState::Open,
+                    flow: FlowControlState::default(),
+                    accepted_at: None,
+                    send_rate: SendRate::default(),
+                    span: Span::none(),
+                },
+            );
+            spawn_client_reader(
+                stream_id,
+                read_half,
+                read_abort_rx,
+                command_tx,
+                data_tx,
+                data_notify.clone(),
+                ReadCoalesce::default(),
+                Arc::default(),
+            );
+
+            drop(local_client);
+            timeout(Duration::from_secs(1), data_notify.notified())
+                .await
+                .expect("reader should notice the local close promptly");
+            drain_stream_data(std::ptr::null_mut(), &mut state as *mut _);
+
+            assert!(
+                !state.streams.contains_key(&stream_id),
+                "stream state should be dropped as soon as the local side closes"
+            );
+            assert!(test_hooks::was_stream_reset(stream_id));
+            assert!(matches!(write_rx.try_recv(), Ok(StreamWrite::Fin)));
+        });
+    }
+
+    #[test]
+    fn close_stream_reports_unknown_stream() {
+        let (command_tx, _command_rx) = mpsc::unbounded_channel();
+        let data_notify = Arc::new(Notify::new());
+        let acceptor = acceptor::ClientAcceptor::new();
+        let mut state = ClientState::new(command_tx, data_notify, false, acceptor);
+        let (reply_tx, reply_rx) = std::sync::mpsc::sync_channel(1);
+
+        handle_command(
+            std::ptr::null_mut(),
+            &mut state as *mut _,
+            Command::CloseStream {
+                stream_id: 8,
+                reply: reply_tx,
+            },
+        );
+
+        assert_eq!(reply_rx.try_recv(), Ok(false));
+    }
+
     #[test]
     fn remote_fin_keeps_local_read_open() {
         let (command_tx, _command_rx) = mpsc::unbounded_channel();
@@ -1011,6 +1424,9 @@ mod tests {
                 recv_state: StreamRecvState::Open,
                 send_state: StreamSendState::Open,
                 flow: FlowControlState::default(),
+                accepted_at: None,
+                send_rate: SendRate::default(),
+                span: Span::none(),
             },
         );
 
@@ -1053,6 +1469,9 @@ mod tests {
                 recv_state: StreamRecvState::Open,
                 send_state: StreamSendState::Open,
                 flow: FlowControlState::default(),
+                accepted_at: None,
+                send_rate: SendRate::default(),
+                span: Span::none(),
             },
         );
 
@@ -1099,6 +1518,9 @@ mod tests {
                 recv_state: StreamRecvState::Open,
                 send_state: StreamSendState::FinQueued,
                 flow: FlowControlState::default(),
+                accepted_at: None,
+                send_rate: SendRate::default(),
+                span: Span::none(),
             },
         );
 
@@ -1152,6 +1574,7 @@ mod tests {
                 Command::NewStream {
                     stream,
                     reservation,
+                    accepted_at: Instant::now(),
                 },
             );
 
@@ -1201,6 +1624,174 @@ mod tests {
             drop(clients);
         });
     }
+
+    #[test]
+    fn connection_storm_is_capped_at_the_setup_limit() {
+        const CONNECTIONS: usize = 300;
+        const MAX_SETUPS: usize = 8;
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            // The default backlog would drop part of the storm in the kernel.
+            let socket = tokio::net::TcpSocket::new_v4().expect("socket");
+            socket
+                .bind("127.0.0.1:0".parse().expect("addr"))
+                .expect("bind");
+            let listener = socket.listen(1024).expect("listen");
+            let addr = listener.local_addr().expect("listener addr");
+            let (command_tx, mut command_rx) = mpsc::unbounded_channel();
+            let acceptor = acceptor::ClientAcceptor::with_setup_limit(Some(MAX_SETUPS));
+            acceptor.set_max_streams_for_test(usize::MAX);
+            acceptor.spawn(listener, command_tx);
+
+            let connects = (0..CONNECTIONS)
+                .map(|_| tokio::spawn(TokioTcpStream::connect(addr)))
+                .collect::<Vec<_>>();
+            let mut clients = Vec::new();
+            for connect in connects {
+                clients.push(connect.await.expect("join").expect("connect"));
+            }
+
+            // The runtime is busy: nothing is set up, so only the first slots fill
+            // and later connections are refused after the brief wait.
+            let mut held = Vec::new();
+            while held.len() < MAX_SETUPS {
+                held.push(
+                    timeout(Duration::from_secs(1), command_rx.recv())
+                        .await
+                        .expect("setup slot")
+                        .expect("command"),
+                );
+            }
+            sleep(Duration::from_millis(1_200)).await;
+            assert!(command_rx.try_recv().is_err());
+            assert_eq!(acceptor.setups_in_progress(), MAX_SETUPS);
+            assert!(acceptor.setups_refused() >= 2);
+
+            // Setting streams up frees the slots; every connection is either set
+            // up or refused, never left hanging.
+            drop(held);
+            let mut set_up = MAX_SETUPS;
+            while set_up + (acceptor.setups_refused() as usize) < CONNECTIONS {
+                let command = timeout(Duration::from_secs(2), command_rx.recv())
+                    .await
+                    .expect("storm drains")
+                    .expect("command");
+                assert!(acceptor.setups_in_progress() <= MAX_SETUPS);
+                drop(command);
+                set_up += 1;
+            }
+            assert_eq!(acceptor.setups_in_progress(), 0);
+            drop(clients);
+        });
+    }
+
+    async fn count_chunks(coalesce: ReadCoalesce) -> (usize, usize) {
+        let (mut writer, mut reader) = tokio::io::duplex(4096);
+        let chatty = tokio::spawn(async move {
+            for _ in 0..10 {
+                writer.write_all(&[b'x'; 10]).await.expect("write");
+                sleep(Duration::from_millis(5)).await;
+            }
+        });
+        let mut buf = vec![0u8; STREAM_READ_CHUNK_BYTES];
+        let (mut chunks, mut total) = (0, 0);
+        loop {
+            let n = reader.read(&mut buf).await.expect("read");
+            if n == 0 {
+                break;
+            }
+            let (n, end) = coalesce_reads(&mut reader, &mut buf, n, coalesce).await;
+            chunks += 1;
+            total += n;
+            if end == CoalesceEnd::Eof {
+                break;
+            }
+        }
+        chatty.await.expect("writer");
+        (chunks, total)
+    }
+
+    #[test]
+    fn coalescing_reduces_chunks_for_chatty_writes() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            let (plain, plain_bytes) = count_chunks(ReadCoalesce::default()).await;
+            let (coalesced, coalesced_bytes) = count_chunks(ReadCoalesce {
+                delay: Duration::from_secs(5),
+                flush_bytes: 60,
+            })
+            .await;
+            assert_eq!(plain_bytes, 100);
+            assert_eq!(coalesced_bytes, 100);
+            // 60 bytes flush on size, the remaining 40 on EOF.
+            assert_eq!(coalesced, 2);
+            assert!(
+                plain > coalesced,
+                "expected fewer chunks with coalescing ({} vs {})",
+                coalesced,
+                plain
+            );
+        });
+    }
+
+    #[test]
+    fn full_tunnel_queue_counts_a_local_read_pause() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            let counters = BackpressureCounters::default();
+            let (data_tx, mut data_rx) = mpsc::channel(1);
+            assert!(queue_local_data(&data_tx, vec![1], &counters).await);
+            assert_eq!(counters.local_read_pauses.load(Ordering::Relaxed), 0);
+
+            let drain = tokio::spawn(async move {
+                sleep(Duration::from_millis(10)).await;
+                let mut received = Vec::new();
+                while let Some(chunk) = data_rx.recv().await {
+                    received.extend(chunk);
+                }
+                received
+            });
+            assert!(queue_local_data(&data_tx, vec![2], &counters).await);
+            assert_eq!(counters.local_read_pauses.load(Ordering::Relaxed), 1);
+            drop(data_tx);
+            assert_eq!(drain.await.expect("drain"), vec![1, 2]);
+        });
+    }
+
+    #[test]
+    fn coalescing_never_waits_past_the_cap() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .enable_time()
+            .build()
+            .expect("build tokio runtime");
+        rt.block_on(async {
+            let (mut writer, mut reader) = tokio::io::duplex(4096);
+            writer.write_all(b"k").await.expect("write");
+            let mut buf = vec![0u8; STREAM_READ_CHUNK_BYTES];
+            let n = reader.read(&mut buf).await.expect("read");
+            let started = std::time::Instant::now();
+            let coalesce = ReadCoalesce {
+                delay: Duration::from_millis(20),
+                flush_bytes: 140,
+            };
+            let (n, end) = coalesce_reads(&mut reader, &mut buf, n, coalesce).await;
+            assert_eq!((n, end), (1, CoalesceEnd::Open));
+            assert!(started.elapsed() < Duration::from_secs(1));
+            drop(writer);
+        });
+    }
 }
 
 pub(crate) fn drain_commands(
@@ -1218,7 +1809,11 @@ pub(crate) fn drain_stream_data(cnx: *mut picoquic_cnx_t, state_ptr: *mut Client
     let mut closed_streams = Vec::new();
     {
         let state = unsafe { &mut *state_ptr };
-        slipstream_core::drain_stream_data!(state.streams, data_rx, pending, closed_streams);
+        if state.bulk_window.is_some() {
+            drain_isolated_stream_data(state, &mut pending, &mut closed_streams);
+        } else {
+            slipstream_core::drain_stream_data!(state.streams, data_rx, pending, closed_streams);
+        }
         for stream_id in &closed_streams {
             if let Some(stream) = state.streams.get_mut(stream_id) {
                 if stream.send_state == StreamSendState::Open {
@@ -1235,6 +1830,44 @@ pub(crate) fn drain_stream_data(cnx: *mut picoquic_cnx_t, state_ptr: *mut Client
     }
 }
 
+/// Like `drain_stream_data!`, but bulk streams are only read while the bulk
+/// window has room; what they leave queued holds back their local reads.
+fn drain_isolated_stream_data(
+    state: &mut ClientState,
+    pending: &mut Vec<(u64, Vec<u8>)>,
+    closed_streams: &mut Vec<u64>,
+) {
+    let Some(bulk_window) = state.bulk_window.as_mut() else {
+        return;
+    };
+    for (stream_id, stream) in state.streams.iter_mut() {
+        let Some(rx) = stream.data_rx.as_mut() else {
+            continue;
+        };
+        let bulk = stream.send_rate.is_bulk();
+        loop {
+            if bulk && !rx.is_empty() && !bulk_window.admit_bulk(state.bulk_in_transit) {
+                break;
+            }
+            match rx.try_recv() {
+                Ok(data) => {
+                    if bulk {
+                        state.bulk_in_transit =
+                            state.bulk_in_transit.saturating_add(data.len() as u64);
+                    }
+                    pending.push((*stream_id, data));
+                }
+                Err(mpsc::error::TryRecvError::Empty) => break,
+                Err(mpsc::error::TryRecvError::Disconnected) => {
+                    stream.data_rx = None;
+                    closed_streams.push(*stream_id);
+                    break;
+                }
+            }
+        }
+    }
+}
+
 pub(crate) fn handle_command(
     cnx: *mut picoquic_cnx_t,
     state_ptr: *mut ClientState,
@@ -1245,6 +1878,7 @@ pub(crate) fn handle_command(
         Command::NewStream {
             stream,
             reservation,
+            accepted_at,
         } => {
             if !reservation.is_fresh() {
                 drop(stream);
@@ -1320,6 +1954,9 @@ pub(crate) fn handle_command(
                     recv_state: StreamRecvState::Open,
                     send_state: StreamSendState::Open,
                     flow: FlowControlState::default(),
+                    accepted_at: Some(accepted_at),
+                    send_rate: SendRate::default(),
+                    span: stream_span(stream_id),
                 },
             );
             spawn_client_reader(
@@ -1329,6 +1966,8 @@ pub(crate) fn handle_command(
                 command_tx.clone(),
                 data_tx,
                 data_notify,
+                state.read_coalesce,
+                state.backpressure.clone(),
             );
             spawn_client_writer(
                 stream_id,
@@ -1360,6 +1999,13 @@ pub(crate) fn handle_command(
                     },
                 );
             }
+            if state
+                .first_stream_boost
+                .admit(stream_id, unsafe { picoquic_current_time() })
+            {
+                unsafe { picoquic_set_stream_priority(cnx, stream_id, BOOSTED_STREAM_PRIORITY) };
+                debug!("stream {}: boosted as an early stream", stream_id);
+            }
             if state.debug_streams {
                 debug!("stream {}: accepted", stream_id);
             } else {
@@ -1381,7 +2027,37 @@ pub(crate) fn handle_command(
                 state.streams.remove(&stream_id);
             } else if let Some(stream) = state.streams.get_mut(&stream_id) {
                 stream.tx_bytes = stream.tx_bytes.saturating_add(data.len() as u64);
+                if let Some(accepted_at) = stream.accepted_at.take() {
+                    state.first_writes.push(accepted_at);
+                }
                 let now = unsafe { picoquic_current_time() };
+                if state.prioritize_interactive || state.bulk_window.is_some() {
+                    let changed = stream.send_rate.record(data.len() as u64, now);
+                    if let Some(bulk) = changed {
+                        // A boosted stream keeps its boost until the window closes.
+                        if state.prioritize_interactive
+                            && !state.first_stream_boost.is_boosted(stream_id)
+                        {
+                            unsafe {
+                                picoquic_set_stream_priority(
+                                    cnx,
+                                    stream_id,
+                                    stream.send_rate.priority(),
+                                )
+                            };
+                        }
+                        debug!(
+                            "stream {}: {}",
+                            stream_id,
+                            if bulk {
+                                "sending in bulk"
+                            } else {
+                                "interactive again"
+                            }
+                        );
+                    }
+                }
+                state.stream_bytes = state.stream_bytes.saturating_add(data.len() as u64);
                 state.debug_enqueued_bytes =
                     state.debug_enqueued_bytes.saturating_add(data.len() as u64);
                 state.debug_last_enqueue_at = now;
@@ -1396,6 +2072,29 @@ pub(crate) fn handle_command(
             if !should_send_fin {
                 return;
             }
+            if state.local_close_policy == LocalClosePolicy::Reset {
+                // The local client is gone; nothing it would read can arrive, so
+                // reset both directions instead of waiting on the server's FIN.
+                if let Some(mut stream) = state.streams.remove(&stream_id) {
+                    if let Some(read_abort_tx) = stream.read_abort_tx.take() {
+                        let _ = read_abort_tx.send(());
+                    }
+                    let _ = stream.write_tx.send(StreamWrite::Fin);
+                    #[cfg(test)]
+                    test_hooks::record_stream_reset(stream_id);
+                    #[cfg(not(test))]
+                    unsafe {
+                        abort_stream_bidi(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR)
+                    };
+                    if state.debug_streams {
+                        debug!(
+                            "stream {}: reset after local close rx_bytes={} tx_bytes={}",
+                            stream_id, stream.flow.rx_bytes, stream.tx_bytes
+                        );
+                    }
+                }
+                return;
+            }
             #[cfg(test)]
             let forced_failure = test_hooks::take_add_to_stream_failure();
             #[cfg(not(test))]
@@ -1505,9 +2204,27 @@ pub(crate) fn handle_command(
             }
             check_stream_invariants(state, stream_id, "StreamWriteDrained");
         }
+        Command::CloseStream { stream_id, reply } => {
+            let closed = if let Some(mut stream) = state.streams.remove(&stream_id) {
+                if let Some(read_abort_tx) = stream.read_abort_tx.take() {
+                    let _ = read_abort_tx.send(());
+                }
+                let _ = stream.write_tx.send(StreamWrite::Fin);
+                unsafe { abort_stream_bidi(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR) };
+                info!(
+                    "stream {}: closed on request rx_bytes={} tx_bytes={}",
+                    stream_id, stream.flow.rx_bytes, stream.tx_bytes
+                );
+                true
+            } else {
+                false
+            };
+            let _ = reply.send(closed);
+        }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn spawn_client_reader(
     stream_id: u64,
     mut read_half: tokio::net::tcp::OwnedReadHalf,
@@ -1515,6 +2232,8 @@ fn spawn_client_reader(
     command_tx: mpsc::UnboundedSender<Command>,
     data_tx: mpsc::Sender<Vec<u8>>,
     data_notify: Arc<Notify>,
+    read_coalesce: ReadCoalesce,
+    backpressure: Arc<BackpressureCounters>,
 ) {
     tokio::spawn(async move {
         let mut buf = vec![0u8; STREAM_READ_CHUNK_BYTES];
@@ -1529,11 +2248,21 @@ fn spawn_client_reader(
                             break;
                         }
                         Ok(n) => {
+                            let (n, end) =
+                                coalesce_reads(&mut read_half, &mut buf, n, read_coalesce).await;
                             let data = buf[..n].to_vec();
-                            if data_tx.send(data).await.is_err() {
+                            if !queue_local_data(&data_tx, data, &backpressure).await {
                                 break;
                             }
                             data_notify.notify_one();
+                            match end {
+                                CoalesceEnd::Open => {}
+                                CoalesceEnd::Eof => break,
+                                CoalesceEnd::Error => {
+                                    let _ = command_tx.send(Command::StreamReadError { stream_id });
+                                    break;
+                                }
+                            }
                         }
                         Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {
                             continue;
@@ -1551,6 +2280,58 @@ fn spawn_client_reader(
     });
 }
 
+/// Queues a chunk for the tunnel, counting a pause when it has to wait for room.
+/// Returns false once the stream's queue is closed.
+async fn queue_local_data(
+    data_tx: &mpsc::Sender<Vec<u8>>,
+    data: Vec<u8>,
+    backpressure: &BackpressureCounters,
+) -> bool {
+    match data_tx.try_send(data) {
+        Ok(()) => true,
+        Err(TrySendError::Full(data)) => {
+            backpressure
+                .local_read_pauses
+                .fetch_add(1, Ordering::Relaxed);
+            data_tx.send(data).await.is_ok()
+        }
+        Err(TrySendError::Closed(_)) => false,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum CoalesceEnd {
+    Open,
+    Eof,
+    Error,
+}
+
+/// Keeps reading after a short read until a query's worth of data is buffered or
+/// the delay since the first byte runs out. The cap is never exceeded, so a lone
+/// keystroke still goes out after at most `delay`.
+async fn coalesce_reads<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+    mut len: usize,
+    coalesce: ReadCoalesce,
+) -> (usize, CoalesceEnd) {
+    if coalesce.delay.is_zero() {
+        return (len, CoalesceEnd::Open);
+    }
+    let flush_at = coalesce.flush_bytes.clamp(1, buf.len());
+    let deadline = Instant::now() + coalesce.delay;
+    while len < flush_at {
+        match timeout_at(deadline, reader.read(&mut buf[len..])).await {
+            Err(_) => break,
+            Ok(Ok(0)) => return (len, CoalesceEnd::Eof),
+            Ok(Ok(n)) => len += n,
+            Ok(Err(err)) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Ok(Err(_)) => return (len, CoalesceEnd::Error),
+        }
+    }
+    (len, CoalesceEnd::Open)
+}
+
 fn spawn_client_writer(
     stream_id: u64,
     mut write_half: tokio::net::tcp::OwnedWriteHalf,
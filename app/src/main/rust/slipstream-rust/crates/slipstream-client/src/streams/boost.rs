@@ -0,0 +1,104 @@
+/// How long after the handshake new streams can be boosted, and boosts last.
+pub const DEFAULT_FIRST_STREAM_BOOST_MS: u64 = 3_000;
+
+/// Priority of ordinary streams, as set by `picoquic_set_default_priority`.
+pub(crate) const DEFAULT_STREAM_PRIORITY: u8 = 2;
+/// Priority of boosted streams: served first-in first-out ahead of every
+/// default-priority stream.
+pub(crate) const BOOSTED_STREAM_PRIORITY: u8 = 1;
+
+/// Sends the first streams of a session ahead of the rest for a short window.
+///
+/// The first stream after the handshake is usually the page the user clicked,
+/// while whatever opens next (prefetches, background sync) can wait. The first
+/// `count` streams opened within `duration_us` of the connection becoming ready
+/// get a higher picoquic priority, and drop back to the default priority when
+/// the window closes. A count of 0 disables it.
+pub(crate) struct FirstStreamBoost {
+    count: usize,
+    duration_us: u64,
+    ready_at_us: Option<u64>,
+    granted: usize,
+    boosted: Vec<u64>,
+}
+
+impl FirstStreamBoost {
+    pub(crate) fn new(count: usize, duration_ms: u64) -> Self {
+        Self {
+            count,
+            duration_us: duration_ms.saturating_mul(1_000),
+            ready_at_us: None,
+            granted: 0,
+            boosted: Vec::new(),
+        }
+    }
+
+    /// Starts the window; called when the connection becomes ready.
+    pub(crate) fn on_ready(&mut self, now_us: u64) {
+        self.ready_at_us = Some(now_us);
+        self.granted = 0;
+        self.boosted.clear();
+    }
+
+    fn window_open(&self, now_us: u64) -> bool {
+        self.ready_at_us
+            .is_some_and(|ready_at| now_us < ready_at.saturating_add(self.duration_us))
+    }
+
+    /// Returns true when `stream_id` should be boosted.
+    pub(crate) fn admit(&mut self, stream_id: u64, now_us: u64) -> bool {
+        if self.granted >= self.count || !self.window_open(now_us) {
+            return false;
+        }
+        self.granted += 1;
+        self.boosted.push(stream_id);
+        true
+    }
+
+    pub(crate) fn is_boosted(&self, stream_id: u64) -> bool {
+        self.boosted.contains(&stream_id)
+    }
+
+    /// Boosted streams to return to the default priority once the window closed.
+    pub(crate) fn expire(&mut self, now_us: u64) -> Vec<u64> {
+        if self.boosted.is_empty() || self.window_open(now_us) {
+            return Vec::new();
+        }
+        std::mem::take(&mut self.boosted)
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.ready_at_us = None;
+        self.granted = 0;
+        self.boosted.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_streams_after_ready_are_boosted_for_the_window() {
+        let mut boost = FirstStreamBoost::new(2, 3_000);
+        // Nothing is boosted before the handshake completes.
+        assert!(!boost.admit(0, 0));
+
+        boost.on_ready(1_000_000);
+        assert!(boost.admit(4, 1_000_000));
+        assert!(boost.admit(8, 1_500_000));
+        // The third stream competes on equal terms.
+        assert!(!boost.admit(12, 1_600_000));
+        assert!(boost.expire(3_999_999).is_empty());
+        assert_eq!(boost.expire(4_000_000), [4, 8]);
+        assert!(boost.expire(4_000_001).is_empty());
+
+        // Streams opened after the window get no boost either.
+        boost.on_ready(10_000_000);
+        assert!(!boost.admit(16, 13_000_000));
+
+        boost.reset();
+        assert!(!boost.admit(20, 13_000_001));
+        assert!(!FirstStreamBoost::new(0, 3_000).admit(0, 0));
+    }
+}
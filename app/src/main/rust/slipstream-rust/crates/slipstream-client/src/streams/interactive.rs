@@ -0,0 +1,144 @@
+use super::boost::DEFAULT_STREAM_PRIORITY;
+
+/// Priority of streams that send in bulk: behind every default-priority stream,
+/// round-robin among themselves.
+pub(crate) const BULK_STREAM_PRIORITY: u8 = 4;
+
+/// Window over which a stream's queued bytes are counted.
+const SEND_WINDOW_US: u64 = 1_000_000;
+/// Bytes queued within one window that make a stream bulk.
+const BULK_BYTES_PER_WINDOW: u64 = 32 * 1024;
+
+/// Tells bulk streams from interactive ones by how fast they queue data.
+///
+/// Each QUIC packet becomes one DNS query, and picoquic fills packets by stream
+/// priority, so a stream that queues more than `BULK_BYTES_PER_WINDOW` in a
+/// window drops to `BULK_STREAM_PRIORITY` and the queries carry the other
+/// streams' data first. It returns to the default priority after a window in
+/// which it stayed under the limit.
+#[derive(Debug, Default)]
+pub(crate) struct SendRate {
+    window_start_us: u64,
+    window_bytes: u64,
+    bulk: bool,
+}
+
+impl SendRate {
+    /// Counts `bytes` queued at `now_us`; returns the new bulk flag when it changed.
+    pub(crate) fn record(&mut self, bytes: u64, now_us: u64) -> Option<bool> {
+        let was_bulk = self.bulk;
+        let elapsed = now_us.saturating_sub(self.window_start_us);
+        if elapsed >= SEND_WINDOW_US {
+            // A stream counts as quiet only after a whole window under the limit.
+            let quiet = elapsed >= 2 * SEND_WINDOW_US || self.window_bytes < BULK_BYTES_PER_WINDOW;
+            if quiet {
+                self.bulk = false;
+            }
+            self.window_start_us = now_us;
+            self.window_bytes = 0;
+        }
+        self.window_bytes = self.window_bytes.saturating_add(bytes);
+        if self.window_bytes >= BULK_BYTES_PER_WINDOW {
+            self.bulk = true;
+        }
+        (self.bulk != was_bulk).then_some(self.bulk)
+    }
+
+    pub(crate) fn is_bulk(&self) -> bool {
+        self.bulk
+    }
+
+    pub(crate) fn priority(&self) -> u8 {
+        if self.bulk {
+            BULK_STREAM_PRIORITY
+        } else {
+            DEFAULT_STREAM_PRIORITY
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000;
+
+    #[test]
+    fn saturating_stream_is_demoted_and_recovers_once_quiet() {
+        let mut rate = SendRate::default();
+        let mut changes = Vec::new();
+        for tick in 0..20 {
+            if let Some(bulk) = rate.record(4_096, tick * 10 * MS) {
+                changes.push((tick, bulk));
+            }
+        }
+        assert_eq!(changes, [(7, true)]);
+        assert_eq!(rate.priority(), BULK_STREAM_PRIORITY);
+
+        // The window that saw the burst keeps it bulk; a quiet one clears it.
+        assert_eq!(rate.record(100, 1_100 * MS), None);
+        assert_eq!(rate.record(100, 2_200 * MS), Some(false));
+        assert_eq!(rate.priority(), DEFAULT_STREAM_PRIORITY);
+    }
+
+    /// Worst wait, in packets, of a trickle stream's writes next to four bulk
+    /// streams. One packet per tick is filled like picoquic does: lowest
+    /// priority value first, round-robin among equals.
+    fn worst_trickle_wait(classify: bool) -> u64 {
+        const PACKET_BYTES: u64 = 140;
+        let mut rates: Vec<SendRate> = (0..5).map(|_| SendRate::default()).collect();
+        let mut queued_at: Option<u64> = None;
+        let mut last_served = 0;
+        // Writes arrive 50 to 149 ticks apart, from a fixed pseudo-random sequence.
+        let mut seed = 1u64;
+        let mut next_write = 0;
+        let mut worst_wait = 0;
+        for tick in 0..3_000u64 {
+            let now = tick * MS;
+            for rate in &mut rates[1..] {
+                rate.record(PACKET_BYTES, now);
+            }
+            if tick >= next_write && queued_at.is_none() {
+                rates[0].record(50, now);
+                queued_at = Some(tick);
+                seed = (seed * 1_103_515_245 + 12_345) % (1 << 31);
+                next_write = tick + 50 + seed % 100;
+            }
+            let priority = |index: usize| {
+                if classify {
+                    rates[index].priority()
+                } else {
+                    DEFAULT_STREAM_PRIORITY
+                }
+            };
+            let pending: Vec<usize> = (0..5)
+                .filter(|&index| index > 0 || queued_at.is_some())
+                .collect();
+            let best = pending.iter().map(|&index| priority(index)).min().unwrap();
+            let eligible: Vec<usize> = pending
+                .into_iter()
+                .filter(|&index| priority(index) == best)
+                .collect();
+            let chosen = eligible
+                .iter()
+                .copied()
+                .find(|&index| index > last_served)
+                .unwrap_or(eligible[0]);
+            last_served = chosen;
+            if chosen == 0 {
+                let wait = tick - queued_at.take().unwrap();
+                // The first window, before any stream is classified, is warm-up.
+                if tick >= 1_000 {
+                    worst_wait = worst_wait.max(wait);
+                }
+            }
+        }
+        worst_wait
+    }
+
+    #[test]
+    fn trickle_stream_jumps_ahead_of_bulk_streams() {
+        assert_eq!(worst_trickle_wait(true), 0);
+        assert!(worst_trickle_wait(false) >= 3);
+    }
+}
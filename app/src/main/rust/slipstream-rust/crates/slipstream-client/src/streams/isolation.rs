@@ -0,0 +1,239 @@
+/// Bulk streams may always have this much in flight.
+const MIN_BULK_WINDOW_BYTES: u64 = 2 * 1024;
+const INITIAL_BULK_WINDOW_BYTES: u64 = 8 * 1024;
+/// Each congestion signal while bulk streams were sending halves their window.
+const BULK_DECREASE_FACTOR: f64 = 0.5;
+/// Every round trip without a signal in which the window held bulk data back,
+/// it grows by this share.
+const BULK_INCREASE_SHARE: f64 = 0.125;
+/// Round trips more than half again the path's minimum mean queues are building.
+const QUEUE_DELAY_DIVISOR: u64 = 2;
+
+/// Congestion accounting of its own for bulk streams over the shared path.
+///
+/// picoquic runs one congestion window for every stream on the connection, so
+/// a bulk download keeps it and the resolver's queue full, and every stream
+/// pays for the losses and delay that causes: an interactive stream's writes
+/// wait behind the queue or are lost with the bulk data. With isolation, bulk
+/// streams (as `SendRate` tells them) only get more data into QUIC while the
+/// path's bytes in flight are under a window of their own. That window backs
+/// off, AIMD style, on losses and on round trips well above the path's
+/// minimum, so it stays short of filling the queue. Interactive streams are
+/// never held back and only answer to picoquic's window.
+#[derive(Debug)]
+pub(crate) struct BulkWindow {
+    window: u64,
+    /// Path losses seen at the last update; `None` before the first.
+    lost: Option<u64>,
+    last_decrease_at: u64,
+    last_increase_at: u64,
+    /// Bulk data was handed to QUIC since the last update.
+    bulk_sent: bool,
+    /// The window held bulk data back since the last increase.
+    limited: bool,
+    /// Bulk reads held back because the window was full.
+    held_back: u64,
+}
+
+impl BulkWindow {
+    pub(crate) fn new() -> Self {
+        Self {
+            window: INITIAL_BULK_WINDOW_BYTES,
+            lost: None,
+            last_decrease_at: 0,
+            last_increase_at: 0,
+            bulk_sent: false,
+            limited: false,
+            held_back: 0,
+        }
+    }
+
+    pub(crate) fn window(&self) -> u64 {
+        self.window
+    }
+
+    pub(crate) fn held_back(&self) -> u64 {
+        self.held_back
+    }
+
+    /// Whether bulk data may be handed to QUIC with `bytes_in_transit` in
+    /// flight on the path; counts the hold-backs.
+    pub(crate) fn admit_bulk(&mut self, bytes_in_transit: u64) -> bool {
+        if bytes_in_transit < self.window {
+            self.bulk_sent = true;
+            return true;
+        }
+        self.limited = true;
+        self.held_back = self.held_back.saturating_add(1);
+        false
+    }
+
+    /// Takes the path's loss counter and round trips. Losses or a queueing
+    /// round trip while bulk streams were sending cut the window once per
+    /// round trip; otherwise it grows each round trip it held data back.
+    pub(crate) fn update(&mut self, lost: u64, rtt_us: u64, rtt_min_us: u64, now_us: u64) {
+        let new_losses = self.lost.is_some_and(|previous| lost > previous);
+        self.lost = Some(lost);
+        let queueing = rtt_min_us > 0 && rtt_us > rtt_min_us + rtt_min_us / QUEUE_DELAY_DIVISOR;
+        let round_trip = rtt_us.max(1);
+        if (new_losses || queueing) && self.bulk_sent {
+            if now_us.saturating_sub(self.last_decrease_at) >= round_trip {
+                let window = (self.window as f64 * BULK_DECREASE_FACTOR) as u64;
+                self.window = window.max(MIN_BULK_WINDOW_BYTES);
+                self.last_decrease_at = now_us;
+            }
+            self.last_increase_at = now_us;
+            self.limited = false;
+        } else if now_us.saturating_sub(self.last_increase_at) >= round_trip {
+            if self.limited {
+                let step = (self.window as f64 * BULK_INCREASE_SHARE) as u64;
+                self.window = self.window.saturating_add(step.max(1));
+            }
+            self.last_increase_at = now_us;
+            self.limited = false;
+        }
+        self.bulk_sent = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Reverse;
+    use std::collections::{BinaryHeap, VecDeque};
+
+    const TICK_US: u64 = 1_000;
+    const PACKET_BYTES: u64 = 200;
+    const BASE_RTT_TICKS: u64 = 20;
+    /// The resolver passes on two packets per tick and queues up to 60 more.
+    const PATH_RATE: u64 = 2;
+    const QUEUE_PACKETS: u64 = 60;
+    const SENDS_PER_TICK: usize = 8;
+
+    /// Share of an interactive stream's writes acknowledged within two base
+    /// round trips, next to a bulk stream that always has data, and the share
+    /// of the path's rate the bulk stream got. The path
+    /// shares one Reno-style window, queues what exceeds its rate and loses
+    /// what overflows the queue; picoquic sends the interactive stream's data
+    /// first when there is room.
+    fn interactive_on_time(isolate: bool) -> (f64, f64) {
+        let mut bulk_window = BulkWindow::new();
+        let mut cwnd = 10.0f64;
+        let mut last_cut = 0u64;
+        let (mut lost_total, mut rtt, mut rtt_min) = (0u64, 0u64, 0u64);
+        let mut queue_free_at = 0u64;
+        // (time the sender learns the packet's fate, sequence, sent at, lost).
+        let mut fates: BinaryHeap<Reverse<(u64, u64, u64, bool)>> = BinaryHeap::new();
+        let mut interactive_writes: Vec<Option<u64>> = Vec::new();
+        let mut carries: Vec<Option<usize>> = Vec::new();
+        let mut interactive_queue: VecDeque<usize> = VecDeque::new();
+        let (mut writes, mut on_time, mut bulk_delivered) = (0u64, 0u64, 0u64);
+        let mut seed = 7u64;
+        let mut next_write = 0;
+        for tick in 0..60_000u64 {
+            while fates
+                .peek()
+                .is_some_and(|Reverse((at, _, _, _))| *at <= tick)
+            {
+                let Reverse((_, seq, sent_at, lost)) = fates.pop().unwrap();
+                if lost {
+                    lost_total += 1;
+                    if tick - last_cut >= rtt {
+                        cwnd = (cwnd / 2.0).max(2.0);
+                        last_cut = tick;
+                    }
+                    if let Some(write) = carries[seq as usize] {
+                        interactive_queue.push_back(write);
+                    }
+                    continue;
+                }
+                cwnd += 1.0 / cwnd;
+                rtt = tick - sent_at;
+                rtt_min = if rtt_min == 0 { rtt } else { rtt_min.min(rtt) };
+                // The first ten seconds are warm-up.
+                if let Some(write) = carries[seq as usize] {
+                    let written_at = interactive_writes[write].take().unwrap();
+                    if written_at >= 10_000 {
+                        writes += 1;
+                        on_time += u64::from(tick - written_at <= 2 * BASE_RTT_TICKS);
+                    }
+                } else if sent_at >= 10_000 {
+                    bulk_delivered += 1;
+                }
+            }
+            if tick >= next_write {
+                interactive_queue.push_back(interactive_writes.len());
+                interactive_writes.push(Some(tick));
+                seed = (seed * 1_103_515_245 + 12_345) % (1 << 31);
+                next_write = tick + 50 + seed % 100;
+            }
+            bulk_window.update(lost_total, rtt * TICK_US, rtt_min * TICK_US, tick * TICK_US);
+            for _ in 0..SENDS_PER_TICK {
+                if fates.len() as f64 >= cwnd.floor() {
+                    break;
+                }
+                let write = interactive_queue.pop_front();
+                if write.is_none()
+                    && isolate
+                    && !bulk_window.admit_bulk(fates.len() as u64 * PACKET_BYTES)
+                {
+                    break;
+                }
+                // Each packet takes 1 / PATH_RATE ticks of the resolver's time.
+                let queued = queue_free_at.saturating_sub(tick * PATH_RATE);
+                let seq = carries.len() as u64;
+                carries.push(write);
+                if queued >= QUEUE_PACKETS {
+                    fates.push(Reverse((tick + BASE_RTT_TICKS, seq, tick, true)));
+                } else {
+                    queue_free_at = queue_free_at.max(tick * PATH_RATE) + 1;
+                    let delivered_at = queue_free_at.div_ceil(PATH_RATE);
+                    fates.push(Reverse((delivered_at + BASE_RTT_TICKS, seq, tick, false)));
+                }
+            }
+        }
+        assert!(writes > 300, "{} writes", writes);
+        let path_packets = (60_000 - 10_000 - BASE_RTT_TICKS) * PATH_RATE;
+        (
+            on_time as f64 / writes as f64,
+            bulk_delivered as f64 / path_packets as f64,
+        )
+    }
+
+    #[test]
+    fn interactive_stream_keeps_its_pace_next_to_a_lossy_bulk_stream() {
+        let (shared, _) = interactive_on_time(false);
+        let (isolated, bulk_rate) = interactive_on_time(true);
+        assert!(isolated >= 0.95, "isolated {}", isolated);
+        assert!(shared < 0.8, "shared {}", shared);
+        // The bulk stream still gets most of the path.
+        assert!(bulk_rate >= 0.8, "bulk got {} of the path", bulk_rate);
+    }
+
+    #[test]
+    fn only_signals_while_bulk_was_sending_cut_the_window() {
+        let mut bulk_window = BulkWindow::new();
+        bulk_window.update(0, 100_000, 100_000, 0);
+        // Losses without bulk data in flight are not bulk's doing.
+        bulk_window.update(5, 100_000, 100_000, 100_000);
+        assert_eq!(bulk_window.window(), INITIAL_BULK_WINDOW_BYTES);
+
+        assert!(bulk_window.admit_bulk(1_000));
+        bulk_window.update(6, 100_000, 100_000, 200_000);
+        assert_eq!(bulk_window.window(), INITIAL_BULK_WINDOW_BYTES / 2);
+        // A queueing round trip in the same round trip is the same overload.
+        assert!(!bulk_window.admit_bulk(INITIAL_BULK_WINDOW_BYTES));
+        assert!(bulk_window.admit_bulk(1_000));
+        bulk_window.update(6, 300_000, 100_000, 250_000);
+        assert_eq!(bulk_window.window(), INITIAL_BULK_WINDOW_BYTES / 2);
+        assert_eq!(bulk_window.held_back(), 1);
+
+        // A quiet round trip in which the window held data back grows it.
+        assert!(!bulk_window.admit_bulk(INITIAL_BULK_WINDOW_BYTES));
+        bulk_window.update(6, 100_000, 100_000, 400_000);
+        assert_eq!(
+            bulk_window.window(),
+            INITIAL_BULK_WINDOW_BYTES / 2 + INITIAL_BULK_WINDOW_BYTES / 16
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! Shared `ClientConfig` fixture for unit tests.
+
+use slipstream_core::{AddressFamily, HostPort};
+use slipstream_dns::CLASS_IN;
+use slipstream_ffi::{
+    ClientConfig, DnsIdStrategy, FormerrPolicy, LabelEncoding, LocalClosePolicy,
+    ModeMismatchPolicy, NonTunnelPolicy, PipelineMode, ProtectionLossPolicy, ProtectionMode,
+    ResolverMode, ResolverPreference, ResolverRolePolicy, ResolverSpec,
+};
+
+/// A recursive IPv4 resolver on port 53.
+pub(crate) fn resolver(host: &str) -> ResolverSpec {
+    ResolverSpec {
+        resolver: HostPort {
+            host: host.to_string(),
+            port: 53,
+            family: AddressFamily::V4,
+        },
+        mode: ResolverMode::Recursive,
+    }
+}
+
+/// A config that passes validation; tests change fields from here, with
+/// struct update syntax or assignments.
+pub(crate) fn config<'a>(domain: &'a str, resolvers: &'a [ResolverSpec]) -> ClientConfig<'a> {
+    ClientConfig {
+        tcp_listen_host: "127.0.0.1",
+        tcp_listen_port: 1080,
+        resolvers,
+        domain,
+        cert: None,
+        congestion_control: None,
+        gso: false,
+        keep_alive_interval: 400,
+        adaptive_keep_alive: false,
+        debug_poll: false,
+        debug_streams: false,
+        record_dns: None,
+        query_log: None,
+        flight_recorder_records: 0,
+        resolver_failure_threshold: 3,
+        resolver_grace_period_ms: 5_000,
+        resolver_recovery_probe_ms: 10_000,
+        resolver_servfail_threshold: 8,
+        resolver_servfail_cooldown_ms: 30_000,
+        confirm_resolvers: 1,
+        handshake_redundancy: 0,
+        status_host: "127.0.0.1",
+        status_port: None,
+        status_control: false,
+        dns_listen_host: "127.0.0.1",
+        dns_listen_port: None,
+        dns_upstream: "1.1.1.1:53",
+        dns_socks_username: None,
+        dns_socks_password: None,
+        dns_id_strategy: DnsIdStrategy::Random,
+        query_class: CLASS_IN,
+        edns_cookies: false,
+        adaptive_query_rate: false,
+        query_ramp_qps: 0,
+        query_ramp_doubling_ms: 500,
+        coalesce_delay_ms: 0,
+        reconnect_min_ms: 250,
+        reconnect_max_ms: 5_000,
+        reconnect_jitter: false,
+        max_reconnect_attempts: 1_000,
+        max_reconnect_duration_ms: 0,
+        block_backoff_after: 0,
+        block_backoff_ms: 300_000,
+        resolver_role_policy: ResolverRolePolicy::Mixed,
+        resolver_preference: ResolverPreference::Order,
+        max_parallel_probes: None,
+        poll_timeout_ms: 5_000,
+        idle_poll_min_ms: 500,
+        idle_poll_max_ms: 0,
+        idle_refresh_ms: 0,
+        poll_pool: 0,
+        stream_striping: false,
+        prioritize_interactive: false,
+        congestion_isolation: false,
+        first_stream_boost_count: 0,
+        first_stream_boost_ms: 3_000,
+        max_connection_setups: None,
+        reassembly_limit_bytes: None,
+        local_close_policy: LocalClosePolicy::HalfClose,
+        protection_mode: ProtectionMode::VpnProtected,
+        protection_loss_policy: ProtectionLossPolicy::Hold,
+        udp_rcvbuf_bytes: None,
+        udp_sndbuf_bytes: None,
+        source_address: None,
+        max_qname_len: None,
+        label_encoding: LabelEncoding::Base32,
+        non_tunnel_policy: NonTunnelPolicy::Report,
+        mode_mismatch_policy: ModeMismatchPolicy::Report,
+        formerr_policy: FormerrPolicy::Adapt,
+        max_lifetime_queries: None,
+        pipeline_mode: PipelineMode::Pipelined,
+        active_windows: &[],
+        quiet_hours: &[],
+        event_grace_ms: 3_000,
+        congestion_controller: None,
+        resolver_transport: None,
+        query_hook: None,
+    }
+}
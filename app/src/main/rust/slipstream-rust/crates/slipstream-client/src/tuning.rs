@@ -0,0 +1,215 @@
+//! Learned tuning kept per network.
+//!
+//! Over a session the client learns how long the network's middleboxes keep
+//! an idle connection (adaptive keep-alive), how many queries per second each
+//! resolver tolerates (adaptive query rate) and which resolvers do not answer
+//! at all. With a network id set, e.g. a hash of the Wi-Fi SSID, these are
+//! kept for that network when a connection ends, and the next connection on
+//! the same network starts from them instead of learning them again. The
+//! payload size is derived from the MTU, so there is nothing to keep there.
+//!
+//! Nothing is written to disk here: the embedder exports the learned tuning as
+//! JSON, stores it and imports it again on the next start.
+
+use crate::error::ClientError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Format version written by `export_learned_tuning`.
+pub const TUNING_VERSION: u32 = 1;
+
+/// What the client learned on one network.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkTuning {
+    /// Keep-alive interval adaptive keep-alive settled on.
+    pub keep_alive_interval_ms: Option<u64>,
+    pub resolvers: Vec<ResolverTuning>,
+}
+
+/// What the client learned about one resolver on a network.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResolverTuning {
+    /// The resolver's address, as `IP:PORT`.
+    pub resolver: String,
+    /// Queries per second adaptive rate control learned it tolerates.
+    pub learned_qps: Option<f64>,
+    /// The resolver was marked dead when the connection ended.
+    pub dead: bool,
+}
+
+impl NetworkTuning {
+    pub(crate) fn resolver(&self, resolver: &str) -> Option<&ResolverTuning> {
+        self.resolvers
+            .iter()
+            .find(|entry| entry.resolver == resolver)
+    }
+}
+
+/// Tuning kept per network id.
+#[derive(Debug, Default)]
+pub(crate) struct TuningStore {
+    networks: BTreeMap<String, NetworkTuning>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct LearnedTuning {
+    // Defaults to 0 rather than the current version so a missing field is caught.
+    version: u32,
+    networks: BTreeMap<String, NetworkTuning>,
+}
+
+impl TuningStore {
+    pub(crate) const fn new() -> Self {
+        Self {
+            networks: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn get(&self, network_id: &str) -> Option<&NetworkTuning> {
+        self.networks.get(network_id)
+    }
+
+    pub(crate) fn record(&mut self, network_id: &str, tuning: NetworkTuning) {
+        self.networks.insert(network_id.to_string(), tuning);
+    }
+
+    pub(crate) fn export(&self) -> String {
+        let learned = LearnedTuning {
+            version: TUNING_VERSION,
+            networks: self.networks.clone(),
+        };
+        serde_json::to_string(&learned).unwrap_or_default()
+    }
+
+    pub(crate) fn import(&mut self, json: &str) -> Result<usize, ClientError> {
+        let imported: LearnedTuning = serde_json::from_str(json)
+            .map_err(|err| ClientError::new(format!("Invalid learned tuning: {}", err)))?;
+        if imported.version == 0 {
+            return Err(ClientError::new("Invalid learned tuning: missing version"));
+        }
+        let count = imported.networks.len();
+        self.networks.extend(imported.networks);
+        Ok(count)
+    }
+}
+
+/// Network the client runs on; `None` keeps nothing.
+static NETWORK_ID: Mutex<Option<String>> = Mutex::new(None);
+static LEARNED: Mutex<TuningStore> = Mutex::new(TuningStore::new());
+
+/// Sets the network the client is on. Tuning learned from now on is kept
+/// under this id, and the next connection starts from what was kept for it.
+/// `None` or a blank id turns keeping tuning off.
+// Called by the Android bridge and library callers; the CLI runs on one network.
+#[allow(dead_code)]
+pub fn set_network_id(network_id: Option<&str>) {
+    *NETWORK_ID.lock().expect("lock network id") = normalize_network_id(network_id);
+}
+
+fn normalize_network_id(network_id: Option<&str>) -> Option<String> {
+    network_id
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+}
+
+/// Serialize the tuning learned on every network as versioned JSON.
+// Called by the Android bridge and library callers, which store the result.
+#[allow(dead_code)]
+pub fn export_learned_tuning() -> String {
+    LEARNED.lock().expect("lock learned tuning").export()
+}
+
+/// Merge exported tuning into what is kept, replacing networks already known.
+/// Returns how many networks were imported.
+///
+/// Newer versions are accepted; fields unknown here are ignored.
+// Called by the Android bridge and library callers, which store the tuning.
+#[allow(dead_code)]
+pub fn import_learned_tuning(json: &str) -> Result<usize, ClientError> {
+    LEARNED.lock().expect("lock learned tuning").import(json)
+}
+
+/// Tuning kept for the current network, if a network id is set.
+pub(crate) fn current_network_tuning() -> Option<NetworkTuning> {
+    let network_id = NETWORK_ID.lock().expect("lock network id").clone()?;
+    LEARNED
+        .lock()
+        .expect("lock learned tuning")
+        .get(&network_id)
+        .cloned()
+}
+
+/// Keeps `tuning` for the current network; does nothing without a network id.
+pub(crate) fn record_current_network(tuning: NetworkTuning) {
+    let Some(network_id) = NETWORK_ID.lock().expect("lock network id").clone() else {
+        return;
+    };
+    LEARNED
+        .lock()
+        .expect("lock learned tuning")
+        .record(&network_id, tuning);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuning(interval_ms: u64) -> NetworkTuning {
+        NetworkTuning {
+            keep_alive_interval_ms: Some(interval_ms),
+            resolvers: vec![ResolverTuning {
+                resolver: "192.0.2.1:53".to_string(),
+                learned_qps: Some(12.5),
+                dead: false,
+            }],
+        }
+    }
+
+    #[test]
+    fn blank_network_ids_keep_nothing() {
+        assert_eq!(
+            normalize_network_id(Some(" home ")),
+            Some("home".to_string())
+        );
+        assert_eq!(normalize_network_id(Some("  ")), None);
+        assert_eq!(normalize_network_id(None), None);
+        assert_eq!(
+            TuningStore::new().export(),
+            r#"{"version":1,"networks":{}}"#
+        );
+    }
+
+    #[test]
+    fn import_merges_and_rejects_unversioned_json() {
+        let mut home = TuningStore::new();
+        home.record("home", tuning(10_000));
+        home.record("office", tuning(8_000));
+        let json = home.export();
+
+        let mut store = TuningStore::new();
+        store.record("office", tuning(4_000));
+        store.record("cafe", tuning(2_000));
+        assert_eq!(store.import(&json).expect("import"), 2);
+        assert_eq!(store.get("home"), Some(&tuning(10_000)));
+        assert_eq!(store.get("office"), Some(&tuning(8_000)));
+        assert_eq!(store.get("cafe"), Some(&tuning(2_000)));
+
+        assert!(store.import(r#"{"networks":{}}"#).is_err());
+        assert!(store.import("not json").is_err());
+        // A newer build's extra fields are ignored.
+        let newer =
+            r#"{"version":2,"networks":{"cafe":{"keep_alive_interval_ms":5000,"mtu":1200}}}"#;
+        assert_eq!(store.import(newer).expect("import newer"), 1);
+        assert_eq!(
+            store
+                .get("cafe")
+                .and_then(|cafe| cafe.keep_alive_interval_ms),
+            Some(5_000)
+        );
+    }
+}
@@ -0,0 +1,380 @@
+//! Dry-run validation of a client configuration.
+//!
+//! Runs the same checks the start path applies, without binding sockets or
+//! starting the runtime, so a UI can flag bad input before the user connects.
+
+use crate::dns::{resolve_resolvers, HealthPolicy};
+use crate::runtime::{compute_mtu, max_query_payload, parse_listen_host, qname_limit};
+use crate::status::unix_status_path;
+use serde::Serialize;
+use slipstream_core::{parse_host_port, AddressKind};
+use slipstream_dns::MAX_DNS_NAME_LEN;
+use slipstream_ffi::{ClientConfig, PipelineMode, ResolverMode, ResolverRolePolicy};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Well-known public recursive resolvers, never the tunnel server itself.
+const PUBLIC_RESOLVERS: [IpAddr; 12] = [
+    IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)),
+    IpAddr::V4(Ipv4Addr::new(8, 8, 4, 4)),
+    IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+    IpAddr::V4(Ipv4Addr::new(1, 0, 0, 1)),
+    IpAddr::V4(Ipv4Addr::new(9, 9, 9, 9)),
+    IpAddr::V4(Ipv4Addr::new(149, 112, 112, 112)),
+    IpAddr::V4(Ipv4Addr::new(208, 67, 222, 222)),
+    IpAddr::V4(Ipv4Addr::new(208, 67, 220, 220)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888)),
+    IpAddr::V6(Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8844)),
+    IpAddr::V6(Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111)),
+    IpAddr::V6(Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1001)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueSeverity {
+    /// The client would refuse to start or fail right away.
+    Error,
+    /// The client starts, but the setting is ignored or likely a mistake.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigIssue {
+    pub severity: IssueSeverity,
+    /// Configuration field the issue is about, named as in `ClientConfig`.
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    fn error(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: IssueSeverity::Error,
+            field,
+            message: message.into(),
+        }
+    }
+
+    fn warning(field: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: IssueSeverity::Warning,
+            field,
+            message: message.into(),
+        }
+    }
+}
+
+/// Checks `config` without starting anything; an empty list means it is valid.
+///
+/// Resolver hostnames are looked up like at start, so this can block on DNS.
+pub fn validate_config(config: &ClientConfig<'_>) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+
+    let max_qname_len = qname_limit(config);
+    let mtu = if max_qname_len > MAX_DNS_NAME_LEN {
+        issues.push(ConfigIssue::error(
+            "max_qname_len",
+            format!("Query names cannot exceed {} characters", MAX_DNS_NAME_LEN),
+        ));
+        None
+    } else {
+        match compute_mtu(config.domain.len(), max_qname_len, config.label_encoding) {
+            Ok(mtu) => Some(mtu),
+            Err(err) => {
+                issues.push(ConfigIssue::error("domain", err.to_string()));
+                None
+            }
+        }
+    };
+    if mtu.is_some() {
+        if let Err(err) = max_query_payload(config, max_qname_len) {
+            issues.push(ConfigIssue::error("domain", err.to_string()));
+        }
+    }
+
+    if config.resolvers.is_empty() {
+        issues.push(ConfigIssue::error(
+            "resolvers",
+            "At least one resolver is required",
+        ));
+    } else {
+        let health_policy = HealthPolicy::new(
+            config.resolver_failure_threshold,
+            config.resolver_grace_period_ms,
+            config.resolver_recovery_probe_ms,
+            config.resolver_servfail_threshold,
+            config.resolver_servfail_cooldown_ms,
+        );
+        if let Err(err) =
+            resolve_resolvers(config.resolvers, mtu.unwrap_or(1), false, health_policy)
+        {
+            issues.push(ConfigIssue::error("resolvers", err.to_string()));
+        }
+        if config.confirm_resolvers > config.resolvers.len() {
+            issues.push(ConfigIssue::warning(
+                "confirm_resolvers",
+                format!(
+                    "Only {} resolvers are configured; all of them must carry data before the tunnel counts as connected",
+                    config.resolvers.len()
+                ),
+            ));
+        }
+        if config.handshake_redundancy > 0 && config.resolvers.len() < 2 {
+            issues.push(ConfigIssue::warning(
+                "handshake_redundancy",
+                "Handshake redundancy needs at least two resolvers and has no effect",
+            ));
+        } else if config.handshake_redundancy > 0 && config.pipeline_mode == PipelineMode::Single {
+            issues.push(ConfigIssue::warning(
+                "handshake_redundancy",
+                "Single-query mode sends no duplicate handshake queries, so handshake redundancy has no effect",
+            ));
+        }
+        if config.stream_striping && config.resolvers.len() < 2 {
+            issues.push(ConfigIssue::warning(
+                "stream_striping",
+                "Stream striping needs at least two resolvers and has no effect",
+            ));
+        }
+        for spec in config
+            .resolvers
+            .iter()
+            .filter(|spec| spec.mode == ResolverMode::Authoritative)
+        {
+            let host = spec
+                .resolver
+                .host
+                .trim_start_matches('[')
+                .trim_end_matches(']');
+            if host
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| PUBLIC_RESOLVERS.contains(&ip))
+            {
+                issues.push(ConfigIssue::warning(
+                    "resolvers",
+                    format!(
+                        "{} is a public recursive resolver; as authoritative it gets paced query bursts and is likely rate limited, so list it as a recursive resolver",
+                        host
+                    ),
+                ));
+            }
+        }
+        let has_mode = |mode| config.resolvers.iter().any(|spec| spec.mode == mode);
+        if config.resolver_role_policy == ResolverRolePolicy::Split
+            && !(has_mode(ResolverMode::Authoritative) && has_mode(ResolverMode::Recursive))
+        {
+            issues.push(ConfigIssue::warning(
+                "resolver_role_policy",
+                "The split role policy needs both recursive and authoritative resolvers and has no effect",
+            ));
+        }
+    }
+
+    if config.tcp_listen_host.is_empty() {
+        issues.push(ConfigIssue::error(
+            "tcp_listen_host",
+            "Listen host must not be empty",
+        ));
+    } else if let Err(err) = parse_listen_host(config.tcp_listen_host) {
+        issues.push(ConfigIssue::error("tcp_listen_host", err.to_string()));
+    }
+    if config.tcp_listen_port == 0 {
+        issues.push(ConfigIssue::warning(
+            "tcp_listen_port",
+            "Listen port 0 lets the OS pick a random port",
+        ));
+    }
+
+    if let Some(congestion_control) = config.congestion_control {
+        if congestion_control != "bbr" && congestion_control != "dcubic" {
+            issues.push(ConfigIssue::error(
+                "congestion_control",
+                format!("Invalid congestion control: {}", congestion_control),
+            ));
+        }
+    }
+
+    if config.keep_alive_interval == 0 {
+        issues.push(ConfigIssue::warning(
+            "keep_alive_interval",
+            "Keep-alives are off; idle sessions may be dropped by NAT or the server",
+        ));
+    }
+
+    if config.reconnect_min_ms > config.reconnect_max_ms {
+        issues.push(ConfigIssue::error(
+            "reconnect_min_ms",
+            "reconnect_min_ms exceeds reconnect_max_ms",
+        ));
+    }
+
+    if config.idle_poll_max_ms > 0 && config.idle_poll_min_ms > config.idle_poll_max_ms {
+        issues.push(ConfigIssue::warning(
+            "idle_poll_min_ms",
+            "idle_poll_min_ms exceeds idle_poll_max_ms; idle polls use the cap as the floor",
+        ));
+    }
+
+    if let Some(path) = unix_status_path(config.status_host) {
+        if path.is_empty() {
+            issues.push(ConfigIssue::error(
+                "status_host",
+                "A unix: status host needs a socket path, as in unix:/run/slipstream.sock",
+            ));
+        }
+        if config.status_port.is_some() {
+            issues.push(ConfigIssue::warning(
+                "status_port",
+                "status_port is ignored when the status host is a Unix socket",
+            ));
+        }
+    }
+    if config.status_port.is_some() && config.status_control {
+        if let Ok(ip) = config.status_host.parse::<IpAddr>() {
+            if !ip.is_loopback() {
+                issues.push(ConfigIssue::error(
+                    "status_control",
+                    format!(
+                        "Status control endpoints need a loopback status host, not {}",
+                        ip
+                    ),
+                ));
+            }
+        }
+    }
+
+    if config.dns_listen_port.is_some() {
+        if let Err(err) = parse_host_port(config.dns_upstream, 53, AddressKind::Target) {
+            issues.push(ConfigIssue::error(
+                "dns_upstream",
+                format!("Invalid DNS upstream: {}", err),
+            ));
+        }
+    }
+
+    if config.source_address.is_some() && config.resolver_transport.is_some() {
+        issues.push(ConfigIssue::warning(
+            "source_address",
+            "A custom resolver transport sends the queries, so the source address is ignored",
+        ));
+    }
+
+    if config.gso {
+        issues.push(ConfigIssue::warning(
+            "gso",
+            "GSO is not implemented in the Rust client loop yet",
+        ));
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_config::{config, resolver};
+    use slipstream_ffi::ResolverSpec;
+
+    #[test]
+    fn valid_config_has_no_issues() {
+        let resolvers = [resolver("1.1.1.1"), resolver("8.8.8.8")];
+        assert_eq!(validate_config(&config("t.example.com", &resolvers)), []);
+    }
+
+    #[test]
+    fn reports_errors_and_warnings_by_field() {
+        let resolvers = [resolver("1.1.1.1"), resolver("1.1.1.1")];
+        let long_domain = "a".repeat(250);
+        let mut config = config(&long_domain, &resolvers);
+        config.tcp_listen_host = "localhost";
+        config.congestion_control = Some("reno");
+        config.keep_alive_interval = 0;
+        config.status_port = Some(8080);
+        config.status_host = "0.0.0.0";
+        config.status_control = true;
+        config.dns_listen_port = Some(5353);
+        config.dns_upstream = "[::1";
+
+        let issues = validate_config(&config);
+        let summary: Vec<_> = issues
+            .iter()
+            .map(|issue| (issue.severity, issue.field))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (IssueSeverity::Error, "domain"),
+                (IssueSeverity::Error, "resolvers"),
+                (IssueSeverity::Error, "tcp_listen_host"),
+                (IssueSeverity::Error, "congestion_control"),
+                (IssueSeverity::Warning, "keep_alive_interval"),
+                (IssueSeverity::Error, "status_control"),
+                (IssueSeverity::Error, "dns_upstream"),
+            ]
+        );
+        assert!(issues[1].message.contains("Duplicate resolver address"));
+        assert!(issues[2].message.contains("\"localhost\""));
+
+        let json = serde_json::to_string(&issues[4]).expect("serialize issue");
+        assert!(json.contains("\"severity\":\"warning\""), "{}", json);
+    }
+
+    #[test]
+    fn lone_resolver_makes_striping_a_warning() {
+        let resolvers = [resolver("1.1.1.1")];
+        let mut config = config("t.example.com", &resolvers);
+        config.stream_striping = true;
+        let issues = validate_config(&config);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, IssueSeverity::Warning);
+        assert_eq!(issues[0].field, "stream_striping");
+    }
+
+    #[test]
+    fn single_query_mode_makes_redundancy_a_warning() {
+        let resolvers = [resolver("1.1.1.1"), resolver("8.8.8.8")];
+        let mut config = config("t.example.com", &resolvers);
+        config.handshake_redundancy = 1;
+        assert!(validate_config(&config).is_empty());
+        config.pipeline_mode = PipelineMode::Single;
+        let issues = validate_config(&config);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, IssueSeverity::Warning);
+        assert_eq!(issues[0].field, "handshake_redundancy");
+    }
+
+    #[test]
+    fn checks_each_mix_of_resolver_modes() {
+        let authoritative = |host: &str| ResolverSpec {
+            mode: ResolverMode::Authoritative,
+            ..resolver(host)
+        };
+        let fields = |resolvers: &[ResolverSpec], policy| {
+            let mut config = config("t.example.com", resolvers);
+            config.resolver_role_policy = policy;
+            validate_config(&config)
+                .into_iter()
+                .map(|issue| issue.field)
+                .collect::<Vec<_>>()
+        };
+        let recursive_only = [resolver("1.1.1.1"), resolver("8.8.8.8")];
+        let authoritative_only = [authoritative("192.0.2.10")];
+        let mixed = [resolver("1.1.1.1"), authoritative("192.0.2.10")];
+        let public_authoritative = [resolver("192.0.2.10"), authoritative("8.8.8.8")];
+
+        for resolvers in [&recursive_only[..], &authoritative_only, &mixed] {
+            assert!(fields(resolvers, ResolverRolePolicy::Mixed).is_empty());
+        }
+        assert_eq!(
+            fields(&public_authoritative, ResolverRolePolicy::Mixed),
+            ["resolvers"]
+        );
+
+        assert!(fields(&mixed, ResolverRolePolicy::Split).is_empty());
+        for resolvers in [&recursive_only[..], &authoritative_only] {
+            assert_eq!(
+                fields(resolvers, ResolverRolePolicy::Split),
+                ["resolver_role_policy"]
+            );
+        }
+    }
+}
@@ -0,0 +1,1052 @@
+//! End-to-end connectivity check through the local tunnel listener.
+//!
+//! A completed QUIC handshake only proves the DNS path works. This fetches a URL
+//! through the listener the same way apps do (SOCKS5 CONNECT via the remote
+//! proxy) so a tunnel that is up but cannot reach anything shows as failed.
+//!
+//! The check blocks, so callers pass a cancellation flag that is polled between
+//! short socket waits; setting it stops the probe promptly.
+//!
+//! Each finished check also updates a small per-destination latency table, so a
+//! UI that probes a set of sites can rank them by what users actually get.
+//!
+//! The same path also runs a speed test: one stream to a TCP echo endpoint is
+//! kept busy for a fixed time and the bytes sent and echoed back are measured.
+//!
+//! It also asks an IP echo service for the public address the traffic leaves
+//! from, to confirm users appear as the server rather than their own network.
+
+use crate::socks5::{self, ConnectReply};
+use openssl::ssl::{SslConnector, SslMethod, SslVerifyMode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const SNIPPET_MAX_BYTES: usize = 256;
+const RESPONSE_MAX_BYTES: usize = 4096;
+// Longest a blocked read or write goes without checking the cancel flag.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// Destinations kept in the latency table; the least recently probed is dropped.
+const MAX_TRACKED_DESTINATIONS: usize = 32;
+// Time allowed for the listener connect, SOCKS5 setup and the latency ping.
+const SPEED_TEST_SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+const SPEED_TEST_MAX_DURATION: Duration = Duration::from_secs(30);
+const SPEED_TEST_CHUNK_BYTES: usize = 16 * 1024;
+const SPEED_TEST_PING: &[u8] = b"slipstream-speed-test-ping\n";
+// How long a looked-up exit address is reused before asking the service again.
+const EXIT_IP_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// IP echo service used when the caller names none; answers with the client
+/// address as plain text.
+pub const DEFAULT_EXIT_IP_URL: &str = "https://ifconfig.me/ip";
+
+static DESTINATIONS: Mutex<DestinationTable> = Mutex::new(DestinationTable::new());
+static SPEED_TEST_RUNNING: AtomicBool = AtomicBool::new(false);
+/// Last exit address found: the service URL, when it answered, and the address.
+static EXIT_IP: Mutex<Option<(String, Instant, IpAddr)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConnectivityResult {
+    /// True when the URL answered with a 2xx or 3xx status.
+    pub success: bool,
+    /// Time from opening the listener connection to the first response byte.
+    pub latency_ms: u64,
+    pub status_code: Option<u16>,
+    /// Start of the response body, lossily decoded as UTF-8.
+    pub snippet: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SpeedTestResult {
+    /// True when the transfer ran and at least one byte was echoed back.
+    pub success: bool,
+    /// Round trip of a small ping to the echo endpoint before the transfer.
+    pub latency_ms: u64,
+    /// Bytes the tunnel accepted from us during the test.
+    pub upload_bytes: u64,
+    /// Bytes echoed back through the tunnel.
+    pub download_bytes: u64,
+    pub upload_bps: u64,
+    pub download_bps: u64,
+    /// Length of the transfer phase, not counting setup.
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExitIpResult {
+    /// Public address the echo service saw the tunnel's traffic come from.
+    pub ip: Option<String>,
+    /// True when `ip` is from a lookup in the last minute rather than a new one.
+    pub cached: bool,
+    /// Time the lookup took; 0 for a cached answer.
+    pub latency_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Latency observed through the tunnel to one destination.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DestinationLatency {
+    /// Time to first response byte of the latest successful check.
+    pub latency_ms: Option<u64>,
+    /// Fastest successful check; less noisy than the latest for ranking.
+    pub min_latency_ms: Option<u64>,
+    pub successes: u64,
+    pub failures: u64,
+}
+
+struct DestinationTable {
+    // Ordered from least to most recently probed.
+    entries: Vec<(String, DestinationLatency)>,
+}
+
+impl DestinationTable {
+    const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, destination: String, latency_ms: Option<u64>) {
+        let mut entry = match self.entries.iter().position(|(key, _)| *key == destination) {
+            Some(index) => self.entries.remove(index).1,
+            None => {
+                if self.entries.len() >= MAX_TRACKED_DESTINATIONS {
+                    self.entries.remove(0);
+                }
+                DestinationLatency::default()
+            }
+        };
+        match latency_ms {
+            Some(latency_ms) => {
+                entry.latency_ms = Some(latency_ms);
+                entry.min_latency_ms = Some(
+                    entry
+                        .min_latency_ms
+                        .map_or(latency_ms, |min| min.min(latency_ms)),
+                );
+                entry.successes = entry.successes.saturating_add(1);
+            }
+            None => entry.failures = entry.failures.saturating_add(1),
+        }
+        self.entries.push((destination, entry));
+    }
+
+    fn snapshot(&self) -> BTreeMap<String, DestinationLatency> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+/// Latency per destination (`scheme://host[:port]`) from past connectivity checks.
+///
+/// Holds up to 32 destinations; cancelled checks and unparsable URLs are not
+/// recorded.
+pub fn destination_latencies() -> BTreeMap<String, DestinationLatency> {
+    DESTINATIONS
+        .lock()
+        .map(|table| table.snapshot())
+        .unwrap_or_default()
+}
+
+/// Credentials for the SOCKS5 proxy at the far end of the tunnel.
+pub struct SocksCredentials<'a> {
+    pub username: &'a str,
+    pub password: &'a str,
+}
+
+struct TargetUrl {
+    tls: bool,
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl TargetUrl {
+    fn destination(&self) -> String {
+        let scheme = if self.tls { "https" } else { "http" };
+        format!("{}://{}", scheme, self.host_header())
+    }
+
+    fn host_header(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        let default_port = if self.tls { 443 } else { 80 };
+        if self.port == default_port {
+            host
+        } else {
+            format!("{}:{}", host, self.port)
+        }
+    }
+}
+
+/// Fetch `url` through the tunnel listener at `listen_addr` and report the outcome.
+///
+/// Returns early with a "Cancelled" error once `cancel` is set.
+pub fn verify_connectivity(
+    listen_addr: SocketAddr,
+    url: &str,
+    credentials: Option<SocksCredentials<'_>>,
+    timeout: Duration,
+    cancel: &AtomicBool,
+) -> ConnectivityResult {
+    let started = Instant::now();
+    let target = parse_url(url);
+    let result = target.as_ref().map_err(Clone::clone).and_then(|target| {
+        fetch(
+            listen_addr,
+            target,
+            credentials.as_ref(),
+            timeout,
+            started,
+            cancel,
+        )
+    });
+    if let Ok(target) = &target {
+        if !cancel.load(Ordering::SeqCst) {
+            let latency_ms = match &result {
+                Ok(result) if result.success => Some(result.latency_ms),
+                _ => None,
+            };
+            if let Ok(mut table) = DESTINATIONS.lock() {
+                table.record(target.destination(), latency_ms);
+            }
+        }
+    }
+    match result {
+        Ok(result) => result,
+        Err(err) => ConnectivityResult {
+            latency_ms: started.elapsed().as_millis() as u64,
+            error: Some(if cancel.load(Ordering::SeqCst) {
+                "Cancelled".to_string()
+            } else {
+                err
+            }),
+            ..ConnectivityResult::default()
+        },
+    }
+}
+
+/// Ask the IP echo service at `url` (or [`DEFAULT_EXIT_IP_URL`]) through the
+/// tunnel listener at `listen_addr` which public address the traffic leaves from.
+///
+/// The request goes through the remote proxy like any connectivity check, so
+/// the answer is the server side's exit address. An answer is reused for a
+/// minute per URL; failures are not cached.
+pub fn exit_ip(
+    listen_addr: SocketAddr,
+    url: Option<&str>,
+    credentials: Option<SocksCredentials<'_>>,
+    timeout: Duration,
+    cancel: &AtomicBool,
+) -> ExitIpResult {
+    let url = url
+        .filter(|url| !url.is_empty())
+        .unwrap_or(DEFAULT_EXIT_IP_URL);
+    if let Ok(cached) = EXIT_IP.lock() {
+        if let Some((cached_url, at, ip)) = cached.as_ref() {
+            if cached_url == url && at.elapsed() < EXIT_IP_CACHE_TTL {
+                return ExitIpResult {
+                    ip: Some(ip.to_string()),
+                    cached: true,
+                    ..ExitIpResult::default()
+                };
+            }
+        }
+    }
+    let check = verify_connectivity(listen_addr, url, credentials, timeout, cancel);
+    let ip = match (&check.error, check.status_code) {
+        (Some(err), _) => Err(format!("Exit IP lookup failed: {}", err)),
+        (None, Some(status)) if !(200..300).contains(&status) => Err(format!(
+            "Exit IP lookup failed: echo service answered with status {}",
+            status
+        )),
+        _ => parse_exit_ip(&check.snippet),
+    };
+    match ip {
+        Ok(ip) => {
+            if let Ok(mut cached) = EXIT_IP.lock() {
+                *cached = Some((url.to_string(), Instant::now(), ip));
+            }
+            ExitIpResult {
+                ip: Some(ip.to_string()),
+                cached: false,
+                latency_ms: check.latency_ms,
+                error: None,
+            }
+        }
+        Err(err) => ExitIpResult {
+            latency_ms: check.latency_ms,
+            error: Some(err),
+            ..ExitIpResult::default()
+        },
+    }
+}
+
+/// Reads the address from an echo service body such as `203.0.113.7\n`.
+fn parse_exit_ip(body: &str) -> Result<IpAddr, String> {
+    body.trim().parse::<IpAddr>().map_err(|_| {
+        "Exit IP lookup failed: echo service did not answer with an IP address".to_string()
+    })
+}
+
+/// Measure throughput and latency through the tunnel listener at `listen_addr`
+/// against a TCP echo server at `echo_host:echo_port`.
+///
+/// Data is pushed on a single stream for `duration` (capped at 30 s) while the
+/// echo is read back, so the test competes with other traffic like one more busy
+/// connection, and flow control throttles it like any other stream. Upload
+/// counts what the tunnel accepted, so it reads high by the amount still
+/// buffered when time ran out. Only one test runs at a time, and `cancel` stops
+/// it early.
+pub fn run_speed_test(
+    listen_addr: SocketAddr,
+    echo_host: &str,
+    echo_port: u16,
+    credentials: Option<SocksCredentials<'_>>,
+    duration: Duration,
+    cancel: &AtomicBool,
+) -> SpeedTestResult {
+    if SPEED_TEST_RUNNING.swap(true, Ordering::SeqCst) {
+        return SpeedTestResult {
+            error: Some("A speed test is already running".to_string()),
+            ..SpeedTestResult::default()
+        };
+    }
+    let result = speed_test(
+        listen_addr,
+        echo_host,
+        echo_port,
+        credentials.as_ref(),
+        duration.min(SPEED_TEST_MAX_DURATION),
+        cancel,
+    );
+    SPEED_TEST_RUNNING.store(false, Ordering::SeqCst);
+    result.unwrap_or_else(|err| SpeedTestResult {
+        error: Some(if cancel.load(Ordering::SeqCst) {
+            "Cancelled".to_string()
+        } else {
+            err
+        }),
+        ..SpeedTestResult::default()
+    })
+}
+
+fn speed_test(
+    listen_addr: SocketAddr,
+    echo_host: &str,
+    echo_port: u16,
+    credentials: Option<&SocksCredentials<'_>>,
+    duration: Duration,
+    cancel: &AtomicBool,
+) -> Result<SpeedTestResult, String> {
+    let started = Instant::now();
+    let stream =
+        TcpStream::connect_timeout(&listen_addr, SPEED_TEST_SETUP_TIMEOUT).map_err(|err| {
+            format!(
+                "Failed to connect to tunnel listener {}: {}",
+                listen_addr, err
+            )
+        })?;
+    stream
+        .set_read_timeout(Some(CANCEL_POLL_INTERVAL))
+        .and_then(|_| stream.set_write_timeout(Some(CANCEL_POLL_INTERVAL)))
+        .map_err(|err| err.to_string())?;
+    let mut setup = CancellableStream {
+        inner: stream,
+        cancel,
+        deadline: started + SPEED_TEST_SETUP_TIMEOUT,
+    };
+    socks5_connect(&mut setup, echo_host, echo_port, credentials)?;
+
+    let ping_at = Instant::now();
+    let mut echo = [0u8; SPEED_TEST_PING.len()];
+    setup
+        .write_all(SPEED_TEST_PING)
+        .and_then(|_| setup.read_exact(&mut echo))
+        .map_err(|err| format!("Echo endpoint did not answer: {}", err))?;
+    if echo != SPEED_TEST_PING {
+        return Err("Echo endpoint returned different data".to_string());
+    }
+    let latency_ms = ping_at.elapsed().as_millis() as u64;
+
+    let mut writer = setup.inner;
+    let mut reader = writer.try_clone().map_err(|err| err.to_string())?;
+    let uploaded = AtomicU64::new(0);
+    let upload_done = AtomicBool::new(false);
+    let transfer_started = Instant::now();
+    let (download_bytes, last_byte_at) = std::thread::scope(|scope| {
+        let reading = scope.spawn(|| {
+            // Echoes still in flight get as long to drain as the test ran.
+            let drain_deadline = transfer_started + duration * 2;
+            let mut received = 0u64;
+            let mut last_byte_at = transfer_started;
+            let mut buf = vec![0u8; SPEED_TEST_CHUNK_BYTES];
+            while !cancel.load(Ordering::SeqCst) {
+                match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        received += n as u64;
+                        last_byte_at = Instant::now();
+                    }
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        let drained = upload_done.load(Ordering::SeqCst)
+                            && received >= uploaded.load(Ordering::SeqCst);
+                        if drained || Instant::now() >= drain_deadline {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+            (received, last_byte_at)
+        });
+
+        let chunk: Vec<u8> = (0..SPEED_TEST_CHUNK_BYTES).map(|i| i as u8).collect();
+        while transfer_started.elapsed() < duration && !cancel.load(Ordering::SeqCst) {
+            match writer.write(&chunk) {
+                Ok(n) => {
+                    uploaded.fetch_add(n as u64, Ordering::SeqCst);
+                }
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) => {}
+                Err(_) => break,
+            }
+        }
+        let _ = writer.shutdown(std::net::Shutdown::Write);
+        upload_done.store(true, Ordering::SeqCst);
+        reading.join().unwrap_or((0, transfer_started))
+    });
+    if cancel.load(Ordering::SeqCst) {
+        return Err("Cancelled".to_string());
+    }
+
+    let upload_bytes = uploaded.load(Ordering::SeqCst);
+    let upload_elapsed = duration.min(transfer_started.elapsed());
+    let download_elapsed = last_byte_at.duration_since(transfer_started);
+    Ok(SpeedTestResult {
+        success: download_bytes > 0,
+        latency_ms,
+        upload_bytes,
+        download_bytes,
+        upload_bps: bits_per_second(upload_bytes, upload_elapsed),
+        download_bps: bits_per_second(download_bytes, download_elapsed),
+        duration_ms: transfer_started.elapsed().as_millis() as u64,
+        error: (download_bytes == 0).then(|| "Nothing was echoed back".to_string()),
+    })
+}
+
+fn bits_per_second(bytes: u64, elapsed: Duration) -> u64 {
+    let micros = elapsed.as_micros().max(1);
+    (bytes as u128 * 8 * 1_000_000 / micros) as u64
+}
+
+/// Stream wrapper that turns short socket timeouts into cancel checks.
+#[derive(Debug)]
+struct CancellableStream<'a> {
+    inner: TcpStream,
+    cancel: &'a AtomicBool,
+    deadline: Instant,
+}
+
+impl CancellableStream<'_> {
+    fn retry<T>(
+        &mut self,
+        mut op: impl FnMut(&mut TcpStream) -> std::io::Result<T>,
+    ) -> std::io::Result<T> {
+        loop {
+            if self.cancel.load(Ordering::SeqCst) {
+                return Err(std::io::Error::other("probe cancelled"));
+            }
+            match op(&mut self.inner) {
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if Instant::now() >= self.deadline {
+                        return Err(std::io::ErrorKind::TimedOut.into());
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Read for CancellableStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.retry(|inner| inner.read(buf))
+    }
+}
+
+impl Write for CancellableStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.retry(|inner| inner.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn fetch(
+    listen_addr: SocketAddr,
+    target: &TargetUrl,
+    credentials: Option<&SocksCredentials<'_>>,
+    timeout: Duration,
+    started: Instant,
+    cancel: &AtomicBool,
+) -> Result<ConnectivityResult, String> {
+    let stream = TcpStream::connect_timeout(&listen_addr, timeout).map_err(|err| {
+        format!(
+            "Failed to connect to tunnel listener {}: {}",
+            listen_addr, err
+        )
+    })?;
+    let poll_interval = Some(CANCEL_POLL_INTERVAL.min(timeout));
+    stream
+        .set_read_timeout(poll_interval)
+        .and_then(|_| stream.set_write_timeout(poll_interval))
+        .map_err(|err| err.to_string())?;
+    let mut stream = CancellableStream {
+        inner: stream,
+        cancel,
+        deadline: started + timeout,
+    };
+    socks5_connect(&mut stream, &target.host, target.port, credentials)?;
+
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: slipstream-verify\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        target.path,
+        target.host_header()
+    );
+    let (response, first_byte_at) = if target.tls {
+        let mut builder = SslConnector::builder(SslMethod::tls())
+            .map_err(|err| format!("TLS setup failed: {}", err))?;
+        // Android has no CA bundle for OpenSSL; the check proves reachability only.
+        builder.set_verify(SslVerifyMode::NONE);
+        let connector = builder.build();
+        let mut tls = connector
+            .connect(&target.host, stream)
+            .map_err(|err| format!("TLS handshake failed: {}", err))?;
+        exchange(&mut tls, request.as_bytes())?
+    } else {
+        exchange(&mut stream, request.as_bytes())?
+    };
+
+    let latency_ms = first_byte_at.duration_since(started).as_millis() as u64;
+    let (status_code, body) = parse_http_response(&response)?;
+    let snippet_len = body.len().min(SNIPPET_MAX_BYTES);
+    Ok(ConnectivityResult {
+        success: (200..400).contains(&status_code),
+        latency_ms,
+        status_code: Some(status_code),
+        snippet: String::from_utf8_lossy(&body[..snippet_len]).into_owned(),
+        error: None,
+    })
+}
+
+fn exchange<S: Read + Write>(stream: &mut S, request: &[u8]) -> Result<(Vec<u8>, Instant), String> {
+    stream
+        .write_all(request)
+        .and_then(|_| stream.flush())
+        .map_err(|err| format!("Failed to send request: {}", err))?;
+    let mut response = Vec::new();
+    let mut first_byte_at = None;
+    let mut buf = [0u8; 1024];
+    while response.len() < RESPONSE_MAX_BYTES {
+        match stream.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => {
+                first_byte_at.get_or_insert_with(Instant::now);
+                response.extend_from_slice(&buf[..n]);
+            }
+            Err(err) if response.is_empty() => {
+                return Err(format!("No response from target: {}", err));
+            }
+            // Keep what arrived; a partial body is enough for a snippet.
+            Err(_) => break,
+        }
+    }
+    match first_byte_at {
+        Some(at) => Ok((response, at)),
+        None => Err("Target closed the connection without responding".to_string()),
+    }
+}
+
+fn socks5_connect<S: Read + Write>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: Option<&SocksCredentials<'_>>,
+) -> Result<(), String> {
+    let io_err = |err: std::io::Error| format!("SOCKS5 handshake failed: {}", err);
+    stream
+        .write_all(socks5::greeting(credentials.is_some()))
+        .map_err(io_err)?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).map_err(io_err)?;
+    if let (true, Some(credentials)) = (
+        socks5::parse_method(reply, credentials.is_some())?,
+        credentials,
+    ) {
+        let login = socks5::login_request(credentials.username, credentials.password)?;
+        stream.write_all(&login).map_err(io_err)?;
+        stream.read_exact(&mut reply).map_err(io_err)?;
+        socks5::parse_login(reply)?;
+    }
+
+    let request = socks5::connect_request(host, port)?;
+    stream.write_all(&request).map_err(io_err)?;
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).map_err(io_err)?;
+    let bound_len = match socks5::parse_connect_reply(header)? {
+        ConnectReply::Failed(reply) => {
+            return Err(connect_error(
+                reply,
+                matches!(host.parse::<IpAddr>(), Ok(IpAddr::V6(_))),
+            ));
+        }
+        ConnectReply::Bound(len) => len,
+        ConnectReply::BoundName => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len).map_err(io_err)?;
+            len[0] as usize + 2
+        }
+    };
+    let mut bound = vec![0u8; bound_len];
+    stream.read_exact(&mut bound).map_err(io_err)?;
+    Ok(())
+}
+
+fn connect_error(reply: u8, ipv6_target: bool) -> String {
+    match reply {
+        // Network/host unreachable or unsupported address type for a v6 literal
+        // almost always means the server side has no IPv6 connectivity.
+        0x03 | 0x04 | 0x08 if ipv6_target => format!(
+            "IPv6 destination unreachable: the tunnel server has no IPv6 route (reply {})",
+            reply
+        ),
+        0x02 => "SOCKS5 CONNECT not allowed by the remote proxy".to_string(),
+        0x03 => "Network unreachable from the tunnel server".to_string(),
+        0x04 => "Host unreachable from the tunnel server".to_string(),
+        0x05 => "Connection refused by the destination".to_string(),
+        0x06 => "Connection to the destination timed out".to_string(),
+        _ => format!("SOCKS5 CONNECT failed (reply {})", reply),
+    }
+}
+
+fn parse_url(url: &str) -> Result<TargetUrl, String> {
+    let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+        (true, rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        (false, rest)
+    } else {
+        return Err(format!(
+            "Unsupported URL (expected http:// or https://): {}",
+            url
+        ));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/"),
+    };
+    let default_port = if tls { 443 } else { 80 };
+    let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+        let (host, tail) = bracketed
+            .split_once(']')
+            .ok_or_else(|| format!("Invalid IPv6 host in URL: {}", url))?;
+        let port = match tail.strip_prefix(':') {
+            Some(port) => parse_port(port, url)?,
+            None if tail.is_empty() => default_port,
+            None => return Err(format!("Invalid URL authority: {}", url)),
+        };
+        (host, port)
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host, parse_port(port, url)?),
+            None => (authority, default_port),
+        }
+    };
+    if host.is_empty() {
+        return Err(format!("URL has no host: {}", url));
+    }
+    Ok(TargetUrl {
+        tls,
+        host: host.to_string(),
+        port,
+        path: path.to_string(),
+    })
+}
+
+fn parse_port(port: &str, url: &str) -> Result<u16, String> {
+    port.parse::<u16>()
+        .map_err(|_| format!("Invalid port in URL: {}", url))
+}
+
+fn parse_http_response(response: &[u8]) -> Result<(u16, &[u8]), String> {
+    let status_line_end = response
+        .windows(2)
+        .position(|window| window == b"\r\n")
+        .unwrap_or(response.len());
+    let status_line = String::from_utf8_lossy(&response[..status_line_end]);
+    let mut parts = status_line.split_whitespace();
+    let version = parts.next().unwrap_or("");
+    if !version.starts_with("HTTP/") {
+        return Err("Target did not answer with HTTP".to_string());
+    }
+    let status_code = parts
+        .next()
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| "Malformed HTTP status line".to_string())?;
+    let body = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|idx| &response[idx + 4..])
+        .unwrap_or(&[]);
+    Ok((status_code, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn parses_urls() {
+        let target = parse_url("https://example.com").expect("url");
+        assert!(target.tls);
+        assert_eq!((target.host.as_str(), target.port), ("example.com", 443));
+        assert_eq!(target.path, "/");
+
+        let target = parse_url("http://[2001:db8::1]:8080/generate_204?x=1").expect("url");
+        assert!(!target.tls);
+        assert_eq!((target.host.as_str(), target.port), ("2001:db8::1", 8080));
+        assert_eq!(target.path, "/generate_204?x=1");
+        assert_eq!(target.host_header(), "[2001:db8::1]:8080");
+
+        assert!(parse_url("ftp://example.com").is_err());
+        assert!(parse_url("http://example.com:99999/").is_err());
+        assert!(parse_url("http:///path").is_err());
+    }
+
+    #[test]
+    fn destination_table_is_bounded_and_tracks_fastest() {
+        let mut table = DestinationTable::new();
+        table.record("https://a".to_string(), Some(300));
+        table.record("https://a".to_string(), Some(100));
+        table.record("https://a".to_string(), Some(200));
+        table.record("https://a".to_string(), None);
+        let entry = &table.snapshot()["https://a"];
+        assert_eq!(entry.latency_ms, Some(200));
+        assert_eq!(entry.min_latency_ms, Some(100));
+        assert_eq!((entry.successes, entry.failures), (3, 1));
+
+        for index in 0..MAX_TRACKED_DESTINATIONS {
+            table.record(format!("https://site{}", index), Some(50));
+        }
+        let snapshot = table.snapshot();
+        assert_eq!(snapshot.len(), MAX_TRACKED_DESTINATIONS);
+        assert!(!snapshot.contains_key("https://a"));
+    }
+
+    #[test]
+    fn fetches_through_socks5_with_auth() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let mut greeting = [0u8; 4];
+            stream.read_exact(&mut greeting).expect("greeting");
+            assert_eq!(greeting, [0x05, 0x02, 0x00, 0x02]);
+            stream.write_all(&[0x05, 0x02]).expect("method");
+            let mut auth = [0u8; 2 + 4 + 1 + 6];
+            stream.read_exact(&mut auth).expect("auth");
+            assert_eq!(&auth[2..6], b"user");
+            assert_eq!(&auth[7..], b"secret");
+            stream.write_all(&[0x01, 0x00]).expect("auth reply");
+            let mut connect = [0u8; 5 + 11 + 2];
+            stream.read_exact(&mut connect).expect("connect");
+            assert_eq!(&connect[5..16], b"example.com");
+            assert_eq!(u16::from_be_bytes([connect[16], connect[17]]), 80);
+            stream
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .expect("connect reply");
+            let mut request = [0u8; 64];
+            let n = stream.read(&mut request).expect("request");
+            assert!(request[..n].starts_with(b"GET /ping HTTP/1.1\r\nHost: example.com\r\n"));
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\npong")
+                .expect("response");
+        });
+
+        let result = verify_connectivity(
+            addr,
+            "http://example.com/ping",
+            Some(SocksCredentials {
+                username: "user",
+                password: "secret",
+            }),
+            Duration::from_secs(5),
+            &AtomicBool::new(false),
+        );
+        server.join().expect("server");
+        assert!(result.success, "unexpected failure: {:?}", result.error);
+        assert_eq!(result.status_code, Some(200));
+        assert_eq!(result.snippet, "pong");
+    }
+
+    #[test]
+    fn exit_ip_is_looked_up_through_the_proxy_and_cached() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        // Serves a single lookup; a second one would find nobody listening.
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).expect("greeting");
+            stream.write_all(&[0x05, 0x00]).expect("method");
+            let mut connect = [0u8; 5 + 14 + 2];
+            stream.read_exact(&mut connect).expect("connect");
+            assert_eq!(&connect[5..19], b"ip.example.net");
+            stream
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .expect("connect reply");
+            let mut request = [0u8; 64];
+            let n = stream.read(&mut request).expect("request");
+            assert!(request[..n].starts_with(b"GET /ip HTTP/1.1\r\n"));
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 12\r\n\r\n203.0.113.7\n")
+                .expect("response");
+        });
+
+        let url = Some("http://ip.example.net/ip");
+        let cancel = AtomicBool::new(false);
+        let result = exit_ip(addr, url, None, Duration::from_secs(5), &cancel);
+        server.join().expect("server");
+        assert_eq!(
+            result.ip.as_deref(),
+            Some("203.0.113.7"),
+            "{:?}",
+            result.error
+        );
+        assert!(!result.cached);
+
+        let again = exit_ip(addr, url, None, Duration::from_secs(5), &cancel);
+        assert_eq!(again.ip.as_deref(), Some("203.0.113.7"));
+        assert!(again.cached);
+
+        // Another service is not answered from the cache, and its failure is clear.
+        let other = exit_ip(
+            addr,
+            Some("http://ip.example.org/"),
+            None,
+            Duration::from_secs(5),
+            &cancel,
+        );
+        assert!(other.ip.is_none());
+        assert!(other
+            .error
+            .expect("error")
+            .starts_with("Exit IP lookup failed: "));
+
+        assert!(parse_exit_ip("<html>blocked</html>").is_err());
+        assert_eq!(
+            parse_exit_ip(" 2001:db8::7\r\n"),
+            Ok("2001:db8::7".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn fetches_ipv6_destination() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).expect("greeting");
+            stream.write_all(&[0x05, 0x00]).expect("method");
+            let mut connect = [0u8; 4 + 16 + 2];
+            stream.read_exact(&mut connect).expect("connect");
+            assert_eq!(connect[3], 0x04);
+            let target: std::net::Ipv6Addr = "2001:db8::1".parse().expect("ip");
+            assert_eq!(&connect[4..20], &target.octets());
+            assert_eq!(u16::from_be_bytes([connect[20], connect[21]]), 8080);
+            // Bound address is an IPv6 socket on the server side.
+            let mut reply = vec![0x05, 0x00, 0x00, 0x04];
+            reply.extend_from_slice(
+                &"2001:db8::2"
+                    .parse::<std::net::Ipv6Addr>()
+                    .unwrap()
+                    .octets(),
+            );
+            reply.extend_from_slice(&40000u16.to_be_bytes());
+            stream.write_all(&reply).expect("connect reply");
+            let mut request = [0u8; 64];
+            let n = stream.read(&mut request).expect("request");
+            assert!(request[..n].starts_with(b"GET / HTTP/1.1\r\nHost: [2001:db8::1]:8080\r\n"));
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .expect("response");
+        });
+
+        let result = verify_connectivity(
+            addr,
+            "http://[2001:db8::1]:8080/",
+            None,
+            Duration::from_secs(5),
+            &AtomicBool::new(false),
+        );
+        server.join().expect("server");
+        assert!(result.success, "unexpected failure: {:?}", result.error);
+        assert_eq!(result.status_code, Some(204));
+    }
+
+    #[test]
+    fn reports_ipv6_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).expect("greeting");
+            stream.write_all(&[0x05, 0x00]).expect("method");
+            let mut connect = [0u8; 4 + 16 + 2];
+            stream.read_exact(&mut connect).expect("connect");
+            stream
+                .write_all(&[0x05, 0x03, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .expect("connect reply");
+        });
+        let result = verify_connectivity(
+            addr,
+            "http://[2001:db8::1]/",
+            None,
+            Duration::from_secs(5),
+            &AtomicBool::new(false),
+        );
+        server.join().expect("server");
+        assert!(!result.success);
+        assert!(result
+            .error
+            .expect("error")
+            .starts_with("IPv6 destination unreachable"));
+    }
+
+    #[test]
+    fn cancel_stops_a_stalled_probe() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).expect("greeting");
+            // Never answer so the probe blocks until cancelled.
+            let mut rest = [0u8; 1];
+            let _ = stream.read(&mut rest);
+        });
+        let cancel = Arc::new(AtomicBool::new(false));
+        let canceller = {
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(200));
+                cancel.store(true, Ordering::SeqCst);
+            })
+        };
+        let started = Instant::now();
+        let result = verify_connectivity(
+            addr,
+            "http://example.com/",
+            None,
+            Duration::from_secs(30),
+            &cancel,
+        );
+        canceller.join().expect("canceller");
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(!result.success);
+        assert_eq!(result.error.as_deref(), Some("Cancelled"));
+        drop(result);
+        server.join().expect("server");
+    }
+
+    #[test]
+    fn reports_socks_failure() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).expect("greeting");
+            stream.write_all(&[0x05, 0xff]).expect("method");
+        });
+        let result = verify_connectivity(
+            addr,
+            "http://example.com/",
+            None,
+            Duration::from_secs(5),
+            &AtomicBool::new(false),
+        );
+        server.join().expect("server");
+        assert!(!result.success);
+        assert!(result.status_code.is_none());
+        assert!(result.error.expect("error").contains("refused auth"));
+    }
+
+    #[test]
+    fn speed_test_measures_echoed_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("accept");
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).expect("greeting");
+            stream.write_all(&[0x05, 0x00]).expect("method");
+            let mut connect = [0u8; 4 + 4 + 2];
+            stream.read_exact(&mut connect).expect("connect");
+            assert_eq!(u16::from_be_bytes([connect[8], connect[9]]), 7);
+            stream
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+                .expect("connect reply");
+            let mut buf = [0u8; 4096];
+            loop {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => stream.write_all(&buf[..n]).expect("echo"),
+                }
+            }
+        });
+
+        let result = run_speed_test(
+            addr,
+            "127.0.0.1",
+            7,
+            None,
+            Duration::from_millis(200),
+            &AtomicBool::new(false),
+        );
+        server.join().expect("server");
+        assert!(result.success, "unexpected failure: {:?}", result.error);
+        assert!(result.upload_bytes > 0);
+        assert_eq!(result.download_bytes, result.upload_bytes);
+        assert!(result.download_bps > 0);
+        assert!(!SPEED_TEST_RUNNING.load(Ordering::SeqCst));
+    }
+}
@@ -2,6 +2,7 @@ use std::sync::OnceLock;
 
 const DEFAULT_STREAM_QUEUE_MAX_BYTES: usize = 2 * 1024 * 1024;
 const DEFAULT_CONN_RESERVE_BYTES: usize = 64 * 1024;
+const DEFAULT_STREAM_BACKPRESSURE_BYTES: usize = DEFAULT_STREAM_QUEUE_MAX_BYTES / 2;
 
 #[derive(Debug, Default)]
 pub struct FlowControlState {
@@ -77,6 +78,9 @@ pub struct StreamReceiveConfig {
     pub multi_stream: bool,
     pub reserve_bytes: usize,
     pub max_queue: usize,
+    /// In multi-stream mode, queue level past which data is no longer consumed
+    /// on receive; `None` always consumes on receive.
+    pub backpressure_bytes: Option<usize>,
 }
 
 impl StreamReceiveConfig {
@@ -90,6 +94,7 @@ impl StreamReceiveConfig {
             multi_stream,
             reserve_bytes,
             max_queue,
+            backpressure_bytes: None,
         }
     }
 }
@@ -153,6 +158,37 @@ pub fn conn_reserve_bytes() -> usize {
     })
 }
 
+/// Queue level at which a stream in multi-stream mode starts holding back flow
+/// control credit, or `None` when disabled with 0.
+pub fn stream_backpressure_bytes() -> Option<usize> {
+    static BACKPRESSURE_BYTES: OnceLock<usize> = OnceLock::new();
+    let bytes = *BACKPRESSURE_BYTES.get_or_init(|| {
+        std::env::var("SLIPSTREAM_STREAM_BACKPRESSURE_BYTES")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_STREAM_BACKPRESSURE_BYTES)
+    });
+    (bytes > 0).then_some(bytes)
+}
+
+/// Consumed offset for a stream in multi-stream mode.
+///
+/// Data is consumed on receive until the stream queues more than
+/// `backpressure_bytes` for its writer. Past that, the excess stays unconsumed,
+/// so the peer's flow control credit only grows as fast as the writer drains.
+pub fn multi_stream_target_offset(
+    rx_bytes: u64,
+    queued_bytes: usize,
+    backpressure_bytes: Option<usize>,
+) -> u64 {
+    match backpressure_bytes {
+        Some(limit) if queued_bytes > limit => {
+            rx_bytes.saturating_sub((queued_bytes - limit) as u64)
+        }
+        _ => rx_bytes,
+    }
+}
+
 pub fn reserve_target_offset(
     rx_bytes: u64,
     queued_bytes: usize,
@@ -358,7 +394,7 @@ where
         if !discarding
             && !consume_stream_data(
                 &mut consumed_offset,
-                rx_bytes,
+                multi_stream_target_offset(rx_bytes, queued_bytes, config.backpressure_bytes),
                 &mut ops.consume,
                 &mut ops.on_consume_error,
             )
@@ -392,3 +428,83 @@ where
 
     reset_stream
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: usize = 1_000;
+    const STREAM_WINDOW: u64 = 20_000;
+    const BACKPRESSURE: usize = 10_000;
+    const DRAIN_PER_TICK: usize = 500;
+
+    #[derive(Default)]
+    struct Stream {
+        flow: FlowControlState,
+    }
+
+    impl HasFlowControlState for Stream {
+        fn flow_control(&self) -> &FlowControlState {
+            &self.flow
+        }
+
+        fn flow_control_mut(&mut self) -> &mut FlowControlState {
+            &mut self.flow
+        }
+    }
+
+    /// Bytes a peer pushes over 100 ticks into a stream whose writer drains
+    /// slowly. Each tick, the peer sends up to the credit it last heard of: the
+    /// consumed offset plus the stream window.
+    fn peer_sent_bytes(backpressure_bytes: Option<usize>) -> u64 {
+        let mut stream = Stream::default();
+        let mut consumed = 0u64;
+        for _ in 0..100 {
+            let credit = consumed + STREAM_WINDOW;
+            while stream.flow.rx_bytes + CHUNK as u64 <= credit {
+                let config = StreamReceiveConfig {
+                    backpressure_bytes,
+                    ..StreamReceiveConfig::new(true, 0)
+                };
+                let reset = handle_stream_receive(
+                    &mut stream,
+                    CHUNK,
+                    config,
+                    StreamReceiveOps {
+                        enqueue: |_: &mut Stream| Ok(()),
+                        on_overflow: |_: &mut Stream| {},
+                        consume: |offset| {
+                            consumed = offset;
+                            0
+                        },
+                        stop_sending: || {},
+                        log_overflow: |_, _, _| {},
+                        on_consume_error: |_, _, _| {},
+                    },
+                );
+                assert!(!reset);
+                assert!(!stream.flow.discarding, "the stream overflowed");
+            }
+            let flow = &mut stream.flow;
+            flow.queued_bytes = flow.queued_bytes.saturating_sub(DRAIN_PER_TICK);
+            let target =
+                multi_stream_target_offset(flow.rx_bytes, flow.queued_bytes, backpressure_bytes);
+            consumed = consumed.max(target);
+            flow.consumed_offset = consumed;
+        }
+        stream.flow.rx_bytes
+    }
+
+    #[test]
+    fn backpressure_limits_the_peer_to_the_drain_rate() {
+        let drained = (100 * DRAIN_PER_TICK) as u64;
+        let throttled = peer_sent_bytes(Some(BACKPRESSURE));
+        // The peer stays within the drained bytes, the queue allowance and one window.
+        assert!(throttled <= drained + BACKPRESSURE as u64 + STREAM_WINDOW);
+        assert!(throttled >= drained);
+
+        // Consuming on receive lets the peer push a window ahead on every tick.
+        let unthrottled = peer_sent_bytes(None);
+        assert!(unthrottled > 10 * throttled);
+    }
+}
@@ -93,3 +93,184 @@ pub fn insert_stream_chunk(
 
     inserted
 }
+
+/// Buffers an out-of-order chunk, refusing it when it reaches past the reorder
+/// window.
+///
+/// The window is counted from `sent_offset`, so at most `window` bytes wait for
+/// a gap to fill. A refused chunk leaves the state untouched; the caller stalls
+/// the stream and relies on the sender's retransmission once the gap drains.
+/// Returns the number of new bytes buffered, or `None` on overflow.
+pub fn buffer_stream_chunk(
+    state: &mut StreamRecvState,
+    offset: u64,
+    data: &[u8],
+    window: usize,
+) -> Option<usize> {
+    let end = offset.saturating_add(data.len() as u64);
+    if end > state.sent_offset.saturating_add(window as u64) {
+        return None;
+    }
+    let inserted = insert_stream_chunk(&mut state.chunks, state.sent_offset, offset, data);
+    state.buffered_bytes = state.buffered_bytes.saturating_add(inserted);
+    Some(inserted)
+}
+
+/// Removes the in-order prefix of the buffer and advances `sent_offset` past it.
+pub fn take_contiguous(state: &mut StreamRecvState) -> Vec<u8> {
+    let mut out = Vec::new();
+    while let Some(entry) = state.chunks.first_entry() {
+        if *entry.key() != state.sent_offset {
+            break;
+        }
+        let chunk = entry.remove();
+        state.sent_offset = state.sent_offset.saturating_add(chunk.len() as u64);
+        state.buffered_bytes = state.buffered_bytes.saturating_sub(chunk.len());
+        out.extend_from_slice(&chunk);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shuffled<T>(mut items: Vec<T>, mut seed: u64) -> Vec<T> {
+        for i in (1..items.len()).rev() {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            items.swap(i, (seed >> 33) as usize % (i + 1));
+        }
+        items
+    }
+
+    #[test]
+    fn heavy_reordering_reassembles_in_order() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(64 * 1024).collect();
+        let mut frames: Vec<(u64, &[u8])> = data
+            .chunks(173)
+            .enumerate()
+            .map(|(idx, chunk)| ((idx * 173) as u64, chunk))
+            .collect();
+        // Duplicates and overlapping retransmissions on top of the shuffle.
+        frames.extend(frames.clone().into_iter().step_by(7));
+        frames.push((100, &data[100..900]));
+        let frames = shuffled(frames, 0x5eed);
+
+        let mut state = StreamRecvState::new();
+        for (offset, chunk) in frames {
+            buffer_stream_chunk(&mut state, offset, chunk, usize::MAX).expect("unbounded");
+        }
+        assert_eq!(take_contiguous(&mut state), data);
+        assert_eq!(state.buffered_bytes, 0);
+        assert!(state.chunks.is_empty());
+    }
+
+    #[test]
+    fn reorder_window_overflow_stalls_until_retransmit() {
+        let data: Vec<u8> = (0..=255u8).cycle().take(20 * 100).collect();
+        let window = 500;
+        let mut pending: Vec<(u64, &[u8])> = shuffled(
+            data.chunks(100)
+                .enumerate()
+                .map(|(idx, chunk)| ((idx * 100) as u64, chunk))
+                .collect(),
+            42,
+        );
+
+        let mut state = StreamRecvState::new();
+        let mut out = Vec::new();
+        let mut refused = 0;
+        while !pending.is_empty() {
+            // Every round "retransmits" whatever was refused before.
+            let mut retry = Vec::new();
+            for (offset, chunk) in pending {
+                if buffer_stream_chunk(&mut state, offset, chunk, window).is_none() {
+                    refused += 1;
+                    retry.push((offset, chunk));
+                }
+                assert!(state.buffered_bytes <= window);
+            }
+            out.extend(take_contiguous(&mut state));
+            pending = retry;
+        }
+        assert!(refused > 0, "the window must have overflowed");
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn connection_cap_bounds_reassembly_under_heavy_reordering() {
+        // QUIC connection flow control as picoquic applies a max_data cap: the
+        // sender may reach `consumed + cap` in total over all streams, and on
+        // the receiver that bounds everything buffered, gaps included.
+        let cap = 2_000u64;
+        let streams: Vec<Vec<u8>> = (0..3u8)
+            .map(|id| (0..=255u8).cycle().skip(id as usize).take(9_000).collect())
+            .collect();
+        let mut next_offset = vec![0u64; streams.len()];
+        let mut lost: Vec<(usize, u64, usize)> = Vec::new();
+        let mut states: Vec<StreamRecvState> =
+            streams.iter().map(|_| StreamRecvState::new()).collect();
+        let mut outputs: Vec<Vec<u8>> = vec![Vec::new(); streams.len()];
+        let mut consumed = 0u64;
+        let mut seed = 7u64;
+        let mut stalled_rounds = 0;
+        for _ in 0..10_000 {
+            if outputs == streams {
+                break;
+            }
+            // Retransmissions first, then new data within the credit.
+            let mut flight = std::mem::take(&mut lost);
+            let mut sent_total: u64 = next_offset.iter().sum();
+            let mut credit_left = consumed + cap - sent_total;
+            if credit_left == 0 {
+                stalled_rounds += 1;
+            }
+            for (id, data) in streams.iter().enumerate() {
+                while credit_left > 0 && (next_offset[id] as usize) < data.len() {
+                    let len = 150
+                        .min(credit_left as usize)
+                        .min(data.len() - next_offset[id] as usize);
+                    flight.push((id, next_offset[id], len));
+                    next_offset[id] += len as u64;
+                    sent_total += len as u64;
+                    credit_left -= len as u64;
+                }
+            }
+            // Deliver in random order and lose about a quarter of the packets.
+            for (id, offset, len) in shuffled(flight, seed) {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                if (seed >> 60) < 4 {
+                    lost.push((id, offset, len));
+                    continue;
+                }
+                let chunk = &streams[id][offset as usize..offset as usize + len];
+                buffer_stream_chunk(&mut states[id], offset, chunk, usize::MAX).expect("unbounded");
+                let buffered: usize = states.iter().map(|state| state.buffered_bytes).sum();
+                assert!(buffered as u64 <= cap);
+            }
+            for (id, state) in states.iter_mut().enumerate() {
+                let ready = take_contiguous(state);
+                consumed += ready.len() as u64;
+                outputs[id].extend(ready);
+            }
+            assert!(sent_total - consumed <= cap);
+        }
+        assert_eq!(outputs, streams);
+        assert!(stalled_rounds > 0, "the cap must have stalled the sender");
+    }
+
+    #[test]
+    fn refused_chunk_leaves_state_untouched() {
+        let mut state = StreamRecvState::new();
+        assert_eq!(buffer_stream_chunk(&mut state, 10, b"abc", 8), None);
+        assert!(state.chunks.is_empty());
+        assert_eq!(state.buffered_bytes, 0);
+        assert_eq!(buffer_stream_chunk(&mut state, 5, b"abc", 8), Some(3));
+        assert!(take_contiguous(&mut state).is_empty());
+        assert_eq!(buffer_stream_chunk(&mut state, 0, b"01234", 8), Some(5));
+        assert_eq!(take_contiguous(&mut state), b"01234abc");
+        assert_eq!(state.sent_offset, 8);
+    }
+}
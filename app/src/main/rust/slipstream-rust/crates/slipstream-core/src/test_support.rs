@@ -1,5 +1,9 @@
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+mod resolver;
+
+pub use resolver::{Fault, FaultScript, FaultStats, FaultyResolver};
+
 pub struct FailureCounter {
     remaining: AtomicUsize,
 }
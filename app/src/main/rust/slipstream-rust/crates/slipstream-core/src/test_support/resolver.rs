@@ -0,0 +1,319 @@
+//! A resolver that mistreats responses on purpose.
+//!
+//! [`FaultyResolver`] sits between a client and the server's DNS port as a UDP
+//! relay. Queries pass through untouched; each response gets the next
+//! [`Fault`] of its [`FaultScript`], so one test can throw corruption, loss,
+//! duplicates, reordering, truncation and delay at the whole pipeline at once
+//! and check that the data still arrives intact.
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long the relay sleeps while no datagram arrives.
+const RELAY_TICK: Duration = Duration::from_millis(1);
+/// A reordered response goes out after the next response or after this long.
+const REORDER_HOLD: Duration = Duration::from_millis(200);
+/// Corruption lands within this many bytes of the end, where the answer data is.
+const CORRUPT_TAIL: usize = 32;
+/// DNS header flag a resolver sets on a response cut to fit.
+const DNS_FLAG_TC: u8 = 0x02;
+
+/// What happens to one response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Pass,
+    Drop,
+    /// Flips one byte near the end of the response, in its answer data.
+    Corrupt,
+    /// Sends the response twice.
+    Duplicate,
+    /// Holds the response back until the one after it was sent.
+    Reorder,
+    /// Keeps the first half of the response and sets the TC flag.
+    Truncate,
+    Delay(Duration),
+}
+
+/// The faults applied to responses, in arrival order.
+#[derive(Debug, Clone)]
+pub struct FaultScript {
+    faults: Vec<Fault>,
+    repeat: bool,
+    next: usize,
+}
+
+impl FaultScript {
+    /// Applies `faults` to the first responses and passes the rest.
+    pub fn new(faults: Vec<Fault>) -> Self {
+        Self {
+            faults,
+            repeat: false,
+            next: 0,
+        }
+    }
+
+    /// Starts over from the first fault once the script ran out.
+    pub fn repeat(mut self) -> Self {
+        self.repeat = true;
+        self
+    }
+
+    fn next_fault(&mut self) -> Fault {
+        if self.next == self.faults.len() && self.repeat {
+            self.next = 0;
+        }
+        match self.faults.get(self.next) {
+            Some(fault) => {
+                self.next += 1;
+                *fault
+            }
+            None => Fault::Pass,
+        }
+    }
+}
+
+/// How many responses got each fault.
+#[derive(Debug, Default)]
+pub struct FaultStats {
+    pub queries: AtomicU64,
+    pub responses: AtomicU64,
+    pub passed: AtomicU64,
+    pub dropped: AtomicU64,
+    pub corrupted: AtomicU64,
+    pub duplicated: AtomicU64,
+    pub reordered: AtomicU64,
+    pub truncated: AtomicU64,
+    pub delayed: AtomicU64,
+}
+
+impl FaultStats {
+    fn count(&self, fault: Fault) {
+        let counter = match fault {
+            Fault::Pass => &self.passed,
+            Fault::Drop => &self.dropped,
+            Fault::Corrupt => &self.corrupted,
+            Fault::Duplicate => &self.duplicated,
+            Fault::Reorder => &self.reordered,
+            Fault::Truncate => &self.truncated,
+            Fault::Delay(_) => &self.delayed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// UDP relay from one client to `upstream` that applies a [`FaultScript`] to
+/// the responses. Stops when dropped.
+pub struct FaultyResolver {
+    pub addr: SocketAddr,
+    stats: Arc<FaultStats>,
+    script: Arc<Mutex<FaultScript>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl FaultyResolver {
+    pub fn spawn(upstream: SocketAddr, script: FaultScript) -> io::Result<Self> {
+        let client_side = UdpSocket::bind("127.0.0.1:0")?;
+        client_side.set_nonblocking(true)?;
+        let upstream_side = UdpSocket::bind("127.0.0.1:0")?;
+        upstream_side.connect(upstream)?;
+        upstream_side.set_nonblocking(true)?;
+        let addr = client_side.local_addr()?;
+        let stats = Arc::new(FaultStats::default());
+        let script = Arc::new(Mutex::new(script));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut relay = Relay {
+            client_side,
+            upstream_side,
+            client: None,
+            stats: Arc::clone(&stats),
+            script: Arc::clone(&script),
+            held: Vec::new(),
+            reordered: None,
+        };
+        let stop_flag = Arc::clone(&stop);
+        let handle = thread::spawn(move || {
+            while !stop_flag.load(Ordering::Relaxed) {
+                match relay.turn() {
+                    Ok(true) => {}
+                    Ok(false) => thread::sleep(RELAY_TICK),
+                    Err(_) => break,
+                }
+            }
+        });
+        Ok(Self {
+            addr,
+            stats,
+            script,
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn stats(&self) -> &FaultStats {
+        &self.stats
+    }
+
+    /// Replaces the script; its first fault goes to the next response.
+    pub fn set_script(&self, script: FaultScript) {
+        *self.script.lock().expect("lock fault script") = script;
+    }
+}
+
+impl Drop for FaultyResolver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct Relay {
+    client_side: UdpSocket,
+    upstream_side: UdpSocket,
+    /// Where responses go: the source of the latest query.
+    client: Option<SocketAddr>,
+    stats: Arc<FaultStats>,
+    script: Arc<Mutex<FaultScript>>,
+    /// Delayed responses and when they are due.
+    held: Vec<(Instant, Vec<u8>)>,
+    /// The response held back by `Reorder`, with when it goes out regardless.
+    reordered: Option<(Instant, Vec<u8>)>,
+}
+
+impl Relay {
+    /// Moves every waiting query and response; false when there was none.
+    fn turn(&mut self) -> io::Result<bool> {
+        let mut buf = [0u8; 4096];
+        let mut moved = false;
+        loop {
+            match self.client_side.recv_from(&mut buf) {
+                Ok((size, peer)) => {
+                    self.client = Some(peer);
+                    self.stats.queries.fetch_add(1, Ordering::Relaxed);
+                    skip_refused(self.upstream_side.send(&buf[..size]))?;
+                    moved = true;
+                }
+                Err(err) if is_idle(&err) || err.kind() == io::ErrorKind::ConnectionRefused => {
+                    break
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        loop {
+            match self.upstream_side.recv(&mut buf) {
+                Ok(size) => {
+                    self.respond(buf[..size].to_vec())?;
+                    moved = true;
+                }
+                // Refused reports an earlier query the server was not up for.
+                Err(err) if is_idle(&err) || err.kind() == io::ErrorKind::ConnectionRefused => {
+                    break
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        self.release_due(Instant::now())?;
+        Ok(moved)
+    }
+
+    fn respond(&mut self, mut response: Vec<u8>) -> io::Result<()> {
+        let index = self.stats.responses.fetch_add(1, Ordering::Relaxed);
+        let fault = self.script.lock().expect("lock fault script").next_fault();
+        self.stats.count(fault);
+        let now = Instant::now();
+        match fault {
+            Fault::Pass => self.send(&response)?,
+            Fault::Drop => {}
+            Fault::Corrupt => {
+                corrupt(&mut response, index);
+                self.send(&response)?;
+            }
+            Fault::Duplicate => {
+                self.send(&response)?;
+                self.send(&response)?;
+            }
+            Fault::Reorder => {
+                if let Some((_, earlier)) = self.reordered.replace((now + REORDER_HOLD, response)) {
+                    self.send(&earlier)?;
+                }
+                return Ok(());
+            }
+            Fault::Truncate => {
+                truncate(&mut response);
+                self.send(&response)?;
+            }
+            Fault::Delay(delay) => {
+                self.held.push((now + delay, response));
+                return Ok(());
+            }
+        }
+        // The response after a reordered one has gone out; now the held one.
+        if let Some((_, held)) = self.reordered.take() {
+            self.send(&held)?;
+        }
+        Ok(())
+    }
+
+    fn release_due(&mut self, now: Instant) -> io::Result<()> {
+        if self.reordered.as_ref().is_some_and(|(due, _)| *due <= now) {
+            if let Some((_, held)) = self.reordered.take() {
+                self.send(&held)?;
+            }
+        }
+        let mut index = 0;
+        while index < self.held.len() {
+            if self.held[index].0 <= now {
+                let (_, response) = self.held.swap_remove(index);
+                self.send(&response)?;
+            } else {
+                index += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn send(&self, response: &[u8]) -> io::Result<()> {
+        match self.client {
+            Some(client) => skip_refused(self.client_side.send_to(response, client)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Flips a byte among the last `CORRUPT_TAIL`, a different one each time.
+fn corrupt(response: &mut [u8], index: u64) {
+    if response.is_empty() {
+        return;
+    }
+    let tail = response.len().min(CORRUPT_TAIL);
+    let offset = response.len() - 1 - (index as usize % tail);
+    response[offset] ^= 0x5a;
+}
+
+fn truncate(response: &mut Vec<u8>) {
+    response.truncate(response.len() / 2);
+    if response.len() > 2 {
+        response[2] |= DNS_FLAG_TC;
+    }
+}
+
+fn is_idle(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+    )
+}
+
+fn skip_refused(result: io::Result<usize>) -> io::Result<()> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => Ok(()),
+        Err(err) => Err(err),
+    }
+}
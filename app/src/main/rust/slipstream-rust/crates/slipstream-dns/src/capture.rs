@@ -0,0 +1,268 @@
+//! Line-oriented capture format for raw DNS wire exchanges.
+//!
+//! Each record is one line: a direction tag (`Q` for client queries, `R` for
+//! resolver responses) followed by a space and the packet bytes in lowercase hex.
+//! Blank lines and lines starting with `#` are ignored, except that a capture
+//! may open with the `CAPTURE_HEADER` version line. Captures only ever hold
+//! wire bytes, so tunneled payloads stay QUIC-encrypted.
+
+use crate::codec::{
+    decode_query_with_domains, decode_response, decode_response_with_ttl, decode_server_time,
+    response_rcode,
+};
+use crate::types::{DecodeQueryError, DnsError, Rcode};
+use std::fmt::Write as _;
+
+/// Format version of the capture files written by this build.
+pub const CAPTURE_VERSION: u32 = 1;
+/// First line of a capture file; older captures without it read as version 1.
+pub const CAPTURE_HEADER: &str = "# slipstream-capture v1";
+const CAPTURE_HEADER_PREFIX: &str = "# slipstream-capture v";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureDirection {
+    Query,
+    Response,
+}
+
+impl CaptureDirection {
+    fn tag(self) -> char {
+        match self {
+            CaptureDirection::Query => 'Q',
+            CaptureDirection::Response => 'R',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub direction: CaptureDirection,
+    pub packet: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayOutcome {
+    /// The packet decoded and carried a tunnel payload of the given length.
+    Payload(usize),
+    /// The query was rejected and the server would answer with this rcode.
+    Reply(Rcode),
+    /// The packet did not decode and would be dropped.
+    Drop,
+}
+
+pub fn format_capture_record(direction: CaptureDirection, packet: &[u8]) -> String {
+    let mut line = String::with_capacity(2 + packet.len() * 2);
+    line.push(direction.tag());
+    line.push(' ');
+    line.push_str(&encode_hex(packet));
+    line
+}
+
+pub fn parse_capture(input: &str) -> Result<Vec<CaptureRecord>, DnsError> {
+    let mut records = Vec::new();
+    for (idx, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if let Some(version) = line.strip_prefix(CAPTURE_HEADER_PREFIX) {
+            match version.parse::<u32>() {
+                Ok(version) if version <= CAPTURE_VERSION => continue,
+                _ => {
+                    return Err(DnsError::new(format!(
+                        "capture line {}: unsupported capture version {:?}",
+                        idx + 1,
+                        version
+                    )))
+                }
+            }
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (tag, hex) = line
+            .split_once(' ')
+            .ok_or_else(|| DnsError::new(format!("capture line {}: missing packet", idx + 1)))?;
+        let direction = match tag {
+            "Q" => CaptureDirection::Query,
+            "R" => CaptureDirection::Response,
+            _ => {
+                return Err(DnsError::new(format!(
+                    "capture line {}: unknown direction {:?}",
+                    idx + 1,
+                    tag
+                )))
+            }
+        };
+        let packet = decode_hex(hex.trim())
+            .ok_or_else(|| DnsError::new(format!("capture line {}: invalid hex", idx + 1)))?;
+        records.push(CaptureRecord { direction, packet });
+    }
+    Ok(records)
+}
+
+pub fn replay_record(record: &CaptureRecord, domains: &[&str]) -> ReplayOutcome {
+    match record.direction {
+        CaptureDirection::Query => match decode_query_with_domains(&record.packet, domains) {
+            Ok(query) => ReplayOutcome::Payload(query.payload.len()),
+            Err(DecodeQueryError::Reply { rcode, .. }) => ReplayOutcome::Reply(rcode),
+            Err(DecodeQueryError::Drop) => ReplayOutcome::Drop,
+        },
+        CaptureDirection::Response => match decode_response(&record.packet) {
+            Some(payload) => ReplayOutcome::Payload(payload.len()),
+            None => ReplayOutcome::Drop,
+        },
+    }
+}
+
+/// Renders what the decode path makes of every record, one line each.
+///
+/// Payload bytes are included in full, so diffing the transcript of a capture
+/// against a stored golden copy catches any change in how real traffic decodes.
+/// Queries are decoded as the server sees them; responses as the client does,
+/// with the rcode, answer TTL and server time stamp it would act on.
+pub fn replay_transcript(records: &[CaptureRecord], domains: &[&str]) -> String {
+    let mut out = String::new();
+    for (idx, record) in records.iter().enumerate() {
+        let _ = write!(
+            out,
+            "{} {} len={}",
+            idx,
+            record.direction.tag(),
+            record.packet.len()
+        );
+        match record.direction {
+            CaptureDirection::Query => match decode_query_with_domains(&record.packet, domains) {
+                Ok(query) => {
+                    let _ = write!(out, " payload={}", encode_hex(&query.payload));
+                }
+                Err(DecodeQueryError::Reply { rcode, .. }) => {
+                    let _ = write!(out, " reply={:?}", rcode);
+                }
+                Err(DecodeQueryError::Drop) => out.push_str(" drop"),
+            },
+            CaptureDirection::Response => {
+                match response_rcode(&record.packet) {
+                    Some(rcode) => {
+                        let _ = write!(out, " rcode={:?}", rcode);
+                    }
+                    None => out.push_str(" rcode=?"),
+                }
+                match decode_response_with_ttl(&record.packet) {
+                    Some((payload, ttl)) => {
+                        let _ = write!(out, " ttl={} payload={}", ttl, encode_hex(&payload));
+                    }
+                    None => out.push_str(" no-payload"),
+                }
+                if let Some(server_time_ms) = decode_server_time(&record.packet) {
+                    let _ = write!(out, " server_time_ms={}", server_time_ms);
+                }
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        out.push(hex_digit(byte >> 4));
+        out.push(hex_digit(byte & 0x0f));
+    }
+    out
+}
+
+fn hex_digit(value: u8) -> char {
+    char::from_digit(value as u32, 16).unwrap_or('0')
+}
+
+fn decode_hex(input: &str) -> Option<Vec<u8>> {
+    if !input.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len() / 2);
+    for pair in bytes.chunks(2) {
+        let hi = (pair[0] as char).to_digit(16)?;
+        let lo = (pair[1] as char).to_digit(16)?;
+        out.push(((hi << 4) | lo) as u8);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_qname;
+    use crate::codec::{encode_query, encode_response};
+    use crate::types::{
+        QueryParams, Question, ResponseParams, CLASS_IN, DEFAULT_RESPONSE_TTL, RR_TXT,
+    };
+
+    #[test]
+    fn capture_round_trips_and_replays() {
+        let qname = build_qname(b"hello", "test.com").expect("qname");
+        let query = encode_query(&QueryParams {
+            id: 7,
+            qname: &qname,
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+            rd: true,
+            cd: false,
+            qdcount: 1,
+            is_query: true,
+        })
+        .expect("query");
+        let question = Question {
+            name: qname.clone(),
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+        };
+        let response = encode_response(&ResponseParams {
+            id: 7,
+            rd: true,
+            cd: false,
+            question: &question,
+            payload: Some(b"world!"),
+            rcode: None,
+            ttl: DEFAULT_RESPONSE_TTL,
+            server_time_ms: None,
+            poll_hold_ms: None,
+        })
+        .expect("response");
+
+        let capture = format!(
+            "# sample\n{}\n\n{}\n",
+            format_capture_record(CaptureDirection::Query, &query),
+            format_capture_record(CaptureDirection::Response, &response)
+        );
+        let records = parse_capture(&capture).expect("parse");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].packet, query);
+        assert_eq!(records[1].packet, response);
+        assert_eq!(
+            replay_record(&records[0], &["test.com"]),
+            ReplayOutcome::Payload(5)
+        );
+        assert_eq!(
+            replay_record(&records[1], &["test.com"]),
+            ReplayOutcome::Payload(6)
+        );
+    }
+
+    #[test]
+    fn parse_capture_rejects_malformed_lines() {
+        assert!(parse_capture("X 00").is_err());
+        assert!(parse_capture("Q 0").is_err());
+        assert!(parse_capture("Q zz").is_err());
+        assert!(parse_capture("Q").is_err());
+    }
+
+    #[test]
+    fn capture_header_is_versioned() {
+        let capture = format!("{}\nQ 00\n", CAPTURE_HEADER);
+        assert_eq!(parse_capture(&capture).expect("parse").len(), 1);
+        // Captures from before the header still load.
+        assert_eq!(parse_capture("Q 00\n").expect("parse").len(), 1);
+        assert!(parse_capture("# slipstream-capture v2\nQ 00\n").is_err());
+        assert!(parse_capture("# slipstream-capture vX\nQ 00\n").is_err());
+    }
+}
@@ -1,15 +1,18 @@
 use crate::base32;
 use crate::dots;
+use crate::safe;
 
 use crate::name::{encode_name, extract_subdomain_multi, parse_name};
 use crate::types::{
-    DecodeQueryError, DecodedQuery, DnsError, QueryParams, Rcode, ResponseParams, EDNS_UDP_PAYLOAD,
-    RR_OPT, RR_TXT,
+    DecodeQueryError, DecodedQuery, DnsError, QueryParams, Rcode, ResponseParams, CLASS_IN,
+    EDNS_OPTION_POLL_HOLD, EDNS_OPTION_SERVER_TIME, EDNS_OPTION_TCP_KEEPALIVE, EDNS_UDP_PAYLOAD,
+    RR_CNAME, RR_OPT, RR_TXT,
 };
 use crate::wire::{
     parse_header, parse_question, parse_question_for_reply, read_u16, read_u32, write_u16,
     write_u32,
 };
+use std::time::Duration;
 
 pub fn decode_query(packet: &[u8], domain: &str) -> Result<DecodedQuery, DecodeQueryError> {
     decode_query_with_domains(packet, &[domain])
@@ -77,7 +80,8 @@ pub fn decode_query_with_domains(
         }
     };
 
-    let undotted = dots::undotify(&subdomain_raw);
+    let safe_raw = safe::strip_marker(&subdomain_raw);
+    let undotted = dots::undotify(safe_raw.unwrap_or(&subdomain_raw));
     if undotted.is_empty() {
         return Err(DecodeQueryError::Reply {
             id: header.id,
@@ -88,9 +92,13 @@ pub fn decode_query_with_domains(
         });
     }
 
-    let payload = match base32::decode(&undotted) {
+    let decoded = match safe_raw {
+        Some(_) => safe::decode(&undotted).ok_or(()),
+        None => base32::decode(&undotted).map_err(|_| ()),
+    };
+    let payload = match decoded {
         Ok(payload) => payload,
-        Err(_) => {
+        Err(()) => {
             return Err(DecodeQueryError::Reply {
                 id: header.id,
                 rd,
@@ -111,6 +119,23 @@ pub fn decode_query_with_domains(
 }
 
 pub fn encode_query(params: &QueryParams<'_>) -> Result<Vec<u8>, DnsError> {
+    encode_query_with_options(params, &[])
+}
+
+/// Like `encode_query`, for a TCP or DoT resolver connection.
+///
+/// Adds an empty edns-tcp-keepalive option (RFC 7828) asking the resolver how
+/// long it keeps an idle connection open; read the answer with
+/// `decode_tcp_keepalive`. The two-byte TCP length prefix is not included. The
+/// option must not be sent over UDP.
+pub fn encode_tcp_query(params: &QueryParams<'_>) -> Result<Vec<u8>, DnsError> {
+    encode_query_with_options(params, &[(EDNS_OPTION_TCP_KEEPALIVE, &[])])
+}
+
+pub(crate) fn encode_query_with_options(
+    params: &QueryParams<'_>,
+    options: &[(u16, &[u8])],
+) -> Result<Vec<u8>, DnsError> {
     let mut out = Vec::with_capacity(256);
     let mut flags = 0u16;
     if !params.is_query {
@@ -136,7 +161,7 @@ pub fn encode_query(params: &QueryParams<'_>) -> Result<Vec<u8>, DnsError> {
         write_u16(&mut out, params.qclass);
     }
 
-    encode_opt_record(&mut out)?;
+    encode_opt_record(&mut out, options)?;
 
     Ok(out)
 }
@@ -182,7 +207,7 @@ pub fn encode_response(params: &ResponseParams<'_>) -> Result<Vec<u8>, DnsError>
         out.extend_from_slice(&[0xC0, 0x0C]);
         write_u16(&mut out, params.question.qtype);
         write_u16(&mut out, params.question.qclass);
-        write_u32(&mut out, 60);
+        write_u32(&mut out, params.ttl);
         let chunk_count = payload_len.div_ceil(255);
         let rdata_len = payload_len + chunk_count;
         if rdata_len > u16::MAX as usize {
@@ -202,12 +227,35 @@ pub fn encode_response(params: &ResponseParams<'_>) -> Result<Vec<u8>, DnsError>
         }
     }
 
-    encode_opt_record(&mut out)?;
+    let time_ms = params.server_time_ms.map(u64::to_be_bytes);
+    let hold_ms = params.poll_hold_ms.map(u32::to_be_bytes);
+    let mut options: Vec<(u16, &[u8])> = Vec::with_capacity(2);
+    if let Some(time_ms) = &time_ms {
+        options.push((EDNS_OPTION_SERVER_TIME, time_ms));
+    }
+    if let Some(hold_ms) = &hold_ms {
+        options.push((EDNS_OPTION_POLL_HOLD, hold_ms));
+    }
+    encode_opt_record(&mut out, &options)?;
 
     Ok(out)
 }
 
 pub fn decode_response(packet: &[u8]) -> Option<Vec<u8>> {
+    decode_response_with_ttl(packet).map(|(payload, _)| payload)
+}
+
+/// Like `decode_response`, also returning the answer TTL as received.
+///
+/// The payload is taken from the first non-empty TXT answer. Other answers (such
+/// as a CNAME some resolvers put in front) are skipped, and the authority and
+/// additional sections are never read, so records appended there cannot shift
+/// or corrupt the payload.
+///
+/// The question name is not compared with the query: responses are matched by
+/// ID, so a resolver that rewrites the name's case (0x20) is still accepted.
+/// The TXT answer must carry the question's class, normally IN.
+pub fn decode_response_with_ttl(packet: &[u8]) -> Option<(Vec<u8>, u32)> {
     let header = parse_header(packet)?;
     if !header.is_response {
         return None;
@@ -216,53 +264,113 @@ pub fn decode_response(packet: &[u8]) -> Option<Vec<u8>> {
     if rcode != Rcode::Ok {
         return None;
     }
-    if header.ancount != 1 {
-        return None;
-    }
 
     let mut offset = header.offset;
+    let mut qclass = None;
     for _ in 0..header.qdcount {
         let (_, new_offset) = parse_name(packet, offset).ok()?;
         offset = new_offset;
         if offset + 4 > packet.len() {
             return None;
         }
+        qclass.get_or_insert(read_u16(packet, offset + 2)?);
         offset += 4;
     }
+    let qclass = qclass.unwrap_or(CLASS_IN);
 
-    let (_, new_offset) = parse_name(packet, offset).ok()?;
-    offset = new_offset;
-    if offset + 10 > packet.len() {
-        return None;
+    for _ in 0..header.ancount {
+        let (_, new_offset) = parse_name(packet, offset).ok()?;
+        offset = new_offset;
+        if offset + 10 > packet.len() {
+            return None;
+        }
+        let rtype = read_u16(packet, offset)?;
+        let rclass = read_u16(packet, offset + 2)?;
+        let ttl = read_u32(packet, offset + 4)?;
+        let rdlen = read_u16(packet, offset + 8)? as usize;
+        offset += 10;
+        if offset + rdlen > packet.len() {
+            return None;
+        }
+        let rdata = &packet[offset..offset + rdlen];
+        offset += rdlen;
+        if rtype != RR_TXT || rclass != qclass {
+            continue;
+        }
+        if let Some(payload) = decode_txt_rdata(rdata) {
+            return Some((payload, ttl));
+        }
     }
-    let qtype = read_u16(packet, offset)?;
-    offset += 2;
-    let _qclass = read_u16(packet, offset)?;
-    offset += 2;
-    let _ttl = read_u32(packet, offset)?;
-    offset += 4;
-    let rdlen = read_u16(packet, offset)? as usize;
-    offset += 2;
-    if offset + rdlen > packet.len() || rdlen < 1 {
+    None
+}
+
+/// Record type of an answer that an ordinary lookup returned instead of tunnel
+/// data, e.g. [`RR_A`] when the tunnel domain is also a real website and the
+/// resolver answered for it rather than reaching the tunnel server.
+///
+/// Tunnel data is an encrypted QUIC packet, so a TXT payload (picked the same
+/// way as by [`decode_response_with_ttl`]) that is all printable text, such as
+/// an SPF record, is reported as [`RR_TXT`]. Without a TXT payload the first
+/// answer that is not a CNAME decides. `None` for tunnel data, errors and
+/// responses without answers.
+pub fn non_tunnel_answer_type(packet: &[u8]) -> Option<u16> {
+    let header = parse_header(packet)?;
+    if !header.is_response || header.rcode? != Rcode::Ok {
         return None;
     }
-    if qtype != RR_TXT {
-        return None;
+
+    let mut offset = header.offset;
+    let mut qclass = None;
+    for _ in 0..header.qdcount {
+        let (_, new_offset) = parse_name(packet, offset).ok()?;
+        offset = new_offset;
+        if offset + 4 > packet.len() {
+            return None;
+        }
+        qclass.get_or_insert(read_u16(packet, offset + 2)?);
+        offset += 4;
     }
+    let qclass = qclass.unwrap_or(CLASS_IN);
 
-    let mut remaining = rdlen;
-    let mut cursor = offset;
-    let mut out = Vec::with_capacity(rdlen);
-    while remaining > 0 {
-        let txt_len = packet[cursor] as usize;
-        cursor += 1;
-        remaining -= 1;
-        if txt_len > remaining {
+    let mut found = None;
+    for _ in 0..header.ancount {
+        let (_, new_offset) = parse_name(packet, offset).ok()?;
+        offset = new_offset;
+        if offset + 10 > packet.len() {
+            return None;
+        }
+        let rtype = read_u16(packet, offset)?;
+        let rclass = read_u16(packet, offset + 2)?;
+        let rdlen = read_u16(packet, offset + 8)? as usize;
+        offset += 10;
+        if offset + rdlen > packet.len() {
             return None;
         }
-        out.extend_from_slice(&packet[cursor..cursor + txt_len]);
+        let rdata = &packet[offset..offset + rdlen];
+        offset += rdlen;
+        if rtype == RR_TXT && rclass == qclass {
+            if let Some(payload) = decode_txt_rdata(rdata) {
+                let text = payload.iter().all(|byte| (0x20..=0x7e).contains(byte));
+                return text.then_some(RR_TXT);
+            }
+        }
+        if found.is_none() || found == Some(RR_CNAME) {
+            found = Some(rtype);
+        }
+    }
+    found
+}
+
+/// Concatenates the character-strings of a TXT record.
+fn decode_txt_rdata(rdata: &[u8]) -> Option<Vec<u8>> {
+    let mut cursor = 0;
+    let mut out = Vec::with_capacity(rdata.len());
+    while cursor < rdata.len() {
+        let txt_len = rdata[cursor] as usize;
+        cursor += 1;
+        let chunk = rdata.get(cursor..cursor + txt_len)?;
+        out.extend_from_slice(chunk);
         cursor += txt_len;
-        remaining -= txt_len;
     }
     if out.is_empty() {
         return None;
@@ -270,25 +378,169 @@ pub fn decode_response(packet: &[u8]) -> Option<Vec<u8>> {
     Some(out)
 }
 
+/// Response code of a DNS response; `None` for queries, truncated headers and
+/// codes this crate does not model.
+pub fn response_rcode(packet: &[u8]) -> Option<Rcode> {
+    parse_header(packet)
+        .filter(|header| header.is_response)
+        .and_then(|header| header.rcode)
+}
+
+/// Whether a response has the RA flag set. Recursive resolvers set it on every
+/// answer; the tunnel server never does, so it tells which one answered.
+pub fn response_recursion_available(packet: &[u8]) -> Option<bool> {
+    parse_header(packet)
+        .filter(|header| header.is_response)
+        .map(|header| header.ra)
+}
+
 pub fn is_response(packet: &[u8]) -> bool {
     parse_header(packet)
         .map(|header| header.is_response)
         .unwrap_or(false)
 }
 
-fn encode_opt_record(out: &mut Vec<u8>) -> Result<(), DnsError> {
+/// Reads the server time option from the OPT record, if the response has one.
+///
+/// Resolvers are free to drop EDNS options they do not know, so a missing
+/// option is normal on recursive paths.
+pub fn decode_server_time(packet: &[u8]) -> Option<u64> {
+    let value = find_edns_option(packet, EDNS_OPTION_SERVER_TIME)?;
+    value.try_into().ok().map(u64::from_be_bytes)
+}
+
+/// Reads the poll hold time (ms) the server announced, if the response has one.
+///
+/// A server that holds polls answers a query without data only once something
+/// is queued or the hold time has passed. Like the server time, the option may
+/// be dropped by resolvers.
+pub fn decode_poll_hold(packet: &[u8]) -> Option<u32> {
+    let value = find_edns_option(packet, EDNS_OPTION_POLL_HOLD)?;
+    value.try_into().ok().map(u32::from_be_bytes)
+}
+
+/// Reads the idle timeout a resolver returned in edns-tcp-keepalive (RFC 7828).
+///
+/// `None` means the resolver did not send the option, or sent it without a
+/// timeout, so the caller's own idle limit applies. A zero timeout asks the
+/// client to close the connection once its outstanding queries are answered.
+pub fn decode_tcp_keepalive(packet: &[u8]) -> Option<Duration> {
+    let value = find_edns_option(packet, EDNS_OPTION_TCP_KEEPALIVE)?;
+    // The timeout is carried in units of 100 milliseconds.
+    let units = read_u16(value, 0).filter(|_| value.len() == 2)?;
+    Some(Duration::from_millis(units as u64 * 100))
+}
+
+/// Returns the value of the first `code` option in the response's OPT record.
+pub(crate) fn find_edns_option(packet: &[u8], code: u16) -> Option<&[u8]> {
+    let header = parse_header(packet)?;
+    if !header.is_response {
+        return None;
+    }
+    let mut offset = header.offset;
+    for _ in 0..header.qdcount {
+        let (_, new_offset) = parse_name(packet, offset).ok()?;
+        offset = new_offset.checked_add(4)?;
+    }
+    let records = header.ancount as usize + header.nscount as usize + header.arcount as usize;
+    let additional_start = header.ancount as usize + header.nscount as usize;
+    for index in 0..records {
+        let (_, new_offset) = parse_name(packet, offset).ok()?;
+        offset = new_offset;
+        let rtype = read_u16(packet, offset)?;
+        let rdlen = read_u16(packet, offset + 8)? as usize;
+        offset += 10;
+        let rdata = packet.get(offset..offset + rdlen)?;
+        offset += rdlen;
+        if index >= additional_start && rtype == RR_OPT {
+            return find_option(rdata, code);
+        }
+    }
+    None
+}
+
+fn find_option(mut rdata: &[u8], code: u16) -> Option<&[u8]> {
+    while rdata.len() >= 4 {
+        let option_code = read_u16(rdata, 0)?;
+        let len = read_u16(rdata, 2)? as usize;
+        let value = rdata.get(4..4 + len)?;
+        if option_code == code {
+            return Some(value);
+        }
+        rdata = &rdata[4 + len..];
+    }
+    None
+}
+
+fn encode_opt_record(out: &mut Vec<u8>, options: &[(u16, &[u8])]) -> Result<(), DnsError> {
     out.push(0);
     write_u16(out, RR_OPT);
     write_u16(out, EDNS_UDP_PAYLOAD);
     write_u32(out, 0);
-    write_u16(out, 0);
+    let rdata_len: usize = options.iter().map(|(_, value)| 4 + value.len()).sum();
+    let rdata_len = u16::try_from(rdata_len).map_err(|_| DnsError::new("EDNS options too long"))?;
+    write_u16(out, rdata_len);
+    for (code, value) in options {
+        write_u16(out, *code);
+        write_u16(out, value.len() as u16);
+        out.extend_from_slice(value);
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::encode_response;
-    use crate::types::{Question, ResponseParams, CLASS_IN, RR_TXT};
+    use super::{
+        decode_poll_hold, decode_query, decode_response, decode_response_with_ttl,
+        decode_server_time, decode_tcp_keepalive, encode_query, encode_response, encode_tcp_query,
+        non_tunnel_answer_type, response_rcode, response_recursion_available,
+    };
+    use crate::name::encode_name;
+    use crate::types::{
+        QueryParams, Question, Rcode, ResponseParams, CLASS_CH, CLASS_IN, DEFAULT_RESPONSE_TTL,
+        EDNS_OPTION_TCP_KEEPALIVE, EDNS_UDP_PAYLOAD, RR_A, RR_CNAME, RR_OPT, RR_TXT,
+    };
+    use crate::wire::{write_u16, write_u32};
+    use std::time::Duration;
+
+    const RR_NS: u16 = 2;
+
+    fn write_record(out: &mut Vec<u8>, rtype: u16, rdata: &[u8]) {
+        // Owner name points back at the question name.
+        out.extend_from_slice(&[0xC0, 0x0C]);
+        write_u16(out, rtype);
+        write_u16(out, CLASS_IN);
+        write_u32(out, 30);
+        write_u16(out, rdata.len() as u16);
+        out.extend_from_slice(rdata);
+    }
+
+    fn response_with_sections(
+        answers: &[(u16, &[u8])],
+        authority: usize,
+        additional: usize,
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_u16(&mut out, 0x1234);
+        write_u16(&mut out, 0x8400);
+        write_u16(&mut out, 1);
+        write_u16(&mut out, answers.len() as u16);
+        write_u16(&mut out, authority as u16);
+        write_u16(&mut out, additional as u16);
+        encode_name("a.test.com.", &mut out).expect("name");
+        write_u16(&mut out, RR_TXT);
+        write_u16(&mut out, CLASS_IN);
+        for (rtype, rdata) in answers {
+            write_record(&mut out, *rtype, rdata);
+        }
+        for _ in 0..authority {
+            write_record(&mut out, RR_NS, &[0xC0, 0x0C]);
+        }
+        for _ in 0..additional {
+            write_record(&mut out, RR_A, &[192, 0, 2, 1]);
+        }
+        out
+    }
 
     #[test]
     fn encode_response_rejects_large_payload() {
@@ -305,7 +557,253 @@ mod tests {
             question: &question,
             payload: Some(&payload),
             rcode: None,
+            ttl: DEFAULT_RESPONSE_TTL,
+            server_time_ms: None,
+            poll_hold_ms: None,
         };
         assert!(encode_response(&params).is_err());
     }
+
+    #[test]
+    fn answer_carries_configured_ttl_and_opt_stays_zero() {
+        let question = Question {
+            name: "a.test.com.".to_string(),
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+        };
+        let params = ResponseParams {
+            id: 0x1234,
+            rd: false,
+            cd: false,
+            question: &question,
+            payload: Some(b"hello"),
+            rcode: None,
+            ttl: 5,
+            server_time_ms: None,
+            poll_hold_ms: None,
+        };
+        let packet = encode_response(&params).expect("encode");
+        let (payload, ttl) = decode_response_with_ttl(&packet).expect("decode");
+        assert_eq!(payload, b"hello");
+        assert_eq!(ttl, 5);
+        // The OPT TTL field holds the extended RCODE and flags, not a cache lifetime.
+        let opt_ttl = &packet[packet.len() - 6..packet.len() - 2];
+        assert_eq!(opt_ttl, &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn ignores_authority_and_additional_records() {
+        let packet = response_with_sections(&[(RR_TXT, b"\x05hello")], 1, 3);
+        assert_eq!(decode_response(&packet).as_deref(), Some(&b"hello"[..]));
+    }
+
+    #[test]
+    fn skips_non_txt_answers_before_the_payload() {
+        let packet = response_with_sections(
+            &[
+                (RR_CNAME, &[0xC0, 0x0C]),
+                (RR_A, &[192, 0, 2, 1]),
+                (RR_TXT, b"\x03abc\x02de"),
+            ],
+            0,
+            1,
+        );
+        let (payload, ttl) = decode_response_with_ttl(&packet).expect("decode");
+        assert_eq!(payload, b"abcde");
+        assert_eq!(ttl, 30);
+    }
+
+    #[test]
+    fn rejects_responses_without_a_txt_payload() {
+        let packet = response_with_sections(&[(RR_A, &[192, 0, 2, 1])], 1, 2);
+        assert!(decode_response(&packet).is_none());
+        let truncated = response_with_sections(&[(RR_TXT, b"\x05hello")], 0, 0);
+        assert!(decode_response(&truncated[..truncated.len() - 2]).is_none());
+    }
+
+    #[test]
+    fn spots_answers_for_a_real_site() {
+        // A resolver that looked the name up itself: the site's CNAME and address.
+        let website = response_with_sections(
+            &[(RR_CNAME, &[0xC0, 0x0C]), (RR_A, &[93, 184, 215, 14])],
+            1,
+            0,
+        );
+        assert!(decode_response(&website).is_none());
+        assert_eq!(non_tunnel_answer_type(&website), Some(RR_A));
+        let spf = response_with_sections(&[(RR_TXT, b"\x0bv=spf1 -all")], 0, 0);
+        assert_eq!(non_tunnel_answer_type(&spf), Some(RR_TXT));
+
+        let quic = response_with_sections(&[(RR_TXT, b"\x05\xc3\x00\x00\x00\x01")], 0, 0);
+        assert_eq!(non_tunnel_answer_type(&quic), None);
+        let empty = response_with_sections(&[], 0, 0);
+        assert_eq!(non_tunnel_answer_type(&empty), None);
+    }
+
+    #[test]
+    fn server_time_option_round_trips() {
+        let question = Question {
+            name: "a.test.com.".to_string(),
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+        };
+        let mut params = ResponseParams {
+            id: 0x1234,
+            rd: true,
+            cd: false,
+            question: &question,
+            payload: Some(b"hello"),
+            rcode: None,
+            ttl: DEFAULT_RESPONSE_TTL,
+            server_time_ms: Some(1_700_000_000_123),
+            poll_hold_ms: None,
+        };
+        let packet = encode_response(&params).expect("encode");
+        assert_eq!(decode_server_time(&packet), Some(1_700_000_000_123));
+        assert_eq!(decode_response(&packet).as_deref(), Some(&b"hello"[..]));
+
+        params.server_time_ms = None;
+        let packet = encode_response(&params).expect("encode");
+        assert_eq!(decode_server_time(&packet), None);
+        assert_eq!(decode_poll_hold(&packet), None);
+
+        params.poll_hold_ms = Some(5_000);
+        params.server_time_ms = Some(42);
+        let packet = encode_response(&params).expect("encode");
+        assert_eq!(decode_poll_hold(&packet), Some(5_000));
+        assert_eq!(decode_server_time(&packet), Some(42));
+        // Records without an OPT, as left by some resolvers, carry no time either.
+        let stripped = response_with_sections(&[(RR_TXT, b"\x05hello")], 1, 2);
+        assert_eq!(decode_server_time(&stripped), None);
+    }
+
+    #[test]
+    fn reads_response_rcode() {
+        let question = Question {
+            name: "a.test.com.".to_string(),
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+        };
+        let packet = encode_response(&ResponseParams {
+            id: 0x1234,
+            rd: true,
+            cd: false,
+            question: &question,
+            payload: None,
+            rcode: Some(Rcode::ServerFailure),
+            ttl: DEFAULT_RESPONSE_TTL,
+            server_time_ms: None,
+            poll_hold_ms: None,
+        })
+        .expect("encode");
+        assert_eq!(response_rcode(&packet), Some(Rcode::ServerFailure));
+        let answer = response_with_sections(&[(RR_TXT, b"\x05hello")], 0, 0);
+        assert_eq!(response_rcode(&answer), Some(Rcode::Ok));
+        let mut refused = packet.clone();
+        refused[3] = (refused[3] & 0xf0) | 5;
+        assert_eq!(response_rcode(&refused), Some(Rcode::Refused));
+        assert_eq!(response_rcode(&packet[..4]), None);
+
+        assert_eq!(response_recursion_available(&packet), Some(false));
+        let mut recursive = packet.clone();
+        recursive[3] |= 0x80;
+        assert_eq!(response_recursion_available(&recursive), Some(true));
+        recursive[2] &= 0x7f;
+        assert_eq!(response_recursion_available(&recursive), None);
+    }
+
+    fn with_opt_record(mut packet: Vec<u8>, options: &[u8]) -> Vec<u8> {
+        let arcount = u16::from_be_bytes([packet[10], packet[11]]) + 1;
+        packet[10..12].copy_from_slice(&arcount.to_be_bytes());
+        packet.push(0);
+        write_u16(&mut packet, RR_OPT);
+        write_u16(&mut packet, EDNS_UDP_PAYLOAD);
+        write_u32(&mut packet, 0);
+        write_u16(&mut packet, options.len() as u16);
+        packet.extend_from_slice(options);
+        packet
+    }
+
+    #[test]
+    fn query_class_is_written_and_echoed() {
+        let qname = crate::build_qname(b"data", "test.com").expect("qname");
+        let query = encode_query(&QueryParams {
+            id: 9,
+            qname: &qname,
+            qtype: RR_TXT,
+            qclass: CLASS_CH,
+            rd: true,
+            cd: false,
+            qdcount: 1,
+            is_query: true,
+        })
+        .expect("encode");
+        let question_end = query.len() - 11;
+        assert_eq!(
+            query[question_end - 2..question_end],
+            CLASS_CH.to_be_bytes()
+        );
+
+        let decoded = decode_query(&query, "test.com").expect("decode");
+        assert_eq!(decoded.question.qclass, CLASS_CH);
+        let response = encode_response(&ResponseParams {
+            id: decoded.id,
+            rd: decoded.rd,
+            cd: decoded.cd,
+            question: &decoded.question,
+            payload: Some(b"reply"),
+            rcode: None,
+            ttl: 0,
+            server_time_ms: None,
+            poll_hold_ms: None,
+        })
+        .expect("response");
+        assert_eq!(decode_response(&response).as_deref(), Some(&b"reply"[..]));
+
+        // A CH answer does not satisfy an IN question.
+        let mut mismatched = response.clone();
+        let qclass_at = 12 + qname.len() + 1 + 2;
+        mismatched[qclass_at..qclass_at + 2].copy_from_slice(&CLASS_IN.to_be_bytes());
+        assert_eq!(decode_response(&mismatched), None);
+    }
+
+    #[test]
+    fn tcp_query_asks_for_keepalive() {
+        let query = encode_tcp_query(&QueryParams {
+            id: 1,
+            qname: "a.test.com.",
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+            rd: true,
+            cd: false,
+            qdcount: 1,
+            is_query: true,
+        })
+        .expect("encode");
+        // OPT rdata: one option with an empty value, as RFC 7828 requires of clients.
+        let mut tail = vec![0, 4];
+        tail.extend_from_slice(&EDNS_OPTION_TCP_KEEPALIVE.to_be_bytes());
+        tail.extend_from_slice(&[0, 0]);
+        assert!(query.ends_with(&tail));
+    }
+
+    #[test]
+    fn parses_returned_tcp_keepalive_timeout() {
+        let answers: &[(u16, &[u8])] = &[(RR_TXT, b"\x05hello")];
+        // A padding option first, then a 120 s (1200 x 100 ms) keepalive.
+        let options = [0, 12, 0, 2, 0, 0, 0, 11, 0, 2, 0x04, 0xb0];
+        let packet = with_opt_record(response_with_sections(answers, 0, 1), &options);
+        assert_eq!(
+            decode_tcp_keepalive(&packet),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(decode_response(&packet).as_deref(), Some(&b"hello"[..]));
+
+        let closing = with_opt_record(response_with_sections(answers, 0, 0), &[0, 11, 0, 2, 0, 0]);
+        assert_eq!(decode_tcp_keepalive(&closing), Some(Duration::ZERO));
+        let no_timeout = with_opt_record(response_with_sections(answers, 0, 0), &[0, 11, 0, 0]);
+        assert_eq!(decode_tcp_keepalive(&no_timeout), None);
+        let absent = with_opt_record(response_with_sections(answers, 0, 0), &[]);
+        assert_eq!(decode_tcp_keepalive(&absent), None);
+    }
 }
@@ -0,0 +1,184 @@
+//! DNS Cookies (RFC 7873).
+//!
+//! Some resolvers drop or rate-limit queries that carry no valid cookie. The
+//! client sends an 8-byte client cookie with every query; once a response
+//! returns a server cookie alongside it, later queries echo both.
+
+use crate::codec::{encode_query_with_options, find_edns_option};
+use crate::types::{DnsError, QueryParams, EDNS_OPTION_COOKIE};
+
+/// Length of the client cookie; fixed by RFC 7873.
+pub const CLIENT_COOKIE_LEN: usize = 8;
+const SERVER_COOKIE_MIN_LEN: usize = 8;
+const SERVER_COOKIE_MAX_LEN: usize = 32;
+
+/// Cookie state towards one resolver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsCookie {
+    client: [u8; CLIENT_COOKIE_LEN],
+    server: Option<Vec<u8>>,
+}
+
+impl DnsCookie {
+    /// `client` should be random and differ per resolver, so that resolvers
+    /// cannot link the client's queries to each other.
+    pub fn new(client: [u8; CLIENT_COOKIE_LEN]) -> Self {
+        Self {
+            client,
+            server: None,
+        }
+    }
+
+    pub fn client(&self) -> &[u8; CLIENT_COOKIE_LEN] {
+        &self.client
+    }
+
+    /// The server cookie learned so far, if any.
+    pub fn server(&self) -> Option<&[u8]> {
+        self.server.as_deref()
+    }
+
+    /// The COOKIE option value for the next query: the client cookie, followed
+    /// by the server cookie once one is known.
+    pub fn option_value(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(CLIENT_COOKIE_LEN + SERVER_COOKIE_MAX_LEN);
+        value.extend_from_slice(&self.client);
+        if let Some(server) = &self.server {
+            value.extend_from_slice(server);
+        }
+        value
+    }
+
+    /// Learns the server cookie from a response; returns true when it changed.
+    ///
+    /// Responses without a cookie leave the state alone. Cookies that do not
+    /// echo our client cookie, or whose server part has an invalid length, are
+    /// ignored as RFC 7873 section 5.3 requires. A BADCOOKIE error carries a
+    /// fresh server cookie, so it is learned the same way and the next
+    /// retransmission succeeds.
+    pub fn observe_response(&mut self, packet: &[u8]) -> bool {
+        let Some(value) = find_edns_option(packet, EDNS_OPTION_COOKIE) else {
+            return false;
+        };
+        let Some((client, server)) = value.split_first_chunk::<CLIENT_COOKIE_LEN>() else {
+            return false;
+        };
+        if client != &self.client
+            || !(SERVER_COOKIE_MIN_LEN..=SERVER_COOKIE_MAX_LEN).contains(&server.len())
+        {
+            return false;
+        }
+        if self.server.as_deref() == Some(server) {
+            return false;
+        }
+        self.server = Some(server.to_vec());
+        true
+    }
+}
+
+/// Like `encode_query`, with a COOKIE option built from `cookie`.
+pub fn encode_query_with_cookie(
+    params: &QueryParams<'_>,
+    cookie: &DnsCookie,
+) -> Result<Vec<u8>, DnsError> {
+    let value = cookie.option_value();
+    encode_query_with_options(params, &[(EDNS_OPTION_COOKIE, &value)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::{encode_query, encode_response};
+    use crate::types::{Question, ResponseParams, CLASS_IN, RR_TXT};
+    use crate::wire::write_u16;
+
+    const CLIENT: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+    const SERVER: [u8; 16] = [0xAA; 16];
+
+    const PARAMS: QueryParams<'static> = QueryParams {
+        id: 0x1234,
+        qname: "a.test.com.",
+        qtype: RR_TXT,
+        qclass: CLASS_IN,
+        rd: true,
+        cd: false,
+        qdcount: 1,
+        is_query: true,
+    };
+
+    fn query(cookie: &DnsCookie) -> Vec<u8> {
+        encode_query_with_cookie(&PARAMS, cookie).expect("encode query")
+    }
+
+    /// The COOKIE option value of a query, checked against the plain query it
+    /// extends: the same packet up to the OPT rdata, which holds only the cookie.
+    fn query_cookie(packet: &[u8]) -> &[u8] {
+        let plain = encode_query(&PARAMS).expect("encode plain query");
+        let opt = plain.len();
+        assert_eq!(&packet[..opt - 2], &plain[..opt - 2]);
+        let rdlen = u16::from_be_bytes([packet[opt - 2], packet[opt - 1]]) as usize;
+        assert_eq!(rdlen, packet.len() - opt);
+        assert_eq!(&packet[opt..opt + 2], &EDNS_OPTION_COOKIE.to_be_bytes());
+        let len = u16::from_be_bytes([packet[opt + 2], packet[opt + 3]]) as usize;
+        assert_eq!(len, rdlen - 4);
+        &packet[opt + 4..]
+    }
+
+    fn response_with_cookie(value: &[u8]) -> Vec<u8> {
+        let question = Question {
+            name: "a.test.com.".to_string(),
+            qtype: RR_TXT,
+            qclass: CLASS_IN,
+        };
+        let mut packet = encode_response(&ResponseParams {
+            id: 0x1234,
+            rd: true,
+            cd: false,
+            question: &question,
+            payload: Some(b"quic"),
+            rcode: None,
+            ttl: 0,
+            server_time_ms: None,
+            poll_hold_ms: None,
+        })
+        .expect("encode response");
+        // The OPT record comes last with empty rdata; give it a COOKIE option.
+        packet.truncate(packet.len() - 2);
+        write_u16(&mut packet, 4 + value.len() as u16);
+        write_u16(&mut packet, EDNS_OPTION_COOKIE);
+        write_u16(&mut packet, value.len() as u16);
+        packet.extend_from_slice(value);
+        packet
+    }
+
+    #[test]
+    fn cookie_handshake_learns_and_echoes_the_server_cookie() {
+        let mut cookie = DnsCookie::new(CLIENT);
+        assert_eq!(query_cookie(&query(&cookie)), CLIENT);
+
+        let echoed = [CLIENT.as_slice(), SERVER.as_slice()].concat();
+        assert!(cookie.observe_response(&response_with_cookie(&echoed)));
+        assert_eq!(cookie.server(), Some(SERVER.as_slice()));
+        assert_eq!(query_cookie(&query(&cookie)), echoed.as_slice());
+
+        // The same cookie again is no change; a rotated one replaces it.
+        assert!(!cookie.observe_response(&response_with_cookie(&echoed)));
+        let rotated = [CLIENT.as_slice(), &[0xBB; 8]].concat();
+        assert!(cookie.observe_response(&response_with_cookie(&rotated)));
+        assert_eq!(query_cookie(&query(&cookie)), rotated.as_slice());
+    }
+
+    #[test]
+    fn foreign_or_malformed_cookies_are_ignored() {
+        let mut cookie = DnsCookie::new(CLIENT);
+        let foreign = [[9u8; 8].as_slice(), SERVER.as_slice()].concat();
+        assert!(!cookie.observe_response(&response_with_cookie(&foreign)));
+        let short = [CLIENT.as_slice(), &[0xAA; 4]].concat();
+        assert!(!cookie.observe_response(&response_with_cookie(&short)));
+        let long = [CLIENT.as_slice(), &[0xAA; 33]].concat();
+        assert!(!cookie.observe_response(&response_with_cookie(&long)));
+        assert!(!cookie.observe_response(&response_with_cookie(&CLIENT)));
+        assert_eq!(cookie.server(), None);
+        assert_eq!(query_cookie(&query(&cookie)), CLIENT);
+    }
+}
@@ -1,27 +1,49 @@
 mod base32;
+mod capture;
 mod codec;
+mod cookie;
 mod dots;
 mod name;
+mod safe;
 mod types;
 mod wire;
 
 pub use base32::{decode as base32_decode, encode as base32_encode, Base32Error};
+pub use capture::{
+    format_capture_record, parse_capture, replay_record, replay_transcript, CaptureDirection,
+    CaptureRecord, ReplayOutcome, CAPTURE_HEADER, CAPTURE_VERSION,
+};
 pub use codec::{
-    decode_query, decode_query_with_domains, decode_response, encode_query, encode_response,
-    is_response,
+    decode_poll_hold, decode_query, decode_query_with_domains, decode_response,
+    decode_response_with_ttl, decode_server_time, decode_tcp_keepalive, encode_query,
+    encode_response, encode_tcp_query, is_response, non_tunnel_answer_type, response_rcode,
+    response_recursion_available,
 };
+pub use cookie::{encode_query_with_cookie, DnsCookie, CLIENT_COOKIE_LEN};
 pub use dots::{dotify, undotify};
+pub use name::MAX_DNS_NAME_LEN;
 pub use types::{
     DecodeQueryError, DecodedQuery, DnsError, QueryParams, Question, Rcode, ResponseParams,
-    CLASS_IN, EDNS_UDP_PAYLOAD, RR_A, RR_OPT, RR_TXT,
+    CLASS_CH, CLASS_IN, DEFAULT_RESPONSE_TTL, EDNS_OPTION_COOKIE, EDNS_OPTION_POLL_HOLD,
+    EDNS_OPTION_SERVER_TIME, EDNS_OPTION_TCP_KEEPALIVE, EDNS_UDP_PAYLOAD, RR_A, RR_CNAME, RR_OPT,
+    RR_TXT,
 };
 
 pub fn build_qname(payload: &[u8], domain: &str) -> Result<String, DnsError> {
+    build_qname_with_limit(payload, domain, name::MAX_DNS_NAME_LEN)
+}
+
+/// Like [`build_qname`], but the name stays within `max_name_len` characters.
+pub fn build_qname_with_limit(
+    payload: &[u8],
+    domain: &str,
+    max_name_len: usize,
+) -> Result<String, DnsError> {
     let domain = domain.trim_end_matches('.');
     if domain.is_empty() {
         return Err(DnsError::new("domain must not be empty"));
     }
-    let max_payload = max_payload_len_for_domain(domain)?;
+    let max_payload = max_payload_len_for_name_len(domain, max_name_len)?;
     if payload.len() > max_payload {
         return Err(DnsError::new("payload too large for domain"));
     }
@@ -30,33 +52,77 @@ pub fn build_qname(payload: &[u8], domain: &str) -> Result<String, DnsError> {
     Ok(format!("{}.{}.", dotted, domain))
 }
 
+/// Like [`build_qname_with_limit`], with the safe label encoding: only the
+/// letters `a` to `p`, behind a one-letter marker label. Servers decode either
+/// encoding without configuration.
+pub fn build_safe_qname_with_limit(
+    payload: &[u8],
+    domain: &str,
+    max_name_len: usize,
+) -> Result<String, DnsError> {
+    let domain = domain.trim_end_matches('.');
+    if domain.is_empty() {
+        return Err(DnsError::new("domain must not be empty"));
+    }
+    let max_payload = max_safe_payload_len_for_name_len(domain, max_name_len)?;
+    if payload.len() > max_payload {
+        return Err(DnsError::new("payload too large for domain"));
+    }
+    let dotted = dotify(&safe::encode(payload));
+    Ok(format!("{}.{}.{}.", safe::SAFE_MARKER, dotted, domain))
+}
+
 pub fn max_payload_len_for_domain(domain: &str) -> Result<usize, DnsError> {
+    max_payload_len_for_name_len(domain, name::MAX_DNS_NAME_LEN)
+}
+
+/// Largest payload whose query name, including `domain`, fits `max_name_len`.
+pub fn max_payload_len_for_name_len(domain: &str, max_name_len: usize) -> Result<usize, DnsError> {
     let domain = domain.trim_end_matches('.');
     if domain.is_empty() {
         return Err(DnsError::new("domain must not be empty"));
     }
-    if domain.len() > name::MAX_DNS_NAME_LEN {
-        return Err(DnsError::new("domain too long"));
+    let max_base32_len = max_encoded_len(domain, max_name_len, 0)?;
+    let mut max_payload = (max_base32_len * 5) / 8;
+    while max_payload > 0 && base32_len(max_payload) > max_base32_len {
+        max_payload -= 1;
+    }
+    Ok(max_payload)
+}
+
+/// Largest payload whose safe-encoded query name, including `domain`, fits
+/// `max_name_len`.
+pub fn max_safe_payload_len_for_name_len(
+    domain: &str,
+    max_name_len: usize,
+) -> Result<usize, DnsError> {
+    let domain = domain.trim_end_matches('.');
+    if domain.is_empty() {
+        return Err(DnsError::new("domain must not be empty"));
     }
-    let max_name_len = name::MAX_DNS_NAME_LEN;
-    let max_dotted_len = max_name_len.saturating_sub(domain.len() + 1);
-    if max_dotted_len == 0 {
-        return Ok(0);
+    let reserved = safe::SAFE_MARKER.len() + 1;
+    Ok(max_encoded_len(domain, max_name_len, reserved)? / 2)
+}
+
+/// Longest undotted encoding that fits `max_name_len` once dotted and placed
+/// under `domain`, after `reserved` characters of marker labels.
+fn max_encoded_len(domain: &str, max_name_len: usize, reserved: usize) -> Result<usize, DnsError> {
+    if max_name_len > name::MAX_DNS_NAME_LEN {
+        return Err(DnsError::new("name length limit exceeds the DNS maximum"));
     }
-    let mut max_base32_len = 0usize;
+    if domain.len() > max_name_len {
+        return Err(DnsError::new("domain too long"));
+    }
+    let max_dotted_len = max_name_len.saturating_sub(domain.len() + 1 + reserved);
+    let mut max_len = 0usize;
     for len in 1..=max_dotted_len {
         let dots = (len - 1) / 57;
         if len + dots > max_dotted_len {
             break;
         }
-        max_base32_len = len;
-    }
-
-    let mut max_payload = (max_base32_len * 5) / 8;
-    while max_payload > 0 && base32_len(max_payload) > max_base32_len {
-        max_payload -= 1;
+        max_len = len;
     }
-    Ok(max_payload)
+    Ok(max_len)
 }
 
 fn base32_len(payload_len: usize) -> usize {
@@ -68,7 +134,11 @@ fn base32_len(payload_len: usize) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use super::{build_qname, max_payload_len_for_domain};
+    use super::{
+        build_qname, build_qname_with_limit, build_safe_qname_with_limit,
+        max_payload_len_for_domain, max_payload_len_for_name_len,
+        max_safe_payload_len_for_name_len,
+    };
 
     #[test]
     fn build_qname_rejects_payload_overflow() {
@@ -84,4 +154,33 @@ mod tests {
         let payload = vec![0u8; 1];
         assert!(build_qname(&payload, &domain).is_err());
     }
+
+    #[test]
+    fn qname_limit_holds_at_max_payload() {
+        for domain in ["t.co", "tunnel.example.com"] {
+            for limit in [64, 100, 150, 200, 253] {
+                let max_payload = max_payload_len_for_name_len(domain, limit).expect("max payload");
+                let payload = vec![0xffu8; max_payload];
+                let qname = build_qname_with_limit(&payload, domain, limit).expect("qname");
+                assert!(
+                    qname.trim_end_matches('.').len() <= limit,
+                    "{} {}",
+                    domain,
+                    limit
+                );
+                let payload = vec![0xffu8; max_payload + 1];
+                assert!(build_qname_with_limit(&payload, domain, limit).is_err());
+
+                let max_payload =
+                    max_safe_payload_len_for_name_len(domain, limit).expect("max payload");
+                let payload = vec![0xffu8; max_payload];
+                let qname = build_safe_qname_with_limit(&payload, domain, limit).expect("qname");
+                assert!(qname.trim_end_matches('.').len() <= limit, "{}", qname);
+                let payload = vec![0xffu8; max_payload + 1];
+                assert!(build_safe_qname_with_limit(&payload, domain, limit).is_err());
+            }
+        }
+        assert!(max_payload_len_for_name_len("t.co", 254).is_err());
+        assert!(max_safe_payload_len_for_name_len("t.co", 254).is_err());
+    }
 }
@@ -1,6 +1,7 @@
 use crate::types::{DnsError, Rcode};
 
-pub(crate) const MAX_DNS_NAME_LEN: usize = 253;
+/// Longest DNS name the protocol allows, without the trailing dot.
+pub const MAX_DNS_NAME_LEN: usize = 253;
 
 fn extract_subdomain(qname: &str, domain: &str) -> Result<String, Rcode> {
     let domain = domain.trim_end_matches('.');
@@ -0,0 +1,41 @@
+//! The "safe" label encoding: each half byte becomes one of the lowercase
+//! letters `a` to `p`, so labels never carry digits and read the same after any
+//! case rewrite. It spends two characters per byte where base32 spends 1.6.
+
+/// Leading label that marks a query name as safe-encoded. A base32 name never
+/// starts with a one-character label, so the two cannot be confused.
+pub(crate) const SAFE_MARKER: &str = "s";
+
+pub(crate) fn encode(input: &[u8]) -> String {
+    let mut out = String::with_capacity(input.len() * 2);
+    for &byte in input {
+        out.push((b'a' + (byte >> 4)) as char);
+        out.push((b'a' + (byte & 0x0f)) as char);
+    }
+    out
+}
+
+/// Decodes undotted safe-encoded text, ignoring case; `None` if it is malformed.
+pub(crate) fn decode(input: &str) -> Option<Vec<u8>> {
+    let bytes = input.as_bytes();
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    bytes
+        .chunks_exact(2)
+        .map(|pair| Some((decode_value(pair[0])? << 4) | decode_value(pair[1])?))
+        .collect()
+}
+
+/// Strips the marker label from a query subdomain; `None` if it is not safe-encoded.
+pub(crate) fn strip_marker(subdomain: &str) -> Option<&str> {
+    let (first, rest) = subdomain.split_once('.')?;
+    first.eq_ignore_ascii_case(SAFE_MARKER).then_some(rest)
+}
+
+fn decode_value(b: u8) -> Option<u8> {
+    match b.to_ascii_lowercase() {
+        value @ b'a'..=b'p' => Some(value - b'a'),
+        _ => None,
+    }
+}
@@ -1,10 +1,23 @@
 use std::fmt;
 
 pub const RR_A: u16 = 1;
+pub const RR_CNAME: u16 = 5;
 pub const RR_TXT: u16 = 16;
 pub const RR_OPT: u16 = 41;
 pub const CLASS_IN: u16 = 1;
+pub const CLASS_CH: u16 = 3;
 pub const EDNS_UDP_PAYLOAD: u16 = 1232;
+/// Answer TTL used unless the server is configured otherwise.
+pub const DEFAULT_RESPONSE_TTL: u32 = 60;
+/// EDNS option (local/experimental range) carrying the server's Unix time in ms.
+pub const EDNS_OPTION_SERVER_TIME: u16 = 65001;
+/// EDNS option (local/experimental range) carrying how long, in ms, the server
+/// holds a poll that finds no data before answering it empty.
+pub const EDNS_OPTION_POLL_HOLD: u16 = 65002;
+/// edns-tcp-keepalive (RFC 7828), only valid on TCP and DoT connections.
+pub const EDNS_OPTION_TCP_KEEPALIVE: u16 = 11;
+/// DNS Cookie (RFC 7873).
+pub const EDNS_OPTION_COOKIE: u16 = 10;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Rcode {
@@ -12,6 +25,7 @@ pub enum Rcode {
     FormatError,
     ServerFailure,
     NameError,
+    Refused,
 }
 
 impl Rcode {
@@ -21,6 +35,7 @@ impl Rcode {
             Rcode::FormatError => 1,
             Rcode::ServerFailure => 2,
             Rcode::NameError => 3,
+            Rcode::Refused => 5,
         }
     }
 
@@ -30,6 +45,7 @@ impl Rcode {
             1 => Some(Rcode::FormatError),
             2 => Some(Rcode::ServerFailure),
             3 => Some(Rcode::NameError),
+            5 => Some(Rcode::Refused),
             _ => None,
         }
     }
@@ -83,6 +99,12 @@ pub struct ResponseParams<'a> {
     pub question: &'a Question,
     pub payload: Option<&'a [u8]>,
     pub rcode: Option<Rcode>,
+    /// TTL of the answer record, in seconds.
+    pub ttl: u32,
+    /// Server wall-clock time (Unix ms) to attach as an EDNS option.
+    pub server_time_ms: Option<u64>,
+    /// Poll hold time (ms) to announce as an EDNS option.
+    pub poll_hold_ms: Option<u32>,
 }
 
 #[derive(Debug, Clone)]
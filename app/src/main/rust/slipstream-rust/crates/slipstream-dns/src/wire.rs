@@ -7,8 +7,11 @@ pub(crate) struct Header {
     pub(crate) is_response: bool,
     pub(crate) rd: bool,
     pub(crate) cd: bool,
+    pub(crate) ra: bool,
     pub(crate) qdcount: u16,
     pub(crate) ancount: u16,
+    pub(crate) nscount: u16,
+    pub(crate) arcount: u16,
     pub(crate) rcode: Option<Rcode>,
     pub(crate) offset: usize,
 }
@@ -21,12 +24,13 @@ pub(crate) fn parse_header(packet: &[u8]) -> Option<Header> {
     let flags = read_u16(packet, 2)?;
     let qdcount = read_u16(packet, 4)?;
     let ancount = read_u16(packet, 6)?;
-    let _nscount = read_u16(packet, 8)?;
-    let _arcount = read_u16(packet, 10)?;
+    let nscount = read_u16(packet, 8)?;
+    let arcount = read_u16(packet, 10)?;
 
     let is_response = flags & 0x8000 != 0;
     let rd = flags & 0x0100 != 0;
     let cd = flags & 0x0010 != 0;
+    let ra = flags & 0x0080 != 0;
     let rcode = Rcode::from_u8((flags & 0x000f) as u8);
 
     Some(Header {
@@ -34,8 +38,11 @@ pub(crate) fn parse_header(packet: &[u8]) -> Option<Header> {
         is_response,
         rd,
         cd,
+        ra,
         qdcount,
         ancount,
+        nscount,
+        arcount,
         rcode,
         offset: 12,
     })
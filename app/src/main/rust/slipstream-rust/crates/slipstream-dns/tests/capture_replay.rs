@@ -0,0 +1,47 @@
+//! Replays every capture in `fixtures/captures` and compares the decode
+//! transcript with the stored `.golden` file next to it.
+//!
+//! Set `SLIPSTREAM_BLESS=1` to rewrite the golden files after an intended
+//! decode change, then review the diff.
+
+use std::fs;
+use std::path::Path;
+
+use slipstream_dns::{parse_capture, replay_transcript};
+
+#[test]
+fn captures_replay_to_golden_transcripts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../fixtures/captures");
+    let bless = std::env::var_os("SLIPSTREAM_BLESS").is_some();
+    let mut replayed = 0;
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .expect("read fixtures/captures")
+        .map(|entry| entry.expect("capture entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "capture"))
+        .collect();
+    entries.sort();
+    for path in entries {
+        let input = fs::read_to_string(&path).expect("read capture");
+        let domain = input
+            .lines()
+            .find_map(|line| line.strip_prefix("# domain: "))
+            .unwrap_or_else(|| panic!("{} has no `# domain:` line", path.display()))
+            .trim();
+        let records = parse_capture(&input).expect("parse capture");
+        let transcript = replay_transcript(&records, &[domain]);
+        let golden_path = path.with_extension("golden");
+        if bless {
+            fs::write(&golden_path, &transcript).expect("write golden transcript");
+        }
+        let golden = fs::read_to_string(&golden_path)
+            .unwrap_or_else(|_| panic!("missing {}", golden_path.display()));
+        assert_eq!(
+            transcript,
+            golden,
+            "{} no longer decodes as recorded",
+            path.display()
+        );
+        replayed += 1;
+    }
+    assert!(replayed > 0, "no captures in {}", dir.display());
+}
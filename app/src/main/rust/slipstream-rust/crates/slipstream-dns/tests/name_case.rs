@@ -0,0 +1,71 @@
+use slipstream_dns::{
+    build_qname, decode_query, decode_response, encode_query, encode_response, QueryParams,
+    Question, ResponseParams, CLASS_IN, RR_TXT,
+};
+
+/// Flips the case of every other letter, the way a 0x20-randomizing resolver
+/// rewrites a query name.
+fn mix_case(name: &str) -> String {
+    name.chars()
+        .enumerate()
+        .map(|(idx, ch)| {
+            if idx % 2 == 0 {
+                ch.to_ascii_uppercase()
+            } else {
+                ch.to_ascii_lowercase()
+            }
+        })
+        .collect()
+}
+
+fn query_for(qname: &str) -> Vec<u8> {
+    encode_query(&QueryParams {
+        id: 0x2020,
+        qname,
+        qtype: RR_TXT,
+        qclass: CLASS_IN,
+        rd: true,
+        cd: false,
+        qdcount: 1,
+        is_query: true,
+    })
+    .expect("encode query")
+}
+
+#[test]
+fn server_decodes_query_with_rewritten_case() {
+    let payload: Vec<u8> = (0..=255u8).take(90).collect();
+    let qname = build_qname(&payload, "Tunnel.Example.com").expect("build qname");
+    let mixed = mix_case(&qname);
+    assert_ne!(mixed, qname);
+
+    let decoded = decode_query(&query_for(&mixed), "tunnel.example.com").expect("decode query");
+    assert_eq!(decoded.payload, payload);
+    // The question is echoed as received so the resolver's own check passes.
+    assert_eq!(decoded.question.name, mixed);
+}
+
+#[test]
+fn client_decodes_response_with_rewritten_question_case() {
+    let payload = b"downstream bytes".to_vec();
+    let qname = build_qname(b"poll", "tunnel.example.com").expect("build qname");
+    let question = Question {
+        name: mix_case(&qname),
+        qtype: RR_TXT,
+        qclass: CLASS_IN,
+    };
+    let response = encode_response(&ResponseParams {
+        id: 0x2020,
+        rd: true,
+        cd: false,
+        question: &question,
+        payload: Some(&payload),
+        rcode: None,
+        ttl: 0,
+        server_time_ms: None,
+        poll_hold_ms: None,
+    })
+    .expect("encode response");
+
+    assert_eq!(decode_response(&response), Some(payload));
+}
@@ -0,0 +1,57 @@
+use slipstream_dns::{
+    build_safe_qname_with_limit, decode_query, encode_query, max_safe_payload_len_for_name_len,
+    QueryParams, CLASS_IN, MAX_DNS_NAME_LEN, RR_TXT,
+};
+
+fn query_for(qname: &str) -> Vec<u8> {
+    encode_query(&QueryParams {
+        id: 0x5afe,
+        qname,
+        qtype: RR_TXT,
+        qclass: CLASS_IN,
+        rd: true,
+        cd: false,
+        qdcount: 1,
+        is_query: true,
+    })
+    .expect("encode query")
+}
+
+#[test]
+fn safe_encoding_round_trips_every_byte() {
+    let domain = "tunnel.example.com";
+    let max_payload =
+        max_safe_payload_len_for_name_len(domain, MAX_DNS_NAME_LEN).expect("max payload");
+    for payload in [
+        (0..=255u8).take(max_payload).collect::<Vec<u8>>(),
+        (0..=255u8).rev().take(max_payload).collect(),
+        vec![0x00],
+    ] {
+        let qname = build_safe_qname_with_limit(&payload, domain, MAX_DNS_NAME_LEN).expect("qname");
+        let data = qname
+            .strip_suffix(&format!(".{}.", domain))
+            .expect("suffix");
+        assert!(
+            data.bytes()
+                .all(|b| b == b'.' || (b'a'..=b'p').contains(&b) || b == b's'),
+            "{}",
+            qname
+        );
+
+        let decoded = decode_query(&query_for(&qname), domain).expect("decode query");
+        assert_eq!(decoded.payload, payload);
+        // A resolver that rewrites the case of the name does not change the payload.
+        let upper = qname.to_ascii_uppercase();
+        let decoded = decode_query(&query_for(&upper), domain).expect("decode query");
+        assert_eq!(decoded.payload, payload);
+    }
+}
+
+#[test]
+fn safe_encoding_costs_a_fifth_of_the_payload() {
+    let domain = "tunnel.example.com";
+    let base32 =
+        slipstream_dns::max_payload_len_for_name_len(domain, MAX_DNS_NAME_LEN).expect("base32");
+    let safe = max_safe_payload_len_for_name_len(domain, MAX_DNS_NAME_LEN).expect("safe");
+    assert_eq!((base32, safe), (143, 114));
+}
@@ -4,7 +4,7 @@ use std::path::Path;
 use serde::Deserialize;
 use slipstream_dns::{
     decode_query, decode_response, encode_query, encode_response, DecodeQueryError, QueryParams,
-    Question, Rcode, ResponseParams, CLASS_IN, RR_A, RR_TXT,
+    Question, Rcode, ResponseParams, CLASS_IN, DEFAULT_RESPONSE_TTL, RR_A, RR_TXT,
 };
 
 #[derive(Debug, Deserialize)]
@@ -123,6 +123,9 @@ fn vectors_match_codec() {
                 question: &question,
                 payload: Some(&payload),
                 rcode: None,
+                ttl: DEFAULT_RESPONSE_TTL,
+                server_time_ms: None,
+                poll_hold_ms: None,
             })
             .expect("encode response_ok");
             let expected = decode_hex(&resp.packet_hex);
@@ -140,6 +143,9 @@ fn vectors_match_codec() {
                 question: &question,
                 payload: None,
                 rcode: None,
+                ttl: DEFAULT_RESPONSE_TTL,
+                server_time_ms: None,
+                poll_hold_ms: None,
             })
             .expect("encode response_no_data");
             let expected = decode_hex(&resp.packet_hex);
@@ -165,6 +171,9 @@ fn vectors_match_codec() {
                 question: &question,
                 payload: None,
                 rcode: Some(rcode),
+                ttl: DEFAULT_RESPONSE_TTL,
+                server_time_ms: None,
+                poll_hold_ms: None,
             })
             .expect("encode response_error");
             let expected = decode_hex(&resp.packet_hex);
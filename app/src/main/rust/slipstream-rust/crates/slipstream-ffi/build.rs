@@ -137,12 +137,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cc_dir = manifest_dir.join("cc");
     let cc_src = cc_dir.join("slipstream_server_cc.c");
     let mixed_cc_src = cc_dir.join("slipstream_mixed_cc.c");
+    let custom_cc_src = cc_dir.join("slipstream_custom_cc.c");
     let poll_src = cc_dir.join("slipstream_poll.c");
     let stateless_packet_src = cc_dir.join("slipstream_stateless_packet.c");
     let test_helpers_src = cc_dir.join("slipstream_test_helpers.c");
     let picotls_layout_src = cc_dir.join("picotls_layout.c");
     println!("cargo:rerun-if-changed={}", cc_src.display());
     println!("cargo:rerun-if-changed={}", mixed_cc_src.display());
+    println!("cargo:rerun-if-changed={}", custom_cc_src.display());
     println!("cargo:rerun-if-changed={}", poll_src.display());
     println!("cargo:rerun-if-changed={}", stateless_packet_src.display());
     println!("cargo:rerun-if-changed={}", test_helpers_src.display());
@@ -159,6 +161,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     compile_cc(&cc, &mixed_cc_src, &mixed_cc_obj, &picoquic_include_dir)?;
     object_paths.push(mixed_cc_obj);
 
+    let custom_cc_obj = out_dir.join("slipstream_custom_cc.c.o");
+    compile_cc(&cc, &custom_cc_src, &custom_cc_obj, &picoquic_include_dir)?;
+    object_paths.push(custom_cc_obj);
+
     let poll_obj = out_dir.join("slipstream_poll.c.o");
     compile_cc(&cc, &poll_src, &poll_obj, &picoquic_include_dir)?;
     object_paths.push(poll_obj);
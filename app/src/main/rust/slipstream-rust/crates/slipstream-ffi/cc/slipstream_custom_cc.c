@@ -0,0 +1,107 @@
+#include <stdint.h>
+#include <string.h>
+
+#include <picoquic_internal.h>
+
+/* Callbacks into a congestion controller implemented outside picoquic (the
+ * Rust CongestionControl trait). picoquic keeps owning loss detection and RTT
+ * estimation; the controller only turns ACK and loss events into a window. */
+typedef struct st_slipstream_custom_cc_callbacks_t {
+    void* ctx;
+    void (*on_ack)(void* ctx, uint64_t path_id, uint64_t acked_bytes, uint64_t rtt_us, uint64_t now_us);
+    void (*on_loss)(void* ctx, uint64_t path_id, uint64_t lost_bytes, uint64_t now_us);
+    uint64_t (*window)(void* ctx, uint64_t path_id);
+    int (*can_send)(void* ctx, uint64_t path_id, uint64_t bytes_in_flight);
+} slipstream_custom_cc_callbacks_t;
+
+static slipstream_custom_cc_callbacks_t slipstream_custom_cc = { 0 };
+
+static void slipstream_custom_cc_update_window(picoquic_cnx_t* cnx, picoquic_path_t* path_x)
+{
+    if (slipstream_custom_cc.window == NULL) {
+        return;
+    }
+    uint64_t path_id = path_x->unique_path_id;
+    uint64_t cwin = slipstream_custom_cc.window(slipstream_custom_cc.ctx, path_id);
+    if (slipstream_custom_cc.can_send != NULL &&
+        !slipstream_custom_cc.can_send(slipstream_custom_cc.ctx, path_id, path_x->bytes_in_transit)) {
+        cwin = path_x->bytes_in_transit;
+    }
+    /* With nothing in flight no ACK would ever reopen the window. */
+    if (path_x->bytes_in_transit == 0 && cwin < path_x->send_mtu) {
+        cwin = path_x->send_mtu;
+    }
+    path_x->cwin = cwin;
+    path_x->is_cc_data_updated = 1;
+    picoquic_update_pacing_data(cnx, path_x, 0);
+}
+
+static void slipstream_custom_cc_init(picoquic_cnx_t* cnx, picoquic_path_t* path_x, uint64_t current_time)
+{
+    (void)current_time;
+    path_x->congestion_alg_state = NULL;
+    path_x->cwin = PICOQUIC_CWIN_INITIAL;
+    slipstream_custom_cc_update_window(cnx, path_x);
+}
+
+static void slipstream_custom_cc_notify(
+    picoquic_cnx_t* cnx,
+    picoquic_path_t* path_x,
+    picoquic_congestion_notification_t notification,
+    picoquic_per_ack_state_t* ack_state,
+    uint64_t current_time)
+{
+    uint64_t path_id = path_x->unique_path_id;
+    switch (notification) {
+    case picoquic_congestion_notification_acknowledgement:
+        if (slipstream_custom_cc.on_ack != NULL && ack_state != NULL) {
+            slipstream_custom_cc.on_ack(slipstream_custom_cc.ctx, path_id,
+                ack_state->nb_bytes_acknowledged, path_x->smoothed_rtt, current_time);
+        }
+        break;
+    case picoquic_congestion_notification_repeat:
+    case picoquic_congestion_notification_timeout:
+    case picoquic_congestion_notification_ecn_ec:
+        if (slipstream_custom_cc.on_loss != NULL) {
+            uint64_t lost = (ack_state != NULL) ? ack_state->nb_bytes_newly_lost : 0;
+            slipstream_custom_cc.on_loss(slipstream_custom_cc.ctx, path_id, lost, current_time);
+        }
+        break;
+    default:
+        break;
+    }
+    slipstream_custom_cc_update_window(cnx, path_x);
+}
+
+static void slipstream_custom_cc_delete(picoquic_path_t* path_x)
+{
+    path_x->congestion_alg_state = NULL;
+}
+
+static void slipstream_custom_cc_observe(picoquic_path_t* path_x, uint64_t* cc_state, uint64_t* cc_param)
+{
+    *cc_state = 0;
+    *cc_param = path_x->cwin;
+}
+
+#define picoquic_slipstream_custom_cc_ID "slipstream_custom"
+#define PICOQUIC_CC_ALGO_NUMBER_SLIPSTREAM_CUSTOM 12
+
+picoquic_congestion_algorithm_t slipstream_custom_cc_algorithm_struct = {
+    picoquic_slipstream_custom_cc_ID, PICOQUIC_CC_ALGO_NUMBER_SLIPSTREAM_CUSTOM,
+    slipstream_custom_cc_init,
+    slipstream_custom_cc_notify,
+    slipstream_custom_cc_delete,
+    slipstream_custom_cc_observe
+};
+
+picoquic_congestion_algorithm_t* slipstream_custom_cc_algorithm = &slipstream_custom_cc_algorithm_struct;
+
+void slipstream_set_custom_cc(const slipstream_custom_cc_callbacks_t* callbacks)
+{
+    if (callbacks == NULL) {
+        memset(&slipstream_custom_cc, 0, sizeof(slipstream_custom_cc));
+        return;
+    }
+    slipstream_custom_cc = *callbacks;
+}
@@ -103,6 +103,48 @@ void slipstream_set_cc_override(const char* alg_name)
     slipstream_cc_override = alg;
 }
 
+void slipstream_set_cc_override_algorithm(picoquic_congestion_algorithm_t const* alg)
+{
+    slipstream_cc_override = alg;
+}
+
+/* Read-only view of the controller running on one path, for diagnostics. The
+ * meaning of cc_state and cc_param is controller-specific: for bbr they are the
+ * state machine phase and the bandwidth estimate, for (d)cubic the phase and
+ * W_max in packets. */
+typedef struct st_slipstream_cc_observation_t {
+    uint64_t algorithm_number;
+    uint64_t cc_state;
+    uint64_t cc_param;
+} slipstream_cc_observation_t;
+
+int slipstream_observe_path_cc(picoquic_cnx_t* cnx, uint64_t unique_path_id, slipstream_cc_observation_t* observation)
+{
+    if (cnx == NULL || observation == NULL) {
+        return -1;
+    }
+    int path_id = picoquic_get_path_id_from_unique(cnx, unique_path_id);
+    if (path_id < 0 || path_id >= cnx->nb_paths || cnx->path[path_id] == NULL) {
+        return -1;
+    }
+    picoquic_path_t* path_x = cnx->path[path_id];
+    picoquic_congestion_algorithm_t const* alg = cnx->congestion_alg;
+    if (alg == slipstream_mixed_cc_algorithm) {
+        alg = slipstream_select_cc(path_x);
+    }
+    if (alg == NULL) {
+        return -1;
+    }
+    observation->algorithm_number = alg->congestion_algorithm_number;
+    observation->cc_state = 0;
+    observation->cc_param = 0;
+    /* The built-in observers dereference their state, which only exists after init. */
+    if (alg->alg_observe != NULL && path_x->congestion_alg_state != NULL) {
+        alg->alg_observe(path_x, &observation->cc_state, &observation->cc_param);
+    }
+    return 0;
+}
+
 void slipstream_set_default_path_mode(int mode)
 {
     slipstream_default_path_mode = slipstream_normalize_mode(mode);
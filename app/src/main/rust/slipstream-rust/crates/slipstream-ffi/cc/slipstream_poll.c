@@ -75,3 +75,23 @@ uint64_t slipstream_get_max_streams_bidir_remote(picoquic_cnx_t *cnx) {
     /* STREAM_RANK_FROM_ID is 1-based and returns stream count, not a zero-based index. */
     return STREAM_RANK_FROM_ID(cnx->max_stream_id_bidir_remote);
 }
+
+uint64_t slipstream_get_unconsumed_data(picoquic_cnx_t *cnx) {
+    if (cnx == NULL || cnx->data_received < cnx->data_consumed) {
+        return 0;
+    }
+    /* Counted up to the highest offset of each stream, so gaps are included. */
+    return cnx->data_received - cnx->data_consumed;
+}
+
+void slipstream_hold_path_retransmit_timer(picoquic_cnx_t *cnx, int path_id, uint64_t floor_us) {
+    if (cnx == NULL || path_id < 0 || path_id >= cnx->nb_paths) {
+        return;
+    }
+    picoquic_path_t* path_x = cnx->path[path_id];
+    /* picoquic sets the timer from the smoothed RTT on every sample, so the
+     * floor only lasts until the next acknowledgement on the path. */
+    if (path_x->retransmit_timer < floor_us) {
+        path_x->retransmit_timer = floor_us;
+    }
+}
@@ -0,0 +1,224 @@
+//! Congestion controllers written in Rust.
+//!
+//! The built-in algorithms (bbr, dcubic) live in picoquic and stay selected by
+//! name. A [`CongestionControl`] implementation can replace them for every path
+//! of a client through `ClientConfig::congestion_controller`, without touching
+//! the C side: `cc/slipstream_custom_cc.c` forwards picoquic's notifications to
+//! the trait and writes the returned window back into the path.
+
+use crate::picoquic::{
+    slipstream_custom_cc_algorithm, slipstream_custom_cc_callbacks_t,
+    slipstream_set_cc_override_algorithm, slipstream_set_custom_cc,
+};
+use libc::{c_int, c_void};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// A congestion controller driven by picoquic's loss detection.
+///
+/// picoquic measures RTT, detects losses and paces packets; the controller only
+/// decides how many bytes each path may have in flight. Every method gets the
+/// path's unique id, so one controller can keep per-path state. All calls come
+/// from the client's event loop thread, one at a time.
+///
+/// Contract:
+/// - [`on_ack`](Self::on_ack) runs for every ACK that acknowledges new data,
+///   with the newly acknowledged bytes and the current smoothed RTT.
+/// - [`on_loss`](Self::on_loss) runs when packets are declared lost, on a
+///   retransmission timeout and on an ECN congestion mark. `lost_bytes` is 0
+///   when picoquic does not know the amount (timeouts).
+/// - [`window`](Self::window) is read after every event and becomes the path's
+///   congestion window in bytes.
+/// - [`can_send`](Self::can_send) is checked right after; returning false closes
+///   the window at the bytes already in flight until the next event. A path with
+///   nothing in flight may always send one packet, since no ACK could otherwise
+///   reopen it.
+///
+/// Methods must not panic: they run inside picoquic callbacks, and a panic
+/// there aborts the process.
+pub trait CongestionControl: Send {
+    fn on_ack(&mut self, path_id: u64, acked_bytes: u64, rtt_us: u64, now_us: u64);
+    fn on_loss(&mut self, path_id: u64, lost_bytes: u64, now_us: u64);
+    fn window(&self, path_id: u64) -> u64;
+    fn can_send(&self, path_id: u64, bytes_in_flight: u64) -> bool {
+        bytes_in_flight < self.window(path_id)
+    }
+}
+
+/// Additive-increase, multiplicative-decrease: a small reference controller.
+///
+/// Grows each path's window by `packet_bytes` per window of acknowledged data
+/// and halves it on loss, at most once per RTT.
+#[derive(Debug, Clone)]
+pub struct Aimd {
+    packet_bytes: u64,
+    initial_window: u64,
+    min_window: u64,
+    paths: HashMap<u64, AimdPath>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct AimdPath {
+    window: u64,
+    acked: u64,
+    rtt_us: u64,
+    last_decrease_us: Option<u64>,
+}
+
+impl Aimd {
+    pub fn new(packet_bytes: u64, initial_packets: u64) -> Self {
+        let packet_bytes = packet_bytes.max(1);
+        Self {
+            packet_bytes,
+            initial_window: packet_bytes.saturating_mul(initial_packets.max(2)),
+            min_window: packet_bytes.saturating_mul(2),
+            paths: HashMap::new(),
+        }
+    }
+
+    fn path(&mut self, path_id: u64) -> &mut AimdPath {
+        let window = self.initial_window;
+        self.paths.entry(path_id).or_insert(AimdPath {
+            window,
+            acked: 0,
+            rtt_us: 0,
+            last_decrease_us: None,
+        })
+    }
+}
+
+impl Default for Aimd {
+    fn default() -> Self {
+        Self::new(1_232, 10)
+    }
+}
+
+impl CongestionControl for Aimd {
+    fn on_ack(&mut self, path_id: u64, acked_bytes: u64, rtt_us: u64, _now_us: u64) {
+        let packet_bytes = self.packet_bytes;
+        let path = self.path(path_id);
+        path.rtt_us = rtt_us;
+        path.acked = path.acked.saturating_add(acked_bytes);
+        while path.acked >= path.window {
+            path.acked -= path.window;
+            path.window = path.window.saturating_add(packet_bytes);
+        }
+    }
+
+    fn on_loss(&mut self, path_id: u64, _lost_bytes: u64, now_us: u64) {
+        let min_window = self.min_window;
+        let path = self.path(path_id);
+        let recent = path
+            .last_decrease_us
+            .is_some_and(|at| now_us.saturating_sub(at) < path.rtt_us);
+        if recent {
+            return;
+        }
+        path.window = (path.window / 2).max(min_window);
+        path.acked = 0;
+        path.last_decrease_us = Some(now_us);
+    }
+
+    fn window(&self, path_id: u64) -> u64 {
+        self.paths
+            .get(&path_id)
+            .map_or(self.initial_window, |path| path.window)
+    }
+}
+
+/// A boxed controller as carried by `ClientConfig`.
+pub struct CustomCongestionControl {
+    inner: Mutex<Box<dyn CongestionControl>>,
+}
+
+impl CustomCongestionControl {
+    pub fn new(controller: Box<dyn CongestionControl>) -> Self {
+        Self {
+            inner: Mutex::new(controller),
+        }
+    }
+
+    /// Routes picoquic's congestion events for every path to this controller
+    /// until the returned guard is dropped.
+    ///
+    /// # Safety
+    /// The QUIC context must have been configured with the mixed congestion
+    /// algorithm, and no other controller may be installed while the guard lives.
+    pub unsafe fn install(&self) -> CustomCongestionGuard<'_> {
+        let callbacks = slipstream_custom_cc_callbacks_t {
+            ctx: self as *const Self as *mut c_void,
+            on_ack: Some(custom_cc_on_ack),
+            on_loss: Some(custom_cc_on_loss),
+            window: Some(custom_cc_window),
+            can_send: Some(custom_cc_can_send),
+        };
+        slipstream_set_custom_cc(&callbacks);
+        slipstream_set_cc_override_algorithm(slipstream_custom_cc_algorithm);
+        CustomCongestionGuard { _controller: self }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut dyn CongestionControl) -> R) -> Option<R> {
+        self.inner.lock().ok().map(|mut inner| f(inner.as_mut()))
+    }
+}
+
+impl fmt::Debug for CustomCongestionControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomCongestionControl")
+    }
+}
+
+/// Uninstalls the custom controller when dropped.
+pub struct CustomCongestionGuard<'a> {
+    _controller: &'a CustomCongestionControl,
+}
+
+impl Drop for CustomCongestionGuard<'_> {
+    fn drop(&mut self) {
+        // SAFETY: clearing the callbacks only resets C-side statics.
+        unsafe {
+            slipstream_set_cc_override_algorithm(std::ptr::null());
+            slipstream_set_custom_cc(std::ptr::null());
+        }
+    }
+}
+
+unsafe fn controller<'a>(ctx: *mut c_void) -> &'a CustomCongestionControl {
+    &*(ctx as *const CustomCongestionControl)
+}
+
+unsafe extern "C" fn custom_cc_on_ack(
+    ctx: *mut c_void,
+    path_id: u64,
+    acked_bytes: u64,
+    rtt_us: u64,
+    now_us: u64,
+) {
+    controller(ctx).with(|cc| cc.on_ack(path_id, acked_bytes, rtt_us, now_us));
+}
+
+unsafe extern "C" fn custom_cc_on_loss(
+    ctx: *mut c_void,
+    path_id: u64,
+    lost_bytes: u64,
+    now_us: u64,
+) {
+    controller(ctx).with(|cc| cc.on_loss(path_id, lost_bytes, now_us));
+}
+
+unsafe extern "C" fn custom_cc_window(ctx: *mut c_void, path_id: u64) -> u64 {
+    controller(ctx)
+        .with(|cc| cc.window(path_id))
+        .unwrap_or(u64::MAX)
+}
+
+unsafe extern "C" fn custom_cc_can_send(
+    ctx: *mut c_void,
+    path_id: u64,
+    bytes_in_flight: u64,
+) -> c_int {
+    controller(ctx)
+        .with(|cc| cc.can_send(path_id, bytes_in_flight))
+        .map_or(1, c_int::from)
+}
@@ -0,0 +1,64 @@
+//! Inspection of outgoing DNS queries.
+//!
+//! A [`QueryHook`] set through `ClientConfig::query_hook` sees every query right
+//! before it goes to the resolver transport, e.g. so an integration test can
+//! record what the send path does or simulate loss. Without a hook the send
+//! path only checks that none is set.
+
+use std::fmt;
+use std::net::SocketAddr;
+
+/// Metadata of one outgoing query.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryInfo<'a> {
+    /// Resolver the query is addressed to.
+    pub resolver: SocketAddr,
+    /// DNS transaction ID.
+    pub id: u16,
+    /// Query name with the trailing dot; its length is the name length.
+    pub qname: &'a str,
+    pub qtype: u16,
+    /// Size of the whole DNS message in bytes.
+    pub size: usize,
+    /// Sent to fetch downstream data rather than because data was queued.
+    pub poll: bool,
+}
+
+/// What happens to a query after the hook saw it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryVerdict {
+    Send,
+    /// Lose the query as if the network dropped it. Only honored when
+    /// slipstream-client is built with the `test-hooks` feature (and in its own
+    /// tests); other builds send the query anyway.
+    Drop,
+}
+
+/// Called with each outgoing query.
+///
+/// The client's event loop calls it from one thread, one query at a time, and
+/// waits for it, so it must return quickly and must not panic.
+pub trait QueryHook: Send + Sync {
+    fn inspect(&self, query: &QueryInfo<'_>) -> QueryVerdict;
+}
+
+/// A boxed hook as carried by `ClientConfig`.
+pub struct CustomQueryHook {
+    inner: Box<dyn QueryHook>,
+}
+
+impl CustomQueryHook {
+    pub fn new(hook: Box<dyn QueryHook>) -> Self {
+        Self { inner: hook }
+    }
+
+    pub fn hook(&self) -> &dyn QueryHook {
+        self.inner.as_ref()
+    }
+}
+
+impl fmt::Debug for CustomQueryHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomQueryHook")
+    }
+}
@@ -3,11 +3,17 @@
 use openssl_sys as _;
 use slipstream_core::HostPort;
 
+pub mod congestion;
+pub mod hook;
 pub mod picoquic;
 pub mod runtime;
+pub mod transport;
 
+pub use congestion::{Aimd, CongestionControl, CustomCongestionControl};
+pub use hook::{CustomQueryHook, QueryHook, QueryInfo, QueryVerdict};
 pub use picoquic::get_pacing_rate;
 pub use picoquic::get_rtt;
+pub use transport::{CustomResolverTransport, ResolverTransport};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(i32)]
@@ -16,6 +22,143 @@ pub enum ResolverMode {
     Authoritative = 2,
 }
 
+/// How DNS transaction IDs are chosen for outgoing queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DnsIdStrategy {
+    #[default]
+    Random,
+    Sequential,
+    /// Every query uses the same ID; only meant for tests.
+    Fixed(u16),
+}
+
+/// Which resolver paths carry tunnel data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResolverRolePolicy {
+    /// Every path carries data; QUIC multipath schedules across all of them.
+    #[default]
+    Mixed,
+    /// While an authoritative path is up, recursive paths are put on standby and
+    /// only carry polls and keep-alives.
+    Split,
+}
+
+/// How the primary resolver is chosen among the usable ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResolverPreference {
+    /// The first usable resolver in configured order.
+    #[default]
+    Order,
+    /// The usable resolver with the highest measured downstream goodput; the
+    /// configured order decides until resolvers have been measured.
+    Bandwidth,
+}
+
+/// How payload bytes are spelled in query name labels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LabelEncoding {
+    /// Base32: letters and the digits 2 to 7, 1.6 characters per byte.
+    #[default]
+    Base32,
+    /// Only the letters `a` to `p`, two characters per byte, for resolvers that
+    /// reject or rewrite base32 labels.
+    Safe,
+}
+
+/// What a resolver's answers that are not tunnel data lead to, such as the
+/// address of a real website under the tunnel domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonTunnelPolicy {
+    /// Drop them, warn once per resolver and report a `non_tunnel_response` event.
+    #[default]
+    Report,
+    /// Also count them as failures, so the resolver is moved off like one
+    /// that stopped answering.
+    Avoid,
+}
+
+/// What happens when a resolver answers in a way that does not match its
+/// configured mode: an authoritative path answered by a recursive resolver, or
+/// a recursive path answered by the tunnel server directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ModeMismatchPolicy {
+    /// Keep the configured mode, warn once per resolver and report a
+    /// `resolver_mode_mismatch` event.
+    #[default]
+    Report,
+    /// Also move an authoritative path answered by a recursive resolver to
+    /// recursive polling, whose query rate such a resolver tolerates. A
+    /// recursive path is never sped up on its own.
+    Adapt,
+}
+
+/// What a run of FORMERR answers to well-formed tunnel queries leads to. Such
+/// a resolver usually objects to something about the query's form, such as
+/// its class or an EDNS option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FormerrPolicy {
+    /// Retry with a plainer query form, dropping the DNS Cookie and then
+    /// falling back to class IN, and report a `resolver_rejects_encoding`
+    /// event for each step. Once no plainer form is left, FORMERR answers
+    /// count as failures.
+    #[default]
+    Adapt,
+    /// Keep the queries as they are, warn once per resolver and report the
+    /// event.
+    Report,
+}
+
+/// How many DNS queries may be unanswered at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PipelineMode {
+    /// As many as polling, pacing and QUIC have ready.
+    #[default]
+    Pipelined,
+    /// One at a time across all resolvers, like an ordinary stub resolver,
+    /// with no duplicate handshake queries; the next query waits for an answer
+    /// or the poll timeout. Much slower.
+    Single,
+}
+
+/// What happens to the tunnel stream when the local TCP client closes its side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LocalClosePolicy {
+    /// Queue a FIN and keep reading until the server finishes its side.
+    #[default]
+    HalfClose,
+    /// Reset the stream in both directions and drop its state immediately.
+    Reset,
+}
+
+/// A daily window, in minutes after local midnight, during which the tunnel runs.
+///
+/// An end before the start wraps past midnight; equal values cover the whole day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActiveWindow {
+    pub start_minute: u16,
+    pub end_minute: u16,
+}
+
+/// Whether the DNS socket is excluded from the Android VPN before use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtectionMode {
+    /// Call `VpnService.protect()` on the UDP socket and fail if it is refused.
+    #[default]
+    VpnProtected,
+    /// Leave the socket alone, for proxy-only use without a `VpnService`.
+    None,
+}
+
+/// What the client does once the VPN stops protecting its DNS socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtectionLossPolicy {
+    /// Drop the tunnel, refuse new local connections and wait to be stopped.
+    #[default]
+    Hold,
+    /// Exit the client with an error.
+    Stop,
+}
+
 #[derive(Debug, Clone)]
 pub struct ResolverSpec {
     pub resolver: HostPort,
@@ -32,8 +175,147 @@ pub struct ClientConfig<'a> {
     pub congestion_control: Option<&'a str>,
     pub gso: bool,
     pub keep_alive_interval: usize,
+    pub adaptive_keep_alive: bool,
     pub debug_poll: bool,
     pub debug_streams: bool,
+    pub record_dns: Option<&'a str>,
+    /// File that gets a line per query sent: time, resolver, DNS ID and name.
+    pub query_log: Option<&'a str>,
+    /// DNS packets kept in memory for a support capture; 0 disables it.
+    pub flight_recorder_records: usize,
+    pub resolver_failure_threshold: u32,
+    pub resolver_grace_period_ms: u64,
+    pub resolver_recovery_probe_ms: u64,
+    /// SERVFAILs in a row that pause a resolver; 0 treats them as normal answers.
+    pub resolver_servfail_threshold: u32,
+    pub resolver_servfail_cooldown_ms: u64,
+    /// Distinct resolvers that must carry tunnel data before the connection is
+    /// reported as up; 1 reports it as soon as the handshake completes.
+    pub confirm_resolvers: usize,
+    /// Other resolvers each handshake query is also sent to, so one that drops
+    /// it does not stall the handshake; 0 sends it to one resolver only.
+    pub handshake_redundancy: usize,
+    pub status_host: &'a str,
+    pub status_port: Option<u16>,
+    /// Serve control requests (POST /stop) on the status endpoint; loopback only.
+    pub status_control: bool,
+    pub dns_listen_host: &'a str,
+    /// UDP port of the local DNS forwarder; queries are resolved through the tunnel.
+    pub dns_listen_port: Option<u16>,
+    /// Resolver (HOST:PORT) the forwarder reaches through the SOCKS5 proxy behind the tunnel.
+    pub dns_upstream: &'a str,
+    pub dns_socks_username: Option<&'a str>,
+    pub dns_socks_password: Option<&'a str>,
+    pub dns_id_strategy: DnsIdStrategy,
+    /// DNS class of every query; IN (1) unless the server expects another.
+    pub query_class: u16,
+    /// Send DNS Cookies (RFC 7873) and echo the cookie each resolver returns.
+    pub edns_cookies: bool,
+    /// Learn each resolver's tolerated query rate from REFUSED answers and
+    /// dropped polls, and stay under it.
+    pub adaptive_query_rate: bool,
+    /// Queries per second each resolver starts a connection at, doubling
+    /// while the tunnel asks for more; 0 sends at full rate from the start.
+    pub query_ramp_qps: u32,
+    /// How often the startup query rate doubles.
+    pub query_ramp_doubling_ms: u64,
+    /// Longest time a small TCP read may wait for more data before it is tunneled.
+    pub coalesce_delay_ms: u64,
+    pub reconnect_min_ms: u64,
+    pub reconnect_max_ms: u64,
+    /// Randomize reconnect delays (decorrelated jitter) instead of plain doubling.
+    pub reconnect_jitter: bool,
+    /// Reconnects in a row without a ready connection before the client gives
+    /// up with a `gave_up` event; 0 retries forever.
+    pub max_reconnect_attempts: u32,
+    /// Longest time to keep reconnecting after losing the connection before
+    /// giving up; 0 is no limit.
+    pub max_reconnect_duration_ms: u64,
+    /// Failed attempts in a row, all failing the same way, that count as a
+    /// blocked domain; 0 disables block detection.
+    pub block_backoff_after: u32,
+    /// Reconnect delay used while a block is suspected.
+    pub block_backoff_ms: u64,
+    pub resolver_role_policy: ResolverRolePolicy,
+    pub resolver_preference: ResolverPreference,
+    /// Path probes to extra resolvers that may be pending validation at once;
+    /// `None` probes every resolver right after the handshake.
+    pub max_parallel_probes: Option<usize>,
+    /// How long a poll may go unanswered before a replacement is sent.
+    pub poll_timeout_ms: u64,
+    /// First gap between polls once no stream data has moved for a while, and
+    /// the longest one while streams are open.
+    pub idle_poll_min_ms: u64,
+    /// Longest gap between polls while no stream is open; 0 polls at full rate.
+    pub idle_poll_max_ms: u64,
+    /// Quiet time after which one poll checks that the session still answers;
+    /// no answer within `poll_timeout_ms` reconnects. 0 disables the refresh.
+    pub idle_refresh_ms: u64,
+    /// Polls kept waiting at a server that holds them until it has data, so it
+    /// can push downstream data at once; 0 disables the pool.
+    pub poll_pool: usize,
+    /// Round-robin each stream's packets across the usable resolver paths.
+    pub stream_striping: bool,
+    /// Send streams that queue data slowly ahead of streams sending in bulk.
+    pub prioritize_interactive: bool,
+    /// Give bulk streams a congestion window of their own, so the losses and
+    /// queueing they cause do not hold back interactive streams.
+    pub congestion_isolation: bool,
+    /// Streams opened right after the handshake that are sent ahead of the rest;
+    /// 0 disables the boost.
+    pub first_stream_boost_count: usize,
+    /// How long after the handshake the boost lasts.
+    pub first_stream_boost_ms: u64,
+    /// Accepted local connections that may wait to be set up as streams at once;
+    /// more wait briefly and are then refused. `None` is unbounded.
+    pub max_connection_setups: Option<usize>,
+    /// Stream data the server may have in flight beyond what the client has
+    /// written to local sockets, over all streams, including out-of-order data
+    /// waiting for a gap to fill. Enforced as QUIC connection flow control, so
+    /// the server stalls at the cap and loss recovery fills the gaps. `None`
+    /// keeps the default of `STREAM_WRITE_BUFFER_BYTES`.
+    pub reassembly_limit_bytes: Option<usize>,
+    pub local_close_policy: LocalClosePolicy,
+    pub protection_mode: ProtectionMode,
+    pub protection_loss_policy: ProtectionLossPolicy,
+    /// `SO_RCVBUF` for the DNS socket; `None` keeps the OS default. The OS may
+    /// grant less.
+    pub udp_rcvbuf_bytes: Option<usize>,
+    /// `SO_SNDBUF` for the DNS socket; `None` keeps the OS default.
+    pub udp_sndbuf_bytes: Option<usize>,
+    /// Local address the DNS socket binds to; `None` lets the OS pick one per
+    /// resolver. Ignored with a custom resolver transport.
+    pub source_address: Option<std::net::IpAddr>,
+    /// Longest query name sent, at most the DNS maximum of 253; shorter names
+    /// carry less data per query. `None` allows the maximum.
+    pub max_qname_len: Option<usize>,
+    /// Label alphabet of query names; the safe encoding carries about a fifth
+    /// less data per query.
+    pub label_encoding: LabelEncoding,
+    /// Answers that are not tunnel data are always dropped; this decides whether
+    /// they also count against the resolver.
+    pub non_tunnel_policy: NonTunnelPolicy,
+    pub mode_mismatch_policy: ModeMismatchPolicy,
+    pub formerr_policy: FormerrPolicy,
+    /// Stop the client once this many DNS queries were sent; `None` is unlimited.
+    pub max_lifetime_queries: Option<u64>,
+    pub pipeline_mode: PipelineMode,
+    /// Local-time windows the tunnel runs in; it is paused outside them. Empty
+    /// keeps it always active.
+    pub active_windows: &'a [ActiveWindow],
+    /// Local-time windows in which an idle tunnel stays connected but keeps
+    /// background activity low. Empty turns quiet hours off.
+    pub quiet_hours: &'a [ActiveWindow],
+    /// How long a disconnect or stall must last before it is reported as an
+    /// event; shorter blips only produce a `recovered` event.
+    pub event_grace_ms: u64,
+    /// Controller that replaces the built-in congestion control on every path;
+    /// takes precedence over `congestion_control`.
+    pub congestion_controller: Option<CustomCongestionControl>,
+    /// Carries DNS queries instead of the built-in UDP socket.
+    pub resolver_transport: Option<CustomResolverTransport>,
+    /// Sees every outgoing query before it is sent, for tests and diagnostics.
+    pub query_hook: Option<CustomQueryHook>,
 }
 
 pub use runtime::{
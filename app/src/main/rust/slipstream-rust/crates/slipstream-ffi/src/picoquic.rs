@@ -7,6 +7,12 @@ pub const PICOQUIC_MAX_PACKET_SIZE: usize = 1536;
 pub const PICOQUIC_RESET_SECRET_SIZE: usize = 16;
 pub const PICOQUIC_PACKET_LOOP_RECV_MAX: usize = 10;
 pub const PICOQUIC_PACKET_LOOP_SEND_MAX: usize = 10;
+pub const PICOQUIC_ERROR_VERSION_NEGOTIATION: u64 = 0x400 + 55;
+pub const PICOQUIC_TRANSPORT_PARAMETER_ERROR: u64 = 0x8;
+pub const PICOQUIC_TRANSPORT_VERSION_NEGOTIATION_ERROR: u64 = 0x11;
+pub const PICOQUIC_TLS_ALERT_WRONG_ALPN: u64 = 0x178;
+pub const PICOQUIC_PATH_STATUS_AVAILABLE: c_int = 0;
+pub const PICOQUIC_PATH_STATUS_STANDBY: c_int = 1;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -35,6 +41,25 @@ pub struct picoquic_congestion_algorithm_t {
     _private: [u8; 0],
 }
 
+/// Callbacks of `cc/slipstream_custom_cc.c`; see `crate::congestion`.
+#[repr(C)]
+pub struct slipstream_custom_cc_callbacks_t {
+    pub ctx: *mut c_void,
+    pub on_ack: Option<unsafe extern "C" fn(*mut c_void, u64, u64, u64, u64)>,
+    pub on_loss: Option<unsafe extern "C" fn(*mut c_void, u64, u64, u64)>,
+    pub window: Option<unsafe extern "C" fn(*mut c_void, u64) -> u64>,
+    pub can_send: Option<unsafe extern "C" fn(*mut c_void, u64, u64) -> c_int>,
+}
+
+/// Output of `slipstream_observe_path_cc`; see `cc/slipstream_mixed_cc.c`.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct slipstream_cc_observation_t {
+    pub algorithm_number: u64,
+    pub cc_state: u64,
+    pub cc_param: u64,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Default)]
 pub struct picoquic_path_quality_t {
@@ -203,6 +228,11 @@ extern "C" {
 
     pub fn picoquic_set_cookie_mode(quic: *mut picoquic_quic_t, cookie_mode: c_int);
     pub fn picoquic_set_default_priority(quic: *mut picoquic_quic_t, default_stream_priority: u8);
+    pub fn picoquic_set_stream_priority(
+        cnx: *mut picoquic_cnx_t,
+        stream_id: u64,
+        stream_priority: u8,
+    ) -> c_int;
     pub fn picoquic_set_default_direct_receive_callback(
         quic: *mut picoquic_quic_t,
         direct_receive_fn: picoquic_stream_direct_receive_fn,
@@ -265,6 +295,8 @@ extern "C" {
 
     pub static mut slipstream_server_cc_algorithm: *mut picoquic_congestion_algorithm_t;
     pub static mut slipstream_mixed_cc_algorithm: *mut picoquic_congestion_algorithm_t;
+    pub static mut slipstream_custom_cc_algorithm: *mut picoquic_congestion_algorithm_t;
+    pub fn slipstream_set_custom_cc(callbacks: *const slipstream_custom_cc_callbacks_t);
 
     pub fn picoquic_create_client_cnx(
         quic: *mut picoquic_quic_t,
@@ -321,14 +353,27 @@ extern "C" {
         unique_path_id: u64,
     ) -> c_int;
     pub fn slipstream_get_max_streams_bidir_remote(cnx: *mut picoquic_cnx_t) -> u64;
+    pub fn slipstream_get_unconsumed_data(cnx: *mut picoquic_cnx_t) -> u64;
     pub fn slipstream_set_cc_override(alg_name: *const c_char);
+    pub fn slipstream_set_cc_override_algorithm(alg: *const picoquic_congestion_algorithm_t);
     pub fn slipstream_set_default_path_mode(mode: c_int);
+    pub fn slipstream_observe_path_cc(
+        cnx: *mut picoquic_cnx_t,
+        unique_path_id: u64,
+        observation: *mut slipstream_cc_observation_t,
+    ) -> c_int;
     pub fn slipstream_set_path_mode(cnx: *mut picoquic_cnx_t, path_id: c_int, mode: c_int);
     pub fn slipstream_set_path_ack_delay(cnx: *mut picoquic_cnx_t, path_id: c_int, disable: c_int);
+    pub fn slipstream_hold_path_retransmit_timer(
+        cnx: *mut picoquic_cnx_t,
+        path_id: c_int,
+        floor_us: u64,
+    );
 
     pub fn picoquic_get_first_cnx(quic: *mut picoquic_quic_t) -> *mut picoquic_cnx_t;
     pub fn picoquic_get_next_cnx(cnx: *mut picoquic_cnx_t) -> *mut picoquic_cnx_t;
     pub fn picoquic_get_cnx_state(cnx: *mut picoquic_cnx_t) -> picoquic_state_enum;
+    pub fn picoquic_get_logging_cnxid(cnx: *mut picoquic_cnx_t) -> picoquic_connection_id_t;
     pub fn picoquic_get_close_reasons(
         cnx: *mut picoquic_cnx_t,
         local_reason: *mut u64,
@@ -433,6 +478,17 @@ extern "C" {
         path_id_p: *mut c_int,
     ) -> c_int;
 
+    pub fn picoquic_set_path_status(
+        cnx: *mut picoquic_cnx_t,
+        unique_path_id: u64,
+        status: c_int,
+    ) -> c_int;
+    /// Restricts a stream's frames to one path; `u64::MAX` lifts the restriction.
+    pub fn picoquic_set_stream_path_affinity(
+        cnx: *mut picoquic_cnx_t,
+        stream_id: u64,
+        unique_path_id: u64,
+    ) -> c_int;
     pub fn picoquic_get_path_addr(
         cnx: *mut picoquic_cnx_t,
         unique_path_id: u64,
@@ -477,6 +533,26 @@ pub unsafe fn get_pacing_rate(cnx: *mut picoquic_cnx_t) -> u64 {
     }
 }
 
+/// Short id of the connection for log correlation: the first four bytes of the
+/// client's initial connection ID, in hex. Client and server derive the same
+/// value; it is empty for a null `cnx`.
+///
+/// # Safety
+/// `cnx` must be null or point to a valid picoquic connection for the duration
+/// of the call.
+pub unsafe fn session_id(cnx: *mut picoquic_cnx_t) -> String {
+    if cnx.is_null() {
+        return String::new();
+    }
+    // SAFETY: caller guarantees cnx is a valid picoquic connection.
+    let cid = picoquic_get_logging_cnxid(cnx);
+    let len = usize::from(cid.id_len).min(4);
+    cid.id[..len]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
 /// # Safety
 /// `cnx` must be null or point to a valid picoquic connection for the duration
 /// of the call.
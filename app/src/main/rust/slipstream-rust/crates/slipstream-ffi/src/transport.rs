@@ -0,0 +1,75 @@
+//! Resolver transports written in Rust.
+//!
+//! The client sends its DNS queries to the resolvers over a UDP socket by
+//! default. A [`ResolverTransport`] implementation can carry them some other way
+//! through `ClientConfig::resolver_transport`, e.g. inside another tunnel. Only
+//! the resolver leg changes: queries and responses stay plain DNS messages.
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::task::{Context, Poll};
+
+/// Carries DNS queries to resolvers and their responses back.
+///
+/// The client's event loop drives the transport from one thread, one call at a
+/// time, and addresses every resolver by the socket address it was configured
+/// with.
+///
+/// Contract:
+/// - [`poll_send`](Self::poll_send) hands off one whole DNS message for
+///   `resolver`. `Ready(Ok(()))` means it was accepted, not that it arrived;
+///   queries may be lost like UDP datagrams and are never retried by the
+///   transport. `Pending` must arrange for `cx` to be woken once sending can
+///   make progress.
+/// - [`poll_recv`](Self::poll_recv) yields one whole DNS response, written to
+///   the start of `buf`, with the address of the resolver it came from.
+///   Responses are matched to resolvers by that address, so it must be the one
+///   the query was sent to. Responses longer than `buf` may be truncated.
+///   `Pending` must arrange for `cx` to be woken once a response is ready.
+/// - [`local_addr`](Self::local_addr) is the address queries appear to come
+///   from. It labels the QUIC paths and may be any address of the right
+///   family.
+/// - Errors that `slipstream_core::net::is_transient_udp_error` accepts (e.g.
+///   connection refused) are skipped like a lost datagram. Any other error ends
+///   the client run.
+///
+/// Socket protection (`ProtectionMode`) only applies to the built-in UDP
+/// socket; a custom transport must keep its own traffic out of a VPN it feeds.
+pub trait ResolverTransport: Send + Sync {
+    fn poll_send(
+        &self,
+        cx: &mut Context<'_>,
+        message: &[u8],
+        resolver: SocketAddr,
+    ) -> Poll<io::Result<()>>;
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>>;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+/// A boxed transport as carried by `ClientConfig`.
+pub struct CustomResolverTransport {
+    inner: Box<dyn ResolverTransport>,
+}
+
+impl CustomResolverTransport {
+    pub fn new(transport: Box<dyn ResolverTransport>) -> Self {
+        Self { inner: transport }
+    }
+
+    pub fn transport(&self) -> &dyn ResolverTransport {
+        self.inner.as_ref()
+    }
+}
+
+impl fmt::Debug for CustomResolverTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomResolverTransport")
+    }
+}
@@ -0,0 +1,41 @@
+use slipstream_ffi::{Aimd, CongestionControl};
+
+#[test]
+fn aimd_grows_per_window_and_halves_once_per_rtt() {
+    let mut cc = Aimd::new(1_000, 4);
+    assert_eq!(cc.window(7), 4_000);
+
+    // A full window of ACKs adds one packet.
+    cc.on_ack(7, 4_000, 50_000, 1_000);
+    assert_eq!(cc.window(7), 5_000);
+    assert!(cc.can_send(7, 4_999));
+    assert!(!cc.can_send(7, 5_000));
+
+    // Losses within one RTT of the first cut count as the same event.
+    cc.on_loss(7, 1_000, 100_000);
+    assert_eq!(cc.window(7), 2_500);
+    cc.on_loss(7, 1_000, 120_000);
+    assert_eq!(cc.window(7), 2_500);
+    cc.on_loss(7, 1_000, 200_000);
+    assert_eq!(cc.window(7), 2_000);
+    cc.on_loss(7, 1_000, 300_000);
+    assert_eq!(cc.window(7), 2_000, "never below two packets");
+
+    // Other paths keep their own window.
+    assert_eq!(cc.window(8), 4_000);
+}
+
+#[test]
+fn custom_controllers_only_need_the_required_methods() {
+    struct Fixed(u64);
+    impl CongestionControl for Fixed {
+        fn on_ack(&mut self, _: u64, _: u64, _: u64, _: u64) {}
+        fn on_loss(&mut self, _: u64, _: u64, _: u64) {}
+        fn window(&self, _: u64) -> u64 {
+            self.0
+        }
+    }
+    let fixed = Fixed(3_000);
+    assert!(fixed.can_send(0, 2_999));
+    assert!(!fixed.can_send(0, 3_000));
+}
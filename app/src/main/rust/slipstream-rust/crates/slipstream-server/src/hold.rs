@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+/// Polls held at once; further polls without data are answered right away.
+const MAX_HELD_POLLS: usize = 4096;
+
+/// What a held poll gets on this pass.
+pub(crate) enum HeldAnswer {
+    /// A QUIC packet became ready for the poll's connection.
+    Data(Vec<u8>),
+    /// Nothing to send yet; keep holding until the deadline.
+    Wait,
+    /// Answer now without data, e.g. because the connection is gone.
+    Empty,
+}
+
+/// A poll to answer now, with the QUIC packet it carries if one was ready.
+pub(crate) type Answered<T> = (T, Option<Vec<u8>>);
+
+/// Queries that found no downstream data, held so the server can answer them
+/// as soon as data is queued instead of waiting for the client's next poll.
+///
+/// A poll that is still empty at its deadline is answered without data, which
+/// keeps it well inside the resolver's own upstream timeout.
+pub(crate) struct HeldPolls<T> {
+    hold_us: u64,
+    held: VecDeque<(T, u64)>,
+}
+
+impl<T> HeldPolls<T> {
+    pub(crate) fn new(hold_ms: u32) -> Self {
+        Self {
+            hold_us: u64::from(hold_ms) * 1_000,
+            held: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    /// Holds `poll` until `now_us` plus the hold time; hands it back when
+    /// holding is off or the pool is full.
+    pub(crate) fn hold(&mut self, poll: T, now_us: u64) -> Option<T> {
+        if self.hold_us == 0 || self.held.len() >= MAX_HELD_POLLS {
+            return Some(poll);
+        }
+        self.held
+            .push_back((poll, now_us.saturating_add(self.hold_us)));
+        None
+    }
+
+    /// Returns the polls to answer now, oldest first, with their packet when
+    /// `prepare` had one. Expired polls come back without data; the rest stay
+    /// held.
+    pub(crate) fn answer<E>(
+        &mut self,
+        now_us: u64,
+        mut prepare: impl FnMut(&T) -> Result<HeldAnswer, E>,
+    ) -> Result<Vec<Answered<T>>, E> {
+        let mut answered = Vec::new();
+        let mut kept = VecDeque::with_capacity(self.held.len());
+        while let Some((poll, deadline)) = self.held.pop_front() {
+            match prepare(&poll) {
+                Ok(HeldAnswer::Data(packet)) => answered.push((poll, Some(packet))),
+                Ok(HeldAnswer::Empty) => answered.push((poll, None)),
+                Ok(HeldAnswer::Wait) if now_us >= deadline => answered.push((poll, None)),
+                Ok(HeldAnswer::Wait) => kept.push_back((poll, deadline)),
+                Err(err) => {
+                    kept.push_back((poll, deadline));
+                    kept.extend(self.held.drain(..));
+                    self.held = kept;
+                    return Err(err);
+                }
+            }
+        }
+        self.held = kept;
+        Ok(answered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+
+    #[test]
+    fn held_poll_carries_pushed_data_and_expires_empty() {
+        let mut polls = HeldPolls::new(500);
+        assert!(polls.hold(1u16, 0).is_none());
+        assert!(polls.hold(2u16, 100_000).is_none());
+
+        // Nothing queued yet: both stay held.
+        let answered = polls
+            .answer(200_000, |_| Ok::<_, Infallible>(HeldAnswer::Wait))
+            .unwrap();
+        assert!(answered.is_empty());
+
+        // Data pushed for the second poll's connection goes out on it at once.
+        let answered = polls
+            .answer(250_000, |&id| {
+                Ok::<_, Infallible>(if id == 2 {
+                    HeldAnswer::Data(b"push".to_vec())
+                } else {
+                    HeldAnswer::Wait
+                })
+            })
+            .unwrap();
+        assert_eq!(answered, [(2, Some(b"push".to_vec()))]);
+
+        // The first one is answered empty at its deadline.
+        let answered = polls
+            .answer(500_000, |_| Ok::<_, Infallible>(HeldAnswer::Wait))
+            .unwrap();
+        assert_eq!(answered, [(1, None)]);
+        assert!(polls.is_empty());
+
+        let mut off = HeldPolls::new(0);
+        assert_eq!(off.hold(3u16, 0), Some(3));
+    }
+}
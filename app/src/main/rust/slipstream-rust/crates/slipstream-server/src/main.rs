@@ -1,4 +1,5 @@
 mod config;
+mod hold;
 mod server;
 mod streams;
 mod target;
@@ -9,6 +10,8 @@ use server::{run_server, ServerConfig};
 use slipstream_core::{
     normalize_domain, parse_host_port, parse_host_port_parts, sip003, AddressKind, HostPort,
 };
+use slipstream_dns::DEFAULT_RESPONSE_TTL;
+use target::IpVersionPolicy;
 use tokio::runtime::Builder;
 use tracing_subscriber::EnvFilter;
 
@@ -43,6 +46,25 @@ struct Args {
     max_connections: u32,
     #[arg(long = "idle-timeout-seconds", default_value_t = 1200)]
     idle_timeout_seconds: u64,
+    #[arg(
+        long = "target-connect-timeout-ms",
+        value_name = "MS",
+        default_value_t = 0
+    )]
+    target_connect_timeout_ms: u64,
+    #[arg(
+        long = "ip-version-policy",
+        value_name = "POLICY",
+        default_value = "prefer-v4",
+        value_parser = parse_ip_version_policy
+    )]
+    ip_version_policy: IpVersionPolicy,
+    #[arg(long = "response-ttl", value_name = "SECONDS", default_value_t = DEFAULT_RESPONSE_TTL)]
+    response_ttl: u32,
+    #[arg(long = "time-stamps")]
+    time_stamps: bool,
+    #[arg(long = "poll-hold-ms", value_name = "MS", default_value_t = 0)]
+    poll_hold_ms: u32,
     #[arg(long = "debug-streams")]
     debug_streams: bool,
     #[arg(long = "debug-commands")]
@@ -172,6 +194,11 @@ fn main() {
         domains,
         max_connections,
         idle_timeout_seconds: args.idle_timeout_seconds,
+        target_connect_timeout_ms: args.target_connect_timeout_ms,
+        ip_version_policy: args.ip_version_policy,
+        response_ttl: args.response_ttl,
+        time_stamps: args.time_stamps,
+        poll_hold_ms: args.poll_hold_ms,
         debug_streams: args.debug_streams,
         debug_commands: args.debug_commands,
     };
@@ -215,6 +242,18 @@ fn parse_fallback_address(input: &str) -> Result<HostPort, String> {
     Ok(parsed)
 }
 
+fn parse_ip_version_policy(input: &str) -> Result<IpVersionPolicy, String> {
+    match input.trim() {
+        "prefer-v4" => Ok(IpVersionPolicy::PreferV4),
+        "prefer-v6" => Ok(IpVersionPolicy::PreferV6),
+        "happy-eyeballs" => Ok(IpVersionPolicy::HappyEyeballs),
+        other => Err(format!(
+            "Invalid ip-version-policy: {} (expected prefer-v4, prefer-v6, or happy-eyeballs)",
+            other
+        )),
+    }
+}
+
 fn parse_max_connections(input: &str) -> Result<u32, String> {
     let trimmed = input.trim();
     let value = trimmed
@@ -1,4 +1,6 @@
 use crate::config::{ensure_cert_key, load_or_create_reset_seed, ResetSeed};
+use crate::hold::{HeldAnswer, HeldPolls};
+use crate::target::{resolve_target_addrs, IpVersionPolicy};
 use crate::udp_fallback::{handle_packet, FallbackManager, PacketContext, MAX_UDP_PACKET_SIZE};
 use slipstream_core::{
     net::is_transient_udp_error, normalize_dual_stack_addr, resolve_host_port, HostPort,
@@ -21,7 +23,7 @@ use std::net::SocketAddr;
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::net::{lookup_host, UdpSocket as TokioUdpSocket};
 use tokio::sync::mpsc;
 use tokio::time::sleep;
@@ -81,6 +83,18 @@ pub struct ServerConfig {
     pub domains: Vec<String>,
     pub max_connections: u32,
     pub idle_timeout_seconds: u64,
+    /// Reset a stream whose target connect has not completed after this long; 0
+    /// leaves it to the OS.
+    pub target_connect_timeout_ms: u64,
+    /// Address family tried first when the target resolves to both.
+    pub ip_version_policy: IpVersionPolicy,
+    /// TTL set on answers; recursive resolvers may cache them for this long.
+    pub response_ttl: u32,
+    /// Stamp responses with the server clock so clients can estimate their offset.
+    pub time_stamps: bool,
+    /// Hold polls that find no data for up to this long (ms) and answer them as
+    /// soon as data is queued; 0 answers them empty right away.
+    pub poll_hold_ms: u32,
     pub debug_streams: bool,
     pub debug_commands: bool,
 }
@@ -174,7 +188,7 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
         None
     };
 
-    let target_addr = resolve_host_port(&config.target_address)
+    let target_addrs = resolve_target_addrs(&config.target_address)
         .map_err(|err| ServerError::new(err.to_string()))?;
     let fallback_addr = match &config.fallback_address {
         Some(address) => {
@@ -194,11 +208,16 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
     let debug_commands = config.debug_commands;
     let idle_timeout = Duration::from_secs(config.idle_timeout_seconds);
     let mut state = Box::new(ServerState::new(
-        target_addr,
+        target_addrs,
         command_tx,
         debug_streams,
         debug_commands,
     ));
+    state.set_target_connect_timeout(
+        (config.target_connect_timeout_ms > 0)
+            .then(|| Duration::from_millis(config.target_connect_timeout_ms)),
+    );
+    state.set_ip_version_policy(config.ip_version_policy);
     let state_ptr: *mut ServerState = &mut *state;
     let _state = state;
 
@@ -249,6 +268,12 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
     let udp = Arc::new(bind_udp_socket(&config.dns_listen_host, config.dns_listen_port).await?);
     let udp_local_addr = udp.local_addr().map_err(map_io)?;
     let map_ipv4_peers = matches!(udp_local_addr, SocketAddr::V6(_));
+    let answer_options = AnswerOptions {
+        ttl: config.response_ttl,
+        time_stamps: config.time_stamps,
+        poll_hold_ms: config.poll_hold_ms,
+        map_ipv4_peers,
+    };
     let local_addr_storage = socket_addr_to_storage(udp_local_addr);
     if let Some(addr) = fallback_addr {
         if addr == udp_local_addr {
@@ -282,6 +307,7 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
     let mut last_seen = HashMap::new();
     let mut last_idle_gc = Instant::now();
     let mut last_flow_block_log_at: u64 = 0;
+    let mut held_polls: HeldPolls<Slot> = HeldPolls::new(config.poll_hold_ms);
 
     loop {
         drain_commands(state_ptr, &mut command_rx);
@@ -371,36 +397,36 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
         drain_commands(state_ptr, &mut command_rx);
         maybe_report_command_stats(state_ptr);
 
-        if slots.is_empty() {
+        if slots.is_empty() && held_polls.is_empty() {
             continue;
         }
 
         let loop_time = unsafe { picoquic_current_time() };
 
-        for slot in slots.iter_mut() {
+        if !held_polls.is_empty() {
+            let active = collect_active_connections(quic);
+            let answered = held_polls.answer(loop_time, |slot| {
+                if !active.contains_key(&(slot.cnx as usize)) {
+                    return Ok(HeldAnswer::Empty);
+                }
+                let send_length = prepare_slot_packet(slot, loop_time, &mut send_buf)?;
+                Ok(if send_length > 0 {
+                    HeldAnswer::Data(send_buf[..send_length].to_vec())
+                } else {
+                    HeldAnswer::Wait
+                })
+            })?;
+            for (slot, packet) in answered {
+                let rcode = packet.is_none().then_some(Rcode::Ok);
+                send_answer(&udp, &slot, packet.as_deref(), rcode, answer_options).await?;
+            }
+        }
+
+        for slot in slots.drain(..) {
             let mut send_length = 0usize;
-            let mut addr_to: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
-            let mut addr_from: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
-            let mut if_index: libc::c_int = 0;
 
             if slot.payload_override.is_none() && slot.rcode.is_none() && !slot.cnx.is_null() {
-                let ret = unsafe {
-                    picoquic_prepare_packet_ex(
-                        slot.cnx,
-                        slot.path_id,
-                        loop_time,
-                        send_buf.as_mut_ptr(),
-                        send_buf.len(),
-                        &mut send_length,
-                        &mut addr_to,
-                        &mut addr_from,
-                        &mut if_index,
-                        std::ptr::null_mut(),
-                    )
-                };
-                if ret < 0 {
-                    return Err(ServerError::new("Failed to prepare QUIC packet"));
-                }
+                send_length = prepare_slot_packet(&slot, loop_time, &mut send_buf)?;
 
                 if send_length == 0 {
                     let cnx_id = slot.cnx as usize;
@@ -443,42 +469,108 @@ pub async fn run_server(config: &ServerConfig) -> Result<i32, ServerError> {
                 }
             }
 
+            let slot = if slot.payload_override.is_none()
+                && slot.rcode.is_none()
+                && !slot.cnx.is_null()
+                && send_length == 0
+            {
+                // No QUIC payload ready; hold the poll for data, or answer it with
+                // NOERROR and an empty payload to clear it.
+                match held_polls.hold(slot, loop_time) {
+                    Some(slot) => slot,
+                    None => continue,
+                }
+            } else {
+                slot
+            };
+
             let payload_override = slot.payload_override.as_deref();
             let (payload, rcode) = if let Some(payload) = payload_override {
                 (Some(payload), slot.rcode)
             } else if send_length > 0 {
                 (Some(&send_buf[..send_length]), slot.rcode)
             } else if slot.rcode.is_none() {
-                // No QUIC payload ready; still answer the poll with NOERROR and empty payload to clear it.
                 (None, Some(slipstream_dns::Rcode::Ok))
             } else {
                 (None, slot.rcode)
             };
-            let response = encode_response(&ResponseParams {
-                id: slot.id,
-                rd: slot.rd,
-                cd: slot.cd,
-                question: &slot.question,
-                payload,
-                rcode,
-            })
-            .map_err(|err| ServerError::new(err.to_string()))?;
-            let peer = if map_ipv4_peers {
-                normalize_dual_stack_addr(slot.peer)
-            } else {
-                slot.peer
-            };
-            if let Err(err) = udp.send_to(&response, peer).await {
-                if !is_transient_udp_error(&err) {
-                    return Err(map_io(err));
-                }
-            }
+            send_answer(&udp, &slot, payload, rcode, answer_options).await?;
         }
     }
 
     Ok(0)
 }
 
+/// Settings applied to every answer the server sends.
+#[derive(Clone, Copy)]
+struct AnswerOptions {
+    ttl: u32,
+    time_stamps: bool,
+    poll_hold_ms: u32,
+    map_ipv4_peers: bool,
+}
+
+fn prepare_slot_packet(
+    slot: &Slot,
+    loop_time: u64,
+    send_buf: &mut [u8],
+) -> Result<usize, ServerError> {
+    let mut send_length = 0usize;
+    let mut addr_to: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut addr_from: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut if_index: libc::c_int = 0;
+    let ret = unsafe {
+        picoquic_prepare_packet_ex(
+            slot.cnx,
+            slot.path_id,
+            loop_time,
+            send_buf.as_mut_ptr(),
+            send_buf.len(),
+            &mut send_length,
+            &mut addr_to,
+            &mut addr_from,
+            &mut if_index,
+            std::ptr::null_mut(),
+        )
+    };
+    if ret < 0 {
+        return Err(ServerError::new("Failed to prepare QUIC packet"));
+    }
+    Ok(send_length)
+}
+
+async fn send_answer(
+    udp: &TokioUdpSocket,
+    slot: &Slot,
+    payload: Option<&[u8]>,
+    rcode: Option<Rcode>,
+    options: AnswerOptions,
+) -> Result<(), ServerError> {
+    let response = encode_response(&ResponseParams {
+        id: slot.id,
+        rd: slot.rd,
+        cd: slot.cd,
+        question: &slot.question,
+        payload,
+        rcode,
+        ttl: options.ttl,
+        server_time_ms: options.time_stamps.then(unix_time_ms),
+        poll_hold_ms: (options.poll_hold_ms > 0).then_some(options.poll_hold_ms),
+    })
+    .map_err(|err| ServerError::new(err.to_string()))?;
+    let peer = if options.map_ipv4_peers {
+        normalize_dual_stack_addr(slot.peer)
+    } else {
+        slot.peer
+    };
+    if let Err(err) = udp.send_to(&response, peer).await {
+        if !is_transient_udp_error(&err) {
+            return Err(map_io(err));
+        }
+    }
+    Ok(())
+}
+
 async fn bind_udp_socket(host: &str, port: u16) -> Result<TokioUdpSocket, ServerError> {
     let addrs: Vec<SocketAddr> = lookup_host((host, port)).await.map_err(map_io)?.collect();
     if addrs.is_empty() {
@@ -521,6 +613,13 @@ fn bind_udp_socket_addr(addr: SocketAddr) -> Result<TokioUdpSocket, ServerError>
     TokioUdpSocket::from_std(std_socket).map_err(map_io)
 }
 
+fn unix_time_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 pub(crate) fn map_io(err: std::io::Error) -> ServerError {
     ServerError::new(err.to_string())
 }
@@ -1,18 +1,20 @@
 use crate::server::{Command, StreamKey, StreamWrite};
-use crate::target::spawn_target_connector;
+use crate::target::{spawn_target_connector, IpVersionPolicy};
 use slipstream_core::flow_control::{
     conn_reserve_bytes, consume_error_log_message, consume_stream_data, handle_stream_receive,
-    overflow_log_message, promote_error_log_message, promote_streams, reserve_target_offset,
-    FlowControlState, HasFlowControlState, PromoteEntry, StreamReceiveConfig, StreamReceiveOps,
+    multi_stream_target_offset, overflow_log_message, promote_error_log_message, promote_streams,
+    reserve_target_offset, stream_backpressure_bytes, FlowControlState, HasFlowControlState,
+    PromoteEntry, StreamReceiveConfig, StreamReceiveOps,
 };
 use slipstream_core::invariants::InvariantReporter;
+use slipstream_core::tcp::stream_write_buffer_bytes;
 #[cfg(test)]
 use slipstream_core::test_support::FailureCounter;
 use slipstream_ffi::picoquic::{
     picoquic_call_back_event_t, picoquic_close, picoquic_close_immediate, picoquic_cnx_t,
     picoquic_current_time, picoquic_get_first_cnx, picoquic_get_next_cnx,
     picoquic_mark_active_stream, picoquic_provide_stream_data_buffer, picoquic_quic_t,
-    picoquic_reset_stream, picoquic_stop_sending, picoquic_stream_data_consumed,
+    picoquic_reset_stream, picoquic_stop_sending, picoquic_stream_data_consumed, session_id,
 };
 use slipstream_ffi::{abort_stream_bidi, SLIPSTREAM_FILE_CANCEL_ERROR, SLIPSTREAM_INTERNAL_ERROR};
 use std::collections::{HashMap, HashSet, VecDeque};
@@ -21,12 +23,14 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, watch};
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
 static INVARIANT_REPORTER: InvariantReporter = InvariantReporter::new(1_000_000);
 
 pub(crate) struct ServerState {
-    target_addr: SocketAddr,
+    target_addrs: Arc<[SocketAddr]>,
+    ip_version_policy: IpVersionPolicy,
+    target_connect_timeout: Option<Duration>,
     streams: HashMap<StreamKey, ServerStream>,
     multi_streams: HashSet<usize>,
     command_tx: mpsc::UnboundedSender<Command>,
@@ -83,13 +87,15 @@ impl ServerStreamMetrics {
 
 impl ServerState {
     pub(crate) fn new(
-        target_addr: SocketAddr,
+        target_addrs: Vec<SocketAddr>,
         command_tx: mpsc::UnboundedSender<Command>,
         debug_streams: bool,
         debug_commands: bool,
     ) -> Self {
         Self {
-            target_addr,
+            target_addrs: target_addrs.into(),
+            ip_version_policy: IpVersionPolicy::default(),
+            target_connect_timeout: None,
             streams: HashMap::new(),
             multi_streams: HashSet::new(),
             command_tx,
@@ -103,6 +109,15 @@ impl ServerState {
         }
     }
 
+    /// `None` leaves target connects to the OS timeout.
+    pub(crate) fn set_target_connect_timeout(&mut self, timeout: Option<Duration>) {
+        self.target_connect_timeout = timeout;
+    }
+
+    pub(crate) fn set_ip_version_policy(&mut self, policy: IpVersionPolicy) {
+        self.ip_version_policy = policy;
+    }
+
     pub(crate) fn stream_debug_metrics(&self, cnx_id: usize) -> ServerStreamMetrics {
         let mut metrics = ServerStreamMetrics {
             multi_stream: self.multi_streams.contains(&cnx_id),
@@ -344,6 +359,31 @@ impl HasFlowControlState for ServerStream {
     }
 }
 
+/// Backpressure threshold for a stream in multi-stream mode.
+///
+/// Unconsumed data counts against the connection's flow control window. Once
+/// held-back data reaches half of it, streams go back to consuming on receive,
+/// so targets that stall cannot keep new streams from sending.
+fn backpressure_limit(state: &ServerState, key: StreamKey) -> Option<usize> {
+    let limit = stream_backpressure_bytes()?;
+    let queued = state.streams.get(&key)?.flow.queued_bytes;
+    if queued < limit {
+        return Some(limit);
+    }
+    let withheld: u64 = state
+        .streams
+        .iter()
+        .filter(|(entry_key, _)| entry_key.cnx == key.cnx)
+        .map(|(_, stream)| {
+            stream
+                .flow
+                .rx_bytes
+                .saturating_sub(stream.flow.consumed_offset)
+        })
+        .sum();
+    (withheld < stream_write_buffer_bytes() as u64 / 2).then_some(limit)
+}
+
 fn mark_multi_stream(state: &mut ServerState, cnx_id: usize) -> bool {
     if state.multi_streams.contains(&cnx_id) {
         return false;
@@ -420,9 +460,13 @@ pub(crate) unsafe extern "C" fn server_callback(
             }
             let _ = picoquic_reset_stream(cnx, stream_id, SLIPSTREAM_FILE_CANCEL_ERROR);
         }
+        picoquic_call_back_event_t::picoquic_callback_ready => {
+            info!("Session {} connected", session_id(cnx));
+        }
         picoquic_call_back_event_t::picoquic_callback_close
         | picoquic_call_back_event_t::picoquic_callback_application_close
         | picoquic_call_back_event_t::picoquic_callback_stateless_reset => {
+            info!("Session {} closed", session_id(cnx));
             remove_connection_streams(state, cnx as usize);
             let _ = picoquic_close(cnx, 0);
         }
@@ -604,7 +648,9 @@ fn handle_stream_data(
         }
         spawn_target_connector(
             key,
-            state.target_addr,
+            state.target_addrs.clone(),
+            state.ip_version_policy,
+            state.target_connect_timeout,
             state.command_tx.clone(),
             debug_streams,
             shutdown_rx,
@@ -657,6 +703,11 @@ fn handle_stream_data(
     } else {
         conn_reserve_bytes()
     };
+    let backpressure_bytes = if multi_stream {
+        backpressure_limit(state, key)
+    } else {
+        None
+    };
 
     {
         let stream = match state.streams.get_mut(&key) {
@@ -667,7 +718,10 @@ fn handle_stream_data(
         if handle_stream_receive(
             stream,
             data.len(),
-            StreamReceiveConfig::new(multi_stream, reserve_bytes),
+            StreamReceiveConfig {
+                backpressure_bytes,
+                ..StreamReceiveConfig::new(multi_stream, reserve_bytes)
+            },
             StreamReceiveOps {
                 enqueue: |stream: &mut ServerStream| {
                     if let Some(write_tx) = stream.write_tx.as_ref() {
@@ -1062,32 +1116,39 @@ pub(crate) fn handle_command(state_ptr: *mut ServerState, command: Command) {
                     return;
                 }
                 stream.flow.queued_bytes = stream.flow.queued_bytes.saturating_sub(bytes);
-                if !state.multi_streams.contains(&cnx_id) {
-                    let new_offset = reserve_target_offset(
+                let new_offset = if state.multi_streams.contains(&cnx_id) {
+                    // Credit held back while the target was slow opens as it drains.
+                    multi_stream_target_offset(
+                        stream.flow.rx_bytes,
+                        stream.flow.queued_bytes,
+                        stream_backpressure_bytes(),
+                    )
+                } else {
+                    reserve_target_offset(
                         stream.flow.rx_bytes,
                         stream.flow.queued_bytes,
                         stream.flow.fin_offset,
                         conn_reserve_bytes(),
-                    );
-                    if !consume_stream_data(
-                        &mut stream.flow.consumed_offset,
-                        new_offset,
-                        |new_offset| unsafe {
-                            picoquic_stream_data_consumed(
-                                cnx_id as *mut picoquic_cnx_t,
-                                stream_id,
-                                new_offset,
-                            )
-                        },
-                        |ret, current, target| {
-                            warn!(
-                                "{}",
-                                consume_error_log_message(stream_id, "", ret, current, target)
-                            );
-                        },
-                    ) {
-                        reset_stream = true;
-                    }
+                    )
+                };
+                if !consume_stream_data(
+                    &mut stream.flow.consumed_offset,
+                    new_offset,
+                    |new_offset| unsafe {
+                        picoquic_stream_data_consumed(
+                            cnx_id as *mut picoquic_cnx_t,
+                            stream_id,
+                            new_offset,
+                        )
+                    },
+                    |ret, current, target| {
+                        warn!(
+                            "{}",
+                            consume_error_log_message(stream_id, "", ret, current, target)
+                        );
+                    },
+                ) {
+                    reset_stream = true;
                 }
             }
             if reset_stream {
@@ -1163,7 +1224,7 @@ mod tests {
     fn mark_active_stream_failure_should_remove_stream() {
         let (command_tx, _command_rx) = mpsc::unbounded_channel();
         let target_addr = SocketAddr::from(([127, 0, 0, 1], 0));
-        let mut state = ServerState::new(target_addr, command_tx, false, false);
+        let mut state = ServerState::new(vec![target_addr], command_tx, false, false);
         let key = StreamKey {
             cnx: 0x1,
             stream_id: 4,
@@ -1208,7 +1269,7 @@ mod tests {
     fn mark_active_stream_readable_failure_should_not_leave_send_pending_stuck() {
         let (command_tx, _command_rx) = mpsc::unbounded_channel();
         let target_addr = SocketAddr::from(([127, 0, 0, 1], 0));
-        let mut state = ServerState::new(target_addr, command_tx, false, false);
+        let mut state = ServerState::new(vec![target_addr], command_tx, false, false);
         let key = StreamKey {
             cnx: 0x1,
             stream_id: 4,
@@ -3,17 +3,148 @@ use crate::server::{
     TARGET_WRITE_COALESCE_DEFAULT_BYTES,
 };
 use slipstream_core::tcp::{stream_read_limit_chunks, tcp_send_buffer_bytes};
-use std::net::SocketAddr;
+use slipstream_core::{resolve_host_port, ConfigError, HostPort};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream as TokioTcpStream;
 use tokio::sync::{mpsc, watch};
 use tracing::{debug, warn};
 
+/// Wait before racing IPv4 against a slow IPv6 attempt (RFC 8305 suggests 250 ms).
+const HAPPY_EYEBALLS_DELAY: Duration = Duration::from_millis(250);
+
+/// Which address family is tried first when the target resolves to both.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpVersionPolicy {
+    /// Try IPv4 addresses first, then IPv6.
+    #[default]
+    PreferV4,
+    /// Try IPv6 addresses first, then IPv4.
+    PreferV6,
+    /// Start IPv6 and race IPv4 against it after a short delay.
+    HappyEyeballs,
+}
+
+/// Resolves the target to every address it has, in resolver order.
+///
+/// An IP literal stays a single address; a hostname keeps both families so the
+/// connect policy can choose between them per stream.
+pub(crate) fn resolve_target_addrs(address: &HostPort) -> Result<Vec<SocketAddr>, ConfigError> {
+    if address.host.parse::<IpAddr>().is_ok() {
+        return resolve_host_port(address).map(|addr| vec![addr]);
+    }
+    let addrs = (address.host.as_str(), address.port)
+        .to_socket_addrs()
+        .map_err(|_| ConfigError::new(format!("Cannot resolve {}", address.host)))?;
+    let mut unique = Vec::new();
+    for addr in addrs {
+        if !unique.contains(&addr) {
+            unique.push(addr);
+        }
+    }
+    if unique.is_empty() {
+        return Err(ConfigError::new(format!(
+            "No addresses found for {}",
+            address.host
+        )));
+    }
+    Ok(unique)
+}
+
+/// Orders `addrs` so the preferred family comes first, keeping resolver order
+/// within each family.
+fn ordered_addrs(addrs: &[SocketAddr], prefer_v6: bool) -> Vec<SocketAddr> {
+    let (mut first, second): (Vec<_>, Vec<_>) =
+        addrs.iter().partition(|addr| addr.is_ipv6() == prefer_v6);
+    first.extend(second);
+    first
+}
+
+/// Tries `addrs` one after another and returns the first connection.
+async fn connect_sequential<T, F, Fut>(addrs: &[SocketAddr], connect: &F) -> std::io::Result<T>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = std::io::Result<T>>,
+{
+    let mut last_err = None;
+    for addr in addrs {
+        match connect(*addr).await {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| std::io::ErrorKind::AddrNotAvailable.into()))
+}
+
+/// Connects to the target following `policy`.
+///
+/// Happy-eyeballs starts on the IPv6 addresses and, if they have not connected
+/// within `HAPPY_EYEBALLS_DELAY`, races the IPv4 addresses against them; the
+/// first success wins and the slower attempt is dropped.
+async fn connect_target<T, F, Fut>(
+    addrs: &[SocketAddr],
+    policy: IpVersionPolicy,
+    connect: F,
+) -> std::io::Result<T>
+where
+    F: Fn(SocketAddr) -> Fut,
+    Fut: Future<Output = std::io::Result<T>>,
+{
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.iter().partition(|addr| addr.is_ipv6());
+    match policy {
+        IpVersionPolicy::PreferV4 => {
+            connect_sequential(&ordered_addrs(addrs, false), &connect).await
+        }
+        IpVersionPolicy::PreferV6 => {
+            connect_sequential(&ordered_addrs(addrs, true), &connect).await
+        }
+        IpVersionPolicy::HappyEyeballs if v6.is_empty() || v4.is_empty() => {
+            connect_sequential(addrs, &connect).await
+        }
+        IpVersionPolicy::HappyEyeballs => {
+            let primary = connect_sequential(&v6, &connect);
+            let fallback = async {
+                tokio::time::sleep(HAPPY_EYEBALLS_DELAY).await;
+                connect_sequential(&v4, &connect).await
+            };
+            tokio::pin!(primary, fallback);
+            tokio::select! {
+                result = &mut primary => match result {
+                    Ok(stream) => Ok(stream),
+                    Err(_) => fallback.await,
+                },
+                result = &mut fallback => match result {
+                    Ok(stream) => Ok(stream),
+                    Err(_) => primary.await,
+                },
+            }
+        }
+    }
+}
+
+/// Fails a target connect with `TimedOut` once `timeout` passes, so a dead target
+/// resets the stream instead of leaving it open until the OS gives up.
+async fn connect_within<T>(
+    connect: impl Future<Output = std::io::Result<T>>,
+    timeout: Option<Duration>,
+) -> std::io::Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, connect)
+            .await
+            .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into())),
+        None => connect.await,
+    }
+}
+
 pub(crate) fn spawn_target_connector(
     key: StreamKey,
-    target_addr: SocketAddr,
+    target_addrs: Arc<[SocketAddr]>,
+    ip_version_policy: IpVersionPolicy,
+    connect_timeout: Option<Duration>,
     command_tx: mpsc::UnboundedSender<Command>,
     debug_streams: bool,
     mut shutdown_rx: watch::Receiver<bool>,
@@ -22,7 +153,10 @@ pub(crate) fn spawn_target_connector(
         if *shutdown_rx.borrow() {
             return;
         }
-        let connect = TokioTcpStream::connect(target_addr);
+        let connect = connect_within(
+            connect_target(&target_addrs, ip_version_policy, TokioTcpStream::connect),
+            connect_timeout,
+        );
         let stream = tokio::select! {
             _ = shutdown_rx.changed() => {
                 return;
@@ -235,3 +369,159 @@ pub(crate) fn spawn_target_writer(
         let _ = write_half.shutdown().await;
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const V4: &str = "192.0.2.1:443";
+    const V6: &str = "[2001:db8::1]:443";
+
+    /// Dual-stack target whose IPv4 and IPv6 connects take `v4`/`v6` (delay, ok)
+    /// and record the order they were attempted in.
+    struct MockTarget {
+        v4: (Duration, bool),
+        v6: (Duration, bool),
+        attempts: Mutex<Vec<SocketAddr>>,
+    }
+
+    impl MockTarget {
+        fn new(v4: (Duration, bool), v6: (Duration, bool)) -> Self {
+            Self {
+                v4,
+                v6,
+                attempts: Mutex::new(Vec::new()),
+            }
+        }
+
+        async fn connect(&self, addr: SocketAddr) -> std::io::Result<SocketAddr> {
+            self.attempts.lock().unwrap().push(addr);
+            let (delay, ok) = if addr.is_ipv6() { self.v6 } else { self.v4 };
+            tokio::time::sleep(delay).await;
+            if ok {
+                Ok(addr)
+            } else {
+                Err(std::io::ErrorKind::ConnectionRefused.into())
+            }
+        }
+
+        fn attempts(&self) -> Vec<SocketAddr> {
+            self.attempts.lock().unwrap().clone()
+        }
+    }
+
+    fn dual_stack() -> Vec<SocketAddr> {
+        vec![V6.parse().unwrap(), V4.parse().unwrap()]
+    }
+
+    const FAST_OK: (Duration, bool) = (Duration::ZERO, true);
+    const FAST_FAIL: (Duration, bool) = (Duration::ZERO, false);
+
+    #[tokio::test]
+    async fn prefer_v4_tries_ipv4_first_and_falls_back_to_ipv6() {
+        let target = MockTarget::new(FAST_OK, FAST_OK);
+        let addrs = dual_stack();
+        let conn = connect_target(&addrs, IpVersionPolicy::PreferV4, |addr| {
+            target.connect(addr)
+        })
+        .await
+        .expect("connect");
+        assert_eq!(conn, V4.parse().unwrap());
+        assert_eq!(target.attempts(), [V4.parse().unwrap()]);
+
+        let target = MockTarget::new(FAST_FAIL, FAST_OK);
+        let conn = connect_target(&addrs, IpVersionPolicy::PreferV4, |addr| {
+            target.connect(addr)
+        })
+        .await
+        .expect("fallback connect");
+        assert_eq!(conn, V6.parse().unwrap());
+        assert_eq!(
+            target.attempts(),
+            [V4.parse().unwrap(), V6.parse().unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn prefer_v6_tries_ipv6_first_and_falls_back_to_ipv4() {
+        let target = MockTarget::new(FAST_OK, FAST_OK);
+        let addrs = dual_stack();
+        let conn = connect_target(&addrs, IpVersionPolicy::PreferV6, |addr| {
+            target.connect(addr)
+        })
+        .await
+        .expect("connect");
+        assert_eq!(conn, V6.parse().unwrap());
+        assert_eq!(target.attempts(), [V6.parse().unwrap()]);
+
+        let target = MockTarget::new(FAST_FAIL, FAST_FAIL);
+        let err = connect_target(&addrs, IpVersionPolicy::PreferV6, |addr| {
+            target.connect(addr)
+        })
+        .await
+        .expect_err("both families refused");
+        assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+        assert_eq!(
+            target.attempts(),
+            [V6.parse().unwrap(), V4.parse().unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs_races_ipv4_against_a_slow_ipv6() {
+        // A quick IPv6 connect wins before IPv4 is ever started.
+        let target = MockTarget::new(FAST_OK, FAST_OK);
+        let addrs = dual_stack();
+        let conn = connect_target(&addrs, IpVersionPolicy::HappyEyeballs, |addr| {
+            target.connect(addr)
+        })
+        .await
+        .expect("connect");
+        assert_eq!(conn, V6.parse().unwrap());
+        assert_eq!(target.attempts(), [V6.parse().unwrap()]);
+
+        // A stalled IPv6 connect loses to IPv4 once the delay passes.
+        let target = MockTarget::new(FAST_OK, (Duration::from_secs(30), true));
+        let started = tokio::time::Instant::now();
+        let conn = connect_target(&addrs, IpVersionPolicy::HappyEyeballs, |addr| {
+            target.connect(addr)
+        })
+        .await
+        .expect("connect");
+        assert_eq!(conn, V4.parse().unwrap());
+        assert!(started.elapsed() >= HAPPY_EYEBALLS_DELAY);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            target.attempts(),
+            [V6.parse().unwrap(), V4.parse().unwrap()]
+        );
+
+        // A refused IPv6 connect still waits for IPv4 rather than failing.
+        let target = MockTarget::new(FAST_OK, FAST_FAIL);
+        let conn = connect_target(&addrs, IpVersionPolicy::HappyEyeballs, |addr| {
+            target.connect(addr)
+        })
+        .await
+        .expect("fallback connect");
+        assert_eq!(conn, V4.parse().unwrap());
+    }
+
+    #[tokio::test]
+    async fn connect_that_never_completes_times_out() {
+        let started = tokio::time::Instant::now();
+        let result = connect_within(
+            std::future::pending::<std::io::Result<()>>(),
+            Some(Duration::from_millis(50)),
+        )
+        .await;
+        assert_eq!(
+            result.expect_err("must time out").kind(),
+            std::io::ErrorKind::TimedOut
+        );
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let result = connect_within(async { Ok(7) }, None).await;
+        assert_eq!(result.expect("ready"), 7);
+    }
+}
@@ -0,0 +1,234 @@
+mod support;
+
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use slipstream_core::test_support::{Fault, FaultScript, FaultyResolver};
+use support::{
+    ensure_client_bin, pick_tcp_port, pick_udp_port, server_bin_path, spawn_accept_loop_target,
+    spawn_server_client_ready, test_cert_and_key, workspace_root, ClientArgs, ServerArgs,
+};
+
+const DOMAIN: &str = "test.example.com";
+const PAYLOAD_LEN: usize = 64 * 1024;
+
+/// Every fault the harness knows, a few clean responses apart.
+fn combined_faults() -> FaultScript {
+    FaultScript::new(vec![
+        Fault::Pass,
+        Fault::Corrupt,
+        Fault::Pass,
+        Fault::Duplicate,
+        Fault::Reorder,
+        Fault::Pass,
+        Fault::Truncate,
+        Fault::Pass,
+        Fault::Drop,
+        Fault::Delay(Duration::from_millis(150)),
+        Fault::Pass,
+        Fault::Pass,
+    ])
+    .repeat()
+}
+
+#[test]
+fn faulty_resolver_follows_its_script() {
+    let upstream = UdpSocket::bind("127.0.0.1:0").expect("bind upstream");
+    let upstream_addr = upstream.local_addr().expect("upstream addr");
+    let echo = thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        for _ in 0..7 {
+            let (size, peer) = upstream.recv_from(&mut buf).expect("upstream recv");
+            upstream.send_to(&buf[..size], peer).expect("upstream send");
+        }
+    });
+    let resolver = FaultyResolver::spawn(
+        upstream_addr,
+        FaultScript::new(vec![
+            Fault::Drop,
+            Fault::Duplicate,
+            Fault::Reorder,
+            Fault::Pass,
+            Fault::Corrupt,
+            Fault::Truncate,
+            Fault::Delay(Duration::from_millis(300)),
+        ]),
+    )
+    .expect("spawn faulty resolver");
+    let client = UdpSocket::bind("127.0.0.1:0").expect("bind client");
+    client
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .expect("client timeout");
+    let mut received = Vec::new();
+    let mut buf = [0u8; 512];
+    for index in 0..7u8 {
+        // Header-sized messages, so truncation can set the TC flag.
+        let message = [index, 0, 0, 0, index, index, index, index];
+        client.send_to(&message, resolver.addr).expect("send query");
+        // Let each response go through the relay before the next query.
+        thread::sleep(Duration::from_millis(50));
+    }
+    let sent_delayed = Instant::now() - Duration::from_millis(50);
+    while received.len() < 7 {
+        let (size, _) = client.recv_from(&mut buf).expect("receive response");
+        received.push(buf[..size].to_vec());
+    }
+    echo.join().expect("echo thread");
+
+    let first_bytes: Vec<u8> = received.iter().map(|response| response[0]).collect();
+    // 0 dropped, 1 twice, 2 after 3, 6 last.
+    assert_eq!(first_bytes, [1, 1, 3, 2, 4, 5, 6]);
+    assert_ne!(received[4], [4, 0, 0, 0, 4, 4, 4, 4], "corrupted");
+    assert_eq!(received[5], [5, 0, 0x02, 0], "truncated with TC");
+    assert!(sent_delayed.elapsed() >= Duration::from_millis(200));
+
+    let stats = resolver.stats();
+    assert_eq!(stats.queries.load(Ordering::Relaxed), 7);
+    assert_eq!(stats.responses.load(Ordering::Relaxed), 7);
+    assert_eq!(stats.dropped.load(Ordering::Relaxed), 1);
+    assert_eq!(stats.delayed.load(Ordering::Relaxed), 1);
+}
+
+#[test]
+fn tunnel_survives_combined_resolver_faults() {
+    let root = workspace_root();
+    let client_bin = ensure_client_bin(&root);
+    let server_bin = server_bin_path();
+
+    let (cert, key) = test_cert_and_key(&root);
+
+    let dns_port = match pick_udp_port() {
+        Ok(port) => port,
+        Err(err) => {
+            eprintln!("skipping faulty resolver e2e test: {}", err);
+            return;
+        }
+    };
+    let tcp_port = match pick_tcp_port() {
+        Ok(port) => port,
+        Err(err) => {
+            eprintln!("skipping faulty resolver e2e test: {}", err);
+            return;
+        }
+    };
+    // Clean until the connection is up, so the handshake fits the harness's
+    // startup timeout.
+    let resolver = match FaultyResolver::spawn(
+        SocketAddr::from((Ipv4Addr::LOCALHOST, dns_port)),
+        FaultScript::new(Vec::new()),
+    ) {
+        Ok(resolver) => resolver,
+        Err(err) => {
+            eprintln!("skipping faulty resolver e2e test: {}", err);
+            return;
+        }
+    };
+
+    let target = match spawn_accept_loop_target::<(), _>(|stream, _tx, stop_flag, _index| {
+        let stop_conn = Arc::clone(&stop_flag);
+        Some(thread::spawn(move || {
+            let mut stream = stream;
+            let _ = stream.set_nodelay(true);
+            let _ = stream.set_read_timeout(Some(Duration::from_millis(200)));
+            let mut buf = [0u8; 4096];
+            while !stop_conn.load(Ordering::Relaxed) {
+                match stream.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        if stream.write_all(&buf[..n]).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err)
+                        if err.kind() == std::io::ErrorKind::TimedOut
+                            || err.kind() == std::io::ErrorKind::WouldBlock =>
+                    {
+                        continue;
+                    }
+                    Err(_) => break,
+                }
+            }
+        }))
+    }) {
+        Ok(target) => target,
+        Err(err) => {
+            eprintln!("skipping faulty resolver e2e test: {}", err);
+            return;
+        }
+    };
+
+    let Some(_harness) = spawn_server_client_ready(
+        ServerArgs {
+            server_bin: &server_bin,
+            dns_listen_host: Some("127.0.0.1"),
+            dns_port,
+            target_address: &format!("127.0.0.1:{}", target.addr.port()),
+            domains: &[DOMAIN],
+            cert: &cert,
+            key: &key,
+            reset_seed_path: None,
+            fallback_addr: None,
+            idle_timeout_seconds: None,
+            envs: &[],
+            rust_log: "info",
+            capture_logs: true,
+        },
+        ClientArgs {
+            client_bin: &client_bin,
+            dns_port: resolver.addr.port(),
+            tcp_port,
+            domain: DOMAIN,
+            cert: Some(&cert),
+            keep_alive_interval: Some(0),
+            envs: &[],
+            rust_log: "info",
+            capture_logs: true,
+        },
+        "skipping faulty resolver e2e test: server failed to start",
+        Duration::from_millis(200),
+    ) else {
+        return;
+    };
+
+    resolver.set_script(combined_faults());
+    let client_addr = SocketAddr::from((Ipv4Addr::LOCALHOST, tcp_port));
+    let mut stream =
+        TcpStream::connect_timeout(&client_addr, Duration::from_secs(2)).expect("connect client");
+    let _ = stream.set_nodelay(true);
+    stream
+        .set_read_timeout(Some(Duration::from_secs(30)))
+        .expect("set read timeout");
+    let payload: Vec<u8> = (0..PAYLOAD_LEN).map(|index| (index % 251) as u8).collect();
+    let mut writer = stream.try_clone().expect("clone client stream");
+    let upload = payload.clone();
+    let sender = thread::spawn(move || writer.write_all(&upload));
+    let mut echoed = vec![0u8; PAYLOAD_LEN];
+    stream
+        .read_exact(&mut echoed)
+        .expect("read the echoed payload");
+    sender
+        .join()
+        .expect("sender thread")
+        .expect("write payload");
+    assert!(echoed == payload, "payload came back altered");
+
+    let stats = resolver.stats();
+    for (fault, count) in [
+        ("corrupted", &stats.corrupted),
+        ("duplicated", &stats.duplicated),
+        ("reordered", &stats.reordered),
+        ("truncated", &stats.truncated),
+        ("dropped", &stats.dropped),
+        ("delayed", &stats.delayed),
+    ] {
+        assert!(
+            count.load(Ordering::Relaxed) > 0,
+            "no response was {}",
+            fault
+        );
+    }
+}
@@ -0,0 +1,231 @@
+mod support;
+
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use support::{
+    ensure_client_bin, log_snapshot, pick_tcp_port, pick_udp_port, server_bin_path,
+    spawn_accept_loop_target, spawn_client_with_args, spawn_server, test_cert_and_key,
+    wait_for_log, workspace_root, ClientArgs, ServerArgs,
+};
+
+const DOMAIN: &str = "test.example.com";
+const CHUNK: usize = 1024;
+
+fn spawn_echo_target() -> std::io::Result<support::TargetHarness<()>> {
+    spawn_accept_loop_target(|stream, _tx, stop_flag, _index| {
+        Some(thread::spawn(move || {
+            let mut stream = stream;
+            let _ = stream.set_nodelay(true);
+            let _ = stream.set_read_timeout(Some(Duration::from_millis(200)));
+            let mut buf = [0u8; 4096];
+            while !stop_flag.load(Ordering::Relaxed) {
+                match stream.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        if stream.write_all(&buf[..n]).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err)
+                        if err.kind() == std::io::ErrorKind::TimedOut
+                            || err.kind() == std::io::ErrorKind::WouldBlock =>
+                    {
+                        continue;
+                    }
+                    Err(_) => break,
+                }
+            }
+        }))
+    })
+}
+
+/// Sends chunks through `stream` and checks each echo until `stop` is set,
+/// counting the bytes that came back intact.
+fn echo_until_stopped(
+    mut stream: TcpStream,
+    stop: Arc<AtomicBool>,
+    echoed: Arc<AtomicUsize>,
+) -> Result<(), String> {
+    let mut sent = vec![0u8; CHUNK];
+    let mut received = vec![0u8; CHUNK];
+    let mut round = 0u8;
+    while !stop.load(Ordering::Relaxed) {
+        sent.fill(round);
+        stream.write_all(&sent).map_err(|err| {
+            format!(
+                "write after {} bytes: {}",
+                echoed.load(Ordering::Relaxed),
+                err
+            )
+        })?;
+        stream.read_exact(&mut received).map_err(|err| {
+            format!(
+                "read after {} bytes: {}",
+                echoed.load(Ordering::Relaxed),
+                err
+            )
+        })?;
+        if received != sent {
+            return Err(format!("echo of round {} was corrupted", round));
+        }
+        echoed.fetch_add(CHUNK, Ordering::Relaxed);
+        round = round.wrapping_add(1);
+    }
+    Ok(())
+}
+
+fn post_status(port: u16, path: &str, body: &str) -> String {
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port)).expect("connect status");
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nX-Slipstream-Control: 1\r\nContent-Length: {}\r\n\r\n{}",
+        path,
+        port,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(request.as_bytes())
+        .expect("write status request");
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    response
+}
+
+fn wait_for_echoed(echoed: &AtomicUsize, at_least: usize, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if echoed.load(Ordering::Relaxed) >= at_least {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
+
+#[test]
+fn reload_keeps_a_busy_stream_open() {
+    let root = workspace_root();
+    let client_bin = ensure_client_bin(&root);
+    let server_bin = server_bin_path();
+
+    let (cert, key) = test_cert_and_key(&root);
+
+    let (dns_port, tcp_port, status_port, unused_port) = match (
+        pick_udp_port(),
+        pick_tcp_port(),
+        pick_tcp_port(),
+        pick_udp_port(),
+    ) {
+        (Ok(dns), Ok(tcp), Ok(status), Ok(unused)) => (dns, tcp, status, unused),
+        _ => {
+            eprintln!("skipping reload under traffic e2e test: no free ports");
+            return;
+        }
+    };
+    let target = match spawn_echo_target() {
+        Ok(target) => target,
+        Err(err) => {
+            eprintln!("skipping reload under traffic e2e test: {}", err);
+            return;
+        }
+    };
+
+    let (mut server, _server_logs) = spawn_server(ServerArgs {
+        server_bin: &server_bin,
+        dns_listen_host: Some("127.0.0.1"),
+        dns_port,
+        target_address: &format!("127.0.0.1:{}", target.addr.port()),
+        domains: &[DOMAIN],
+        cert: &cert,
+        key: &key,
+        reset_seed_path: None,
+        fallback_addr: None,
+        idle_timeout_seconds: None,
+        envs: &[],
+        rust_log: "info",
+        capture_logs: false,
+    });
+    thread::sleep(Duration::from_millis(200));
+    if server.has_exited() {
+        eprintln!("skipping reload under traffic e2e test: server failed to start");
+        return;
+    }
+
+    let status_port_arg = status_port.to_string();
+    let (_client, client_logs) = spawn_client_with_args(
+        ClientArgs {
+            client_bin: &client_bin,
+            dns_port,
+            tcp_port,
+            domain: DOMAIN,
+            cert: Some(&cert),
+            keep_alive_interval: Some(0),
+            envs: &[],
+            rust_log: "info",
+            capture_logs: true,
+        },
+        &["--status-port", &status_port_arg, "--status-control"],
+    );
+    let client_logs = client_logs.expect("client logs");
+    if !wait_for_log(&client_logs, "Connection ready", Duration::from_secs(10)) {
+        let snapshot = log_snapshot(&client_logs);
+        panic!("client did not become ready\n{}", snapshot);
+    }
+
+    let stream = TcpStream::connect_timeout(
+        &SocketAddr::from((Ipv4Addr::LOCALHOST, tcp_port)),
+        Duration::from_secs(2),
+    )
+    .expect("connect tunnel stream");
+    let _ = stream.set_nodelay(true);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(10)));
+    let stop = Arc::new(AtomicBool::new(false));
+    let echoed = Arc::new(AtomicUsize::new(0));
+    let traffic = {
+        let stop = Arc::clone(&stop);
+        let echoed = Arc::clone(&echoed);
+        thread::spawn(move || echo_until_stopped(stream, stop, echoed))
+    };
+    if !wait_for_echoed(&echoed, 8 * CHUNK, Duration::from_secs(10)) {
+        let snapshot = log_snapshot(&client_logs);
+        panic!("no data moved before the reload\n{}", snapshot);
+    }
+
+    // Adding a resolver is a reload the CLI can trigger; it is never answered,
+    // so the busy stream has to stay on the path it started on.
+    let added = format!(r#"{{"add":["127.0.0.1:{}"]}}"#, unused_port);
+    let response = post_status(status_port, "/resolvers", &added);
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(
+        response.contains(r#""applied":["resolvers"]"#),
+        "{}",
+        response
+    );
+    if !wait_for_log(
+        &client_logs,
+        "Reload: adding resolver",
+        Duration::from_secs(5),
+    ) {
+        let snapshot = log_snapshot(&client_logs);
+        panic!("client did not pick up the reload\n{}", snapshot);
+    }
+
+    let after_reload = echoed.load(Ordering::Relaxed);
+    let moved = wait_for_echoed(&echoed, after_reload + 16 * CHUNK, Duration::from_secs(10));
+    stop.store(true, Ordering::Relaxed);
+    let result = traffic.join().expect("traffic thread");
+    if let Err(err) = result {
+        let snapshot = log_snapshot(&client_logs);
+        panic!("stream failed across the reload: {}\n{}", err, snapshot);
+    }
+    if !moved {
+        let snapshot = log_snapshot(&client_logs);
+        panic!("stream stalled after the reload\n{}", snapshot);
+    }
+}
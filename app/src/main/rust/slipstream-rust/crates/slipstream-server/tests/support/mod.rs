@@ -210,6 +210,14 @@ pub fn spawn_server(args: ServerArgs<'_>) -> (ChildGuard, Option<LogCapture>) {
 }
 
 pub fn spawn_client(args: ClientArgs<'_>) -> (ChildGuard, Option<LogCapture>) {
+    spawn_client_with_args(args, &[])
+}
+
+/// Like `spawn_client`, with further command-line arguments for the client.
+pub fn spawn_client_with_args(
+    args: ClientArgs<'_>,
+    extra_args: &[&str],
+) -> (ChildGuard, Option<LogCapture>) {
     let mut cmd = Command::new(args.client_bin);
     cmd.arg("--tcp-listen-port")
         .arg(args.tcp_port.to_string())
@@ -223,6 +231,7 @@ pub fn spawn_client(args: ClientArgs<'_>) -> (ChildGuard, Option<LogCapture>) {
     if let Some(interval) = args.keep_alive_interval {
         cmd.arg("--keep-alive-interval").arg(interval.to_string());
     }
+    cmd.args(extra_args);
     for (key, value) in args.envs {
         cmd.env(key, value);
     }
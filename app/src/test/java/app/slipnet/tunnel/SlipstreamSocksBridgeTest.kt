@@ -0,0 +1,191 @@
+package app.slipnet.tunnel
+
+import java.io.DataInputStream
+import java.io.OutputStream
+import java.net.InetAddress
+import java.net.InetSocketAddress
+import java.net.ServerSocket
+import java.net.Socket
+import java.util.concurrent.ArrayBlockingQueue
+import java.util.concurrent.CountDownLatch
+import java.util.concurrent.TimeUnit
+import org.junit.After
+import org.junit.Assert.assertArrayEquals
+import org.junit.Assert.assertEquals
+import org.junit.Assert.assertTrue
+import org.junit.Test
+
+class SlipstreamSocksBridgeTest {
+    private companion object {
+        const val USERNAME = "user"
+        const val PASSWORD = "pass"
+    }
+
+    private val remote = ServerSocket(0, 50, InetAddress.getLoopbackAddress())
+
+    @After
+    fun tearDown() {
+        SlipstreamSocksBridge.stop()
+        remote.close()
+    }
+
+    /**
+     * A remote proxy that takes the greeting and the CONNECT but never replies to it,
+     * as when its connect to the destination hangs.
+     */
+    private fun startHangingRemote() {
+        Thread {
+            try {
+                val socket = remote.accept()
+                val input = DataInputStream(socket.getInputStream())
+                input.readFully(ByteArray(3)) // greeting offering no auth
+                socket.getOutputStream().apply { write(byteArrayOf(0x05, 0x00)); flush() }
+                input.readFully(ByteArray(10)) // CONNECT to an IPv4 address
+                Thread.sleep(10_000)
+                socket.close()
+            } catch (_: Exception) {
+            }
+        }.apply { isDaemon = true; start() }
+    }
+
+    /**
+     * A remote proxy that asks for the username/password login, counts down [authenticated]
+     * once it accepted it and hands the connection to [then].
+     */
+    private fun startAuthenticatingRemote(
+        authenticated: CountDownLatch,
+        then: (DataInputStream, OutputStream) -> Unit
+    ) {
+        Thread {
+            try {
+                remote.accept().use { socket ->
+                    socket.soTimeout = 5_000
+                    val input = DataInputStream(socket.getInputStream())
+                    val output = socket.getOutputStream()
+                    input.readFully(ByteArray(3)) // greeting offering username/password
+                    output.apply { write(byteArrayOf(0x05, 0x02)); flush() }
+                    val login = ByteArray(3 + USERNAME.length + PASSWORD.length)
+                    input.readFully(login)
+                    if (!login.contentEquals(byteArrayOf(0x01, 0x04) + USERNAME.toByteArray() +
+                            byteArrayOf(0x04) + PASSWORD.toByteArray())) {
+                        return@use
+                    }
+                    output.apply { write(byteArrayOf(0x01, 0x00)); flush() }
+                    authenticated.countDown()
+                    then(input, output)
+                }
+            } catch (_: Exception) {
+            }
+        }.apply { isDaemon = true; start() }
+    }
+
+    /** Starts the bridge with warmup and credentials; the port to connect to. */
+    private fun startWarmBridge(): Int {
+        val listen = ServerSocket(0).use { it.localPort }
+        val started = SlipstreamSocksBridge.start(
+            slipstreamPort = remote.localPort,
+            listenPort = listen,
+            socksUsername = USERNAME,
+            socksPassword = PASSWORD,
+            warmup = true
+        )
+        assertTrue(started.isSuccess)
+        return listen
+    }
+
+    @Test
+    fun warmupAuthenticatesBeforeTheRequestIsSent() {
+        val authenticated = CountDownLatch(1)
+        val forwarded = ArrayBlockingQueue<ByteArray>(1)
+        startAuthenticatingRemote(authenticated) { input, output ->
+            forwarded.put(ByteArray(10).also { input.readFully(it) })
+            output.write(byteArrayOf(0x05, 0x00, 0x00, 0x01, 192.toByte(), 0, 2, 1, 0x01, 0xBB.toByte()))
+            output.flush()
+        }
+        val listen = startWarmBridge()
+
+        Socket().use { app ->
+            app.connect(InetSocketAddress("127.0.0.1", listen), 2_000)
+            app.soTimeout = 5_000
+            val output = app.getOutputStream()
+            val input = DataInputStream(app.getInputStream())
+            output.write(byteArrayOf(0x05, 0x01, 0x00))
+            output.flush()
+            input.readFully(ByteArray(2))
+
+            // The remote greeting and login are done while the request is still unsent.
+            assertTrue(authenticated.await(5, TimeUnit.SECONDS))
+            val connect = byteArrayOf(0x05, 0x01, 0x00, 0x01, 192.toByte(), 0, 2, 1, 0x01, 0xBB.toByte())
+            output.write(connect)
+            output.flush()
+            val reply = ByteArray(10).also { input.readFully(it) }
+
+            assertEquals(0x00, reply[1].toInt())
+            // The CONNECT goes out on the authenticated connection, with no second greeting.
+            assertArrayEquals(connect, forwarded.poll(5, TimeUnit.SECONDS))
+        }
+    }
+
+    @Test
+    fun refusedRequestClosesTheWarmConnection() {
+        val authenticated = CountDownLatch(1)
+        val closed = CountDownLatch(1)
+        startAuthenticatingRemote(authenticated) { input, _ ->
+            if (input.read() == -1) closed.countDown()
+        }
+        val listen = startWarmBridge()
+
+        Socket().use { app ->
+            app.connect(InetSocketAddress("127.0.0.1", listen), 2_000)
+            app.soTimeout = 5_000
+            val output = app.getOutputStream()
+            val input = DataInputStream(app.getInputStream())
+            output.write(byteArrayOf(0x05, 0x01, 0x00))
+            output.flush()
+            input.readFully(ByteArray(2))
+            assertTrue(authenticated.await(5, TimeUnit.SECONDS))
+
+            // UDP ASSOCIATE is refused as soon as its command byte is read.
+            output.write(byteArrayOf(0x05, 0x03, 0x00))
+            output.flush()
+            val reply = ByteArray(10).also { input.readFully(it) }
+
+            assertEquals(0x07, reply[1].toInt())
+            assertTrue(closed.await(5, TimeUnit.SECONDS))
+        }
+    }
+
+    @Test
+    fun connectToAHangingRemoteIsAnsweredWithHostUnreachable() {
+        startHangingRemote()
+        val listen = ServerSocket(0).use { it.localPort }
+        val started = SlipstreamSocksBridge.start(
+            slipstreamPort = remote.localPort,
+            listenPort = listen,
+            connectTimeoutMs = 300
+        )
+        assertTrue(started.isSuccess)
+
+        Socket().use { app ->
+            app.connect(InetSocketAddress("127.0.0.1", listen), 2_000)
+            app.soTimeout = 5_000
+            val output = app.getOutputStream()
+            val input = DataInputStream(app.getInputStream())
+            output.write(byteArrayOf(0x05, 0x01, 0x00))
+            output.flush()
+            val method = ByteArray(2).also { input.readFully(it) }
+            assertEquals(0x00, method[1].toInt())
+
+            val sentAt = System.nanoTime()
+            output.write(byteArrayOf(0x05, 0x01, 0x00, 0x01, 192.toByte(), 0, 2, 1, 0x01, 0xBB.toByte()))
+            output.flush()
+            val reply = ByteArray(10).also { input.readFully(it) }
+            val waitedMs = (System.nanoTime() - sentAt) / 1_000_000
+
+            assertEquals(0x04, reply[1].toInt())
+            assertTrue("waited ${waitedMs}ms", waitedMs in 250..4_000)
+            // The stream is closed after the reply.
+            assertEquals(-1, input.read())
+        }
+    }
+}